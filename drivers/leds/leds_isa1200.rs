@@ -0,0 +1,206 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Rust Imagis ISA1200 haptic motor driver.
+//!
+//! The ISA1200 drives the vibration motor of many Tegra tablets and phones. The motor is exposed
+//! as an LED whose brightness is the strength of the vibration, with the `transient` trigger,
+//! through which user space turns it on for a given time like with Android's `timed_output`.
+//!
+//! The motor is driven either from an external PWM signal, given by the `pwms` property of the
+//! devicetree node, or from the PWM generator of the chip, whose period is given by the
+//! `imagis,period` property. The `imagis,erm` property selects an eccentric rotating mass motor
+//! instead of a linear resonant actuator, and the `enable-gpios` and `ldo-enable-gpios`
+//! properties give the lines that enable the chip and its regulator.
+
+use kernel::{
+    c_str, delay,
+    device::Device,
+    gpio, i2c, leds, of,
+    prelude::*,
+    pwm::{self, Pwm},
+    regmap::{Config, Regmap},
+    sync::Mutex,
+};
+
+kernel::module_i2c_driver! {
+    type: Isa1200Driver,
+    name: "leds_isa1200",
+    author: "Rust for Linux Contributors",
+    description: "Imagis ISA1200 haptic motor driver",
+    license: "GPL",
+}
+
+/// The control register, with the mode of the chip.
+const HCTRL0: u32 = 0x30;
+const HCTRL0_HAP_EN: u32 = 1 << 7;
+const HCTRL0_PWM_INPUT_MODE: u32 = 1 << 3;
+const HCTRL0_PWM_GEN_MODE: u32 = 2 << 3;
+
+/// The motor configuration register.
+const HCTRL1: u32 = 0x31;
+const HCTRL1_ERM: u32 = 1 << 5;
+const HCTRL1_SMART_ENABLE: u32 = 1 << 3;
+
+/// The period of the PWM generator.
+const HCTRL4: u32 = 0x34;
+
+/// The duty cycle of the PWM generator.
+const HCTRL5: u32 = 0x35;
+
+const CONFIG: Config = Config::new(8, 8).with_max_register(HCTRL5);
+
+const MAX_BRIGHTNESS: u32 = 255;
+
+/// The source of the PWM signal that drives the motor.
+enum Source {
+    /// An external PWM output.
+    Input(Pwm),
+    /// The PWM generator of the chip, with its period.
+    Generator(u8),
+}
+
+struct State {
+    source: Source,
+    on: bool,
+}
+
+struct Isa1200 {
+    regmap: Regmap,
+    enable: Option<gpio::Desc>,
+    ldo_enable: Option<gpio::Desc>,
+    erm: bool,
+    state: Pin<Box<Mutex<State>>>,
+}
+
+impl Isa1200 {
+    /// Returns the duty cycle, out of `period`, that drives the motor at `brightness`.
+    ///
+    /// Half of the period stops the motor, and the full period drives it at full strength.
+    fn duty(period: u64, brightness: u32) -> u64 {
+        period / 2 + period / 2 * u64::from(brightness) / u64::from(MAX_BRIGHTNESS)
+    }
+
+    fn power_on(&self, source: &Source) -> Result {
+        if let Some(ldo_enable) = &self.ldo_enable {
+            ldo_enable.set_value_cansleep(true);
+        }
+        if let Some(enable) = &self.enable {
+            enable.set_value_cansleep(true);
+        }
+        // The chip needs some time to come out of reset before it can be configured.
+        delay::usleep_range(200, 300);
+
+        let mut hctrl1 = HCTRL1_SMART_ENABLE;
+        if self.erm {
+            hctrl1 |= HCTRL1_ERM;
+        }
+        self.regmap.write(HCTRL1, hctrl1)?;
+        let mode = match source {
+            Source::Input(_) => HCTRL0_PWM_INPUT_MODE,
+            Source::Generator(period) => {
+                self.regmap.write(HCTRL4, (*period).into())?;
+                HCTRL0_PWM_GEN_MODE
+            }
+        };
+        self.regmap.write(HCTRL0, HCTRL0_HAP_EN | mode)
+    }
+
+    fn power_off(&self) -> Result {
+        let ret = self.regmap.write(HCTRL0, 0);
+        if let Some(enable) = &self.enable {
+            enable.set_value_cansleep(false);
+        }
+        if let Some(ldo_enable) = &self.ldo_enable {
+            ldo_enable.set_value_cansleep(false);
+        }
+        ret
+    }
+}
+
+impl leds::Operations for Isa1200 {
+    fn brightness_set(&self, brightness: u32) -> Result {
+        let mut state = self.state.lock();
+        if brightness == 0 {
+            if let Source::Input(pwm) = &mut state.source {
+                pwm.apply(&pwm::State {
+                    enabled: false,
+                    ..pwm.state()
+                })?;
+            }
+            if state.on {
+                state.on = false;
+                self.power_off()?;
+            }
+            return Ok(());
+        }
+
+        if !state.on {
+            self.power_on(&state.source)?;
+            state.on = true;
+        }
+        match &mut state.source {
+            Source::Input(pwm) => {
+                let mut pwm_state = pwm.init_state();
+                pwm_state.duty_cycle = Self::duty(pwm_state.period, brightness);
+                pwm_state.enabled = true;
+                pwm.apply(&pwm_state)
+            }
+            Source::Generator(period) => {
+                let duty = Self::duty((*period).into(), brightness);
+                self.regmap.write(HCTRL5, duty as u32)
+            }
+        }
+    }
+}
+
+struct DeviceData {
+    led: Pin<Box<leds::Registration<Isa1200>>>,
+}
+
+struct Isa1200Driver;
+
+#[vtable]
+impl i2c::Driver for Isa1200Driver {
+    type Data = Box<DeviceData>;
+
+    const NAME: &'static CStr = c_str!("isa1200");
+    const OF_MATCH: of::IdTable = kernel::of_id_table![c_str!("imagis,isa1200")];
+
+    fn probe(client: &i2c::Client) -> Result<Box<DeviceData>> {
+        let dev: &Device = client.device();
+        let np = dev.of_node().ok_or(ENODEV)?;
+
+        let source = if np.has_property(c_str!("pwms")) {
+            Source::Input(Pwm::get(dev, None)?)
+        } else {
+            let period = np.read_u32(c_str!("imagis,period"))?;
+            Source::Generator(u8::try_from(period).map_err(|_| EINVAL)?)
+        };
+        let enable = gpio::Desc::get_optional(dev, Some(c_str!("enable")), gpio::Flags::OutLow)?;
+        let ldo_enable =
+            gpio::Desc::get_optional(dev, Some(c_str!("ldo-enable")), gpio::Flags::OutLow)?;
+
+        let isa1200 = Isa1200 {
+            regmap: Regmap::new_i2c(dev, &CONFIG)?,
+            enable,
+            ldo_enable,
+            erm: np.has_property(c_str!("imagis,erm")),
+            state: Box::pin_init(kernel::new_mutex!(
+                State { source, on: false },
+                "Isa1200::state"
+            ))?,
+        };
+
+        let config = leds::Config {
+            name: CString::try_from_fmt(fmt!("vibrator"))?,
+            max_brightness: MAX_BRIGHTNESS,
+            default_trigger: Some(c_str!("transient")),
+        };
+        let led = Box::pin_init(leds::Registration::register(dev, config, isa1200))?;
+        Ok(Box::try_new(DeviceData { led })?)
+    }
+
+    fn shutdown(_client: &i2c::Client, data: &DeviceData) {
+        let _ = leds::Operations::brightness_set(data.led.ops(), 0);
+    }
+}
@@ -0,0 +1,688 @@
+// SPDX-License-Identifier: GPL-2.0
+/*
+ * Non-trivial C macros cannot be used in Rust. Similarly, inlined C functions
+ * cannot be called either. This file explicitly creates functions ("helpers")
+ * that wrap those so that they can be called from Rust.
+ *
+ * Even though Rust kernel modules should never use directly the bindings, some
+ * of these helpers need to be exported because Rust generics and inlined
+ * functions may not get their code generated in the crate where they are
+ * defined. Other helpers, called from non-inline functions, may not be
+ * exported, in principle. However, in general, the Rust compiler does not
+ * guarantee codegen will be performed for a non-inline function either.
+ * Therefore, this file exports all the helpers. In the future, this may be
+ * revisited to reduce the number of exports after the compiler is informed
+ * about the places codegen is required.
+ *
+ * All symbols are exported as GPL-only to guarantee no GPL-only feature is
+ * accidentally exposed.
+ */
+
+#include <asm/barrier.h>
+#include <linux/bitops.h>
+#include <linux/bug.h>
+#include <linux/build_bug.h>
+#include <linux/cred.h>
+#include <linux/delay.h>
+#include <linux/err.h>
+#include <linux/errname.h>
+#include <linux/etherdevice.h>
+#include <linux/freezer.h>
+#include <linux/io.h>
+#include <linux/jiffies.h>
+#include <linux/kernel.h>
+#include <linux/mutex.h>
+#include <linux/netdevice.h>
+#include <linux/percpu.h>
+#include <linux/pid_namespace.h>
+#include <linux/preempt.h>
+#include <linux/rbtree.h>
+#include <linux/refcount.h>
+#include <linux/sched/signal.h>
+#include <linux/skbuff.h>
+#include <linux/spinlock.h>
+#include <linux/uidgid.h>
+#include <linux/uio.h>
+#include <linux/wait.h>
+#include <linux/workqueue.h>
+#include <linux/xarray.h>
+#include <net/net_namespace.h>
+#include <net/netns/generic.h>
+
+__noreturn void rust_helper_BUG(void)
+{
+	BUG();
+}
+EXPORT_SYMBOL_GPL(rust_helper_BUG);
+
+void rust_helper_mutex_lock(struct mutex *lock)
+{
+	mutex_lock(lock);
+}
+EXPORT_SYMBOL_GPL(rust_helper_mutex_lock);
+
+void rust_helper___spin_lock_init(spinlock_t *lock, const char *name,
+				  struct lock_class_key *key)
+{
+#ifdef CONFIG_DEBUG_SPINLOCK
+	__raw_spin_lock_init(spinlock_check(lock), name, key);
+#else
+	spin_lock_init(lock);
+#endif
+}
+EXPORT_SYMBOL_GPL(rust_helper___spin_lock_init);
+
+void rust_helper_spin_lock(spinlock_t *lock)
+{
+	spin_lock(lock);
+}
+EXPORT_SYMBOL_GPL(rust_helper_spin_lock);
+
+void rust_helper_spin_unlock(spinlock_t *lock)
+{
+	spin_unlock(lock);
+}
+EXPORT_SYMBOL_GPL(rust_helper_spin_unlock);
+
+size_t rust_helper_copy_to_iter(const void *addr, size_t bytes,
+				 struct iov_iter *i)
+{
+	return copy_to_iter(addr, bytes, i);
+}
+EXPORT_SYMBOL_GPL(rust_helper_copy_to_iter);
+
+size_t rust_helper_copy_from_iter(void *addr, size_t bytes,
+				   struct iov_iter *i)
+{
+	return copy_from_iter(addr, bytes, i);
+}
+EXPORT_SYMBOL_GPL(rust_helper_copy_from_iter);
+
+size_t rust_helper_iov_iter_count(const struct iov_iter *i)
+{
+	return iov_iter_count(i);
+}
+EXPORT_SYMBOL_GPL(rust_helper_iov_iter_count);
+
+void rust_helper_init_wait(struct wait_queue_entry *wq_entry)
+{
+	init_wait(wq_entry);
+}
+EXPORT_SYMBOL_GPL(rust_helper_init_wait);
+
+void rust_helper_init_work(struct work_struct *work, work_func_t func)
+{
+	INIT_WORK(work, func);
+}
+EXPORT_SYMBOL_GPL(rust_helper_init_work);
+
+int rust_helper_signal_pending(struct task_struct *t)
+{
+	return signal_pending(t);
+}
+EXPORT_SYMBOL_GPL(rust_helper_signal_pending);
+
+void rust_helper_might_resched(void)
+{
+	might_resched();
+}
+EXPORT_SYMBOL_GPL(rust_helper_might_resched);
+
+struct net *rust_helper_maybe_get_net(struct net *net)
+{
+	return maybe_get_net(net);
+}
+EXPORT_SYMBOL_GPL(rust_helper_maybe_get_net);
+
+refcount_t rust_helper_REFCOUNT_INIT(int n)
+{
+	return (refcount_t)REFCOUNT_INIT(n);
+}
+EXPORT_SYMBOL_GPL(rust_helper_REFCOUNT_INIT);
+
+void rust_helper_refcount_inc(refcount_t *r)
+{
+	refcount_inc(r);
+}
+EXPORT_SYMBOL_GPL(rust_helper_refcount_inc);
+
+bool rust_helper_refcount_dec_and_test(refcount_t *r)
+{
+	return refcount_dec_and_test(r);
+}
+EXPORT_SYMBOL_GPL(rust_helper_refcount_dec_and_test);
+
+__force void *rust_helper_ERR_PTR(long err)
+{
+	return ERR_PTR(err);
+}
+EXPORT_SYMBOL_GPL(rust_helper_ERR_PTR);
+
+bool rust_helper_IS_ERR(__force const void *ptr)
+{
+	return IS_ERR(ptr);
+}
+EXPORT_SYMBOL_GPL(rust_helper_IS_ERR);
+
+long rust_helper_PTR_ERR(__force const void *ptr)
+{
+	return PTR_ERR(ptr);
+}
+EXPORT_SYMBOL_GPL(rust_helper_PTR_ERR);
+
+const char *rust_helper_errname(int err)
+{
+	return errname(err);
+}
+EXPORT_SYMBOL_GPL(rust_helper_errname);
+
+struct task_struct *rust_helper_get_current(void)
+{
+	return current;
+}
+EXPORT_SYMBOL_GPL(rust_helper_get_current);
+
+void rust_helper_get_task_struct(struct task_struct *t)
+{
+	get_task_struct(t);
+}
+EXPORT_SYMBOL_GPL(rust_helper_get_task_struct);
+
+void rust_helper_put_task_struct(struct task_struct *t)
+{
+	put_task_struct(t);
+}
+EXPORT_SYMBOL_GPL(rust_helper_put_task_struct);
+
+#ifdef CONFIG_HAS_IOPORT
+u8 rust_helper_inb(unsigned long addr)
+{
+	return inb(addr);
+}
+EXPORT_SYMBOL_GPL(rust_helper_inb);
+
+u16 rust_helper_inw(unsigned long addr)
+{
+	return inw(addr);
+}
+EXPORT_SYMBOL_GPL(rust_helper_inw);
+
+u32 rust_helper_inl(unsigned long addr)
+{
+	return inl(addr);
+}
+EXPORT_SYMBOL_GPL(rust_helper_inl);
+
+void rust_helper_outb(u8 value, unsigned long addr)
+{
+	outb(value, addr);
+}
+EXPORT_SYMBOL_GPL(rust_helper_outb);
+
+void rust_helper_outw(u16 value, unsigned long addr)
+{
+	outw(value, addr);
+}
+EXPORT_SYMBOL_GPL(rust_helper_outw);
+
+void rust_helper_outl(u32 value, unsigned long addr)
+{
+	outl(value, addr);
+}
+EXPORT_SYMBOL_GPL(rust_helper_outl);
+#endif
+
+kuid_t rust_helper_task_uid(struct task_struct *task)
+{
+	return task_uid(task);
+}
+EXPORT_SYMBOL_GPL(rust_helper_task_uid);
+
+kuid_t rust_helper_task_euid(struct task_struct *task)
+{
+	return task_euid(task);
+}
+EXPORT_SYMBOL_GPL(rust_helper_task_euid);
+
+struct user_namespace *rust_helper_current_user_ns(void)
+{
+	return current_user_ns();
+}
+EXPORT_SYMBOL_GPL(rust_helper_current_user_ns);
+
+bool rust_helper_uid_valid(kuid_t uid)
+{
+	return uid_valid(uid);
+}
+EXPORT_SYMBOL_GPL(rust_helper_uid_valid);
+
+bool rust_helper_gid_valid(kgid_t gid)
+{
+	return gid_valid(gid);
+}
+EXPORT_SYMBOL_GPL(rust_helper_gid_valid);
+
+struct pid *rust_helper_task_tgid(struct task_struct *task)
+{
+	return task_tgid(task);
+}
+EXPORT_SYMBOL_GPL(rust_helper_task_tgid);
+
+pid_t rust_helper_task_pid_vnr(struct task_struct *task)
+{
+	return task_pid_vnr(task);
+}
+EXPORT_SYMBOL_GPL(rust_helper_task_pid_vnr);
+
+pid_t rust_helper_task_tgid_vnr(struct task_struct *task)
+{
+	return task_tgid_vnr(task);
+}
+EXPORT_SYMBOL_GPL(rust_helper_task_tgid_vnr);
+
+struct pid_namespace *rust_helper_get_pid_ns(struct pid_namespace *ns)
+{
+	return get_pid_ns(ns);
+}
+EXPORT_SYMBOL_GPL(rust_helper_get_pid_ns);
+
+bool rust_helper_freezing(struct task_struct *p)
+{
+	return freezing(p);
+}
+EXPORT_SYMBOL_GPL(rust_helper_freezing);
+
+bool rust_helper_try_to_freeze(void)
+{
+	return try_to_freeze();
+}
+EXPORT_SYMBOL_GPL(rust_helper_try_to_freeze);
+
+void rust_helper_set_current_state(unsigned int state)
+{
+	set_current_state(state);
+}
+EXPORT_SYMBOL_GPL(rust_helper_set_current_state);
+
+unsigned long rust_helper_msecs_to_jiffies(const unsigned int m)
+{
+	return msecs_to_jiffies(m);
+}
+EXPORT_SYMBOL_GPL(rust_helper_msecs_to_jiffies);
+
+unsigned long rust_helper_usecs_to_jiffies(const unsigned int u)
+{
+	return usecs_to_jiffies(u);
+}
+EXPORT_SYMBOL_GPL(rust_helper_usecs_to_jiffies);
+
+void rust_helper_fsleep(unsigned long usecs)
+{
+	fsleep(usecs);
+}
+EXPORT_SYMBOL_GPL(rust_helper_fsleep);
+
+void rust_helper_udelay(unsigned long usecs)
+{
+	udelay(usecs);
+}
+EXPORT_SYMBOL_GPL(rust_helper_udelay);
+
+void rust_helper_mdelay(unsigned long msecs)
+{
+	mdelay(msecs);
+}
+EXPORT_SYMBOL_GPL(rust_helper_mdelay);
+
+void *rust_helper_netdev_priv(const struct net_device *dev)
+{
+	return netdev_priv(dev);
+}
+EXPORT_SYMBOL_GPL(rust_helper_netdev_priv);
+
+bool rust_helper_netif_carrier_ok(const struct net_device *dev)
+{
+	return netif_carrier_ok(dev);
+}
+EXPORT_SYMBOL_GPL(rust_helper_netif_carrier_ok);
+
+void rust_helper_netif_start_queue(struct net_device *dev)
+{
+	netif_start_queue(dev);
+}
+EXPORT_SYMBOL_GPL(rust_helper_netif_start_queue);
+
+void rust_helper_netif_stop_queue(struct net_device *dev)
+{
+	netif_stop_queue(dev);
+}
+EXPORT_SYMBOL_GPL(rust_helper_netif_stop_queue);
+
+void rust_helper_netif_wake_queue(struct net_device *dev)
+{
+	netif_wake_queue(dev);
+}
+EXPORT_SYMBOL_GPL(rust_helper_netif_wake_queue);
+
+bool rust_helper_netif_queue_stopped(const struct net_device *dev)
+{
+	return netif_queue_stopped(dev);
+}
+EXPORT_SYMBOL_GPL(rust_helper_netif_queue_stopped);
+
+void rust_helper_dev_hold(struct net_device *dev)
+{
+	dev_hold(dev);
+}
+EXPORT_SYMBOL_GPL(rust_helper_dev_hold);
+
+void rust_helper_dev_put(struct net_device *dev)
+{
+	dev_put(dev);
+}
+EXPORT_SYMBOL_GPL(rust_helper_dev_put);
+
+void rust_helper_dev_kfree_skb_any(struct sk_buff *skb)
+{
+	dev_kfree_skb_any(skb);
+}
+EXPORT_SYMBOL_GPL(rust_helper_dev_kfree_skb_any);
+
+void rust_helper_dev_consume_skb_any(struct sk_buff *skb)
+{
+	dev_consume_skb_any(skb);
+}
+EXPORT_SYMBOL_GPL(rust_helper_dev_consume_skb_any);
+
+void rust_helper_eth_hw_addr_set(struct net_device *dev, const u8 *addr)
+{
+	eth_hw_addr_set(dev, addr);
+}
+EXPORT_SYMBOL_GPL(rust_helper_eth_hw_addr_set);
+
+void rust_helper_eth_hw_addr_random(struct net_device *dev)
+{
+	eth_hw_addr_random(dev);
+}
+EXPORT_SYMBOL_GPL(rust_helper_eth_hw_addr_random);
+
+unsigned int rust_helper_skb_headlen(const struct sk_buff *skb)
+{
+	return skb_headlen(skb);
+}
+EXPORT_SYMBOL_GPL(rust_helper_skb_headlen);
+
+unsigned int rust_helper_skb_headroom(const struct sk_buff *skb)
+{
+	return skb_headroom(skb);
+}
+EXPORT_SYMBOL_GPL(rust_helper_skb_headroom);
+
+int rust_helper_skb_tailroom(const struct sk_buff *skb)
+{
+	return skb_tailroom(skb);
+}
+EXPORT_SYMBOL_GPL(rust_helper_skb_tailroom);
+
+void rust_helper_skb_reserve(struct sk_buff *skb, int len)
+{
+	skb_reserve(skb, len);
+}
+EXPORT_SYMBOL_GPL(rust_helper_skb_reserve);
+
+bool rust_helper_pskb_may_pull(struct sk_buff *skb, unsigned int len)
+{
+	return pskb_may_pull(skb, len);
+}
+EXPORT_SYMBOL_GPL(rust_helper_pskb_may_pull);
+
+void rust_helper_netif_napi_del(struct napi_struct *napi)
+{
+	netif_napi_del(napi);
+}
+EXPORT_SYMBOL_GPL(rust_helper_netif_napi_del);
+
+struct net *rust_helper_get_net(struct net *net)
+{
+	return get_net(net);
+}
+EXPORT_SYMBOL_GPL(rust_helper_get_net);
+
+void rust_helper_put_net(struct net *net)
+{
+	put_net(net);
+}
+EXPORT_SYMBOL_GPL(rust_helper_put_net);
+
+void *rust_helper_net_generic(const struct net *net, unsigned int id)
+{
+	return net_generic(net, id);
+}
+EXPORT_SYMBOL_GPL(rust_helper_net_generic);
+
+struct net *rust_helper_dev_net(const struct net_device *dev)
+{
+	return dev_net(dev);
+}
+EXPORT_SYMBOL_GPL(rust_helper_dev_net);
+
+void rust_helper_dev_net_set(struct net_device *dev, struct net *net)
+{
+	dev_net_set(dev, net);
+}
+EXPORT_SYMBOL_GPL(rust_helper_dev_net_set);
+
+gfp_t rust_helper_XA_FLAGS_ALLOC(void)
+{
+	return XA_FLAGS_ALLOC;
+}
+EXPORT_SYMBOL_GPL(rust_helper_XA_FLAGS_ALLOC);
+
+gfp_t rust_helper_XA_FLAGS_ALLOC1(void)
+{
+	return XA_FLAGS_ALLOC1;
+}
+EXPORT_SYMBOL_GPL(rust_helper_XA_FLAGS_ALLOC1);
+
+xa_mark_t rust_helper_XA_PRESENT(void)
+{
+	return XA_PRESENT;
+}
+EXPORT_SYMBOL_GPL(rust_helper_XA_PRESENT);
+
+void rust_helper_xa_init_flags(struct xarray *xa, gfp_t flags)
+{
+	xa_init_flags(xa, flags);
+}
+EXPORT_SYMBOL_GPL(rust_helper_xa_init_flags);
+
+void rust_helper_xa_lock(struct xarray *xa)
+{
+	xa_lock(xa);
+}
+EXPORT_SYMBOL_GPL(rust_helper_xa_lock);
+
+void rust_helper_xa_unlock(struct xarray *xa)
+{
+	xa_unlock(xa);
+}
+EXPORT_SYMBOL_GPL(rust_helper_xa_unlock);
+
+int rust_helper_xa_err(void *entry)
+{
+	return xa_err(entry);
+}
+EXPORT_SYMBOL_GPL(rust_helper_xa_err);
+
+int rust_helper_xa_insert(struct xarray *xa, unsigned long index, void *entry,
+			  gfp_t gfp)
+{
+	return xa_insert(xa, index, entry, gfp);
+}
+EXPORT_SYMBOL_GPL(rust_helper_xa_insert);
+
+int rust_helper_xa_reserve(struct xarray *xa, unsigned long index, gfp_t gfp)
+{
+	return xa_reserve(xa, index, gfp);
+}
+EXPORT_SYMBOL_GPL(rust_helper_xa_reserve);
+
+void rust_helper_xa_release(struct xarray *xa, unsigned long index)
+{
+	xa_release(xa, index);
+}
+EXPORT_SYMBOL_GPL(rust_helper_xa_release);
+
+int rust_helper_xa_alloc(struct xarray *xa, u32 *id, void *entry,
+			 struct xa_limit limit, gfp_t gfp)
+{
+	return xa_alloc(xa, id, entry, limit, gfp);
+}
+EXPORT_SYMBOL_GPL(rust_helper_xa_alloc);
+
+int rust_helper_xa_alloc_cyclic(struct xarray *xa, u32 *id, void *entry,
+				struct xa_limit limit, u32 *next, gfp_t gfp)
+{
+	return xa_alloc_cyclic(xa, id, entry, limit, next, gfp);
+}
+EXPORT_SYMBOL_GPL(rust_helper_xa_alloc_cyclic);
+
+void rust_helper_rb_link_node(struct rb_node *node, struct rb_node *parent,
+			      struct rb_node **rb_link)
+{
+	rb_link_node(node, parent, rb_link);
+}
+EXPORT_SYMBOL_GPL(rust_helper_rb_link_node);
+
+void rust_helper_set_bit(long nr, volatile unsigned long *addr)
+{
+	set_bit(nr, addr);
+}
+EXPORT_SYMBOL_GPL(rust_helper_set_bit);
+
+void rust_helper_clear_bit(long nr, volatile unsigned long *addr)
+{
+	clear_bit(nr, addr);
+}
+EXPORT_SYMBOL_GPL(rust_helper_clear_bit);
+
+void rust_helper_change_bit(long nr, volatile unsigned long *addr)
+{
+	change_bit(nr, addr);
+}
+EXPORT_SYMBOL_GPL(rust_helper_change_bit);
+
+bool rust_helper_test_and_set_bit(long nr, volatile unsigned long *addr)
+{
+	return test_and_set_bit(nr, addr);
+}
+EXPORT_SYMBOL_GPL(rust_helper_test_and_set_bit);
+
+bool rust_helper_test_and_clear_bit(long nr, volatile unsigned long *addr)
+{
+	return test_and_clear_bit(nr, addr);
+}
+EXPORT_SYMBOL_GPL(rust_helper_test_and_clear_bit);
+
+bool rust_helper_test_bit(long nr, const volatile unsigned long *addr)
+{
+	return test_bit(nr, addr);
+}
+EXPORT_SYMBOL_GPL(rust_helper_test_bit);
+
+void rust_helper_mb(void)
+{
+	mb();
+}
+EXPORT_SYMBOL_GPL(rust_helper_mb);
+
+void rust_helper_rmb(void)
+{
+	rmb();
+}
+EXPORT_SYMBOL_GPL(rust_helper_rmb);
+
+void rust_helper_wmb(void)
+{
+	wmb();
+}
+EXPORT_SYMBOL_GPL(rust_helper_wmb);
+
+void rust_helper_dma_rmb(void)
+{
+	dma_rmb();
+}
+EXPORT_SYMBOL_GPL(rust_helper_dma_rmb);
+
+void rust_helper_dma_wmb(void)
+{
+	dma_wmb();
+}
+EXPORT_SYMBOL_GPL(rust_helper_dma_wmb);
+
+void rust_helper_smp_mb(void)
+{
+	smp_mb();
+}
+EXPORT_SYMBOL_GPL(rust_helper_smp_mb);
+
+void rust_helper_smp_rmb(void)
+{
+	smp_rmb();
+}
+EXPORT_SYMBOL_GPL(rust_helper_smp_rmb);
+
+void rust_helper_smp_wmb(void)
+{
+	smp_wmb();
+}
+EXPORT_SYMBOL_GPL(rust_helper_smp_wmb);
+
+void rust_helper_preempt_disable(void)
+{
+	preempt_disable();
+}
+EXPORT_SYMBOL_GPL(rust_helper_preempt_disable);
+
+void rust_helper_preempt_enable(void)
+{
+	preempt_enable();
+}
+EXPORT_SYMBOL_GPL(rust_helper_preempt_enable);
+
+#ifdef CONFIG_DEBUG_ATOMIC_SLEEP
+/*
+ * The atomic sections entered by Rust code on each CPU. The layout of the
+ * entries is defined by `Cpu` in `rust/kernel/context.rs`.
+ */
+static DEFINE_PER_CPU(unsigned long [3], rust_atomic_sections);
+
+void *rust_helper_this_cpu_atomic_sections(void)
+{
+	return this_cpu_ptr(&rust_atomic_sections);
+}
+EXPORT_SYMBOL_GPL(rust_helper_this_cpu_atomic_sections);
+#endif
+
+/*
+ * We use `bindgen`'s `--size_t-is-usize` option to bind the C `size_t` type
+ * as the Rust `usize` type, so we can use it in contexts where Rust
+ * expects a `usize` like slice (array) indices. `usize` is defined to be
+ * the same as C's `uintptr_t` type (can hold any pointer) but not
+ * necessarily the same as `size_t` (can hold the size of any single
+ * object). Most modern platforms use the same concrete integer type for
+ * both of them, but in case we find ourselves on a platform where
+ * that's not true, fail early instead of risking ABI or
+ * integer-overflow issues.
+ *
+ * If your platform fails this assertion, it means that you are in
+ * danger of integer-overflow bugs (even if you attempt to add
+ * `--no-size_t-is-usize`). It may be easiest to change the kernel ABI on
+ * your platform such that `size_t` matches `uintptr_t` (i.e., to increase
+ * `size_t`, because `uintptr_t` has to be at least as big as `size_t`).
+ */
+static_assert(
+	sizeof(size_t) == sizeof(uintptr_t) &&
+	__alignof__(size_t) == __alignof__(uintptr_t),
+	"Rust code expects C `size_t` to match Rust `usize`"
+);
@@ -7,10 +7,13 @@
 
 use crate::bindings;
 
+// All allocations are done with `GFP_KERNEL`, so they may sleep and must be annotated as such with
+// `might_sleep!`. This flags allocations from atomic context even when the slab fast path is hit.
 struct KernelAllocator;
 
 unsafe impl GlobalAlloc for KernelAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        crate::might_sleep!();
         // `krealloc()` is used instead of `kmalloc()` because the latter is
         // an inline function and cannot be bound to as a result.
         unsafe { bindings::krealloc(ptr::null(), layout.size(), bindings::GFP_KERNEL) as *mut u8 }
@@ -33,6 +36,7 @@ unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
 // Note that `#[no_mangle]` implies exported too, nowadays.
 #[no_mangle]
 fn __rust_alloc(size: usize, _align: usize) -> *mut u8 {
+    crate::might_sleep!();
     unsafe { bindings::krealloc(core::ptr::null(), size, bindings::GFP_KERNEL) as *mut u8 }
 }
 
@@ -43,6 +47,7 @@ fn __rust_dealloc(ptr: *mut u8, _size: usize, _align: usize) {
 
 #[no_mangle]
 fn __rust_realloc(ptr: *mut u8, _old_size: usize, _align: usize, new_size: usize) -> *mut u8 {
+    crate::might_sleep!();
     unsafe {
         bindings::krealloc(
             ptr as *const core::ffi::c_void,
@@ -54,6 +59,7 @@ fn __rust_realloc(ptr: *mut u8, _old_size: usize, _align: usize, new_size: usize
 
 #[no_mangle]
 fn __rust_alloc_zeroed(size: usize, _align: usize) -> *mut u8 {
+    crate::might_sleep!();
     unsafe {
         bindings::krealloc(
             core::ptr::null(),
@@ -0,0 +1,303 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Backlight devices.
+//!
+//! A backlight driver registers a [`Backlight`], which user space controls through
+//! `/sys/class/backlight`, and display drivers through the devicetree node of its device.
+//!
+//! C header: [`include/linux/backlight.h`](../../../../include/linux/backlight.h)
+
+use crate::{
+    bindings, device,
+    error::{code::*, from_err_ptr, from_result, to_result, Result},
+    str::CStr,
+    types::Opaque,
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::c_int,
+    marker::{PhantomData, PhantomPinned},
+    mem::MaybeUninit,
+    pin::Pin,
+    ptr,
+};
+use macros::vtable;
+
+/// The kind of control of a backlight, the kernel's `enum backlight_type`.
+///
+/// User space prefers firmware interfaces to platform ones, and those to raw ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Type {
+    /// The hardware is controlled directly, e.g. through a PWM output.
+    Raw,
+    /// The hardware is controlled through a platform interface.
+    Platform,
+    /// The hardware is controlled through a firmware interface.
+    Firmware,
+}
+
+impl Type {
+    fn as_raw(self) -> bindings::backlight_type {
+        match self {
+            Self::Raw => bindings::backlight_type_BACKLIGHT_RAW,
+            Self::Platform => bindings::backlight_type_BACKLIGHT_PLATFORM,
+            Self::Firmware => bindings::backlight_type_BACKLIGHT_FIRMWARE,
+        }
+    }
+}
+
+/// The initial properties of a backlight, the kernel's `struct backlight_properties`.
+#[derive(Clone, Copy, Debug)]
+pub struct Properties {
+    /// The kind of control of the backlight.
+    pub kind: Type,
+    /// The maximum brightness.
+    pub max_brightness: u32,
+    /// The initial brightness.
+    pub brightness: u32,
+    /// Whether the backlight is initially powered on.
+    pub powered: bool,
+}
+
+/// The operations of a backlight, the kernel's `struct backlight_ops`.
+///
+/// The driver data of the backlight implements this trait.
+#[vtable]
+pub trait Operations: Send + Sync + Sized + 'static {
+    /// Applies the brightness and power state of the backlight to the hardware.
+    ///
+    /// This is called when either changes, including when the system suspends and resumes.
+    /// [`Device::brightness`] returns the brightness to apply, which is zero when the backlight
+    /// is blanked.
+    fn update_status(bl: &Device<Self>) -> Result;
+
+    /// Returns the brightness of the hardware, if it can differ from the requested one.
+    fn get_brightness(_bl: &Device<Self>) -> Result<u32> {
+        Err(EINVAL)
+    }
+}
+
+/// A backlight device, the kernel's `struct backlight_device`.
+///
+/// # Invariants
+///
+/// The device is valid while references to it exist, and its driver data is a `Backlight<T>`.
+#[repr(transparent)]
+pub struct Device<T: Operations>(Opaque<bindings::backlight_device>, PhantomData<T>);
+
+impl<T: Operations> Device<T> {
+    /// Creates a reference to a [`Device`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is a backlight registered by a `Backlight<T>` for the
+    /// lifetime of the returned reference.
+    unsafe fn from_raw<'a>(ptr: *mut bindings::backlight_device) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct backlight_device` pointer.
+    pub fn as_raw(&self) -> *mut bindings::backlight_device {
+        self.0.get()
+    }
+
+    /// Returns the driver data of the backlight.
+    pub fn data(&self) -> &T {
+        // SAFETY: The driver data of the device is a `Backlight<T>` by the type invariants,
+        // which outlives the device.
+        unsafe { &(*bindings::bl_get_data(self.as_raw()).cast::<Backlight<T>>()).data }
+    }
+
+    /// Returns the brightness to apply, which is zero when the backlight is blanked or suspended.
+    pub fn brightness(&self) -> u32 {
+        // SAFETY: The device is valid by the type invariants.
+        unsafe { bindings::backlight_get_brightness(self.as_raw()) as _ }
+    }
+
+    /// Returns the maximum brightness.
+    pub fn max_brightness(&self) -> u32 {
+        // SAFETY: The device is valid by the type invariants, and the maximum never changes.
+        unsafe { (*self.as_raw()).props.max_brightness as _ }
+    }
+
+    /// Returns whether the backlight is blanked, i.e. powered off or suspended.
+    pub fn is_blank(&self) -> bool {
+        // SAFETY: The device is valid by the type invariants.
+        unsafe { bindings::backlight_is_blank(self.as_raw()) }
+    }
+
+    /// Requests the brightness `brightness`, as if written by user space.
+    ///
+    /// Fails with `EINVAL` if it is above the maximum brightness.
+    pub fn set_brightness(&self, brightness: u32) -> Result {
+        // SAFETY: The device is valid by the type invariants.
+        to_result(unsafe {
+            bindings::backlight_device_set_brightness(self.as_raw(), brightness as _)
+        })
+    }
+
+    /// Powers the backlight on, e.g. when the display is enabled.
+    pub fn enable(&self) -> Result {
+        // SAFETY: The device is valid by the type invariants.
+        to_result(unsafe { bindings::backlight_enable(self.as_raw()) })
+    }
+
+    /// Powers the backlight off, e.g. when the display is disabled.
+    pub fn disable(&self) -> Result {
+        // SAFETY: The device is valid by the type invariants.
+        to_result(unsafe { bindings::backlight_disable(self.as_raw()) })
+    }
+}
+
+// SAFETY: The methods that take `&self` are safe to call concurrently, and the driver data is
+// `Sync`.
+unsafe impl<T: Operations> Sync for Device<T> {}
+
+/// A registered backlight.
+///
+/// The backlight is unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `bd` is null until the backlight is registered by [`Backlight::register`], and then a
+/// backlight device whose driver data is this `Backlight<T>`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, device::Device, prelude::*, pwm::Pwm, sync::{Arc, Mutex}};
+/// use kernel::backlight::{self, Backlight, Operations, Properties};
+///
+/// struct PwmBacklight {
+///     pwm: Arc<Mutex<Pwm>>,
+/// }
+///
+/// #[vtable]
+/// impl Operations for PwmBacklight {
+///     fn update_status(bl: &backlight::Device<Self>) -> Result {
+///         let mut pwm = bl.data().pwm.lock();
+///         let mut state = pwm.init_state();
+///         state.duty_cycle = state.period * bl.brightness() as u64 / bl.max_brightness() as u64;
+///         state.enabled = !bl.is_blank();
+///         pwm.apply(&state)
+///     }
+/// }
+///
+/// fn probe(dev: &Device, pwm: Arc<Mutex<Pwm>>) -> Result<Pin<Box<Backlight<PwmBacklight>>>> {
+///     let props = Properties {
+///         kind: backlight::Type::Raw,
+///         max_brightness: 255,
+///         brightness: 128,
+///         powered: true,
+///     };
+///     Backlight::register(dev, c_str!("pwm-backlight"), &props, PwmBacklight { pwm })
+/// }
+/// ```
+pub struct Backlight<T: Operations> {
+    bd: *mut bindings::backlight_device,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: Operations> Backlight<T> {
+    const OPS: bindings::backlight_ops = bindings::backlight_ops {
+        options: bindings::BL_CORE_SUSPENDRESUME,
+        update_status: Some(Self::update_status_callback),
+        get_brightness: if T::HAS_GET_BRIGHTNESS {
+            Some(Self::get_brightness_callback)
+        } else {
+            None
+        },
+        // SAFETY: All other fields are optional, for which zero is valid.
+        ..unsafe { MaybeUninit::zeroed().assume_init() }
+    };
+
+    /// Registers a backlight named `name` whose parent is `parent`, with the properties `props`
+    /// and the driver data `data`.
+    pub fn register(
+        parent: &device::Device,
+        name: &CStr,
+        props: &Properties,
+        data: T,
+    ) -> Result<Pin<Box<Self>>> {
+        crate::might_sleep!();
+        let mut bl = Pin::from(Box::try_new(Self {
+            bd: ptr::null_mut(),
+            data,
+            _pin: PhantomPinned,
+        })?);
+
+        // SAFETY: All other fields are optional, for which zero is valid.
+        let mut raw: bindings::backlight_properties =
+            unsafe { MaybeUninit::zeroed().assume_init() };
+        raw.type_ = props.kind.as_raw();
+        raw.max_brightness = props.max_brightness as _;
+        raw.brightness = props.brightness.min(props.max_brightness) as _;
+        raw.power = if props.powered {
+            bindings::FB_BLANK_UNBLANK
+        } else {
+            bindings::FB_BLANK_POWERDOWN
+        } as _;
+
+        let this: *const Self = &*bl;
+        // SAFETY: `parent` is valid, `name` and `raw` are copied, and the ops are static. The
+        // driver data is pinned, and the backlight is unregistered before it is freed.
+        let bd = from_err_ptr(unsafe {
+            bindings::backlight_device_register(
+                name.as_char_ptr(),
+                parent.as_raw(),
+                this as *mut _,
+                &Self::OPS,
+                &raw,
+            )
+        })?;
+        // INVARIANT: The backlight was registered above, with this as its driver data.
+        // SAFETY: `bd` isn't structurally pinned.
+        unsafe { bl.as_mut().get_unchecked_mut() }.bd = bd;
+        Ok(bl)
+    }
+
+    /// Returns the backlight device.
+    pub fn device(&self) -> &Device<T> {
+        // SAFETY: The backlight is registered once it is returned by `register`, by the type
+        // invariants, and its driver data is `self`.
+        unsafe { Device::from_raw(self.bd) }
+    }
+
+    /// Returns the driver data of the backlight.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    unsafe extern "C" fn update_status_callback(bd: *mut bindings::backlight_device) -> c_int {
+        from_result(|| {
+            // SAFETY: The backlight core calls this with a backlight registered by `register`.
+            T::update_status(unsafe { Device::from_raw(bd) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn get_brightness_callback(bd: *mut bindings::backlight_device) -> c_int {
+        from_result(|| {
+            // SAFETY: The backlight core calls this with a backlight registered by `register`.
+            Ok(T::get_brightness(unsafe { Device::from_raw(bd) })? as _)
+        })
+    }
+}
+
+impl<T: Operations> Drop for Backlight<T> {
+    fn drop(&mut self) {
+        if !self.bd.is_null() {
+            // SAFETY: The backlight was registered by the type invariants.
+            unsafe { bindings::backlight_device_unregister(self.bd) };
+        }
+    }
+}
+
+// SAFETY: The backlight can be unregistered from any thread, and the driver data is `Send`.
+unsafe impl<T: Operations> Send for Backlight<T> {}
+
+// SAFETY: The methods that take `&self` only read fields that never change, and the driver data
+// is `Sync`.
+unsafe impl<T: Operations> Sync for Backlight<T> {}
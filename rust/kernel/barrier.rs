@@ -0,0 +1,108 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Memory barriers.
+//!
+//! These are the kernel's barriers, which order accesses to memory as seen by other CPUs and by
+//! devices. [`core::sync::atomic::fence`] only orders accesses between CPUs, and compiles to
+//! instructions that are too weak for devices on some architectures (e.g. `dmb ish` on arm64,
+//! which doesn't cover the outer shareable domain of DMA masters), so drivers use these instead.
+//!
+//! The barriers come in three flavours:
+//!
+//! - [`mb`], [`rmb`] and [`wmb`] order all accesses, including to I/O memory. They are the
+//!   strongest, and the most expensive.
+//! - [`dma_rmb`] and [`dma_wmb`] order accesses to coherent DMA memory, e.g. descriptors shared
+//!   with a device. They don't order accesses to I/O memory.
+//! - [`smp_mb`], [`smp_rmb`] and [`smp_wmb`] order accesses to normal memory between CPUs. They
+//!   are compiler barriers on uniprocessor kernels.
+//!
+//! Barriers don't make memory coherent: buffers mapped for DMA on non-coherent devices must also
+//! be synchronised with the functions of [`crate::cache`].
+//!
+//! C header: [`include/asm-generic/barrier.h`](../../../../include/asm-generic/barrier.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/core-api/wrappers/memory-barriers.html>
+
+use crate::bindings;
+
+/// Orders all memory accesses before the barrier before all memory accesses after it, the
+/// kernel's `mb()`.
+///
+/// This includes accesses to I/O memory and to DMA memory.
+#[inline]
+pub fn mb() {
+    // SAFETY: Barriers have no safety requirements.
+    unsafe { bindings::mb() };
+}
+
+/// Orders all reads before the barrier before all reads after it, the kernel's `rmb()`.
+///
+/// E.g. it orders the read of a status register of a device before the reads of the buffer that
+/// the device filled.
+#[inline]
+pub fn rmb() {
+    // SAFETY: Barriers have no safety requirements.
+    unsafe { bindings::rmb() };
+}
+
+/// Orders all writes before the barrier before all writes after it, the kernel's `wmb()`.
+///
+/// E.g. it orders the writes of a buffer before the write of a doorbell register that makes the
+/// device read it. The `writel()` accessors of [`crate::io_mem::IoMem`] already include it, but
+/// the `_relaxed` ones don't.
+#[inline]
+pub fn wmb() {
+    // SAFETY: Barriers have no safety requirements.
+    unsafe { bindings::wmb() };
+}
+
+/// Orders reads of coherent DMA memory before the barrier before those after it, the kernel's
+/// `dma_rmb()`.
+///
+/// E.g. it orders the read of the ownership bit of a descriptor, which the device clears once it
+/// is done, before the reads of the other fields of the descriptor.
+#[inline]
+pub fn dma_rmb() {
+    // SAFETY: Barriers have no safety requirements.
+    unsafe { bindings::dma_rmb() };
+}
+
+/// Orders writes to coherent DMA memory before the barrier before those after it, the kernel's
+/// `dma_wmb()`.
+///
+/// E.g. it orders the writes of the fields of a descriptor before the write of its ownership bit,
+/// which hands it to the device. A [`wmb`] is still needed before notifying the device through
+/// I/O memory, unless the accessor includes it.
+#[inline]
+pub fn dma_wmb() {
+    // SAFETY: Barriers have no safety requirements.
+    unsafe { bindings::dma_wmb() };
+}
+
+/// Orders accesses to normal memory before the barrier before those after it, as seen by other
+/// CPUs, the kernel's `smp_mb()`.
+#[inline]
+pub fn smp_mb() {
+    // SAFETY: Barriers have no safety requirements.
+    unsafe { bindings::smp_mb() };
+}
+
+/// Orders reads of normal memory before the barrier before those after it, as seen by other
+/// CPUs, the kernel's `smp_rmb()`.
+///
+/// It pairs with an [`smp_wmb`] on the writing CPU.
+#[inline]
+pub fn smp_rmb() {
+    // SAFETY: Barriers have no safety requirements.
+    unsafe { bindings::smp_rmb() };
+}
+
+/// Orders writes to normal memory before the barrier before those after it, as seen by other
+/// CPUs, the kernel's `smp_wmb()`.
+///
+/// It pairs with an [`smp_rmb`] on the reading CPU.
+#[inline]
+pub fn smp_wmb() {
+    // SAFETY: Barriers have no safety requirements.
+    unsafe { bindings::smp_wmb() };
+}
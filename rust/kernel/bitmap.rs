@@ -0,0 +1,403 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Bitmaps and bit operations.
+//!
+//! [`Bitmap`] is a bitmap of a fixed maximum size, e.g. embedded in a driver's state or in a
+//! static, and [`BitmapVec`] is allocated at runtime, for sizes that are only known then, e.g.
+//! from the device tree. Both have the same methods:
+//!
+//! - Atomic bit operations, e.g. `test_and_set_bit`, which take shared references and may be
+//!   called concurrently, also from atomic context.
+//! - Non-atomic ones, e.g. `set` and the region helpers, which take mutable references.
+//! - Searches, e.g. `find_first_zero_bit`, which may run concurrently with atomic operations but
+//!   may then miss bits that change meanwhile.
+//!
+//! Bit indices must be smaller than the length of the bitmap, or the methods panic, like slice
+//! indexing.
+//!
+//! C headers: [`include/linux/bitmap.h`](../../../../include/linux/bitmap.h),
+//! [`include/linux/bitops.h`](../../../../include/linux/bitops.h) and
+//! [`include/linux/find.h`](../../../../include/linux/find.h)
+//!
+//! # Examples
+//!
+//! Allocating hardware channels:
+//!
+//! ```
+//! use kernel::{bitmap::{self, Bitmap}, prelude::*};
+//!
+//! const CHANNELS: usize = 24;
+//!
+//! static USED: Bitmap<{ bitmap::bits_to_longs(CHANNELS) }> = Bitmap::new(CHANNELS);
+//!
+//! fn request_channel() -> Result<usize> {
+//!     loop {
+//!         let ch = USED.find_first_zero_bit().ok_or(EBUSY)?;
+//!         // Another thread may have taken the channel since it was found.
+//!         if !USED.test_and_set_bit(ch) {
+//!             return Ok(ch);
+//!         }
+//!     }
+//! }
+//!
+//! fn release_channel(ch: usize) {
+//!     USED.clear_bit(ch);
+//! }
+//! ```
+
+use crate::{
+    bindings,
+    error::{code::*, to_result, Result},
+};
+use alloc::vec::Vec;
+use core::{
+    cell::UnsafeCell,
+    ffi::{c_uint, c_ulong},
+    mem::MaybeUninit,
+};
+
+/// The number of bits in a `long`, `BITS_PER_LONG` in C.
+pub const BITS_PER_LONG: usize = c_ulong::BITS as usize;
+
+/// Returns the number of `long`s that hold `nbits` bits, like `BITS_TO_LONGS` in C.
+///
+/// Unlike the C macro, this doesn't overflow for `nbits` close to `usize::MAX`.
+pub const fn bits_to_longs(nbits: usize) -> usize {
+    // `usize::div_ceil` isn't stable yet.
+    nbits / BITS_PER_LONG + (nbits % BITS_PER_LONG != 0) as usize
+}
+
+/// Converts the result of a C search, which is `nbits` if nothing was found.
+fn found(ret: c_ulong, nbits: usize) -> Option<usize> {
+    let ret = ret as usize;
+    if ret < nbits {
+        Some(ret)
+    } else {
+        None
+    }
+}
+
+macro_rules! impl_bitmap {
+    ($ty:ty, $($gen:tt)*) => {
+        impl<$($gen)*> $ty {
+            /// Returns the number of bits of the bitmap.
+            pub fn len(&self) -> usize {
+                self.nbits
+            }
+
+            /// Returns whether the bitmap has no bits.
+            pub fn is_empty(&self) -> bool {
+                self.nbits == 0
+            }
+
+            fn as_ptr(&self) -> *mut c_ulong {
+                self.words().as_ptr() as *mut c_ulong
+            }
+
+            fn check(&self, bit: usize) -> usize {
+                assert!(bit < self.nbits, "bit {} out of range for bitmap of {}", bit, self.nbits);
+                bit
+            }
+
+            fn check_range(&self, start: usize, nr: usize) {
+                assert!(
+                    start <= self.nbits && nr <= self.nbits - start,
+                    "bits {}+{} out of range for bitmap of {}",
+                    start,
+                    nr,
+                    self.nbits
+                );
+            }
+
+            /// Atomically sets `bit`.
+            pub fn set_bit(&self, bit: usize) {
+                let bit = self.check(bit);
+                // SAFETY: The bitmap is valid, and `bit` is in range. The bits may be changed
+                // concurrently, since they are in `UnsafeCell`s.
+                unsafe { bindings::set_bit(bit as _, self.as_ptr()) };
+            }
+
+            /// Atomically clears `bit`.
+            pub fn clear_bit(&self, bit: usize) {
+                let bit = self.check(bit);
+                // SAFETY: As in `set_bit`.
+                unsafe { bindings::clear_bit(bit as _, self.as_ptr()) };
+            }
+
+            /// Atomically flips `bit`.
+            pub fn change_bit(&self, bit: usize) {
+                let bit = self.check(bit);
+                // SAFETY: As in `set_bit`.
+                unsafe { bindings::change_bit(bit as _, self.as_ptr()) };
+            }
+
+            /// Atomically sets `bit`, and returns whether it was set before.
+            ///
+            /// This implies a full memory barrier.
+            pub fn test_and_set_bit(&self, bit: usize) -> bool {
+                let bit = self.check(bit);
+                // SAFETY: As in `set_bit`.
+                unsafe { bindings::test_and_set_bit(bit as _, self.as_ptr()) }
+            }
+
+            /// Atomically clears `bit`, and returns whether it was set before.
+            ///
+            /// This implies a full memory barrier.
+            pub fn test_and_clear_bit(&self, bit: usize) -> bool {
+                let bit = self.check(bit);
+                // SAFETY: As in `set_bit`.
+                unsafe { bindings::test_and_clear_bit(bit as _, self.as_ptr()) }
+            }
+
+            /// Returns whether `bit` is set.
+            pub fn test_bit(&self, bit: usize) -> bool {
+                let bit = self.check(bit);
+                // SAFETY: As in `set_bit`.
+                unsafe { bindings::test_bit(bit as _, self.as_ptr()) }
+            }
+
+            /// Sets `bit`, without atomicity.
+            pub fn set(&mut self, bit: usize) {
+                let bit = self.check(bit);
+                *self.words_mut()[bit / BITS_PER_LONG].get_mut() |= 1 << (bit % BITS_PER_LONG);
+            }
+
+            /// Clears `bit`, without atomicity.
+            pub fn clear(&mut self, bit: usize) {
+                let bit = self.check(bit);
+                *self.words_mut()[bit / BITS_PER_LONG].get_mut() &= !(1 << (bit % BITS_PER_LONG));
+            }
+
+            /// Clears all the bits.
+            pub fn zero(&mut self) {
+                for word in self.words_mut() {
+                    *word.get_mut() = 0;
+                }
+            }
+
+            /// Sets bits `start` to `start + nr - 1`, without atomicity.
+            pub fn set_range(&mut self, start: usize, nr: usize) {
+                self.check_range(start, nr);
+                // SAFETY: The bitmap is valid and mutably borrowed, and the range is in it.
+                unsafe { bindings::__bitmap_set(self.as_ptr(), start as _, nr as _) };
+            }
+
+            /// Clears bits `start` to `start + nr - 1`, without atomicity.
+            pub fn clear_range(&mut self, start: usize, nr: usize) {
+                self.check_range(start, nr);
+                // SAFETY: The bitmap is valid and mutably borrowed, and the range is in it.
+                unsafe { bindings::__bitmap_clear(self.as_ptr(), start as _, nr as _) };
+            }
+
+            /// Returns the number of bits that are set.
+            pub fn weight(&self) -> usize {
+                // SAFETY: The bitmap is valid for `nbits` bits.
+                unsafe { bindings::__bitmap_weight(self.as_ptr(), self.nbits as _) as usize }
+            }
+
+            /// Returns the first bit that is set, if any.
+            pub fn find_first_bit(&self) -> Option<usize> {
+                // SAFETY: The bitmap is valid for `nbits` bits.
+                let ret = unsafe { bindings::_find_first_bit(self.as_ptr(), self.nbits as _) };
+                found(ret, self.nbits)
+            }
+
+            /// Returns the first bit that is clear, if any.
+            pub fn find_first_zero_bit(&self) -> Option<usize> {
+                // SAFETY: The bitmap is valid for `nbits` bits.
+                let ret = unsafe { bindings::_find_first_zero_bit(self.as_ptr(), self.nbits as _) };
+                found(ret, self.nbits)
+            }
+
+            /// Returns the first bit at `start` or above that is set, if any.
+            pub fn find_next_bit(&self, start: usize) -> Option<usize> {
+                if start >= self.nbits {
+                    return None;
+                }
+                // SAFETY: The bitmap is valid for `nbits` bits.
+                let ret = unsafe {
+                    bindings::_find_next_bit(self.as_ptr(), self.nbits as _, start as _)
+                };
+                found(ret, self.nbits)
+            }
+
+            /// Returns the first bit at `start` or above that is clear, if any.
+            pub fn find_next_zero_bit(&self, start: usize) -> Option<usize> {
+                if start >= self.nbits {
+                    return None;
+                }
+                // SAFETY: The bitmap is valid for `nbits` bits.
+                let ret = unsafe {
+                    bindings::_find_next_zero_bit(self.as_ptr(), self.nbits as _, start as _)
+                };
+                found(ret, self.nbits)
+            }
+
+            /// Returns an iterator over the bits that are set, in increasing order.
+            pub fn iter_set(&self) -> impl Iterator<Item = usize> + '_ {
+                let mut next = self.find_first_bit();
+                core::iter::from_fn(move || {
+                    let bit = next?;
+                    next = self.find_next_bit(bit + 1);
+                    Some(bit)
+                })
+            }
+
+            /// Returns the start of the first run of `nr` clear bits at `start` or above, whose
+            /// start is aligned to `align`, a power of two, if any.
+            ///
+            /// The run can then be allocated with [`Self::set_range`].
+            pub fn find_next_zero_area(
+                &self,
+                start: usize,
+                nr: usize,
+                align: usize,
+            ) -> Option<usize> {
+                if !align.is_power_of_two() {
+                    return None;
+                }
+                // SAFETY: The bitmap is valid for `nbits` bits.
+                let ret = unsafe {
+                    bindings::bitmap_find_next_zero_area_off(
+                        self.as_ptr(),
+                        self.nbits as _,
+                        start as _,
+                        nr as _,
+                        (align - 1) as _,
+                        0,
+                    )
+                };
+                // The C function returns a start past the end of the bitmap if there is no run.
+                match ret as usize {
+                    pos if pos <= self.nbits && nr <= self.nbits - pos => Some(pos),
+                    _ => None,
+                }
+            }
+
+            /// Finds a free region of `1 << order` bits, aligned to its size, and allocates it.
+            ///
+            /// Returns the start of the region, or `ENOMEM` if there is none.
+            pub fn find_free_region(&mut self, order: u32) -> Result<usize> {
+                // SAFETY: The bitmap is valid for `nbits` bits and mutably borrowed.
+                let ret = unsafe {
+                    bindings::bitmap_find_free_region(self.as_ptr(), self.nbits as _, order as _)
+                };
+                to_result(ret)?;
+                Ok(ret as usize)
+            }
+
+            /// Allocates the region of `1 << order` bits at `pos`, which must be aligned to its
+            /// size.
+            ///
+            /// Fails with `EBUSY` if any bit of the region is already set.
+            pub fn allocate_region(&mut self, pos: usize, order: u32) -> Result {
+                self.check_region(pos, order)?;
+                // SAFETY: The bitmap is valid and mutably borrowed, and the region is in it.
+                to_result(unsafe {
+                    bindings::bitmap_allocate_region(self.as_ptr(), pos as _, order as _)
+                })
+            }
+
+            /// Releases the region of `1 << order` bits at `pos`, which was allocated by
+            /// [`Self::find_free_region`] or [`Self::allocate_region`].
+            pub fn release_region(&mut self, pos: usize, order: u32) {
+                if self.check_region(pos, order).is_ok() {
+                    // SAFETY: The bitmap is valid and mutably borrowed, and the region is in it.
+                    unsafe { bindings::bitmap_release_region(self.as_ptr(), pos as _, order as _) };
+                }
+            }
+
+            fn check_region(&self, pos: usize, order: u32) -> Result {
+                let size = 1usize.checked_shl(order).ok_or(EINVAL)?;
+                if pos % size != 0 || pos > self.nbits || size > self.nbits - pos {
+                    return Err(EINVAL);
+                }
+                Ok(())
+            }
+        }
+
+        // SAFETY: The bitmap is only changed atomically through shared references.
+        unsafe impl<$($gen)*> Sync for $ty {}
+    };
+}
+
+/// A bitmap of at most `LONGS * BITS_PER_LONG` bits, stored inline.
+///
+/// Use [`bits_to_longs`] to compute `LONGS` from the number of bits.
+///
+/// # Invariants
+///
+/// `nbits` is at most `LONGS * BITS_PER_LONG`.
+pub struct Bitmap<const LONGS: usize> {
+    words: [UnsafeCell<c_ulong>; LONGS],
+    nbits: usize,
+}
+
+impl<const LONGS: usize> Bitmap<LONGS> {
+    /// Creates a bitmap of `nbits` bits, which are all clear.
+    ///
+    /// Panics if `nbits` is larger than `LONGS * BITS_PER_LONG`, at compile time when used in a
+    /// constant or static.
+    pub const fn new(nbits: usize) -> Self {
+        assert!(nbits <= LONGS * BITS_PER_LONG);
+        // INVARIANT: `nbits` is checked above.
+        Self {
+            // SAFETY: Zero is a valid `UnsafeCell<c_ulong>`.
+            words: unsafe { MaybeUninit::zeroed().assume_init() },
+            nbits,
+        }
+    }
+
+    fn words(&self) -> &[UnsafeCell<c_ulong>] {
+        &self.words
+    }
+
+    fn words_mut(&mut self) -> &mut [UnsafeCell<c_ulong>] {
+        &mut self.words
+    }
+}
+
+impl_bitmap!(Bitmap<LONGS>, const LONGS: usize);
+
+/// A bitmap allocated at runtime.
+///
+/// # Invariants
+///
+/// `words` holds at least `nbits` bits.
+pub struct BitmapVec {
+    words: Vec<UnsafeCell<c_ulong>>,
+    nbits: usize,
+}
+
+impl BitmapVec {
+    /// Allocates a bitmap of `nbits` bits, which are all clear.
+    ///
+    /// Fails with `EINVAL` if `nbits` doesn't fit the `unsigned int` lengths of the C bitmap
+    /// functions, and with `ENOMEM` if the size of the bitmap overflows.
+    pub fn try_new(nbits: usize) -> Result<Self> {
+        if c_uint::try_from(nbits).is_err() {
+            return Err(EINVAL);
+        }
+        let longs = bits_to_longs(nbits);
+        longs
+            .checked_mul(core::mem::size_of::<c_ulong>())
+            .filter(|&size| size <= isize::MAX as usize)
+            .ok_or(ENOMEM)?;
+        let mut words = Vec::try_with_capacity(longs)?;
+        for _ in 0..longs {
+            words.try_push(UnsafeCell::new(0))?;
+        }
+        // INVARIANT: `words` was allocated for `nbits` bits above.
+        Ok(Self { words, nbits })
+    }
+
+    fn words(&self) -> &[UnsafeCell<c_ulong>] {
+        &self.words
+    }
+
+    fn words_mut(&mut self) -> &mut [UnsafeCell<c_ulong>] {
+        &mut self.words
+    }
+}
+
+impl_bitmap!(BitmapVec,);
@@ -0,0 +1,88 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Block devices.
+//!
+//! I/O is described by bios ([`bio::Bio`]), each of which covers a contiguous range of sectors
+//! and holds a list of memory segments. Drivers using the multi-queue block layer receive bios
+//! merged into requests ([`mq::Request`]).
+//!
+//! C header: [`include/linux/blkdev.h`](../../../../include/linux/blkdev.h)
+
+use crate::{bindings, error::Result};
+
+pub mod bio;
+pub mod mq;
+
+/// The size of a sector in bytes. Positions and lengths of I/O are expressed in sectors of this
+/// size regardless of the block size of the device.
+pub const SECTOR_SIZE: u32 = 1 << SECTOR_SHIFT;
+
+/// The base-2 logarithm of [`SECTOR_SIZE`].
+pub const SECTOR_SHIFT: u32 = bindings::SECTOR_SHIFT;
+
+/// The operation of a bio or request, the kernel's `enum req_op`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReqOp {
+    /// Reads sectors.
+    Read,
+    /// Writes sectors.
+    Write,
+    /// Flushes the volatile write cache.
+    Flush,
+    /// Discards sectors.
+    Discard,
+    /// Securely erases sectors.
+    SecureErase,
+    /// Writes zeroes to sectors, without transferring data.
+    WriteZeroes,
+    /// Any other operation, e.g. for zoned devices or driver-private requests.
+    Other(u32),
+}
+
+impl ReqOp {
+    fn from_opf(opf: bindings::blk_opf_t) -> Self {
+        match opf & bindings::req_op_REQ_OP_MASK {
+            bindings::req_op_REQ_OP_READ => Self::Read,
+            bindings::req_op_REQ_OP_WRITE => Self::Write,
+            bindings::req_op_REQ_OP_FLUSH => Self::Flush,
+            bindings::req_op_REQ_OP_DISCARD => Self::Discard,
+            bindings::req_op_REQ_OP_SECURE_ERASE => Self::SecureErase,
+            bindings::req_op_REQ_OP_WRITE_ZEROES => Self::WriteZeroes,
+            op => Self::Other(op),
+        }
+    }
+
+    /// Returns `true` if the operation carries data, i.e. has memory segments.
+    pub fn has_data(self) -> bool {
+        !matches!(
+            self,
+            Self::Flush | Self::Discard | Self::SecureErase | Self::WriteZeroes
+        )
+    }
+
+    fn as_raw(self) -> u32 {
+        match self {
+            Self::Read => bindings::req_op_REQ_OP_READ,
+            Self::Write => bindings::req_op_REQ_OP_WRITE,
+            Self::Flush => bindings::req_op_REQ_OP_FLUSH,
+            Self::Discard => bindings::req_op_REQ_OP_DISCARD,
+            Self::SecureErase => bindings::req_op_REQ_OP_SECURE_ERASE,
+            Self::WriteZeroes => bindings::req_op_REQ_OP_WRITE_ZEROES,
+            Self::Other(op) => op,
+        }
+    }
+
+    /// Returns `true` if the operation modifies the device, like `op_is_write` in C.
+    pub fn is_write(self) -> bool {
+        self.as_raw() & 1 != 0
+    }
+}
+
+/// Converts the result of an I/O into a `blk_status_t`, where `0` is `BLK_STS_OK`.
+fn to_blk_status(status: Result) -> bindings::blk_status_t {
+    match status {
+        Ok(()) => 0,
+        // SAFETY: `errno_to_blk_status` accepts any error code.
+        Err(e) => unsafe { bindings::errno_to_blk_status(e.to_errno()) },
+    }
+}
@@ -0,0 +1,397 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Bios, the unit of block I/O.
+//!
+//! A bio describes an operation on a contiguous range of sectors, and the memory that data is
+//! transferred from or to as a vector of segments. Owned bios (e.g. those passed to bio-based
+//! drivers) are represented by [`Bio`], which must be completed with [`Bio::end_io`]. Bios that
+//! are part of a request are accessed through a shared [`BioRef`].
+//!
+//! C header: [`include/linux/bio.h`](../../../../include/linux/bio.h)
+
+use super::{to_blk_status, ReqOp, SECTOR_SHIFT};
+use crate::{
+    bindings,
+    device::Device,
+    dma::DataDirection,
+    error::{code::*, Result},
+    types::{ARef, Opaque},
+};
+use core::{marker::PhantomData, mem::ManuallyDrop, ops::Deref, ptr::NonNull};
+
+/// A shared reference to a bio, the kernel's `struct bio`.
+///
+/// # Invariants
+///
+/// The bio is valid and is not completed while it is shared.
+#[repr(transparent)]
+pub struct BioRef(Opaque<bindings::bio>);
+
+impl BioRef {
+    /// Creates a reference to a [`BioRef`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is valid, non-null, and not completed for the lifetime of
+    /// the returned reference.
+    pub unsafe fn as_ref<'a>(ptr: *mut bindings::bio) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct bio` pointer.
+    pub fn as_raw(&self) -> *mut bindings::bio {
+        self.0.get()
+    }
+
+    /// Returns the operation of the bio.
+    pub fn op(&self) -> ReqOp {
+        // SAFETY: The bio is valid by the type invariants.
+        ReqOp::from_opf(unsafe { (*self.as_raw()).bi_opf })
+    }
+
+    /// Returns the first sector of the bio.
+    pub fn sector(&self) -> u64 {
+        // SAFETY: The bio is valid by the type invariants.
+        unsafe { (*self.as_raw()).bi_iter.bi_sector }
+    }
+
+    /// Returns the remaining size of the bio in bytes.
+    pub fn size(&self) -> u32 {
+        // SAFETY: The bio is valid by the type invariants.
+        unsafe { (*self.as_raw()).bi_iter.bi_size }
+    }
+
+    /// Returns an iterator over the single-page segments of the bio, like
+    /// `bio_for_each_segment` in C.
+    ///
+    /// The iterator is empty for operations without data (see [`ReqOp::has_data`]).
+    pub fn segments(&self) -> Segments<'_> {
+        // SAFETY: The bio is valid by the type invariants.
+        let bio = unsafe { &*self.as_raw() };
+        let iter = if self.op().has_data() {
+            bio.bi_iter
+        } else {
+            bindings::bvec_iter {
+                bi_size: 0,
+                ..bio.bi_iter
+            }
+        };
+        // INVARIANT: `iter` is the iterator of the bio, which is valid.
+        Segments {
+            bvecs: bio.bi_io_vec,
+            iter,
+            _p: PhantomData,
+        }
+    }
+}
+
+/// An owned bio.
+///
+/// The bio is completed with an I/O error if it is dropped without calling [`Bio::end_io`].
+///
+/// # Invariants
+///
+/// The pointer is a valid bio that we are responsible for completing.
+pub struct Bio(NonNull<bindings::bio>);
+
+// SAFETY: Bios can be completed from any context and thread.
+unsafe impl Send for Bio {}
+
+// SAFETY: `Bio` only gives shared access to the bio through `&self`.
+unsafe impl Sync for Bio {}
+
+impl Bio {
+    /// Takes over the responsibility of completing a bio.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a valid bio that the caller is responsible for completing, e.g. one passed
+    /// to `submit_bio`.
+    pub unsafe fn from_raw(ptr: NonNull<bindings::bio>) -> Self {
+        // INVARIANT: Guaranteed by the safety requirements of the function.
+        Self(ptr)
+    }
+
+    /// Completes the bio with the given status, like `bio_endio` in C.
+    ///
+    /// This may be called from any context.
+    pub fn end_io(self, status: Result) {
+        let bio = ManuallyDrop::new(self);
+        // SAFETY: We are responsible for completing the bio by the type invariants, and `bio`
+        // isn't dropped so it is only completed here.
+        unsafe {
+            (*bio.0.as_ptr()).bi_status = to_blk_status(status);
+            bindings::bio_endio(bio.0.as_ptr());
+        }
+    }
+}
+
+impl Deref for Bio {
+    type Target = BioRef;
+
+    fn deref(&self) -> &BioRef {
+        // SAFETY: The bio is valid and isn't completed while `self` is borrowed.
+        unsafe { BioRef::as_ref(self.0.as_ptr()) }
+    }
+}
+
+impl Drop for Bio {
+    fn drop(&mut self) {
+        // SAFETY: We are responsible for completing the bio by the type invariants.
+        unsafe {
+            (*self.0.as_ptr()).bi_status = to_blk_status(Err(EIO));
+            bindings::bio_endio(self.0.as_ptr());
+        }
+    }
+}
+
+/// An iterator over a chain of bios, e.g. those of a request.
+///
+/// # Invariants
+///
+/// `next` is either null or a valid bio whose chain isn't completed for `'a`.
+pub struct Bios<'a> {
+    next: *mut bindings::bio,
+    _p: PhantomData<&'a BioRef>,
+}
+
+impl<'a> Bios<'a> {
+    /// Creates an iterator over the bios chained from `first` with `bi_next`.
+    ///
+    /// # Safety
+    ///
+    /// `first` must be null or a valid bio, and neither it nor the bios chained to it may be
+    /// completed for `'a`.
+    pub(crate) unsafe fn new(first: *mut bindings::bio) -> Self {
+        // INVARIANT: Guaranteed by the safety requirements of the function.
+        Self {
+            next: first,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<'a> Iterator for Bios<'a> {
+    type Item = &'a BioRef;
+
+    fn next(&mut self) -> Option<&'a BioRef> {
+        if self.next.is_null() {
+            return None;
+        }
+        // SAFETY: `next` is a valid bio that isn't completed for `'a` by the type invariants.
+        let bio = unsafe { BioRef::as_ref(self.next) };
+        // INVARIANT: `bi_next` is either null or the next bio in the chain.
+        // SAFETY: See above.
+        self.next = unsafe { (*self.next).bi_next };
+        Some(bio)
+    }
+}
+
+/// A memory segment of a bio that lies within a single page, the kernel's `struct bio_vec`.
+pub struct Segment<'a> {
+    bvec: bindings::bio_vec,
+    sector: u64,
+    _p: PhantomData<&'a BioRef>,
+}
+
+impl<'a> Segment<'a> {
+    /// Returns the first sector of the device that the segment is transferred from or to.
+    pub fn sector(&self) -> u64 {
+        self.sector
+    }
+
+    /// Returns the length of the segment in bytes.
+    pub fn len(&self) -> usize {
+        self.bvec.bv_len as _
+    }
+
+    /// Returns `true` if the segment is empty.
+    pub fn is_empty(&self) -> bool {
+        self.bvec.bv_len == 0
+    }
+
+    /// Returns the offset of the segment in its page.
+    pub fn page_offset(&self) -> usize {
+        self.bvec.bv_offset as _
+    }
+
+    /// Returns the page of the segment.
+    pub fn page(&self) -> *mut bindings::page {
+        self.bvec.bv_page
+    }
+
+    /// Copies the contents of the segment to the start of `dst`, e.g. to handle a write.
+    ///
+    /// Fails with `EINVAL` if `dst` is shorter than the segment.
+    pub fn copy_to_slice(&self, dst: &mut [u8]) -> Result {
+        if dst.len() < self.len() {
+            return Err(EINVAL);
+        }
+        // SAFETY: The page is valid while the bio is alive, and the segment lies within it.
+        // `dst` was checked to be long enough above.
+        unsafe {
+            let vaddr = bindings::kmap_local_page(self.bvec.bv_page);
+            core::ptr::copy_nonoverlapping(
+                vaddr.cast::<u8>().add(self.page_offset()),
+                dst.as_mut_ptr(),
+                self.len(),
+            );
+            bindings::kunmap_local(vaddr);
+        }
+        Ok(())
+    }
+
+    /// Copies the start of `src` to the segment, e.g. to handle a read.
+    ///
+    /// Fails with `EINVAL` if `src` is shorter than the segment.
+    pub fn copy_from_slice(&mut self, src: &[u8]) -> Result {
+        if src.len() < self.len() {
+            return Err(EINVAL);
+        }
+        // SAFETY: The page is valid while the bio is alive, and the segment lies within it.
+        // `src` was checked to be long enough above.
+        unsafe {
+            let vaddr = bindings::kmap_local_page(self.bvec.bv_page);
+            core::ptr::copy_nonoverlapping(
+                src.as_ptr(),
+                vaddr.cast::<u8>().add(self.page_offset()),
+                self.len(),
+            );
+            bindings::kunmap_local(vaddr);
+        }
+        Ok(())
+    }
+
+    /// Maps the segment for DMA by `dev`.
+    ///
+    /// The mapping is undone when the returned object is dropped, which must happen before the
+    /// bio is completed.
+    pub fn dma_map(&self, dev: &Device, dir: DataDirection) -> Result<SegmentMapping<'a>> {
+        if crate::fault_inject::should_fail_dma(self.len()) {
+            return Err(ENOMEM);
+        }
+        // SAFETY: `dev` is valid, and the segment lies within its page, which is valid while the
+        // bio is alive.
+        let addr = unsafe {
+            bindings::dma_map_page_attrs(
+                dev.as_raw(),
+                self.bvec.bv_page,
+                self.page_offset(),
+                self.len(),
+                dir.as_raw(),
+                0,
+            )
+        };
+        // SAFETY: `addr` was just returned for `dev`.
+        if unsafe { bindings::dma_mapping_error(dev.as_raw(), addr) } != 0 {
+            return Err(ENOMEM);
+        }
+        // INVARIANT: The segment was mapped above.
+        Ok(SegmentMapping {
+            dev: dev.into(),
+            addr,
+            len: self.len(),
+            dir,
+            _p: PhantomData,
+        })
+    }
+}
+
+/// A segment of a bio mapped for DMA.
+///
+/// # Invariants
+///
+/// `addr` is a mapping of `len` bytes for `dev` in the direction `dir`, returned by
+/// `dma_map_page_attrs`.
+pub struct SegmentMapping<'a> {
+    dev: ARef<Device>,
+    addr: bindings::dma_addr_t,
+    len: usize,
+    dir: DataDirection,
+    _p: PhantomData<&'a BioRef>,
+}
+
+impl SegmentMapping<'_> {
+    /// Returns the bus address of the segment, to be programmed into the device.
+    pub fn dma_addr(&self) -> bindings::dma_addr_t {
+        self.addr
+    }
+
+    /// Returns the length of the mapping in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the mapping is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Drop for SegmentMapping<'_> {
+    fn drop(&mut self) {
+        // SAFETY: The mapping is valid by the type invariants.
+        unsafe {
+            bindings::dma_unmap_page_attrs(
+                self.dev.as_raw(),
+                self.addr,
+                self.len,
+                self.dir.as_raw(),
+                0,
+            )
+        };
+    }
+}
+
+/// An iterator over the single-page segments of a bio, see [`BioRef::segments`].
+///
+/// # Invariants
+///
+/// `iter` is an iterator over `bvecs`, the vector of a bio that isn't completed for `'a`.
+pub struct Segments<'a> {
+    bvecs: *mut bindings::bio_vec,
+    iter: bindings::bvec_iter,
+    _p: PhantomData<&'a BioRef>,
+}
+
+impl<'a> Iterator for Segments<'a> {
+    type Item = Segment<'a>;
+
+    fn next(&mut self) -> Option<Segment<'a>> {
+        if self.iter.bi_size == 0 {
+            return None;
+        }
+        let page_size = bindings::PAGE_SIZE as u32;
+        // SAFETY: The iterator isn't done, so `bi_idx` is within the vector, which is valid by the
+        // type invariants.
+        let bvec = unsafe { &*self.bvecs.add(self.iter.bi_idx as _) };
+
+        // This is `bvec_iter_bvec` in C: the current multi-page segment is clamped to the rest of
+        // the bio, then to the page that the iterator is in.
+        let mp_len = self.iter.bi_size.min(bvec.bv_len - self.iter.bi_bvec_done);
+        let mp_offset = bvec.bv_offset + self.iter.bi_bvec_done;
+        let offset = mp_offset % page_size;
+        let len = mp_len.min(page_size - offset);
+        let segment = Segment {
+            bvec: bindings::bio_vec {
+                // SAFETY: The pages of a multi-page segment are contiguous.
+                bv_page: unsafe { bvec.bv_page.add((mp_offset / page_size) as _) },
+                bv_len: len,
+                bv_offset: offset,
+            },
+            sector: self.iter.bi_sector,
+            _p: PhantomData,
+        };
+
+        // This is `bio_advance_iter_single` in C.
+        self.iter.bi_sector += u64::from(len >> SECTOR_SHIFT);
+        self.iter.bi_size -= len;
+        self.iter.bi_bvec_done += len;
+        if self.iter.bi_bvec_done == bvec.bv_len {
+            self.iter.bi_bvec_done = 0;
+            self.iter.bi_idx += 1;
+        }
+        Some(segment)
+    }
+}
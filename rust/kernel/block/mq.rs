@@ -0,0 +1,20 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! The multi-queue block layer.
+//!
+//! Drivers using the multi-queue block layer implement [`Operations`] and allocate a [`TagSet`],
+//! which holds the requests of their hardware queues. Disks are then set up and added with a
+//! [`GenDiskBuilder`]. I/O is received as requests ([`Request`]), each made of one or more bios
+//! covering contiguous sectors.
+//!
+//! C header: [`include/linux/blk-mq.h`](../../../../include/linux/blk-mq.h)
+
+mod gen_disk;
+mod operations;
+mod request;
+mod tag_set;
+
+pub use gen_disk::{GenDisk, GenDiskBuilder};
+pub use operations::{Operations, QueueRqResult};
+pub use request::{Request, RequestSegments};
+pub use tag_set::TagSet;
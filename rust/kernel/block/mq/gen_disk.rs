@@ -0,0 +1,389 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Disks of blk-mq drivers.
+//!
+//! C header: [`include/linux/blkdev.h`](../../../../../include/linux/blkdev.h)
+
+use super::{Operations, TagSet};
+use crate::{
+    bindings,
+    block::SECTOR_SHIFT,
+    error::{code::*, from_err_ptr, to_result, Result},
+    str::{CStr, CString},
+    sync::Arc,
+    types::ForeignOwnable,
+};
+use core::{fmt, ptr, ptr::NonNull};
+
+/// The block device operations of disks created by [`GenDiskBuilder`].
+///
+/// None of the callbacks are implemented, so there is no need for an owner to be pinned while the
+/// disk is open.
+const FOPS: bindings::block_device_operations =
+    // SAFETY: All fields are optional, for which zero is valid.
+    unsafe { core::mem::MaybeUninit::zeroed().assume_init() };
+
+/// A builder for [`GenDisk`], which sets up the queue limits of the disk.
+///
+/// The limits are validated when the disk is built, and are left at the block layer's defaults
+/// unless set.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::block::mq::{GenDisk, GenDiskBuilder, Operations, QueueRqResult, Request, TagSet};
+/// # use kernel::prelude::*;
+///
+/// struct NullBlk;
+///
+/// #[vtable]
+/// impl Operations for NullBlk {
+///     type QueueData = ();
+///
+///     fn queue_rq(_data: (), rq: Request, _is_last: bool) -> QueueRqResult {
+///         rq.start();
+///         rq.end_ok();
+///         QueueRqResult::Queued
+///     }
+/// }
+///
+/// fn create() -> Result<GenDisk<NullBlk>> {
+///     let tagset = TagSet::try_new(1, 256)?;
+///     GenDiskBuilder::new()
+///         .capacity_sectors(1 << 21)
+///         .logical_block_size(4096)
+///         .rotational(false)
+///         .build(fmt!("rnullb{}", 0), tagset, ())
+/// }
+/// ```
+pub struct GenDiskBuilder {
+    capacity_sectors: u64,
+    logical_block_size: u32,
+    physical_block_size: u32,
+    max_hw_sectors: u32,
+    max_segments: u16,
+    max_segment_size: u32,
+    max_discard_sectors: u32,
+    discard_granularity: u32,
+    rotational: bool,
+    write_cache: bool,
+    fua: bool,
+    removable: bool,
+    read_only: bool,
+}
+
+impl Default for GenDiskBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GenDiskBuilder {
+    /// Creates a builder for an empty, non-rotational disk with 512-byte blocks.
+    pub const fn new() -> Self {
+        Self {
+            capacity_sectors: 0,
+            logical_block_size: 512,
+            physical_block_size: 0,
+            max_hw_sectors: 0,
+            max_segments: 0,
+            max_segment_size: 0,
+            max_discard_sectors: 0,
+            discard_granularity: 0,
+            rotational: false,
+            write_cache: false,
+            fua: false,
+            removable: false,
+            read_only: false,
+        }
+    }
+
+    /// Sets the initial capacity of the disk in sectors of [`crate::block::SECTOR_SIZE`] bytes.
+    pub fn capacity_sectors(mut self, capacity: u64) -> Self {
+        self.capacity_sectors = capacity;
+        self
+    }
+
+    /// Sets the smallest unit that the device can address, in bytes.
+    ///
+    /// It must be a power of two between 512 and the page size.
+    pub fn logical_block_size(mut self, size: u32) -> Self {
+        self.logical_block_size = size;
+        self
+    }
+
+    /// Sets the smallest unit that the device can write without a read-modify-write cycle, in
+    /// bytes.
+    ///
+    /// It must be a power of two, and at least the logical block size, which it defaults to.
+    pub fn physical_block_size(mut self, size: u32) -> Self {
+        self.physical_block_size = size;
+        self
+    }
+
+    /// Sets the maximum size of a request in sectors, which must be at least one page.
+    pub fn max_hw_sectors(mut self, sectors: u32) -> Self {
+        self.max_hw_sectors = sectors;
+        self
+    }
+
+    /// Sets the maximum number of segments of a request.
+    pub fn max_segments(mut self, segments: u16) -> Self {
+        self.max_segments = segments;
+        self
+    }
+
+    /// Sets the maximum size of a segment in bytes, which must be at least one page.
+    pub fn max_segment_size(mut self, size: u32) -> Self {
+        self.max_segment_size = size;
+        self
+    }
+
+    /// Enables discard requests of up to `max_sectors` sectors, on blocks of `granularity` bytes.
+    ///
+    /// `granularity` must be a multiple of the logical block size, which it defaults to if zero.
+    pub fn discard(mut self, max_sectors: u32, granularity: u32) -> Self {
+        self.max_discard_sectors = max_sectors;
+        self.discard_granularity = granularity;
+        self
+    }
+
+    /// Sets whether the device is rotational, i.e. has a seek penalty.
+    pub fn rotational(mut self, rotational: bool) -> Self {
+        self.rotational = rotational;
+        self
+    }
+
+    /// Declares that the device has a volatile write cache, and whether it supports forced unit
+    /// access (FUA) writes.
+    ///
+    /// The device then receives flush requests, see [`crate::block::ReqOp::Flush`].
+    pub fn write_cache(mut self, fua: bool) -> Self {
+        self.write_cache = true;
+        self.fua = fua;
+        self
+    }
+
+    /// Sets whether the device has removable media.
+    pub fn removable(mut self, removable: bool) -> Self {
+        self.removable = removable;
+        self
+    }
+
+    /// Sets whether the disk is read-only.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    fn validate(&mut self) -> Result {
+        let page_size = bindings::PAGE_SIZE as u32;
+        let lbs = self.logical_block_size;
+        if !lbs.is_power_of_two() || !(512..=page_size).contains(&lbs) {
+            return Err(EINVAL);
+        }
+        if self.physical_block_size == 0 {
+            self.physical_block_size = lbs;
+        }
+        if !self.physical_block_size.is_power_of_two() || self.physical_block_size < lbs {
+            return Err(EINVAL);
+        }
+        if self.max_hw_sectors != 0 && self.max_hw_sectors < page_size >> SECTOR_SHIFT {
+            return Err(EINVAL);
+        }
+        if self.max_segment_size != 0 && self.max_segment_size < page_size {
+            return Err(EINVAL);
+        }
+        if self.max_discard_sectors != 0 {
+            if self.discard_granularity == 0 {
+                self.discard_granularity = lbs;
+            }
+            if self.discard_granularity % lbs != 0 {
+                return Err(EINVAL);
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the settings to a newly allocated disk.
+    ///
+    /// # Safety
+    ///
+    /// `disk` must be valid and not added yet.
+    unsafe fn apply(&self, disk: *mut bindings::gendisk) {
+        // SAFETY: The caller guarantees that `disk` is valid and not added, so we have exclusive
+        // access to it and its queue.
+        unsafe {
+            let q = (*disk).queue;
+            bindings::blk_queue_logical_block_size(q, self.logical_block_size);
+            bindings::blk_queue_physical_block_size(q, self.physical_block_size);
+            if self.max_hw_sectors != 0 {
+                bindings::blk_queue_max_hw_sectors(q, self.max_hw_sectors);
+            }
+            if self.max_segments != 0 {
+                bindings::blk_queue_max_segments(q, self.max_segments);
+            }
+            if self.max_segment_size != 0 {
+                bindings::blk_queue_max_segment_size(q, self.max_segment_size);
+            }
+            if self.max_discard_sectors != 0 {
+                (*q).limits.discard_granularity = self.discard_granularity;
+                bindings::blk_queue_max_discard_sectors(q, self.max_discard_sectors);
+            }
+            if self.rotational {
+                bindings::blk_queue_flag_clear(bindings::QUEUE_FLAG_NONROT, q);
+            } else {
+                bindings::blk_queue_flag_set(bindings::QUEUE_FLAG_NONROT, q);
+            }
+            bindings::blk_queue_write_cache(q, self.write_cache, self.fua);
+            if self.removable {
+                (*disk).flags |= bindings::GENHD_FL_REMOVABLE as _;
+            }
+            bindings::set_capacity(disk, self.capacity_sectors);
+            bindings::set_disk_ro(disk, self.read_only);
+        }
+    }
+
+    /// Validates the settings, then allocates a disk with the given `name` and adds it.
+    ///
+    /// `data` is passed to the [`Operations`] of the disk. Fails with `EINVAL` if the settings are
+    /// invalid or the name is too long.
+    pub fn build<T: Operations>(
+        mut self,
+        name: fmt::Arguments<'_>,
+        tagset: Arc<TagSet<T>>,
+        data: T::QueueData,
+    ) -> Result<GenDisk<T>> {
+        crate::might_sleep!();
+        self.validate()?;
+        let name = CString::try_from_fmt(name)?;
+        if name.len() >= bindings::DISK_NAME_LEN as usize {
+            return Err(EINVAL);
+        }
+
+        let data = data.into_foreign();
+        // SAFETY: The tag set is valid and outlives the disk, which holds a reference to it.
+        let disk = match from_err_ptr(unsafe {
+            bindings::__blk_mq_alloc_disk(
+                tagset.as_raw(),
+                data.cast_mut(),
+                crate::static_lock_class!().as_ptr(),
+            )
+        }) {
+            Ok(disk) => disk,
+            Err(e) => {
+                // SAFETY: `data` was returned by `into_foreign` above, and wasn't used.
+                unsafe { T::QueueData::from_foreign(data) };
+                return Err(e);
+            }
+        };
+
+        // SAFETY: The disk was just allocated and isn't added yet. The name fits in `disk_name`
+        // with its `NUL` terminator, as checked above.
+        let ret = unsafe {
+            (*disk).fops = &FOPS;
+            ptr::copy_nonoverlapping(
+                name.as_char_ptr(),
+                (*disk).disk_name.as_mut_ptr(),
+                name.len() + 1,
+            );
+            self.apply(disk);
+            bindings::device_add_disk(ptr::null_mut(), disk, ptr::null_mut())
+        };
+        if let Err(e) = to_result(ret) {
+            // SAFETY: The disk wasn't added, so nothing else uses it or `data`.
+            unsafe {
+                bindings::put_disk(disk);
+                T::QueueData::from_foreign(data);
+            }
+            return Err(e);
+        }
+
+        // INVARIANT: The disk was allocated with `data` as queue data, and added above.
+        Ok(GenDisk {
+            // SAFETY: `from_err_ptr` succeeded, so `disk` isn't null.
+            disk: unsafe { NonNull::new_unchecked(disk) },
+            _tagset: tagset,
+        })
+    }
+}
+
+/// A disk of a blk-mq driver, the kernel's `struct gendisk`.
+///
+/// The disk is deleted when this is dropped.
+///
+/// # Invariants
+///
+/// `disk` was allocated with `_tagset` and added, and its queue data is a pointer returned by
+/// `T::QueueData::into_foreign`.
+pub struct GenDisk<T: Operations> {
+    disk: NonNull<bindings::gendisk>,
+    _tagset: Arc<TagSet<T>>,
+}
+
+// SAFETY: The disk can be deleted from any thread, and the queue data is `Send`.
+unsafe impl<T: Operations> Send for GenDisk<T> {}
+
+// SAFETY: The methods that take `&self` use functions with their own synchronisation, and the
+// queue data is `Sync`.
+unsafe impl<T: Operations> Sync for GenDisk<T> {}
+
+impl<T: Operations> GenDisk<T> {
+    fn as_raw(&self) -> *mut bindings::gendisk {
+        self.disk.as_ptr()
+    }
+
+    /// Returns the name of the disk.
+    pub fn name(&self) -> &CStr {
+        // SAFETY: The disk is valid by the type invariants, and its name is `NUL`-terminated.
+        unsafe { CStr::from_char_ptr((*self.as_raw()).disk_name.as_ptr()) }
+    }
+
+    /// Returns the driver data of the disk.
+    pub fn queue_data(&self) -> <T::QueueData as ForeignOwnable>::Borrowed<'_> {
+        // SAFETY: The queue data is a pointer returned by `into_foreign` by the type invariants,
+        // which is freed when `self` is dropped.
+        unsafe { T::QueueData::borrow((*(*self.as_raw()).queue).queuedata) }
+    }
+
+    /// Returns the capacity of the disk in sectors.
+    pub fn capacity_sectors(&self) -> u64 {
+        // SAFETY: The disk is valid by the type invariants.
+        unsafe { bindings::get_capacity(self.as_raw()) }
+    }
+
+    /// Changes the capacity of the disk, and notifies user space if it changed.
+    pub fn set_capacity_sectors(&self, capacity: u64) {
+        // SAFETY: The disk is valid by the type invariants.
+        unsafe { bindings::set_capacity_and_notify(self.as_raw(), capacity) };
+    }
+
+    /// Sets whether the disk is read-only, and notifies user space if it changed.
+    pub fn set_read_only(&self, read_only: bool) {
+        // SAFETY: The disk is valid by the type invariants.
+        unsafe { bindings::set_disk_ro(self.as_raw(), read_only) };
+    }
+
+    /// Reports that the media of the disk changed, which invalidates cached data and rescans the
+    /// partitions on the next open.
+    pub fn notify_media_change(&self) {
+        // SAFETY: The disk is valid by the type invariants.
+        unsafe {
+            bindings::disk_force_media_change(self.as_raw(), bindings::DISK_EVENT_MEDIA_CHANGE as _)
+        };
+    }
+}
+
+impl<T: Operations> Drop for GenDisk<T> {
+    fn drop(&mut self) {
+        let disk = self.as_raw();
+        // SAFETY: The disk was added by the type invariants. Once `del_gendisk` returns, no more
+        // requests are issued, so the queue data can be freed.
+        unsafe {
+            let data = (*(*disk).queue).queuedata;
+            bindings::del_gendisk(disk);
+            T::QueueData::from_foreign(data);
+            bindings::put_disk(disk);
+        }
+    }
+}
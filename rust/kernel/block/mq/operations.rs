@@ -0,0 +1,105 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Operations of blk-mq drivers.
+//!
+//! C header: [`include/linux/blk-mq.h`](../../../../../include/linux/blk-mq.h)
+
+use super::Request;
+use crate::{bindings, block::to_blk_status, error::code::*, types::ForeignOwnable};
+use core::{marker::PhantomData, ptr::NonNull};
+use macros::vtable;
+
+/// The result of [`Operations::queue_rq`].
+pub enum QueueRqResult {
+    /// The driver took care of the request, and will complete it.
+    Queued,
+    /// The driver couldn't queue the request (e.g. because its hardware queue is full), which is
+    /// handed back to the block layer to be retried later.
+    Busy(Request),
+}
+
+/// Operations of a blk-mq driver, the kernel's `struct blk_mq_ops`.
+#[vtable]
+pub trait Operations: Sized + 'static {
+    /// The driver's data associated with each disk, stored in its request queue.
+    type QueueData: ForeignOwnable + Send + Sync;
+
+    /// Queues a request for processing by the device.
+    ///
+    /// `is_last` is `false` if more requests will be queued right away, in which case the driver
+    /// may defer notifying the device until [`Operations::commit_rqs`] is called or a request with
+    /// `is_last` set is queued. This may be called from atomic context and must not sleep.
+    fn queue_rq(
+        data: <Self::QueueData as ForeignOwnable>::Borrowed<'_>,
+        rq: Request,
+        is_last: bool,
+    ) -> QueueRqResult;
+
+    /// Notifies the device of requests queued without `is_last`, when the block layer stops
+    /// queueing requests earlier than it announced.
+    fn commit_rqs(_data: <Self::QueueData as ForeignOwnable>::Borrowed<'_>) {}
+}
+
+pub(super) struct OperationsVtable<T: Operations>(PhantomData<T>);
+
+impl<T: Operations> OperationsVtable<T> {
+    /// Returns the driver data of the queue of a hardware context.
+    ///
+    /// # Safety
+    ///
+    /// `hctx` must be a valid hardware context of a disk created by [`super::GenDiskBuilder`]
+    /// with operations `T`, which is still alive.
+    unsafe fn queue_data<'a>(
+        hctx: *mut bindings::blk_mq_hw_ctx,
+    ) -> <T::QueueData as ForeignOwnable>::Borrowed<'a> {
+        // SAFETY: The caller guarantees that the queue data was set by `GenDiskBuilder::build`
+        // to a pointer returned by `T::QueueData::into_foreign`, which is only freed after the
+        // disk is deleted and no more requests are issued.
+        unsafe { T::QueueData::borrow((*(*hctx).queue).queuedata) }
+    }
+
+    unsafe extern "C" fn queue_rq_callback(
+        hctx: *mut bindings::blk_mq_hw_ctx,
+        bd: *const bindings::blk_mq_queue_data,
+    ) -> bindings::blk_status_t {
+        // SAFETY: The block layer only calls this for hardware contexts of live disks.
+        let data = unsafe { Self::queue_data(hctx) };
+        // SAFETY: `bd` is valid, and holds a request issued to the driver, which must complete
+        // it unless it returns an error.
+        let (rq, is_last) = unsafe {
+            (
+                Request::from_raw(NonNull::new_unchecked((*bd).rq)),
+                (*bd).last,
+            )
+        };
+        match T::queue_rq(data, rq, is_last) {
+            QueueRqResult::Queued => 0,
+            QueueRqResult::Busy(rq) => {
+                // The block layer takes the request back when `BLK_STS_RESOURCE` (the status of
+                // `ENOMEM`) is returned.
+                rq.into_raw();
+                to_blk_status(Err(ENOMEM))
+            }
+        }
+    }
+
+    unsafe extern "C" fn commit_rqs_callback(hctx: *mut bindings::blk_mq_hw_ctx) {
+        // SAFETY: The block layer only calls this for hardware contexts of live disks.
+        T::commit_rqs(unsafe { Self::queue_data(hctx) });
+    }
+
+    const VTABLE: bindings::blk_mq_ops = bindings::blk_mq_ops {
+        queue_rq: Some(Self::queue_rq_callback),
+        commit_rqs: if T::HAS_COMMIT_RQS {
+            Some(Self::commit_rqs_callback)
+        } else {
+            None
+        },
+        // SAFETY: All other fields are optional callbacks, for which zero is valid.
+        ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+    };
+
+    pub(super) const fn build() -> &'static bindings::blk_mq_ops {
+        &Self::VTABLE
+    }
+}
@@ -0,0 +1,167 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Block layer requests.
+//!
+//! C header: [`include/linux/blk-mq.h`](../../../../../include/linux/blk-mq.h)
+
+use crate::{
+    bindings,
+    block::{
+        bio::{Bios, Segment, Segments},
+        to_blk_status, ReqOp, SECTOR_SHIFT,
+    },
+    error::{code::*, Result},
+};
+use core::{mem::ManuallyDrop, ptr::NonNull};
+
+/// A request of the multi-queue block layer, the kernel's `struct request`.
+///
+/// The driver is responsible for completing the request, with [`Request::end`] or
+/// [`Request::end_ok`]. It is completed with an I/O error if it is dropped instead.
+///
+/// # Invariants
+///
+/// The pointer is a valid request that was issued to the driver and that we are responsible for
+/// completing.
+///
+/// # Examples
+///
+/// A driver for a RAM disk copies the data of each segment:
+///
+/// ```
+/// use kernel::block::{mq::Request, ReqOp, SECTOR_SHIFT};
+/// # use kernel::prelude::*;
+///
+/// fn handle(rq: Request, storage: &mut [u8]) {
+///     rq.start();
+///     let status = (|| -> Result {
+///         for mut seg in rq.segments() {
+///             let start = (seg.sector() << SECTOR_SHIFT) as usize;
+///             let data = storage.get_mut(start..).ok_or(EIO)?;
+///             match rq.op() {
+///                 ReqOp::Read => seg.copy_from_slice(data)?,
+///                 ReqOp::Write => seg.copy_to_slice(data)?,
+///                 _ => return Err(EOPNOTSUPP),
+///             }
+///         }
+///         Ok(())
+///     })();
+///     rq.end(status);
+/// }
+/// ```
+pub struct Request(NonNull<bindings::request>);
+
+// SAFETY: Requests can be completed from any context and thread.
+unsafe impl Send for Request {}
+
+// SAFETY: `Request` only gives shared access to the request through `&self`.
+unsafe impl Sync for Request {}
+
+impl Request {
+    /// Takes over the responsibility of completing a request.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a valid request that was issued to the driver (e.g. passed to `queue_rq`),
+    /// and that the caller is responsible for completing.
+    pub unsafe fn from_raw(ptr: NonNull<bindings::request>) -> Self {
+        // INVARIANT: Guaranteed by the safety requirements of the function.
+        Self(ptr)
+    }
+
+    /// Returns the raw `struct request` pointer.
+    pub fn as_raw(&self) -> *mut bindings::request {
+        self.0.as_ptr()
+    }
+
+    /// Gives up the responsibility of completing the request, and returns the raw pointer.
+    pub fn into_raw(self) -> *mut bindings::request {
+        ManuallyDrop::new(self).as_raw()
+    }
+
+    /// Returns the operation of the request.
+    pub fn op(&self) -> ReqOp {
+        // SAFETY: The request is valid by the type invariants.
+        ReqOp::from_opf(unsafe { (*self.as_raw()).cmd_flags })
+    }
+
+    /// Returns the first sector of the request, like `blk_rq_pos` in C.
+    pub fn sector(&self) -> u64 {
+        // SAFETY: The request is valid by the type invariants.
+        unsafe { (*self.as_raw()).__sector }
+    }
+
+    /// Returns the remaining length of the request in bytes, like `blk_rq_bytes` in C.
+    pub fn bytes(&self) -> u32 {
+        // SAFETY: The request is valid by the type invariants.
+        unsafe { (*self.as_raw()).__data_len }
+    }
+
+    /// Returns the remaining length of the request in sectors, like `blk_rq_sectors` in C.
+    pub fn sectors(&self) -> u32 {
+        self.bytes() >> SECTOR_SHIFT
+    }
+
+    /// Returns an iterator over the bios of the request.
+    pub fn bios(&self) -> Bios<'_> {
+        // SAFETY: The request is valid by the type invariants, and its bios are only completed
+        // when the request is, which can't happen while it is borrowed.
+        unsafe { Bios::new((*self.as_raw()).bio) }
+    }
+
+    /// Returns an iterator over the single-page segments of all bios of the request, like
+    /// `rq_for_each_segment` in C.
+    pub fn segments(&self) -> RequestSegments<'_> {
+        let mut bios = self.bios();
+        let segments = bios.next().map(|bio| bio.segments());
+        RequestSegments { bios, segments }
+    }
+
+    /// Reports that the driver started processing the request, like `blk_mq_start_request` in
+    /// C. This must be called before the request is passed to the device.
+    pub fn start(&self) {
+        // SAFETY: The request is valid by the type invariants.
+        unsafe { bindings::blk_mq_start_request(self.as_raw()) };
+    }
+
+    /// Completes the request with the given status, like `blk_mq_end_request` in C.
+    ///
+    /// This may be called from any context.
+    pub fn end(self, status: Result) {
+        let rq = ManuallyDrop::new(self);
+        // SAFETY: We are responsible for completing the request by the type invariants, and `rq`
+        // isn't dropped so it is only completed here.
+        unsafe { bindings::blk_mq_end_request(rq.as_raw(), to_blk_status(status)) };
+    }
+
+    /// Completes the request successfully.
+    pub fn end_ok(self) {
+        self.end(Ok(()));
+    }
+}
+
+impl Drop for Request {
+    fn drop(&mut self) {
+        // SAFETY: We are responsible for completing the request by the type invariants.
+        unsafe { bindings::blk_mq_end_request(self.as_raw(), to_blk_status(Err(EIO))) };
+    }
+}
+
+/// An iterator over the single-page segments of a request, see [`Request::segments`].
+pub struct RequestSegments<'a> {
+    bios: Bios<'a>,
+    segments: Option<Segments<'a>>,
+}
+
+impl<'a> Iterator for RequestSegments<'a> {
+    type Item = Segment<'a>;
+
+    fn next(&mut self) -> Option<Segment<'a>> {
+        loop {
+            if let Some(segment) = self.segments.as_mut()?.next() {
+                return Some(segment);
+            }
+            self.segments = self.bios.next().map(|bio| bio.segments());
+        }
+    }
+}
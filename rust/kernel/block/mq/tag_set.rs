@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Tag sets, which hold the requests of the hardware queues of blk-mq drivers.
+//!
+//! C header: [`include/linux/blk-mq.h`](../../../../../include/linux/blk-mq.h)
+
+use super::{operations::OperationsVtable, Operations};
+use crate::{
+    bindings,
+    error::{code::*, to_result, Error, Result},
+    init,
+    sync::Arc,
+    types::Opaque,
+};
+use core::{
+    marker::{PhantomData, PhantomPinned},
+    ptr,
+};
+
+/// A tag set, the kernel's `struct blk_mq_tag_set`.
+///
+/// A tag set may be shared by several disks of the same driver, see
+/// [`super::GenDiskBuilder::build`].
+///
+/// # Invariants
+///
+/// `inner` was allocated by `blk_mq_alloc_tag_set`, with operations `T`.
+pub struct TagSet<T: Operations> {
+    inner: Opaque<bindings::blk_mq_tag_set>,
+    _pin: PhantomPinned,
+    _p: PhantomData<T>,
+}
+
+// SAFETY: Tag sets have their own synchronisation and can be freed from any thread.
+unsafe impl<T: Operations> Send for TagSet<T> {}
+
+// SAFETY: The tag set is only used by the block layer, which has its own synchronisation.
+unsafe impl<T: Operations> Sync for TagSet<T> {}
+
+impl<T: Operations> TagSet<T> {
+    /// Allocates a tag set for `nr_hw_queues` hardware queues, each holding up to `queue_depth`
+    /// requests.
+    ///
+    /// Fails with `EINVAL` if either of them is zero.
+    pub fn try_new(nr_hw_queues: u32, queue_depth: u32) -> Result<Arc<Self>> {
+        if nr_hw_queues == 0 || queue_depth == 0 {
+            return Err(EINVAL);
+        }
+        // SAFETY: The closure initialises `inner` on success, and leaves nothing to clean up on
+        // failure. The other fields are zero-sized.
+        let init = unsafe {
+            init::pin_init_from_closure::<_, Error>(move |slot: *mut Self| {
+                let set = Opaque::raw_get(ptr::addr_of!((*slot).inner));
+                set.write(bindings::blk_mq_tag_set {
+                    ops: OperationsVtable::<T>::build(),
+                    nr_hw_queues,
+                    queue_depth,
+                    numa_node: bindings::NUMA_NO_NODE,
+                    flags: bindings::BLK_MQ_F_SHOULD_MERGE,
+                    // SAFETY: All other fields are filled in by `blk_mq_alloc_tag_set`.
+                    ..core::mem::MaybeUninit::zeroed().assume_init()
+                });
+                // INVARIANT: The tag set is only considered initialised if this succeeds.
+                to_result(bindings::blk_mq_alloc_tag_set(set))
+            })
+        };
+        Arc::pin_init(init)
+    }
+
+    pub(super) fn as_raw(&self) -> *mut bindings::blk_mq_tag_set {
+        self.inner.get()
+    }
+}
+
+impl<T: Operations> Drop for TagSet<T> {
+    fn drop(&mut self) {
+        // SAFETY: The tag set was allocated by the type invariants, and it is no longer used by
+        // any disk since they hold a reference to it.
+        unsafe { bindings::blk_mq_free_tag_set(self.inner.get()) };
+    }
+}
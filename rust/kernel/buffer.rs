@@ -0,0 +1,210 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Growable byte buffers.
+//!
+//! [`Buffer`] is a heap-allocated buffer of bytes that grows as data is appended to it, for
+//! assembling output whose size isn't known in advance, e.g. log lines, messages to send, or the
+//! contents of a file before they are copied to a [`SeqFile`](crate::seq_file::SeqFile). Unlike
+//! [`fmt::Write`], every write through the [`Write`] trait reports allocation failures as
+//! `ENOMEM`, and formatting with [`write!`] returns a kernel [`Result`].
+
+use crate::error::{code::*, Error, Result};
+use alloc::vec::Vec;
+use core::{fmt, ops::Deref};
+
+/// A destination of bytes, whose writes may fail.
+///
+/// Types implementing it can be written to with [`write!`] and [`writeln!`], which then return a
+/// [`Result`] with the error of the failing write, instead of the opaque [`fmt::Error`].
+pub trait Write {
+    /// Appends `data`.
+    ///
+    /// Nothing is appended on failure.
+    fn write_bytes(&mut self, data: &[u8]) -> Result;
+
+    /// Appends the string `s`.
+    fn write_str(&mut self, s: &str) -> Result {
+        self.write_bytes(s.as_bytes())
+    }
+
+    /// Appends the formatted `args`, which [`write!`] calls.
+    ///
+    /// Part of the output may have been appended on failure.
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> Result {
+        write_fmt(self, args)
+    }
+}
+
+/// Formats `args` into `w`, returning the error of the first write that failed.
+fn write_fmt<W: Write + ?Sized>(w: &mut W, args: fmt::Arguments<'_>) -> Result {
+    struct Adapter<'a, W: ?Sized> {
+        inner: &'a mut W,
+        error: Option<Error>,
+    }
+
+    impl<W: Write + ?Sized> fmt::Write for Adapter<'_, W> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.inner.write_bytes(s.as_bytes()).map_err(|e| {
+                self.error = Some(e);
+                fmt::Error
+            })
+        }
+    }
+
+    let mut adapter = Adapter {
+        inner: w,
+        error: None,
+    };
+    match fmt::write(&mut adapter, args) {
+        Ok(()) => Ok(()),
+        // Errors not coming from a write are returned by a `Display` implementation.
+        Err(_) => Err(adapter.error.unwrap_or(EINVAL)),
+    }
+}
+
+impl Write for Vec<u8> {
+    fn write_bytes(&mut self, data: &[u8]) -> Result {
+        self.try_extend_from_slice(data)?;
+        Ok(())
+    }
+}
+
+/// A growable buffer of bytes, optionally limited in size.
+///
+/// # Invariants
+///
+/// `buf` is never longer than `limit`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::buffer::{Buffer, Write};
+/// # use kernel::prelude::*;
+///
+/// fn describe(ids: &[u32]) -> Result<Buffer> {
+///     let mut buf = Buffer::new();
+///     write!(buf, "{} ids:", ids.len())?;
+///     for id in ids {
+///         write!(buf, " {:#x}", id)?;
+///     }
+///     buf.write_str("\n")?;
+///     Ok(buf)
+/// }
+///
+/// let buf = describe(&[1, 0x20])?;
+/// assert_eq!(&*buf, b"2 ids: 0x1 0x20\n");
+///
+/// // A limited buffer rejects writes that don't fit, and keeps what was there before.
+/// let mut buf = Buffer::with_limit(8);
+/// write!(buf, "{}", 1234)?;
+/// assert_eq!(write!(buf, "{}", 56789), Err(ENOSPC));
+/// assert_eq!(&*buf, b"1234");
+/// # Ok::<(), Error>(())
+/// ```
+pub struct Buffer {
+    buf: Vec<u8>,
+    limit: usize,
+}
+
+impl Buffer {
+    /// Creates an empty buffer, without allocating.
+    pub const fn new() -> Self {
+        Self::with_limit(usize::MAX)
+    }
+
+    /// Creates an empty buffer that can't grow larger than `limit` bytes.
+    ///
+    /// Writes that would make it larger fail with `ENOSPC`.
+    pub const fn with_limit(limit: usize) -> Self {
+        // INVARIANT: The buffer is empty.
+        Self {
+            buf: Vec::new(),
+            limit,
+        }
+    }
+
+    /// Creates an empty buffer that can hold `capacity` bytes without reallocating.
+    pub fn try_with_capacity(capacity: usize) -> Result<Self> {
+        let mut buf = Self::new();
+        buf.try_reserve(capacity)?;
+        Ok(buf)
+    }
+
+    /// Returns the number of bytes in the buffer.
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns whether the buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Returns the maximum size of the buffer.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Makes room for at least `additional` more bytes, without exceeding the limit.
+    pub fn try_reserve(&mut self, additional: usize) -> Result {
+        let additional = additional.min(self.limit - self.buf.len());
+        self.buf.try_reserve(additional)?;
+        Ok(())
+    }
+
+    /// Shortens the buffer to `len` bytes. It does nothing if the buffer is already shorter.
+    pub fn truncate(&mut self, len: usize) {
+        self.buf.truncate(len);
+    }
+
+    /// Removes all the bytes, keeping the allocated memory.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    /// Returns the bytes of the buffer.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Converts the buffer into the vector holding its bytes.
+    pub fn into_vec(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for Buffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl Write for Buffer {
+    fn write_bytes(&mut self, data: &[u8]) -> Result {
+        if data.len() > self.limit - self.buf.len() {
+            return Err(ENOSPC);
+        }
+        // INVARIANT: The check above ensures that the buffer doesn't exceed its limit.
+        self.buf.try_extend_from_slice(data)?;
+        Ok(())
+    }
+
+    /// Appends the formatted `args`.
+    ///
+    /// Unlike for other implementations, nothing is appended on failure.
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> Result {
+        let len = self.buf.len();
+        write_fmt(self, args).map_err(|e| {
+            self.buf.truncate(len);
+            e
+        })
+    }
+}
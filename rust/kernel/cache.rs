@@ -0,0 +1,58 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Cache maintenance of DMA buffers.
+//!
+//! On devices that aren't cache-coherent, e.g. most DMA masters of ARM SoCs, the CPU caches must
+//! be written back before the device reads a buffer, and invalidated before the CPU reads what
+//! the device wrote. Buffers allocated with `dma_alloc_coherent` are uncached and need neither,
+//! but streaming mappings, e.g. of [`SegmentMapping`](crate::block::bio::SegmentMapping), are
+//! handed between the CPU and the device with these functions. They are no-ops on coherent
+//! devices.
+//!
+//! The architecture's cache flush and invalidate primitives aren't used directly: the DMA API
+//! also handles bounce buffers and the errata of the caches.
+//!
+//! C header: [`include/linux/dma-mapping.h`](../../../../include/linux/dma-mapping.h)
+
+use crate::{bindings, device::Device, dma::DataDirection};
+
+/// Hands the `size` bytes at `addr` of a streaming mapping to the CPU, the kernel's
+/// `dma_sync_single_for_cpu()`.
+///
+/// For mappings from the device, this invalidates the caches, so the CPU reads what the device
+/// wrote. It must be called after the device is done, e.g. once its completion is observed, and
+/// before the CPU reads the buffer.
+///
+/// # Safety
+///
+/// The range must be within a streaming mapping of `dev` in the direction `dir`, which is alive,
+/// and the device must not access it until [`sync_for_device`] is called.
+pub unsafe fn sync_for_cpu(
+    dev: &Device,
+    addr: bindings::dma_addr_t,
+    size: usize,
+    dir: DataDirection,
+) {
+    // SAFETY: `dev` is valid, and the range is mapped for it by the safety requirements.
+    unsafe { bindings::dma_sync_single_for_cpu(dev.as_raw(), addr, size, dir.as_raw()) };
+}
+
+/// Hands the `size` bytes at `addr` of a streaming mapping back to the device, the kernel's
+/// `dma_sync_single_for_device()`.
+///
+/// For mappings to the device, this writes the caches back, so the device reads what the CPU
+/// wrote. It must be called after the CPU is done, and before the device is started.
+///
+/// # Safety
+///
+/// The range must be within a streaming mapping of `dev` in the direction `dir`, which is alive,
+/// and the CPU must not access it until [`sync_for_cpu`] is called.
+pub unsafe fn sync_for_device(
+    dev: &Device,
+    addr: bindings::dma_addr_t,
+    size: usize,
+    dir: DataDirection,
+) {
+    // SAFETY: `dev` is valid, and the range is mapped for it by the safety requirements.
+    unsafe { bindings::dma_sync_single_for_device(dev.as_raw(), addr, size, dir.as_raw()) };
+}
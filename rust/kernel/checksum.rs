@@ -0,0 +1,69 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Cyclic redundancy checks.
+//!
+//! The functions take the CRC of the data before `data`, or the initial value of the CRC, and
+//! return the CRC including `data`, so a CRC is computed incrementally by passing the result of
+//! each call to the next. They don't invert the CRC before or after, which protocols that do,
+//! e.g. Ethernet with CRC32, must do themselves.
+//!
+//! C headers: [`include/linux/crc32.h`](../../../../include/linux/crc32.h),
+//! [`include/linux/crc16.h`](../../../../include/linux/crc16.h),
+//! [`include/linux/crc-itu-t.h`](../../../../include/linux/crc-itu-t.h) and
+//! [`include/linux/crc-ccitt.h`](../../../../include/linux/crc-ccitt.h)
+//!
+//! # Examples
+//!
+//! ```
+//! use kernel::checksum;
+//!
+//! fn frame_crc(header: &[u8], payload: &[u8]) -> u32 {
+//!     let crc = checksum::crc32_le(!0, header);
+//!     !checksum::crc32_le(crc, payload)
+//! }
+//! ```
+
+use crate::bindings;
+
+/// Returns the little-endian CRC32 of `data`, with the polynomial 0x04c11db7, e.g. of Ethernet.
+#[cfg(CONFIG_CRC32)]
+pub fn crc32_le(crc: u32, data: &[u8]) -> u32 {
+    // SAFETY: `data` is valid for reads of its length.
+    unsafe { bindings::crc32_le(crc, data.as_ptr(), data.len()) }
+}
+
+/// Returns the big-endian CRC32 of `data`, with the polynomial 0x04c11db7.
+#[cfg(CONFIG_CRC32)]
+pub fn crc32_be(crc: u32, data: &[u8]) -> u32 {
+    // SAFETY: `data` is valid for reads of its length.
+    unsafe { bindings::crc32_be(crc, data.as_ptr(), data.len()) }
+}
+
+/// Returns the CRC32C of `data`, with the Castagnoli polynomial 0x1edc6f41, e.g. of iSCSI.
+#[cfg(CONFIG_CRC32)]
+pub fn crc32c_le(crc: u32, data: &[u8]) -> u32 {
+    // SAFETY: `data` is valid for reads of its length.
+    unsafe { bindings::__crc32c_le(crc, data.as_ptr(), data.len()) }
+}
+
+/// Returns the CRC16 of `data`, with the polynomial 0x8005, e.g. of Modbus.
+#[cfg(CONFIG_CRC16)]
+pub fn crc16(crc: u16, data: &[u8]) -> u16 {
+    // SAFETY: `data` is valid for reads of its length.
+    unsafe { bindings::crc16(crc, data.as_ptr(), data.len()) }
+}
+
+/// Returns the CRC-ITU-T of `data`, with the polynomial 0x1021, most significant bit first.
+#[cfg(CONFIG_CRC_ITU_T)]
+pub fn crc_itu_t(crc: u16, data: &[u8]) -> u16 {
+    // SAFETY: `data` is valid for reads of its length.
+    unsafe { bindings::crc_itu_t(crc, data.as_ptr(), data.len()) }
+}
+
+/// Returns the CRC-CCITT of `data`, with the polynomial 0x1021, least significant bit first,
+/// e.g. of HDLC.
+#[cfg(CONFIG_CRC_CCITT)]
+pub fn crc_ccitt(crc: u16, data: &[u8]) -> u16 {
+    // SAFETY: `data` is valid for reads of its length.
+    unsafe { bindings::crc_ccitt(crc, data.as_ptr(), data.len()) }
+}
@@ -0,0 +1,108 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Device classes.
+//!
+//! C header: [`include/linux/device/class.h`](../../../../include/linux/device/class.h)
+
+use crate::{
+    bindings,
+    error::{to_result, Result},
+    str::{CStr, CString},
+    sysfs::AttributeGroups,
+    types::Opaque,
+};
+use alloc::boxed::Box;
+use core::{fmt, ptr};
+
+/// A registered device class, which appears in `/sys/class/`.
+///
+/// The class is unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `class` is a registered class, named `name`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{attribute_group, attribute_groups, c_str, class, class_attr, fmt, sysfs};
+/// # use kernel::prelude::*;
+///
+/// struct Version;
+///
+/// #[vtable]
+/// impl sysfs::ClassAttribute for Version {
+///     const NAME: &'static CStr = c_str!("version");
+///     type Value = u32;
+///
+///     fn show() -> Result<u32> {
+///         Ok(2)
+///     }
+/// }
+///
+/// class_attr!(static CLASS_ATTR_VERSION = Version);
+/// attribute_group!(static CLASS_GROUP = [CLASS_ATTR_VERSION]);
+/// attribute_groups!(static CLASS_GROUPS = [CLASS_GROUP]);
+///
+/// fn register() -> Result<class::Registration> {
+///     class::Registration::try_new(fmt!("my_class"), Some(&CLASS_GROUPS), None)
+/// }
+/// ```
+pub struct Registration {
+    class: Box<Opaque<bindings::class>>,
+    name: CString,
+}
+
+impl Registration {
+    /// Registers a new class named `name`, which may be built at runtime.
+    ///
+    /// `class_groups` are attributes of the class itself, while `dev_groups` are added to every
+    /// device of the class.
+    pub fn try_new(
+        name: fmt::Arguments<'_>,
+        class_groups: Option<&'static AttributeGroups>,
+        dev_groups: Option<&'static AttributeGroups>,
+    ) -> Result<Self> {
+        let name = CString::try_from_fmt(name)?;
+        // SAFETY: All-zeroes is a valid, unregistered `struct class`.
+        let class = Box::try_new(Opaque::new(unsafe {
+            core::mem::MaybeUninit::<bindings::class>::zeroed().assume_init()
+        }))?;
+        let ptr = class.get();
+        // SAFETY: `ptr` is valid and not registered yet, so we have exclusive access to it. The
+        // name is owned by the registration, whose heap buffer doesn't move and is only freed
+        // after the class is unregistered, and the groups are static.
+        unsafe {
+            (*ptr).name = name.as_char_ptr();
+            (*ptr).class_groups = class_groups.map_or(ptr::null_mut(), |g| g.as_ptr());
+            (*ptr).dev_groups = dev_groups.map_or(ptr::null_mut(), |g| g.as_ptr());
+        }
+        // SAFETY: `ptr` is a valid class, which is boxed so it won't move.
+        to_result(unsafe { bindings::class_register(ptr) })?;
+        // INVARIANT: The class was registered above.
+        Ok(Self { class, name })
+    }
+
+    /// Returns the name of the class.
+    pub fn name(&self) -> &CStr {
+        &self.name
+    }
+
+    /// Returns the raw `struct class` pointer.
+    pub fn as_raw(&self) -> *mut bindings::class {
+        self.class.get()
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, the class is registered.
+        unsafe { bindings::class_unregister(self.class.get()) };
+    }
+}
+
+// SAFETY: Classes can be unregistered from any thread.
+unsafe impl Send for Registration {}
+
+// SAFETY: `Registration` has no methods that take `&self` other than `as_raw`.
+unsafe impl Sync for Registration {}
@@ -0,0 +1,298 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Clock event devices.
+//!
+//! A clock event device is a timer that raises an interrupt, either once after a given number of
+//! cycles or periodically, which the tick and high-resolution timer code program to run timers.
+//! A driver registers a [`ClockEvent`] for each of its timers, and calls
+//! [`ClockEvent::handle_event`] from the handler of the timer's interrupt.
+//!
+//! All the callbacks of [`Operations`] are called with interrupts disabled, so they must not
+//! sleep, and they only get a shared reference to the data of the timer.
+//!
+//! C header: [`include/linux/clockchips.h`](../../../../include/linux/clockchips.h)
+
+use crate::{
+    bindings,
+    error::{code::*, from_result, Result},
+    str::CStr,
+    types::Opaque,
+};
+use alloc::boxed::Box;
+use core::{ffi::c_int, ffi::c_ulong, mem::MaybeUninit};
+use macros::vtable;
+
+/// The operations of a clock event device.
+///
+/// The driver data of the device implements this trait. Devices implementing
+/// [`Operations::set_next_event`] support one-shot mode, and devices implementing
+/// [`Operations::set_state_periodic`] support periodic mode.
+#[vtable]
+pub trait Operations: Send + Sync + Sized + 'static {
+    /// The name of the device.
+    const NAME: &'static CStr;
+
+    /// The rating of the device, which the core uses to select the best one for each CPU, like
+    /// the rating of a clock source.
+    const RATING: u32;
+
+    /// Programs the timer to fire after `cycles` cycles, in one-shot mode.
+    fn set_next_event(_ced: &ClockEvent<Self>, _cycles: u64) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Switches the timer to periodic mode, firing once per tick.
+    fn set_state_periodic(_ced: &ClockEvent<Self>) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Switches the timer to one-shot mode, after which [`Operations::set_next_event`] programs
+    /// it.
+    fn set_state_oneshot(_ced: &ClockEvent<Self>) -> Result {
+        Ok(())
+    }
+
+    /// Stops the timer.
+    fn set_state_shutdown(_ced: &ClockEvent<Self>) -> Result {
+        Ok(())
+    }
+}
+
+/// The configuration of a [`ClockEvent`].
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// The frequency of the timer, in Hz.
+    pub freq: u32,
+    /// The smallest number of cycles that can be programmed.
+    pub min_delta: u64,
+    /// The largest number of cycles that can be programmed.
+    pub max_delta: u64,
+    /// The CPU whose timer interrupts the device raises, or `None` if it can raise them on any
+    /// CPU.
+    pub cpu: Option<u32>,
+    /// The interrupt of the timer, if any, whose affinity is set by the core.
+    pub irq: Option<u32>,
+}
+
+/// A clock event device, the kernel's `struct clock_event_device`.
+///
+/// Clock event devices can't be unregistered, so registered devices are never freed.
+///
+/// # Invariants
+///
+/// `ced` is registered, and its operations are those of `T`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, prelude::*};
+/// use kernel::clockevents::{ClockEvent, Config, Operations};
+/// use kernel::io_mem::IoMem;
+/// use kernel::irq;
+///
+/// const TIMER_PTV: usize = 0x0;
+/// const TIMER_PCR: usize = 0x4;
+/// const TIMER_EN: u32 = 1 << 31;
+/// const TIMER_INTR_CLR: u32 = 1 << 30;
+///
+/// struct Timer {
+///     regs: IoMem<0x8>,
+/// }
+///
+/// #[vtable]
+/// impl Operations for Timer {
+///     const NAME: &'static CStr = c_str!("tegra_timer");
+///     const RATING: u32 = 300;
+///
+///     fn set_next_event(ced: &ClockEvent<Self>, cycles: u64) -> Result {
+///         ced.data().regs.writel(TIMER_EN | (cycles as u32 - 1), TIMER_PTV);
+///         Ok(())
+///     }
+///
+///     fn set_state_shutdown(ced: &ClockEvent<Self>) -> Result {
+///         ced.data().regs.writel(0, TIMER_PTV);
+///         Ok(())
+///     }
+/// }
+///
+/// struct TimerIrq(&'static ClockEvent<Timer>);
+///
+/// impl irq::Handler for TimerIrq {
+///     fn handle_irq(&self) -> irq::Return {
+///         self.0.data().regs.writel(TIMER_INTR_CLR, TIMER_PCR);
+///         self.0.handle_event();
+///         irq::Return::Handled
+///     }
+/// }
+///
+/// fn init(regs: IoMem<0x8>, irq: u32, cpu: u32) -> Result<&'static ClockEvent<Timer>> {
+///     let config = Config {
+///         freq: 1_000_000,
+///         min_delta: 1,
+///         max_delta: 0x1fff_ffff,
+///         cpu: Some(cpu),
+///         irq: Some(irq),
+///     };
+///     ClockEvent::register(&config, Timer { regs })
+/// }
+/// ```
+#[repr(C)]
+pub struct ClockEvent<T: Operations> {
+    // Must be the first field, see `ClockEvent::from_raw`.
+    ced: Opaque<bindings::clock_event_device>,
+    data: T,
+}
+
+impl<T: Operations> ClockEvent<T> {
+    /// Registers a clock event device with the configuration `config` and the driver data
+    /// `data`.
+    ///
+    /// The device may be programmed as soon as it is registered, i.e. before this returns, so its
+    /// interrupt must be requested beforehand, or the device must not raise it until it is.
+    ///
+    /// Fails with `EINVAL` if `T` implements neither one-shot nor periodic mode, or if
+    /// [`Config::cpu`] isn't a valid CPU id.
+    pub fn register(config: &Config, data: T) -> Result<&'static Self> {
+        if !T::HAS_SET_NEXT_EVENT && !T::HAS_SET_STATE_PERIODIC {
+            return Err(EINVAL);
+        }
+        let cpumask = match config.cpu {
+            // SAFETY: `cpumask_of` returns a static mask for valid CPU ids.
+            Some(cpu) if cpu < crate::cpumask::nr_cpu_ids() => unsafe { bindings::cpumask_of(cpu) },
+            Some(_) => return Err(EINVAL),
+            // SAFETY: The mask of possible CPUs is static.
+            None => unsafe { &bindings::__cpu_possible_mask },
+        };
+        crate::might_sleep!();
+        let mut features = 0;
+        if T::HAS_SET_NEXT_EVENT {
+            features |= bindings::CLOCK_EVT_FEAT_ONESHOT;
+        }
+        if T::HAS_SET_STATE_PERIODIC {
+            features |= bindings::CLOCK_EVT_FEAT_PERIODIC;
+        }
+        let this = Box::try_new(Self {
+            ced: Opaque::new(bindings::clock_event_device {
+                name: T::NAME.as_char_ptr(),
+                rating: T::RATING as _,
+                features: features as _,
+                irq: config.irq.map_or(-1, |irq| irq as _),
+                cpumask,
+                set_next_event: if T::HAS_SET_NEXT_EVENT {
+                    Some(Self::set_next_event_callback)
+                } else {
+                    None
+                },
+                set_state_periodic: if T::HAS_SET_STATE_PERIODIC {
+                    Some(Self::set_state_periodic_callback)
+                } else {
+                    None
+                },
+                set_state_oneshot: if T::HAS_SET_STATE_ONESHOT {
+                    Some(Self::set_state_oneshot_callback)
+                } else {
+                    None
+                },
+                set_state_shutdown: if T::HAS_SET_STATE_SHUTDOWN {
+                    Some(Self::set_state_shutdown_callback)
+                } else {
+                    None
+                },
+                // SAFETY: All other fields are optional or filled in on registration.
+                ..unsafe { MaybeUninit::zeroed().assume_init() }
+            }),
+            data,
+        })?;
+        // The device can't be unregistered, so it is never freed.
+        let this: &'static Self = Box::leak(this);
+        // SAFETY: The device is valid forever, and its name and CPU mask are static.
+        unsafe {
+            bindings::clockevents_config_and_register(
+                this.ced.get(),
+                config.freq,
+                config.min_delta as c_ulong,
+                config.max_delta as c_ulong,
+            )
+        };
+        // INVARIANT: The device was registered above.
+        Ok(this)
+    }
+
+    /// Creates a reference to a [`ClockEvent`] from its `struct clock_event_device`.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ced` is the `ced` field of a registered `ClockEvent<T>`.
+    unsafe fn from_raw<'a>(ced: *mut bindings::clock_event_device) -> &'a Self {
+        // SAFETY: `ced` is the first field of `ClockEvent`, which is `repr(C)`, and registered
+        // devices are never freed.
+        unsafe { &*ced.cast() }
+    }
+
+    /// Returns the driver data of the device.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    /// Runs the expired timers, from the handler of the timer's interrupt.
+    ///
+    /// It must be called in hard interrupt context.
+    pub fn handle_event(&self) {
+        let ced = self.ced.get();
+        // SAFETY: The device is registered by the type invariants, so the core has set its event
+        // handler, which it only changes with interrupts disabled.
+        unsafe {
+            if let Some(handler) = (*ced).event_handler {
+                handler(ced);
+            }
+        }
+    }
+
+    unsafe extern "C" fn set_next_event_callback(
+        evt: c_ulong,
+        ced: *mut bindings::clock_event_device,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The core calls this with a device registered by `register`.
+            T::set_next_event(unsafe { Self::from_raw(ced) }, evt as _)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn set_state_periodic_callback(
+        ced: *mut bindings::clock_event_device,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The core calls this with a device registered by `register`.
+            T::set_state_periodic(unsafe { Self::from_raw(ced) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn set_state_oneshot_callback(
+        ced: *mut bindings::clock_event_device,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The core calls this with a device registered by `register`.
+            T::set_state_oneshot(unsafe { Self::from_raw(ced) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn set_state_shutdown_callback(
+        ced: *mut bindings::clock_event_device,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The core calls this with a device registered by `register`.
+            T::set_state_shutdown(unsafe { Self::from_raw(ced) })?;
+            Ok(0)
+        })
+    }
+}
+
+// SAFETY: The methods that take `&self` either only read fields that never change or must be
+// called from the timer's interrupt, and the driver data is `Sync`.
+unsafe impl<T: Operations> Sync for ClockEvent<T> {}
+
+// SAFETY: The device is never freed, and the driver data is `Send`.
+unsafe impl<T: Operations> Send for ClockEvent<T> {}
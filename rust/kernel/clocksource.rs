@@ -0,0 +1,171 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Clock sources.
+//!
+//! A clock source is a free-running counter, which the timekeeping core reads to keep track of
+//! time. A driver registers a [`Registration`], whose [`Source::read`] is called in any context,
+//! including with interrupts disabled and from NMIs, so it can't take locks or sleep, and only
+//! gets a shared reference to its data. Timer interrupts are provided by clock event devices
+//! instead, see [`clockevents`](crate::clockevents).
+//!
+//! C header: [`include/linux/clocksource.h`](../../../../include/linux/clocksource.h)
+
+use crate::{
+    bindings,
+    error::{code::*, to_result, Error, Result},
+    init::{self, InPlaceInit},
+    str::CStr,
+    types::Opaque,
+};
+use alloc::boxed::Box;
+use core::{
+    marker::PhantomPinned,
+    mem::{ManuallyDrop, MaybeUninit},
+    pin::Pin,
+    ptr,
+};
+
+/// A counter used as a clock source.
+///
+/// The driver data of the clock source implements this trait.
+pub trait Source: Send + Sync + Sized + 'static {
+    /// The name of the clock source, shown in
+    /// `/sys/devices/system/clocksource/clocksource0/available_clocksource`.
+    const NAME: &'static CStr;
+
+    /// The rating of the clock source, which the core uses to select the best one: 1-99 for
+    /// unfit sources, 100-199 for working ones, 200-299 for good ones, 300-399 for desirable ones
+    /// and 400-499 for perfect ones.
+    const RATING: u32;
+
+    /// The width of the counter in bits, at most 64.
+    const BITS: u32;
+
+    /// Reads the counter.
+    ///
+    /// It is called in any context, including with interrupts disabled and from NMIs, so it must
+    /// not sleep, take locks or print. Only the low [`Source::BITS`] bits are used.
+    fn read(&self) -> u64;
+}
+
+/// A registered clock source, the kernel's `struct clocksource`.
+///
+/// The clock source is unregistered when this is dropped, after the core switched to another
+/// one if it was in use. If no other clock source can replace it, it stays registered, and its
+/// memory is leaked.
+///
+/// # Invariants
+///
+/// The clock source of `inner` is registered, and reads the counter of its data.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, clocksource, io_mem::IoMem, prelude::*};
+///
+/// const TIMERUS_CNTR_1US: usize = 0x10;
+///
+/// struct Timerus {
+///     regs: IoMem<0x100>,
+/// }
+///
+/// impl clocksource::Source for Timerus {
+///     const NAME: &'static CStr = c_str!("timer_us");
+///     const RATING: u32 = 300;
+///     const BITS: u32 = 32;
+///
+///     fn read(&self) -> u64 {
+///         self.regs.readl(TIMERUS_CNTR_1US).into()
+///     }
+/// }
+///
+/// fn probe(regs: IoMem<0x100>) -> Result<clocksource::Registration<Timerus>> {
+///     clocksource::Registration::register(1_000_000, Timerus { regs })
+/// }
+/// ```
+pub struct Registration<T: Source> {
+    inner: ManuallyDrop<Pin<Box<Inner<T>>>>,
+}
+
+#[repr(C)]
+struct Inner<T: Source> {
+    // Must be the first field, see `Inner::read_callback`.
+    cs: Opaque<bindings::clocksource>,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: Source> Registration<T> {
+    /// Registers a clock source whose counter runs at `hz` Hz, with the driver data `data`.
+    ///
+    /// Fails with `EINVAL` if [`Source::BITS`] is zero or more than 64.
+    pub fn register(hz: u32, data: T) -> Result<Self> {
+        if T::BITS == 0 || T::BITS > 64 {
+            return Err(EINVAL);
+        }
+        crate::might_sleep!();
+        // SAFETY: The closure initialises all fields on success, and drops those it initialised
+        // on failure. The clock source isn't moved once it is registered, since it is pinned.
+        let init = unsafe {
+            init::pin_init_from_closure::<_, Error>(move |slot: *mut Inner<T>| {
+                // The data is written before registering, since the counter can be read as soon
+                // as the clock source is registered.
+                let data_ptr = ptr::addr_of_mut!((*slot).data);
+                data_ptr.write(data);
+                let cs = Opaque::raw_get(ptr::addr_of!((*slot).cs));
+                cs.write(bindings::clocksource {
+                    name: T::NAME.as_char_ptr(),
+                    rating: T::RATING as _,
+                    read: Some(Inner::<T>::read_callback),
+                    mask: u64::MAX >> (64 - T::BITS),
+                    flags: bindings::CLOCK_SOURCE_IS_CONTINUOUS as _,
+                    // SAFETY: All other fields are optional or filled in on registration.
+                    ..MaybeUninit::zeroed().assume_init()
+                });
+                if let Err(e) = to_result(bindings::__clocksource_register_scale(cs, 1, hz)) {
+                    ptr::drop_in_place(data_ptr);
+                    return Err(e);
+                }
+                Ok(())
+            })
+        };
+        // INVARIANT: The clock source is only initialised if it was registered.
+        Ok(Self {
+            inner: ManuallyDrop::new(Box::pin_init(init)?),
+        })
+    }
+
+    /// Returns the driver data of the clock source.
+    pub fn data(&self) -> &T {
+        &self.inner.data
+    }
+}
+
+impl<T: Source> Inner<T> {
+    unsafe extern "C" fn read_callback(cs: *mut bindings::clocksource) -> u64 {
+        // SAFETY: The core calls this with a clock source registered by `register`, which is the
+        // first field of an `Inner<T>`, which is `repr(C)`.
+        let this = unsafe { &*cs.cast::<Self>() };
+        this.data.read()
+    }
+}
+
+impl<T: Source> Drop for Registration<T> {
+    fn drop(&mut self) {
+        // SAFETY: The clock source is registered by the type invariants.
+        if unsafe { bindings::clocksource_unregister(self.inner.cs.get()) } != 0 {
+            // It is still in use, and would be read after it is freed.
+            crate::pr_warn!("Leaking clocksource {}, which can't be replaced\n", T::NAME);
+            return;
+        }
+        // SAFETY: The clock source isn't registered anymore, and `inner` isn't used afterwards.
+        unsafe { ManuallyDrop::drop(&mut self.inner) };
+    }
+}
+
+// SAFETY: The clock source can be unregistered from any thread, and the driver data is `Send`.
+unsafe impl<T: Source> Send for Registration<T> {}
+
+// SAFETY: The methods that take `&self` only read fields that never change, and the driver data is
+// `Sync`.
+unsafe impl<T: Source> Sync for Registration<T> {}
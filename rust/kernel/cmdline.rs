@@ -0,0 +1,192 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Kernel command line and boot configuration.
+//!
+//! Built-in code that must pick a mode of operation before module parameters are parsed, or that
+//! has no module parameters, reads its boot parameters from the command line, with [`params`] or
+//! [`get`], or from the boot configuration, with [`bootconfig_value`].
+//!
+//! C header: [`include/linux/init.h`](../../../../include/linux/init.h)
+
+use crate::{
+    bindings,
+    error::{code::*, Result},
+    str::{self, BStr, CStr},
+};
+
+/// Returns the command line that the kernel was booted with, including the parameters that were
+/// embedded in the kernel or taken from the boot configuration.
+pub fn saved() -> &'static CStr {
+    // SAFETY: `saved_command_line` is set up before any Rust code runs, and is never freed nor
+    // changed afterwards.
+    unsafe { CStr::from_char_ptr(bindings::saved_command_line) }
+}
+
+/// Returns the parameters of the command line that the kernel was booted with, see [`saved`].
+pub fn params() -> Params<'static> {
+    parse(saved())
+}
+
+/// Returns the last parameter named `name` of the command line that the kernel was booted with,
+/// like the kernel does for parameters given several times.
+///
+/// Dashes and underscores in names are equivalent.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{cmdline, prelude::*};
+///
+/// // E.g. `tegra_mode=0x2`.
+/// let mode: u32 = cmdline::get(b"tegra_mode")
+///     .map(|p| p.value_as())
+///     .transpose()?
+///     .unwrap_or(1);
+/// # Ok::<(), Error>(())
+/// ```
+pub fn get(name: &BStr) -> Option<Param<'static>> {
+    params().filter(|p| p.is(name)).last()
+}
+
+/// Returns the parameters of the command line `cmdline`.
+pub fn parse(cmdline: &BStr) -> Params<'_> {
+    Params { rest: cmdline }
+}
+
+/// An iterator over the parameters of a command line, returned by [`parse`].
+///
+/// Parameters are separated by whitespace, and their values can be quoted with `"` to contain
+/// whitespace, like `next_arg` does in C. The iteration stops at `--`, after which the arguments
+/// are those of init.
+#[derive(Clone, Debug)]
+pub struct Params<'a> {
+    rest: &'a BStr,
+}
+
+impl<'a> Iterator for Params<'a> {
+    type Item = Param<'a>;
+
+    fn next(&mut self) -> Option<Param<'a>> {
+        let start = self.rest.iter().position(|c| !c.is_ascii_whitespace())?;
+        let arg = &self.rest[start..];
+
+        let mut in_quote = false;
+        let mut end = arg.len();
+        let mut equals = None;
+        for (i, &c) in arg.iter().enumerate() {
+            match c {
+                b'"' => in_quote = !in_quote,
+                b'=' if equals.is_none() && !in_quote => equals = Some(i),
+                c if c.is_ascii_whitespace() && !in_quote => {
+                    end = i;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        self.rest = &arg[end..];
+        let arg = &arg[..end];
+        if arg == b"--" {
+            self.rest = &[];
+            return None;
+        }
+
+        let (name, value) = match equals {
+            Some(i) => (&arg[..i], Some(unquote(&arg[i + 1..]))),
+            None => (arg, None),
+        };
+        Some(Param {
+            name: unquote(name),
+            value,
+        })
+    }
+}
+
+/// Removes the quotes around `s`, if any.
+fn unquote(s: &BStr) -> &BStr {
+    let s = s.strip_prefix(b"\"").unwrap_or(s);
+    s.strip_suffix(b"\"").unwrap_or(s)
+}
+
+/// A boot parameter, `name` or `name=value`.
+#[derive(Clone, Copy, Debug)]
+pub struct Param<'a> {
+    name: &'a BStr,
+    value: Option<&'a BStr>,
+}
+
+impl<'a> Param<'a> {
+    /// Returns the name of the parameter.
+    pub fn name(&self) -> &'a BStr {
+        self.name
+    }
+
+    /// Returns the value of the parameter, without quotes, or `None` if it has none.
+    pub fn value(&self) -> Option<&'a BStr> {
+        self.value
+    }
+
+    /// Returns whether the parameter is named `name`, with dashes and underscores being
+    /// equivalent, like `parameq` does in C.
+    pub fn is(&self, name: &BStr) -> bool {
+        let dash = |c: &u8| if *c == b'-' { b'_' } else { *c };
+        self.name.len() == name.len() && self.name.iter().map(dash).eq(name.iter().map(dash))
+    }
+
+    /// Parses the value of the parameter as a `T`.
+    pub fn value_as<T: FromParam>(&self) -> Result<T> {
+        T::from_param(self.value)
+    }
+}
+
+/// A type that boot parameters can be parsed as.
+pub trait FromParam: Sized {
+    /// Parses `value`, which is `None` for parameters without a value.
+    fn from_param(value: Option<&BStr>) -> Result<Self>;
+}
+
+impl FromParam for bool {
+    /// Accepts the values of `kstrtobool`, and no value, which is `true`.
+    fn from_param(value: Option<&BStr>) -> Result<Self> {
+        value.map_or(Ok(true), str::kstrtobool)
+    }
+}
+
+macro_rules! impl_from_param_int {
+    ($($t:ty),*) => {
+        $(
+            impl FromParam for $t {
+                /// Accepts the values of `kstrto*` in base 0, e.g. `0x10` for 16.
+                fn from_param(value: Option<&BStr>) -> Result<Self> {
+                    str::kstrto(value.ok_or(EINVAL)?, 0)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_param_int!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+/// Returns the first value of the key `key` of the boot configuration, e.g. `kernel.foo.bar`, or
+/// `None` if there is no such key or it has no value.
+///
+/// # Safety
+///
+/// The boot configuration is freed at the end of the boot, so this must only be called from
+/// built-in initialisation code, and the value must not be used after it.
+#[cfg(CONFIG_BOOT_CONFIG)]
+pub unsafe fn bootconfig_value(key: &CStr) -> Option<&'static CStr> {
+    let mut vnode = core::ptr::null_mut();
+    // SAFETY: `key` is only used during the call, and the boot configuration is valid by the
+    // safety requirements.
+    let value = unsafe {
+        bindings::xbc_node_find_value(core::ptr::null_mut(), key.as_char_ptr(), &mut vnode)
+    };
+    if value.is_null() {
+        None
+    } else {
+        // SAFETY: The value is a string of the boot configuration, which is valid by the safety
+        // requirements.
+        Some(unsafe { CStr::from_char_ptr(value) })
+    }
+}
@@ -0,0 +1,131 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Fallible cloning and construction of collections.
+//!
+//! The kernel builds `alloc` with `no_global_oom_handling`, so the methods of [`Vec`], [`Box`]
+//! and friends that would abort on allocation failure, e.g. `Vec::push`, `Clone::clone` or
+//! `vec!`, aren't available. The fallible `try_*` methods replace them, and this module adds the
+//! missing pieces: [`TryClone`], to duplicate values that own allocations, and [`try_vec!`], to
+//! build vectors.
+//!
+//! [`try_vec!`]: crate::try_vec
+
+use crate::{
+    error::{Error, Result},
+    str::CString,
+};
+use alloc::{boxed::Box, vec::Vec};
+
+/// Values that can be duplicated, failing if memory can't be allocated.
+///
+/// It is the fallible equivalent of [`Clone`], for types owning allocations.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::collections::TryClone;
+/// # use kernel::prelude::*;
+///
+/// let names = kernel::try_vec![Box::try_new(1u32)?, Box::try_new(2)?]?;
+/// let copy = names.try_clone()?;
+/// assert_eq!(*copy[1], 2);
+/// # Ok::<(), Error>(())
+/// ```
+pub trait TryClone: Sized {
+    /// Returns a copy of the value.
+    fn try_clone(&self) -> Result<Self>;
+}
+
+macro_rules! impl_try_clone_copy {
+    ($($t:ty),*) => {
+        $(
+            impl TryClone for $t {
+                fn try_clone(&self) -> Result<Self> {
+                    Ok(*self)
+                }
+            }
+        )*
+    };
+}
+
+impl_try_clone_copy!(bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+impl<T: TryClone> TryClone for Vec<T> {
+    fn try_clone(&self) -> Result<Self> {
+        let mut v = Vec::try_with_capacity(self.len())?;
+        for item in self {
+            // The capacity was reserved above, so this doesn't allocate.
+            v.try_push(item.try_clone()?)?;
+        }
+        Ok(v)
+    }
+}
+
+impl<T: TryClone> TryClone for Box<T> {
+    fn try_clone(&self) -> Result<Self> {
+        Ok(Box::try_new((**self).try_clone()?)?)
+    }
+}
+
+impl<T: TryClone> TryClone for Option<T> {
+    fn try_clone(&self) -> Result<Self> {
+        self.as_ref().map(T::try_clone).transpose()
+    }
+}
+
+impl TryClone for CString {
+    fn try_clone(&self) -> Result<Self> {
+        Ok(CString::try_from(&**self)?)
+    }
+}
+
+impl TryClone for Error {
+    fn try_clone(&self) -> Result<Self> {
+        Ok(*self)
+    }
+}
+
+/// Creates a [`Vec`] holding the arguments, failing if memory can't be allocated.
+///
+/// It is the fallible equivalent of `vec!`, and evaluates to a [`Result<Vec<T>>`](Result).
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// let v = kernel::try_vec![1u8, 2, 3]?;
+/// assert_eq!(v, [1, 2, 3]);
+///
+/// let zeroes = kernel::try_vec![0u32; 16]?;
+/// assert_eq!(zeroes.len(), 16);
+/// # Ok::<(), Error>(())
+/// ```
+#[macro_export]
+macro_rules! try_vec {
+    (@unit $x:expr) => {
+        ()
+    };
+    () => {
+        $crate::error::Result::<_>::Ok($crate::prelude::Vec::new())
+    };
+    ($elem:expr; $n:expr) => {
+        (|| -> $crate::error::Result<$crate::prelude::Vec<_>> {
+            let n: usize = $n;
+            let mut v = $crate::prelude::Vec::try_with_capacity(n)?;
+            v.try_resize(n, $elem)?;
+            Ok(v)
+        })()
+    };
+    ($($x:expr),+ $(,)?) => {
+        (|| -> $crate::error::Result<$crate::prelude::Vec<_>> {
+            let mut v = $crate::prelude::Vec::try_with_capacity(
+                [$($crate::try_vec!(@unit $x)),+].len(),
+            )?;
+            $(
+                // The capacity was reserved above, so this doesn't allocate.
+                v.try_push($x)?;
+            )+
+            Ok(v)
+        })()
+    };
+}
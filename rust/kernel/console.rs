@@ -0,0 +1,216 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Consoles, which print kernel messages.
+//!
+//! A console is selected on the command line with `console=<name><index>`, e.g. `console=ttyS0`.
+//! Standalone consoles implement [`Console`]; consoles of serial ports are created with
+//! [`crate::serial::UartDriver::register_with_console`], and write through the port.
+//!
+//! C header: [`include/linux/console.h`](../../../../include/linux/console.h)
+
+use crate::{
+    bindings,
+    error::{code::*, from_result, Result},
+    str::CStr,
+    types::Opaque,
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::{c_char, c_int, c_uint},
+    marker::{PhantomData, PhantomPinned},
+    pin::Pin,
+};
+use macros::vtable;
+
+/// Flags of a console, the kernel's `CON_*` values.
+pub mod flags {
+    /// Prints the messages that were logged before the console was registered.
+    pub const PRINTBUFFER: i16 = crate::bindings::cons_flags_CON_PRINTBUFFER as _;
+    /// The console is the one that `/dev/console` refers to.
+    pub const CONSDEV: i16 = crate::bindings::cons_flags_CON_CONSDEV as _;
+    /// The console is enabled even if it isn't selected on the command line.
+    pub const ENABLED: i16 = crate::bindings::cons_flags_CON_ENABLED as _;
+    /// The console is a boot console, which is unregistered when a real console is registered.
+    pub const BOOT: i16 = crate::bindings::cons_flags_CON_BOOT as _;
+    /// The console can be used while a CPU is offline.
+    pub const ANYTIME: i16 = crate::bindings::cons_flags_CON_ANYTIME as _;
+}
+
+/// A console that doesn't belong to a serial port.
+#[vtable]
+pub trait Console: Sync + 'static {
+    /// The name of the console, without its index, e.g. `myrustcon` for `console=myrustcon0`.
+    ///
+    /// It must be shorter than 16 bytes.
+    const NAME: &'static CStr;
+
+    /// The flags of the console, see [`flags`].
+    const FLAGS: i16 = flags::PRINTBUFFER;
+
+    /// The index of the console, or `-1` to use the one from the command line.
+    const INDEX: i16 = -1;
+
+    /// Writes kernel messages.
+    ///
+    /// This may be called from any context, including NMIs and while the kernel is crashing, and
+    /// must not sleep.
+    fn write(index: i16, s: &[u8]);
+
+    /// Prepares the console with the options from the command line (the part after the comma in
+    /// `console=myrustcon0,115200n8`), before it is enabled.
+    ///
+    /// `index` is the index from the command line, or [`Console::INDEX`].
+    fn setup(_index: i16, _options: Option<&CStr>) -> Result {
+        Ok(())
+    }
+}
+
+/// A console, the kernel's `struct console`.
+///
+/// The console is unregistered when this is dropped.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, console::{self, Console}};
+/// # use kernel::prelude::*;
+///
+/// struct DebugCon;
+///
+/// #[vtable]
+/// impl Console for DebugCon {
+///     const NAME: &'static CStr = c_str!("myrustcon");
+///
+///     fn write(_index: i16, s: &[u8]) {
+///         for &c in s {
+///             // Write `c` to a debug port.
+///             let _ = c;
+///         }
+///     }
+/// }
+///
+/// fn setup() -> Result<Pin<Box<console::Registration>>> {
+///     let mut con = console::Registration::try_new::<DebugCon>()?;
+///     con.as_mut().register();
+///     Ok(con)
+/// }
+/// ```
+pub struct Registration {
+    con: Opaque<bindings::console>,
+    _pin: PhantomPinned,
+}
+
+// SAFETY: The console can be unregistered from any thread.
+unsafe impl Send for Registration {}
+
+// SAFETY: The console is only used by the printk core, which has its own synchronisation.
+unsafe impl Sync for Registration {}
+
+impl Registration {
+    /// Creates a console that writes with `T`, without registering it.
+    pub fn try_new<T: Console>() -> Result<Pin<Box<Self>>> {
+        // SAFETY: The callbacks only use the console they are called with.
+        unsafe {
+            Self::new_raw(
+                T::NAME,
+                T::FLAGS,
+                T::INDEX,
+                bindings::console {
+                    write: Some(Adapter::<T>::write_callback),
+                    setup: Some(Adapter::<T>::setup_callback),
+                    // SAFETY: All other fields are optional, for which zero is valid.
+                    ..core::mem::MaybeUninit::zeroed().assume_init()
+                },
+            )
+        }
+    }
+
+    /// Creates a console from `con`, with the given name, flags and index, without registering
+    /// it.
+    ///
+    /// Fails with `EINVAL` if the name is too long.
+    ///
+    /// # Safety
+    ///
+    /// The callbacks of `con` must be safe to call with the console while it is registered.
+    pub(crate) unsafe fn new_raw(
+        name: &CStr,
+        flags: i16,
+        index: i16,
+        mut con: bindings::console,
+    ) -> Result<Pin<Box<Self>>> {
+        if name.len() >= con.name.len() {
+            return Err(EINVAL);
+        }
+        for (dst, &src) in con.name.iter_mut().zip(name.as_bytes()) {
+            *dst = src as c_char;
+        }
+        con.flags = flags;
+        con.index = index;
+        Ok(Pin::from(Box::try_new(Self {
+            con: Opaque::new(con),
+            _pin: PhantomPinned,
+        })?))
+    }
+
+    pub(crate) fn as_raw(&self) -> *mut bindings::console {
+        self.con.get()
+    }
+
+    /// Registers the console, which is then enabled if it is selected on the command line (or has
+    /// the [`flags::ENABLED`] flag).
+    pub fn register(self: Pin<&mut Self>) {
+        // SAFETY: The console is pinned and is unregistered when it is dropped. Registering it
+        // again is rejected by `register_console`.
+        unsafe { bindings::register_console(self.as_raw()) };
+    }
+
+    /// Returns `true` if the console is enabled.
+    pub fn is_enabled(&self) -> bool {
+        // SAFETY: The console is valid, and its flags are read once.
+        let flags = unsafe { core::ptr::addr_of!((*self.as_raw()).flags).read_volatile() };
+        flags & flags::ENABLED != 0
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        // SAFETY: The console is valid. This is a no-op if it isn't registered.
+        unsafe { bindings::unregister_console(self.as_raw()) };
+    }
+}
+
+struct Adapter<T: Console>(PhantomData<T>);
+
+impl<T: Console> Adapter<T> {
+    unsafe extern "C" fn write_callback(
+        co: *mut bindings::console,
+        s: *const c_char,
+        count: c_uint,
+    ) {
+        // SAFETY: The printk core calls this with a registered console and a valid buffer of
+        // `count` bytes.
+        let (index, s) = unsafe {
+            (
+                (*co).index,
+                core::slice::from_raw_parts(s.cast(), count as _),
+            )
+        };
+        T::write(index, s);
+    }
+
+    unsafe extern "C" fn setup_callback(co: *mut bindings::console, options: *mut c_char) -> c_int {
+        from_result(|| {
+            // SAFETY: The printk core calls this with a registered console and either null or a
+            // `NUL`-terminated string of options.
+            let (index, options) = unsafe {
+                (
+                    (*co).index,
+                    (!options.is_null()).then(|| CStr::from_char_ptr(options)),
+                )
+            };
+            T::setup(index, options)?;
+            Ok(0)
+        })
+    }
+}
@@ -0,0 +1,179 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Tracking of atomic contexts.
+//!
+//! With `CONFIG_DEBUG_ATOMIC_SLEEP`, [`might_sleep`](crate::might_sleep), which all the sleeping
+//! abstractions call (allocations with `GFP_KERNEL`, mutexes, [`msleep`](crate::delay::msleep),
+//! ...), warns if it is called in atomic context. The kernel crate then also tracks the atomic
+//! sections entered by Rust code, e.g. interrupt handlers, spinlock guards and RCU read-side
+//! critical sections, so that the warning names the place where the section was entered, which
+//! the stack trace of the warning doesn't show.
+//!
+//! Without `CONFIG_DEBUG_ATOMIC_SLEEP`, [`AtomicSection`] is empty and compiles to nothing.
+
+#[cfg(CONFIG_DEBUG_ATOMIC_SLEEP)]
+use crate::{bindings, c_str};
+use core::marker::PhantomData;
+#[cfg(CONFIG_DEBUG_ATOMIC_SLEEP)]
+use core::{
+    ffi::c_ulong,
+    mem::size_of,
+    panic::Location,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
+
+/// The kind of an atomic section.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// A hard interrupt handler.
+    HardIrq,
+    /// A softirq, e.g. a tasklet or a timer callback.
+    SoftIrq,
+    /// An RCU read-side critical section.
+    Rcu,
+    /// Any other atomic section, e.g. with a spinlock held or preemption disabled.
+    Atomic,
+}
+
+#[cfg(CONFIG_DEBUG_ATOMIC_SLEEP)]
+impl Kind {
+    fn from_raw(kind: usize) -> Self {
+        match kind {
+            0 => Self::HardIrq,
+            1 => Self::SoftIrq,
+            2 => Self::Rcu,
+            _ => Self::Atomic,
+        }
+    }
+}
+
+/// The atomic sections entered on a CPU.
+///
+/// This is the layout of the per-CPU `rust_atomic_sections` array of `rust/helpers.c`, which is
+/// zero-initialised.
+#[cfg(CONFIG_DEBUG_ATOMIC_SLEEP)]
+#[repr(C)]
+struct Cpu {
+    /// The number of nested sections.
+    depth: AtomicUsize,
+    /// The kind of the outermost section.
+    kind: AtomicUsize,
+    /// Where the outermost section was entered.
+    location: AtomicPtr<Location<'static>>,
+}
+
+#[cfg(CONFIG_DEBUG_ATOMIC_SLEEP)]
+crate::static_assert!(size_of::<Cpu>() == 3 * size_of::<c_ulong>());
+
+#[cfg(CONFIG_DEBUG_ATOMIC_SLEEP)]
+impl Cpu {
+    /// Returns the sections of the current CPU.
+    ///
+    /// Sections disable preemption, so the sections entered on a CPU are those of the task running
+    /// on it, and of the interrupts that interrupted it. Interrupts may enter sections
+    /// concurrently, which is why the fields are atomic.
+    ///
+    /// # Safety
+    ///
+    /// Preemption must be disabled while the returned reference exists.
+    unsafe fn current<'a>() -> &'a Self {
+        // SAFETY: The per-CPU storage is static and has the layout of `Cpu`. All zeroes is a valid
+        // value of its fields. The caller keeps us on this CPU.
+        unsafe { &*bindings::this_cpu_atomic_sections().cast::<Self>() }
+    }
+}
+
+/// An atomic section, which ends when this is dropped.
+///
+/// Abstractions that call Rust code in atomic context enter one around it, so that sleeping in it
+/// is reported. Drivers may also enter one around code that must not sleep, e.g. with a lock of C
+/// code held.
+///
+/// With `CONFIG_DEBUG_ATOMIC_SLEEP`, the section disables preemption, so that it is tracked on the
+/// CPU it was entered on.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::context::{AtomicSection, Kind};
+///
+/// fn update_hardware_state() {
+///     // Called with the lock of the C core held.
+///     let _section = AtomicSection::enter(Kind::Atomic);
+///     // ...
+/// }
+/// ```
+pub struct AtomicSection {
+    _not_send: PhantomData<*mut ()>,
+}
+
+impl AtomicSection {
+    /// Enters an atomic section of the kind `kind`, at the location of the caller.
+    #[inline]
+    #[track_caller]
+    #[cfg_attr(not(CONFIG_DEBUG_ATOMIC_SLEEP), allow(unused_variables))]
+    pub fn enter(kind: Kind) -> Self {
+        #[cfg(CONFIG_DEBUG_ATOMIC_SLEEP)]
+        {
+            // SAFETY: Preemption is enabled again when the section is dropped.
+            unsafe { bindings::preempt_disable() };
+            // SAFETY: Preemption was disabled above.
+            let cpu = unsafe { Cpu::current() };
+            if cpu.depth.fetch_add(1, Ordering::Relaxed) == 0 {
+                cpu.kind.store(kind as usize, Ordering::Relaxed);
+                let location: *const Location<'static> = Location::caller();
+                cpu.location.store(location.cast_mut(), Ordering::Relaxed);
+            }
+        }
+        Self {
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl Drop for AtomicSection {
+    #[inline]
+    fn drop(&mut self) {
+        #[cfg(CONFIG_DEBUG_ATOMIC_SLEEP)]
+        {
+            // SAFETY: Preemption is disabled until the end of the section, which isn't `Send`, so
+            // this is the CPU it was entered on.
+            unsafe { Cpu::current() }
+                .depth
+                .fetch_sub(1, Ordering::Relaxed);
+            // SAFETY: Preemption was disabled when the section was entered.
+            unsafe { bindings::preempt_enable() };
+        }
+    }
+}
+
+/// Reports where the atomic section that the current task is in was entered, if any, after
+/// [`might_sleep`](crate::might_sleep) warned about it.
+#[cfg(CONFIG_DEBUG_ATOMIC_SLEEP)]
+pub(crate) fn report_section() {
+    // SAFETY: Preemption is enabled again below.
+    unsafe { bindings::preempt_disable() };
+    // SAFETY: Preemption was disabled above. If the task is in a section, it can't be interrupted
+    // by one that is still running, so the sections of the CPU are those of the task.
+    let cpu = unsafe { Cpu::current() };
+    let location = cpu.location.load(Ordering::Relaxed);
+    if cpu.depth.load(Ordering::Relaxed) != 0 && !location.is_null() {
+        // SAFETY: The name is a `NUL`-terminated string that lives forever.
+        let ratelimit =
+            unsafe { bindings::__printk_ratelimit(c_str!("might_sleep").as_char_ptr()) };
+        if ratelimit != 0 {
+            let kind = Kind::from_raw(cpu.kind.load(Ordering::Relaxed));
+            // SAFETY: The location was stored by `AtomicSection::enter`, and is static.
+            let location = unsafe { &*location };
+            crate::pr_err!(
+                "Rust {:?} section entered at {}:{}\n",
+                kind,
+                location.file(),
+                location.line()
+            );
+        }
+    }
+    // SAFETY: Preemption was disabled above.
+    unsafe { bindings::preempt_enable() };
+}
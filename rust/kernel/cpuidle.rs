@@ -0,0 +1,259 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! CPU idle drivers.
+//!
+//! A CPU idle driver describes the idle states of the CPUs, from the shallowest to the deepest,
+//! and registers a [`Registration`]. The cpuidle governor then picks a state whenever a CPU has
+//! nothing to run, from the expected idle time and the latency and residency of the states, and
+//! the driver enters it.
+//!
+//! C header: [`include/linux/cpuidle.h`](../../../../include/linux/cpuidle.h)
+
+use crate::{
+    bindings,
+    cpumask::CpuMask,
+    error::{code::*, from_result, to_result, Result},
+    str::{CStr, CString},
+    types::Opaque,
+    ThisModule,
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::{c_char, c_int},
+    fmt,
+    marker::PhantomPinned,
+    mem::MaybeUninit,
+    pin::Pin,
+    ptr,
+};
+use macros::vtable;
+
+/// Flags of idle states, the kernel's `CPUIDLE_FLAG_*` values.
+pub mod flags {
+    /// The local timer of the CPU stops in the state, so a broadcast timer wakes it up.
+    pub const TIMER_STOP: u32 = crate::bindings::CPUIDLE_FLAG_TIMER_STOP;
+    /// The state is disabled by default, and can be enabled through sysfs.
+    pub const OFF: u32 = crate::bindings::CPUIDLE_FLAG_OFF;
+    /// The state is never entered.
+    pub const UNUSABLE: u32 = crate::bindings::CPUIDLE_FLAG_UNUSABLE;
+}
+
+/// An idle state, the kernel's `struct cpuidle_state`.
+#[derive(Clone, Copy, Debug)]
+pub struct State<'a> {
+    /// The name of the state, of at most 15 bytes.
+    pub name: &'a CStr,
+    /// The description of the state, of at most 31 bytes.
+    pub desc: &'a CStr,
+    /// The worst-case time to leave the state, in microseconds.
+    pub exit_latency_us: u32,
+    /// The minimum time to stay in the state for it to save power, in microseconds, including
+    /// the time to enter and leave it.
+    pub target_residency_us: u32,
+    /// The flags of the state, see [`flags`].
+    pub flags: u32,
+}
+
+/// The operations of a CPU idle driver, the kernel's `struct cpuidle_state` callbacks.
+///
+/// The driver data of the driver implements this trait. The operations are called on the CPU
+/// that goes idle, with interrupts disabled, and must not sleep nor enable interrupts.
+#[vtable]
+pub trait Operations: Send + Sync + Sized + 'static {
+    /// Puts the CPU `cpu` into the idle state `index`, until an interrupt wakes it up, and
+    /// returns the index of the state that was actually entered.
+    fn enter(data: &Self, cpu: u32, index: usize) -> Result<usize>;
+
+    /// Puts the CPU `cpu` into the idle state `index` during suspend-to-idle, with the tick
+    /// stopped.
+    fn enter_s2idle(_data: &Self, _cpu: u32, _index: usize) -> Result {
+        Err(EINVAL)
+    }
+}
+
+/// Copies `src` into the fixed-size string `dst`, failing with `EINVAL` if it is too long.
+fn copy_str(dst: &mut [c_char], src: &CStr) -> Result {
+    let src = src.as_bytes_with_nul();
+    if src.len() > dst.len() {
+        return Err(EINVAL);
+    }
+    for (d, &s) in dst.iter_mut().zip(src) {
+        *d = s as c_char;
+    }
+    Ok(())
+}
+
+/// A registered CPU idle driver.
+///
+/// The driver is unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `drv` is registered if `registered` is `true`. Its name is `name`, and its CPU mask is `cpus`,
+/// or all possible CPUs if it is `None`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, fmt, prelude::*, ThisModule};
+/// use kernel::cpuidle::{self, flags, Operations, State};
+///
+/// struct Idle;
+///
+/// #[vtable]
+/// impl Operations for Idle {
+///     fn enter(_data: &Self, _cpu: u32, index: usize) -> Result<usize> {
+///         // Program the power controller for the state, then wait for an interrupt.
+///         Ok(index)
+///     }
+/// }
+///
+/// fn register(module: &'static ThisModule) -> Result<Pin<Box<cpuidle::Registration<Idle>>>> {
+///     let states = [
+///         State {
+///             name: c_str!("WFI"),
+///             desc: c_str!("CPU clock gated"),
+///             exit_latency_us: 1,
+///             target_residency_us: 1,
+///             flags: 0,
+///         },
+///         State {
+///             name: c_str!("C7"),
+///             desc: c_str!("CPU power gated"),
+///             exit_latency_us: 2000,
+///             target_residency_us: 10000,
+///             flags: flags::TIMER_STOP,
+///         },
+///     ];
+///     cpuidle::Registration::register(fmt!("soc_idle"), module, &states, None, Idle)
+/// }
+/// ```
+#[repr(C)]
+pub struct Registration<T: Operations> {
+    // Must be the first field, see `data_of`.
+    drv: Opaque<bindings::cpuidle_driver>,
+    name: CString,
+    cpus: Option<CpuMask>,
+    registered: bool,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: Operations> Registration<T> {
+    /// Registers a CPU idle driver named `name`, which may be built at runtime, for the module
+    /// `module`, with the idle states `states` and the driver data `data`.
+    ///
+    /// The driver handles the CPUs in `cpus`, or all possible ones if it is `None`. The first
+    /// state must be the one that the CPU enters without help, e.g. WFI, which is used when no
+    /// other state fits.
+    pub fn register(
+        name: fmt::Arguments<'_>,
+        module: &'static ThisModule,
+        states: &[State<'_>],
+        cpus: Option<CpuMask>,
+        data: T,
+    ) -> Result<Pin<Box<Self>>> {
+        crate::might_sleep!();
+        if states.is_empty() || states.len() > bindings::CPUIDLE_STATE_MAX as usize {
+            return Err(EINVAL);
+        }
+        let name = CString::try_from_fmt(name)?;
+
+        let mut reg = Pin::from(Box::try_new(Self {
+            // SAFETY: All-zeroes is a valid, unregistered driver.
+            drv: Opaque::new(unsafe { MaybeUninit::zeroed().assume_init() }),
+            name,
+            cpus,
+            registered: false,
+            data,
+            _pin: PhantomPinned,
+        })?);
+        // SAFETY: The driver isn't registered yet, so nothing else uses it. The name and the CPU
+        // mask are on the heap, and the driver is unregistered before they are freed.
+        let drv = unsafe { &mut *reg.drv.get() };
+        drv.name = reg.name.as_char_ptr();
+        drv.owner = module.as_ptr();
+        drv.cpumask = reg
+            .cpus
+            .as_ref()
+            .map_or(ptr::null_mut(), |cpus| cpus.as_raw() as *mut _);
+        drv.state_count = states.len() as _;
+        for (raw, state) in drv.states.iter_mut().zip(states) {
+            copy_str(&mut raw.name, state.name)?;
+            copy_str(&mut raw.desc, state.desc)?;
+            raw.exit_latency = state.exit_latency_us;
+            raw.target_residency = state.target_residency_us;
+            raw.flags = state.flags;
+            raw.enter = Some(Self::enter_callback);
+            if T::HAS_ENTER_S2IDLE {
+                raw.enter_s2idle = Some(Self::enter_s2idle_callback);
+            }
+        }
+
+        // SAFETY: The driver was set up above, and is pinned.
+        to_result(unsafe { bindings::cpuidle_register(reg.drv.get(), ptr::null()) })?;
+        // INVARIANT: The driver was registered above.
+        // SAFETY: `registered` isn't structurally pinned.
+        unsafe { reg.as_mut().get_unchecked_mut() }.registered = true;
+        Ok(reg)
+    }
+
+    /// Returns the driver data.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    /// Returns the driver data of the driver `drv`.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `drv` is a driver registered by a `Registration<T>`.
+    unsafe fn data_of<'a>(drv: *mut bindings::cpuidle_driver) -> &'a T {
+        // SAFETY: `drv` is the first field of a `Registration<T>`, which is `repr(C)`, and
+        // outlives the driver by the safety requirements.
+        unsafe { &(*drv.cast::<Self>()).data }
+    }
+
+    unsafe extern "C" fn enter_callback(
+        dev: *mut bindings::cpuidle_device,
+        drv: *mut bindings::cpuidle_driver,
+        index: c_int,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The cpuidle core calls this with the device of the idle CPU and a driver
+            // registered by `register`.
+            let (data, cpu) = unsafe { (Self::data_of(drv), (*dev).cpu) };
+            Ok(T::enter(data, cpu, index as _)? as _)
+        })
+    }
+
+    unsafe extern "C" fn enter_s2idle_callback(
+        dev: *mut bindings::cpuidle_device,
+        drv: *mut bindings::cpuidle_driver,
+        index: c_int,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The cpuidle core calls this with the device of the idle CPU and a driver
+            // registered by `register`.
+            let (data, cpu) = unsafe { (Self::data_of(drv), (*dev).cpu) };
+            T::enter_s2idle(data, cpu, index as _)?;
+            Ok(0)
+        })
+    }
+}
+
+impl<T: Operations> Drop for Registration<T> {
+    fn drop(&mut self) {
+        if self.registered {
+            // SAFETY: The driver was registered by the type invariants. Unregistering it waits
+            // for CPUs to leave its states.
+            unsafe { bindings::cpuidle_unregister(self.drv.get()) };
+        }
+    }
+}
+
+// SAFETY: The driver can be unregistered from any thread, and the driver data is `Send`.
+unsafe impl<T: Operations> Send for Registration<T> {}
+
+// SAFETY: The methods that take `&self` only read the driver data, which is `Sync`.
+unsafe impl<T: Operations> Sync for Registration<T> {}
@@ -0,0 +1,110 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! CPU masks.
+//!
+//! C header: [`include/linux/cpumask.h`](../../../../include/linux/cpumask.h)
+
+use crate::{
+    bindings,
+    error::{code::*, Result},
+};
+use alloc::boxed::Box;
+
+/// Returns the number of possible CPU ids, i.e. one more than the highest possible CPU id.
+pub fn nr_cpu_ids() -> u32 {
+    // SAFETY: `nr_cpu_ids` is set during early boot and never changes afterwards.
+    unsafe { bindings::nr_cpu_ids }
+}
+
+/// Returns the id of the CPU the caller is running on.
+///
+/// Unless preemption is disabled, the caller may be migrated to another CPU at any time, so the
+/// returned value is only a hint.
+pub fn current_cpu() -> u32 {
+    // SAFETY: FFI call with no additional requirements.
+    unsafe { bindings::raw_smp_processor_id() as _ }
+}
+
+/// An owned, heap-allocated set of CPUs.
+///
+/// Wraps a `struct cpumask`. Masks can be large (`NR_CPUS` bits), so they are kept off the stack.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::cpumask::CpuMask;
+/// # use kernel::prelude::*;
+///
+/// fn first_two() -> Result<CpuMask> {
+///     let mut mask = CpuMask::try_new()?;
+///     mask.set(0)?;
+///     mask.set(1)?;
+///     assert_eq!(mask.weight(), 2);
+///     Ok(mask)
+/// }
+/// ```
+pub struct CpuMask(Box<bindings::cpumask>);
+
+impl CpuMask {
+    /// Allocates a new, empty mask.
+    pub fn try_new() -> Result<Self> {
+        // SAFETY: `struct cpumask` is a plain bitmap, for which all zeroes is a valid (empty)
+        // value.
+        Ok(Self(Box::try_new(unsafe { core::mem::zeroed() })?))
+    }
+
+    /// Allocates a new mask containing only `cpu`.
+    pub fn try_of_cpu(cpu: u32) -> Result<Self> {
+        let mut mask = Self::try_new()?;
+        mask.set(cpu)?;
+        Ok(mask)
+    }
+
+    fn check(cpu: u32) -> Result {
+        if cpu < nr_cpu_ids() {
+            Ok(())
+        } else {
+            Err(EINVAL)
+        }
+    }
+
+    /// Adds `cpu` to the mask.
+    ///
+    /// Fails with `EINVAL` if `cpu` isn't a possible CPU id.
+    pub fn set(&mut self, cpu: u32) -> Result {
+        Self::check(cpu)?;
+        // SAFETY: `cpu` was checked above and `self.0` is a valid mask that we own exclusively.
+        unsafe { bindings::__cpumask_set_cpu(cpu as _, &mut *self.0) };
+        Ok(())
+    }
+
+    /// Removes `cpu` from the mask.
+    ///
+    /// Fails with `EINVAL` if `cpu` isn't a possible CPU id.
+    pub fn clear(&mut self, cpu: u32) -> Result {
+        Self::check(cpu)?;
+        // SAFETY: `cpu` was checked above and `self.0` is a valid mask that we own exclusively.
+        unsafe { bindings::__cpumask_clear_cpu(cpu as _, &mut *self.0) };
+        Ok(())
+    }
+
+    /// Returns `true` if `cpu` is in the mask.
+    pub fn test(&self, cpu: u32) -> bool {
+        if Self::check(cpu).is_err() {
+            return false;
+        }
+        // SAFETY: `cpu` was checked above and `self.0` is a valid mask.
+        unsafe { bindings::cpumask_test_cpu(cpu as _, &*self.0) }
+    }
+
+    /// Returns the number of CPUs in the mask.
+    pub fn weight(&self) -> u32 {
+        // SAFETY: `self.0` is a valid mask.
+        unsafe { bindings::cpumask_weight(&*self.0) }
+    }
+
+    /// Returns a raw pointer to the underlying `struct cpumask`.
+    pub fn as_raw(&self) -> *const bindings::cpumask {
+        &*self.0
+    }
+}
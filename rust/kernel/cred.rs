@@ -0,0 +1,247 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Credentials management and capability checks.
+//!
+//! C headers: [`include/linux/cred.h`](../../../../include/linux/cred.h) and
+//! [`include/linux/capability.h`](../../../../include/linux/capability.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/security/credentials.html>
+
+use crate::{
+    bindings,
+    file::File,
+    task::{Kgid, Kuid},
+    types::{AlwaysRefCounted, Opaque},
+};
+
+/// Wraps the kernel's `struct cred`.
+///
+/// Most fields of credentials are immutable. When things have their credentials changed, that
+/// happens by replacing the credential instead of changing an existing credential. See the
+/// [reference] for more information.
+///
+/// # Invariants
+///
+/// Instances of this type are always ref-counted, that is, a call to `get_cred` ensures that the
+/// allocation remains valid at least until the matching call to `put_cred`.
+///
+/// [reference]: https://www.kernel.org/doc/html/latest/security/credentials.html
+#[repr(transparent)]
+pub struct Credential(Opaque<bindings::cred>);
+
+// SAFETY: By design, the only way to access a `Credential` is via an immutable reference or an
+// `ARef`. This means that the only situation in which a `Credential` can be accessed mutably is
+// when the refcount drops to zero and the destructor runs. It is safe for that to happen on any
+// thread, so it is ok for this type to be `Send`.
+unsafe impl Send for Credential {}
+
+// SAFETY: It's OK to access `Credential` through shared references from other threads because
+// we're either accessing properties that don't change or that are properly synchronised by C
+// code.
+unsafe impl Sync for Credential {}
+
+impl Credential {
+    /// Creates a reference to a [`Credential`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `ptr` is valid and remains valid for the lifetime of the
+    /// returned [`Credential`] reference.
+    pub unsafe fn from_ptr<'a>(ptr: *const bindings::cred) -> &'a Credential {
+        // SAFETY: The safety requirements guarantee the validity of the dereference, while the
+        // `Credential` type being transparent makes the cast ok.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct cred` pointer.
+    pub fn as_ptr(&self) -> *const bindings::cred {
+        self.0.get()
+    }
+
+    /// Returns the real UID of these credentials.
+    pub fn uid(&self) -> Kuid {
+        // SAFETY: By the type invariant, we know that `self.0` is valid, and `uid` is immutable.
+        Kuid::from_raw(unsafe { (*self.0.get()).uid })
+    }
+
+    /// Returns the effective UID of these credentials.
+    pub fn euid(&self) -> Kuid {
+        // SAFETY: By the type invariant, we know that `self.0` is valid, and `euid` is immutable.
+        Kuid::from_raw(unsafe { (*self.0.get()).euid })
+    }
+
+    /// Returns the real GID of these credentials.
+    pub fn gid(&self) -> Kgid {
+        // SAFETY: By the type invariant, we know that `self.0` is valid, and `gid` is immutable.
+        Kgid::from_raw(unsafe { (*self.0.get()).gid })
+    }
+
+    /// Returns the effective GID of these credentials.
+    pub fn egid(&self) -> Kgid {
+        // SAFETY: By the type invariant, we know that `self.0` is valid, and `egid` is immutable.
+        Kgid::from_raw(unsafe { (*self.0.get()).egid })
+    }
+
+    /// Returns the user namespace these credentials belong to.
+    pub fn user_ns(&self) -> &UserNamespace {
+        // SAFETY: By the type invariant, we know that `self.0` is valid. Credentials hold a
+        // reference to their user namespace, so it lives at least as long as `self`.
+        unsafe { UserNamespace::from_ptr((*self.0.get()).user_ns) }
+    }
+
+    /// Returns `true` if these credentials have the given capability in `ns`.
+    ///
+    /// Unlike [`capable`], this doesn't audit the check or mark the task as having used
+    /// privileges, since the credentials need not belong to the current task.
+    pub fn has_ns_capability(&self, ns: &UserNamespace, cap: Capability) -> bool {
+        // SAFETY: Both `self` and `ns` are valid by their type invariants.
+        unsafe {
+            bindings::security_capable(
+                self.as_ptr(),
+                ns.as_ptr(),
+                cap as _,
+                bindings::CAP_OPT_NOAUDIT as _,
+            ) == 0
+        }
+    }
+}
+
+// SAFETY: The type invariants guarantee that `Credential` is always ref-counted.
+unsafe impl AlwaysRefCounted for Credential {
+    fn inc_ref(&self) {
+        // SAFETY: The existence of a shared reference means that the refcount is nonzero.
+        unsafe { bindings::get_cred(self.0.get()) };
+    }
+
+    unsafe fn dec_ref(obj: core::ptr::NonNull<Credential>) {
+        // SAFETY: The safety requirements guarantee that the refcount is nonzero. The cast is okay
+        // because `Credential` has the same representation as `struct cred`.
+        unsafe { bindings::put_cred(obj.cast().as_ptr()) };
+    }
+}
+
+/// Wraps the kernel's `struct user_namespace`.
+///
+/// # Invariants
+///
+/// Instances are valid user namespaces that outlive the references to them.
+#[repr(transparent)]
+pub struct UserNamespace(Opaque<bindings::user_namespace>);
+
+// SAFETY: The fields accessed through `UserNamespace` are immutable after creation.
+unsafe impl Sync for UserNamespace {}
+
+impl UserNamespace {
+    /// Creates a reference to a [`UserNamespace`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `ptr` is valid and remains valid for the lifetime of the
+    /// returned [`UserNamespace`] reference.
+    pub unsafe fn from_ptr<'a>(ptr: *const bindings::user_namespace) -> &'a UserNamespace {
+        // SAFETY: The safety requirements guarantee the validity of the dereference, while the
+        // `UserNamespace` type being transparent makes the cast ok.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the initial user namespace.
+    pub fn init() -> &'static UserNamespace {
+        // SAFETY: `init_user_ns` is a static that lives forever.
+        unsafe { Self::from_ptr(core::ptr::addr_of!(bindings::init_user_ns)) }
+    }
+
+    /// Returns the raw `struct user_namespace` pointer.
+    pub fn as_ptr(&self) -> *mut bindings::user_namespace {
+        self.0.get()
+    }
+}
+
+/// A POSIX capability, as defined in `include/uapi/linux/capability.h`.
+///
+/// See `capabilities(7)` for what each of them allows.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum Capability {
+    Chown = bindings::CAP_CHOWN,
+    DacOverride = bindings::CAP_DAC_OVERRIDE,
+    DacReadSearch = bindings::CAP_DAC_READ_SEARCH,
+    Fowner = bindings::CAP_FOWNER,
+    Fsetid = bindings::CAP_FSETID,
+    Kill = bindings::CAP_KILL,
+    Setgid = bindings::CAP_SETGID,
+    Setuid = bindings::CAP_SETUID,
+    Setpcap = bindings::CAP_SETPCAP,
+    LinuxImmutable = bindings::CAP_LINUX_IMMUTABLE,
+    NetBindService = bindings::CAP_NET_BIND_SERVICE,
+    NetBroadcast = bindings::CAP_NET_BROADCAST,
+    NetAdmin = bindings::CAP_NET_ADMIN,
+    NetRaw = bindings::CAP_NET_RAW,
+    IpcLock = bindings::CAP_IPC_LOCK,
+    IpcOwner = bindings::CAP_IPC_OWNER,
+    SysModule = bindings::CAP_SYS_MODULE,
+    SysRawio = bindings::CAP_SYS_RAWIO,
+    SysChroot = bindings::CAP_SYS_CHROOT,
+    SysPtrace = bindings::CAP_SYS_PTRACE,
+    SysPacct = bindings::CAP_SYS_PACCT,
+    SysAdmin = bindings::CAP_SYS_ADMIN,
+    SysBoot = bindings::CAP_SYS_BOOT,
+    SysNice = bindings::CAP_SYS_NICE,
+    SysResource = bindings::CAP_SYS_RESOURCE,
+    SysTime = bindings::CAP_SYS_TIME,
+    SysTtyConfig = bindings::CAP_SYS_TTY_CONFIG,
+    Mknod = bindings::CAP_MKNOD,
+    Lease = bindings::CAP_LEASE,
+    AuditWrite = bindings::CAP_AUDIT_WRITE,
+    AuditControl = bindings::CAP_AUDIT_CONTROL,
+    Setfcap = bindings::CAP_SETFCAP,
+    MacOverride = bindings::CAP_MAC_OVERRIDE,
+    MacAdmin = bindings::CAP_MAC_ADMIN,
+    Syslog = bindings::CAP_SYSLOG,
+    WakeAlarm = bindings::CAP_WAKE_ALARM,
+    BlockSuspend = bindings::CAP_BLOCK_SUSPEND,
+    AuditRead = bindings::CAP_AUDIT_READ,
+    Perfmon = bindings::CAP_PERFMON,
+    Bpf = bindings::CAP_BPF,
+    CheckpointRestore = bindings::CAP_CHECKPOINT_RESTORE,
+}
+
+/// Returns `true` if the current task has the given capability in the initial user namespace.
+///
+/// This is the equivalent of `capable()` in C: the check is audited and, on success, the task is
+/// flagged as having used superuser privileges.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::cred::{capable, Capability};
+/// # use kernel::prelude::*;
+///
+/// fn reset_hardware() -> Result {
+///     if !capable(Capability::SysAdmin) {
+///         return Err(EPERM);
+///     }
+///     // ...
+///     Ok(())
+/// }
+/// ```
+pub fn capable(cap: Capability) -> bool {
+    // SAFETY: FFI call with no additional requirements.
+    unsafe { bindings::capable(cap as _) }
+}
+
+/// Returns `true` if the current task has the given capability in the user namespace `ns`.
+pub fn ns_capable(ns: &UserNamespace, cap: Capability) -> bool {
+    // SAFETY: `ns` is valid by the type invariants.
+    unsafe { bindings::ns_capable(ns.as_ptr(), cap as _) }
+}
+
+/// Returns `true` if the task that opened `file` had the given capability in `ns` when it did so.
+///
+/// This checks the credentials the file was opened with rather than those of the current task,
+/// which is what ioctl handlers should use to prevent privileged operations through file
+/// descriptors passed to less privileged processes.
+pub fn file_ns_capable(file: &File, ns: &UserNamespace, cap: Capability) -> bool {
+    // SAFETY: `file` and `ns` are valid by their type invariants.
+    unsafe { bindings::file_ns_capable(file.as_ptr(), ns.as_ptr(), cap as _) }
+}
@@ -0,0 +1,15 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Cryptography.
+//!
+//! Transforms of the crypto API are allocated by the name of their algorithm, e.g. `sha256` or
+//! `cbc(aes)`, which picks the best implementation that the kernel has, hardware-accelerated or
+//! not. Hashes are computed with [`hash::Shash`], and data is encrypted and decrypted with
+//! [`skcipher::Skcipher`].
+//!
+//! C header: [`include/linux/crypto.h`](../../../../include/linux/crypto.h)
+
+#[cfg(CONFIG_CRYPTO_HASH)]
+pub mod hash;
+#[cfg(CONFIG_CRYPTO_SKCIPHER)]
+pub mod skcipher;
@@ -0,0 +1,175 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Synchronous message digests.
+//!
+//! C header: [`include/crypto/hash.h`](../../../../include/crypto/hash.h)
+
+use crate::{
+    bindings,
+    error::{code::*, from_err_ptr, to_result, Result},
+    str::CStr,
+};
+use alloc::vec::Vec;
+use core::ptr::NonNull;
+
+/// A synchronous message digest transform, the kernel's `struct crypto_shash`.
+///
+/// The transform can be used from several threads at once, but its key can only be set while it
+/// isn't used.
+///
+/// # Invariants
+///
+/// `tfm` is a valid transform.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, crypto::hash::Shash, prelude::*};
+///
+/// fn verify(image: &[u8], chunks: &[&[u8]], expected: &[u8; 32]) -> Result {
+///     let sha256 = Shash::new(c_str!("sha256"))?;
+///     let mut digest = [0u8; 32];
+///
+///     // One-shot.
+///     sha256.digest(image, &mut digest)?;
+///
+///     // Streaming.
+///     let mut hasher = sha256.hasher()?;
+///     for chunk in chunks {
+///         hasher.update(chunk)?;
+///     }
+///     hasher.finalize(&mut digest)?;
+///
+///     if digest != *expected {
+///         return Err(EBADMSG);
+///     }
+///     Ok(())
+/// }
+/// ```
+pub struct Shash {
+    tfm: NonNull<bindings::crypto_shash>,
+}
+
+impl Shash {
+    /// Allocates a transform of the algorithm named `name`, e.g. `sha256` or `hmac(sha256)`.
+    ///
+    /// Fails with `ENOENT` if the kernel has no such algorithm.
+    pub fn new(name: &CStr) -> Result<Self> {
+        crate::might_sleep!();
+        // SAFETY: `name` is only used during the call.
+        let tfm = from_err_ptr(unsafe { bindings::crypto_alloc_shash(name.as_char_ptr(), 0, 0) })?;
+        // INVARIANT: The transform was allocated above.
+        Ok(Self {
+            // SAFETY: `crypto_alloc_shash` never returns null on success.
+            tfm: unsafe { NonNull::new_unchecked(tfm) },
+        })
+    }
+
+    /// Returns the size of the digests, in bytes.
+    pub fn digest_size(&self) -> usize {
+        // SAFETY: The transform is valid by the type invariants.
+        unsafe { bindings::crypto_shash_digestsize(self.tfm.as_ptr()) as _ }
+    }
+
+    /// Sets the key of keyed algorithms, e.g. HMACs.
+    pub fn set_key(&mut self, key: &[u8]) -> Result {
+        // SAFETY: The transform is valid by the type invariants, and isn't used concurrently as
+        // `self` is borrowed mutably. The key is copied.
+        to_result(unsafe {
+            bindings::crypto_shash_setkey(self.tfm.as_ptr(), key.as_ptr(), key.len() as _)
+        })
+    }
+
+    /// Computes the digest of `data` into `out`.
+    ///
+    /// Fails with `EINVAL` if `out` is smaller than [`Shash::digest_size`].
+    pub fn digest(&self, data: &[u8], out: &mut [u8]) -> Result {
+        if out.len() < self.digest_size() {
+            return Err(EINVAL);
+        }
+        // SAFETY: The transform is valid by the type invariants, and `out` is large enough.
+        to_result(unsafe {
+            bindings::crypto_shash_tfm_digest(
+                self.tfm.as_ptr(),
+                data.as_ptr(),
+                data.len() as _,
+                out.as_mut_ptr(),
+            )
+        })
+    }
+
+    /// Starts computing a digest of data that is given in several parts.
+    pub fn hasher(&self) -> Result<Hasher<'_>> {
+        // SAFETY: The transform is valid by the type invariants.
+        let size = core::mem::size_of::<bindings::shash_desc>()
+            + unsafe { bindings::crypto_shash_descsize(self.tfm.as_ptr()) } as usize;
+        // The kernel allocator aligns the context of the algorithm as `CRYPTO_MINALIGN` requires.
+        let mut desc = Vec::new();
+        desc.try_resize(size, 0)?;
+        let mut hasher = Hasher { shash: self, desc };
+        // SAFETY: The descriptor is large enough for the transform, and zeroed.
+        unsafe { (*hasher.as_raw()).tfm = self.tfm.as_ptr() };
+        // SAFETY: The descriptor was set up above.
+        to_result(unsafe { bindings::crypto_shash_init(hasher.as_raw()) })?;
+        Ok(hasher)
+    }
+}
+
+impl Drop for Shash {
+    fn drop(&mut self) {
+        // SAFETY: The transform is valid by the type invariants, and the hashers borrow it, so
+        // none is left.
+        unsafe { bindings::crypto_free_shash(self.tfm.as_ptr()) };
+    }
+}
+
+// SAFETY: The transform can be freed from any thread.
+unsafe impl Send for Shash {}
+
+// SAFETY: The methods that take `&self` only read the transform, and keep their state in their
+// own descriptors.
+unsafe impl Sync for Shash {}
+
+/// The computation of a digest, returned by [`Shash::hasher`].
+///
+/// # Invariants
+///
+/// `desc` is a `struct shash_desc` of `shash`, followed by its context, which was initialised.
+pub struct Hasher<'a> {
+    shash: &'a Shash,
+    desc: Vec<u8>,
+}
+
+impl Hasher<'_> {
+    fn as_raw(&mut self) -> *mut bindings::shash_desc {
+        self.desc.as_mut_ptr().cast()
+    }
+
+    /// Adds `data` to the digest.
+    pub fn update(&mut self, data: &[u8]) -> Result {
+        // SAFETY: The descriptor was initialised by the type invariants.
+        to_result(unsafe {
+            bindings::crypto_shash_update(self.as_raw(), data.as_ptr(), data.len() as _)
+        })
+    }
+
+    /// Finishes the digest, and writes it into `out`.
+    ///
+    /// Fails with `EINVAL` if `out` is smaller than [`Shash::digest_size`].
+    pub fn finalize(mut self, out: &mut [u8]) -> Result {
+        if out.len() < self.shash.digest_size() {
+            return Err(EINVAL);
+        }
+        // SAFETY: The descriptor was initialised by the type invariants, and `out` is large
+        // enough.
+        to_result(unsafe { bindings::crypto_shash_final(self.as_raw(), out.as_mut_ptr()) })
+    }
+}
+
+impl Drop for Hasher<'_> {
+    fn drop(&mut self) {
+        // The context may hold the key or partial digests, which must not leak.
+        // SAFETY: The descriptor is valid for writes of its length.
+        unsafe { bindings::memzero_explicit(self.desc.as_mut_ptr().cast(), self.desc.len()) };
+    }
+}
@@ -0,0 +1,159 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Symmetric key ciphers.
+//!
+//! C header: [`include/crypto/skcipher.h`](../../../../include/crypto/skcipher.h)
+
+use crate::{
+    bindings,
+    error::{code::*, from_err_ptr, to_result, Result},
+    str::CStr,
+};
+use alloc::vec::Vec;
+use core::{
+    ffi::c_int,
+    mem::MaybeUninit,
+    ptr::{self, NonNull},
+};
+
+/// A symmetric key cipher transform, the kernel's `struct crypto_skcipher`.
+///
+/// Only synchronous implementations are used, so that encryption and decryption complete before
+/// they return. The transform can be used from several threads at once, but its key can only be
+/// set while it isn't used.
+///
+/// # Invariants
+///
+/// `tfm` is a valid, synchronous transform.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, crypto::skcipher::Skcipher, prelude::*};
+///
+/// fn seal(key: &[u8; 32], iv: &[u8; 16], data: &mut Vec<u8>) -> Result {
+///     let mut aes = Skcipher::new(c_str!("cbc(aes)"))?;
+///     aes.set_key(key)?;
+///     // The IV is updated by the cipher, so a copy is used.
+///     let mut iv = *iv;
+///     aes.encrypt(&mut iv, data)
+/// }
+/// ```
+pub struct Skcipher {
+    tfm: NonNull<bindings::crypto_skcipher>,
+}
+
+impl Skcipher {
+    /// Allocates a transform of the algorithm named `name`, e.g. `cbc(aes)` or `xts(aes)`.
+    ///
+    /// Fails with `ENOENT` if the kernel has no synchronous implementation of the algorithm.
+    pub fn new(name: &CStr) -> Result<Self> {
+        crate::might_sleep!();
+        // SAFETY: `name` is only used during the call.
+        let tfm = from_err_ptr(unsafe {
+            bindings::crypto_alloc_skcipher(name.as_char_ptr(), 0, bindings::CRYPTO_ALG_ASYNC)
+        })?;
+        // INVARIANT: The transform was allocated above, with the mask excluding asynchronous
+        // implementations.
+        Ok(Self {
+            // SAFETY: `crypto_alloc_skcipher` never returns null on success.
+            tfm: unsafe { NonNull::new_unchecked(tfm) },
+        })
+    }
+
+    /// Returns the size of the IVs, in bytes.
+    pub fn iv_size(&self) -> usize {
+        // SAFETY: The transform is valid by the type invariants.
+        unsafe { bindings::crypto_skcipher_ivsize(self.tfm.as_ptr()) as _ }
+    }
+
+    /// Returns the size of the blocks, in bytes, which the length of the data must be a multiple
+    /// of for block cipher modes.
+    pub fn block_size(&self) -> usize {
+        // SAFETY: The transform is valid by the type invariants.
+        unsafe { bindings::crypto_skcipher_blocksize(self.tfm.as_ptr()) as _ }
+    }
+
+    /// Sets the key.
+    pub fn set_key(&mut self, key: &[u8]) -> Result {
+        // SAFETY: The transform is valid by the type invariants, and isn't used concurrently as
+        // `self` is borrowed mutably. The key is copied.
+        to_result(unsafe {
+            bindings::crypto_skcipher_setkey(self.tfm.as_ptr(), key.as_ptr(), key.len() as _)
+        })
+    }
+
+    /// Encrypts `data` in place, with the IV `iv`, which is updated to chain further calls.
+    ///
+    /// Fails with `EINVAL` if `iv` isn't [`Skcipher::iv_size`] bytes long, or if the length of
+    /// `data` doesn't suit the algorithm.
+    pub fn encrypt(&self, iv: &mut [u8], data: &mut Vec<u8>) -> Result {
+        self.crypt(iv, data, bindings::crypto_skcipher_encrypt)
+    }
+
+    /// Decrypts `data` in place, with the IV `iv`, which is updated to chain further calls.
+    ///
+    /// Fails with `EINVAL` if `iv` isn't [`Skcipher::iv_size`] bytes long, or if the length of
+    /// `data` doesn't suit the algorithm.
+    pub fn decrypt(&self, iv: &mut [u8], data: &mut Vec<u8>) -> Result {
+        self.crypt(iv, data, bindings::crypto_skcipher_decrypt)
+    }
+
+    /// Runs `op` on `data`.
+    ///
+    /// The data is in a `Vec`, because scatterlists need it to be in the linear mapping, which
+    /// the kernel allocator guarantees but e.g. stacks don't.
+    fn crypt(
+        &self,
+        iv: &mut [u8],
+        data: &mut Vec<u8>,
+        op: unsafe extern "C" fn(*mut bindings::skcipher_request) -> c_int,
+    ) -> Result {
+        crate::might_sleep!();
+        if iv.len() != self.iv_size() {
+            return Err(EINVAL);
+        }
+        let len = u32::try_from(data.len()).map_err(|_| EINVAL)?;
+
+        // SAFETY: The transform is valid by the type invariants.
+        let req =
+            unsafe { bindings::skcipher_request_alloc(self.tfm.as_ptr(), bindings::GFP_KERNEL) };
+        if req.is_null() {
+            return Err(ENOMEM);
+        }
+        // SAFETY: All-zeroes is a valid scatterlist, which is initialised below.
+        let mut sg: bindings::scatterlist = unsafe { MaybeUninit::zeroed().assume_init() };
+        // SAFETY: The request was allocated above. The data is in the linear mapping, and both it
+        // and the IV, which is as large as the transform expects, outlive the request, which
+        // completes synchronously by the type invariants.
+        let ret = unsafe {
+            bindings::sg_init_one(&mut sg, data.as_mut_ptr().cast(), len);
+            bindings::skcipher_request_set_callback(req, 0, None, ptr::null_mut());
+            bindings::skcipher_request_set_crypt(
+                req,
+                &mut sg,
+                &mut sg,
+                len,
+                iv.as_mut_ptr().cast(),
+            );
+            op(req)
+        };
+        // SAFETY: The request completed above, and isn't used anymore.
+        unsafe { bindings::skcipher_request_free(req) };
+        to_result(ret)
+    }
+}
+
+impl Drop for Skcipher {
+    fn drop(&mut self) {
+        // SAFETY: The transform is valid by the type invariants.
+        unsafe { bindings::crypto_free_skcipher(self.tfm.as_ptr()) };
+    }
+}
+
+// SAFETY: The transform can be freed from any thread.
+unsafe impl Send for Skcipher {}
+
+// SAFETY: The methods that take `&self` only read the transform, and keep their state in their
+// own requests.
+unsafe impl Sync for Skcipher {}
@@ -0,0 +1,306 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Debug filesystem.
+//!
+//! debugfs is a simple filesystem for exposing debugging knobs and state. It has no stable ABI
+//! and files may come and go at any time, so drivers shouldn't treat failures to create entries
+//! as fatal.
+//!
+//! C header: [`include/linux/debugfs.h`](../../../../include/linux/debugfs.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/filesystems/debugfs.html>
+
+use crate::{
+    bindings,
+    error::{code::*, from_err_ptr, Result},
+    file::{self, OpenAdapter, OperationsVtable},
+    seq_file::{self, SeqFileVtable},
+    str::CStr,
+    sync::Arc,
+    ThisModule,
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    any::Any,
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU64},
+};
+
+/// A debugfs directory tree owned by a driver.
+///
+/// All the directories and files created through the registration live inside a single
+/// top-level directory, which is removed recursively (together with everything in it) when the
+/// registration is dropped. The values backing the files are kept alive by the registration until
+/// then, and the module that owns the registration can't be unloaded while the files implemented
+/// in Rust are open.
+///
+/// # Invariants
+///
+/// `root` is a valid debugfs directory that is owned by the registration.
+///
+/// # Examples
+///
+/// ```
+/// use core::sync::atomic::AtomicU32;
+/// use kernel::{c_str, debugfs, sync::Arc, ThisModule};
+/// # use kernel::prelude::*;
+///
+/// fn add_debugfs(
+///     module: &'static ThisModule,
+///     counter: &Arc<AtomicU32>,
+/// ) -> Result<debugfs::Registration> {
+///     let mut reg = debugfs::Registration::try_new(c_str!("my_driver"), module)?;
+///     let regs = reg.create_dir(reg.root(), c_str!("regs"))?;
+///     reg.create_u32(reg.root(), c_str!("irq_count"), 0o444, counter.clone())?;
+///     reg.create_bool(regs, c_str!("trace"), 0o644, Arc::try_new(false.into())?)?;
+///     Ok(reg)
+/// }
+/// ```
+pub struct Registration {
+    root: *mut bindings::dentry,
+    module: &'static ThisModule,
+    resources: Vec<Box<dyn Any + Send + Sync>>,
+}
+
+// SAFETY: debugfs entries can be created and removed from any thread, and the resources are
+// `Send` themselves.
+unsafe impl Send for Registration {}
+
+// SAFETY: `Registration` has no methods that take `&self` other than accessors of the root
+// directory.
+unsafe impl Sync for Registration {}
+
+/// A directory in the tree of a [`Registration`].
+///
+/// This is only a handle: the directory is owned by the registration it was created from, and
+/// it can only be used to create entries with that same registration.
+#[derive(Clone, Copy)]
+pub struct Dir {
+    root: *mut bindings::dentry,
+    dentry: *mut bindings::dentry,
+}
+
+impl Registration {
+    /// Creates a new directory named `name` at the top of debugfs, on behalf of `module`.
+    pub fn try_new(name: &CStr, module: &'static ThisModule) -> Result<Self> {
+        // SAFETY: `name` is a valid C string, and a null parent means the debugfs root.
+        let root = from_err_ptr(unsafe {
+            bindings::debugfs_create_dir(name.as_char_ptr(), core::ptr::null_mut())
+        })?;
+        // INVARIANT: The directory was just created and is owned by us.
+        Ok(Self {
+            root,
+            module,
+            resources: Vec::new(),
+        })
+    }
+
+    /// Returns the top-level directory of the registration.
+    pub fn root(&self) -> Dir {
+        Dir {
+            root: self.root,
+            dentry: self.root,
+        }
+    }
+
+    /// Checks that `dir` belongs to this registration and returns its dentry.
+    fn parent(&self, dir: Dir) -> Result<*mut bindings::dentry> {
+        if dir.root != self.root {
+            return Err(EINVAL);
+        }
+        Ok(dir.dentry)
+    }
+
+    /// Keeps `res` alive until the registration is dropped.
+    fn keep<T: Any + Send + Sync>(&mut self, res: Box<T>) -> Result {
+        self.resources.try_push(res)?;
+        Ok(())
+    }
+
+    /// Creates a subdirectory named `name` in `parent`.
+    pub fn create_dir(&mut self, parent: Dir, name: &CStr) -> Result<Dir> {
+        let parent = self.parent(parent)?;
+        // SAFETY: `name` is a valid C string and `parent` is a directory of our tree.
+        let dentry =
+            from_err_ptr(unsafe { bindings::debugfs_create_dir(name.as_char_ptr(), parent) })?;
+        Ok(Dir {
+            root: self.root,
+            dentry,
+        })
+    }
+
+    /// Creates a file that reads and writes `value` as a decimal `u32`.
+    pub fn create_u32(
+        &mut self,
+        parent: Dir,
+        name: &CStr,
+        mode: u16,
+        value: Arc<AtomicU32>,
+    ) -> Result {
+        let parent = self.parent(parent)?;
+        let ptr = &*value as *const AtomicU32 as *mut u32;
+        let value = Box::try_new(value)?;
+        self.keep(value)?;
+        // SAFETY: `name` is a valid C string and `parent` is a directory of our tree. `ptr` is
+        // kept alive until the file is removed, and `AtomicU32` has the same layout as `u32`.
+        unsafe { bindings::debugfs_create_u32(name.as_char_ptr(), mode, parent, ptr) };
+        Ok(())
+    }
+
+    /// Creates a file that reads and writes `value` as a decimal `u64`.
+    pub fn create_u64(
+        &mut self,
+        parent: Dir,
+        name: &CStr,
+        mode: u16,
+        value: Arc<AtomicU64>,
+    ) -> Result {
+        let parent = self.parent(parent)?;
+        let ptr = &*value as *const AtomicU64 as *mut u64;
+        let value = Box::try_new(value)?;
+        self.keep(value)?;
+        // SAFETY: `name` is a valid C string and `parent` is a directory of our tree. `ptr` is
+        // kept alive until the file is removed, and `AtomicU64` has the same layout as `u64`.
+        unsafe { bindings::debugfs_create_u64(name.as_char_ptr(), mode, parent, ptr) };
+        Ok(())
+    }
+
+    /// Creates a file that reads `value` as `Y` or `N`, and accepts the usual boolean spellings
+    /// on writes.
+    pub fn create_bool(
+        &mut self,
+        parent: Dir,
+        name: &CStr,
+        mode: u16,
+        value: Arc<AtomicBool>,
+    ) -> Result {
+        let parent = self.parent(parent)?;
+        let ptr = &*value as *const AtomicBool as *mut bool;
+        let value = Box::try_new(value)?;
+        self.keep(value)?;
+        // SAFETY: `name` is a valid C string and `parent` is a directory of our tree. `ptr` is
+        // kept alive until the file is removed, and `AtomicBool` has the same layout as `bool`.
+        unsafe { bindings::debugfs_create_bool(name.as_char_ptr(), mode, parent, ptr) };
+        Ok(())
+    }
+
+    /// Creates a read-only file exposing the contents of `data`.
+    pub fn create_blob(&mut self, parent: Dir, name: &CStr, mode: u16, data: Vec<u8>) -> Result {
+        let parent = self.parent(parent)?;
+        let mut blob = Box::try_new(Blob {
+            wrapper: bindings::debugfs_blob_wrapper {
+                data: core::ptr::null_mut(),
+                size: data.len() as _,
+            },
+            data,
+        })?;
+        blob.wrapper.data = blob.data.as_mut_ptr().cast();
+        let ptr = &mut blob.wrapper as *mut _;
+        self.keep(blob)?;
+        // SAFETY: `name` is a valid C string and `parent` is a directory of our tree. The blob is
+        // boxed and kept alive until the file is removed, so `ptr` remains valid.
+        from_err_ptr(unsafe {
+            bindings::debugfs_create_blob(name.as_char_ptr(), mode, parent, ptr)
+        })?;
+        Ok(())
+    }
+
+    /// Creates a file implemented by `T`.
+    ///
+    /// `data` is passed to [`file::Operations::open`] whenever the file is opened.
+    pub fn create_file<T: file::Operations>(
+        &mut self,
+        parent: Dir,
+        name: &CStr,
+        mode: u16,
+        data: T::OpenData,
+    ) -> Result
+    where
+        T::OpenData: Send + 'static,
+    {
+        let parent = self.parent(parent)?;
+        let data = Box::try_new(data)?;
+        let ptr = &*data as *const T::OpenData as *mut core::ffi::c_void;
+        self.keep(data)?;
+        // SAFETY: `DebugfsAdapter` extracts the open data from the inode in which
+        // `debugfs_create_file` stores it.
+        let fops = Box::try_new(FileOps(unsafe {
+            OperationsVtable::<DebugfsAdapter, T>::build_owned(self.module)
+        }))?;
+        let fops_ptr = &fops.0 as *const bindings::file_operations;
+        self.keep(fops)?;
+        // SAFETY: `name` is a valid C string and `parent` is a directory of our tree. The open data
+        // and the file operations are kept alive until the file is removed.
+        from_err_ptr(unsafe {
+            bindings::debugfs_create_file(name.as_char_ptr(), mode, parent, ptr, fops_ptr)
+        })?;
+        Ok(())
+    }
+
+    /// Creates a file listing the records produced by `data`.
+    pub fn create_seq_file<T: seq_file::Operations>(
+        &mut self,
+        parent: Dir,
+        name: &CStr,
+        mode: u16,
+        data: T,
+    ) -> Result {
+        let parent = self.parent(parent)?;
+        let data = Box::try_new(data)?;
+        let ptr = &*data as *const T as *mut core::ffi::c_void;
+        self.keep(data)?;
+        // SAFETY: `DebugfsAdapter` extracts `data` from the inode in which `debugfs_create_file`
+        // stores it.
+        let fops = Box::try_new(FileOps(unsafe {
+            SeqFileVtable::<DebugfsAdapter, T>::build_owned(self.module)
+        }))?;
+        let fops_ptr = &fops.0 as *const bindings::file_operations;
+        self.keep(fops)?;
+        // SAFETY: `name` is a valid C string and `parent` is a directory of our tree. `data` and
+        // the file operations are kept alive until the file is removed.
+        from_err_ptr(unsafe {
+            bindings::debugfs_create_file(name.as_char_ptr(), mode, parent, ptr, fops_ptr)
+        })?;
+        Ok(())
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, `root` is a valid directory owned by us. Removal is
+        // recursive and waits for in-flight file operations to finish, so the resources can be
+        // freed afterwards.
+        unsafe { bindings::debugfs_remove(self.root) };
+    }
+}
+
+/// The backing storage of a blob file.
+struct Blob {
+    wrapper: bindings::debugfs_blob_wrapper,
+    data: Vec<u8>,
+}
+
+// SAFETY: The raw pointer in `wrapper` points to `data`, which is `Send`.
+unsafe impl Send for Blob {}
+
+// SAFETY: The blob is never modified after the file is created.
+unsafe impl Sync for Blob {}
+
+/// The file operations of a file implemented in Rust, owned by the module of the registration.
+struct FileOps(bindings::file_operations);
+
+// SAFETY: The file operations only point to functions and to the module, and are never modified
+// after the file is created.
+unsafe impl Send for FileOps {}
+
+// SAFETY: As above.
+unsafe impl Sync for FileOps {}
+
+struct DebugfsAdapter;
+
+impl<T: Sync> OpenAdapter<T> for DebugfsAdapter {
+    unsafe fn convert(inode: *mut bindings::inode, _file: *mut bindings::file) -> *const T {
+        // SAFETY: The caller guarantees that the inode belongs to a file created by
+        // `Registration::create_file`, which stored a pointer to the open data in `i_private`.
+        unsafe { (*inode).i_private as *const T }
+    }
+}
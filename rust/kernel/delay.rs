@@ -0,0 +1,72 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Delays and sleeps.
+//!
+//! C header: [`include/linux/delay.h`](../../../../include/linux/delay.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/timers/timers-howto.html>
+
+use crate::bindings;
+use core::time::Duration;
+
+/// Sleeps for at least `ms` milliseconds.
+///
+/// The sleep is uninterruptible. Since it is based on jiffies, short sleeps (below ~20ms) may
+/// take considerably longer than requested; use [`usleep_range`] for those.
+///
+/// Must not be called from atomic context.
+pub fn msleep(ms: u32) {
+    crate::might_sleep!();
+    // SAFETY: FFI call with no additional requirements.
+    unsafe { bindings::msleep(ms) };
+}
+
+/// Sleeps for at least `ms` milliseconds, or until a signal is received.
+///
+/// Returns the remaining time in milliseconds if the sleep was interrupted, and zero otherwise.
+///
+/// Must not be called from atomic context.
+pub fn msleep_interruptible(ms: u32) -> u32 {
+    crate::might_sleep!();
+    // SAFETY: FFI call with no additional requirements.
+    unsafe { bindings::msleep_interruptible(ms) as _ }
+}
+
+/// Sleeps for somewhere between `min_us` and `max_us` microseconds.
+///
+/// The range gives the timer subsystem room to coalesce wakeups. Must not be called from atomic
+/// context.
+pub fn usleep_range(min_us: u64, max_us: u64) {
+    crate::might_sleep!();
+    // SAFETY: FFI call with no additional requirements.
+    unsafe {
+        bindings::usleep_range_state(min_us as _, max_us as _, bindings::TASK_UNINTERRUPTIBLE)
+    };
+}
+
+/// Sleeps for at least `delay`, picking the most appropriate mechanism for its length.
+///
+/// This is the equivalent of `fsleep` in C. Must not be called from atomic context.
+pub fn sleep(delay: Duration) {
+    crate::might_sleep!();
+    // SAFETY: FFI call with no additional requirements.
+    unsafe { bindings::fsleep(delay.as_micros().min(u64::MAX as u128) as _) };
+}
+
+/// Busy-waits for `us` microseconds.
+///
+/// This doesn't sleep, so it may be used from atomic context, but it wastes CPU time: only use it
+/// for very short delays.
+pub fn udelay(us: u32) {
+    // SAFETY: FFI call with no additional requirements.
+    unsafe { bindings::udelay(us as _) };
+}
+
+/// Busy-waits for `ms` milliseconds.
+///
+/// This doesn't sleep, so it may be used from atomic context, but it wastes CPU time. Prefer
+/// [`msleep`] wherever sleeping is allowed.
+pub fn mdelay(ms: u32) {
+    // SAFETY: FFI call with no additional requirements.
+    unsafe { bindings::mdelay(ms as _) };
+}
@@ -0,0 +1,373 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Device frequency scaling.
+//!
+//! A driver of a device whose clock can be scaled, e.g. a memory controller, registers a
+//! [`Registration`], and a devfreq governor then picks the frequency of the device, e.g. from
+//! its load. The frequencies are those of the OPP table of the device, see [`OppTable`].
+//!
+//! C header: [`include/linux/devfreq.h`](../../../../include/linux/devfreq.h)
+
+use crate::{
+    bindings, c_str, device,
+    error::{code::*, from_err_ptr, from_result, to_result, Result},
+    str::CStr,
+    types::ARef,
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::{c_int, c_ulong, c_void},
+    marker::PhantomPinned,
+    mem::MaybeUninit,
+    pin::Pin,
+    ptr,
+};
+use macros::vtable;
+
+/// Flags of frequency requests, the kernel's `DEVFREQ_FLAG_*` values.
+pub mod flags {
+    /// The lowest frequency at or above the requested one is wanted, instead of the highest one
+    /// at or below it.
+    pub const LEAST_UPPER_BOUND: u32 = crate::bindings::DEVFREQ_FLAG_LEAST_UPPER_BOUND;
+}
+
+/// The names of the governors that are built into the kernel.
+pub mod governor {
+    use crate::{c_str, str::CStr};
+
+    /// Scales the frequency with the load of the device.
+    pub const SIMPLE_ONDEMAND: &CStr = c_str!("simple_ondemand");
+    /// Keeps the highest frequency.
+    pub const PERFORMANCE: &CStr = c_str!("performance");
+    /// Keeps the lowest frequency.
+    pub const POWERSAVE: &CStr = c_str!("powersave");
+    /// Lets user space pick the frequency.
+    pub const USERSPACE: &CStr = c_str!("userspace");
+}
+
+/// The load of a device, the kernel's `struct devfreq_dev_status`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Status {
+    /// The time that the load was measured over.
+    pub total_time: u64,
+    /// The time that the device was busy during `total_time`, in the same unit.
+    pub busy_time: u64,
+    /// The current frequency of the device, in hertz.
+    pub current_frequency: u64,
+}
+
+/// The initial parameters of a device, part of the kernel's `struct devfreq_dev_profile`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Profile {
+    /// The initial frequency of the device, in hertz.
+    pub initial_freq: u64,
+    /// How often the governor checks the load of the device, in milliseconds, or zero to never.
+    pub polling_ms: u32,
+    /// Whether the load is checked with a deferrable timer, which doesn't wake idle CPUs up,
+    /// instead of a delayed one.
+    pub deferrable: bool,
+}
+
+/// The operations of a device, the kernel's `struct devfreq_dev_profile` callbacks.
+///
+/// The driver data of the device implements this trait. Frequencies are in hertz.
+#[vtable]
+pub trait Operations: Send + Sync + Sized + 'static {
+    /// Sets the frequency of the device to the OPP recommended for `freq` and `flags`, see
+    /// [`recommended_opp`], and returns the frequency that was set.
+    fn target(data: &Self, freq: u64, flags: u32) -> Result<u64>;
+
+    /// Returns the load of the device since the last call, which load-based governors need.
+    fn get_dev_status(data: &Self) -> Result<Status>;
+
+    /// Returns the current frequency of the device.
+    fn get_cur_freq(_data: &Self) -> Result<u64> {
+        Err(EINVAL)
+    }
+}
+
+/// Returns the frequency of the OPP of `dev` that is recommended for the frequency `freq` and
+/// the flags `flags`, see [`flags`].
+///
+/// This is the highest frequency at or below `freq`, or the lowest one at or above it with
+/// [`flags::LEAST_UPPER_BOUND`].
+pub fn recommended_opp(dev: &device::Device, freq: u64, flags: u32) -> Result<u64> {
+    let mut freq = freq as c_ulong;
+    // SAFETY: `dev` is valid, and `freq` is valid for writes.
+    let opp =
+        from_err_ptr(unsafe { bindings::devfreq_recommended_opp(dev.as_raw(), &mut freq, flags) })?;
+    // SAFETY: The OPP was returned with a reference above.
+    unsafe { bindings::dev_pm_opp_put(opp) };
+    Ok(freq as _)
+}
+
+/// The OPP table of a device, added from its devicetree node.
+///
+/// The table is removed when this is dropped.
+///
+/// # Invariants
+///
+/// The OPP table of `dev` was added by [`OppTable::add_of`].
+pub struct OppTable {
+    dev: ARef<device::Device>,
+}
+
+impl OppTable {
+    /// Adds the OPP table of `dev` described by its `operating-points-v2` property.
+    pub fn add_of(dev: &device::Device) -> Result<Self> {
+        // SAFETY: `dev` is valid.
+        to_result(unsafe { bindings::dev_pm_opp_of_add_table(dev.as_raw()) })?;
+        // INVARIANT: The table was added above.
+        Ok(Self { dev: dev.into() })
+    }
+}
+
+impl Drop for OppTable {
+    fn drop(&mut self) {
+        // SAFETY: The table was added by the type invariants.
+        unsafe { bindings::dev_pm_opp_of_remove_table(self.dev.as_raw()) };
+    }
+}
+
+/// A device registered for frequency scaling.
+///
+/// The device is unregistered when this is dropped.
+///
+/// The devfreq core calls the operations with the device, which the registration is looked up
+/// from.
+///
+/// # Invariants
+///
+/// `devfreq` is null until the device is registered by [`Registration::register`], and then a
+/// devfreq device whose profile is `profile`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{device::Device, devfreq::{self, governor, OppTable, Profile, Status}, prelude::*};
+///
+/// struct Emc {
+///     _opps: OppTable,
+/// }
+///
+/// #[vtable]
+/// impl devfreq::Operations for Emc {
+///     fn target(_data: &Self, freq: u64, _flags: u32) -> Result<u64> {
+///         Ok(freq)
+///     }
+///
+///     fn get_dev_status(_data: &Self) -> Result<Status> {
+///         Ok(Status::default())
+///     }
+/// }
+///
+/// fn probe(dev: &Device) -> Result<Pin<Box<devfreq::Registration<Emc>>>> {
+///     let emc = Emc { _opps: OppTable::add_of(dev)? };
+///     let profile = Profile {
+///         initial_freq: 204_000_000,
+///         polling_ms: 100,
+///         deferrable: false,
+///     };
+///     devfreq::Registration::register(dev, &profile, governor::SIMPLE_ONDEMAND, emc)
+/// }
+/// ```
+#[repr(C)]
+pub struct Registration<T: Operations> {
+    // Must be the first field, see `with_data`.
+    profile: bindings::devfreq_dev_profile,
+    devfreq: *mut bindings::devfreq,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: Operations> Registration<T> {
+    /// Registers the device `dev` for frequency scaling with the initial parameters `profile`,
+    /// the governor named `governor`, see [`governor`], and the driver data `data`.
+    ///
+    /// The frequencies of the device are those of its OPP table, which must be added first.
+    pub fn register(
+        dev: &device::Device,
+        profile: &Profile,
+        governor: &CStr,
+        data: T,
+    ) -> Result<Pin<Box<Self>>> {
+        crate::might_sleep!();
+        // SAFETY: All other fields are optional, for which zero is valid. The frequency table is
+        // filled in from the OPP table by the devfreq core.
+        let mut raw: bindings::devfreq_dev_profile = unsafe { MaybeUninit::zeroed().assume_init() };
+        raw.initial_freq = profile.initial_freq as _;
+        raw.polling_ms = profile.polling_ms;
+        raw.timer = if profile.deferrable {
+            bindings::DEVFREQ_TIMER_DEFERRABLE
+        } else {
+            bindings::DEVFREQ_TIMER_DELAYED
+        } as _;
+        raw.target = Some(Self::target_callback);
+        raw.get_dev_status = Some(Self::get_dev_status_callback);
+        if T::HAS_GET_CUR_FREQ {
+            raw.get_cur_freq = Some(Self::get_cur_freq_callback);
+        }
+
+        let mut reg = Pin::from(Box::try_new(Self {
+            profile: raw,
+            devfreq: ptr::null_mut(),
+            data,
+            _pin: PhantomPinned,
+        })?);
+        // SAFETY: `devfreq` isn't structurally pinned, and the profile isn't moved.
+        let this = unsafe { reg.as_mut().get_unchecked_mut() };
+        // SAFETY: `dev` is valid, and the governor name is copied. The profile is pinned, and
+        // the device is unregistered before it is freed.
+        let devfreq = from_err_ptr(unsafe {
+            bindings::devfreq_add_device(
+                dev.as_raw(),
+                &mut this.profile,
+                governor.as_char_ptr(),
+                ptr::null_mut(),
+            )
+        })?;
+        // INVARIANT: The device was registered above, with `profile`.
+        this.devfreq = devfreq;
+        Ok(reg)
+    }
+
+    /// Returns the driver data of the device.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    /// Makes the governor check the load of the device and pick its frequency, e.g. when the
+    /// device got busy.
+    pub fn update(&self) -> Result {
+        crate::might_sleep!();
+        // SAFETY: The device is registered by the type invariants, and its lock is held while
+        // it is updated.
+        unsafe {
+            bindings::mutex_lock(&mut (*self.devfreq).lock);
+            let ret = bindings::update_devfreq(self.devfreq);
+            bindings::mutex_unlock(&mut (*self.devfreq).lock);
+            to_result(ret)
+        }
+    }
+
+    /// Stops the governor, e.g. when the device is suspended.
+    pub fn suspend(&self) -> Result {
+        // SAFETY: The device is registered by the type invariants.
+        to_result(unsafe { bindings::devfreq_suspend_device(self.devfreq) })
+    }
+
+    /// Restarts the governor after [`Registration::suspend`].
+    pub fn resume(&self) -> Result {
+        // SAFETY: The device is registered by the type invariants.
+        to_result(unsafe { bindings::devfreq_resume_device(self.devfreq) })
+    }
+
+    unsafe extern "C" fn match_callback(dev: *mut bindings::device, _data: *mut c_void) -> c_int {
+        // SAFETY: The driver core calls this with a valid child device. Devices of the
+        // `devfreq` class are embedded in a devfreq device, whose profile is valid.
+        unsafe {
+            let class = (*dev).class;
+            if class.is_null() || CStr::from_char_ptr((*class).name) != c_str!("devfreq") {
+                return 0;
+            }
+            let profile = (*Self::devfreq_of(dev)).profile;
+            ((*profile).target == Some(Self::target_callback)) as _
+        }
+    }
+
+    /// Returns the devfreq device that `dev` is embedded in.
+    fn devfreq_of(dev: *mut bindings::device) -> *mut bindings::devfreq {
+        let devfreq = MaybeUninit::<bindings::devfreq>::uninit();
+        let base = devfreq.as_ptr();
+        // SAFETY: The field is only projected, not read.
+        let offset = unsafe { ptr::addr_of!((*base).dev) } as usize - base as usize;
+        dev.cast::<u8>().wrapping_sub(offset).cast()
+    }
+
+    /// Calls `f` with the driver data of the devfreq device of `dev`.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `dev` is a device registered by a `Registration<T>`.
+    unsafe fn with_data<R>(
+        dev: *mut bindings::device,
+        f: impl FnOnce(&T) -> Result<R>,
+    ) -> Result<R> {
+        // SAFETY: `dev` is valid by the safety requirements. The devfreq device is returned with
+        // a reference, which is dropped below.
+        let child = unsafe {
+            bindings::device_find_child(dev, ptr::null_mut(), Some(Self::match_callback))
+        };
+        if child.is_null() {
+            return Err(ENODEV);
+        }
+        // SAFETY: The profile of the devfreq device is the first field of a `Registration<T>`,
+        // which outlives the devfreq device.
+        let ret = f(unsafe { &(*(*Self::devfreq_of(child)).profile.cast::<Self>()).data });
+        // SAFETY: The reference was taken by `device_find_child`.
+        unsafe { bindings::put_device(child) };
+        ret
+    }
+
+    unsafe extern "C" fn target_callback(
+        dev: *mut bindings::device,
+        freq: *mut c_ulong,
+        flags: u32,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The devfreq core passes a valid frequency.
+            let target = unsafe { *freq } as u64;
+            // SAFETY: The devfreq core calls this with a device registered by `register`.
+            let set = unsafe { Self::with_data(dev, |data| T::target(data, target, flags)) }?;
+            // SAFETY: The devfreq core passes a valid pointer to write the frequency to.
+            unsafe { *freq = set as _ };
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn get_dev_status_callback(
+        dev: *mut bindings::device,
+        stat: *mut bindings::devfreq_dev_status,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The devfreq core calls this with a device registered by `register`.
+            let status = unsafe { Self::with_data(dev, T::get_dev_status) }?;
+            // SAFETY: The devfreq core passes a valid status to fill in.
+            unsafe {
+                (*stat).total_time = status.total_time as _;
+                (*stat).busy_time = status.busy_time as _;
+                (*stat).current_frequency = status.current_frequency as _;
+            }
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn get_cur_freq_callback(
+        dev: *mut bindings::device,
+        freq: *mut c_ulong,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The devfreq core calls this with a device registered by `register`.
+            let cur = unsafe { Self::with_data(dev, T::get_cur_freq) }?;
+            // SAFETY: The devfreq core passes a valid pointer to write the frequency to.
+            unsafe { *freq = cur as _ };
+            Ok(0)
+        })
+    }
+}
+
+impl<T: Operations> Drop for Registration<T> {
+    fn drop(&mut self) {
+        if !self.devfreq.is_null() {
+            // SAFETY: The device was registered by the type invariants.
+            unsafe { bindings::devfreq_remove_device(self.devfreq) };
+        }
+    }
+}
+
+// SAFETY: The device can be unregistered from any thread, and the driver data is `Send`.
+unsafe impl<T: Operations> Send for Registration<T> {}
+
+// SAFETY: The methods that take `&self` are serialised by the devfreq core, and the driver data
+// is `Sync`.
+unsafe impl<T: Operations> Sync for Registration<T> {}
@@ -0,0 +1,158 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Generic devices that are part of the kernel's driver model.
+//!
+//! C header: [`include/linux/device.h`](../../../../include/linux/device.h)
+
+use crate::{
+    bindings,
+    str::CStr,
+    types::{ARef, AlwaysRefCounted, Opaque},
+};
+use core::ptr;
+
+#[cfg(CONFIG_PM_SLEEP)]
+use crate::error::{to_result, Result};
+
+/// A reference-counted device.
+///
+/// This structure represents the Rust abstraction for a C `struct device`. This implementation
+/// abstracts the usage of an already existing C `struct device` within Rust code that we get
+/// passed from the C side.
+///
+/// # Invariants
+///
+/// The pointer stored in `Self` is non-null and valid for the lifetime of the instance. A `Device`
+/// is always reference-counted, that is, a call to `get_device` ensures that the allocation remains
+/// valid at least until the matching call to `put_device`.
+#[repr(transparent)]
+pub struct Device(Opaque<bindings::device>);
+
+impl Device {
+    /// Creates a new reference-counted abstraction instance of an existing `struct device` pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is valid, non-null, and has a non-zero reference count.
+    pub unsafe fn from_raw(ptr: *mut bindings::device) -> ARef<Self> {
+        // SAFETY: By the safety requirements, `ptr` is valid and its refcount is non-zero, so
+        // `as_ref` produces a valid reference from which a new `ARef` can be created.
+        unsafe { Self::as_ref(ptr) }.into()
+    }
+
+    /// Creates a reference to a [`Device`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is valid, non-null, and has a non-zero reference count for
+    /// the entire duration when the returned reference exists.
+    pub unsafe fn as_ref<'a>(ptr: *mut bindings::device) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Obtain the raw `struct device *`.
+    pub fn as_raw(&self) -> *mut bindings::device {
+        self.0.get()
+    }
+
+    /// Returns the kobject embedded in the device.
+    pub fn kobj(&self) -> &crate::kobject::Kobject {
+        // SAFETY: By the type invariant, `self.as_raw()` is a valid device, and its kobject shares
+        // its reference count.
+        unsafe { crate::kobject::Kobject::as_ref(ptr::addr_of_mut!((*self.as_raw()).kobj)) }
+    }
+
+    /// Returns the name of the device.
+    pub fn name(&self) -> &CStr {
+        // SAFETY: By the type invariant, `self.as_raw()` is a valid device. `dev_name` returns a
+        // `NUL`-terminated string that lives at least as long as the device.
+        unsafe { CStr::from_char_ptr(bindings::dev_name(self.as_raw())) }
+    }
+
+    /// Returns the devicetree node associated with the device, if any.
+    #[cfg(CONFIG_OF)]
+    pub fn of_node(&self) -> Option<&crate::of::DeviceNode> {
+        // SAFETY: By the type invariant, `self.as_raw()` is a valid device.
+        let np = unsafe { *ptr::addr_of!((*self.as_raw()).of_node) };
+        if np.is_null() {
+            None
+        } else {
+            // SAFETY: The device holds a reference to its node for as long as it is alive.
+            Some(unsafe { crate::of::DeviceNode::as_ref(np) })
+        }
+    }
+
+    /// Returns the parent of the device, if any.
+    pub fn parent(&self) -> Option<&Device> {
+        // SAFETY: By the type invariant, `self.as_raw()` is a valid device.
+        let parent = unsafe { *ptr::addr_of!((*self.as_raw()).parent) };
+        if parent.is_null() {
+            None
+        } else {
+            // SAFETY: A device holds a reference to its parent, so the parent is valid for at
+            // least as long as `self`.
+            Some(unsafe { Self::as_ref(parent) })
+        }
+    }
+
+    /// Sets whether the device can wake the system up, and enables it to if `enable` is `true`.
+    ///
+    /// This creates the wakeup source of the device, which [`Device::stay_awake`],
+    /// [`Device::relax`] and [`Device::wakeup_event`] use, or destroys it.
+    #[cfg(CONFIG_PM_SLEEP)]
+    pub fn init_wakeup(&self, enable: bool) -> Result {
+        crate::might_sleep!();
+        // SAFETY: By the type invariant, `self.as_raw()` is a valid device.
+        to_result(unsafe { bindings::device_init_wakeup(self.as_raw(), enable) })
+    }
+
+    /// Keeps the system from suspending until [`Device::relax`] is called, if the device has a
+    /// wakeup source.
+    ///
+    /// This may be called from atomic context.
+    #[cfg(CONFIG_PM_SLEEP)]
+    pub fn stay_awake(&self) {
+        // SAFETY: By the type invariant, `self.as_raw()` is a valid device.
+        unsafe { bindings::pm_stay_awake(self.as_raw()) };
+    }
+
+    /// Undoes [`Device::stay_awake`].
+    ///
+    /// This may be called from atomic context.
+    #[cfg(CONFIG_PM_SLEEP)]
+    pub fn relax(&self) {
+        // SAFETY: By the type invariant, `self.as_raw()` is a valid device.
+        unsafe { bindings::pm_relax(self.as_raw()) };
+    }
+
+    /// Reports a wakeup event of the device, which keeps the system from suspending for `msec`
+    /// milliseconds, if the device has a wakeup source.
+    ///
+    /// This may be called from atomic context.
+    #[cfg(CONFIG_PM_SLEEP)]
+    pub fn wakeup_event(&self, msec: u32) {
+        // SAFETY: By the type invariant, `self.as_raw()` is a valid device.
+        unsafe { bindings::pm_wakeup_dev_event(self.as_raw(), msec, false) };
+    }
+}
+
+// SAFETY: Instances of `Device` are always reference-counted.
+unsafe impl AlwaysRefCounted for Device {
+    fn inc_ref(&self) {
+        // SAFETY: The existence of a shared reference guarantees that the refcount is non-zero.
+        unsafe { bindings::get_device(self.as_raw()) };
+    }
+
+    unsafe fn dec_ref(obj: ptr::NonNull<Self>) {
+        // SAFETY: The safety requirements guarantee that the refcount is non-zero.
+        unsafe { bindings::put_device(obj.cast().as_ptr()) }
+    }
+}
+
+// SAFETY: As by the type invariant `Device` can be sent to any thread.
+unsafe impl Send for Device {}
+
+// SAFETY: `Device` can be shared among threads because all immutable methods are protected by the
+// synchronization in `struct device`.
+unsafe impl Sync for Device {}
@@ -0,0 +1,149 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Direct memory access (DMA).
+//!
+//! C headers: [`include/linux/dma-mapping.h`](../../../../include/linux/dma-mapping.h) and
+//! [`include/linux/of_reserved_mem.h`](../../../../include/linux/of_reserved_mem.h)
+
+use crate::bindings;
+#[cfg(CONFIG_OF_RESERVED_MEM)]
+use crate::{
+    c_str,
+    device::Device,
+    error::{code::*, to_result, Result},
+    of::{DeviceNode, ReservedMem},
+    types::ARef,
+};
+
+/// The direction of a DMA transfer, the kernel's `enum dma_data_direction`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataDirection {
+    /// The device may both read and write the memory.
+    Bidirectional,
+    /// The device reads the memory.
+    ToDevice,
+    /// The device writes the memory.
+    FromDevice,
+}
+
+impl DataDirection {
+    pub(crate) fn as_raw(self) -> bindings::dma_data_direction {
+        match self {
+            Self::Bidirectional => bindings::dma_data_direction_DMA_BIDIRECTIONAL,
+            Self::ToDevice => bindings::dma_data_direction_DMA_TO_DEVICE,
+            Self::FromDevice => bindings::dma_data_direction_DMA_FROM_DEVICE,
+        }
+    }
+}
+
+/// The kind of a reserved memory region, as declared in the devicetree.
+#[cfg(CONFIG_OF_RESERVED_MEM)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReservedMemKind {
+    /// A `shared-dma-pool` without the `reusable` property: a dedicated coherent pool from which
+    /// `dma_alloc_coherent` allocations for the device are served.
+    CoherentPool,
+
+    /// A `reusable` `shared-dma-pool`: a CMA area that the page allocator may use while the
+    /// device doesn't need it.
+    Cma,
+}
+
+/// A devicetree reserved memory region assigned to a device for DMA.
+///
+/// Created with [`ReservedMemory::init_by_idx`], which is the equivalent of
+/// `of_reserved_mem_device_init_by_idx`. Once assigned, DMA allocations for the device are served
+/// from the region. The assignment is undone when the object is dropped.
+///
+/// # Invariants
+///
+/// The `index`-th `memory-region` of `dev` is assigned to `dev` and `rmem` describes it.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::{device::Device, dma::{ReservedMemKind, ReservedMemory}, prelude::*};
+/// fn setup_carveout(dev: &Device) -> Result<ReservedMemory> {
+///     let region = ReservedMemory::init_by_idx(dev, 0)?;
+///     if region.kind() == ReservedMemKind::Cma {
+///         pr_info!("Using CMA area {}\n", region.region().name());
+///     }
+///     Ok(region)
+/// }
+/// ```
+#[cfg(CONFIG_OF_RESERVED_MEM)]
+pub struct ReservedMemory {
+    dev: ARef<Device>,
+    rmem: &'static ReservedMem,
+    kind: ReservedMemKind,
+}
+
+#[cfg(CONFIG_OF_RESERVED_MEM)]
+impl ReservedMemory {
+    /// Assigns the `index`-th region listed in the `memory-region` property of `dev`'s devicetree
+    /// node to `dev`.
+    ///
+    /// Only `shared-dma-pool` regions can be assigned to devices; it fails with `EINVAL` for other
+    /// regions (e.g. `no-map` firmware carveouts), which drivers manage themselves after looking
+    /// them up with [`ReservedMem::lookup`].
+    ///
+    /// A device should only have one region assigned at a time: the kernel doesn't check this,
+    /// and dropping any of the returned objects undoes all the assignments of the device.
+    pub fn init_by_idx(dev: &Device, index: u32) -> Result<Self> {
+        let np = dev.of_node().ok_or(ENODEV)?;
+        let target: ARef<DeviceNode> = np
+            .parse_phandle(c_str!("memory-region"), index)
+            .ok_or(ENODEV)?;
+        let rmem = ReservedMem::lookup(&target).ok_or(EINVAL)?;
+
+        // Other regions have no device operations, for which the assignment fails anyway.
+        if !target.is_compatible(c_str!("shared-dma-pool")) {
+            return Err(EINVAL);
+        }
+        let kind = if target.has_property(c_str!("reusable")) {
+            ReservedMemKind::Cma
+        } else {
+            ReservedMemKind::CoherentPool
+        };
+
+        // SAFETY: `dev` and `np` are valid by their type invariants.
+        to_result(unsafe {
+            bindings::of_reserved_mem_device_init_by_idx(dev.as_raw(), np.as_raw(), index as _)
+        })?;
+
+        // INVARIANT: The region was successfully assigned above.
+        Ok(Self {
+            dev: dev.into(),
+            rmem,
+            kind,
+        })
+    }
+
+    /// Returns the underlying reserved memory region.
+    pub fn region(&self) -> &'static ReservedMem {
+        self.rmem
+    }
+
+    /// Returns the kind of the region.
+    pub fn kind(&self) -> ReservedMemKind {
+        self.kind
+    }
+
+    /// Returns the physical base address of the region.
+    pub fn base(&self) -> bindings::phys_addr_t {
+        self.rmem.base()
+    }
+
+    /// Returns the size of the region in bytes.
+    pub fn size(&self) -> usize {
+        self.rmem.size()
+    }
+}
+
+#[cfg(CONFIG_OF_RESERVED_MEM)]
+impl Drop for ReservedMemory {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, a region is assigned to the device.
+        unsafe { bindings::of_reserved_mem_device_release(self.dev.as_raw()) };
+    }
+}
@@ -0,0 +1,20 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! DRM drivers.
+//!
+//! A DRM driver implements [`drv::Driver`], allocates a [`device::Device`] for each of its devices
+//! and registers it with a [`drv::Registration`], which creates its `/dev/dri` nodes. Buffers are
+//! GEM objects ([`gem::Object`]) backed by shmem, and driver-specific ioctls are declared with
+//! [`declare_drm_ioctls`].
+//!
+//! C header: [`include/drm/drm_drv.h`](../../../../include/drm/drm_drv.h)
+
+pub mod device;
+pub mod drv;
+pub mod file;
+pub mod gem;
+pub mod ioctl;
+#[cfg(all(CONFIG_DRM_MIPI_DSI, CONFIG_OF))]
+pub mod mipi_dsi;
+#[cfg(CONFIG_DRM_PANEL)]
+pub mod panel;
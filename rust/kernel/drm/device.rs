@@ -0,0 +1,163 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! DRM devices.
+//!
+//! C header: [`include/drm/drm_device.h`](../../../../include/drm/drm_device.h)
+
+use crate::{
+    bindings, device,
+    drm::{drv::feature, drv::Driver, file, gem},
+    error::{from_err_ptr, Result},
+    str::CString,
+    types::{ARef, AlwaysRefCounted, ForeignOwnable, Opaque},
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::c_void,
+    fmt,
+    marker::PhantomData,
+    ptr::{self, NonNull},
+};
+
+/// The driver of a device, `Device::VTABLE` with the name of the device.
+#[repr(C)]
+struct VTable {
+    // Must be the first field, see `Device::release_callback`.
+    driver: bindings::drm_driver,
+    name: CString,
+}
+
+/// A DRM device of the driver `T`, the kernel's `struct drm_device`.
+///
+/// # Invariants
+///
+/// The device is reference-counted, and its `dev_private` holds the driver data, returned by
+/// [`ForeignOwnable::into_foreign`]. Its driver is a boxed `VTable` owned by the device. Both are
+/// freed when the device is released.
+#[repr(transparent)]
+pub struct Device<T: Driver>(Opaque<bindings::drm_device>, PhantomData<T>);
+
+impl<T: Driver> Device<T> {
+    const FOPS: bindings::file_operations = gem::create_fops();
+
+    /// The driver of the devices of `T`, but for the name, which is set by [`Device::new`].
+    const VTABLE: bindings::drm_driver = bindings::drm_driver {
+        open: Some(file::open_callback::<T::File>),
+        postclose: Some(file::postclose_callback::<T::File>),
+        release: Some(Self::release_callback),
+        gem_create_object: Some(gem::create_object_callback::<T::Object>),
+        prime_handle_to_fd: Some(bindings::drm_gem_prime_handle_to_fd),
+        prime_fd_to_handle: Some(bindings::drm_gem_prime_fd_to_handle),
+        gem_prime_import_sg_table: Some(bindings::drm_gem_shmem_prime_import_sg_table),
+        gem_prime_mmap: Some(bindings::drm_gem_prime_mmap),
+        major: T::INFO.major,
+        minor: T::INFO.minor,
+        patchlevel: T::INFO.patchlevel,
+        name: ptr::null_mut(),
+        desc: T::INFO.desc.as_char_ptr() as *mut _,
+        date: T::INFO.date.as_char_ptr() as *mut _,
+        driver_features: T::FEATURES | feature::GEM,
+        ioctls: T::IOCTLS.as_ptr(),
+        num_ioctls: T::IOCTLS.len() as _,
+        fops: &Self::FOPS,
+        // SAFETY: All other fields are optional, for which zero is valid.
+        ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+    };
+
+    /// Allocates a DRM device whose parent is `parent`, with the driver name `name`, which may be
+    /// built at runtime, and the driver data `data`.
+    ///
+    /// The device is made available to user space with [`crate::drm::drv::Registration`].
+    pub fn new(
+        parent: &device::Device,
+        name: fmt::Arguments<'_>,
+        data: T::Data,
+    ) -> Result<ARef<Self>> {
+        crate::might_sleep!();
+        let name = CString::try_from_fmt(name)?;
+        let vtable = Box::into_raw(Box::try_new(VTable {
+            driver: bindings::drm_driver {
+                name: name.as_char_ptr() as *mut _,
+                ..Self::VTABLE
+            },
+            name,
+        })?);
+        // SAFETY: The vtable and the name it points to are on the heap, and only freed when the
+        // device is released. `parent` is valid.
+        let raw = match from_err_ptr(unsafe {
+            bindings::drm_dev_alloc(ptr::addr_of!((*vtable).driver), parent.as_raw())
+        }) {
+            Ok(raw) => raw,
+            Err(e) => {
+                // SAFETY: The vtable was leaked above, and the device wasn't allocated.
+                drop(unsafe { Box::from_raw(vtable) });
+                return Err(e);
+            }
+        };
+        // SAFETY: The device was just allocated, and nothing else uses it yet.
+        unsafe { (*raw).dev_private = data.into_foreign() as *mut c_void };
+        // INVARIANT: The device was allocated with a reference, which is owned by the `ARef`, and
+        // `dev_private` and its driver were set above.
+        // SAFETY: `raw` is valid and non-null.
+        Ok(unsafe { ARef::from_raw(NonNull::new_unchecked(raw.cast())) })
+    }
+
+    /// Creates a reference to a [`Device`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is a valid device of the driver `T` for the lifetime of the
+    /// returned reference.
+    #[doc(hidden)]
+    pub unsafe fn as_ref<'a>(ptr: *mut bindings::drm_device) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct drm_device` pointer.
+    pub fn as_raw(&self) -> *mut bindings::drm_device {
+        self.0.get()
+    }
+
+    /// Returns the driver data of the device.
+    pub fn data(&self) -> <T::Data as ForeignOwnable>::Borrowed<'_> {
+        // SAFETY: `dev_private` holds the driver data by the type invariants, which is only freed
+        // when the last reference to the device is dropped.
+        unsafe { T::Data::borrow((*self.as_raw()).dev_private) }
+    }
+
+    unsafe extern "C" fn release_callback(raw: *mut bindings::drm_device) {
+        // SAFETY: The DRM core calls this when the last reference to the device is dropped, so
+        // the driver data isn't used anymore.
+        drop(unsafe { T::Data::from_foreign((*raw).dev_private) });
+        // SAFETY: The driver of the device is a boxed `VTable`, whose first field it is, by the
+        // type invariants. The DRM core still checks the features of the driver while it
+        // releases the device, so it is pointed to the static vtable, which has the same ones,
+        // before the box is freed.
+        unsafe {
+            let vtable = (*raw).driver.cast::<VTable>() as *mut VTable;
+            (*raw).driver = &Self::VTABLE;
+            drop(Box::from_raw(vtable));
+        }
+    }
+}
+
+// SAFETY: Instances of `Device` are always reference-counted.
+unsafe impl<T: Driver> AlwaysRefCounted for Device<T> {
+    fn inc_ref(&self) {
+        // SAFETY: The existence of a shared reference guarantees that the refcount is non-zero.
+        unsafe { bindings::drm_dev_get(self.as_raw()) };
+    }
+
+    unsafe fn dec_ref(obj: NonNull<Self>) {
+        // SAFETY: The safety requirements guarantee that the refcount is non-zero.
+        unsafe { bindings::drm_dev_put(obj.cast().as_ptr()) };
+    }
+}
+
+// SAFETY: The device can be used and released from any thread, and the driver data is `Send`.
+unsafe impl<T: Driver> Send for Device<T> {}
+
+// SAFETY: The methods that take `&self` are safe to call concurrently, and the driver data is
+// `Sync`.
+unsafe impl<T: Driver> Sync for Device<T> {}
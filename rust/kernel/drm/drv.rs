@@ -0,0 +1,104 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! DRM drivers and their registration.
+//!
+//! C header: [`include/drm/drm_drv.h`](../../../../include/drm/drm_drv.h)
+
+use crate::{
+    bindings,
+    drm::{device::Device, file::DriverFile, gem::DriverObject},
+    error::{to_result, Result},
+    str::CStr,
+    types::{ARef, ForeignOwnable},
+};
+use macros::vtable;
+
+/// Features of a DRM driver, the kernel's `DRIVER_*` values.
+///
+/// GEM support is always enabled.
+pub mod feature {
+    /// The driver supports mode setting.
+    pub const MODESET: u32 = crate::bindings::drm_driver_feature_DRIVER_MODESET;
+    /// The driver has a render node.
+    pub const RENDER: u32 = crate::bindings::drm_driver_feature_DRIVER_RENDER;
+    /// The driver supports atomic mode setting.
+    pub const ATOMIC: u32 = crate::bindings::drm_driver_feature_DRIVER_ATOMIC;
+    /// The driver supports `drm_syncobj` for explicit synchronisation.
+    pub const SYNCOBJ: u32 = crate::bindings::drm_driver_feature_DRIVER_SYNCOBJ;
+    /// The driver supports timeline `drm_syncobj`s.
+    pub const SYNCOBJ_TIMELINE: u32 = crate::bindings::drm_driver_feature_DRIVER_SYNCOBJ_TIMELINE;
+
+    pub(crate) const GEM: u32 = crate::bindings::drm_driver_feature_DRIVER_GEM;
+}
+
+/// Information about a DRM driver, returned by the `DRM_IOCTL_VERSION` ioctl.
+///
+/// The name of the driver is given to [`crate::drm::device::Device::new`], as it may be built at
+/// runtime.
+pub struct DriverInfo {
+    /// The major version number of the driver.
+    pub major: i32,
+    /// The minor version number of the driver.
+    pub minor: i32,
+    /// The patch level of the driver.
+    pub patchlevel: i32,
+    /// A description of the driver.
+    pub desc: &'static CStr,
+    /// The date of the driver, as `YYYYMMDD`.
+    pub date: &'static CStr,
+}
+
+/// A DRM driver.
+#[vtable]
+pub trait Driver: Sized + Send + Sync + 'static {
+    /// The data associated with each device of the driver.
+    type Data: ForeignOwnable + Send + Sync;
+
+    /// The data associated with each open file of a device of the driver.
+    type File: DriverFile<Driver = Self>;
+
+    /// The data associated with each GEM object of the driver.
+    type Object: DriverObject<Driver = Self>;
+
+    /// Information about the driver.
+    const INFO: DriverInfo;
+
+    /// The features of the driver, see [`feature`].
+    const FEATURES: u32;
+
+    /// The driver-specific ioctls, declared with [`crate::declare_drm_ioctls`].
+    const IOCTLS: &'static [bindings::drm_ioctl_desc];
+}
+
+/// A registration of a DRM device, which makes it available to user space.
+///
+/// The device is unregistered when this is dropped, after which user space can't open it
+/// anymore. Files that are already open keep a reference to the device.
+///
+/// # Invariants
+///
+/// The device is registered.
+pub struct Registration<T: Driver>(ARef<Device<T>>);
+
+impl<T: Driver> Registration<T> {
+    /// Registers `dev`, which creates its `/dev/dri` nodes.
+    pub fn new(dev: &Device<T>) -> Result<Self> {
+        crate::might_sleep!();
+        // SAFETY: `dev` is valid, and is unregistered when the registration is dropped.
+        to_result(unsafe { bindings::drm_dev_register(dev.as_raw(), 0) })?;
+        // INVARIANT: The device was registered above.
+        Ok(Self(dev.into()))
+    }
+
+    /// Returns the registered device.
+    pub fn device(&self) -> &Device<T> {
+        &self.0
+    }
+}
+
+impl<T: Driver> Drop for Registration<T> {
+    fn drop(&mut self) {
+        // SAFETY: The device is registered by the type invariants.
+        unsafe { bindings::drm_dev_unregister(self.0.as_raw()) };
+    }
+}
@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Open files of DRM devices.
+//!
+//! C header: [`include/drm/drm_file.h`](../../../../include/drm/drm_file.h)
+
+use crate::{
+    bindings,
+    drm::{device::Device, drv::Driver},
+    error::{from_result, Result},
+    types::Opaque,
+};
+use alloc::boxed::Box;
+use core::{ffi::c_int, marker::PhantomData, pin::Pin};
+
+/// The data associated with an open file of a DRM device.
+pub trait DriverFile: Send + Sync + Sized + 'static {
+    /// The driver of the device.
+    type Driver: Driver;
+
+    /// Called when user space opens the device.
+    fn open(dev: &Device<Self::Driver>) -> Result<Pin<Box<Self>>>;
+}
+
+/// An open file of a DRM device, the kernel's `struct drm_file`.
+///
+/// # Invariants
+///
+/// The file is valid while references to it exist, and its `driver_priv` holds the file data,
+/// returned by [`DriverFile::open`].
+#[repr(transparent)]
+pub struct File<T: DriverFile>(Opaque<bindings::drm_file>, PhantomData<T>);
+
+impl<T: DriverFile> File<T> {
+    /// Creates a reference to a [`File`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is an open file of a device of `T::Driver` for the lifetime
+    /// of the returned reference.
+    #[doc(hidden)]
+    pub unsafe fn as_ref<'a>(ptr: *mut bindings::drm_file) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct drm_file` pointer.
+    pub fn as_raw(&self) -> *mut bindings::drm_file {
+        self.0.get()
+    }
+
+    /// Returns the file data.
+    pub fn inner(&self) -> Pin<&T> {
+        // SAFETY: `driver_priv` holds the pinned file data by the type invariants, which is only
+        // freed when the file is closed.
+        unsafe { Pin::new_unchecked(&*(*self.as_raw()).driver_priv.cast::<T>()) }
+    }
+}
+
+pub(crate) unsafe extern "C" fn open_callback<T: DriverFile>(
+    raw_dev: *mut bindings::drm_device,
+    raw_file: *mut bindings::drm_file,
+) -> c_int {
+    from_result(|| {
+        // SAFETY: The DRM core calls this with a device of `T::Driver`.
+        let inner = T::open(unsafe { Device::as_ref(raw_dev) })?;
+        // SAFETY: The file is being opened, so nothing else uses `driver_priv`. The file data is
+        // only moved out of its box when the file is closed.
+        unsafe { (*raw_file).driver_priv = Box::into_raw(Pin::into_inner_unchecked(inner)).cast() };
+        Ok(0)
+    })
+}
+
+pub(crate) unsafe extern "C" fn postclose_callback<T: DriverFile>(
+    _raw_dev: *mut bindings::drm_device,
+    raw_file: *mut bindings::drm_file,
+) {
+    // SAFETY: The DRM core calls this once the file is closed, and `driver_priv` holds the file
+    // data set in `open_callback`, which isn't used anymore.
+    drop(unsafe { Box::from_raw((*raw_file).driver_priv.cast::<T>()) });
+}
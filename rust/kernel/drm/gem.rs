@@ -0,0 +1,275 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! GEM objects, the buffers of DRM devices.
+//!
+//! The objects are backed by shmem, using the kernel's GEM shmem helpers, and can be mapped by
+//! user space and shared with other devices through PRIME.
+//!
+//! C headers: [`include/drm/drm_gem.h`](../../../../include/drm/drm_gem.h) and
+//! [`include/drm/drm_gem_shmem_helper.h`](../../../../include/drm/drm_gem_shmem_helper.h)
+
+use crate::{
+    bindings,
+    drm::{device::Device, drv::Driver, file::File},
+    error::{code::*, from_err_ptr, from_result, to_result, Result},
+    types::{ARef, AlwaysRefCounted, Opaque},
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::c_int,
+    marker::PhantomPinned,
+    mem::MaybeUninit,
+    ops::Deref,
+    ptr::{self, NonNull},
+};
+
+type DriverFileOf<T> = <<T as DriverObject>::Driver as Driver>::File;
+
+/// The data associated with each GEM object of a driver.
+pub trait DriverObject: Send + Sync + Sized + 'static {
+    /// The driver of the objects.
+    type Driver: Driver<Object = Self>;
+
+    /// Creates the data of a new object of `size` bytes.
+    ///
+    /// This is called both for objects created with [`Object::new`] and for objects imported
+    /// from other devices.
+    fn new(dev: &Device<Self::Driver>, size: usize) -> Result<Self>;
+
+    /// Called when a handle to the object is created for `file`.
+    fn open(_obj: &Object<Self>, _file: &File<DriverFileOf<Self>>) -> Result {
+        Ok(())
+    }
+
+    /// Called when a handle to the object is deleted by `file`.
+    fn close(_obj: &Object<Self>, _file: &File<DriverFileOf<Self>>) {}
+}
+
+/// A GEM object, the kernel's `struct drm_gem_shmem_object`.
+///
+/// Objects are reference-counted, and user space refers to them with handles, which are local to
+/// an open file of the device.
+///
+/// # Invariants
+///
+/// The object is valid while references to it exist, and was allocated by
+/// `create_object_callback`.
+#[repr(C)]
+pub struct Object<T: DriverObject> {
+    // Must be the first field, since the shmem helpers free the object from a pointer to it.
+    obj: Opaque<bindings::drm_gem_shmem_object>,
+    funcs: Opaque<bindings::drm_gem_object_funcs>,
+    inner: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: DriverObject> Object<T> {
+    /// Creates an object of at least `size` bytes, rounded up to a multiple of the page size.
+    ///
+    /// Fails with `EINVAL` if `size` is zero.
+    pub fn new(dev: &Device<T::Driver>, size: usize) -> Result<ARef<Self>> {
+        let page_mask = bindings::PAGE_SIZE as usize - 1;
+        if size == 0 {
+            return Err(EINVAL);
+        }
+        let size = size.checked_add(page_mask).ok_or(EINVAL)? & !page_mask;
+        // SAFETY: `dev` is valid, and its driver allocates objects with `create_object_callback`.
+        let shmem = from_err_ptr(unsafe { bindings::drm_gem_shmem_create(dev.as_raw(), size) })?;
+        // INVARIANT: The object was allocated by `create_object_callback` as an `Object<T>`, since
+        // `T::Driver::Object` is `T`, with a reference that is owned by the `ARef`.
+        // SAFETY: `shmem` is valid and non-null.
+        Ok(unsafe { ARef::from_raw(NonNull::new_unchecked(shmem.cast())) })
+    }
+
+    /// Returns the object that `handle` refers to in `file`.
+    ///
+    /// Fails with `ENOENT` if there is no such object.
+    pub fn lookup_handle(file: &File<DriverFileOf<T>>, handle: u32) -> Result<ARef<Self>> {
+        // SAFETY: `file` is valid. The lookup takes a reference to the object.
+        let obj = unsafe { bindings::drm_gem_object_lookup(file.as_raw(), handle) };
+        let obj = NonNull::new(obj).ok_or(ENOENT)?;
+        // SAFETY: All objects of a device of `T::Driver` are allocated by
+        // `create_object_callback` as `Object<T>`, including imported ones, and the reference
+        // taken above is owned by the `ARef`.
+        Ok(unsafe { ARef::from_raw(obj.cast()) })
+    }
+
+    /// Returns the raw `struct drm_gem_object` pointer.
+    pub fn as_raw(&self) -> *mut bindings::drm_gem_object {
+        // SAFETY: The object is valid by the type invariants.
+        unsafe { ptr::addr_of_mut!((*self.obj.get()).base) }
+    }
+
+    /// Returns the size of the object, in bytes.
+    pub fn size(&self) -> usize {
+        // SAFETY: The object is valid by the type invariants, and its size never changes.
+        unsafe { (*self.as_raw()).size }
+    }
+
+    /// Returns the device that the object belongs to.
+    pub fn dev(&self) -> &Device<T::Driver> {
+        // SAFETY: The object holds a reference to its device, which is of `T::Driver`.
+        unsafe { Device::as_ref((*self.as_raw()).dev) }
+    }
+
+    /// Creates a handle to the object for `file`, which user space uses to refer to it.
+    pub fn create_handle(&self, file: &File<DriverFileOf<T>>) -> Result<u32> {
+        let mut handle = 0;
+        // SAFETY: The object and `file` are valid, and belong to the same device.
+        to_result(unsafe {
+            bindings::drm_gem_handle_create(file.as_raw(), self.as_raw(), &mut handle)
+        })?;
+        Ok(handle)
+    }
+
+    /// Returns the offset to pass to `mmap` on the device to map the object.
+    pub fn mmap_offset(&self) -> Result<u64> {
+        // SAFETY: The object is valid. This does nothing if the offset was already allocated.
+        to_result(unsafe { bindings::drm_gem_create_mmap_offset(self.as_raw()) })?;
+        // SAFETY: The offset was allocated above, and is only freed with the object.
+        Ok(unsafe {
+            bindings::drm_vma_node_offset_addr(ptr::addr_of_mut!((*self.as_raw()).vma_node))
+        })
+    }
+
+    /// Returns the object of `obj`.
+    ///
+    /// # Safety
+    ///
+    /// `obj` must be a valid object allocated by `create_object_callback` for `T`.
+    unsafe fn from_gem<'a>(obj: *mut bindings::drm_gem_object) -> &'a Self {
+        // SAFETY: The object is the first field of `Object<T>`, and `base` is the first field of
+        // `drm_gem_shmem_object`.
+        unsafe { &*obj.cast() }
+    }
+
+    unsafe extern "C" fn free_callback(obj: *mut bindings::drm_gem_object) {
+        let this = obj.cast::<Self>();
+        // SAFETY: The DRM core calls this when the last reference to the object is dropped. The
+        // driver data is dropped in place, then the shmem helpers release the object and free
+        // its allocation.
+        unsafe {
+            ptr::drop_in_place(ptr::addr_of_mut!((*this).inner));
+            bindings::drm_gem_shmem_free(this.cast());
+        }
+    }
+
+    unsafe extern "C" fn open_callback(
+        obj: *mut bindings::drm_gem_object,
+        file: *mut bindings::drm_file,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The DRM core calls this with an object of `T` and an open file of its
+            // device.
+            let (obj, file) = unsafe { (Self::from_gem(obj), File::as_ref(file)) };
+            T::open(obj, file)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn close_callback(
+        obj: *mut bindings::drm_gem_object,
+        file: *mut bindings::drm_file,
+    ) {
+        // SAFETY: The DRM core calls this with an object of `T` and an open file of its device.
+        let (obj, file) = unsafe { (Self::from_gem(obj), File::as_ref(file)) };
+        T::close(obj, file);
+    }
+
+    fn build_funcs() -> bindings::drm_gem_object_funcs {
+        bindings::drm_gem_object_funcs {
+            free: Some(Self::free_callback),
+            open: Some(Self::open_callback),
+            close: Some(Self::close_callback),
+            print_info: Some(bindings::drm_gem_shmem_object_print_info),
+            pin: Some(bindings::drm_gem_shmem_object_pin),
+            unpin: Some(bindings::drm_gem_shmem_object_unpin),
+            get_sg_table: Some(bindings::drm_gem_shmem_object_get_sg_table),
+            vmap: Some(bindings::drm_gem_shmem_object_vmap),
+            vunmap: Some(bindings::drm_gem_shmem_object_vunmap),
+            mmap: Some(bindings::drm_gem_shmem_object_mmap),
+            // SAFETY: `drm_gem_shmem_vm_ops` is never modified.
+            vm_ops: unsafe { ptr::addr_of!(bindings::drm_gem_shmem_vm_ops) },
+            // SAFETY: All other fields are optional, for which zero is valid.
+            ..unsafe { MaybeUninit::zeroed().assume_init() }
+        }
+    }
+}
+
+impl<T: DriverObject> Deref for Object<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+// SAFETY: Instances of `Object` are always reference-counted.
+unsafe impl<T: DriverObject> AlwaysRefCounted for Object<T> {
+    fn inc_ref(&self) {
+        // SAFETY: The existence of a shared reference guarantees that the refcount is non-zero.
+        unsafe { bindings::drm_gem_object_get(self.as_raw()) };
+    }
+
+    unsafe fn dec_ref(obj: NonNull<Self>) {
+        // SAFETY: The safety requirements guarantee that the refcount is non-zero.
+        unsafe { bindings::drm_gem_object_put(obj.cast().as_ptr()) };
+    }
+}
+
+// SAFETY: Objects can be used and freed from any thread, and the driver data is `Send`.
+unsafe impl<T: DriverObject> Send for Object<T> {}
+
+// SAFETY: The methods that take `&self` are safe to call concurrently, and the driver data is
+// `Sync`.
+unsafe impl<T: DriverObject> Sync for Object<T> {}
+
+/// Allocates the objects of a device, the kernel's `drm_driver.gem_create_object`.
+pub(crate) unsafe extern "C" fn create_object_callback<T: DriverObject>(
+    raw_dev: *mut bindings::drm_device,
+    size: usize,
+) -> *mut bindings::drm_gem_object {
+    // SAFETY: The DRM core calls this with a device of `T::Driver`.
+    let dev = unsafe { Device::as_ref(raw_dev) };
+    let inner = match T::new(dev, size) {
+        Ok(inner) => inner,
+        Err(e) => return e.to_ptr(),
+    };
+    let obj = match Box::try_new(Object {
+        // SAFETY: The shmem helpers initialise the object after this returns, and expect it to be
+        // zeroed.
+        obj: Opaque::new(unsafe { MaybeUninit::zeroed().assume_init() }),
+        funcs: Opaque::new(Object::<T>::build_funcs()),
+        inner,
+        _pin: PhantomPinned,
+    }) {
+        Ok(obj) => Box::into_raw(obj),
+        Err(_) => return ENOMEM.to_ptr(),
+    };
+    // SAFETY: `obj` was just allocated. Its funcs live as long as the object.
+    unsafe {
+        let gem = (*obj).as_raw();
+        (*gem).funcs = (*obj).funcs.get();
+        gem
+    }
+}
+
+/// Creates the file operations of a DRM device with GEM, like the kernel's `DEFINE_DRM_GEM_FOPS`.
+pub(crate) const fn create_fops() -> bindings::file_operations {
+    // SAFETY: All fields are optional, for which zero is valid.
+    let mut fops: bindings::file_operations = unsafe { MaybeUninit::zeroed().assume_init() };
+    // The owner isn't known here, so unlike with C drivers, open files don't pin the module of
+    // the driver.
+    fops.open = Some(bindings::drm_open);
+    fops.release = Some(bindings::drm_release);
+    fops.unlocked_ioctl = Some(bindings::drm_ioctl);
+    #[cfg(CONFIG_COMPAT)]
+    {
+        fops.compat_ioctl = Some(bindings::drm_compat_ioctl);
+    }
+    fops.poll = Some(bindings::drm_poll);
+    fops.read = Some(bindings::drm_read);
+    fops.llseek = Some(bindings::noop_llseek);
+    fops.mmap = Some(bindings::drm_gem_mmap);
+    fops
+}
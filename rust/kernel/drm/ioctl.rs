@@ -0,0 +1,117 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Driver-specific ioctls of DRM devices.
+//!
+//! C header: [`include/drm/drm_ioctl.h`](../../../../include/drm/drm_ioctl.h)
+
+#![allow(non_snake_case)]
+
+use crate::ioctl;
+
+/// The ioctl may only be used by authenticated clients (or render clients).
+pub const AUTH: u32 = crate::bindings::drm_ioctl_flags_DRM_AUTH;
+/// The ioctl may only be used by the DRM master.
+pub const MASTER: u32 = crate::bindings::drm_ioctl_flags_DRM_MASTER;
+/// The ioctl may only be used with `CAP_SYS_ADMIN`.
+pub const ROOT_ONLY: u32 = crate::bindings::drm_ioctl_flags_DRM_ROOT_ONLY;
+/// The ioctl may be used on render nodes.
+pub const RENDER_ALLOW: u32 = crate::bindings::drm_ioctl_flags_DRM_RENDER_ALLOW;
+
+/// Builds the number of a driver-specific DRM ioctl without an argument.
+pub const fn IO(nr: u32) -> u32 {
+    ioctl::_IO(uapi::DRM_IOCTL_BASE, uapi::DRM_COMMAND_BASE + nr)
+}
+
+/// Builds the number of a driver-specific DRM ioctl that reads `T`.
+pub const fn IOR<T>(nr: u32) -> u32 {
+    ioctl::_IOR::<T>(uapi::DRM_IOCTL_BASE, uapi::DRM_COMMAND_BASE + nr)
+}
+
+/// Builds the number of a driver-specific DRM ioctl that writes `T`.
+pub const fn IOW<T>(nr: u32) -> u32 {
+    ioctl::_IOW::<T>(uapi::DRM_IOCTL_BASE, uapi::DRM_COMMAND_BASE + nr)
+}
+
+/// Builds the number of a driver-specific DRM ioctl that reads and writes `T`.
+pub const fn IOWR<T>(nr: u32) -> u32 {
+    ioctl::_IOWR::<T>(uapi::DRM_IOCTL_BASE, uapi::DRM_COMMAND_BASE + nr)
+}
+
+/// Declares the driver-specific ioctls of a DRM driver, as [`crate::drm::drv::Driver::IOCTLS`].
+///
+/// Each ioctl is given as `(name, direction, type, flags, handler)`, where the direction is one
+/// of `IO`, `IOR`, `IOW` and `IOWR` (see the functions of the same names in
+/// [`crate::drm::ioctl`]), the type is that of the argument and the flags are the ones in
+/// [`crate::drm::ioctl`]. The ioctls are numbered from zero, in the order they are declared, which
+/// must match the numbers used by user space.
+///
+/// The handler is called with the device, the argument, which the DRM core copies from and back to
+/// user space, and the open file. It returns the value returned to user space.
+///
+/// # Examples
+///
+/// ```ignore
+/// use kernel::drm::{self, device::Device, file::File, ioctl};
+///
+/// fn get_param(dev: &Device<MyDriver>, arg: &mut MyGetParam, file: &File<MyFile>) -> Result<u32> {
+///     arg.value = 42;
+///     Ok(0)
+/// }
+///
+/// #[vtable]
+/// impl drm::drv::Driver for MyDriver {
+///     // ...
+///     kernel::declare_drm_ioctls! {
+///         (MY_GET_PARAM, IOWR, MyGetParam, ioctl::RENDER_ALLOW, get_param),
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! declare_drm_ioctls {
+    ($(($name:ident, $dir:ident, $ty:ty, $flags:expr, $func:expr)),* $(,)?) => {
+        const IOCTLS: &'static [$crate::bindings::drm_ioctl_desc] = {
+            let mut _nr: u32 = 0;
+            &[$(
+                $crate::bindings::drm_ioctl_desc {
+                    cmd: {
+                        let cmd = $crate::declare_drm_ioctls!(@cmd $dir, $ty, _nr);
+                        _nr += 1;
+                        cmd
+                    },
+                    func: {
+                        #[allow(non_snake_case)]
+                        unsafe extern "C" fn $name(
+                            raw_dev: *mut $crate::bindings::drm_device,
+                            raw_data: *mut ::core::ffi::c_void,
+                            raw_file: *mut $crate::bindings::drm_file,
+                        ) -> ::core::ffi::c_int {
+                            // SAFETY: The DRM core calls this with a device of the driver, a
+                            // kernel copy of the argument, whose size is encoded in the ioctl
+                            // number, and an open file of the device.
+                            let (dev, data, file) = unsafe {
+                                (
+                                    $crate::drm::device::Device::as_ref(raw_dev),
+                                    &mut *raw_data.cast::<$ty>(),
+                                    $crate::drm::file::File::as_ref(raw_file),
+                                )
+                            };
+                            match $func(dev, data, file) {
+                                Ok(ret) => ::core::convert::TryFrom::try_from(ret).unwrap_or(
+                                    $crate::error::code::ERANGE.to_errno(),
+                                ),
+                                Err(e) => e.to_errno(),
+                            }
+                        }
+                        Some($name)
+                    },
+                    flags: $flags,
+                    name: $crate::c_str!(::core::stringify!($name)).as_char_ptr(),
+                }
+            ),*]
+        };
+    };
+    (@cmd IO, $ty:ty, $nr:expr) => { $crate::drm::ioctl::IO($nr) };
+    (@cmd IOR, $ty:ty, $nr:expr) => { $crate::drm::ioctl::IOR::<$ty>($nr) };
+    (@cmd IOW, $ty:ty, $nr:expr) => { $crate::drm::ioctl::IOW::<$ty>($nr) };
+    (@cmd IOWR, $ty:ty, $nr:expr) => { $crate::drm::ioctl::IOWR::<$ty>($nr) };
+}
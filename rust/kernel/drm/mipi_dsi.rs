@@ -0,0 +1,510 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! MIPI DSI peripherals, such as display panels.
+//!
+//! DSI peripherals are described in the devicetree as children of their DSI host, and are handled
+//! by drivers that implement [`Driver`]. Drivers configure the link with [`Device::attach`], and
+//! send commands to the peripheral with the DCS helpers of [`Device`].
+//!
+//! C header: [`include/drm/drm_mipi_dsi.h`](../../../../include/drm/drm_mipi_dsi.h)
+
+use crate::{
+    bindings, device,
+    error::{from_result, to_result, Error, Result},
+    of,
+    str::CStr,
+    types::{ForeignOwnable, Opaque},
+    ThisModule,
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::c_int,
+    marker::{PhantomData, PhantomPinned},
+    pin::Pin,
+    ptr,
+};
+use macros::vtable;
+
+/// Flags of the link of a peripheral, the kernel's `MIPI_DSI_MODE_*` values.
+pub mod mode_flags {
+    /// The peripheral is in video mode, instead of command mode.
+    pub const VIDEO: u64 = crate::bindings::MIPI_DSI_MODE_VIDEO as u64;
+    /// Video mode with burst transfers.
+    pub const VIDEO_BURST: u64 = crate::bindings::MIPI_DSI_MODE_VIDEO_BURST as u64;
+    /// Video mode with sync pulses, instead of sync events.
+    pub const VIDEO_SYNC_PULSE: u64 = crate::bindings::MIPI_DSI_MODE_VIDEO_SYNC_PULSE as u64;
+    /// Commands are sent in low power mode.
+    pub const LPM: u64 = crate::bindings::MIPI_DSI_MODE_LPM as u64;
+    /// The clock lane may be stopped between transfers.
+    pub const CLOCK_NON_CONTINUOUS: u64 = crate::bindings::MIPI_DSI_CLOCK_NON_CONTINUOUS as u64;
+    /// No end of transmission packet is sent.
+    pub const NO_EOT_PACKET: u64 = crate::bindings::MIPI_DSI_MODE_NO_EOT_PACKET as u64;
+}
+
+/// The pixel format of a video mode stream, the kernel's `enum mipi_dsi_pixel_format`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// 24 bits per pixel.
+    Rgb888,
+    /// 18 bits per pixel, each pixel in 3 bytes.
+    Rgb666,
+    /// 18 bits per pixel, packed.
+    Rgb666Packed,
+    /// 16 bits per pixel.
+    Rgb565,
+}
+
+impl PixelFormat {
+    fn as_raw(self) -> bindings::mipi_dsi_pixel_format {
+        match self {
+            Self::Rgb888 => bindings::mipi_dsi_pixel_format_MIPI_DSI_FMT_RGB888,
+            Self::Rgb666 => bindings::mipi_dsi_pixel_format_MIPI_DSI_FMT_RGB666,
+            Self::Rgb666Packed => bindings::mipi_dsi_pixel_format_MIPI_DSI_FMT_RGB666_PACKED,
+            Self::Rgb565 => bindings::mipi_dsi_pixel_format_MIPI_DSI_FMT_RGB565,
+        }
+    }
+}
+
+/// The tearing effect output of a peripheral, set with [`Device::dcs_set_tear_on`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TearMode {
+    /// The output is on during vertical blanking.
+    VBlank,
+    /// The output is on during both vertical and horizontal blanking.
+    VHBlank,
+}
+
+/// The configuration of the link of a peripheral, applied by [`Device::attach`].
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// The number of data lanes.
+    pub lanes: u32,
+    /// The pixel format of video mode streams.
+    pub format: PixelFormat,
+    /// The flags of the link, see [`mode_flags`].
+    pub mode_flags: u64,
+}
+
+/// Converts the return value of a transfer to the number of bytes transferred.
+fn to_size(ret: isize) -> Result<usize> {
+    if ret < 0 {
+        Err(Error::from_errno(ret as _))
+    } else {
+        Ok(ret as _)
+    }
+}
+
+/// A DSI peripheral, the kernel's `struct mipi_dsi_device`.
+///
+/// # Invariants
+///
+/// The peripheral is valid while references to it exist.
+#[repr(transparent)]
+pub struct Device(Opaque<bindings::mipi_dsi_device>);
+
+impl Device {
+    /// Creates a reference to a [`Device`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is valid for the lifetime of the returned reference.
+    pub unsafe fn as_ref<'a>(ptr: *mut bindings::mipi_dsi_device) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct mipi_dsi_device` pointer.
+    pub fn as_raw(&self) -> *mut bindings::mipi_dsi_device {
+        self.0.get()
+    }
+
+    /// Returns the device of the peripheral.
+    pub fn dev(&self) -> &device::Device {
+        // SAFETY: The peripheral is valid by the type invariants, and embeds its device.
+        unsafe { device::Device::as_ref(ptr::addr_of_mut!((*self.as_raw()).dev)) }
+    }
+
+    /// Returns the virtual channel of the peripheral.
+    pub fn channel(&self) -> u32 {
+        // SAFETY: The peripheral is valid by the type invariants, and its channel never changes.
+        unsafe { (*self.as_raw()).channel }
+    }
+
+    /// Configures the link with `config`, and attaches the peripheral to its host, which makes
+    /// the host set up its output.
+    ///
+    /// This may fail with `EPROBE_DEFER` if the host isn't ready yet.
+    pub fn attach(&self, config: &Config) -> Result {
+        // SAFETY: The peripheral is valid by the type invariants, and the configuration is only
+        // read by the host when the peripheral is attached.
+        unsafe {
+            let raw = self.as_raw();
+            (*raw).lanes = config.lanes;
+            (*raw).format = config.format.as_raw();
+            (*raw).mode_flags = config.mode_flags as _;
+            to_result(bindings::mipi_dsi_attach(raw))
+        }
+    }
+
+    /// Detaches the peripheral from its host.
+    pub fn detach(&self) -> Result {
+        // SAFETY: The peripheral is valid by the type invariants.
+        to_result(unsafe { bindings::mipi_dsi_detach(self.as_raw()) })
+    }
+
+    /// Sends a generic write packet with `payload`.
+    pub fn generic_write(&self, payload: &[u8]) -> Result {
+        // SAFETY: The peripheral is valid by the type invariants, and `payload` is valid for
+        // reads of its length.
+        to_size(unsafe {
+            bindings::mipi_dsi_generic_write(self.as_raw(), payload.as_ptr().cast(), payload.len())
+        })?;
+        Ok(())
+    }
+
+    /// Sends a generic read packet with `params`, and reads the response into `buf`.
+    ///
+    /// Returns the number of bytes read.
+    pub fn generic_read(&self, params: &[u8], buf: &mut [u8]) -> Result<usize> {
+        // SAFETY: The peripheral is valid by the type invariants, `params` is valid for reads
+        // and `buf` for writes of their lengths.
+        to_size(unsafe {
+            bindings::mipi_dsi_generic_read(
+                self.as_raw(),
+                params.as_ptr().cast(),
+                params.len(),
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+            )
+        })
+    }
+
+    /// Sends the DCS command `cmd` with the parameters `params`.
+    ///
+    /// Commands with at most one parameter are sent as short writes, the others as long writes.
+    pub fn dcs_write(&self, cmd: u8, params: &[u8]) -> Result {
+        // SAFETY: The peripheral is valid by the type invariants, and `params` is valid for reads
+        // of its length.
+        to_size(unsafe {
+            bindings::mipi_dsi_dcs_write(self.as_raw(), cmd, params.as_ptr().cast(), params.len())
+        })?;
+        Ok(())
+    }
+
+    /// Sends `buf`, a DCS command followed by its parameters.
+    ///
+    /// This is [`Device::dcs_write`] for the initialisation sequences of panels, which are
+    /// usually tables of such buffers.
+    pub fn dcs_write_buffer(&self, buf: &[u8]) -> Result {
+        // SAFETY: The peripheral is valid by the type invariants, and `buf` is valid for reads
+        // of its length.
+        to_size(unsafe {
+            bindings::mipi_dsi_dcs_write_buffer(self.as_raw(), buf.as_ptr().cast(), buf.len())
+        })?;
+        Ok(())
+    }
+
+    /// Sends the DCS command `cmd`, and reads the response into `buf`.
+    ///
+    /// Returns the number of bytes read.
+    pub fn dcs_read(&self, cmd: u8, buf: &mut [u8]) -> Result<usize> {
+        // SAFETY: The peripheral is valid by the type invariants, and `buf` is valid for writes
+        // of its length.
+        to_size(unsafe {
+            bindings::mipi_dsi_dcs_read(self.as_raw(), cmd, buf.as_mut_ptr().cast(), buf.len())
+        })
+    }
+
+    /// Sends the DCS `nop` command.
+    pub fn dcs_nop(&self) -> Result {
+        // SAFETY: The peripheral is valid by the type invariants.
+        to_result(unsafe { bindings::mipi_dsi_dcs_nop(self.as_raw()) })
+    }
+
+    /// Resets the peripheral with the DCS `soft_reset` command.
+    pub fn dcs_soft_reset(&self) -> Result {
+        // SAFETY: The peripheral is valid by the type invariants.
+        to_result(unsafe { bindings::mipi_dsi_dcs_soft_reset(self.as_raw()) })
+    }
+
+    /// Returns the power mode of the peripheral, read with the DCS `get_power_mode` command.
+    pub fn dcs_get_power_mode(&self) -> Result<u8> {
+        let mut mode = 0;
+        // SAFETY: The peripheral is valid by the type invariants.
+        to_result(unsafe { bindings::mipi_dsi_dcs_get_power_mode(self.as_raw(), &mut mode) })?;
+        Ok(mode)
+    }
+
+    /// Puts the peripheral to sleep with the DCS `enter_sleep_mode` command.
+    pub fn dcs_enter_sleep_mode(&self) -> Result {
+        // SAFETY: The peripheral is valid by the type invariants.
+        to_result(unsafe { bindings::mipi_dsi_dcs_enter_sleep_mode(self.as_raw()) })
+    }
+
+    /// Wakes the peripheral up with the DCS `exit_sleep_mode` command.
+    pub fn dcs_exit_sleep_mode(&self) -> Result {
+        // SAFETY: The peripheral is valid by the type invariants.
+        to_result(unsafe { bindings::mipi_dsi_dcs_exit_sleep_mode(self.as_raw()) })
+    }
+
+    /// Turns the display off with the DCS `set_display_off` command.
+    pub fn dcs_set_display_off(&self) -> Result {
+        // SAFETY: The peripheral is valid by the type invariants.
+        to_result(unsafe { bindings::mipi_dsi_dcs_set_display_off(self.as_raw()) })
+    }
+
+    /// Turns the display on with the DCS `set_display_on` command.
+    pub fn dcs_set_display_on(&self) -> Result {
+        // SAFETY: The peripheral is valid by the type invariants.
+        to_result(unsafe { bindings::mipi_dsi_dcs_set_display_on(self.as_raw()) })
+    }
+
+    /// Sets the columns that the host writes to, from `start` to `end` inclusive.
+    pub fn dcs_set_column_address(&self, start: u16, end: u16) -> Result {
+        // SAFETY: The peripheral is valid by the type invariants.
+        to_result(unsafe { bindings::mipi_dsi_dcs_set_column_address(self.as_raw(), start, end) })
+    }
+
+    /// Sets the pages (rows) that the host writes to, from `start` to `end` inclusive.
+    pub fn dcs_set_page_address(&self, start: u16, end: u16) -> Result {
+        // SAFETY: The peripheral is valid by the type invariants.
+        to_result(unsafe { bindings::mipi_dsi_dcs_set_page_address(self.as_raw(), start, end) })
+    }
+
+    /// Turns the tearing effect output off.
+    pub fn dcs_set_tear_off(&self) -> Result {
+        // SAFETY: The peripheral is valid by the type invariants.
+        to_result(unsafe { bindings::mipi_dsi_dcs_set_tear_off(self.as_raw()) })
+    }
+
+    /// Turns the tearing effect output on, in the mode `mode`.
+    pub fn dcs_set_tear_on(&self, mode: TearMode) -> Result {
+        let mode = match mode {
+            TearMode::VBlank => bindings::mipi_dsi_dcs_tear_mode_MIPI_DSI_DCS_TEAR_MODE_VBLANK,
+            TearMode::VHBlank => bindings::mipi_dsi_dcs_tear_mode_MIPI_DSI_DCS_TEAR_MODE_VHBLANK,
+        };
+        // SAFETY: The peripheral is valid by the type invariants.
+        to_result(unsafe { bindings::mipi_dsi_dcs_set_tear_on(self.as_raw(), mode) })
+    }
+
+    /// Sets the pixel format of the interface, the DCS `set_pixel_format` parameter.
+    pub fn dcs_set_pixel_format(&self, format: u8) -> Result {
+        // SAFETY: The peripheral is valid by the type invariants.
+        to_result(unsafe { bindings::mipi_dsi_dcs_set_pixel_format(self.as_raw(), format) })
+    }
+
+    /// Sets the brightness of the display.
+    pub fn dcs_set_display_brightness(&self, brightness: u16) -> Result {
+        // SAFETY: The peripheral is valid by the type invariants.
+        to_result(unsafe {
+            bindings::mipi_dsi_dcs_set_display_brightness(self.as_raw(), brightness)
+        })
+    }
+
+    /// Returns the brightness of the display.
+    pub fn dcs_get_display_brightness(&self) -> Result<u16> {
+        let mut brightness = 0;
+        // SAFETY: The peripheral is valid by the type invariants.
+        to_result(unsafe {
+            bindings::mipi_dsi_dcs_get_display_brightness(self.as_raw(), &mut brightness)
+        })?;
+        Ok(brightness)
+    }
+}
+
+// SAFETY: Transfers to the peripheral are serialised by its host, and the peripheral can be used
+// from any thread.
+unsafe impl Send for Device {}
+
+// SAFETY: The methods that take `&self` are safe to call concurrently.
+unsafe impl Sync for Device {}
+
+/// A driver of DSI peripherals.
+#[vtable]
+pub trait Driver: Sized + 'static {
+    /// The data associated with each peripheral bound to the driver.
+    type Data: ForeignOwnable + Send + Sync;
+
+    /// The name of the driver.
+    const NAME: &'static CStr;
+
+    /// The match table of the devicetree nodes that the driver handles.
+    const OF_MATCH: of::IdTable;
+
+    /// Binds the driver to `dsi`.
+    fn probe(dsi: &Device) -> Result<Self::Data>;
+
+    /// Unbinds the driver from `dsi`, which drops `data` once this returns.
+    fn remove(_dsi: &Device, _data: Self::Data) {}
+
+    /// Called when the system shuts down, e.g. to turn the display off.
+    fn shutdown(_dsi: &Device, _data: <Self::Data as ForeignOwnable>::Borrowed<'_>) {}
+}
+
+/// The registration of a driver of DSI peripherals.
+///
+/// The driver is unregistered when this is dropped, which unbinds it from its peripherals.
+///
+/// # Invariants
+///
+/// `driver` is registered if `registered` is `true`.
+pub struct Registration<T: Driver> {
+    driver: Opaque<bindings::mipi_dsi_driver>,
+    registered: bool,
+    _pin: PhantomPinned,
+    _p: PhantomData<T>,
+}
+
+// SAFETY: The driver can be unregistered from any thread.
+unsafe impl<T: Driver> Send for Registration<T> {}
+
+// SAFETY: `Registration` has no methods that take `&self`.
+unsafe impl<T: Driver> Sync for Registration<T> {}
+
+impl<T: Driver> Registration<T> {
+    /// Registers the driver on behalf of `module`.
+    ///
+    /// The driver is bound to the peripherals that match [`Driver::OF_MATCH`].
+    pub fn register(module: &'static ThisModule) -> Result<Pin<Box<Self>>> {
+        let mut reg = Pin::from(Box::try_new(Self {
+            driver: Opaque::new(bindings::mipi_dsi_driver {
+                driver: bindings::device_driver {
+                    name: T::NAME.as_char_ptr(),
+                    of_match_table: T::OF_MATCH.as_ptr(),
+                    // SAFETY: All other fields are optional, for which zero is valid.
+                    ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+                },
+                probe: Some(Self::probe_callback),
+                remove: Some(Self::remove_callback),
+                shutdown: if T::HAS_SHUTDOWN {
+                    Some(Self::shutdown_callback)
+                } else {
+                    None
+                },
+            }),
+            registered: false,
+            _pin: PhantomPinned,
+            _p: PhantomData,
+        })?);
+        // SAFETY: `driver` is valid and pinned, its match table is static, and the driver is
+        // unregistered before it is freed.
+        to_result(unsafe {
+            bindings::mipi_dsi_driver_register_full(reg.driver.get(), module.as_ptr())
+        })?;
+        // INVARIANT: The driver was registered above.
+        // SAFETY: `reg` isn't moved out of.
+        unsafe { reg.as_mut().get_unchecked_mut() }.registered = true;
+        Ok(reg)
+    }
+
+    unsafe extern "C" fn probe_callback(dsi: *mut bindings::mipi_dsi_device) -> c_int {
+        from_result(|| {
+            // SAFETY: The driver core calls this with a valid peripheral, which stays valid until
+            // the driver is unbound.
+            let dsi = unsafe { Device::as_ref(dsi) };
+            let data = T::probe(dsi)?;
+            // SAFETY: The driver data of the device belongs to the driver bound to it, and is
+            // freed in `remove_callback`.
+            unsafe { (*dsi.dev().as_raw()).driver_data = data.into_foreign() as _ };
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn remove_callback(dsi: *mut bindings::mipi_dsi_device) {
+        // SAFETY: The driver core calls this with a peripheral that was bound by
+        // `probe_callback`, so its driver data is set. It isn't used once this returns.
+        unsafe {
+            let dsi = Device::as_ref(dsi);
+            let data = T::Data::from_foreign((*dsi.dev().as_raw()).driver_data);
+            T::remove(dsi, data);
+        }
+    }
+
+    unsafe extern "C" fn shutdown_callback(dsi: *mut bindings::mipi_dsi_device) {
+        // SAFETY: The driver core calls this with a peripheral that was bound by
+        // `probe_callback`, so its driver data is set.
+        unsafe {
+            let dsi = Device::as_ref(dsi);
+            T::shutdown(dsi, T::Data::borrow((*dsi.dev().as_raw()).driver_data));
+        }
+    }
+}
+
+impl<T: Driver> Drop for Registration<T> {
+    fn drop(&mut self) {
+        if self.registered {
+            // SAFETY: The driver was registered by the type invariants.
+            unsafe { bindings::mipi_dsi_driver_unregister(self.driver.get()) };
+        }
+    }
+}
+
+/// Declares a kernel module that registers a driver of DSI peripherals, and emits the OF device
+/// table that allows the module to be loaded when a matching peripheral is found.
+///
+/// # Examples
+///
+/// ```ignore
+/// use kernel::{c_str, drm::mipi_dsi, of};
+/// use kernel::prelude::*;
+///
+/// kernel::module_mipi_dsi_driver! {
+///     type: Panel,
+///     name: "rust_panel",
+///     author: "Rust for Linux Contributors",
+///     description: "DSI panel driver",
+///     license: "GPL",
+/// }
+///
+/// struct Panel;
+///
+/// #[vtable]
+/// impl mipi_dsi::Driver for Panel {
+///     type Data = ();
+///
+///     const NAME: &'static CStr = c_str!("rust-panel");
+///     const OF_MATCH: of::IdTable = kernel::of_id_table![c_str!("vendor,rust-panel")];
+///
+///     fn probe(dsi: &mipi_dsi::Device) -> Result {
+///         dsi.attach(&mipi_dsi::Config {
+///             lanes: 4,
+///             format: mipi_dsi::PixelFormat::Rgb888,
+///             mode_flags: mipi_dsi::mode_flags::VIDEO | mipi_dsi::mode_flags::LPM,
+///         })
+///     }
+///
+///     fn remove(dsi: &mipi_dsi::Device, _data: ()) {
+///         let _ = dsi.detach();
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! module_mipi_dsi_driver {
+    (type: $type:ty, $($f:tt)*) => {
+        struct Module {
+            _reg: ::core::pin::Pin<
+                $crate::prelude::Box<$crate::drm::mipi_dsi::Registration<$type>>,
+            >,
+        }
+
+        $crate::prelude::module! {
+            type: Module,
+            $($f)*
+        }
+
+        impl $crate::Module for Module {
+            fn init(module: &'static $crate::ThisModule) -> $crate::error::Result<Self> {
+                Ok(Module {
+                    _reg: $crate::drm::mipi_dsi::Registration::register(module)?,
+                })
+            }
+        }
+
+        #[cfg(MODULE)]
+        #[no_mangle]
+        static __mod_of__mipi_dsi_of_match_device_table: [
+            $crate::of::DeviceId;
+            <$type as $crate::drm::mipi_dsi::Driver>::OF_MATCH.len_with_end()
+        ] = <$type as $crate::drm::mipi_dsi::Driver>::OF_MATCH.to_array();
+    };
+}
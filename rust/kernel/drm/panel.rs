@@ -0,0 +1,345 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! DRM panels.
+//!
+//! A panel driver registers a [`Panel`] for its device, which display drivers find through the
+//! devicetree node of the device, with `of_drm_find_panel()`, and then power up and down around
+//! scanout.
+//!
+//! C header: [`include/drm/drm_panel.h`](../../../../include/drm/drm_panel.h)
+
+use crate::{
+    bindings, device,
+    error::{code::*, from_result, Result},
+    types::Opaque,
+};
+use alloc::boxed::Box;
+use core::{ffi::c_int, marker::PhantomPinned, mem::MaybeUninit, pin::Pin};
+use macros::vtable;
+
+/// The type of the connector of a panel, the kernel's `DRM_MODE_CONNECTOR_*` values.
+pub mod connector_type {
+    /// A parallel RGB interface.
+    pub const DPI: u32 = crate::bindings::DRM_MODE_CONNECTOR_DPI;
+    /// A MIPI DSI interface.
+    pub const DSI: u32 = crate::bindings::DRM_MODE_CONNECTOR_DSI;
+    /// An LVDS interface.
+    pub const LVDS: u32 = crate::bindings::DRM_MODE_CONNECTOR_LVDS;
+}
+
+/// Flags of a display mode, the kernel's `DRM_MODE_FLAG_*` values.
+pub mod mode_flags {
+    /// The horizontal sync is active high.
+    pub const PHSYNC: u32 = crate::bindings::DRM_MODE_FLAG_PHSYNC;
+    /// The horizontal sync is active low.
+    pub const NHSYNC: u32 = crate::bindings::DRM_MODE_FLAG_NHSYNC;
+    /// The vertical sync is active high.
+    pub const PVSYNC: u32 = crate::bindings::DRM_MODE_FLAG_PVSYNC;
+    /// The vertical sync is active low.
+    pub const NVSYNC: u32 = crate::bindings::DRM_MODE_FLAG_NVSYNC;
+}
+
+/// The timings of a display mode, the kernel's `struct drm_display_mode`.
+///
+/// The horizontal timings are in pixels, and the vertical ones in lines.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DisplayMode {
+    /// The pixel clock, in kHz.
+    pub clock: u32,
+    /// The number of visible pixels of a line.
+    pub hdisplay: u16,
+    /// The start of the horizontal sync.
+    pub hsync_start: u16,
+    /// The end of the horizontal sync.
+    pub hsync_end: u16,
+    /// The total number of pixels of a line.
+    pub htotal: u16,
+    /// The number of visible lines.
+    pub vdisplay: u16,
+    /// The start of the vertical sync.
+    pub vsync_start: u16,
+    /// The end of the vertical sync.
+    pub vsync_end: u16,
+    /// The total number of lines.
+    pub vtotal: u16,
+    /// The flags of the mode, see [`mode_flags`].
+    pub flags: u32,
+}
+
+/// A connector that a panel is attached to, the kernel's `struct drm_connector`.
+///
+/// # Invariants
+///
+/// The connector is valid while references to it exist.
+#[repr(transparent)]
+pub struct Connector(Opaque<bindings::drm_connector>);
+
+impl Connector {
+    /// Returns the raw `struct drm_connector` pointer.
+    pub fn as_raw(&self) -> *mut bindings::drm_connector {
+        self.0.get()
+    }
+
+    /// Adds `mode` to the modes of the connector, as the preferred one if `preferred` is `true`.
+    pub fn add_mode(&self, mode: &DisplayMode, preferred: bool) -> Result {
+        // SAFETY: All other fields are computed from the timings, for which zero is valid.
+        let mut raw: bindings::drm_display_mode = unsafe { MaybeUninit::zeroed().assume_init() };
+        raw.clock = mode.clock as _;
+        raw.hdisplay = mode.hdisplay;
+        raw.hsync_start = mode.hsync_start;
+        raw.hsync_end = mode.hsync_end;
+        raw.htotal = mode.htotal;
+        raw.vdisplay = mode.vdisplay;
+        raw.vsync_start = mode.vsync_start;
+        raw.vsync_end = mode.vsync_end;
+        raw.vtotal = mode.vtotal;
+        raw.flags = mode.flags;
+
+        // SAFETY: The connector is valid by the type invariants, and `raw` is copied.
+        let new = unsafe { bindings::drm_mode_duplicate((*self.as_raw()).dev, &raw) };
+        if new.is_null() {
+            return Err(ENOMEM);
+        }
+        // SAFETY: `new` was just allocated, and the connector takes ownership of it.
+        unsafe {
+            bindings::drm_mode_set_name(new);
+            (*new).type_ = bindings::DRM_MODE_TYPE_DRIVER;
+            if preferred {
+                (*new).type_ |= bindings::DRM_MODE_TYPE_PREFERRED;
+            }
+            bindings::drm_mode_probed_add(self.as_raw(), new);
+        }
+        Ok(())
+    }
+
+    /// Sets the physical size of the display, in millimetres.
+    pub fn set_physical_size(&self, width_mm: u32, height_mm: u32) {
+        // SAFETY: The connector is valid by the type invariants, and its display information is
+        // only updated while probing its modes, from which this is called.
+        unsafe {
+            (*self.as_raw()).display_info.width_mm = width_mm;
+            (*self.as_raw()).display_info.height_mm = height_mm;
+        }
+    }
+}
+
+/// The operations of a panel, the kernel's `struct drm_panel_funcs`.
+///
+/// The driver data of the panel implements this trait. The display driver calls
+/// [`Operations::prepare`] and [`Operations::enable`] before scanout starts, and
+/// [`Operations::disable`] and [`Operations::unprepare`] after it stops.
+#[vtable]
+pub trait Operations: Send + Sync + Sized + 'static {
+    /// Powers the panel up, before the display driver starts sending video data.
+    fn prepare(_panel: &Panel<Self>) -> Result {
+        Ok(())
+    }
+
+    /// Turns the display on, e.g. its backlight, once video data is sent.
+    fn enable(_panel: &Panel<Self>) -> Result {
+        Ok(())
+    }
+
+    /// Turns the display off, before the display driver stops sending video data.
+    fn disable(_panel: &Panel<Self>) -> Result {
+        Ok(())
+    }
+
+    /// Powers the panel down, once the display driver stopped sending video data.
+    fn unprepare(_panel: &Panel<Self>) -> Result {
+        Ok(())
+    }
+
+    /// Adds the modes of the panel to `connector`, and returns their number.
+    fn get_modes(panel: &Panel<Self>, connector: &Connector) -> Result<u32>;
+}
+
+/// A registered panel, the kernel's `struct drm_panel`.
+///
+/// The panel is removed when this is dropped, and display drivers can't find it anymore.
+///
+/// # Invariants
+///
+/// `panel` is initialised and added.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{device::Device, prelude::*};
+/// use kernel::drm::panel::{self, Connector, DisplayMode, Operations, Panel};
+///
+/// const MODE: DisplayMode = DisplayMode {
+///     clock: 68000,
+///     hdisplay: 1280,
+///     hsync_start: 1280 + 48,
+///     hsync_end: 1280 + 48 + 32,
+///     htotal: 1280 + 48 + 32 + 80,
+///     vdisplay: 800,
+///     vsync_start: 800 + 3,
+///     vsync_end: 800 + 3 + 6,
+///     vtotal: 800 + 3 + 6 + 14,
+///     flags: 0,
+/// };
+///
+/// struct Lvds;
+///
+/// #[vtable]
+/// impl Operations for Lvds {
+///     fn get_modes(_panel: &Panel<Self>, connector: &Connector) -> Result<u32> {
+///         connector.add_mode(&MODE, true)?;
+///         connector.set_physical_size(217, 136);
+///         Ok(1)
+///     }
+/// }
+///
+/// fn probe(dev: &Device) -> Result<Pin<Box<Panel<Lvds>>>> {
+///     Panel::register(dev, panel::connector_type::LVDS, Lvds)
+/// }
+/// ```
+#[repr(C)]
+pub struct Panel<T: Operations> {
+    // Must be the first field, so that the callbacks can find the panel.
+    panel: Opaque<bindings::drm_panel>,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: Operations> Panel<T> {
+    const FUNCS: bindings::drm_panel_funcs = bindings::drm_panel_funcs {
+        prepare: if T::HAS_PREPARE {
+            Some(Self::prepare_callback)
+        } else {
+            None
+        },
+        enable: if T::HAS_ENABLE {
+            Some(Self::enable_callback)
+        } else {
+            None
+        },
+        disable: if T::HAS_DISABLE {
+            Some(Self::disable_callback)
+        } else {
+            None
+        },
+        unprepare: if T::HAS_UNPREPARE {
+            Some(Self::unprepare_callback)
+        } else {
+            None
+        },
+        get_modes: Some(Self::get_modes_callback),
+        // SAFETY: All other fields are optional, for which zero is valid.
+        ..unsafe { MaybeUninit::zeroed().assume_init() }
+    };
+
+    /// Registers a panel of `dev`, attached with a connector of `connector_type`, with the driver
+    /// data `data`.
+    ///
+    /// Display drivers find the panel through the devicetree node of `dev`.
+    pub fn register(dev: &device::Device, connector_type: u32, data: T) -> Result<Pin<Box<Self>>> {
+        let panel = Pin::from(Box::try_new(Self {
+            // SAFETY: The panel is initialised by `drm_panel_init` below.
+            panel: Opaque::new(unsafe { MaybeUninit::zeroed().assume_init() }),
+            data,
+            _pin: PhantomPinned,
+        })?);
+        // INVARIANT: The panel is initialised and added here.
+        // SAFETY: `panel` is pinned, and removed before it is freed. `dev` outlives the panel,
+        // since its driver drops the panel when it is unbound.
+        unsafe {
+            bindings::drm_panel_init(
+                panel.panel.get(),
+                dev.as_raw(),
+                &Self::FUNCS,
+                connector_type as _,
+            );
+            bindings::drm_panel_add(panel.panel.get());
+        }
+        Ok(panel)
+    }
+
+    /// Returns the raw `struct drm_panel` pointer.
+    pub fn as_raw(&self) -> *mut bindings::drm_panel {
+        self.panel.get()
+    }
+
+    /// Returns the driver data of the panel.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    /// Returns the device of the panel.
+    pub fn dev(&self) -> &device::Device {
+        // SAFETY: The panel is initialised by the type invariants, and its device outlives it.
+        unsafe { device::Device::as_ref((*self.as_raw()).dev) }
+    }
+
+    /// Returns the panel of `panel`.
+    ///
+    /// # Safety
+    ///
+    /// `panel` must be the `panel` field of a valid `Panel<T>`.
+    unsafe fn from_raw<'a>(panel: *mut bindings::drm_panel) -> &'a Self {
+        // SAFETY: `panel` is the first field of `Panel<T>`, which is `repr(C)`.
+        unsafe { &*panel.cast() }
+    }
+
+    unsafe extern "C" fn prepare_callback(panel: *mut bindings::drm_panel) -> c_int {
+        from_result(|| {
+            // SAFETY: The DRM core calls this with a panel registered by `register`.
+            T::prepare(unsafe { Self::from_raw(panel) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn enable_callback(panel: *mut bindings::drm_panel) -> c_int {
+        from_result(|| {
+            // SAFETY: The DRM core calls this with a panel registered by `register`.
+            T::enable(unsafe { Self::from_raw(panel) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn disable_callback(panel: *mut bindings::drm_panel) -> c_int {
+        from_result(|| {
+            // SAFETY: The DRM core calls this with a panel registered by `register`.
+            T::disable(unsafe { Self::from_raw(panel) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn unprepare_callback(panel: *mut bindings::drm_panel) -> c_int {
+        from_result(|| {
+            // SAFETY: The DRM core calls this with a panel registered by `register`.
+            T::unprepare(unsafe { Self::from_raw(panel) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn get_modes_callback(
+        panel: *mut bindings::drm_panel,
+        connector: *mut bindings::drm_connector,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The DRM core calls this with a panel registered by `register`, and the
+            // connector it is attached to, whose modes are being probed. `Connector` is
+            // transparent.
+            let (panel, connector) =
+                unsafe { (Self::from_raw(panel), &*connector.cast::<Connector>()) };
+            Ok(T::get_modes(panel, connector)? as _)
+        })
+    }
+}
+
+impl<T: Operations> Drop for Panel<T> {
+    fn drop(&mut self) {
+        // SAFETY: The panel was added by the type invariants.
+        unsafe { bindings::drm_panel_remove(self.as_raw()) };
+    }
+}
+
+// SAFETY: The panel can be removed from any thread, and the driver data is `Send`.
+unsafe impl<T: Operations> Send for Panel<T> {}
+
+// SAFETY: The methods that take `&self` only read fields that never change, and the driver data
+// is `Sync`.
+unsafe impl<T: Operations> Sync for Panel<T> {}
@@ -0,0 +1,195 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! EFI runtime services.
+//!
+//! On EFI platforms, the firmware keeps providing runtime services once the kernel is booted,
+//! e.g. to read and write the variables stored in its non-volatile memory. They are only
+//! available if the kernel was booted through EFI and the firmware didn't disable them, which
+//! [`runtime_services_supported`] reports.
+//!
+//! C header: [`include/linux/efi.h`](../../../../include/linux/efi.h)
+
+use crate::{
+    bindings,
+    error::{code::*, to_result, Result},
+};
+use alloc::vec::Vec;
+use core::ptr;
+
+/// Runtime services, the kernel's `EFI_RT_SUPPORTED_*` values.
+pub mod rt {
+    /// `GetTime()`.
+    pub const GET_TIME: u32 = crate::bindings::EFI_RT_SUPPORTED_GET_TIME;
+    /// `SetTime()`.
+    pub const SET_TIME: u32 = crate::bindings::EFI_RT_SUPPORTED_SET_TIME;
+    /// `GetVariable()`.
+    pub const GET_VARIABLE: u32 = crate::bindings::EFI_RT_SUPPORTED_GET_VARIABLE;
+    /// `GetNextVariableName()`.
+    pub const GET_NEXT_VARIABLE_NAME: u32 =
+        crate::bindings::EFI_RT_SUPPORTED_GET_NEXT_VARIABLE_NAME;
+    /// `SetVariable()`.
+    pub const SET_VARIABLE: u32 = crate::bindings::EFI_RT_SUPPORTED_SET_VARIABLE;
+    /// `QueryVariableInfo()`.
+    pub const QUERY_VARIABLE_INFO: u32 = crate::bindings::EFI_RT_SUPPORTED_QUERY_VARIABLE_INFO;
+    /// `ResetSystem()`.
+    pub const RESET_SYSTEM: u32 = crate::bindings::EFI_RT_SUPPORTED_RESET_SYSTEM;
+}
+
+/// Attributes of variables, the kernel's `EFI_VARIABLE_*` values.
+pub mod attr {
+    /// The variable is stored in non-volatile memory.
+    pub const NON_VOLATILE: u32 = crate::bindings::EFI_VARIABLE_NON_VOLATILE;
+    /// The variable is accessible before `ExitBootServices()`.
+    pub const BOOTSERVICE_ACCESS: u32 = crate::bindings::EFI_VARIABLE_BOOTSERVICE_ACCESS;
+    /// The variable is accessible through the runtime services.
+    pub const RUNTIME_ACCESS: u32 = crate::bindings::EFI_VARIABLE_RUNTIME_ACCESS;
+    /// The variable can only be written with a time-based authentication.
+    pub const TIME_BASED_AUTHENTICATED_WRITE_ACCESS: u32 =
+        crate::bindings::EFI_VARIABLE_TIME_BASED_AUTHENTICATED_WRITE_ACCESS;
+}
+
+/// Returns whether the runtime services in `mask`, a combination of [`rt`] values, are supported.
+///
+/// They aren't if the kernel wasn't booted through EFI, if they are disabled with `noefi` or
+/// `efi=noruntime`, or if the firmware reported them as unsupported.
+pub fn runtime_services_supported(mask: u32) -> bool {
+    // SAFETY: These only read global state set at boot.
+    unsafe {
+        bindings::efi_enabled(bindings::EFI_RUNTIME_SERVICES as _)
+            && bindings::efi_rt_services_supported(mask)
+    }
+}
+
+/// Returns the revision of the runtime services, e.g. `0x0002_0046` for UEFI 2.70.
+///
+/// It is zero if the kernel wasn't booted through EFI.
+pub fn runtime_version() -> u32 {
+    // SAFETY: `efi` is set at boot, and not changed afterwards.
+    unsafe { bindings::efi.runtime_version as _ }
+}
+
+/// The GUID of the vendor of a variable, the kernel's `efi_guid_t`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Guid(bindings::efi_guid_t);
+
+impl Guid {
+    /// The vendor of the variables defined by the UEFI specification, e.g. `SecureBoot`.
+    pub const GLOBAL_VARIABLE: Self = Self::new(
+        0x8be4df61,
+        0x93ca,
+        0x11d2,
+        [0xaa, 0x0d, 0x00, 0xe0, 0x98, 0x03, 0x2b, 0x8c],
+    );
+
+    /// Creates the GUID `a-b-c-d`, like the kernel's `EFI_GUID()`.
+    pub const fn new(a: u32, b: u16, c: u16, d: [u8; 8]) -> Self {
+        let a = a.to_le_bytes();
+        let b = b.to_le_bytes();
+        let c = c.to_le_bytes();
+        Self(bindings::efi_guid_t {
+            b: [
+                a[0], a[1], a[2], a[3], b[0], b[1], c[0], c[1], d[0], d[1], d[2], d[3], d[4], d[5],
+                d[6], d[7],
+            ],
+        })
+    }
+}
+
+/// Converts `name` to a null-terminated UCS-2 string, as expected by the firmware.
+fn ucs2_name(name: &str) -> Result<Vec<u16>> {
+    let mut ucs2 = Vec::try_with_capacity(name.len() + 1)?;
+    for c in name.chars() {
+        // Characters outside of the BMP can't be encoded.
+        ucs2.try_push(u16::try_from(u32::from(c)).map_err(|_| EINVAL)?)?;
+    }
+    ucs2.try_push(0)?;
+    Ok(ucs2)
+}
+
+/// Reads the variable `name` of the vendor `vendor` to `data`, with the lock of the variables held.
+///
+/// # Safety
+///
+/// `data` must be null if `size` is zero, and valid for writes of `size` bytes otherwise.
+unsafe fn get_variable_raw(
+    name: &str,
+    vendor: &Guid,
+    attributes: &mut u32,
+    size: &mut usize,
+    data: *mut u8,
+) -> Result {
+    crate::might_sleep!();
+    if !runtime_services_supported(rt::GET_VARIABLE) {
+        return Err(EOPNOTSUPP);
+    }
+    let mut name = ucs2_name(name)?;
+    let mut vendor = vendor.0;
+    let mut raw_size: core::ffi::c_ulong = *size as _;
+    // SAFETY: The lock is released below.
+    to_result(unsafe { bindings::efivar_lock() })?;
+    // SAFETY: The lock is held, and the name, vendor, attributes and size are valid for the
+    // duration of the call. `data` is valid for writes of `size` bytes by the safety
+    // requirements.
+    let status = unsafe {
+        bindings::efivar_get_variable(
+            name.as_mut_ptr(),
+            &mut vendor,
+            attributes,
+            &mut raw_size,
+            data.cast(),
+        )
+    };
+    // SAFETY: The lock was taken above.
+    unsafe { bindings::efivar_unlock() };
+    *size = raw_size as _;
+    // SAFETY: `efi_status_to_err` has no safety requirements.
+    to_result(unsafe { bindings::efi_status_to_err(status) })
+}
+
+/// Reads the variable `name` of the vendor `vendor` to `buf`.
+///
+/// Returns the attributes of the variable, a combination of [`attr`] values, and its size. Fails
+/// with `ENOENT` if the variable doesn't exist, with `ENOSPC` if it doesn't fit in `buf`, and with
+/// `EOPNOTSUPP` if the runtime services aren't available.
+///
+/// This may sleep.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{efi, prelude::*};
+///
+/// fn secure_boot_enabled() -> Result<bool> {
+///     let mut value = [0u8; 1];
+///     let (_, size) = efi::get_variable("SecureBoot", &efi::Guid::GLOBAL_VARIABLE, &mut value)?;
+///     Ok(size == 1 && value[0] == 1)
+/// }
+/// ```
+pub fn get_variable(name: &str, vendor: &Guid, buf: &mut [u8]) -> Result<(u32, usize)> {
+    let mut attributes = 0;
+    let mut size = buf.len();
+    let data = if buf.is_empty() {
+        ptr::null_mut()
+    } else {
+        buf.as_mut_ptr()
+    };
+    // SAFETY: `data` is null if `buf` is empty, and valid for writes of its length otherwise.
+    unsafe { get_variable_raw(name, vendor, &mut attributes, &mut size, data) }?;
+    Ok((attributes, size))
+}
+
+/// Returns the size of the variable `name` of the vendor `vendor`.
+///
+/// Fails like [`get_variable`]. This may sleep.
+pub fn variable_size(name: &str, vendor: &Guid) -> Result<usize> {
+    let mut attributes = 0;
+    let mut size = 0;
+    // SAFETY: `data` is null and `size` is zero.
+    match unsafe { get_variable_raw(name, vendor, &mut attributes, &mut size, ptr::null_mut()) } {
+        // The firmware reports the size of the variable if the buffer is too small.
+        Err(e) if e == ENOSPC => Ok(size),
+        Err(e) => Err(e),
+        // Variables can't be empty, but some firmwares report them anyway.
+        Ok(()) => Ok(size),
+    }
+}
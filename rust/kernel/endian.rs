@@ -0,0 +1,233 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Integers with an explicit byte order.
+//!
+//! Hardware descriptors and wire formats store integers in a fixed byte order, which the kernel
+//! types as `__le32`, `__be16`, etc. The types of this module, e.g. [`Le32`] and [`Be16`], have
+//! the same layout as these: fields of `repr(C)` structures read from devices, from userspace
+//! with [`IoBufferReader::read`](crate::io_buffer::IoBufferReader::read) or from DMA memory can be
+//! declared with them, and are converted to native integers when they are accessed.
+//!
+//! Unaligned fields, e.g. of packed network headers, are read and written in byte buffers with
+//! [`Le32::get_unaligned`] and [`Le32::put_unaligned`], and the equivalents of the other types.
+//!
+//! C header: [`include/linux/byteorder/generic.h`](../../../../include/linux/byteorder/generic.h)
+//!
+//! # Examples
+//!
+//! ```
+//! use kernel::endian::{Be16, Le16, Le32};
+//! use kernel::io_buffer::ReadableFromBytes;
+//! use kernel::prelude::*;
+//!
+//! // A descriptor of a DMA engine.
+//! #[repr(C)]
+//! struct Descriptor {
+//!     addr: Le32,
+//!     len: Le16,
+//!     flags: Le16,
+//! }
+//!
+//! // SAFETY: All the fields are `ReadableFromBytes`, and there is no padding.
+//! unsafe impl ReadableFromBytes for Descriptor {}
+//!
+//! const DESC_DONE: Le16 = Le16::new(1 << 15);
+//!
+//! fn done(desc: &Descriptor) -> Option<usize> {
+//!     ((desc.flags & DESC_DONE) == DESC_DONE).then(|| desc.len.get().into())
+//! }
+//!
+//! // The EtherType of a frame, at an odd offset in a tagged frame.
+//! fn ethertype(frame: &[u8], offset: usize) -> Result<u16> {
+//!     Ok(Be16::get_unaligned(frame, offset)?.get())
+//! }
+//! ```
+
+use crate::{
+    error::{code::*, Result},
+    io_buffer::{ReadableFromBytes, WritableToBytes},
+};
+use core::{
+    fmt,
+    mem::size_of,
+    ops::{Add, AddAssign, BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, Not, Sub, SubAssign},
+};
+
+macro_rules! define_endian {
+    ($(#[$doc:meta])* $name:ident, $native:ty, $to:ident, $from:ident) => {
+        $(#[$doc])*
+        #[repr(transparent)]
+        #[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+        pub struct $name($native);
+
+        impl $name {
+            /// Creates a value holding `value`, which is in native byte order.
+            pub const fn new(value: $native) -> Self {
+                Self(value.$to())
+            }
+
+            /// Returns the value, in native byte order.
+            pub const fn get(self) -> $native {
+                <$native>::$from(self.0)
+            }
+
+            /// Sets the value to `value`, which is in native byte order.
+            pub fn set(&mut self, value: $native) {
+                *self = Self::new(value);
+            }
+
+            /// Creates a value from its representation in memory.
+            pub const fn from_bytes(bytes: [u8; size_of::<$native>()]) -> Self {
+                Self(<$native>::from_ne_bytes(bytes))
+            }
+
+            /// Returns the representation of the value in memory.
+            pub const fn to_bytes(self) -> [u8; size_of::<$native>()] {
+                self.0.to_ne_bytes()
+            }
+
+            /// Reads the value at `offset` in `buf`, which needn't be aligned.
+            ///
+            /// Fails with `EINVAL` if the value is out of `buf`.
+            pub fn get_unaligned(buf: &[u8], offset: usize) -> Result<Self> {
+                let end = offset.checked_add(size_of::<$native>()).ok_or(EINVAL)?;
+                let bytes = buf.get(offset..end).ok_or(EINVAL)?;
+                // The length of `bytes` is the size of the value.
+                Ok(Self::from_bytes(bytes.try_into().map_err(|_| EINVAL)?))
+            }
+
+            /// Writes the value at `offset` in `buf`, which needn't be aligned.
+            ///
+            /// Fails with `EINVAL` if the value is out of `buf`.
+            pub fn put_unaligned(self, buf: &mut [u8], offset: usize) -> Result {
+                let end = offset.checked_add(size_of::<$native>()).ok_or(EINVAL)?;
+                buf.get_mut(offset..end)
+                    .ok_or(EINVAL)?
+                    .copy_from_slice(&self.to_bytes());
+                Ok(())
+            }
+        }
+
+        impl From<$native> for $name {
+            fn from(value: $native) -> Self {
+                Self::new(value)
+            }
+        }
+
+        impl From<$name> for $native {
+            fn from(value: $name) -> Self {
+                value.get()
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Debug::fmt(&self.get(), f)
+            }
+        }
+
+        impl Add for $name {
+            type Output = Self;
+
+            fn add(self, rhs: Self) -> Self {
+                Self::new(self.get() + rhs.get())
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, rhs: Self) {
+                *self = *self + rhs;
+            }
+        }
+
+        impl Sub for $name {
+            type Output = Self;
+
+            fn sub(self, rhs: Self) -> Self {
+                Self::new(self.get() - rhs.get())
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, rhs: Self) {
+                *self = *self - rhs;
+            }
+        }
+
+        // Bitwise operations don't depend on the byte order, so they are done on the raw values.
+        impl BitAnd for $name {
+            type Output = Self;
+
+            fn bitand(self, rhs: Self) -> Self {
+                Self(self.0 & rhs.0)
+            }
+        }
+
+        impl BitAndAssign for $name {
+            fn bitand_assign(&mut self, rhs: Self) {
+                self.0 &= rhs.0;
+            }
+        }
+
+        impl BitOr for $name {
+            type Output = Self;
+
+            fn bitor(self, rhs: Self) -> Self {
+                Self(self.0 | rhs.0)
+            }
+        }
+
+        impl BitOrAssign for $name {
+            fn bitor_assign(&mut self, rhs: Self) {
+                self.0 |= rhs.0;
+            }
+        }
+
+        impl BitXor for $name {
+            type Output = Self;
+
+            fn bitxor(self, rhs: Self) -> Self {
+                Self(self.0 ^ rhs.0)
+            }
+        }
+
+        impl Not for $name {
+            type Output = Self;
+
+            fn not(self) -> Self {
+                Self(!self.0)
+            }
+        }
+
+        // SAFETY: The value is an integer, for which all bit patterns are valid.
+        unsafe impl ReadableFromBytes for $name {}
+
+        // SAFETY: The value is an integer, which has no uninitialised bytes.
+        unsafe impl WritableToBytes for $name {}
+    };
+}
+
+define_endian!(
+    /// A little-endian 16-bit integer, the kernel's `__le16`.
+    Le16, u16, to_le, from_le
+);
+define_endian!(
+    /// A little-endian 32-bit integer, the kernel's `__le32`.
+    Le32, u32, to_le, from_le
+);
+define_endian!(
+    /// A little-endian 64-bit integer, the kernel's `__le64`.
+    Le64, u64, to_le, from_le
+);
+define_endian!(
+    /// A big-endian 16-bit integer, the kernel's `__be16`.
+    Be16, u16, to_be, from_be
+);
+define_endian!(
+    /// A big-endian 32-bit integer, the kernel's `__be32`.
+    Be32, u32, to_be, from_be
+);
+define_endian!(
+    /// A big-endian 64-bit integer, the kernel's `__be64`.
+    Be64, u64, to_be, from_be
+);
@@ -13,7 +13,7 @@
 
 use core::convert::From;
 use core::fmt;
-use core::num::TryFromIntError;
+use core::num::{NonZeroI32, TryFromIntError};
 use core::str::Utf8Error;
 
 /// Contains the C-compatible error codes.
@@ -24,7 +24,11 @@ macro_rules! declare_err {
             $(
             #[doc = $doc]
             )*
-            pub const $err: super::Error = super::Error(-(crate::bindings::$err as i32));
+            pub const $err: super::Error =
+                match super::Error::try_from_errno(-(crate::bindings::$err as i32)) {
+                    Some(err) => err,
+                    None => panic!("Invalid errno in `declare_err!`"),
+                };
         };
     }
 
@@ -91,27 +95,52 @@ macro_rules! declare_err {
 /// # Invariants
 ///
 /// The value is a valid `errno` (i.e. `>= -MAX_ERRNO && < 0`).
+///
+/// As the value is never zero, a [`Result`] that holds no value in the success case is no larger
+/// than the error code itself.
 #[derive(Clone, Copy, PartialEq, Eq)]
-pub struct Error(core::ffi::c_int);
+pub struct Error(NonZeroI32);
+
+crate::static_assert!(core::mem::size_of::<Result>() == core::mem::size_of::<core::ffi::c_int>());
 
 impl Error {
+    /// Creates an [`Error`] from a kernel error code, if it is one.
+    ///
+    /// Returns [`None`] if `errno` is out of range, i.e. not `>= -MAX_ERRNO && < 0`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kernel::error::{code::*, Error};
+    /// assert_eq!(Error::try_from_errno(-22), Some(EINVAL));
+    /// assert_eq!(Error::try_from_errno(0), None);
+    /// assert_eq!(Error::try_from_errno(22), None);
+    /// assert_eq!(Error::try_from_errno(-4096), None);
+    /// ```
+    pub const fn try_from_errno(errno: core::ffi::c_int) -> Option<Error> {
+        if errno < -(bindings::MAX_ERRNO as i32) || errno >= 0 {
+            return None;
+        }
+        // INVARIANT: The check above ensures the type invariant will hold.
+        match NonZeroI32::new(errno) {
+            Some(errno) => Some(Error(errno)),
+            None => None,
+        }
+    }
+
     /// Creates an [`Error`] from a kernel error code.
     ///
     /// It is a bug to pass an out-of-range `errno`. `EINVAL` would
     /// be returned in such a case.
     pub(crate) fn from_errno(errno: core::ffi::c_int) -> Error {
-        if errno < -(bindings::MAX_ERRNO as i32) || errno >= 0 {
+        Self::try_from_errno(errno).unwrap_or_else(|| {
             // TODO: Make it a `WARN_ONCE` once available.
             crate::pr_warn!(
                 "attempted to create `Error` with out of range `errno`: {}",
                 errno
             );
-            return code::EINVAL;
-        }
-
-        // INVARIANT: The check above ensures the type invariant
-        // will hold.
-        Error(errno)
+            code::EINVAL
+        })
     }
 
     /// Creates an [`Error`] from a kernel error code.
@@ -122,26 +151,27 @@ pub(crate) fn from_errno(errno: core::ffi::c_int) -> Error {
     unsafe fn from_errno_unchecked(errno: core::ffi::c_int) -> Error {
         // INVARIANT: The contract ensures the type invariant
         // will hold.
-        Error(errno)
+        // SAFETY: The contract ensures that `errno` is negative, so non-zero.
+        Error(unsafe { NonZeroI32::new_unchecked(errno) })
     }
 
     /// Returns the kernel error code.
-    pub fn to_errno(self) -> core::ffi::c_int {
-        self.0
+    pub const fn to_errno(self) -> core::ffi::c_int {
+        self.0.get()
     }
 
     /// Returns the error encoded as a pointer.
     #[allow(dead_code)]
     pub(crate) fn to_ptr<T>(self) -> *mut T {
         // SAFETY: self.0 is a valid error due to its invariant.
-        unsafe { bindings::ERR_PTR(self.0.into()) as *mut _ }
+        unsafe { bindings::ERR_PTR(self.to_errno().into()) as *mut _ }
     }
 
     /// Returns a string representing the error, if one exists.
     #[cfg(not(testlib))]
     pub fn name(&self) -> Option<&'static CStr> {
         // SAFETY: Just an FFI call, there are no extra safety requirements.
-        let ptr = unsafe { bindings::errname(-self.0) };
+        let ptr = unsafe { bindings::errname(-self.to_errno()) };
         if ptr.is_null() {
             None
         } else {
@@ -165,7 +195,7 @@ impl fmt::Debug for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self.name() {
             // Print out number if no name can be found.
-            None => f.debug_tuple("Error").field(&-self.0).finish(),
+            None => f.debug_tuple("Error").field(&-self.to_errno()).finish(),
             // SAFETY: These strings are ASCII-only.
             Some(name) => f
                 .debug_tuple(unsafe { core::str::from_utf8_unchecked(name) })
@@ -0,0 +1,229 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! External connectors.
+//!
+//! Drivers of connectors, e.g. of a micro-USB port, register a [`Provider`] and report the
+//! cables that are attached to it, and consumers, e.g. USB or charger drivers, look it up with
+//! [`Device::get_by_phandle`] and read or follow the states of the cables.
+//!
+//! C header: [`include/linux/extcon.h`](../../../../include/linux/extcon.h)
+
+use crate::{
+    bindings, device,
+    error::{code::*, from_err_ptr, to_result, Result},
+    notifier,
+    types::Opaque,
+};
+use core::ptr::NonNull;
+
+/// Types of cables, the kernel's `EXTCON_*` values.
+pub mod cable {
+    /// No cable, which terminates lists of cables.
+    pub const NONE: u32 = crate::bindings::EXTCON_NONE;
+    /// A USB host, which the device is a peripheral of.
+    pub const USB: u32 = crate::bindings::EXTCON_USB;
+    /// A USB peripheral, which the device is the host of, e.g. through an OTG adapter.
+    pub const USB_HOST: u32 = crate::bindings::EXTCON_USB_HOST;
+    /// A USB standard downstream port, which charges with up to 500 mA.
+    pub const CHG_USB_SDP: u32 = crate::bindings::EXTCON_CHG_USB_SDP;
+    /// A USB dedicated charging port.
+    pub const CHG_USB_DCP: u32 = crate::bindings::EXTCON_CHG_USB_DCP;
+    /// A USB charging downstream port.
+    pub const CHG_USB_CDP: u32 = crate::bindings::EXTCON_CHG_USB_CDP;
+    /// A USB accessory charger adapter.
+    pub const CHG_USB_ACA: u32 = crate::bindings::EXTCON_CHG_USB_ACA;
+    /// A fast charger.
+    pub const CHG_USB_FAST: u32 = crate::bindings::EXTCON_CHG_USB_FAST;
+    /// A slow charger.
+    pub const CHG_USB_SLOW: u32 = crate::bindings::EXTCON_CHG_USB_SLOW;
+    /// Headphones.
+    pub const JACK_HEADPHONE: u32 = crate::bindings::EXTCON_JACK_HEADPHONE;
+    /// An HDMI display.
+    pub const DISP_HDMI: u32 = crate::bindings::EXTCON_DISP_HDMI;
+}
+
+/// An external connector, the kernel's `struct extcon_dev`.
+///
+/// # Invariants
+///
+/// The connector is valid while references to it exist.
+#[repr(transparent)]
+pub struct Device(Opaque<bindings::extcon_dev>);
+
+impl Device {
+    /// Creates a reference to a [`Device`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is a registered connector for the lifetime of the returned
+    /// reference.
+    unsafe fn from_raw<'a>(ptr: *mut bindings::extcon_dev) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct extcon_dev` pointer.
+    pub fn as_raw(&self) -> *mut bindings::extcon_dev {
+        self.0.get()
+    }
+
+    /// Returns the connector referred to by the `extcon` property of the devicetree node of
+    /// `dev`, at the position `index`.
+    ///
+    /// Fails with `EPROBE_DEFER` if the connector isn't registered yet. The connector is
+    /// device-managed by its provider, which the device link created by the driver core keeps
+    /// bound while the driver of `dev` is.
+    pub fn get_by_phandle(dev: &device::Device, index: u32) -> Result<&Self> {
+        // SAFETY: `dev` is valid.
+        let edev = from_err_ptr(unsafe {
+            bindings::extcon_get_edev_by_phandle(dev.as_raw(), index as _)
+        })?;
+        // SAFETY: The connector was found above, and is registered while `dev` is bound.
+        Ok(unsafe { Self::from_raw(edev) })
+    }
+
+    /// Returns whether the cable `id` is attached, see [`cable`].
+    ///
+    /// Fails with `EINVAL` if the connector doesn't support the cable.
+    pub fn get_state(&self, id: u32) -> Result<bool> {
+        // SAFETY: The connector is valid by the type invariants.
+        let ret = unsafe { bindings::extcon_get_state(self.as_raw(), id) };
+        to_result(ret)?;
+        Ok(ret != 0)
+    }
+
+    /// Returns the notifier chain of the cable `id`, which handlers can be added to with a
+    /// [`notifier::Registration`].
+    ///
+    /// The handlers are called with whether the cable is attached as the action, in atomic
+    /// context.
+    pub fn cable(&self, id: u32) -> Cable<'_> {
+        Cable { dev: self, id }
+    }
+}
+
+// SAFETY: The states of the cables are protected by the lock of the connector.
+unsafe impl Send for Device {}
+
+// SAFETY: The states of the cables are protected by the lock of the connector.
+unsafe impl Sync for Device {}
+
+/// The notifier chain of a cable of a connector, returned by [`Device::cable`].
+///
+/// # Examples
+///
+/// ```
+/// use core::ffi::{c_ulong, c_void};
+/// use kernel::{device, extcon, notifier, prelude::*};
+///
+/// struct Vbus;
+///
+/// impl notifier::Handler for Vbus {
+///     fn notify(&self, attached: c_ulong, _data: *mut c_void) -> Result<notifier::Notify> {
+///         pr_info!("USB host {}\n", if attached != 0 { "attached" } else { "detached" });
+///         Ok(notifier::Notify::Ok)
+///     }
+/// }
+///
+/// fn listen<'a>(
+///     cable: &'a extcon::Cable<'a>,
+/// ) -> Result<notifier::Registration<'a, extcon::Cable<'a>, Vbus>> {
+///     notifier::Registration::try_new(cable, Vbus, 0)
+/// }
+///
+/// fn probe(dev: &device::Device) -> Result {
+///     let edev = extcon::Device::get_by_phandle(dev, 0)?;
+///     let cable = edev.cable(extcon::cable::USB);
+///     let _reg = listen(&cable)?;
+///     Ok(())
+/// }
+/// ```
+pub struct Cable<'a> {
+    dev: &'a Device,
+    id: u32,
+}
+
+impl notifier::Head for Cable<'_> {
+    unsafe fn register(&self, nb: *mut bindings::notifier_block) -> Result {
+        // SAFETY: The connector is valid, and the caller guarantees that `nb` is valid.
+        to_result(unsafe { bindings::extcon_register_notifier(self.dev.as_raw(), self.id, nb) })
+    }
+
+    unsafe fn unregister(&self, nb: *mut bindings::notifier_block) {
+        // SAFETY: The connector is valid, and the caller guarantees that `nb` is on the chain.
+        unsafe { bindings::extcon_unregister_notifier(self.dev.as_raw(), self.id, nb) };
+    }
+}
+
+/// A registered external connector.
+///
+/// The connector is device-managed: it is unregistered and freed when its parent is unbound from
+/// its driver, which must drop this before.
+///
+/// # Invariants
+///
+/// `edev` is a connector registered by [`Provider::register`].
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{device, extcon::{self, cable, Provider}, prelude::*};
+///
+/// static CABLES: [u32; 3] = [cable::USB, cable::USB_HOST, cable::NONE];
+///
+/// fn probe(dev: &device::Device, id_grounded: bool) -> Result<Provider> {
+///     let extcon = Provider::register(dev, &CABLES)?;
+///     extcon.set_state(cable::USB_HOST, id_grounded)?;
+///     Ok(extcon)
+/// }
+/// ```
+pub struct Provider {
+    edev: NonNull<bindings::extcon_dev>,
+}
+
+impl Provider {
+    /// Registers a connector whose parent is `parent`, which supports the cables `cables`.
+    ///
+    /// The list of cables must be terminated by [`cable::NONE`], and fails with `EINVAL`
+    /// otherwise.
+    pub fn register(parent: &device::Device, cables: &'static [u32]) -> Result<Self> {
+        crate::might_sleep!();
+        if cables.last() != Some(&cable::NONE) {
+            return Err(EINVAL);
+        }
+
+        // SAFETY: `parent` is valid, and `cables` is static and terminated.
+        let edev = from_err_ptr(unsafe {
+            bindings::devm_extcon_dev_allocate(parent.as_raw(), cables.as_ptr())
+        })?;
+        // SAFETY: The connector was just allocated for `parent`.
+        to_result(unsafe { bindings::devm_extcon_dev_register(parent.as_raw(), edev) })?;
+        // INVARIANT: The connector was registered above.
+        Ok(Self {
+            // SAFETY: `devm_extcon_dev_allocate` never returns null on success.
+            edev: unsafe { NonNull::new_unchecked(edev) },
+        })
+    }
+
+    /// Returns the connector.
+    pub fn device(&self) -> &Device {
+        // SAFETY: The connector is registered by the type invariants.
+        unsafe { Device::from_raw(self.edev.as_ptr()) }
+    }
+
+    /// Sets whether the cable `id` is attached, and notifies the consumers and user space if it
+    /// changed.
+    ///
+    /// This may sleep.
+    pub fn set_state(&self, id: u32, attached: bool) -> Result {
+        crate::might_sleep!();
+        // SAFETY: The connector is registered by the type invariants.
+        to_result(unsafe { bindings::extcon_set_state_sync(self.edev.as_ptr(), id, attached) })
+    }
+}
+
+// SAFETY: The states of the cables are protected by the lock of the connector.
+unsafe impl Send for Provider {}
+
+// SAFETY: The states of the cables are protected by the lock of the connector.
+unsafe impl Sync for Provider {}
@@ -0,0 +1,169 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Fault injection.
+//!
+//! Fault injection makes selected operations fail on purpose, with a probability, interval and
+//! number of times that are configured at runtime, to test the error paths of their callers. The
+//! Rust abstractions inherit the fault injection of the C functions they call:
+//!
+//! - Allocations fail as set up in `failslab` in debugfs, with `CONFIG_FAILSLAB`.
+//! - Reads and writes of user slices fail as set up in `fail_usercopy`, with
+//!   `CONFIG_FAULT_INJECTION_USERCOPY`.
+//!
+//! DMA mappings of the Rust abstractions, which have no equivalent in C, fail as set up in
+//! [`DMA`]. Drivers declare a [`FaultAttr`] of their own to make other operations fail.
+//!
+//! C header: [`include/linux/fault-inject.h`](../../../../include/linux/fault-inject.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/fault-injection/fault-injection.html>
+
+#[cfg(CONFIG_FAULT_INJECTION)]
+use crate::{bindings, types::Opaque};
+#[cfg(CONFIG_FAULT_INJECTION_DEBUG_FS)]
+use crate::{
+    error::{from_err_ptr, Result},
+    str::CStr,
+};
+#[cfg(CONFIG_FAULT_INJECTION_DEBUG_FS)]
+use core::marker::PhantomData;
+
+/// The fault attributes of the DMA mappings of the Rust abstractions.
+///
+/// They never fail by default. Test modules expose them, e.g. as `fail_rust_dma`, with
+/// [`FaultAttr::create_debugfs`], to set them up.
+#[cfg(CONFIG_FAULT_INJECTION)]
+pub static DMA: FaultAttr = FaultAttr::new();
+
+/// Returns whether a DMA mapping of `size` bytes must fail, as set up in [`DMA`].
+///
+/// Drivers that map memory for DMA directly with the C API call this to make their mappings
+/// fail like those of the abstractions. It always returns `false` without
+/// `CONFIG_FAULT_INJECTION`.
+#[cfg_attr(not(CONFIG_FAULT_INJECTION), allow(unused_variables))]
+pub fn should_fail_dma(size: usize) -> bool {
+    #[cfg(CONFIG_FAULT_INJECTION)]
+    if DMA.should_fail(size) {
+        return true;
+    }
+    false
+}
+
+/// Fault attributes, the kernel's `struct fault_attr`.
+///
+/// They decide whether an operation fails, and never do until they are set up, e.g. through
+/// debugfs.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{fault_inject::FaultAttr, prelude::*};
+///
+/// static FAIL_RESET: FaultAttr = FaultAttr::new();
+///
+/// fn reset() -> Result {
+///     if FAIL_RESET.should_fail(1) {
+///         return Err(ETIMEDOUT);
+///     }
+///     // Reset the hardware.
+///     Ok(())
+/// }
+/// ```
+#[cfg(CONFIG_FAULT_INJECTION)]
+#[repr(transparent)]
+pub struct FaultAttr(Opaque<bindings::fault_attr>);
+
+#[cfg(CONFIG_FAULT_INJECTION)]
+impl FaultAttr {
+    /// Creates fault attributes that never fail, like `FAULT_ATTR_INITIALIZER` in C.
+    pub const fn new() -> Self {
+        // SAFETY: All fields are integers, atomics, pointers and locks, for which zero is valid.
+        let mut attr: bindings::fault_attr =
+            unsafe { core::mem::MaybeUninit::zeroed().assume_init() };
+        attr.interval = 1;
+        attr.times.counter = 1;
+        attr.require_end = core::ffi::c_ulong::MAX;
+        attr.stacktrace_depth = 32;
+        attr.verbose = 2;
+        // The rate limit is disabled by its zero interval, so its lock is never taken.
+        attr.ratelimit_state.burst = bindings::DEFAULT_RATELIMIT_BURST as _;
+        Self(Opaque::new(attr))
+    }
+
+    /// Returns the raw `struct fault_attr` pointer.
+    pub fn as_raw(&self) -> *mut bindings::fault_attr {
+        self.0.get()
+    }
+
+    /// Returns whether the operation on `size` bytes, or 1 if it has no size, must fail.
+    ///
+    /// This may be called from atomic context.
+    pub fn should_fail(&self, size: usize) -> bool {
+        // SAFETY: The attributes are valid, and `should_fail` synchronises their accesses.
+        unsafe { bindings::should_fail(self.as_raw(), size as _) }
+    }
+
+    /// Creates a directory named `name` at the top of debugfs, with the files that set the
+    /// attributes up, e.g. `probability` and `times`.
+    ///
+    /// The directory is removed when the returned object is dropped.
+    #[cfg(CONFIG_FAULT_INJECTION_DEBUG_FS)]
+    pub fn create_debugfs(&self, name: &CStr) -> Result<DebugfsDir<'_>> {
+        // SAFETY: The attributes are valid, and outlive the directory, which is removed when the
+        // returned object is dropped. A null parent means the debugfs root, and `name` is copied.
+        let dentry = from_err_ptr(unsafe {
+            bindings::fault_create_debugfs_attr(
+                name.as_char_ptr(),
+                core::ptr::null_mut(),
+                self.as_raw(),
+            )
+        })?;
+        // INVARIANT: The directory was created above, for `self`.
+        Ok(DebugfsDir {
+            dentry,
+            _p: PhantomData,
+        })
+    }
+}
+
+#[cfg(CONFIG_FAULT_INJECTION)]
+impl Default for FaultAttr {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// SAFETY: The attributes are only changed through debugfs, or atomically by `should_fail`.
+#[cfg(CONFIG_FAULT_INJECTION)]
+unsafe impl Send for FaultAttr {}
+
+// SAFETY: The attributes are only changed through debugfs, or atomically by `should_fail`.
+#[cfg(CONFIG_FAULT_INJECTION)]
+unsafe impl Sync for FaultAttr {}
+
+/// The debugfs directory of fault attributes, returned by [`FaultAttr::create_debugfs`].
+///
+/// # Invariants
+///
+/// `dentry` is a debugfs directory of fault attributes that outlive `'a`.
+#[cfg(CONFIG_FAULT_INJECTION_DEBUG_FS)]
+pub struct DebugfsDir<'a> {
+    dentry: *mut bindings::dentry,
+    _p: PhantomData<&'a FaultAttr>,
+}
+
+#[cfg(CONFIG_FAULT_INJECTION_DEBUG_FS)]
+impl Drop for DebugfsDir<'_> {
+    fn drop(&mut self) {
+        // SAFETY: The directory is valid by the type invariants. Removing it waits for the files
+        // in it to stop being used.
+        unsafe { bindings::debugfs_remove(self.dentry) };
+    }
+}
+
+// SAFETY: The directory can be removed from any thread.
+#[cfg(CONFIG_FAULT_INJECTION_DEBUG_FS)]
+unsafe impl Send for DebugfsDir<'_> {}
+
+// SAFETY: The directory has no methods that take `&self`.
+#[cfg(CONFIG_FAULT_INJECTION_DEBUG_FS)]
+unsafe impl Sync for DebugfsDir<'_> {}
@@ -0,0 +1,762 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Framebuffer devices (fbdev).
+//!
+//! A framebuffer exposes the contents of a display as memory, through `/dev/fbN` and to the
+//! framebuffer console. The framebuffers registered here are backed by system memory, which is
+//! allocated when they are registered. Drivers of displays that can't be mapped (e.g. SPI panels)
+//! implement [`Operations::deferred_io`] to copy the parts of the memory that were written to
+//! the display.
+//!
+//! C header: [`include/linux/fb.h`](../../../../include/linux/fb.h)
+
+use crate::{
+    bindings,
+    device::Device,
+    error::{code::*, from_result, to_result, Result},
+    str::CStr,
+    types::{ARef, Opaque},
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::{c_char, c_int, c_uint, c_void},
+    marker::PhantomPinned,
+    mem::MaybeUninit,
+    ops::Range,
+    pin::Pin,
+    ptr,
+};
+use macros::vtable;
+
+/// The way pixel values are mapped to colours, the kernel's `FB_VISUAL_*` values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Visual {
+    /// Monochrome, where 1 is black and 0 is white.
+    Mono01,
+    /// Monochrome, where 1 is white and 0 is black.
+    Mono10,
+    /// True colour, where pixels hold their red, green and blue components.
+    TrueColor,
+    /// Pseudo colour, where pixels are indices into a palette.
+    PseudoColor,
+    /// Direct colour, where the components of pixels are indices into per-component palettes.
+    DirectColor,
+    /// Pseudo colour with a read-only palette.
+    StaticPseudoColor,
+}
+
+impl Visual {
+    fn as_raw(self) -> u32 {
+        match self {
+            Self::Mono01 => bindings::FB_VISUAL_MONO01,
+            Self::Mono10 => bindings::FB_VISUAL_MONO10,
+            Self::TrueColor => bindings::FB_VISUAL_TRUECOLOR,
+            Self::PseudoColor => bindings::FB_VISUAL_PSEUDOCOLOR,
+            Self::DirectColor => bindings::FB_VISUAL_DIRECTCOLOR,
+            Self::StaticPseudoColor => bindings::FB_VISUAL_STATIC_PSEUDOCOLOR,
+        }
+    }
+}
+
+/// A blanking mode of the display, the kernel's `FB_BLANK_*` values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlankMode {
+    /// The display is on.
+    Unblank,
+    /// The display shows nothing, but its sync signals are kept.
+    Normal,
+    /// The vertical sync signal is stopped.
+    VsyncSuspend,
+    /// The horizontal sync signal is stopped.
+    HsyncSuspend,
+    /// The display is powered down.
+    Powerdown,
+}
+
+impl BlankMode {
+    fn from_raw(mode: c_int) -> Option<Self> {
+        Some(match mode {
+            m if m == bindings::FB_BLANK_UNBLANK as c_int => Self::Unblank,
+            m if m == bindings::FB_BLANK_NORMAL as c_int => Self::Normal,
+            m if m == bindings::FB_BLANK_VSYNC_SUSPEND as c_int => Self::VsyncSuspend,
+            m if m == bindings::FB_BLANK_HSYNC_SUSPEND as c_int => Self::HsyncSuspend,
+            m if m == bindings::FB_BLANK_POWERDOWN as c_int => Self::Powerdown,
+            _ => return None,
+        })
+    }
+}
+
+/// The position of a colour component in a pixel.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bitfield {
+    /// The offset of the component from the least significant bit of the pixel.
+    pub offset: u32,
+    /// The number of bits of the component.
+    pub length: u32,
+}
+
+impl Bitfield {
+    /// Creates a component of `length` bits at `offset`.
+    pub const fn new(offset: u32, length: u32) -> Self {
+        Self { offset, length }
+    }
+
+    fn from_raw(bf: &bindings::fb_bitfield) -> Self {
+        Self::new(bf.offset, bf.length)
+    }
+
+    fn as_raw(self) -> bindings::fb_bitfield {
+        bindings::fb_bitfield {
+            offset: self.offset,
+            length: self.length,
+            msb_right: 0,
+        }
+    }
+
+    /// Converts a 16-bit colour value to this component of a pixel.
+    fn pack(self, value: u32) -> u32 {
+        if self.length == 0 || self.length > 16 {
+            return 0;
+        }
+        ((value & 0xffff) >> (16 - self.length)) << self.offset
+    }
+}
+
+/// The fixed properties of a framebuffer, the kernel's `struct fb_fix_screeninfo`.
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct FixScreenInfo(bindings::fb_fix_screeninfo);
+
+impl FixScreenInfo {
+    /// Creates the properties of a framebuffer with packed pixels, identified as `id`.
+    ///
+    /// Fails with `EINVAL` if `id` is 16 bytes long or longer.
+    pub fn new(id: &CStr, visual: Visual) -> Result<Self> {
+        // SAFETY: All fields are integers, for which zero is valid.
+        let mut fix: bindings::fb_fix_screeninfo = unsafe { MaybeUninit::zeroed().assume_init() };
+        if id.len() >= fix.id.len() {
+            return Err(EINVAL);
+        }
+        for (dst, &src) in fix.id.iter_mut().zip(id.as_bytes()) {
+            *dst = src as c_char;
+        }
+        fix.type_ = bindings::FB_TYPE_PACKED_PIXELS;
+        fix.visual = visual.as_raw();
+        fix.accel = bindings::FB_ACCEL_NONE;
+        Ok(Self(fix))
+    }
+
+    /// Sets the length of a line of the framebuffer, in bytes.
+    ///
+    /// By default, lines are as long as the virtual horizontal resolution.
+    pub fn set_line_length(&mut self, line_length: u32) -> &mut Self {
+        self.0.line_length = line_length;
+        self
+    }
+
+    /// Sets the steps in which the framebuffer can be panned, in pixels and lines.
+    ///
+    /// A step of zero means that the framebuffer can't be panned in that direction.
+    pub fn set_pan_step(&mut self, x: u16, y: u16) -> &mut Self {
+        self.0.xpanstep = x;
+        self.0.ypanstep = y;
+        self
+    }
+
+    /// Returns the length of a line of the framebuffer, in bytes.
+    pub fn line_length(&self) -> u32 {
+        self.0.line_length
+    }
+}
+
+/// The variable properties of a framebuffer, the kernel's `struct fb_var_screeninfo`.
+///
+/// These are the current video mode, which user space can change if the driver implements
+/// [`Operations::check_var`].
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct VarScreenInfo(bindings::fb_var_screeninfo);
+
+impl VarScreenInfo {
+    /// Creates a video mode with the given resolution and pixel size, whose virtual resolution
+    /// is the same as the visible one.
+    pub fn new(xres: u32, yres: u32, bits_per_pixel: u32) -> Self {
+        // SAFETY: All fields are integers, for which zero is valid.
+        let mut var: bindings::fb_var_screeninfo = unsafe { MaybeUninit::zeroed().assume_init() };
+        var.xres = xres;
+        var.yres = yres;
+        var.xres_virtual = xres;
+        var.yres_virtual = yres;
+        var.bits_per_pixel = bits_per_pixel;
+        var.activate = bindings::FB_ACTIVATE_NOW;
+        var.vmode = bindings::FB_VMODE_NONINTERLACED;
+        Self(var)
+    }
+
+    /// Sets the virtual resolution, e.g. to allow panning.
+    pub fn set_virtual_resolution(&mut self, xres: u32, yres: u32) -> &mut Self {
+        self.0.xres_virtual = xres;
+        self.0.yres_virtual = yres;
+        self
+    }
+
+    /// Sets the position of the colour components in a pixel.
+    pub fn set_components(
+        &mut self,
+        red: Bitfield,
+        green: Bitfield,
+        blue: Bitfield,
+        transp: Bitfield,
+    ) -> &mut Self {
+        self.0.red = red.as_raw();
+        self.0.green = green.as_raw();
+        self.0.blue = blue.as_raw();
+        self.0.transp = transp.as_raw();
+        self
+    }
+
+    /// Sets the physical size of the display, in millimetres.
+    pub fn set_physical_size(&mut self, width: u32, height: u32) -> &mut Self {
+        self.0.width = width;
+        self.0.height = height;
+        self
+    }
+
+    /// Sets the offset of the visible part of the framebuffer, in pixels and lines.
+    pub fn set_offset(&mut self, x: u32, y: u32) -> &mut Self {
+        self.0.xoffset = x;
+        self.0.yoffset = y;
+        self
+    }
+
+    /// Returns the visible resolution.
+    pub fn resolution(&self) -> (u32, u32) {
+        (self.0.xres, self.0.yres)
+    }
+
+    /// Returns the virtual resolution.
+    pub fn virtual_resolution(&self) -> (u32, u32) {
+        (self.0.xres_virtual, self.0.yres_virtual)
+    }
+
+    /// Returns the offset of the visible part of the framebuffer, in pixels and lines.
+    pub fn offset(&self) -> (u32, u32) {
+        (self.0.xoffset, self.0.yoffset)
+    }
+
+    /// Returns the number of bits of a pixel.
+    pub fn bits_per_pixel(&self) -> u32 {
+        self.0.bits_per_pixel
+    }
+
+    /// Returns the position of the red, green, blue and transparency components in a pixel.
+    pub fn components(&self) -> [Bitfield; 4] {
+        [
+            Bitfield::from_raw(&self.0.red),
+            Bitfield::from_raw(&self.0.green),
+            Bitfield::from_raw(&self.0.blue),
+            Bitfield::from_raw(&self.0.transp),
+        ]
+    }
+
+    /// Returns `true` if the resolution and pixel format of `self` and `other` are the same.
+    pub fn same_format(&self, other: &Self) -> bool {
+        self.resolution() == other.resolution()
+            && self.virtual_resolution() == other.virtual_resolution()
+            && self.bits_per_pixel() == other.bits_per_pixel()
+            && self.components() == other.components()
+    }
+}
+
+/// The operations of a framebuffer, the kernel's `struct fb_ops`.
+///
+/// The driver data of the framebuffer implements this trait. Drawing to the framebuffer is done
+/// by the kernel in system memory, so all operations are optional.
+#[vtable]
+pub trait Operations: Send + Sync + Sized + 'static {
+    /// The delay between the first write to the framebuffer and the call to
+    /// [`Operations::deferred_io`], in milliseconds.
+    const DEFERRED_IO_DELAY_MS: u32 = 50;
+
+    /// Validates the video mode requested by user space, adjusting `var` to the nearest mode
+    /// that is supported.
+    ///
+    /// The video mode can't be changed if this isn't implemented.
+    fn check_var(_fb: &Framebuffer<Self>, _var: &mut VarScreenInfo) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Applies the video mode in [`Framebuffer::var`], after it was validated by
+    /// [`Operations::check_var`].
+    fn set_par(_fb: &Framebuffer<Self>) -> Result {
+        Ok(())
+    }
+
+    /// Shows the part of the framebuffer at the offset in `var`, which is within the virtual
+    /// resolution and a multiple of the pan steps.
+    fn pan_display(_fb: &Framebuffer<Self>, _var: &VarScreenInfo) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Blanks or unblanks the display.
+    fn blank(_fb: &Framebuffer<Self>, _mode: BlankMode) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Copies the bytes of the framebuffer in `dirty` to the display.
+    ///
+    /// This is called from a workqueue, [`Operations::DEFERRED_IO_DELAY_MS`] after the
+    /// framebuffer was written to, either by the kernel or through a mapping.
+    fn deferred_io(_fb: &Framebuffer<Self>, _dirty: Range<usize>) {}
+}
+
+/// A registered framebuffer, the kernel's `struct fb_info`.
+///
+/// The framebuffer is unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `info` and `screen` are valid allocations, of which `screen` holds `len` bytes and is the
+/// memory of `info`. `info` is registered if `registered` is `true`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, device::Device};
+/// use kernel::fb::{Bitfield, FixScreenInfo, Framebuffer, Operations, VarScreenInfo, Visual};
+/// # use core::ops::Range;
+/// # use kernel::prelude::*;
+///
+/// struct Panel;
+///
+/// #[vtable]
+/// impl Operations for Panel {
+///     fn deferred_io(fb: &Framebuffer<Self>, dirty: Range<usize>) {
+///         let mut line = [0u8; 240 * 2];
+///         let len = fb.fix().line_length() as usize;
+///         for y in dirty.start / len..(dirty.end + len - 1) / len {
+///             if fb.read(y * len, &mut line).is_ok() {
+///                 // Send the line to the panel.
+///             }
+///         }
+///     }
+/// }
+///
+/// fn probe(dev: &Device) -> Result<Pin<Box<Framebuffer<Panel>>>> {
+///     let fix = FixScreenInfo::new(c_str!("rustpanel"), Visual::TrueColor)?;
+///     let mut var = VarScreenInfo::new(240, 320, 16);
+///     var.set_components(
+///         Bitfield::new(11, 5),
+///         Bitfield::new(5, 6),
+///         Bitfield::new(0, 5),
+///         Bitfield::default(),
+///     );
+///     Framebuffer::register(dev, fix, var, Panel)
+/// }
+/// ```
+pub struct Framebuffer<T: Operations> {
+    info: *mut bindings::fb_info,
+    screen: *mut u8,
+    len: usize,
+    ops: Opaque<bindings::fb_ops>,
+    defio: Opaque<bindings::fb_deferred_io>,
+    pseudo_palette: Opaque<[u32; 16]>,
+    defio_init: bool,
+    registered: bool,
+    _dev: ARef<Device>,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+// SAFETY: The framebuffer can be unregistered from any thread, and the driver data is `Send`.
+unsafe impl<T: Operations> Send for Framebuffer<T> {}
+
+// SAFETY: The methods that take `&self` only read the framebuffer, and the driver data is `Sync`.
+unsafe impl<T: Operations> Sync for Framebuffer<T> {}
+
+impl<T: Operations> Framebuffer<T> {
+    /// Allocates the memory of a framebuffer of `dev` and registers it, which creates its
+    /// `/dev/fbN` device.
+    ///
+    /// The memory holds the virtual resolution of `var`. If the line length of `fix` is zero, it
+    /// is computed from the virtual horizontal resolution.
+    pub fn register(
+        dev: &Device,
+        mut fix: FixScreenInfo,
+        var: VarScreenInfo,
+        data: T,
+    ) -> Result<Pin<Box<Self>>> {
+        crate::might_sleep!();
+        let (xres_virtual, yres_virtual) = var.virtual_resolution();
+        if fix.line_length() == 0 {
+            let bits = xres_virtual
+                .checked_mul(var.bits_per_pixel())
+                .ok_or(EINVAL)?;
+            fix.set_line_length((bits + 7) / 8);
+        }
+        let len = fix.line_length().checked_mul(yres_virtual).ok_or(EINVAL)? as usize;
+        if len == 0 {
+            return Err(EINVAL);
+        }
+
+        // SAFETY: `vzalloc` may be called with any size.
+        let screen = unsafe { bindings::vzalloc(len as _) }.cast::<u8>();
+        if screen.is_null() {
+            return Err(ENOMEM);
+        }
+        // SAFETY: `dev` is valid, and no private data is requested.
+        let info = unsafe { bindings::framebuffer_alloc(0, dev.as_raw()) };
+        if info.is_null() {
+            // SAFETY: `screen` was allocated above, and isn't used by anything else.
+            unsafe { bindings::vfree(screen.cast()) };
+            return Err(ENOMEM);
+        }
+
+        // INVARIANT: `info` and `screen` were allocated above and are freed when `fb` is
+        // dropped.
+        let mut fb = Pin::from(Box::try_new(Self {
+            info,
+            screen,
+            len,
+            ops: Opaque::new(Self::build_ops()),
+            // SAFETY: All fields are optional, for which zero is valid.
+            defio: Opaque::new(unsafe { MaybeUninit::zeroed().assume_init() }),
+            pseudo_palette: Opaque::new([0; 16]),
+            defio_init: false,
+            registered: false,
+            _dev: dev.into(),
+            data,
+            _pin: PhantomPinned,
+        })?);
+
+        fix.0.smem_len = len as _;
+        // SAFETY: `info` is valid and not registered yet. `fb` is pinned, so the pointers to it
+        // stay valid until it is dropped, which unregisters `info` first.
+        unsafe {
+            (*info).fbops = fb.ops.get();
+            (*info).fix = fix.0;
+            (*info).var = var.0;
+            (*info).__bindgen_anon_1.screen_buffer = screen.cast();
+            (*info).screen_size = len as _;
+            (*info).pseudo_palette = fb.pseudo_palette.get().cast();
+            (*info).flags = bindings::FBINFO_VIRTFB as _;
+            (*info).par = &*fb as *const Self as *mut c_void;
+        }
+
+        // SAFETY: `fb` isn't moved out of.
+        let fb_mut = unsafe { fb.as_mut().get_unchecked_mut() };
+        if T::HAS_DEFERRED_IO {
+            // SAFETY: `defio` isn't used by the kernel yet.
+            unsafe {
+                let defio = fb_mut.defio.get();
+                (*defio).delay = bindings::msecs_to_jiffies(T::DEFERRED_IO_DELAY_MS) as _;
+                (*defio).deferred_io = Some(Self::deferred_io_callback);
+                (*info).fbdefio = defio;
+            }
+            // SAFETY: `info` has a valid `fbdefio`, which is cleaned up when `fb` is dropped.
+            to_result(unsafe { bindings::fb_deferred_io_init(info) })?;
+            fb_mut.defio_init = true;
+        }
+
+        // SAFETY: `info` is fully initialised.
+        to_result(unsafe { bindings::register_framebuffer(info) })?;
+        // INVARIANT: The framebuffer was registered above.
+        fb_mut.registered = true;
+        Ok(fb)
+    }
+
+    fn build_ops() -> bindings::fb_ops {
+        bindings::fb_ops {
+            fb_read: Some(bindings::fb_sys_read),
+            fb_write: Some(Self::write_callback),
+            fb_fillrect: Some(Self::fillrect_callback),
+            fb_copyarea: Some(Self::copyarea_callback),
+            fb_imageblit: Some(Self::imageblit_callback),
+            fb_mmap: if T::HAS_DEFERRED_IO {
+                Some(bindings::fb_deferred_io_mmap)
+            } else {
+                Some(Self::mmap_callback)
+            },
+            fb_setcolreg: Some(Self::setcolreg_callback),
+            fb_check_var: if T::HAS_CHECK_VAR {
+                Some(Self::check_var_callback)
+            } else {
+                None
+            },
+            fb_set_par: if T::HAS_SET_PAR {
+                Some(Self::set_par_callback)
+            } else {
+                None
+            },
+            fb_pan_display: if T::HAS_PAN_DISPLAY {
+                Some(Self::pan_display_callback)
+            } else {
+                None
+            },
+            fb_blank: if T::HAS_BLANK {
+                Some(Self::blank_callback)
+            } else {
+                None
+            },
+            // SAFETY: All other fields are optional, for which zero is valid.
+            ..unsafe { MaybeUninit::zeroed().assume_init() }
+        }
+    }
+
+    /// Returns the driver data of the framebuffer.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    /// Returns the number of the framebuffer, the `N` in `/dev/fbN`.
+    pub fn node(&self) -> i32 {
+        // SAFETY: `info` is valid by the type invariants.
+        unsafe { (*self.info).node }
+    }
+
+    /// Returns the fixed properties of the framebuffer.
+    pub fn fix(&self) -> FixScreenInfo {
+        // SAFETY: `info` is valid by the type invariants.
+        FixScreenInfo(unsafe { (*self.info).fix })
+    }
+
+    /// Returns the current video mode of the framebuffer.
+    pub fn var(&self) -> VarScreenInfo {
+        // SAFETY: `info` is valid by the type invariants.
+        VarScreenInfo(unsafe { (*self.info).var })
+    }
+
+    /// Returns the size of the memory of the framebuffer, in bytes.
+    pub fn screen_size(&self) -> usize {
+        self.len
+    }
+
+    /// Copies the memory of the framebuffer at `offset` into `buf`.
+    ///
+    /// Fails with `EINVAL` if the range is out of bounds. The memory may be written to
+    /// concurrently, e.g. by user space through a mapping, in which case `buf` holds a mix of
+    /// old and new contents.
+    pub fn read(&self, offset: usize, buf: &mut [u8]) -> Result {
+        let end = offset.checked_add(buf.len()).ok_or(EINVAL)?;
+        if end > self.len {
+            return Err(EINVAL);
+        }
+        // SAFETY: The range is within `screen` by the check above and the type invariants, and
+        // doesn't overlap with `buf`.
+        unsafe { ptr::copy_nonoverlapping(self.screen.add(offset), buf.as_mut_ptr(), buf.len()) };
+        Ok(())
+    }
+
+    /// Schedules a call to [`Operations::deferred_io`] with the whole framebuffer.
+    ///
+    /// This does nothing if the driver doesn't implement it.
+    pub fn schedule_update(&self) {
+        if self.defio_init {
+            // SAFETY: `info` is valid, and its deferred work was initialised by
+            // `fb_deferred_io_init`.
+            unsafe {
+                bindings::schedule_delayed_work(
+                    ptr::addr_of_mut!((*self.info).deferred_work),
+                    (*self.defio.get()).delay,
+                );
+            }
+        }
+    }
+
+    /// Returns the framebuffer of `info`.
+    ///
+    /// # Safety
+    ///
+    /// `info` must be the `fb_info` of a registered [`Framebuffer<T>`].
+    unsafe fn from_info<'a>(info: *mut bindings::fb_info) -> &'a Self {
+        // SAFETY: `par` points to the framebuffer, which is valid while it is registered.
+        unsafe { &*((*info).par as *const Self) }
+    }
+
+    unsafe extern "C" fn write_callback(
+        info: *mut bindings::fb_info,
+        buf: *const c_char,
+        count: usize,
+        ppos: *mut bindings::loff_t,
+    ) -> isize {
+        // SAFETY: The fbdev core calls this with a registered framebuffer and a user buffer of
+        // `count` bytes.
+        unsafe {
+            let ret = bindings::fb_sys_write(info, buf, count, ppos);
+            Self::from_info(info).schedule_update();
+            ret
+        }
+    }
+
+    unsafe extern "C" fn fillrect_callback(
+        info: *mut bindings::fb_info,
+        rect: *const bindings::fb_fillrect,
+    ) {
+        // SAFETY: The fbdev core calls this with a registered framebuffer and a valid rectangle.
+        unsafe {
+            bindings::sys_fillrect(info, rect);
+            Self::from_info(info).schedule_update();
+        }
+    }
+
+    unsafe extern "C" fn copyarea_callback(
+        info: *mut bindings::fb_info,
+        area: *const bindings::fb_copyarea,
+    ) {
+        // SAFETY: The fbdev core calls this with a registered framebuffer and a valid area.
+        unsafe {
+            bindings::sys_copyarea(info, area);
+            Self::from_info(info).schedule_update();
+        }
+    }
+
+    unsafe extern "C" fn imageblit_callback(
+        info: *mut bindings::fb_info,
+        image: *const bindings::fb_image,
+    ) {
+        // SAFETY: The fbdev core calls this with a registered framebuffer and a valid image.
+        unsafe {
+            bindings::sys_imageblit(info, image);
+            Self::from_info(info).schedule_update();
+        }
+    }
+
+    unsafe extern "C" fn mmap_callback(
+        info: *mut bindings::fb_info,
+        vma: *mut bindings::vm_area_struct,
+    ) -> c_int {
+        // SAFETY: The fbdev core calls this with a registered framebuffer and a valid VMA. The
+        // memory of the framebuffer was allocated with `vzalloc`, and `remap_vmalloc_range`
+        // checks that the VMA is within it.
+        unsafe {
+            let fb = Self::from_info(info);
+            bindings::remap_vmalloc_range(vma, fb.screen.cast(), (*vma).vm_pgoff)
+        }
+    }
+
+    unsafe extern "C" fn setcolreg_callback(
+        regno: c_uint,
+        red: c_uint,
+        green: c_uint,
+        blue: c_uint,
+        transp: c_uint,
+        info: *mut bindings::fb_info,
+    ) -> c_int {
+        // SAFETY: The fbdev core calls this with a registered framebuffer.
+        let fb = unsafe { Self::from_info(info) };
+        if regno >= 16 || fb.fix().0.visual != bindings::FB_VISUAL_TRUECOLOR {
+            return EINVAL.to_errno();
+        }
+        let [r, g, b, t] = fb.var().components();
+        let value = r.pack(red) | g.pack(green) | b.pack(blue) | t.pack(transp);
+        // SAFETY: `regno` is within the palette. The palette is only used by the framebuffer
+        // console, which holds the console lock while calling this.
+        unsafe { (*fb.pseudo_palette.get())[regno as usize] = value };
+        0
+    }
+
+    unsafe extern "C" fn check_var_callback(
+        var: *mut bindings::fb_var_screeninfo,
+        info: *mut bindings::fb_info,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The fbdev core calls this with a registered framebuffer and a valid video
+            // mode that isn't used by anything else. `VarScreenInfo` is transparent.
+            let (fb, var) = unsafe { (Self::from_info(info), &mut *var.cast::<VarScreenInfo>()) };
+            T::check_var(fb, var)?;
+            // The memory can't be reallocated, so the new mode must fit in it.
+            let (_, yres_virtual) = var.virtual_resolution();
+            if yres_virtual as usize * fb.fix().line_length() as usize > fb.len {
+                return Err(EINVAL);
+            }
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn set_par_callback(info: *mut bindings::fb_info) -> c_int {
+        from_result(|| {
+            // SAFETY: The fbdev core calls this with a registered framebuffer.
+            T::set_par(unsafe { Self::from_info(info) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn pan_display_callback(
+        var: *mut bindings::fb_var_screeninfo,
+        info: *mut bindings::fb_info,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The fbdev core calls this with a registered framebuffer and a valid video
+            // mode. `VarScreenInfo` is transparent.
+            let (fb, var) = unsafe { (Self::from_info(info), &*var.cast::<VarScreenInfo>()) };
+            T::pan_display(fb, var)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn blank_callback(mode: c_int, info: *mut bindings::fb_info) -> c_int {
+        from_result(|| {
+            let mode = BlankMode::from_raw(mode).ok_or(EINVAL)?;
+            // SAFETY: The fbdev core calls this with a registered framebuffer.
+            T::blank(unsafe { Self::from_info(info) }, mode)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn deferred_io_callback(
+        info: *mut bindings::fb_info,
+        pagereflist: *mut bindings::list_head,
+    ) {
+        // SAFETY: The fbdev core calls this with a registered framebuffer.
+        let fb = unsafe { Self::from_info(info) };
+        let page_size = bindings::PAGE_SIZE as usize;
+        let list_offset = {
+            let pageref = MaybeUninit::<bindings::fb_deferred_io_pageref>::uninit();
+            let base = pageref.as_ptr();
+            // SAFETY: The pointer is only used to compute the offset of the field.
+            unsafe { ptr::addr_of!((*base).list) as usize - base as usize }
+        };
+
+        let mut dirty: Option<Range<usize>> = None;
+        // SAFETY: The list is valid and holds the `fb_deferred_io_pageref`s of the pages that
+        // were written to, and isn't modified while this runs.
+        let mut entry = unsafe { (*pagereflist).next };
+        while entry != pagereflist {
+            // SAFETY: `entry` is the `list` field of a `fb_deferred_io_pageref`, see above.
+            let (offset, next) = unsafe {
+                let pageref = entry
+                    .cast::<u8>()
+                    .sub(list_offset)
+                    .cast::<bindings::fb_deferred_io_pageref>();
+                ((*pageref).offset as usize, (*entry).next)
+            };
+            let page = offset..(offset + page_size).min(fb.len);
+            dirty = Some(match dirty {
+                Some(d) => d.start.min(page.start)..d.end.max(page.end),
+                None => page,
+            });
+            entry = next;
+        }
+
+        // An empty list means that the kernel drew to the framebuffer, see `schedule_update`.
+        T::deferred_io(fb, dirty.unwrap_or(0..fb.len));
+    }
+}
+
+impl<T: Operations> Drop for Framebuffer<T> {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, `info` is valid, and is registered if `registered` is
+        // `true`. Once it is unregistered and its deferred work is cancelled, nothing uses it or
+        // its memory anymore.
+        unsafe {
+            if self.registered {
+                bindings::unregister_framebuffer(self.info);
+            }
+            if self.defio_init {
+                bindings::fb_deferred_io_cleanup(self.info);
+            }
+            bindings::framebuffer_release(self.info);
+            bindings::vfree(self.screen.cast());
+        }
+    }
+}
@@ -0,0 +1,909 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Files and file descriptors.
+//!
+//! C headers: [`include/linux/fs.h`](../../../../include/linux/fs.h) and
+//! [`include/linux/file.h`](../../../../include/linux/file.h)
+
+use crate::{
+    bindings,
+    cred::Credential,
+    error::{code::*, from_result, Error, Result},
+    io_buffer::{IoBufferReader, IoBufferWriter},
+    iov_iter::IovIter,
+    mm::virt,
+    signal::Signal,
+    sync::poll::PollTable,
+    types::{ARef, AlwaysRefCounted, ForeignOwnable, Opaque},
+    user_ptr::{UserSlicePtr, UserSlicePtrReader, UserSlicePtrWriter},
+    ThisModule,
+};
+use core::{ffi::c_int, marker::PhantomData, ptr};
+use macros::vtable;
+
+/// Flags associated with a [`File`].
+pub mod flags {
+    /// File is opened in append mode.
+    pub const O_APPEND: u32 = bindings::O_APPEND;
+
+    /// Signal-driven I/O is enabled.
+    pub const O_ASYNC: u32 = bindings::FASYNC;
+
+    /// Close-on-exec flag is set.
+    pub const O_CLOEXEC: u32 = bindings::O_CLOEXEC;
+
+    /// File was created if it didn't already exist.
+    pub const O_CREAT: u32 = bindings::O_CREAT;
+
+    /// Direct I/O is enabled for this file.
+    pub const O_DIRECT: u32 = bindings::O_DIRECT;
+
+    /// File must be a directory.
+    pub const O_DIRECTORY: u32 = bindings::O_DIRECTORY;
+
+    /// Like [`O_SYNC`] except metadata is not synced.
+    pub const O_DSYNC: u32 = bindings::O_DSYNC;
+
+    /// Ensure that this file is created with the `open(2)` call.
+    pub const O_EXCL: u32 = bindings::O_EXCL;
+
+    /// Large file size enabled (`off64_t` over `off_t`).
+    pub const O_LARGEFILE: u32 = bindings::O_LARGEFILE;
+
+    /// Do not update the file last access time.
+    pub const O_NOATIME: u32 = bindings::O_NOATIME;
+
+    /// File should not be used as process's controlling terminal.
+    pub const O_NOCTTY: u32 = bindings::O_NOCTTY;
+
+    /// If basename of path is a symbolic link, fail open.
+    pub const O_NOFOLLOW: u32 = bindings::O_NOFOLLOW;
+
+    /// File is using nonblocking I/O.
+    pub const O_NONBLOCK: u32 = bindings::O_NONBLOCK;
+
+    /// Also known as `O_NDELAY`.
+    ///
+    /// This is effectively the same flag as [`O_NONBLOCK`] on all architectures except SPARC64.
+    pub const O_NDELAY: u32 = bindings::O_NDELAY;
+
+    /// Used to obtain a path file descriptor.
+    pub const O_PATH: u32 = bindings::O_PATH;
+
+    /// Write operations on this file will flush data and metadata.
+    pub const O_SYNC: u32 = bindings::O_SYNC;
+
+    /// This file is an unnamed temporary regular file.
+    pub const O_TMPFILE: u32 = bindings::O_TMPFILE;
+
+    /// File should be truncated to length 0.
+    pub const O_TRUNC: u32 = bindings::O_TRUNC;
+
+    /// Bitmask for access mode flags.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kernel::file;
+    /// # fn do_something() {}
+    /// # let flags = 0;
+    /// if (flags & file::flags::O_ACCMODE) == file::flags::O_RDONLY {
+    ///     do_something();
+    /// }
+    /// ```
+    pub const O_ACCMODE: u32 = bindings::O_ACCMODE;
+
+    /// File is read only.
+    pub const O_RDONLY: u32 = bindings::O_RDONLY;
+
+    /// File is write only.
+    pub const O_WRONLY: u32 = bindings::O_WRONLY;
+
+    /// File can be both read and written.
+    pub const O_RDWR: u32 = bindings::O_RDWR;
+}
+
+/// Wraps the kernel's `struct file`.
+///
+/// # Invariants
+///
+/// Instances of this type are always ref-counted, that is, a call to `get_file` ensures that the
+/// allocation remains valid at least until the matching call to `fput`.
+#[repr(transparent)]
+pub struct File(Opaque<bindings::file>);
+
+// SAFETY: By design, the only way to access a `File` is via an immutable reference or an `ARef`.
+// This means that the only situation in which a `File` can be accessed mutably is when the
+// refcount drops to zero and the destructor runs. It is safe for that to happen on any thread, so
+// it is ok for this type to be `Send`.
+unsafe impl Send for File {}
+
+// SAFETY: All methods defined on `File` that take `&self` are safe to call even if other threads
+// are concurrently accessing the same `struct file`, because those methods either access immutable
+// properties or have proper synchronization to ensure that such accesses are safe.
+unsafe impl Sync for File {}
+
+impl File {
+    /// Constructs a new `struct file` wrapper from a file descriptor.
+    ///
+    /// The file descriptor belongs to the current process.
+    pub fn fget(fd: u32) -> Result<ARef<Self>, BadFdError> {
+        // SAFETY: FFI call, there are no requirements on `fd`.
+        let ptr = ptr::NonNull::new(unsafe { bindings::fget(fd) }).ok_or(BadFdError)?;
+
+        // SAFETY: `fget` increments the refcount before returning.
+        Ok(unsafe { ARef::from_raw(ptr.cast()) })
+    }
+
+    /// Creates a reference to a [`File`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that `ptr` points at a valid file and that its refcount does not
+    /// reach zero during the lifetime 'a.
+    pub unsafe fn from_ptr<'a>(ptr: *const bindings::file) -> &'a File {
+        // SAFETY: The safety requirements guarantee the validity of the dereference, while the
+        // `File` type being transparent makes the cast ok.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns a raw pointer to the inner C struct.
+    #[inline]
+    pub fn as_ptr(&self) -> *mut bindings::file {
+        self.0.get()
+    }
+
+    /// Returns the credentials of the task that originally opened the file.
+    pub fn cred(&self) -> &Credential {
+        // SAFETY: It's okay to read the `f_cred` field without synchronization because `f_cred` is
+        // never changed after initialization of the file.
+        let ptr = unsafe { (*self.as_ptr()).f_cred };
+
+        // SAFETY: The signature of this function ensures that the caller will only access the
+        // returned credential while the file is still valid, and the C side ensures that the
+        // credential stays valid at least as long as the file.
+        unsafe { Credential::from_ptr(ptr) }
+    }
+
+    /// Returns the flags associated with the file.
+    ///
+    /// The flags are a combination of the constants in [`flags`].
+    pub fn flags(&self) -> u32 {
+        // This `read_volatile` is intended to correspond to a READ_ONCE call.
+        //
+        // SAFETY: The file is valid because the shared reference guarantees a nonzero refcount.
+        //
+        // TODO: Replace with `read_once` when available on the Rust side.
+        unsafe { core::ptr::addr_of!((*self.as_ptr()).f_flags).read_volatile() }
+    }
+}
+
+// SAFETY: The type invariants guarantee that `File` is always ref-counted.
+unsafe impl AlwaysRefCounted for File {
+    fn inc_ref(&self) {
+        // SAFETY: The existence of a shared reference means that the refcount is nonzero.
+        unsafe { bindings::get_file(self.as_ptr()) };
+    }
+
+    unsafe fn dec_ref(obj: ptr::NonNull<File>) {
+        // SAFETY: The safety requirements guarantee that the refcount is nonzero. The file
+        // represented by `obj` is valid because `File` is `repr(transparent)`.
+        unsafe { bindings::fput(obj.cast().as_ptr()) }
+    }
+}
+
+/// Represents the `EBADF` error code.
+///
+/// Used for methods that can only fail with `EBADF`.
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub struct BadFdError;
+
+impl From<BadFdError> for Error {
+    fn from(_: BadFdError) -> Error {
+        EBADF
+    }
+}
+
+impl core::fmt::Debug for BadFdError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.pad("EBADF")
+    }
+}
+
+/// The reason for a `SIGIO`, reported to the process in `si_code`, the kernel's `POLL_*` values.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Band {
+    /// Data can be read.
+    In = bindings::POLL_IN,
+    /// Data can be written.
+    Out = bindings::POLL_OUT,
+    /// A message is available.
+    Msg = bindings::POLL_MSG,
+    /// An error occurred.
+    Err = bindings::POLL_ERR,
+    /// Urgent data can be read.
+    Pri = bindings::POLL_PRI,
+    /// The device was disconnected.
+    Hup = bindings::POLL_HUP,
+}
+
+/// The processes to notify asynchronously of events of a file, e.g. with `SIGIO`, the kernel's
+/// list of `struct fasync_struct`.
+///
+/// Processes enable notifications with `fcntl(F_SETFL, O_ASYNC)`, which calls
+/// [`Operations::fasync`], which adds or removes the file with [`FasyncQueue::update`]. Files are
+/// removed from the queue when they are released, since the VFS calls [`Operations::fasync`]
+/// again then.
+///
+/// The queue usually outlives the files in it, e.g. by being in the data of the device that the
+/// files are opened on. Files still in the queue when it is dropped are removed from it.
+///
+/// # Invariants
+///
+/// Each file in the queue is removed from it with [`FasyncQueue::update`], and so through a
+/// shared reference to the queue, before the file is released.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::file::{self, Band, FasyncQueue, File};
+/// use kernel::{prelude::*, signal::Signal, sync::{Arc, ArcBorrow}};
+///
+/// struct Device {
+///     async_queue: FasyncQueue,
+/// }
+///
+/// impl Device {
+///     fn data_ready(&self) {
+///         self.async_queue.kill(Signal::Io, Band::In);
+///     }
+/// }
+///
+/// struct DeviceFile;
+///
+/// #[vtable]
+/// impl file::Operations for DeviceFile {
+///     type Data = Arc<Device>;
+///     type OpenData = Arc<Device>;
+///
+///     fn open(device: &Arc<Device>, _file: &File) -> Result<Arc<Device>> {
+///         Ok(device.clone())
+///     }
+///
+///     fn fasync(device: ArcBorrow<'_, Device>, file: &File, fd: i32, on: bool) -> Result {
+///         // SAFETY: The VFS calls this again with `on` cleared when the file is released, and
+///         // the data of the file keeps the device alive until then.
+///         unsafe { device.async_queue.update(fd, file, on) }
+///     }
+/// }
+/// ```
+pub struct FasyncQueue {
+    head: Opaque<*mut bindings::fasync_struct>,
+}
+
+impl FasyncQueue {
+    /// Creates an empty queue.
+    pub const fn new() -> Self {
+        Self {
+            head: Opaque::new(ptr::null_mut()),
+        }
+    }
+
+    /// Adds `file`, open as `fd`, to the queue if `on` is set, or removes it otherwise.
+    ///
+    /// This may sleep.
+    ///
+    /// # Safety
+    ///
+    /// If `file` is added, callers must ensure that it is removed from this queue with this
+    /// function before it is released. Calling this from [`Operations::fasync`] with a queue that
+    /// the data of the file keeps alive does that, as the VFS calls it with `on` cleared when the
+    /// file is released.
+    pub unsafe fn update(&self, fd: i32, file: &File, on: bool) -> Result {
+        crate::might_sleep!();
+        // INVARIANT: The caller guarantees that the file is removed before it is released.
+        // SAFETY: The file is valid, and the list is protected by the lock of the file and by
+        // `fasync_lock`.
+        let ret = unsafe { bindings::fasync_helper(fd, file.as_ptr(), on.into(), self.head.get()) };
+        if ret < 0 {
+            return Err(Error::from_errno(ret));
+        }
+        Ok(())
+    }
+
+    /// Sends `sig`, with the reason `band`, to the owners of the files in the queue.
+    ///
+    /// This may be called in any context.
+    pub fn kill(&self, sig: Signal, band: Band) {
+        // SAFETY: The list is read under RCU by `kill_fasync`.
+        unsafe { bindings::kill_fasync(self.head.get(), sig.to_raw(), band as _) };
+    }
+}
+
+impl Default for FasyncQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for FasyncQueue {
+    fn drop(&mut self) {
+        let head = self.head.get();
+        // SAFETY: By the type invariants, the files left in the queue are only released after
+        // they are removed from it through a shared reference to it, which can't exist while
+        // it is dropped. So they stay valid meanwhile, even if they are being closed. Each call
+        // removes the first entry, since it is the one of `fa_file`, under `fasync_lock`.
+        unsafe {
+            while !(*head).is_null() {
+                bindings::fasync_helper(-1, (**head).fa_file, 0, head);
+            }
+        }
+    }
+}
+
+// SAFETY: The list is protected by the locks of the kernel, and the files are removed from it
+// when they are released, so it can be dropped from any thread.
+unsafe impl Send for FasyncQueue {}
+
+// SAFETY: The list is only accessed by `fasync_helper` and `kill_fasync`, which synchronise with
+// each other.
+unsafe impl Sync for FasyncQueue {}
+
+/// Equivalent to [`std::io::SeekFrom`].
+///
+/// [`std::io::SeekFrom`]: https://doc.rust-lang.org/std/io/enum.SeekFrom.html
+pub enum SeekFrom {
+    /// Sets the offset to the provided number of bytes.
+    Start(u64),
+
+    /// Sets the offset to the size of this object plus the specified number of bytes.
+    End(i64),
+
+    /// Sets the offset to the current position plus the specified number of bytes.
+    Current(i64),
+}
+
+/// Corresponds to the kernel's `struct file_operations`.
+///
+/// You implement this trait whenever you would create a `struct file_operations`. Operations that
+/// are not implemented are left empty in the generated table, so the kernel falls back to its
+/// default behaviour for them (usually failing with `EINVAL` or `ESPIPE`).
+///
+/// File descriptors may be used from multiple threads/processes concurrently, so your type must be
+/// [`Sync`]. It must also be [`Send`] because [`Operations::release`] will be called from the
+/// thread that decrements that associated file's refcount to zero.
+#[vtable]
+pub trait Operations {
+    /// The type of the context data returned by [`Operations::open`] and made available to other
+    /// methods.
+    type Data: ForeignOwnable + Send + Sync;
+
+    /// The type of the context data passed to [`Operations::open`].
+    ///
+    /// It is provided by whoever registers the file operations, e.g. a debugfs file or a misc
+    /// device.
+    type OpenData: Sync;
+
+    /// Creates a new instance of this file.
+    ///
+    /// Corresponds to the `open` function pointer in `struct file_operations`.
+    fn open(context: &Self::OpenData, file: &File) -> Result<Self::Data>;
+
+    /// Cleans up after the last reference to the file goes away.
+    ///
+    /// Corresponds to the `release` function pointer in `struct file_operations`.
+    fn release(_data: Self::Data, _file: &File) {}
+
+    /// Reads data from this file to the caller's buffer.
+    ///
+    /// Returns the number of bytes written to `writer`. Corresponds to the `read` function
+    /// pointer in `struct file_operations`.
+    fn read(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _file: &File,
+        _writer: &mut impl IoBufferWriter,
+        _offset: u64,
+    ) -> Result<usize> {
+        Err(EINVAL)
+    }
+
+    /// Writes data from the caller's buffer to this file.
+    ///
+    /// Returns the number of bytes consumed from `reader`. Corresponds to the `write` function
+    /// pointer in `struct file_operations`.
+    fn write(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _file: &File,
+        _reader: &mut impl IoBufferReader,
+        _offset: u64,
+    ) -> Result<usize> {
+        Err(EINVAL)
+    }
+
+    /// Reads data from this file to the buffers of `iter`, for vectored, asynchronous and splice
+    /// reads.
+    ///
+    /// Returns the number of bytes written to `iter`. If [`Operations::read`] is also
+    /// implemented, `read` uses it instead. Corresponds to the `read_iter` function pointer in
+    /// `struct file_operations`.
+    fn read_iter(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _file: &File,
+        _iter: &mut IovIter,
+        _offset: u64,
+    ) -> Result<usize> {
+        Err(EINVAL)
+    }
+
+    /// Writes data from the buffers of `iter` to this file, for vectored, asynchronous and splice
+    /// writes.
+    ///
+    /// Returns the number of bytes consumed from `iter`. If [`Operations::write`] is also
+    /// implemented, `write` uses it instead. Corresponds to the `write_iter` function pointer in
+    /// `struct file_operations`.
+    fn write_iter(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _file: &File,
+        _iter: &mut IovIter,
+        _offset: u64,
+    ) -> Result<usize> {
+        Err(EINVAL)
+    }
+
+    /// Changes the position of the file.
+    ///
+    /// Returns the new position. Corresponds to the `llseek` function pointer in
+    /// `struct file_operations`.
+    fn seek(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _file: &File,
+        _offset: SeekFrom,
+    ) -> Result<u64> {
+        Err(EINVAL)
+    }
+
+    /// Performs IO control operations that are specific to the file.
+    ///
+    /// `cmd` is the ioctl command number (see [`crate::ioctl`] to decode it) and `arg` its
+    /// untyped argument, usually a user pointer. Corresponds to the `unlocked_ioctl` function
+    /// pointer in `struct file_operations`.
+    fn ioctl(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _file: &File,
+        _cmd: u32,
+        _arg: usize,
+    ) -> Result<i32> {
+        Err(ENOTTY)
+    }
+
+    /// Performs 32-bit IO control operations that are specific to the file on 64-bit kernels.
+    ///
+    /// Corresponds to the `compat_ioctl` function pointer in `struct file_operations`.
+    fn compat_ioctl(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _file: &File,
+        _cmd: u32,
+        _arg: usize,
+    ) -> Result<i32> {
+        Err(ENOTTY)
+    }
+
+    /// Syncs pending changes to this file.
+    ///
+    /// Corresponds to the `fsync` function pointer in `struct file_operations`.
+    fn fsync(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _file: &File,
+        _start: u64,
+        _end: u64,
+        _datasync: bool,
+    ) -> Result<u32> {
+        Err(EINVAL)
+    }
+
+    /// Checks the readiness of the file, for `poll`, `select` and `epoll`.
+    ///
+    /// Registers the condition variables that signal changes of the readiness in `table`, if
+    /// any, and returns the [`events`](crate::sync::poll::events) that are ready. Errors are
+    /// reported as `POLLERR`. Corresponds to the `poll` function pointer in
+    /// `struct file_operations`.
+    fn poll(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _file: &File,
+        _table: Option<&mut PollTable>,
+    ) -> Result<u32> {
+        Ok(bindings::DEFAULT_POLLMASK)
+    }
+
+    /// Enables or disables asynchronous notifications of the file, open as `fd`, when its
+    /// `O_ASYNC` flag changes.
+    ///
+    /// It is usually implemented with [`FasyncQueue::update`]. The VFS also calls it with `on`
+    /// cleared when a file with `O_ASYNC` set is released, before [`Operations::release`], which
+    /// is when the file must be removed from the queues it was added to.
+    /// Corresponds to the `fasync` function pointer in `struct file_operations`.
+    fn fasync(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _file: &File,
+        _fd: i32,
+        _on: bool,
+    ) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Maps areas of memory of the file into the address space of the calling process.
+    ///
+    /// It is called with the mmap lock of the process held. Corresponds to the `mmap` function
+    /// pointer in `struct file_operations`.
+    fn mmap(
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _file: &File,
+        _vma: &mut virt::Area,
+    ) -> Result {
+        Err(EINVAL)
+    }
+}
+
+/// Trait for extracting the [`Operations::OpenData`] of a file at open time.
+///
+/// Each kind of registration (a debugfs file, a misc device, ...) stashes its context data
+/// somewhere in the inode or the file; implementations of this trait know where.
+pub trait OpenAdapter<T: Sync> {
+    /// Converts untyped data stored in [`struct inode`] and [`struct file`] (when
+    /// [`struct file_operations::open`] is called) into the given type.
+    ///
+    /// # Safety
+    ///
+    /// This function must be called only when [`struct file_operations::open`] is being called
+    /// for a file that was created by a registration of the adapter's kind.
+    ///
+    /// [`struct inode`]: ../../../include/linux/fs.h
+    /// [`struct file`]: ../../../include/linux/fs.h
+    /// [`struct file_operations::open`]: ../../../include/linux/fs.h
+    unsafe fn convert(_inode: *mut bindings::inode, _file: *mut bindings::file) -> *const T;
+}
+
+/// Builds the `struct file_operations` table for an implementation of [`Operations`].
+pub(crate) struct OperationsVtable<A, T>(PhantomData<A>, PhantomData<T>);
+
+impl<A: OpenAdapter<T::OpenData>, T: Operations> OperationsVtable<A, T> {
+    /// Called by the VFS when an inode should be opened.
+    ///
+    /// # Safety
+    ///
+    /// `inode` must be a valid inode of a file registered with an `A` adapter and `file` must be
+    /// a file that is being opened.
+    unsafe extern "C" fn open_callback(
+        inode: *mut bindings::inode,
+        file: *mut bindings::file,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The caller guarantees that `inode` and `file` come from an `A`
+            // registration, so the open data can be extracted and is valid for the duration of
+            // the call.
+            let arg = unsafe { &*A::convert(inode, file) };
+            // SAFETY: `file` is valid for the duration of the call.
+            let ptr = T::open(arg, unsafe { File::from_ptr(file) })?.into_foreign();
+            // SAFETY: `file` is being opened, so nothing else accesses `private_data` yet. It is
+            // freed in `release_callback`.
+            unsafe { (*file).private_data = ptr as _ };
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn read_callback(
+        file: *mut bindings::file,
+        buf: *mut core::ffi::c_char,
+        len: usize,
+        offset: *mut bindings::loff_t,
+    ) -> isize {
+        from_result(|| {
+            // SAFETY: The caller guarantees that `buf` and `len` describe a user buffer.
+            let mut data: UserSlicePtrWriter = unsafe { UserSlicePtr::new(buf as _, len).writer() };
+            // SAFETY: `private_data` was initialised by `open_callback` with a value returned by
+            // `T::Data::into_foreign`, and it's only freed in `release_callback`.
+            let f = unsafe { T::Data::borrow((*file).private_data) };
+            // SAFETY: `file` and `offset` are valid for the duration of the call.
+            let (file, pos) = unsafe { (File::from_ptr(file), *offset) };
+            let read = T::read(f, file, &mut data, pos.try_into()?)?;
+            // SAFETY: `offset` is valid for the duration of the call.
+            unsafe { *offset += bindings::loff_t::try_from(read)? };
+            Ok(read as _)
+        })
+    }
+
+    unsafe extern "C" fn write_callback(
+        file: *mut bindings::file,
+        buf: *const core::ffi::c_char,
+        len: usize,
+        offset: *mut bindings::loff_t,
+    ) -> isize {
+        from_result(|| {
+            // SAFETY: The caller guarantees that `buf` and `len` describe a user buffer.
+            let mut data: UserSlicePtrReader = unsafe { UserSlicePtr::new(buf as _, len).reader() };
+            // SAFETY: `private_data` was initialised by `open_callback` with a value returned by
+            // `T::Data::into_foreign`, and it's only freed in `release_callback`.
+            let f = unsafe { T::Data::borrow((*file).private_data) };
+            // SAFETY: `file` and `offset` are valid for the duration of the call.
+            let (file, pos) = unsafe { (File::from_ptr(file), *offset) };
+            let written = T::write(f, file, &mut data, pos.try_into()?)?;
+            // SAFETY: `offset` is valid for the duration of the call.
+            unsafe { *offset += bindings::loff_t::try_from(written)? };
+            Ok(written as _)
+        })
+    }
+
+    unsafe extern "C" fn read_iter_callback(
+        iocb: *mut bindings::kiocb,
+        raw_iter: *mut bindings::iov_iter,
+    ) -> isize {
+        from_result(|| {
+            // SAFETY: The caller guarantees that `raw_iter` is valid for the duration of the call.
+            let iter = unsafe { IovIter::from_ptr(raw_iter) };
+            // SAFETY: `iocb` is valid for the duration of the call, and refers to an open file.
+            let (file, pos) = unsafe { ((*iocb).ki_filp, (*iocb).ki_pos) };
+            // SAFETY: `private_data` was initialised by `open_callback` with a value returned by
+            // `T::Data::into_foreign`, and it's only freed in `release_callback`.
+            let f = unsafe { T::Data::borrow((*file).private_data) };
+            // SAFETY: `file` is valid for the duration of the call.
+            let read = T::read_iter(f, unsafe { File::from_ptr(file) }, iter, pos.try_into()?)?;
+            // SAFETY: `iocb` is valid for the duration of the call.
+            unsafe { (*iocb).ki_pos += bindings::loff_t::try_from(read)? };
+            Ok(read as _)
+        })
+    }
+
+    unsafe extern "C" fn write_iter_callback(
+        iocb: *mut bindings::kiocb,
+        raw_iter: *mut bindings::iov_iter,
+    ) -> isize {
+        from_result(|| {
+            // SAFETY: The caller guarantees that `raw_iter` is valid for the duration of the call.
+            let iter = unsafe { IovIter::from_ptr(raw_iter) };
+            // SAFETY: `iocb` is valid for the duration of the call, and refers to an open file.
+            let (file, pos) = unsafe { ((*iocb).ki_filp, (*iocb).ki_pos) };
+            // SAFETY: `private_data` was initialised by `open_callback` with a value returned by
+            // `T::Data::into_foreign`, and it's only freed in `release_callback`.
+            let f = unsafe { T::Data::borrow((*file).private_data) };
+            // SAFETY: `file` is valid for the duration of the call.
+            let written = T::write_iter(f, unsafe { File::from_ptr(file) }, iter, pos.try_into()?)?;
+            // SAFETY: `iocb` is valid for the duration of the call.
+            unsafe { (*iocb).ki_pos += bindings::loff_t::try_from(written)? };
+            Ok(written as _)
+        })
+    }
+
+    unsafe extern "C" fn release_callback(
+        _inode: *mut bindings::inode,
+        file: *mut bindings::file,
+    ) -> c_int {
+        // SAFETY: `file` is valid for the duration of the call, and this is the last call on it.
+        let ptr = unsafe { core::mem::replace(&mut (*file).private_data, ptr::null_mut()) };
+        // SAFETY: `ptr` was returned by `into_foreign` in `open_callback`, and no other callbacks
+        // can be running anymore.
+        let data = unsafe { T::Data::from_foreign(ptr) };
+        // SAFETY: `file` is valid for the duration of the call.
+        T::release(data, unsafe { File::from_ptr(file) });
+        0
+    }
+
+    unsafe extern "C" fn llseek_callback(
+        file: *mut bindings::file,
+        offset: bindings::loff_t,
+        whence: c_int,
+    ) -> bindings::loff_t {
+        from_result(|| {
+            let off = match whence as u32 {
+                bindings::SEEK_SET => SeekFrom::Start(offset.try_into()?),
+                bindings::SEEK_CUR => SeekFrom::Current(offset),
+                bindings::SEEK_END => SeekFrom::End(offset),
+                _ => return Err(EINVAL),
+            };
+            // SAFETY: `private_data` was initialised by `open_callback` with a value returned by
+            // `T::Data::into_foreign`, and it's only freed in `release_callback`.
+            let f = unsafe { T::Data::borrow((*file).private_data) };
+            // SAFETY: `file` is valid for the duration of the call.
+            let off = T::seek(f, unsafe { File::from_ptr(file) }, off)?;
+            Ok(off.try_into()?)
+        })
+    }
+
+    unsafe extern "C" fn unlocked_ioctl_callback(
+        file: *mut bindings::file,
+        cmd: core::ffi::c_uint,
+        arg: core::ffi::c_ulong,
+    ) -> core::ffi::c_long {
+        from_result(|| {
+            // SAFETY: `private_data` was initialised by `open_callback` with a value returned by
+            // `T::Data::into_foreign`, and it's only freed in `release_callback`.
+            let f = unsafe { T::Data::borrow((*file).private_data) };
+            // SAFETY: `file` is valid for the duration of the call.
+            let ret = T::ioctl(f, unsafe { File::from_ptr(file) }, cmd, arg as _)?;
+            Ok(ret as _)
+        })
+    }
+
+    unsafe extern "C" fn compat_ioctl_callback(
+        file: *mut bindings::file,
+        cmd: core::ffi::c_uint,
+        arg: core::ffi::c_ulong,
+    ) -> core::ffi::c_long {
+        from_result(|| {
+            // SAFETY: `private_data` was initialised by `open_callback` with a value returned by
+            // `T::Data::into_foreign`, and it's only freed in `release_callback`.
+            let f = unsafe { T::Data::borrow((*file).private_data) };
+            // SAFETY: `file` is valid for the duration of the call.
+            let ret = T::compat_ioctl(f, unsafe { File::from_ptr(file) }, cmd, arg as _)?;
+            Ok(ret as _)
+        })
+    }
+
+    unsafe extern "C" fn fsync_callback(
+        file: *mut bindings::file,
+        start: bindings::loff_t,
+        end: bindings::loff_t,
+        datasync: c_int,
+    ) -> c_int {
+        from_result(|| {
+            let start = start.try_into()?;
+            let end = end.try_into()?;
+            // SAFETY: `private_data` was initialised by `open_callback` with a value returned by
+            // `T::Data::into_foreign`, and it's only freed in `release_callback`.
+            let f = unsafe { T::Data::borrow((*file).private_data) };
+            // SAFETY: `file` is valid for the duration of the call.
+            let res = T::fsync(
+                f,
+                unsafe { File::from_ptr(file) },
+                start,
+                end,
+                datasync != 0,
+            )?;
+            Ok(res.try_into()?)
+        })
+    }
+
+    unsafe extern "C" fn poll_callback(
+        file: *mut bindings::file,
+        wait: *mut bindings::poll_table,
+    ) -> bindings::__poll_t {
+        // SAFETY: `private_data` was initialised by `open_callback` with a value returned by
+        // `T::Data::into_foreign`, and it's only freed in `release_callback`.
+        let f = unsafe { T::Data::borrow((*file).private_data) };
+        // SAFETY: The VFS calls this with a table that is null or valid for the duration of the
+        // call.
+        let table = unsafe { PollTable::from_ptr(wait) };
+        // SAFETY: `file` is valid for the duration of the call.
+        match T::poll(f, unsafe { File::from_ptr(file) }, table) {
+            Ok(events) => events as _,
+            Err(_) => bindings::POLLERR as _,
+        }
+    }
+
+    unsafe extern "C" fn fasync_callback(fd: c_int, file: *mut bindings::file, on: c_int) -> c_int {
+        from_result(|| {
+            // SAFETY: `private_data` was initialised by `open_callback` with a value returned by
+            // `T::Data::into_foreign`, and it's only freed in `release_callback`.
+            let f = unsafe { T::Data::borrow((*file).private_data) };
+            // SAFETY: `file` is valid for the duration of the call.
+            T::fasync(f, unsafe { File::from_ptr(file) }, fd, on != 0)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn mmap_callback(
+        file: *mut bindings::file,
+        vma: *mut bindings::vm_area_struct,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: `private_data` was initialised by `open_callback` with a value returned by
+            // `T::Data::into_foreign`, and it's only freed in `release_callback`.
+            let f = unsafe { T::Data::borrow((*file).private_data) };
+            // SAFETY: The VFS calls this with a valid area being set up, with the mmap lock held
+            // for writing.
+            let area = unsafe { virt::Area::from_ptr(vma) };
+            // SAFETY: `file` is valid for the duration of the call.
+            T::mmap(f, unsafe { File::from_ptr(file) }, area)?;
+            Ok(0)
+        })
+    }
+
+    const VTABLE: bindings::file_operations = bindings::file_operations {
+        open: Some(Self::open_callback),
+        release: Some(Self::release_callback),
+        read: if T::HAS_READ {
+            Some(Self::read_callback)
+        } else {
+            None
+        },
+        write: if T::HAS_WRITE {
+            Some(Self::write_callback)
+        } else {
+            None
+        },
+        read_iter: if T::HAS_READ_ITER {
+            Some(Self::read_iter_callback)
+        } else {
+            None
+        },
+        write_iter: if T::HAS_WRITE_ITER {
+            Some(Self::write_iter_callback)
+        } else {
+            None
+        },
+        // Splicing from the file goes through `read_iter`.
+        splice_read: if T::HAS_READ_ITER {
+            Some(bindings::generic_file_splice_read)
+        } else {
+            None
+        },
+        // Splicing to the file goes through `write_iter`.
+        splice_write: if T::HAS_WRITE_ITER {
+            Some(bindings::iter_file_splice_write)
+        } else {
+            None
+        },
+        llseek: if T::HAS_SEEK {
+            Some(Self::llseek_callback)
+        } else {
+            None
+        },
+        unlocked_ioctl: if T::HAS_IOCTL {
+            Some(Self::unlocked_ioctl_callback)
+        } else {
+            None
+        },
+        compat_ioctl: if T::HAS_COMPAT_IOCTL {
+            Some(Self::compat_ioctl_callback)
+        } else {
+            None
+        },
+        fsync: if T::HAS_FSYNC {
+            Some(Self::fsync_callback)
+        } else {
+            None
+        },
+        poll: if T::HAS_POLL {
+            Some(Self::poll_callback)
+        } else {
+            None
+        },
+        fasync: if T::HAS_FASYNC {
+            Some(Self::fasync_callback)
+        } else {
+            None
+        },
+        mmap: if T::HAS_MMAP {
+            Some(Self::mmap_callback)
+        } else {
+            None
+        },
+        // SAFETY: All other fields are either pointers, for which `NULL` means "not implemented",
+        // or plain integers, for which zero is the correct default.
+        ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+    };
+
+    /// Builds an instance of [`struct file_operations`].
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the adapter is compatible with the way the device is
+    /// registered.
+    pub(crate) const unsafe fn build() -> &'static bindings::file_operations {
+        &Self::VTABLE
+    }
+
+    /// Builds an instance of [`struct file_operations`] owned by `module`, which can't be unloaded
+    /// while files using it are open.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the adapter is compatible with the way the device is
+    /// registered.
+    pub(crate) unsafe fn build_owned(module: &'static ThisModule) -> bindings::file_operations {
+        bindings::file_operations {
+            owner: module.as_ptr(),
+            ..Self::VTABLE
+        }
+    }
+}
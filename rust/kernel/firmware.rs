@@ -0,0 +1,66 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Firmware protocol clients.
+//!
+//! Some SoCs expose clocks, resets, sensors, etc. through a firmware running on an auxiliary
+//! processor, e.g. the BPMP of Tegra SoCs or an SCMI server, which drivers talk to by exchanging
+//! messages over a mailbox. A [`Transport`] sends the request of a [`Message`] to the firmware and
+//! returns its typed reply.
+
+use crate::{
+    error::Result,
+    io_buffer::{ReadableFromBytes, WritableToBytes},
+};
+use core::{
+    mem::{size_of, MaybeUninit},
+    slice,
+};
+
+#[cfg(CONFIG_TEGRA_BPMP)]
+pub mod bpmp;
+
+/// A message of a firmware protocol.
+///
+/// The request and the reply are laid out as in the ABI of the firmware, usually as `repr(C)`
+/// structures.
+pub trait Message {
+    /// The identifier of the message, e.g. the MRQ of BPMP messages.
+    const ID: u32;
+
+    /// The request, which is sent to the firmware.
+    type Request: WritableToBytes;
+
+    /// The reply of the firmware.
+    ///
+    /// The parts of the reply that the firmware doesn't send are zero.
+    type Reply: ReadableFromBytes;
+}
+
+/// A transport of messages to a firmware.
+pub trait Transport {
+    /// Sends the message `id` with the payload `request`, and waits for the reply of the firmware,
+    /// which is written to `reply`.
+    ///
+    /// Fails if the message can't be delivered, or if the firmware returns an error.
+    fn transfer_raw(&self, id: u32, request: &[u8], reply: &mut [u8]) -> Result;
+
+    /// Sends the request of the message `M`, and returns the reply of the firmware.
+    fn transfer<M: Message>(&self, request: &M::Request) -> Result<M::Reply> {
+        // SAFETY: The request has no uninitialised bytes, since it is `WritableToBytes`.
+        let request = unsafe {
+            slice::from_raw_parts(
+                (request as *const M::Request).cast::<u8>(),
+                size_of::<M::Request>(),
+            )
+        };
+        let mut reply = MaybeUninit::<M::Reply>::zeroed();
+        // SAFETY: The reply is initialised with zeroes, and any bytes are a valid reply, since it
+        // is `ReadableFromBytes`.
+        let bytes = unsafe {
+            slice::from_raw_parts_mut(reply.as_mut_ptr().cast::<u8>(), size_of::<M::Reply>())
+        };
+        self.transfer_raw(M::ID, request, bytes)?;
+        // SAFETY: See above.
+        Ok(unsafe { reply.assume_init() })
+    }
+}
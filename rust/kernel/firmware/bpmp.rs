@@ -0,0 +1,170 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Tegra BPMP.
+//!
+//! The BPMP is the boot and power management processor of Tegra186 and later SoCs. Its firmware
+//! controls clocks, resets, power domains and thermal sensors, which drivers request with MRQs
+//! (message requests) sent through the HSP mailbox.
+//!
+//! C header: [`include/soc/tegra/bpmp.h`](../../../../include/soc/tegra/bpmp.h)
+
+use super::Transport;
+use crate::{
+    bindings,
+    device::Device,
+    error::{code::*, from_err_ptr, to_result, Error, Result},
+};
+use core::{
+    ffi::{c_int, c_void},
+    ptr::NonNull,
+};
+
+/// A reference to the BPMP, the kernel's `struct tegra_bpmp`.
+///
+/// Messages are sent with the [`Transport`] implementation, which may sleep, or with
+/// [`Bpmp::atomic`].
+///
+/// # Invariants
+///
+/// `bpmp` is valid, and this holds a reference to it.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::firmware::{bpmp::Bpmp, Message, Transport};
+/// use kernel::io_buffer::{ReadableFromBytes, WritableToBytes};
+/// use kernel::{device::Device, prelude::*};
+///
+/// #[repr(C)]
+/// struct PingRequest {
+///     challenge: u32,
+/// }
+///
+/// // SAFETY: The request has no padding.
+/// unsafe impl WritableToBytes for PingRequest {}
+///
+/// #[repr(C)]
+/// struct PingReply {
+///     reply: u32,
+/// }
+///
+/// // SAFETY: Any bytes are a valid reply.
+/// unsafe impl ReadableFromBytes for PingReply {}
+///
+/// struct Ping;
+///
+/// impl Message for Ping {
+///     const ID: u32 = 0;
+///     type Request = PingRequest;
+///     type Reply = PingReply;
+/// }
+///
+/// fn probe(dev: &Device) -> Result<Bpmp> {
+///     let bpmp = Bpmp::get(dev)?;
+///     let reply = bpmp.transfer::<Ping>(&PingRequest { challenge: 1 })?;
+///     if reply.reply != 2 {
+///         return Err(EIO);
+///     }
+///     Ok(bpmp)
+/// }
+/// ```
+pub struct Bpmp {
+    bpmp: NonNull<bindings::tegra_bpmp>,
+}
+
+impl Bpmp {
+    /// Gets the BPMP referenced by the `nvidia,bpmp` property of the devicetree node of `dev`.
+    ///
+    /// Fails with `EPROBE_DEFER` if the BPMP isn't probed yet.
+    pub fn get(dev: &Device) -> Result<Self> {
+        // SAFETY: `dev` is valid.
+        let bpmp = from_err_ptr(unsafe { bindings::tegra_bpmp_get(dev.as_raw()) })?;
+        // INVARIANT: `tegra_bpmp_get` took a reference to the BPMP.
+        Ok(Self {
+            bpmp: NonNull::new(bpmp).ok_or(ENODEV)?,
+        })
+    }
+
+    /// Returns whether the firmware handles the MRQ `mrq`.
+    pub fn mrq_is_supported(&self, mrq: u32) -> bool {
+        // SAFETY: The BPMP is valid by the type invariants.
+        unsafe { bindings::tegra_bpmp_mrq_is_supported(self.bpmp.as_ptr(), mrq) }
+    }
+
+    /// Returns a transport that sends messages without sleeping, e.g. with interrupts disabled
+    /// during system suspend.
+    pub fn atomic(&self) -> Atomic<'_> {
+        Atomic { bpmp: self }
+    }
+
+    fn transfer_with(
+        &self,
+        id: u32,
+        request: &[u8],
+        reply: &mut [u8],
+        transfer: unsafe extern "C" fn(
+            *mut bindings::tegra_bpmp,
+            *mut bindings::tegra_bpmp_message,
+        ) -> c_int,
+    ) -> Result {
+        let mut msg = bindings::tegra_bpmp_message {
+            mrq: id,
+            tx: bindings::tegra_bpmp_message__bindgen_ty_1 {
+                data: request.as_ptr().cast::<c_void>(),
+                size: request.len(),
+            },
+            rx: bindings::tegra_bpmp_message__bindgen_ty_2 {
+                data: reply.as_mut_ptr().cast::<c_void>(),
+                size: reply.len(),
+                ret: 0,
+            },
+            flags: 0,
+        };
+        // SAFETY: The BPMP is valid by the type invariants, and the buffers of the message are
+        // valid for the duration of the transfer.
+        to_result(unsafe { transfer(self.bpmp.as_ptr(), &mut msg) })?;
+        // The firmware returns negative `BPMP_E*` codes, whose values are those of errnos.
+        if msg.rx.ret < 0 {
+            return Err(Error::try_from_errno(msg.rx.ret).unwrap_or(EIO));
+        }
+        Ok(())
+    }
+}
+
+impl Transport for Bpmp {
+    /// Sends a message to the BPMP and waits for its reply.
+    ///
+    /// This may sleep.
+    fn transfer_raw(&self, id: u32, request: &[u8], reply: &mut [u8]) -> Result {
+        crate::might_sleep!();
+        self.transfer_with(id, request, reply, bindings::tegra_bpmp_transfer)
+    }
+}
+
+impl Drop for Bpmp {
+    fn drop(&mut self) {
+        // SAFETY: This holds a reference to the BPMP by the type invariants.
+        unsafe { bindings::tegra_bpmp_put(self.bpmp.as_ptr()) };
+    }
+}
+
+// SAFETY: Transfers are serialised by the BPMP driver, and the reference can be dropped from any
+// thread.
+unsafe impl Send for Bpmp {}
+
+// SAFETY: See above.
+unsafe impl Sync for Bpmp {}
+
+/// A transport that sends messages to the BPMP without sleeping, see [`Bpmp::atomic`].
+///
+/// Atomic transfers busy-wait for the reply, and may only be used while interrupts are disabled.
+pub struct Atomic<'a> {
+    bpmp: &'a Bpmp,
+}
+
+impl Transport for Atomic<'_> {
+    fn transfer_raw(&self, id: u32, request: &[u8], reply: &mut [u8]) -> Result {
+        self.bpmp
+            .transfer_with(id, request, reply, bindings::tegra_bpmp_transfer_atomic)
+    }
+}
@@ -0,0 +1,70 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Task freezer.
+//!
+//! During system suspend and hibernation, user space processes and freezable kernel threads are
+//! "frozen": parked at well-defined points until the system resumes. Kernel threads are not
+//! freezable by default; a thread that touches hardware should opt in with [`set_freezable`] and
+//! call [`try_to_freeze`] (or use the freezable waits) regularly, otherwise suspend may stall or
+//! the thread may access a suspended device.
+//!
+//! C header: [`include/linux/freezer.h`](../../../../include/linux/freezer.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/power/freezing-of-tasks.html>
+
+use crate::{
+    bindings,
+    error::{code::*, Result},
+};
+use core::time::Duration;
+
+/// Makes the current kernel thread freezable.
+///
+/// Must only be called from a kernel thread.
+pub fn set_freezable() {
+    // SAFETY: FFI call with no additional requirements.
+    unsafe { bindings::set_freezable() };
+}
+
+/// Returns `true` if the current task should freeze.
+pub fn freezing() -> bool {
+    // SAFETY: `get_current` always returns a valid task.
+    unsafe { bindings::freezing(bindings::get_current()) }
+}
+
+/// Enters the refrigerator if a freeze is pending for the current task.
+///
+/// Returns `true` if the task was frozen (and has now been thawed). Must not be called with locks
+/// held, since the task may stay frozen for a long time.
+pub fn try_to_freeze() -> bool {
+    crate::might_sleep!();
+    // SAFETY: FFI call with no additional requirements.
+    unsafe { bindings::try_to_freeze() }
+}
+
+/// Sleeps for up to `timeout` in a state that doesn't block the freezer.
+///
+/// The sleep is interruptible, and ends early if the task is woken up (e.g. by
+/// [`Task::wake_up`]) or receives a signal. The task may be frozen while it sleeps, in which case
+/// it resumes sleeping (or returns) only after it is thawed.
+///
+/// Returns the remaining time if the sleep ended early. Fails with `EINVAL` if `timeout` doesn't
+/// fit in a `u32` of microseconds (about 71 minutes), without sleeping.
+///
+/// [`Task::wake_up`]: crate::task::Task::wake_up
+pub fn sleep_freezable(timeout: Duration) -> Result<Duration> {
+    let usecs = u32::try_from(timeout.as_micros()).map_err(|_| EINVAL)?;
+    crate::might_sleep!();
+    // SAFETY: FFI call with no additional requirements.
+    let jiffies = unsafe { bindings::usecs_to_jiffies(usecs) };
+    // SAFETY: Setting the state of the current task is always allowed.
+    unsafe {
+        bindings::set_current_state((bindings::TASK_INTERRUPTIBLE | bindings::TASK_FREEZABLE) as _)
+    };
+    // SAFETY: FFI call; the task state was set above, as `schedule_timeout` requires.
+    let remaining = unsafe { bindings::schedule_timeout(jiffies as _) };
+    // SAFETY: FFI call with no additional requirements.
+    Ok(Duration::from_micros(
+        unsafe { bindings::jiffies_to_usecs(remaining as _) } as _,
+    ))
+}
@@ -0,0 +1,381 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! File system registration.
+//!
+//! This covers simple in-memory file systems, along the lines of those built with `libfs`: the
+//! whole tree is created when the file system is mounted and doesn't change afterwards.
+//!
+//! C headers: [`include/linux/fs.h`](../../../../include/linux/fs.h) and
+//! [`include/linux/fs_context.h`](../../../../include/linux/fs_context.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/filesystems/vfs.html>
+
+use crate::{
+    bindings,
+    error::{code::*, from_result, to_result, Result},
+    file::{self, OpenAdapter, OperationsVtable},
+    str::CStr,
+    types::Opaque,
+    ThisModule,
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{any::Any, ffi::c_int, marker::PhantomData, mem::MaybeUninit};
+
+/// A file system type.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, file, fs, io_buffer::IoBufferWriter};
+/// # use kernel::prelude::*;
+///
+/// struct Status;
+///
+/// #[vtable]
+/// impl file::Operations for Status {
+///     type Data = ();
+///     type OpenData = ();
+///
+///     fn open(_: &(), _: &file::File) -> Result {
+///         Ok(())
+///     }
+///
+///     fn read(
+///         _: (),
+///         _: &file::File,
+///         writer: &mut impl IoBufferWriter,
+///         offset: u64,
+///     ) -> Result<usize> {
+///         let status = b"ok\n";
+///         let start = status.len().min(offset as usize);
+///         let len = writer.len().min(status.len() - start);
+///         writer.write_slice(&status[start..][..len])?;
+///         Ok(len)
+///     }
+/// }
+///
+/// struct MyFs;
+///
+/// impl fs::Type for MyFs {
+///     const NAME: &'static CStr = c_str!("myfs");
+///     const MAGIC: u32 = 0x6d796673;
+///
+///     fn fill_super(sb: &mut fs::NewSuperBlock<'_>) -> Result {
+///         let dir = sb.create_dir(sb.root(), c_str!("hw"))?;
+///         sb.create_file::<Status>(dir, c_str!("status"), 0o444, ())
+///     }
+/// }
+/// ```
+pub trait Type {
+    /// The name of the file system type, as passed to `mount -t`.
+    const NAME: &'static CStr;
+
+    /// The magic number reported by `statfs`.
+    const MAGIC: u32;
+
+    /// Populates a new superblock.
+    ///
+    /// The root directory already exists when this is called.
+    fn fill_super(sb: &mut NewSuperBlock<'_>) -> Result;
+}
+
+/// A registration of a file system type.
+///
+/// The type is unregistered when this is dropped. Superblocks hold a reference to the module that
+/// owns the type, so the module can't be unloaded, and the registration it holds can't be
+/// dropped, while the file system is mounted.
+///
+/// # Invariants
+///
+/// `fs` is a registered file system type.
+pub struct Registration {
+    fs: Box<Opaque<bindings::file_system_type>>,
+}
+
+impl Registration {
+    /// Registers the file system type `T`.
+    ///
+    /// `module` is the owner of the type, which must be the module that holds the registration,
+    /// i.e. the one passed to its [`Module::init`](crate::Module::init).
+    pub fn try_new<T: Type>(module: &'static ThisModule) -> Result<Self> {
+        // SAFETY: All-zeroes is a valid, unregistered `struct file_system_type`.
+        let fs = Box::try_new(Opaque::new(unsafe {
+            MaybeUninit::<bindings::file_system_type>::zeroed().assume_init()
+        }))?;
+        let ptr = fs.get();
+        // SAFETY: `ptr` is valid and not registered yet, so we have exclusive access to it.
+        unsafe {
+            (*ptr).name = T::NAME.as_char_ptr();
+            (*ptr).owner = module.as_ptr();
+            (*ptr).init_fs_context = Some(init_fs_context_callback::<T>);
+            (*ptr).kill_sb = Some(kill_sb_callback);
+        }
+
+        // The lockdep keys embedded in the type aren't static, so they must be registered.
+        #[cfg(CONFIG_LOCKDEP)]
+        // SAFETY: The keys are valid and live as long as the registration.
+        for_each_key(ptr, |key| unsafe { bindings::lockdep_register_key(key) });
+
+        // SAFETY: `ptr` is a valid file system type, which is boxed so it won't move.
+        if let Err(e) = to_result(unsafe { bindings::register_filesystem(ptr) }) {
+            #[cfg(CONFIG_LOCKDEP)]
+            // SAFETY: The keys were registered above.
+            for_each_key(ptr, |key| unsafe { bindings::lockdep_unregister_key(key) });
+            return Err(e);
+        }
+
+        // INVARIANT: The type was registered above.
+        Ok(Self { fs })
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let ptr = self.fs.get();
+        // SAFETY: By the type invariants, the type is registered.
+        unsafe { bindings::unregister_filesystem(ptr) };
+        #[cfg(CONFIG_LOCKDEP)]
+        // SAFETY: The keys were registered in `try_new`. No superblocks of the type are left,
+        // since they hold a reference to the owning module.
+        for_each_key(ptr, |key| unsafe { bindings::lockdep_unregister_key(key) });
+    }
+}
+
+// SAFETY: File system types can be unregistered from any thread.
+unsafe impl Send for Registration {}
+
+// SAFETY: `Registration` has no methods that take `&self`.
+unsafe impl Sync for Registration {}
+
+#[cfg(CONFIG_LOCKDEP)]
+fn for_each_key(fs: *mut bindings::file_system_type, f: impl Fn(*mut bindings::lock_class_key)) {
+    // SAFETY: The caller passes a valid file system type.
+    let fs = unsafe { &mut *fs };
+    f(&mut fs.s_lock_key);
+    f(&mut fs.s_umount_key);
+    f(&mut fs.s_vfs_rename_key);
+    for key in fs.s_writers_key.iter_mut() {
+        f(key);
+    }
+    f(&mut fs.i_lock_key);
+    f(&mut fs.i_mutex_key);
+    f(&mut fs.invalidate_lock_key);
+    f(&mut fs.i_mutex_dir_key);
+}
+
+/// Per-superblock data, stored in `s_fs_info`.
+struct SbInfo {
+    resources: Vec<Box<dyn Any + Send + Sync>>,
+}
+
+/// A superblock being set up in [`Type::fill_super`].
+pub struct NewSuperBlock<'a> {
+    sb: *mut bindings::super_block,
+    _p: PhantomData<&'a mut bindings::super_block>,
+}
+
+/// A directory of a [`NewSuperBlock`].
+///
+/// This is only a handle, which can't outlive [`Type::fill_super`].
+#[derive(Clone, Copy)]
+pub struct Dir<'a> {
+    dentry: *mut bindings::dentry,
+    _p: PhantomData<&'a bindings::dentry>,
+}
+
+impl<'a> NewSuperBlock<'a> {
+    /// Returns the root directory.
+    pub fn root(&self) -> Dir<'a> {
+        Dir {
+            // SAFETY: `fill_super_callback` sets the root before creating `self`.
+            dentry: unsafe { (*self.sb).s_root },
+            _p: PhantomData,
+        }
+    }
+
+    fn info(&mut self) -> &mut SbInfo {
+        // SAFETY: `fill_super_callback` sets `s_fs_info` before creating `self`.
+        unsafe { &mut *((*self.sb).s_fs_info as *mut SbInfo) }
+    }
+
+    /// Allocates a new inode with the given mode.
+    fn new_inode(&mut self, mode: u32) -> Result<*mut bindings::inode> {
+        // SAFETY: `self.sb` is a valid superblock being set up.
+        let inode = unsafe { bindings::new_inode(self.sb) };
+        if inode.is_null() {
+            return Err(ENOMEM);
+        }
+        // SAFETY: `inode` was just allocated, so we have exclusive access to it.
+        unsafe {
+            (*inode).i_ino = bindings::get_next_ino() as _;
+            (*inode).i_mode = mode as _;
+            let now = bindings::current_time(inode);
+            (*inode).i_atime = now;
+            (*inode).i_mtime = now;
+            (*inode).i_ctime = now;
+        }
+        Ok(inode)
+    }
+
+    /// Adds `inode` to `parent` under `name`, consuming the inode reference.
+    fn add(
+        &mut self,
+        parent: Dir<'a>,
+        name: &CStr,
+        inode: *mut bindings::inode,
+    ) -> Result<Dir<'a>> {
+        // SAFETY: `parent` is a directory of this superblock and `name` a valid C string.
+        let dentry = unsafe { bindings::d_alloc_name(parent.dentry, name.as_char_ptr()) };
+        if dentry.is_null() {
+            // SAFETY: We own the reference to `inode`.
+            unsafe { bindings::iput(inode) };
+            return Err(ENOMEM);
+        }
+        // SAFETY: `dentry` and `inode` are valid. The dentry reference is kept until the
+        // superblock is killed by `kill_litter_super`.
+        unsafe { bindings::d_add(dentry, inode) };
+        Ok(Dir {
+            dentry,
+            _p: PhantomData,
+        })
+    }
+
+    /// Creates a subdirectory named `name` in `parent`.
+    pub fn create_dir(&mut self, parent: Dir<'a>, name: &CStr) -> Result<Dir<'a>> {
+        let inode = self.new_inode(bindings::S_IFDIR | 0o755)?;
+        // SAFETY: `inode` was just allocated and the operations are static. `parent` is a valid
+        // directory with an inode.
+        unsafe {
+            (*inode).i_op = &bindings::simple_dir_inode_operations;
+            (*inode).i_fop = &bindings::simple_dir_operations;
+            // Directories start with two links: their entry in the parent and `.`.
+            bindings::inc_nlink(inode);
+            bindings::inc_nlink((*parent.dentry).d_inode);
+        }
+        self.add(parent, name, inode)
+    }
+
+    /// Creates a regular file named `name` in `parent`, implemented by `T`.
+    ///
+    /// `data` is passed to [`file::Operations::open`] whenever the file is opened, and is kept
+    /// alive until the file system is unmounted.
+    pub fn create_file<T: file::Operations>(
+        &mut self,
+        parent: Dir<'a>,
+        name: &CStr,
+        mode: u16,
+        data: T::OpenData,
+    ) -> Result
+    where
+        T::OpenData: Send + 'static,
+    {
+        let data = Box::try_new(data)?;
+        let ptr = &*data as *const T::OpenData as *mut core::ffi::c_void;
+        self.info().resources.try_push(data)?;
+        let inode = self.new_inode(bindings::S_IFREG | (mode as u32 & 0o777))?;
+        // SAFETY: `inode` was just allocated. The open data lives until the superblock is killed,
+        // and `FsAdapter` extracts it from `i_private`.
+        unsafe {
+            (*inode).i_fop = OperationsVtable::<FsAdapter, T>::build();
+            (*inode).i_private = ptr;
+        }
+        self.add(parent, name, inode)?;
+        Ok(())
+    }
+}
+
+struct FsAdapter;
+
+impl<T: Sync> OpenAdapter<T> for FsAdapter {
+    unsafe fn convert(inode: *mut bindings::inode, _file: *mut bindings::file) -> *const T {
+        // SAFETY: The caller guarantees that the inode belongs to a file created by
+        // `NewSuperBlock::create_file`, which stored a pointer to the open data in `i_private`.
+        unsafe { (*inode).i_private as *const T }
+    }
+}
+
+const SUPER_OPS: bindings::super_operations = bindings::super_operations {
+    statfs: Some(bindings::simple_statfs),
+    drop_inode: Some(bindings::generic_delete_inode),
+    // SAFETY: All other fields are pointers, for which `NULL` means "not implemented".
+    ..unsafe { MaybeUninit::zeroed().assume_init() }
+};
+
+unsafe extern "C" fn init_fs_context_callback<T: Type>(fc: *mut bindings::fs_context) -> c_int {
+    // SAFETY: The caller passes a valid context being initialised.
+    unsafe { (*fc).ops = ContextVtable::<T>::build() };
+    0
+}
+
+unsafe extern "C" fn kill_sb_callback(sb: *mut bindings::super_block) {
+    // SAFETY: The caller passes a valid superblock that is being destroyed.
+    let info = unsafe { (*sb).s_fs_info as *mut SbInfo };
+    // SAFETY: Drops the dentries created by `NewSuperBlock::add` along with the superblock.
+    unsafe { bindings::kill_litter_super(sb) };
+    if !info.is_null() {
+        // SAFETY: `info` was allocated by `fill_super_callback`, and no file can be open anymore.
+        drop(unsafe { Box::from_raw(info) });
+    }
+}
+
+struct ContextVtable<T>(PhantomData<T>);
+
+impl<T: Type> ContextVtable<T> {
+    unsafe extern "C" fn get_tree_callback(fc: *mut bindings::fs_context) -> c_int {
+        // SAFETY: The caller passes a valid context.
+        unsafe { bindings::get_tree_nodev(fc, Some(Self::fill_super_callback)) }
+    }
+
+    unsafe extern "C" fn fill_super_callback(
+        sb: *mut bindings::super_block,
+        _fc: *mut bindings::fs_context,
+    ) -> c_int {
+        from_result(|| {
+            let info = Box::try_new(SbInfo {
+                resources: Vec::new(),
+            })?;
+            // SAFETY: The caller passes a new superblock that we have exclusive access to. If
+            // anything below fails, `kill_sb_callback` frees `s_fs_info`.
+            unsafe {
+                (*sb).s_fs_info = Box::into_raw(info).cast();
+                (*sb).s_magic = T::MAGIC as _;
+                (*sb).s_op = &SUPER_OPS;
+                (*sb).s_blocksize = bindings::PAGE_SIZE as _;
+                (*sb).s_blocksize_bits = bindings::PAGE_SHIFT as _;
+                (*sb).s_time_gran = 1;
+            }
+
+            let mut new = NewSuperBlock {
+                sb,
+                _p: PhantomData,
+            };
+            let inode = new.new_inode(bindings::S_IFDIR | 0o755)?;
+            // SAFETY: `inode` was just allocated and the operations are static.
+            unsafe {
+                (*inode).i_op = &bindings::simple_dir_inode_operations;
+                (*inode).i_fop = &bindings::simple_dir_operations;
+                bindings::set_nlink(inode, 2);
+            }
+            // SAFETY: `inode` is valid; `d_make_root` consumes the reference even on failure.
+            let root = unsafe { bindings::d_make_root(inode) };
+            if root.is_null() {
+                return Err(ENOMEM);
+            }
+            // SAFETY: We have exclusive access to the superblock.
+            unsafe { (*sb).s_root = root };
+
+            T::fill_super(&mut new)?;
+            Ok(0)
+        })
+    }
+
+    const VTABLE: bindings::fs_context_operations = bindings::fs_context_operations {
+        get_tree: Some(Self::get_tree_callback),
+        // SAFETY: All other fields are pointers, for which `NULL` means "not implemented".
+        ..unsafe { MaybeUninit::zeroed().assume_init() }
+    };
+
+    const fn build() -> &'static bindings::fs_context_operations {
+        &Self::VTABLE
+    }
+}
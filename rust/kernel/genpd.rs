@@ -0,0 +1,354 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Generic power domains.
+//!
+//! A driver of power partitions, e.g. of a power management controller, creates a [`Domain`]
+//! for each partition and registers them with a [`Provider`], which the `power-domains`
+//! properties of the devicetree refer to. The devices in the partitions are then attached to
+//! their domains by their bus, or with an [`Attachment`], and the domains are powered on and off
+//! as the devices are resumed and suspended.
+//!
+//! C header: [`include/linux/pm_domain.h`](../../../../include/linux/pm_domain.h)
+
+use crate::{
+    bindings, device,
+    error::{code::*, from_err_ptr, from_result, to_result, Result},
+    str::{CStr, CString},
+    types::{ARef, Opaque},
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    ffi::c_int,
+    fmt,
+    marker::PhantomPinned,
+    mem::{ManuallyDrop, MaybeUninit},
+    pin::Pin,
+};
+use macros::vtable;
+
+/// Flags of power domains, the kernel's `GENPD_FLAG_*` values.
+pub mod flags {
+    /// The domain is never powered off.
+    pub const ALWAYS_ON: u32 = crate::bindings::GENPD_FLAG_ALWAYS_ON;
+    /// The domain is kept powered on during system suspend if one of its devices can wake the
+    /// system up.
+    pub const ACTIVE_WAKEUP: u32 = crate::bindings::GENPD_FLAG_ACTIVE_WAKEUP;
+    /// The domain is kept powered on at runtime, but powered off during system suspend.
+    pub const RPM_ALWAYS_ON: u32 = crate::bindings::GENPD_FLAG_RPM_ALWAYS_ON;
+    /// The clocks of the devices in the domain are managed by runtime PM.
+    pub const PM_CLK: u32 = crate::bindings::GENPD_FLAG_PM_CLK;
+}
+
+/// The operations of a power domain, the kernel's `struct generic_pm_domain` callbacks.
+///
+/// The driver data of the domain implements this trait. The operations may sleep.
+#[vtable]
+pub trait Operations: Send + Sync + Sized + 'static {
+    /// Powers the domain on.
+    fn power_on(data: &Self) -> Result;
+
+    /// Powers the domain off.
+    fn power_off(data: &Self) -> Result;
+}
+
+/// A power domain, the kernel's `struct generic_pm_domain`.
+///
+/// # Invariants
+///
+/// The domain is initialised, and valid while references to it exist.
+#[repr(transparent)]
+pub struct Genpd(Opaque<bindings::generic_pm_domain>);
+
+impl Genpd {
+    /// Returns the raw `struct generic_pm_domain` pointer.
+    pub fn as_raw(&self) -> *mut bindings::generic_pm_domain {
+        self.0.get()
+    }
+
+    /// Makes `sub` a subdomain of the domain, which is then kept powered on while `sub` is.
+    pub fn add_subdomain(&self, sub: &Genpd) -> Result {
+        // SAFETY: Both domains are initialised by the type invariants.
+        to_result(unsafe { bindings::pm_genpd_add_subdomain(self.as_raw(), sub.as_raw()) })
+    }
+
+    /// Undoes [`Genpd::add_subdomain`].
+    pub fn remove_subdomain(&self, sub: &Genpd) -> Result {
+        // SAFETY: Both domains are initialised by the type invariants.
+        to_result(unsafe { bindings::pm_genpd_remove_subdomain(self.as_raw(), sub.as_raw()) })
+    }
+}
+
+// SAFETY: The domain is protected by its own lock.
+unsafe impl Send for Genpd {}
+
+// SAFETY: The domain is protected by its own lock.
+unsafe impl Sync for Genpd {}
+
+#[repr(C)]
+struct Inner<T> {
+    // Must be the first field, see `Domain::data_of`.
+    genpd: Genpd,
+    name: CString,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+/// A power domain with driver data.
+///
+/// The domain is removed when this is dropped. It can only be removed once it has no devices,
+/// subdomains nor provider anymore, and is leaked otherwise.
+///
+/// # Invariants
+///
+/// `inner.genpd` is a domain initialised by [`Domain::new`], whose callbacks are those of `T`.
+pub struct Domain<T: Operations> {
+    inner: ManuallyDrop<Pin<Box<Inner<T>>>>,
+}
+
+impl<T: Operations> Domain<T> {
+    /// Creates a power domain named `name`, which may be built at runtime, with the flags
+    /// `flags`, see [`flags`], and the driver data `data`.
+    ///
+    /// `is_off` tells whether the domain is initially powered off.
+    pub fn new(name: fmt::Arguments<'_>, flags: u32, is_off: bool, data: T) -> Result<Self> {
+        crate::might_sleep!();
+        let name = CString::try_from_fmt(name)?;
+        let inner = Pin::from(Box::try_new(Inner {
+            // SAFETY: All-zeroes is a valid, uninitialised domain.
+            genpd: Genpd(Opaque::new(unsafe { MaybeUninit::zeroed().assume_init() })),
+            name,
+            data,
+            _pin: PhantomPinned,
+        })?);
+        let genpd = inner.genpd.as_raw();
+        // SAFETY: The domain isn't initialised yet, so nothing else uses it. The domain and its
+        // name are pinned, and it is removed before they are freed.
+        unsafe {
+            (*genpd).name = inner.name.as_char_ptr();
+            (*genpd).flags = flags;
+            (*genpd).power_on = Some(Self::power_on_callback);
+            (*genpd).power_off = Some(Self::power_off_callback);
+        }
+        // SAFETY: The domain was set up above.
+        to_result(unsafe { bindings::pm_genpd_init(genpd, core::ptr::null_mut(), is_off) })?;
+        // INVARIANT: The domain was initialised above.
+        Ok(Self {
+            inner: ManuallyDrop::new(inner),
+        })
+    }
+
+    /// Returns the power domain.
+    pub fn genpd(&self) -> &Genpd {
+        &self.inner.genpd
+    }
+
+    /// Returns the driver data of the domain.
+    pub fn data(&self) -> &T {
+        &self.inner.data
+    }
+
+    /// Returns the driver data of the domain `genpd`.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `genpd` is a domain initialised by a `Domain<T>`.
+    unsafe fn data_of<'a>(genpd: *mut bindings::generic_pm_domain) -> &'a T {
+        // SAFETY: `genpd` is the first field of an `Inner<T>`, which is `repr(C)`, and outlives
+        // the domain by the safety requirements.
+        unsafe { &(*genpd.cast::<Inner<T>>()).data }
+    }
+
+    unsafe extern "C" fn power_on_callback(genpd: *mut bindings::generic_pm_domain) -> c_int {
+        from_result(|| {
+            // SAFETY: The genpd core calls this with a domain initialised by `new`.
+            T::power_on(unsafe { Self::data_of(genpd) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn power_off_callback(genpd: *mut bindings::generic_pm_domain) -> c_int {
+        from_result(|| {
+            // SAFETY: The genpd core calls this with a domain initialised by `new`.
+            T::power_off(unsafe { Self::data_of(genpd) })?;
+            Ok(0)
+        })
+    }
+}
+
+impl<T: Operations> Drop for Domain<T> {
+    fn drop(&mut self) {
+        // SAFETY: The domain was initialised by the type invariants.
+        let ret = unsafe { bindings::pm_genpd_remove(self.inner.genpd.as_raw()) };
+        if ret != 0 {
+            // The genpd core still uses the domain, so it can't be freed.
+            crate::pr_warn!("Leaking power domain that is still in use\n");
+            return;
+        }
+        // SAFETY: The domain was removed above, and isn't used anymore.
+        unsafe { ManuallyDrop::drop(&mut self.inner) };
+    }
+}
+
+/// A provider of power domains, registered for a devicetree node.
+///
+/// The provider is removed when this is dropped.
+///
+/// # Invariants
+///
+/// A provider of the domains `_domains`, described by `_onecell` and `_raw`, was added for `np`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{device::Device, fmt, prelude::*};
+/// use kernel::genpd::{self, Domain, Provider};
+///
+/// struct Partition {
+///     id: u32,
+/// }
+///
+/// #[vtable]
+/// impl genpd::Operations for Partition {
+///     fn power_on(data: &Self) -> Result {
+///         pr_info!("powering partition {} on\n", data.id);
+///         Ok(())
+///     }
+///
+///     fn power_off(data: &Self) -> Result {
+///         pr_info!("powering partition {} off\n", data.id);
+///         Ok(())
+///     }
+/// }
+///
+/// fn probe(dev: &Device) -> Result<(Domain<Partition>, Domain<Partition>)> {
+///     let np = dev.of_node().ok_or(ENODEV)?;
+///     let gpu = Domain::new(fmt!("3d"), 0, true, Partition { id: 0 })?;
+///     let venc = Domain::new(fmt!("venc{}", 0), 0, true, Partition { id: 1 })?;
+///     let provider = Provider::register(np, &[gpu.genpd(), venc.genpd()])?;
+///     // The provider must be dropped before the domains, e.g. by keeping it in a field that is
+///     // declared before them.
+///     drop(provider);
+///     Ok((gpu, venc))
+/// }
+/// ```
+#[cfg(CONFIG_PM_GENERIC_DOMAINS_OF)]
+pub struct Provider<'a> {
+    np: ARef<crate::of::DeviceNode>,
+    _onecell: Box<bindings::genpd_onecell_data>,
+    _raw: Vec<*mut bindings::generic_pm_domain>,
+    _domains: Vec<&'a Genpd>,
+}
+
+#[cfg(CONFIG_PM_GENERIC_DOMAINS_OF)]
+impl<'a> Provider<'a> {
+    /// Registers a provider of the domains `domains` for the node `np`, which devices refer to
+    /// by their index in `domains`.
+    pub fn register(np: &crate::of::DeviceNode, domains: &[&'a Genpd]) -> Result<Self> {
+        crate::might_sleep!();
+        let mut list = Vec::try_with_capacity(domains.len())?;
+        let mut raw = Vec::try_with_capacity(domains.len())?;
+        for &genpd in domains {
+            list.try_push(genpd)?;
+            raw.try_push(genpd.as_raw())?;
+        }
+
+        // SAFETY: All other fields are optional, for which zero is valid.
+        let mut onecell: Box<bindings::genpd_onecell_data> =
+            Box::try_new(unsafe { MaybeUninit::zeroed().assume_init() })?;
+        onecell.domains = raw.as_mut_ptr();
+        onecell.num_domains = raw.len() as _;
+
+        // SAFETY: `np` is valid. The onecell data and the array of domains are on the heap, and
+        // the domains are borrowed, and the provider is removed before they are freed.
+        to_result(unsafe { bindings::of_genpd_add_provider_onecell(np.as_raw(), &mut *onecell) })?;
+        // INVARIANT: The provider was added above.
+        Ok(Self {
+            np: np.into(),
+            _onecell: onecell,
+            _raw: raw,
+            _domains: list,
+        })
+    }
+}
+
+#[cfg(CONFIG_PM_GENERIC_DOMAINS_OF)]
+impl Drop for Provider<'_> {
+    fn drop(&mut self) {
+        // SAFETY: The provider was added by the type invariants. Removing it keeps devices from
+        // looking the domains up.
+        unsafe { bindings::of_genpd_del_provider(self.np.as_raw()) };
+    }
+}
+
+// SAFETY: The provider can be removed from any thread, and the domains are `Sync`.
+#[cfg(CONFIG_PM_GENERIC_DOMAINS_OF)]
+unsafe impl Send for Provider<'_> {}
+
+// SAFETY: The provider has no methods that take `&self`.
+#[cfg(CONFIG_PM_GENERIC_DOMAINS_OF)]
+unsafe impl Sync for Provider<'_> {}
+
+/// The attachment of a device to its power domain, as described by the devicetree.
+///
+/// The device is detached when this is dropped.
+///
+/// Devices on platform buses are attached to their domain by the bus, and only devices with
+/// several domains need to attach to them by name.
+///
+/// # Invariants
+///
+/// `dev` is attached to its power domain, either itself or as a virtual device created for the
+/// attachment.
+pub struct Attachment {
+    dev: ARef<device::Device>,
+}
+
+impl Attachment {
+    /// Attaches `dev` to its power domain, and powers it on if `power_on` is `true`.
+    ///
+    /// Returns `None` if the device has no power domain.
+    pub fn attach(dev: &device::Device, power_on: bool) -> Result<Option<Self>> {
+        crate::might_sleep!();
+        // SAFETY: `dev` is valid.
+        let ret = unsafe { bindings::dev_pm_domain_attach(dev.as_raw(), power_on) };
+        to_result(ret)?;
+        if ret == 0 {
+            return Ok(None);
+        }
+        // INVARIANT: The device was attached above.
+        Ok(Some(Self { dev: dev.into() }))
+    }
+
+    /// Attaches `dev` to its power domain named `name` in its `power-domain-names` property,
+    /// through a virtual device, which the domain can be controlled through with runtime PM.
+    ///
+    /// Returns `None` if the device has no power domains.
+    pub fn attach_by_name(dev: &device::Device, name: &CStr) -> Result<Option<Self>> {
+        crate::might_sleep!();
+        // SAFETY: `dev` is valid, and `name` is only used during the call.
+        let virt = from_err_ptr(unsafe {
+            bindings::dev_pm_domain_attach_by_name(dev.as_raw(), name.as_char_ptr())
+        })?;
+        if virt.is_null() {
+            return Ok(None);
+        }
+        // INVARIANT: The virtual device was created and attached above.
+        Ok(Some(Self {
+            // SAFETY: The virtual device was just created, and is valid.
+            dev: unsafe { device::Device::from_raw(virt) },
+        }))
+    }
+
+    /// Returns the attached device, which is virtual if it was attached by name.
+    pub fn device(&self) -> &device::Device {
+        &self.dev
+    }
+}
+
+impl Drop for Attachment {
+    fn drop(&mut self) {
+        // SAFETY: The device is attached by the type invariants. Virtual devices are
+        // unregistered by this, but stay allocated until the reference in `dev` is dropped.
+        unsafe { bindings::dev_pm_domain_detach(self.dev.as_raw(), true) };
+    }
+}
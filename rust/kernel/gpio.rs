@@ -0,0 +1,140 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! GPIO consumers.
+//!
+//! Drivers get the GPIOs wired to their devices, e.g. enable and reset lines, with [`Desc::get`],
+//! and drive them with [`Desc::set_value_cansleep`]. Values are logical: the polarity given in
+//! the devicetree is applied by the GPIO core, so `true` means asserted.
+//!
+//! C header: [`include/linux/gpio/consumer.h`](../../../../include/linux/gpio/consumer.h)
+
+use crate::{
+    bindings,
+    device::Device,
+    error::{from_err_ptr, to_result, Error, Result},
+    str::CStr,
+};
+use core::ptr::{self, NonNull};
+
+/// How a GPIO is configured when it is requested, the kernel's `enum gpiod_flags`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flags {
+    /// The GPIO is left as it is.
+    AsIs,
+    /// The GPIO is an input.
+    In,
+    /// The GPIO is an output, deasserted.
+    OutLow,
+    /// The GPIO is an output, asserted.
+    OutHigh,
+}
+
+impl Flags {
+    fn as_raw(self) -> bindings::gpiod_flags {
+        match self {
+            Self::AsIs => bindings::gpiod_flags_GPIOD_ASIS,
+            Self::In => bindings::gpiod_flags_GPIOD_IN,
+            Self::OutLow => bindings::gpiod_flags_GPIOD_OUT_LOW,
+            Self::OutHigh => bindings::gpiod_flags_GPIOD_OUT_HIGH,
+        }
+    }
+}
+
+/// A GPIO used by a driver, the kernel's `struct gpio_desc`.
+///
+/// The GPIO is released when this is dropped.
+///
+/// # Invariants
+///
+/// `desc` is a valid GPIO, requested by `Desc`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, delay, device::Device, gpio, prelude::*};
+///
+/// fn reset(dev: &Device) -> Result<Option<gpio::Desc>> {
+///     let reset = gpio::Desc::get_optional(dev, Some(c_str!("reset")), gpio::Flags::OutHigh)?;
+///     if let Some(reset) = &reset {
+///         delay::usleep_range(1000, 2000);
+///         reset.set_value_cansleep(false);
+///     }
+///     Ok(reset)
+/// }
+/// ```
+pub struct Desc {
+    desc: NonNull<bindings::gpio_desc>,
+}
+
+impl Desc {
+    /// Requests the GPIO of `dev` named `con_id` in the devicetree, i.e. its `<con_id>-gpios`
+    /// property, or its `gpios` property if `con_id` is `None`, and configures it with `flags`.
+    ///
+    /// This may fail with `EPROBE_DEFER` if the GPIO controller isn't probed yet.
+    pub fn get(dev: &Device, con_id: Option<&CStr>, flags: Flags) -> Result<Self> {
+        Self::get_optional(dev, con_id, flags)?.ok_or(crate::error::code::ENOENT)
+    }
+
+    /// Like [`Desc::get`], but returns `None` if `dev` has no such GPIO.
+    pub fn get_optional(dev: &Device, con_id: Option<&CStr>, flags: Flags) -> Result<Option<Self>> {
+        let con_id = con_id.map_or(ptr::null(), |id| id.as_char_ptr());
+        // SAFETY: `dev` is valid, and `con_id` is either null or a valid string.
+        let desc = from_err_ptr(unsafe {
+            bindings::gpiod_get_optional(dev.as_raw(), con_id, flags.as_raw())
+        })?;
+        // INVARIANT: The GPIO was requested if it isn't null.
+        Ok(NonNull::new(desc).map(|desc| Self { desc }))
+    }
+
+    fn as_raw(&self) -> *mut bindings::gpio_desc {
+        self.desc.as_ptr()
+    }
+
+    /// Returns the logical value of the GPIO.
+    ///
+    /// This may sleep, e.g. for GPIOs of I2C expanders.
+    pub fn value_cansleep(&self) -> Result<bool> {
+        crate::might_sleep!();
+        // SAFETY: The GPIO is valid by the type invariants.
+        let ret = unsafe { bindings::gpiod_get_value_cansleep(self.as_raw()) };
+        if ret < 0 {
+            Err(Error::from_errno(ret))
+        } else {
+            Ok(ret != 0)
+        }
+    }
+
+    /// Sets the logical value of the GPIO, which must be an output.
+    ///
+    /// This may sleep, e.g. for GPIOs of I2C expanders.
+    pub fn set_value_cansleep(&self, value: bool) {
+        crate::might_sleep!();
+        // SAFETY: The GPIO is valid by the type invariants.
+        unsafe { bindings::gpiod_set_value_cansleep(self.as_raw(), value as _) };
+    }
+
+    /// Configures the GPIO as an output, with the logical value `value`.
+    pub fn direction_output(&self, value: bool) -> Result {
+        // SAFETY: The GPIO is valid by the type invariants.
+        to_result(unsafe { bindings::gpiod_direction_output(self.as_raw(), value as _) })
+    }
+
+    /// Configures the GPIO as an input.
+    pub fn direction_input(&self) -> Result {
+        // SAFETY: The GPIO is valid by the type invariants.
+        to_result(unsafe { bindings::gpiod_direction_input(self.as_raw()) })
+    }
+}
+
+impl Drop for Desc {
+    fn drop(&mut self) {
+        // SAFETY: The GPIO was requested by `Desc` by the type invariants.
+        unsafe { bindings::gpiod_put(self.as_raw()) };
+    }
+}
+
+// SAFETY: The GPIO can be used and released from any thread.
+unsafe impl Send for Desc {}
+
+// SAFETY: The GPIO core serialises accesses to the GPIO.
+unsafe impl Sync for Desc {}
@@ -0,0 +1,528 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Hash tables.
+//!
+//! [`HashTable`] has a fixed number of buckets, like the kernel's `DECLARE_HASHTABLE`, and is
+//! accessed through mutable references, e.g. under a lock. [`RhashTable`] is resizable, like
+//! `struct rhashtable`, and supports lookups under RCU, concurrently with insertions and
+//! removals, which makes it suited to hot lookup paths.
+//!
+//! Keys implement [`HashKey`], which the integer types do.
+//!
+//! C headers: [`include/linux/hashtable.h`](../../../../include/linux/hashtable.h),
+//! [`include/linux/hash.h`](../../../../include/linux/hash.h) and
+//! [`include/linux/rhashtable.h`](../../../../include/linux/rhashtable.h)
+
+use crate::{
+    bindings,
+    error::{code::*, to_result, Error, Result},
+    init::{self, PinInit},
+    sync::rcu,
+    types::Opaque,
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    ffi::c_void,
+    marker::{PhantomData, PhantomPinned},
+    mem::{self, MaybeUninit},
+    ptr::{self, addr_of, addr_of_mut, NonNull},
+};
+
+/// The multiplier of [`hash_32`], `GOLDEN_RATIO_32` in C.
+pub const GOLDEN_RATIO_32: u32 = 0x61c88647;
+
+/// The multiplier of [`hash_64`], `GOLDEN_RATIO_64` in C.
+pub const GOLDEN_RATIO_64: u64 = 0x61c8864680b583eb;
+
+/// Hashes `val` into `bits` bits, which must be between 1 and 32, like `hash_32` in C.
+pub const fn hash_32(val: u32, bits: u32) -> u32 {
+    val.wrapping_mul(GOLDEN_RATIO_32) >> (32 - bits)
+}
+
+/// Hashes `val` into `bits` bits, which must be between 1 and 32, like `hash_64` in C on 64-bit
+/// architectures.
+pub const fn hash_64(val: u64, bits: u32) -> u32 {
+    (val.wrapping_mul(GOLDEN_RATIO_64) >> (64 - bits)) as u32
+}
+
+/// A key of a hash table.
+///
+/// # Safety
+///
+/// Keys must have no padding bytes nor interior mutability, and two keys must be equal exactly
+/// when their bytes are, since [`RhashTable`] hashes and compares their bytes.
+pub unsafe trait HashKey: Copy + Eq {
+    /// Hashes the key into `bits` bits, which are between 1 and 32.
+    fn hash(&self, bits: u32) -> u32;
+}
+
+macro_rules! impl_hash_key {
+    ($hash:ident, $unsigned:ty, $($t:ty),*) => {
+        $(
+            // SAFETY: Integers have no padding, and are equal exactly when their bytes are.
+            unsafe impl HashKey for $t {
+                fn hash(&self, bits: u32) -> u32 {
+                    $hash(*self as $unsigned, bits)
+                }
+            }
+        )*
+    };
+}
+
+impl_hash_key!(hash_32, u32, u8, u16, u32, i8, i16, i32);
+impl_hash_key!(hash_64, u64, u64, i64, usize, isize);
+
+/// A node of a [`HashTable`].
+///
+/// The link comes first, so that a pointer to it is a pointer to the node.
+#[repr(C)]
+struct Node<K, V> {
+    link: bindings::hlist_node,
+    key: K,
+    value: V,
+}
+
+/// A hash table with a fixed number of buckets, which maps keys of type `K` to values of type
+/// `V`.
+///
+/// # Invariants
+///
+/// `buckets` has `1 << bits` entries, and is never reallocated. Their nodes are links of
+/// [`Node<K, V>`] allocated with [`Box`], and owned by the table, and are in the bucket that
+/// their key hashes to. There are `len` nodes, whose keys are unique.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{hashtable::HashTable, prelude::*};
+///
+/// struct Client {
+///     requests: u64,
+/// }
+///
+/// fn account(clients: &mut HashTable<u32, Client>, id: u32) -> Result {
+///     match clients.get_mut(&id) {
+///         Some(client) => client.requests += 1,
+///         None => {
+///             clients.try_insert(id, Client { requests: 1 })?;
+///         }
+///     }
+///     Ok(())
+/// }
+///
+/// fn dump(clients: &HashTable<u32, Client>) {
+///     for (id, client) in clients {
+///         pr_info!("client {}: {} requests\n", id, client.requests);
+///     }
+/// }
+///
+/// fn new_clients() -> Result<HashTable<u32, Client>> {
+///     HashTable::try_new(6)
+/// }
+/// ```
+pub struct HashTable<K: HashKey, V> {
+    buckets: Vec<bindings::hlist_head>,
+    bits: u32,
+    len: usize,
+    _p: PhantomData<Box<Node<K, V>>>,
+}
+
+impl<K: HashKey, V> HashTable<K, V> {
+    /// Creates an empty table with `1 << bits` buckets, where `bits` is between 1 and 31.
+    pub fn try_new(bits: u32) -> Result<Self> {
+        if !(1..=31).contains(&bits) {
+            return Err(EINVAL);
+        }
+        let mut buckets = Vec::try_with_capacity(1 << bits)?;
+        for _ in 0..1 << bits {
+            buckets.try_push(bindings::hlist_head {
+                first: ptr::null_mut(),
+            })?;
+        }
+        // INVARIANT: The buckets were allocated above, and are empty.
+        Ok(Self {
+            buckets,
+            bits,
+            len: 0,
+            _p: PhantomData,
+        })
+    }
+
+    /// Returns the number of entries in the table.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the table is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the index of the bucket of `key`.
+    fn bucket_index(&self, key: &K) -> usize {
+        key.hash(self.bits) as usize
+    }
+
+    /// Returns the node with the given key, if any.
+    fn find(&self, key: &K) -> Option<NonNull<Node<K, V>>> {
+        let mut link = self.buckets[self.bucket_index(key)].first;
+        while !link.is_null() {
+            let node = link as *mut Node<K, V>;
+            // SAFETY: By the type invariants, non-null links are those of a node owned by the
+            // table.
+            unsafe {
+                if (*node).key == *key {
+                    return NonNull::new(node);
+                }
+                link = (*link).next;
+            }
+        }
+        None
+    }
+
+    /// Inserts `value` for `key`, and returns the value it replaced, if any.
+    ///
+    /// This allocates memory, unless there is already an entry for `key`.
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<Option<V>> {
+        if let Some(node) = self.find(&key) {
+            // SAFETY: The node is owned by the table, which is mutably borrowed.
+            let old = unsafe { &mut (*node.as_ptr()).value };
+            return Ok(Some(mem::replace(old, value)));
+        }
+        let node = Box::into_raw(Box::try_new(Node {
+            // SAFETY: All fields are pointers, for which zero is valid. The link is set below.
+            link: unsafe { mem::zeroed() },
+            key,
+            value,
+        })?);
+        let index = self.bucket_index(&key);
+        // INVARIANT: The node is added to the bucket of its key, which it wasn't in yet, and the
+        // table owns it from then on.
+        // SAFETY: The node is valid, as it was just converted from a `Box`, and the bucket is
+        // valid.
+        unsafe { bindings::hlist_add_head(addr_of_mut!((*node).link), &mut self.buckets[index]) };
+        self.len += 1;
+        Ok(None)
+    }
+
+    /// Returns a reference to the value for `key`, if any.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        // SAFETY: The node is owned by the table, which is borrowed for as long as the reference.
+        self.find(key)
+            .map(|node| unsafe { &(*node.as_ptr()).value })
+    }
+
+    /// Returns a mutable reference to the value for `key`, if any.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        // SAFETY: The node is owned by the table, which is mutably borrowed for as long as the
+        // reference.
+        self.find(key)
+            .map(|node| unsafe { &mut (*node.as_ptr()).value })
+    }
+
+    /// Returns whether the table has an entry for `key`.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find(key).is_some()
+    }
+
+    /// Removes the entry for `key`, and returns its value, if any.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let node = self.find(key)?.as_ptr();
+        self.len -= 1;
+        // SAFETY: The node is in the table. The table gives up its ownership, and the node was
+        // allocated with `Box` by the type invariants.
+        unsafe {
+            bindings::hlist_del(addr_of_mut!((*node).link));
+            Some(Box::from_raw(node).value)
+        }
+    }
+
+    /// Returns an iterator over the entries in the bucket of `key`.
+    ///
+    /// They include the entry for `key`, if any, and entries for other keys that hash to the
+    /// same bucket, like `hash_for_each_possible` in C.
+    pub fn bucket(&self, key: &K) -> Iter<'_, K, V> {
+        let index = self.bucket_index(key);
+        Iter {
+            buckets: &self.buckets[index..=index],
+            next: ptr::null_mut(),
+            _p: PhantomData,
+        }
+    }
+
+    /// Returns an iterator over the entries of the table, bucket by bucket, like
+    /// `hash_for_each` in C.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            buckets: &self.buckets,
+            next: ptr::null_mut(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<K: HashKey, V> Drop for HashTable<K, V> {
+    fn drop(&mut self) {
+        for bucket in self.buckets.iter() {
+            let mut link = bucket.first;
+            while !link.is_null() {
+                let node = link as *mut Node<K, V>;
+                // SAFETY: By the type invariants, the node was allocated with `Box` and is owned
+                // by the table. Its successor is read before it is freed.
+                unsafe {
+                    link = (*link).next;
+                    drop(Box::from_raw(node));
+                }
+            }
+        }
+    }
+}
+
+// SAFETY: The table owns its keys and values, so it can be sent to another thread if they can.
+unsafe impl<K: HashKey + Send, V: Send> Send for HashTable<K, V> {}
+
+// SAFETY: Shared references to the table only give out shared references to its keys and
+// values.
+unsafe impl<K: HashKey + Sync, V: Sync> Sync for HashTable<K, V> {}
+
+/// An iterator over the entries of a [`HashTable`], returned by [`HashTable::iter`] and
+/// [`HashTable::bucket`].
+pub struct Iter<'a, K: HashKey, V> {
+    buckets: &'a [bindings::hlist_head],
+    next: *mut bindings::hlist_node,
+    _p: PhantomData<&'a HashTable<K, V>>,
+}
+
+impl<'a, K: HashKey, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next.is_null() {
+            let (first, rest) = self.buckets.split_first()?;
+            self.next = first.first;
+            self.buckets = rest;
+        }
+        let node = self.next as *mut Node<K, V>;
+        // SAFETY: `next` is the link of a node owned by the table, which is borrowed for `'a`.
+        unsafe {
+            self.next = (*self.next).next;
+            Some((&(*node).key, &(*node).value))
+        }
+    }
+}
+
+impl<'a, K: HashKey, V> IntoIterator for &'a HashTable<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// SAFETY: The iterator only gives out shared references to the keys and values.
+unsafe impl<K: HashKey + Sync, V: Sync> Send for Iter<'_, K, V> {}
+
+// SAFETY: The iterator has no methods that take `&self`.
+unsafe impl<K: HashKey + Sync, V: Sync> Sync for Iter<'_, K, V> {}
+
+/// A node of an [`RhashTable`].
+#[repr(C)]
+struct RNode<K, V> {
+    head: bindings::rhash_head,
+    key: K,
+    value: V,
+}
+
+/// A resizable hash table, which maps keys of type `K` to values of type `V`.
+///
+/// The table grows and shrinks as entries are inserted and removed. Lookups run under RCU, so
+/// they don't block, nor are blocked by, insertions and removals, which may run concurrently
+/// from several threads.
+///
+/// # Invariants
+///
+/// `ht` is an initialised `struct rhashtable` with the parameters `params`, whose objects are
+/// [`RNode<K, V>`] allocated with [`Box`], and owned by the table. Objects are only freed after
+/// an RCU grace period once they are removed from the table.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{hashtable::RhashTable, prelude::*, sync::rcu};
+///
+/// struct Session {
+///     uid: u32,
+/// }
+///
+/// fn login(sessions: &RhashTable<u64, Session>, cookie: u64, uid: u32) -> Result {
+///     sessions.insert(cookie, Session { uid })
+/// }
+///
+/// fn lookup_uid(sessions: &RhashTable<u64, Session>, cookie: u64) -> Option<u32> {
+///     let guard = rcu::read_lock();
+///     sessions.get(&cookie, &guard).map(|s| s.uid)
+/// }
+///
+/// fn logout(sessions: &RhashTable<u64, Session>, cookie: u64) -> Result {
+///     sessions.remove(&cookie).ok_or(ENOENT)?;
+///     Ok(())
+/// }
+///
+/// fn new_sessions() -> Result<Pin<Box<RhashTable<u64, Session>>>> {
+///     Box::pin_init(RhashTable::new())
+/// }
+/// ```
+pub struct RhashTable<K: HashKey, V> {
+    ht: Opaque<bindings::rhashtable>,
+    params: bindings::rhashtable_params,
+    _p: PhantomData<Box<RNode<K, V>>>,
+    _pin: PhantomPinned,
+}
+
+impl<K: HashKey, V> RhashTable<K, V> {
+    /// Returns the parameters of tables of [`RNode<K, V>`].
+    fn params() -> bindings::rhashtable_params {
+        // SAFETY: All fields are integers, booleans and optional function pointers, for which
+        // zero is valid.
+        let mut params: bindings::rhashtable_params = unsafe { mem::zeroed() };
+        let node = MaybeUninit::<RNode<K, V>>::uninit();
+        let base = node.as_ptr();
+        // SAFETY: The field pointers are only computed, within the node.
+        let (head, key) = unsafe { (addr_of!((*base).head), addr_of!((*base).key)) };
+        params.head_offset = (head as usize - base as usize) as _;
+        params.key_offset = (key as usize - base as usize) as _;
+        params.key_len = mem::size_of::<K>() as _;
+        params.automatic_shrinking = true;
+        params
+    }
+
+    /// Creates a new, empty table.
+    pub fn new() -> impl PinInit<Self, Error> {
+        // SAFETY: The closure initialises all fields of the table, and nothing else on failure.
+        unsafe {
+            init::pin_init_from_closure(move |slot: *mut Self| {
+                let params = addr_of_mut!((*slot).params);
+                params.write(Self::params());
+                to_result(bindings::rhashtable_init(
+                    Opaque::raw_get(addr_of!((*slot).ht)),
+                    params,
+                ))?;
+                addr_of_mut!((*slot)._p).write(PhantomData);
+                addr_of_mut!((*slot)._pin).write(PhantomPinned);
+                Ok(())
+            })
+        }
+    }
+
+    /// Returns the node for `key`, if any.
+    ///
+    /// The caller must be in an RCU read-side critical section, for as long as it uses the
+    /// node.
+    fn lookup(&self, key: &K) -> Option<NonNull<RNode<K, V>>> {
+        // SAFETY: The table is initialised with `params`, and `key` is valid for reads of
+        // `key_len` bytes.
+        let obj = unsafe {
+            bindings::rhashtable_lookup(
+                self.ht.get(),
+                key as *const K as *const c_void,
+                self.params,
+            )
+        };
+        NonNull::new(obj.cast())
+    }
+
+    /// Inserts `value` for `key`.
+    ///
+    /// Fails with `EEXIST` if there is already an entry for `key`. This allocates memory, so it
+    /// may sleep.
+    pub fn insert(&self, key: K, value: V) -> Result {
+        let node = Box::into_raw(Box::try_new(RNode {
+            // SAFETY: All fields are pointers, for which zero is valid. The head is set when the
+            // node is inserted.
+            head: unsafe { mem::zeroed() },
+            key,
+            value,
+        })?);
+        // SAFETY: The table is initialised with `params`, and the node is valid, as it was just
+        // converted from a `Box`.
+        let ret = unsafe {
+            bindings::rhashtable_lookup_insert_fast(
+                self.ht.get(),
+                addr_of_mut!((*node).head),
+                self.params,
+            )
+        };
+        if ret < 0 {
+            // SAFETY: The table didn't take ownership of the node.
+            drop(unsafe { Box::from_raw(node) });
+        }
+        // INVARIANT: On success, the table owns the node.
+        to_result(ret)
+    }
+
+    /// Returns a reference to the value for `key`, if any.
+    ///
+    /// The value stays valid while `guard` is held, even if it is removed from the table
+    /// meanwhile.
+    pub fn get<'a>(&'a self, key: &K, _guard: &'a rcu::Guard) -> Option<&'a V> {
+        // SAFETY: The node is only freed after an RCU grace period once it is removed, so not
+        // before `guard` is dropped.
+        self.lookup(key)
+            .map(|node| unsafe { &(*node.as_ptr()).value })
+    }
+
+    /// Returns whether the table has an entry for `key`.
+    pub fn contains_key(&self, key: &K) -> bool {
+        let guard = rcu::read_lock();
+        self.get(key, &guard).is_some()
+    }
+
+    /// Removes the entry for `key`, and returns its value, if any.
+    ///
+    /// This waits for the lookups that may still use the value to complete, so it sleeps.
+    pub fn remove(&self, key: &K) -> Option<V> {
+        let guard = rcu::read_lock();
+        let node = self.lookup(key)?.as_ptr();
+        // SAFETY: The table is initialised with `params`, and the node is valid while `guard` is
+        // held. It is only removed by one thread, which fails with `ENOENT` for the others.
+        let ret = unsafe {
+            bindings::rhashtable_remove_fast(self.ht.get(), addr_of_mut!((*node).head), self.params)
+        };
+        drop(guard);
+        if ret != 0 {
+            return None;
+        }
+        rcu::synchronize();
+        // SAFETY: The node was removed from the table above, and the lookups that may have
+        // found it have completed. It was allocated with `Box` by the type invariants.
+        Some(unsafe { Box::from_raw(node) }.value)
+    }
+}
+
+impl<K: HashKey, V> Drop for RhashTable<K, V> {
+    fn drop(&mut self) {
+        // SAFETY: The table is initialised, and isn't used after this. No lookups can be running
+        // since the table is mutably borrowed, so the objects can be freed right away.
+        unsafe {
+            bindings::rhashtable_free_and_destroy(
+                self.ht.get(),
+                Some(free_callback::<K, V>),
+                ptr::null_mut(),
+            )
+        };
+    }
+}
+
+unsafe extern "C" fn free_callback<K, V>(ptr: *mut c_void, _arg: *mut c_void) {
+    // SAFETY: The objects of the table are `RNode<K, V>` allocated with `Box`, and owned by the
+    // table, which is being destroyed.
+    drop(unsafe { Box::from_raw(ptr as *mut RNode<K, V>) });
+}
+
+// SAFETY: The table owns its keys and values, so it can be sent to another thread if they can.
+unsafe impl<K: HashKey + Send, V: Send> Send for RhashTable<K, V> {}
+
+// SAFETY: The table synchronises its accesses. Values are removed, and so moved, on any thread,
+// and shared references to them are given out to any thread.
+unsafe impl<K: HashKey + Send + Sync, V: Send + Sync> Sync for RhashTable<K, V> {}
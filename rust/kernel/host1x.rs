@@ -0,0 +1,821 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Clients of the Tegra host1x.
+//!
+//! host1x is the DMA engine that feeds the Tegra multimedia engines (2D, 3D, VIC, ...) with
+//! commands. Each engine is a [`Client`] of host1x, which is initialised once all the clients of
+//! the host1x logical device have been registered. Engines are synchronised with the CPU through
+//! syncpoints ([`Syncpt`]), which are incremented by the engines, and receive commands through
+//! channels ([`Channel`]), to which jobs ([`Job`]) made of gathers of command words ([`Gather`])
+//! are submitted.
+//!
+//! C header: [`include/linux/host1x.h`](../../../../include/linux/host1x.h)
+
+use crate::{
+    bindings,
+    device::Device,
+    error::{code::*, from_result, to_result, Result},
+    types::{ARef, AlwaysRefCounted, Opaque},
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    ffi::{c_int, c_void},
+    marker::PhantomPinned,
+    mem::MaybeUninit,
+    pin::Pin,
+    ptr::{self, NonNull},
+};
+use macros::vtable;
+
+/// Flags of syncpoints, the kernel's `HOST1X_SYNCPT_*` values.
+pub mod syncpt_flags {
+    /// The syncpoint is incremented by the CPU rather than by the engine.
+    pub const CLIENT_MANAGED: u64 = crate::bindings::HOST1X_SYNCPT_CLIENT_MANAGED as _;
+    /// The syncpoint has a wait base.
+    pub const HAS_BASE: u64 = crate::bindings::HOST1X_SYNCPT_HAS_BASE as _;
+}
+
+/// Builders of host1x opcodes, the first word of each command in a gather.
+pub mod opcode {
+    /// Selects the class of the following commands, and writes the registers in `mask` starting
+    /// at `offset`.
+    pub const fn setclass(class: u32, offset: u32, mask: u32) -> u32 {
+        (offset << 16) | (class << 6) | mask
+    }
+
+    /// Writes the `count` following words to consecutive registers starting at `offset`.
+    pub const fn incr(offset: u32, count: u32) -> u32 {
+        (1 << 28) | (offset << 16) | count
+    }
+
+    /// Writes the `count` following words to the register at `offset`.
+    pub const fn nonincr(offset: u32, count: u32) -> u32 {
+        (2 << 28) | (offset << 16) | count
+    }
+
+    /// Writes the following words to the registers in `mask`, relative to `offset`.
+    pub const fn mask(offset: u32, mask: u32) -> u32 {
+        (3 << 28) | (offset << 16) | mask
+    }
+
+    /// Writes the 16-bit `value` to the register at `offset`.
+    pub const fn imm(offset: u32, value: u32) -> u32 {
+        (4 << 28) | (offset << 16) | value
+    }
+}
+
+/// The condition under which an engine increments a syncpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncptCondition {
+    /// As soon as the increment is received.
+    Immediate = 0,
+    /// Once all previous operations are done.
+    OpDone = 1,
+    /// Once all previous reads are done.
+    RdDone = 2,
+    /// Once it is safe to write the registers of the engine again.
+    RegWrSafe = 3,
+}
+
+/// The operations of a host1x client, the kernel's `struct host1x_client_ops`.
+///
+/// The driver data of the client implements this trait.
+#[vtable]
+pub trait ClientOperations: Send + Sync + Sized + 'static {
+    /// Initialises the client, once the host1x logical device is probed.
+    ///
+    /// Channels and syncpoints are usually requested here.
+    fn init(_client: &Client<Self>) -> Result {
+        Ok(())
+    }
+
+    /// Tears down the client, when the host1x logical device is removed.
+    fn exit(_client: &Client<Self>) -> Result {
+        Ok(())
+    }
+
+    /// Suspends the client.
+    fn suspend(_client: &Client<Self>) -> Result {
+        Ok(())
+    }
+
+    /// Resumes the client.
+    fn resume(_client: &Client<Self>) -> Result {
+        Ok(())
+    }
+}
+
+/// A registered host1x client, the kernel's `struct host1x_client`.
+///
+/// The client is unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// The client is registered with host1x if `registered` is `true`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::device::Device;
+/// use kernel::host1x::{opcode, Client, ClientOperations, Job, PushBuffer};
+/// # use kernel::prelude::*;
+///
+/// const CLASS_GR2D: u32 = 0x51;
+///
+/// struct Gr2d;
+///
+/// #[vtable]
+/// impl ClientOperations for Gr2d {}
+///
+/// fn probe(dev: &Device) -> Result<Pin<Box<Client<Gr2d>>>> {
+///     Client::register(dev, CLASS_GR2D, Gr2d)
+/// }
+///
+/// fn run(client: &Client<Gr2d>) -> Result {
+///     let channel = client.request_channel()?;
+///     let syncpt = client.request_syncpt(0)?;
+///
+///     let mut pb = PushBuffer::try_with_capacity(4)?;
+///     pb.push(opcode::setclass(CLASS_GR2D, 0, 0))?;
+///     pb.incr_syncpt(kernel::host1x::SyncptCondition::OpDone, syncpt.id())?;
+///     let gather = pb.finish()?;
+///
+///     let mut job = Job::new(&channel, 1)?;
+///     job.set_class(CLASS_GR2D);
+///     job.set_syncpt(&syncpt, 1);
+///     job.add_gather(&gather, 0)?;
+///     let fence = job.submit(client.dev())?;
+///     syncpt.wait(fence, 1000)?;
+///     Ok(())
+/// }
+/// ```
+#[repr(C)]
+pub struct Client<T: ClientOperations> {
+    // Must be the first field, see `Client::from_raw`.
+    client: Opaque<bindings::host1x_client>,
+    dev: ARef<Device>,
+    registered: bool,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+// SAFETY: The client can be unregistered from any thread, and the driver data is `Send`.
+unsafe impl<T: ClientOperations> Send for Client<T> {}
+
+// SAFETY: The methods that take `&self` use functions with their own synchronisation, and the
+// driver data is `Sync`.
+unsafe impl<T: ClientOperations> Sync for Client<T> {}
+
+impl<T: ClientOperations> Client<T> {
+    const OPS: bindings::host1x_client_ops = bindings::host1x_client_ops {
+        init: if T::HAS_INIT {
+            Some(Self::init_callback)
+        } else {
+            None
+        },
+        exit: if T::HAS_EXIT {
+            Some(Self::exit_callback)
+        } else {
+            None
+        },
+        suspend: if T::HAS_SUSPEND {
+            Some(Self::suspend_callback)
+        } else {
+            None
+        },
+        resume: if T::HAS_RESUME {
+            Some(Self::resume_callback)
+        } else {
+            None
+        },
+        // SAFETY: All other fields are optional, for which zero is valid.
+        ..unsafe { MaybeUninit::zeroed().assume_init() }
+    };
+
+    /// Registers `dev` as a host1x client of the class `class`, one of the `HOST1X_CLASS_*`
+    /// values.
+    pub fn register(dev: &Device, class: u32, data: T) -> Result<Pin<Box<Self>>> {
+        crate::might_sleep!();
+        let mut client = Pin::from(Box::try_new(Self {
+            client: Opaque::new(bindings::host1x_client {
+                dev: dev.as_raw(),
+                ops: &Self::OPS,
+                class,
+                // SAFETY: All other fields are optional or initialised by host1x.
+                ..unsafe { MaybeUninit::zeroed().assume_init() }
+            }),
+            dev: dev.into(),
+            registered: false,
+            data,
+            _pin: PhantomPinned,
+        })?);
+        // SAFETY: The client is pinned and is unregistered before it is freed. This is what
+        // `host1x_client_register` expands to.
+        unsafe {
+            bindings::__host1x_client_init(client.as_raw(), crate::static_lock_class!().as_ptr());
+            to_result(bindings::__host1x_client_register(client.as_raw()))?;
+        }
+        // INVARIANT: The client was registered above.
+        // SAFETY: `client` isn't moved out of.
+        unsafe { client.as_mut().get_unchecked_mut() }.registered = true;
+        Ok(client)
+    }
+
+    fn as_raw(&self) -> *mut bindings::host1x_client {
+        self.client.get()
+    }
+
+    /// Returns the driver data of the client.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    /// Returns the device of the client.
+    pub fn dev(&self) -> &Device {
+        &self.dev
+    }
+
+    /// Requests a channel to submit jobs to.
+    ///
+    /// Fails with `EBUSY` if all the channels are in use.
+    pub fn request_channel(&self) -> Result<Channel> {
+        // SAFETY: The client is registered, since `register` only returns it once it is.
+        let ch = unsafe { bindings::host1x_channel_request(self.as_raw()) };
+        // INVARIANT: The reference to the channel returned by the request is owned by `Channel`.
+        Ok(Channel(NonNull::new(ch).ok_or(EBUSY)?))
+    }
+
+    /// Requests a syncpoint with the given [`syncpt_flags`].
+    ///
+    /// Fails with `EBUSY` if all the syncpoints are in use.
+    pub fn request_syncpt(&self, flags: u64) -> Result<Syncpt> {
+        // SAFETY: The client is registered, since `register` only returns it once it is.
+        let sp = unsafe { bindings::host1x_syncpt_request(self.as_raw(), flags as _) };
+        // INVARIANT: The reference to the syncpoint returned by the request is owned by `Syncpt`.
+        Ok(Syncpt(NonNull::new(sp).ok_or(EBUSY)?))
+    }
+
+    /// Returns the client of `client`.
+    ///
+    /// # Safety
+    ///
+    /// `client` must be the `host1x_client` of a registered [`Client<T>`].
+    unsafe fn from_raw<'a>(client: *mut bindings::host1x_client) -> &'a Self {
+        // SAFETY: `client` is the first field of `Client<T>`, which is `repr(C)`.
+        unsafe { &*client.cast() }
+    }
+
+    unsafe extern "C" fn init_callback(client: *mut bindings::host1x_client) -> c_int {
+        from_result(|| {
+            // SAFETY: host1x calls this with a registered client.
+            T::init(unsafe { Self::from_raw(client) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn exit_callback(client: *mut bindings::host1x_client) -> c_int {
+        from_result(|| {
+            // SAFETY: host1x calls this with a registered client.
+            T::exit(unsafe { Self::from_raw(client) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn suspend_callback(client: *mut bindings::host1x_client) -> c_int {
+        from_result(|| {
+            // SAFETY: host1x calls this with a registered client.
+            T::suspend(unsafe { Self::from_raw(client) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn resume_callback(client: *mut bindings::host1x_client) -> c_int {
+        from_result(|| {
+            // SAFETY: host1x calls this with a registered client.
+            T::resume(unsafe { Self::from_raw(client) })?;
+            Ok(0)
+        })
+    }
+}
+
+impl<T: ClientOperations> Drop for Client<T> {
+    fn drop(&mut self) {
+        if self.registered {
+            // SAFETY: The client is registered by the type invariants.
+            unsafe { bindings::host1x_client_unregister(self.as_raw()) };
+        }
+    }
+}
+
+/// A syncpoint, the kernel's `struct host1x_syncpt`.
+///
+/// A syncpoint is a counter that engines increment when they reach points of their command
+/// stream, and that the CPU waits on.
+///
+/// # Invariants
+///
+/// The pointer is valid, and `Syncpt` owns a reference to it.
+pub struct Syncpt(NonNull<bindings::host1x_syncpt>);
+
+// SAFETY: Syncpoints are reference-counted and synchronised by host1x.
+unsafe impl Send for Syncpt {}
+
+// SAFETY: Syncpoints are reference-counted and synchronised by host1x.
+unsafe impl Sync for Syncpt {}
+
+impl Syncpt {
+    fn as_raw(&self) -> *mut bindings::host1x_syncpt {
+        self.0.as_ptr()
+    }
+
+    /// Returns the hardware ID of the syncpoint.
+    pub fn id(&self) -> u32 {
+        // SAFETY: The syncpoint is valid by the type invariants.
+        unsafe { bindings::host1x_syncpt_id(self.as_raw()) }
+    }
+
+    /// Reads the value of the syncpoint from the hardware.
+    pub fn read(&self) -> u32 {
+        // SAFETY: The syncpoint is valid by the type invariants.
+        unsafe { bindings::host1x_syncpt_read(self.as_raw()) }
+    }
+
+    /// Returns the last value of the syncpoint that was read from the hardware.
+    pub fn read_min(&self) -> u32 {
+        // SAFETY: The syncpoint is valid by the type invariants.
+        unsafe { bindings::host1x_syncpt_read_min(self.as_raw()) }
+    }
+
+    /// Returns the value that the syncpoint reaches once all the submitted work is done.
+    pub fn read_max(&self) -> u32 {
+        // SAFETY: The syncpoint is valid by the type invariants.
+        unsafe { bindings::host1x_syncpt_read_max(self.as_raw()) }
+    }
+
+    /// Increments the syncpoint from the CPU.
+    pub fn incr(&self) -> Result {
+        // SAFETY: The syncpoint is valid by the type invariants.
+        to_result(unsafe { bindings::host1x_syncpt_incr(self.as_raw()) })
+    }
+
+    /// Reserves `incrs` increments of the syncpoint, and returns the value it reaches after them.
+    pub fn incr_max(&self, incrs: u32) -> u32 {
+        // SAFETY: The syncpoint is valid by the type invariants.
+        unsafe { bindings::host1x_syncpt_incr_max(self.as_raw(), incrs) }
+    }
+
+    /// Waits for the syncpoint to reach `thresh`, for at most `timeout_ms` milliseconds, and
+    /// returns its value.
+    ///
+    /// Fails with `EAGAIN` if the syncpoint didn't reach `thresh` in time.
+    pub fn wait(&self, thresh: u32, timeout_ms: u32) -> Result<u32> {
+        crate::might_sleep!();
+        let mut value = 0;
+        // SAFETY: The syncpoint is valid by the type invariants.
+        to_result(unsafe {
+            bindings::host1x_syncpt_wait(
+                self.as_raw(),
+                thresh,
+                bindings::msecs_to_jiffies(timeout_ms) as _,
+                &mut value,
+            )
+        })?;
+        Ok(value)
+    }
+}
+
+impl Clone for Syncpt {
+    fn clone(&self) -> Self {
+        // SAFETY: The syncpoint is valid by the type invariants.
+        unsafe { bindings::host1x_syncpt_get(self.as_raw()) };
+        // INVARIANT: The reference taken above is owned by the new `Syncpt`.
+        Self(self.0)
+    }
+}
+
+impl Drop for Syncpt {
+    fn drop(&mut self) {
+        // SAFETY: `Syncpt` owns a reference to the syncpoint by the type invariants.
+        unsafe { bindings::host1x_syncpt_put(self.as_raw()) };
+    }
+}
+
+/// A channel of host1x, the kernel's `struct host1x_channel`.
+///
+/// # Invariants
+///
+/// The pointer is valid, and `Channel` owns a reference to it.
+pub struct Channel(NonNull<bindings::host1x_channel>);
+
+// SAFETY: Channels are reference-counted and synchronised by host1x.
+unsafe impl Send for Channel {}
+
+// SAFETY: Channels are reference-counted and synchronised by host1x.
+unsafe impl Sync for Channel {}
+
+impl Channel {
+    fn as_raw(&self) -> *mut bindings::host1x_channel {
+        self.0.as_ptr()
+    }
+
+    /// Stops the channel, cancelling the jobs that were submitted to it.
+    pub fn stop(&self) {
+        // SAFETY: The channel is valid by the type invariants.
+        unsafe { bindings::host1x_channel_stop(self.as_raw()) };
+    }
+}
+
+impl Clone for Channel {
+    fn clone(&self) -> Self {
+        // SAFETY: The channel is valid by the type invariants.
+        unsafe { bindings::host1x_channel_get(self.as_raw()) };
+        // INVARIANT: The reference taken above is owned by the new `Channel`.
+        Self(self.0)
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        // SAFETY: `Channel` owns a reference to the channel by the type invariants.
+        unsafe { bindings::host1x_channel_put(self.as_raw()) };
+    }
+}
+
+/// A builder of gathers.
+pub struct PushBuffer {
+    words: Vec<u32>,
+}
+
+impl PushBuffer {
+    /// Creates an empty push buffer with room for `words` words.
+    pub fn try_with_capacity(words: usize) -> Result<Self> {
+        Ok(Self {
+            words: Vec::try_with_capacity(words)?,
+        })
+    }
+
+    /// Appends `word`.
+    pub fn push(&mut self, word: u32) -> Result {
+        self.words.try_push(word)?;
+        Ok(())
+    }
+
+    /// Appends the words in `words`.
+    pub fn extend(&mut self, words: &[u32]) -> Result {
+        self.words.try_extend_from_slice(words)?;
+        Ok(())
+    }
+
+    /// Appends a command that increments the syncpoint `id` once `cond` is met, in the current
+    /// class.
+    ///
+    /// This uses the layout of the `INCR_SYNCPT` register of Tegra20 to Tegra210.
+    pub fn incr_syncpt(&mut self, cond: SyncptCondition, id: u32) -> Result {
+        self.push(opcode::imm(0, ((cond as u32) << 8) | (id & 0xff)))
+    }
+
+    /// Returns the number of words in the push buffer.
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Returns `true` if the push buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Turns the push buffer into a gather that can be added to jobs.
+    ///
+    /// Fails with `EINVAL` if the push buffer is empty.
+    pub fn finish(self) -> Result<ARef<Gather>> {
+        if self.words.is_empty() {
+            return Err(EINVAL);
+        }
+        let gather = Box::into_raw(Box::try_new(Gather {
+            // SAFETY: The buffer object is initialised below.
+            bo: Opaque::new(unsafe { MaybeUninit::zeroed().assume_init() }),
+            // SAFETY: `REFCOUNT_INIT` has no preconditions.
+            refcount: Opaque::new(unsafe { bindings::REFCOUNT_INIT(1) }),
+            words: self.words,
+        })?);
+        // SAFETY: `gather` was just allocated, and nothing else uses it yet.
+        unsafe { bindings::host1x_bo_init((*gather).bo.get(), &Gather::BO_OPS) };
+        // INVARIANT: The gather was allocated in a box with a reference count of one, which is
+        // owned by the `ARef`.
+        // SAFETY: `gather` is valid and non-null.
+        Ok(unsafe { ARef::from_raw(NonNull::new_unchecked(gather)) })
+    }
+}
+
+/// Command words that the engines fetch with DMA, backed by a host1x buffer object (the kernel's
+/// `struct host1x_bo`).
+///
+/// # Invariants
+///
+/// The gather is allocated in a box, which is freed when its reference count drops to zero. The
+/// words are never modified.
+#[repr(C)]
+pub struct Gather {
+    // Must be the first field, see `Gather::from_bo`.
+    bo: Opaque<bindings::host1x_bo>,
+    refcount: Opaque<bindings::refcount_t>,
+    words: Vec<u32>,
+}
+
+// SAFETY: The words are never modified, and the buffer object is synchronised by host1x.
+unsafe impl Send for Gather {}
+
+// SAFETY: The words are never modified, and the buffer object is synchronised by host1x.
+unsafe impl Sync for Gather {}
+
+/// A mapping of a gather for a device.
+#[repr(C)]
+struct Mapping {
+    // Must be the first field, see `Gather::unpin_callback`.
+    map: bindings::host1x_bo_mapping,
+    sgt: bindings::sg_table,
+}
+
+impl Gather {
+    const BO_OPS: bindings::host1x_bo_ops = bindings::host1x_bo_ops {
+        get: Some(Self::get_callback),
+        put: Some(Self::put_callback),
+        pin: Some(Self::pin_callback),
+        unpin: Some(Self::unpin_callback),
+        mmap: Some(Self::mmap_callback),
+        munmap: Some(Self::munmap_callback),
+    };
+
+    /// Returns the command words of the gather.
+    pub fn words(&self) -> &[u32] {
+        &self.words
+    }
+
+    fn as_bo(&self) -> *mut bindings::host1x_bo {
+        self.bo.get()
+    }
+
+    /// Returns the gather of `bo`.
+    ///
+    /// # Safety
+    ///
+    /// `bo` must be the buffer object of a valid [`Gather`].
+    unsafe fn from_bo<'a>(bo: *mut bindings::host1x_bo) -> &'a Self {
+        // SAFETY: `bo` is the first field of `Gather`, which is `repr(C)`.
+        unsafe { &*bo.cast() }
+    }
+
+    unsafe extern "C" fn get_callback(bo: *mut bindings::host1x_bo) -> *mut bindings::host1x_bo {
+        // SAFETY: host1x calls this with the buffer object of a gather that it holds a reference
+        // to.
+        unsafe { Self::from_bo(bo) }.inc_ref();
+        bo
+    }
+
+    unsafe extern "C" fn put_callback(bo: *mut bindings::host1x_bo) {
+        // SAFETY: host1x calls this with the buffer object of a gather, dropping a reference that
+        // it took with `get_callback`.
+        unsafe { Self::dec_ref(NonNull::new_unchecked(bo.cast())) };
+    }
+
+    unsafe extern "C" fn pin_callback(
+        dev: *mut bindings::device,
+        bo: *mut bindings::host1x_bo,
+        dir: bindings::dma_data_direction,
+    ) -> *mut bindings::host1x_bo_mapping {
+        // SAFETY: host1x calls this with the buffer object of a gather that it holds a reference
+        // to, and a valid device.
+        let gather = unsafe { Self::from_bo(bo) };
+        let size = gather.words.len() * core::mem::size_of::<u32>();
+        if crate::fault_inject::should_fail_dma(size) {
+            return ENOMEM.to_ptr();
+        }
+        let mapping = match Box::try_new(Mapping {
+            // SAFETY: All fields are integers or pointers, for which zero is valid.
+            map: unsafe { MaybeUninit::zeroed().assume_init() },
+            // SAFETY: All fields are integers or pointers, for which zero is valid.
+            sgt: unsafe { MaybeUninit::zeroed().assume_init() },
+        }) {
+            Ok(mapping) => Box::into_raw(mapping),
+            Err(_) => return ENOMEM.to_ptr(),
+        };
+
+        // SAFETY: `mapping` was just allocated. The words are contiguous in memory and never
+        // modified, so they can be mapped with a single entry.
+        let ret = unsafe {
+            let sgt = ptr::addr_of_mut!((*mapping).sgt);
+            let ret = bindings::sg_alloc_table(sgt, 1, bindings::GFP_KERNEL);
+            if ret == 0 {
+                bindings::sg_init_one((*sgt).sgl, gather.words.as_ptr().cast(), size as _);
+                let ret = bindings::dma_map_sgtable(dev, sgt, dir, 0);
+                if ret != 0 {
+                    bindings::sg_free_table(sgt);
+                }
+                ret
+            } else {
+                ret
+            }
+        };
+        if ret != 0 {
+            // SAFETY: `mapping` isn't used by anything else.
+            drop(unsafe { Box::from_raw(mapping) });
+            return crate::error::Error::from_errno(ret).to_ptr();
+        }
+
+        gather.inc_ref();
+        // SAFETY: `mapping` was just allocated, and its table was mapped above. The reference to
+        // the gather taken above is dropped in `unpin_callback`.
+        unsafe {
+            let map = ptr::addr_of_mut!((*mapping).map);
+            bindings::kref_init(ptr::addr_of_mut!((*map).ref_));
+            (*map).bo = bo;
+            (*map).sgt = ptr::addr_of_mut!((*mapping).sgt);
+            (*map).direction = dir;
+            (*map).dev = dev;
+            (*map).phys = (*(*mapping).sgt.sgl).dma_address;
+            (*map).size = size;
+            (*map).chunks = 1;
+            map
+        }
+    }
+
+    unsafe extern "C" fn unpin_callback(map: *mut bindings::host1x_bo_mapping) {
+        let mapping = map.cast::<Mapping>();
+        // SAFETY: host1x calls this once the last reference to a mapping created by
+        // `pin_callback` is dropped, so the mapping and its reference to the gather can be
+        // released.
+        unsafe {
+            let sgt = ptr::addr_of_mut!((*mapping).sgt);
+            bindings::dma_unmap_sgtable((*map).dev, sgt, (*map).direction, 0);
+            bindings::sg_free_table(sgt);
+            Self::dec_ref(NonNull::new_unchecked((*map).bo.cast()));
+            drop(Box::from_raw(mapping));
+        }
+    }
+
+    unsafe extern "C" fn mmap_callback(bo: *mut bindings::host1x_bo) -> *mut c_void {
+        // SAFETY: host1x calls this with the buffer object of a gather that it holds a reference
+        // to. The words are always mapped, and are never modified, so host1x only reads them.
+        unsafe { Self::from_bo(bo) }.words.as_ptr() as *mut c_void
+    }
+
+    unsafe extern "C" fn munmap_callback(_bo: *mut bindings::host1x_bo, _addr: *mut c_void) {}
+}
+
+// SAFETY: Instances of `Gather` are always reference-counted.
+unsafe impl AlwaysRefCounted for Gather {
+    fn inc_ref(&self) {
+        // SAFETY: The existence of a shared reference guarantees that the refcount is non-zero.
+        unsafe { bindings::refcount_inc(self.refcount.get()) };
+    }
+
+    unsafe fn dec_ref(obj: NonNull<Self>) {
+        // SAFETY: The safety requirements guarantee that the refcount is non-zero. Once it drops
+        // to zero, nothing else references the gather, which was allocated in a box by the type
+        // invariants.
+        unsafe {
+            if bindings::refcount_dec_and_test((*obj.as_ptr()).refcount.get()) {
+                drop(Box::from_raw(obj.as_ptr()));
+            }
+        }
+    }
+}
+
+/// A job to submit to a channel, the kernel's `struct host1x_job`.
+///
+/// # Invariants
+///
+/// `job` is valid, and `Job` owns a reference to it. The gathers of the job are kept alive by
+/// `gathers` until the job is pinned, and its channel by `_channel`.
+pub struct Job {
+    job: NonNull<bindings::host1x_job>,
+    num_cmdbufs: u32,
+    gathers: Vec<ARef<Gather>>,
+    _channel: Channel,
+}
+
+// SAFETY: The job is only used through `&mut self` until it is submitted.
+unsafe impl Send for Job {}
+
+impl Job {
+    /// Allocates a job for `channel`, with room for `num_cmdbufs` gathers and waits.
+    pub fn new(channel: &Channel, num_cmdbufs: u32) -> Result<Self> {
+        crate::might_sleep!();
+        // SAFETY: The channel is valid. The firewall can't be skipped, since the gathers are
+        // built by the kernel but their contents may come from user space.
+        let job = unsafe { bindings::host1x_job_alloc(channel.as_raw(), num_cmdbufs, 0, false) };
+        // INVARIANT: The reference to the job returned by the allocation is owned by `Job`.
+        Ok(Self {
+            job: NonNull::new(job).ok_or(ENOMEM)?,
+            num_cmdbufs,
+            gathers: Vec::new(),
+            _channel: channel.clone(),
+        })
+    }
+
+    fn as_raw(&self) -> *mut bindings::host1x_job {
+        self.job.as_ptr()
+    }
+
+    fn check_room(&self) -> Result {
+        // SAFETY: The job is valid by the type invariants.
+        if unsafe { (*self.as_raw()).num_cmds } >= self.num_cmdbufs {
+            return Err(ENOSPC);
+        }
+        Ok(())
+    }
+
+    /// Sets the class that the job starts in, one of the `HOST1X_CLASS_*` values.
+    pub fn set_class(&mut self, class: u32) {
+        // SAFETY: The job is valid and not submitted yet.
+        unsafe { (*self.as_raw()).class = class };
+    }
+
+    /// Sets the syncpoint that the job increments `incrs` times.
+    pub fn set_syncpt(&mut self, syncpt: &Syncpt, incrs: u32) {
+        let syncpt = core::mem::ManuallyDrop::new(syncpt.clone());
+        // SAFETY: The job is valid and not submitted yet. It takes over the reference to the
+        // syncpoint, which it drops when it is freed, after dropping the previous one.
+        unsafe {
+            let job = self.as_raw();
+            if !(*job).syncpt.is_null() {
+                bindings::host1x_syncpt_put((*job).syncpt);
+            }
+            (*job).syncpt = syncpt.as_raw();
+            (*job).syncpt_incrs = incrs;
+        }
+    }
+
+    /// Sets the timeout of the job, in milliseconds.
+    pub fn set_timeout(&mut self, timeout_ms: u32) {
+        // SAFETY: The job is valid and not submitted yet.
+        unsafe { (*self.as_raw()).timeout = timeout_ms };
+    }
+
+    /// Adds `gather`, starting at the word at `offset`, to the job.
+    ///
+    /// Fails with `ENOSPC` if the job is full, and with `EINVAL` if `offset` is out of bounds.
+    pub fn add_gather(&mut self, gather: &ARef<Gather>, offset: u32) -> Result {
+        self.check_room()?;
+        let len = gather.words().len();
+        if offset as usize >= len {
+            return Err(EINVAL);
+        }
+        self.gathers.try_push(gather.clone())?;
+        // SAFETY: The job is valid and not submitted yet, and the gather is kept alive by
+        // `gathers` until the job is pinned.
+        unsafe {
+            bindings::host1x_job_add_gather(
+                self.as_raw(),
+                gather.as_bo(),
+                (len - offset as usize) as _,
+                offset * core::mem::size_of::<u32>() as u32,
+            )
+        };
+        Ok(())
+    }
+
+    /// Makes the job wait for the syncpoint `id` to reach `thresh` before running the next
+    /// gather, which runs in the class `next_class`.
+    ///
+    /// If `relative` is `true`, `thresh` is relative to the value of the syncpoint of the job
+    /// when the job is submitted. Fails with `ENOSPC` if the job is full.
+    pub fn add_wait(&mut self, id: u32, thresh: u32, relative: bool, next_class: u32) -> Result {
+        self.check_room()?;
+        // SAFETY: The job is valid and not submitted yet.
+        unsafe { bindings::host1x_job_add_wait(self.as_raw(), id, thresh, relative, next_class) };
+        Ok(())
+    }
+
+    /// Pins the gathers of the job for `dev` and submits it, and returns the value that the
+    /// syncpoint of the job reaches when the job is done.
+    ///
+    /// Fails with `EINVAL` if the job has no syncpoint.
+    pub fn submit(self, dev: &Device) -> Result<u32> {
+        crate::might_sleep!();
+        let job = self.as_raw();
+        // SAFETY: The job is valid and not submitted yet.
+        if unsafe { (*job).syncpt.is_null() } {
+            return Err(EINVAL);
+        }
+        // SAFETY: The job is valid, and its gathers are alive. Pinning takes references to them.
+        to_result(unsafe { bindings::host1x_job_pin(job, dev.as_raw()) })?;
+        // SAFETY: The job is pinned. host1x takes a reference to it until it is done, and unpins
+        // it then.
+        let ret = unsafe { bindings::host1x_job_submit(job) };
+        if ret != 0 {
+            // SAFETY: The job was pinned above, and wasn't submitted.
+            unsafe { bindings::host1x_job_unpin(job) };
+            return Err(crate::error::Error::from_errno(ret));
+        }
+        // SAFETY: The job was submitted, which set its end value.
+        Ok(unsafe { (*job).syncpt_end })
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        // SAFETY: `Job` owns a reference to the job by the type invariants.
+        unsafe { bindings::host1x_job_put(self.as_raw()) };
+    }
+}
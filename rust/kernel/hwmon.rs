@@ -0,0 +1,441 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Hardware monitoring devices.
+//!
+//! A driver of a monitoring chip describes its channels, e.g. temperatures or voltages, and the
+//! attributes of each, then registers a [`Registration`] that the hwmon core reads and writes the
+//! attributes through. Values are in the units of the hwmon sysfs ABI, e.g. millicelsius and
+//! millivolts.
+//!
+//! C header: [`include/linux/hwmon.h`](../../../../include/linux/hwmon.h)
+
+use crate::{
+    bindings, device,
+    error::{code::*, from_err_ptr, from_result, Result},
+    str::{CStr, CString},
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    ffi::{c_char, c_int, c_long, c_void},
+    fmt,
+    marker::PhantomPinned,
+    pin::Pin,
+    ptr,
+};
+use macros::vtable;
+
+/// The type of a channel, the kernel's `enum hwmon_sensor_types`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SensorType {
+    /// Attributes of the whole chip, see [`chip`].
+    Chip,
+    /// A temperature, see [`temp`].
+    Temp,
+    /// A voltage, see [`voltage`].
+    Voltage,
+    /// A current, see [`current`].
+    Current,
+    /// A power, see [`power`].
+    Power,
+    /// A fan, see [`fan`].
+    Fan,
+    /// A PWM output, see [`pwm`].
+    Pwm,
+}
+
+impl SensorType {
+    fn from_raw(raw: bindings::hwmon_sensor_types) -> Option<Self> {
+        Some(match raw {
+            bindings::hwmon_sensor_types_hwmon_chip => Self::Chip,
+            bindings::hwmon_sensor_types_hwmon_temp => Self::Temp,
+            bindings::hwmon_sensor_types_hwmon_in => Self::Voltage,
+            bindings::hwmon_sensor_types_hwmon_curr => Self::Current,
+            bindings::hwmon_sensor_types_hwmon_power => Self::Power,
+            bindings::hwmon_sensor_types_hwmon_fan => Self::Fan,
+            bindings::hwmon_sensor_types_hwmon_pwm => Self::Pwm,
+            _ => return None,
+        })
+    }
+
+    fn as_raw(self) -> bindings::hwmon_sensor_types {
+        match self {
+            Self::Chip => bindings::hwmon_sensor_types_hwmon_chip,
+            Self::Temp => bindings::hwmon_sensor_types_hwmon_temp,
+            Self::Voltage => bindings::hwmon_sensor_types_hwmon_in,
+            Self::Current => bindings::hwmon_sensor_types_hwmon_curr,
+            Self::Power => bindings::hwmon_sensor_types_hwmon_power,
+            Self::Fan => bindings::hwmon_sensor_types_hwmon_fan,
+            Self::Pwm => bindings::hwmon_sensor_types_hwmon_pwm,
+        }
+    }
+}
+
+/// Attributes of the whole chip, the kernel's `enum hwmon_chip_attributes`.
+pub mod chip {
+    /// Registers the temperature channels as thermal zones of the devicetree.
+    pub const REGISTER_TZ: u32 = crate::bindings::hwmon_chip_attributes_hwmon_chip_register_tz;
+    /// The update interval of the chip, in milliseconds.
+    pub const UPDATE_INTERVAL: u32 =
+        crate::bindings::hwmon_chip_attributes_hwmon_chip_update_interval;
+}
+
+/// Attributes of temperature channels, the kernel's `enum hwmon_temp_attributes`.
+pub mod temp {
+    /// The measured temperature.
+    pub const INPUT: u32 = crate::bindings::hwmon_temp_attributes_hwmon_temp_input;
+    /// The minimum temperature.
+    pub const MIN: u32 = crate::bindings::hwmon_temp_attributes_hwmon_temp_min;
+    /// The maximum temperature.
+    pub const MAX: u32 = crate::bindings::hwmon_temp_attributes_hwmon_temp_max;
+    /// The critical temperature.
+    pub const CRIT: u32 = crate::bindings::hwmon_temp_attributes_hwmon_temp_crit;
+    /// The label of the channel, read with [`super::Operations::read_string`].
+    pub const LABEL: u32 = crate::bindings::hwmon_temp_attributes_hwmon_temp_label;
+    /// Whether the maximum temperature was exceeded.
+    pub const MAX_ALARM: u32 = crate::bindings::hwmon_temp_attributes_hwmon_temp_max_alarm;
+    /// Whether the critical temperature was exceeded.
+    pub const CRIT_ALARM: u32 = crate::bindings::hwmon_temp_attributes_hwmon_temp_crit_alarm;
+}
+
+/// Attributes of voltage channels, the kernel's `enum hwmon_in_attributes`.
+pub mod voltage {
+    /// The measured voltage.
+    pub const INPUT: u32 = crate::bindings::hwmon_in_attributes_hwmon_in_input;
+    /// The minimum voltage.
+    pub const MIN: u32 = crate::bindings::hwmon_in_attributes_hwmon_in_min;
+    /// The maximum voltage.
+    pub const MAX: u32 = crate::bindings::hwmon_in_attributes_hwmon_in_max;
+    /// The label of the channel, read with [`super::Operations::read_string`].
+    pub const LABEL: u32 = crate::bindings::hwmon_in_attributes_hwmon_in_label;
+    /// Whether the voltage is out of range.
+    pub const ALARM: u32 = crate::bindings::hwmon_in_attributes_hwmon_in_alarm;
+}
+
+/// Attributes of current channels, the kernel's `enum hwmon_curr_attributes`.
+pub mod current {
+    /// The measured current.
+    pub const INPUT: u32 = crate::bindings::hwmon_curr_attributes_hwmon_curr_input;
+    /// The maximum current.
+    pub const MAX: u32 = crate::bindings::hwmon_curr_attributes_hwmon_curr_max;
+    /// The label of the channel, read with [`super::Operations::read_string`].
+    pub const LABEL: u32 = crate::bindings::hwmon_curr_attributes_hwmon_curr_label;
+}
+
+/// Attributes of power channels, the kernel's `enum hwmon_power_attributes`.
+pub mod power {
+    /// The measured power.
+    pub const INPUT: u32 = crate::bindings::hwmon_power_attributes_hwmon_power_input;
+    /// The label of the channel, read with [`super::Operations::read_string`].
+    pub const LABEL: u32 = crate::bindings::hwmon_power_attributes_hwmon_power_label;
+}
+
+/// Attributes of fan channels, the kernel's `enum hwmon_fan_attributes`.
+pub mod fan {
+    /// The measured speed.
+    pub const INPUT: u32 = crate::bindings::hwmon_fan_attributes_hwmon_fan_input;
+    /// The label of the channel, read with [`super::Operations::read_string`].
+    pub const LABEL: u32 = crate::bindings::hwmon_fan_attributes_hwmon_fan_label;
+}
+
+/// Attributes of PWM channels, the kernel's `enum hwmon_pwm_attributes`.
+pub mod pwm {
+    /// The duty cycle, from 0 to 255.
+    pub const INPUT: u32 = crate::bindings::hwmon_pwm_attributes_hwmon_pwm_input;
+    /// The control mode of the output.
+    pub const ENABLE: u32 = crate::bindings::hwmon_pwm_attributes_hwmon_pwm_enable;
+}
+
+/// Returns the configuration of a channel with the attributes `attrs`, e.g. [`temp::INPUT`].
+pub const fn attributes(attrs: &[u32]) -> u32 {
+    let mut config = 0;
+    let mut i = 0;
+    while i < attrs.len() {
+        config |= 1 << attrs[i];
+        i += 1;
+    }
+    config
+}
+
+/// The channels of one type of a chip, like the kernel's `struct hwmon_channel_info`.
+#[derive(Clone, Copy, Debug)]
+pub struct Channels<'a> {
+    /// The type of the channels.
+    pub kind: SensorType,
+    /// The configuration of each channel, returned by [`attributes`].
+    pub config: &'a [u32],
+}
+
+/// The operations of a monitoring chip, the kernel's `struct hwmon_ops`.
+///
+/// The driver data of the chip implements this trait. The operations are called with the type,
+/// attribute and channel number of an attribute, and may sleep.
+#[vtable]
+pub trait Operations: Send + Sync + Sized + 'static {
+    /// Returns the sysfs permissions of an attribute, or zero to hide it.
+    ///
+    /// By default, attributes are read-only, or writable too if the driver implements
+    /// [`Operations::write`].
+    fn is_visible(_data: &Self, _kind: SensorType, _attr: u32, _channel: i32) -> u16 {
+        if Self::HAS_WRITE {
+            0o644
+        } else {
+            0o444
+        }
+    }
+
+    /// Reads a numerical attribute.
+    fn read(data: &Self, kind: SensorType, attr: u32, channel: i32) -> Result<i64>;
+
+    /// Reads a string attribute, i.e. a label.
+    fn read_string(
+        _data: &Self,
+        _kind: SensorType,
+        _attr: u32,
+        _channel: i32,
+    ) -> Result<&'static CStr> {
+        Err(EOPNOTSUPP)
+    }
+
+    /// Writes a numerical attribute.
+    fn write(_data: &Self, _kind: SensorType, _attr: u32, _channel: i32, _val: i64) -> Result {
+        Err(EOPNOTSUPP)
+    }
+}
+
+/// A registered monitoring chip.
+///
+/// The chip is unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `hwmon` is null until the chip is registered by [`Registration::register`], and then a hwmon
+/// device whose driver data is this `Registration<T>`. `_name`, `chip`, `_infos`, `_info_ptrs`
+/// and `_configs` describe the chip, and don't change while it is registered.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{device::Device, fmt, prelude::*};
+/// use kernel::hwmon::{self, attributes, temp, voltage, Channels, Operations, SensorType};
+///
+/// struct Monitor;
+///
+/// #[vtable]
+/// impl Operations for Monitor {
+///     fn read(_data: &Self, kind: SensorType, attr: u32, channel: i32) -> Result<i64> {
+///         match (kind, attr) {
+///             (SensorType::Temp, temp::INPUT) => Ok(40000),
+///             (SensorType::Voltage, voltage::INPUT) => Ok(1200 + channel as i64 * 600),
+///             _ => Err(EOPNOTSUPP),
+///         }
+///     }
+/// }
+///
+/// fn probe(dev: &Device) -> Result<Pin<Box<hwmon::Registration<Monitor>>>> {
+///     let input = attributes(&[voltage::INPUT]);
+///     let channels = [
+///         Channels {
+///             kind: SensorType::Temp,
+///             config: &[attributes(&[temp::INPUT])],
+///         },
+///         Channels {
+///             kind: SensorType::Voltage,
+///             config: &[input, input],
+///         },
+///     ];
+///     hwmon::Registration::register(dev, fmt!("monitor"), &channels, Monitor)
+/// }
+/// ```
+pub struct Registration<T: Operations> {
+    hwmon: *mut bindings::device,
+    _name: CString,
+    chip: bindings::hwmon_chip_info,
+    _infos: Vec<bindings::hwmon_channel_info>,
+    _info_ptrs: Vec<*const bindings::hwmon_channel_info>,
+    _configs: Vec<u32>,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: Operations> Registration<T> {
+    const OPS: bindings::hwmon_ops = bindings::hwmon_ops {
+        is_visible: Some(Self::is_visible_callback),
+        read: Some(Self::read_callback),
+        read_string: if T::HAS_READ_STRING {
+            Some(Self::read_string_callback)
+        } else {
+            None
+        },
+        write: if T::HAS_WRITE {
+            Some(Self::write_callback)
+        } else {
+            None
+        },
+    };
+
+    /// Registers a monitoring chip named `name`, which may be built at runtime, whose parent is
+    /// `parent`, with the channels `channels` and the driver data `data`.
+    ///
+    /// The name must not contain dashes, spaces or stars.
+    pub fn register(
+        parent: &device::Device,
+        name: fmt::Arguments<'_>,
+        channels: &[Channels<'_>],
+        data: T,
+    ) -> Result<Pin<Box<Self>>> {
+        crate::might_sleep!();
+        let name = CString::try_from_fmt(name)?;
+
+        // The configurations of all channels are stored together, each zero-terminated.
+        let mut configs = Vec::new();
+        for ch in channels {
+            configs.try_extend_from_slice(ch.config)?;
+            configs.try_push(0)?;
+        }
+
+        let mut infos = Vec::try_with_capacity(channels.len())?;
+        let mut offset = 0;
+        for ch in channels {
+            infos.try_push(bindings::hwmon_channel_info {
+                type_: ch.kind.as_raw(),
+                config: configs[offset..].as_ptr(),
+            })?;
+            offset += ch.config.len() + 1;
+        }
+
+        let mut info_ptrs = Vec::try_with_capacity(channels.len() + 1)?;
+        for info in &infos {
+            info_ptrs.try_push(info as *const _)?;
+        }
+        info_ptrs.try_push(ptr::null())?;
+
+        let mut reg = Pin::from(Box::try_new(Self {
+            hwmon: ptr::null_mut(),
+            _name: name,
+            chip: bindings::hwmon_chip_info {
+                ops: &Self::OPS,
+                info: info_ptrs.as_ptr(),
+            },
+            _infos: infos,
+            _info_ptrs: info_ptrs,
+            _configs: configs,
+            data,
+            _pin: PhantomPinned,
+        })?);
+
+        let this: *const Self = &*reg;
+        // SAFETY: `parent` is valid, and the ops are static. The name and the chip description
+        // are on the heap, and the driver data is pinned, and the chip is unregistered before
+        // they are freed.
+        let hwmon = from_err_ptr(unsafe {
+            bindings::hwmon_device_register_with_info(
+                parent.as_raw(),
+                (*this)._name.as_char_ptr(),
+                this as *mut c_void,
+                &(*this).chip,
+                ptr::null_mut(),
+            )
+        })?;
+        // INVARIANT: The chip was registered above, with this as its driver data.
+        // SAFETY: `hwmon` isn't structurally pinned.
+        unsafe { reg.as_mut().get_unchecked_mut() }.hwmon = hwmon;
+        Ok(reg)
+    }
+
+    /// Returns the driver data of the chip.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    /// Returns the driver data of the hwmon device `dev`.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `dev` is a hwmon device registered by `register`.
+    unsafe fn data_of<'a>(dev: *mut bindings::device) -> &'a T {
+        // SAFETY: The driver data of the device is a `Registration<T>` by the safety
+        // requirements, which outlives the device.
+        unsafe { &(*(*dev).driver_data.cast::<Self>()).data }
+    }
+
+    unsafe extern "C" fn is_visible_callback(
+        drvdata: *const c_void,
+        kind: bindings::hwmon_sensor_types,
+        attr: u32,
+        channel: c_int,
+    ) -> bindings::umode_t {
+        let kind = match SensorType::from_raw(kind) {
+            Some(kind) => kind,
+            None => return 0,
+        };
+        // SAFETY: The hwmon core calls this with the driver data passed to it by `register`.
+        let data = unsafe { &(*drvdata.cast::<Self>()).data };
+        T::is_visible(data, kind, attr, channel) as _
+    }
+
+    unsafe extern "C" fn read_callback(
+        dev: *mut bindings::device,
+        kind: bindings::hwmon_sensor_types,
+        attr: u32,
+        channel: c_int,
+        val: *mut c_long,
+    ) -> c_int {
+        from_result(|| {
+            let kind = SensorType::from_raw(kind).ok_or(EOPNOTSUPP)?;
+            // SAFETY: The hwmon core calls this with a device registered by `register`.
+            let value = T::read(unsafe { Self::data_of(dev) }, kind, attr, channel)?;
+            // SAFETY: The hwmon core passes a valid pointer to write the value to.
+            unsafe { *val = value as _ };
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn read_string_callback(
+        dev: *mut bindings::device,
+        kind: bindings::hwmon_sensor_types,
+        attr: u32,
+        channel: c_int,
+        s: *mut *const c_char,
+    ) -> c_int {
+        from_result(|| {
+            let kind = SensorType::from_raw(kind).ok_or(EOPNOTSUPP)?;
+            // SAFETY: The hwmon core calls this with a device registered by `register`.
+            let value = T::read_string(unsafe { Self::data_of(dev) }, kind, attr, channel)?;
+            // SAFETY: The hwmon core passes a valid pointer to write the string to, which is
+            // static.
+            unsafe { *s = value.as_char_ptr() };
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn write_callback(
+        dev: *mut bindings::device,
+        kind: bindings::hwmon_sensor_types,
+        attr: u32,
+        channel: c_int,
+        val: c_long,
+    ) -> c_int {
+        from_result(|| {
+            let kind = SensorType::from_raw(kind).ok_or(EOPNOTSUPP)?;
+            // SAFETY: The hwmon core calls this with a device registered by `register`.
+            T::write(unsafe { Self::data_of(dev) }, kind, attr, channel, val as _)?;
+            Ok(0)
+        })
+    }
+}
+
+impl<T: Operations> Drop for Registration<T> {
+    fn drop(&mut self) {
+        if !self.hwmon.is_null() {
+            // SAFETY: The chip was registered by the type invariants.
+            unsafe { bindings::hwmon_device_unregister(self.hwmon) };
+        }
+    }
+}
+
+// SAFETY: The chip can be unregistered from any thread, and the driver data is `Send`. The
+// pointers in the chip description are only used by the hwmon core.
+unsafe impl<T: Operations> Send for Registration<T> {}
+
+// SAFETY: The methods that take `&self` only read the driver data, which is `Sync`.
+unsafe impl<T: Operations> Sync for Registration<T> {}
@@ -0,0 +1,199 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Hardware spinlocks.
+//!
+//! A hardware spinlock synchronises accesses to resources shared with other processors, e.g.
+//! registers or memory shared with the firmware of a remote processor, which the kernel's locks
+//! can't do. The lock is held while a [`Guard`] exists, with preemption, and optionally
+//! interrupts, disabled, so the critical section must be short and must not sleep.
+//!
+//! C header: [`include/linux/hwspinlock.h`](../../../../include/linux/hwspinlock.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/locking/hwspinlock.html>
+
+use crate::{
+    bindings,
+    error::{code::*, to_result, Result},
+};
+use core::{
+    ffi::{c_int, c_ulong},
+    marker::PhantomData,
+    ptr::{self, NonNull},
+};
+
+#[cfg(CONFIG_OF)]
+use crate::{of::DeviceNode, str::CStr};
+
+/// A hardware spinlock, requested for exclusive use by the driver.
+///
+/// The lock is freed when this is dropped.
+///
+/// # Invariants
+///
+/// `lock` was requested with `hwspin_lock_request_specific`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{device::Device, hwspinlock::HwSpinlock, io_mem::IoMem, prelude::*};
+///
+/// const SHARED_CTRL: usize = 0x20;
+///
+/// fn set_shared_bits(hwlock: &HwSpinlock, regs: &IoMem<0x100>, bits: u32) -> Result {
+///     // The firmware of the other core holds the lock for at most a few microseconds.
+///     let _guard = hwlock.lock_timeout_irqsave(10)?;
+///     let ctrl = regs.readl(SHARED_CTRL);
+///     regs.writel(ctrl | bits, SHARED_CTRL);
+///     Ok(())
+/// }
+///
+/// fn probe(dev: &Device) -> Result<HwSpinlock> {
+///     let np = dev.of_node().ok_or(ENODEV)?;
+///     HwSpinlock::of_get(np, 0)
+/// }
+/// ```
+pub struct HwSpinlock {
+    lock: NonNull<bindings::hwspinlock>,
+}
+
+impl HwSpinlock {
+    /// Requests the hardware spinlock `id`.
+    ///
+    /// Fails with `EBUSY` if it is already in use or doesn't exist.
+    pub fn request(id: u32) -> Result<Self> {
+        crate::might_sleep!();
+        // SAFETY: FFI call with no additional requirements.
+        let lock = unsafe { bindings::hwspin_lock_request_specific(id) };
+        // INVARIANT: The lock was requested if it isn't null.
+        Ok(Self {
+            lock: NonNull::new(lock).ok_or(EBUSY)?,
+        })
+    }
+
+    /// Requests the hardware spinlock at `index` in the `hwlocks` property of `np`.
+    ///
+    /// Fails with `EPROBE_DEFER` if the provider of the lock isn't registered yet.
+    #[cfg(CONFIG_OF)]
+    pub fn of_get(np: &DeviceNode, index: u32) -> Result<Self> {
+        // SAFETY: `np` is valid by its type invariants.
+        let id = unsafe { bindings::of_hwspin_lock_get_id(np.as_raw(), index as _) };
+        to_result(id)?;
+        Self::request(id as _)
+    }
+
+    /// Requests the hardware spinlock named `name` in the `hwlock-names` property of `np`.
+    ///
+    /// Fails with `EPROBE_DEFER` if the provider of the lock isn't registered yet.
+    #[cfg(CONFIG_OF)]
+    pub fn of_get_by_name(np: &DeviceNode, name: &CStr) -> Result<Self> {
+        // SAFETY: `np` is valid by its type invariants, and `name` is a valid string.
+        let id = unsafe { bindings::of_hwspin_lock_get_id_byname(np.as_raw(), name.as_char_ptr()) };
+        to_result(id)?;
+        Self::request(id as _)
+    }
+
+    /// Returns the global id of the lock, e.g. to tell it to the other processor.
+    pub fn id(&self) -> u32 {
+        // SAFETY: The lock was requested by the type invariants.
+        unsafe { bindings::hwspin_lock_get_id(self.lock.as_ptr()) as _ }
+    }
+
+    fn lock_inner(&self, timeout_ms: Option<u32>, mode: c_int) -> Result<Guard<'_>> {
+        let mut flags: c_ulong = 0;
+        // SAFETY: The lock was requested by the type invariants, and `flags` is only written in
+        // `HWLOCK_IRQSTATE` mode.
+        let ret = unsafe {
+            match timeout_ms {
+                Some(to) => bindings::__hwspin_lock_timeout(
+                    self.lock.as_ptr(),
+                    to,
+                    mode,
+                    ptr::addr_of_mut!(flags),
+                ),
+                None => {
+                    bindings::__hwspin_trylock(self.lock.as_ptr(), mode, ptr::addr_of_mut!(flags))
+                }
+            }
+        };
+        to_result(ret)?;
+        // INVARIANT: The lock was taken above, in `mode`.
+        Ok(Guard {
+            lock: self,
+            mode,
+            flags,
+            _not_send: PhantomData,
+        })
+    }
+
+    /// Takes the lock, spinning for up to `timeout_ms` milliseconds, with preemption disabled.
+    ///
+    /// Fails with `ETIMEDOUT` if the lock couldn't be taken in time.
+    pub fn lock_timeout(&self, timeout_ms: u32) -> Result<Guard<'_>> {
+        self.lock_inner(Some(timeout_ms), 0)
+    }
+
+    /// Takes the lock like [`HwSpinlock::lock_timeout`], with local interrupts disabled, and
+    /// restores their state when the lock is released.
+    pub fn lock_timeout_irqsave(&self, timeout_ms: u32) -> Result<Guard<'_>> {
+        self.lock_inner(Some(timeout_ms), bindings::HWLOCK_IRQSTATE as _)
+    }
+
+    /// Tries to take the lock once, with preemption disabled.
+    ///
+    /// Fails with `EBUSY` if it is held.
+    pub fn try_lock(&self) -> Result<Guard<'_>> {
+        self.lock_inner(None, 0)
+    }
+
+    /// Tries to take the lock once, with local interrupts disabled, and restores their state
+    /// when the lock is released.
+    ///
+    /// Fails with `EBUSY` if it is held.
+    pub fn try_lock_irqsave(&self) -> Result<Guard<'_>> {
+        self.lock_inner(None, bindings::HWLOCK_IRQSTATE as _)
+    }
+}
+
+impl Drop for HwSpinlock {
+    fn drop(&mut self) {
+        // SAFETY: The lock was requested by the type invariants, and isn't held since guards
+        // borrow it.
+        unsafe { bindings::hwspin_lock_free(self.lock.as_ptr()) };
+    }
+}
+
+// SAFETY: The lock can be used and freed from any thread; the core serialises the attempts to
+// take it from this processor.
+unsafe impl Send for HwSpinlock {}
+
+// SAFETY: The methods that take `&self` can be called concurrently, see above.
+unsafe impl Sync for HwSpinlock {}
+
+/// A held hardware spinlock.
+///
+/// The lock is released when this is dropped. It can't be sent to another thread, since
+/// preemption, and possibly interrupts, are disabled on the CPU that took it.
+///
+/// # Invariants
+///
+/// `lock` is held, and was taken in `mode`, which saved the interrupt state in `flags` if it is
+/// `HWLOCK_IRQSTATE`.
+pub struct Guard<'a> {
+    lock: &'a HwSpinlock,
+    mode: c_int,
+    flags: c_ulong,
+    _not_send: PhantomData<*mut ()>,
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        // SAFETY: The lock is held in `mode` by the type invariants.
+        unsafe {
+            bindings::__hwspin_unlock(
+                self.lock.lock.as_ptr(),
+                self.mode,
+                ptr::addr_of_mut!(self.flags),
+            )
+        };
+    }
+}
@@ -0,0 +1,597 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! I2C clients and SMBus transfers.
+//!
+//! A [`Client`] is a device on an I2C bus, with its address and adapter. Simple devices, e.g.
+//! EEPROMs and sensors, are driven with the SMBus helpers of the client, which the I2C core
+//! emulates with plain I2C messages on adapters that don't support SMBus natively.
+//!
+//! Drivers of I2C clients implement [`Driver`], and are registered with
+//! [`module_i2c_driver!`](crate::module_i2c_driver).
+//!
+//! C header: [`include/linux/i2c.h`](../../../../include/linux/i2c.h)
+
+use crate::{
+    bindings,
+    device::Device,
+    error::{code::*, Error, Result},
+    str::CStr,
+    types::{AlwaysRefCounted, Opaque},
+};
+use core::{mem::MaybeUninit, ptr};
+
+#[cfg(CONFIG_OF)]
+use crate::{
+    error::{from_result, to_result},
+    of::{self, DeviceNode},
+    types::{ARef, ForeignOwnable},
+    ThisModule,
+};
+#[cfg(CONFIG_OF)]
+use alloc::boxed::Box;
+#[cfg(CONFIG_OF)]
+use core::{
+    ffi::c_int,
+    marker::{PhantomData, PhantomPinned},
+    pin::Pin,
+};
+#[cfg(CONFIG_OF)]
+use macros::vtable;
+
+/// The maximum length of an SMBus block transfer.
+pub const SMBUS_BLOCK_MAX: usize = bindings::I2C_SMBUS_BLOCK_MAX as usize;
+
+/// The transfers an adapter supports, the kernel's `I2C_FUNC_*`, see
+/// [`Client::check_functionality`].
+pub mod functionality {
+    /// Plain I2C messages.
+    pub const I2C: u32 = crate::bindings::I2C_FUNC_I2C;
+    /// SMBus receive and send byte.
+    pub const SMBUS_BYTE: u32 = crate::bindings::I2C_FUNC_SMBUS_BYTE;
+    /// SMBus read and write byte data.
+    pub const SMBUS_BYTE_DATA: u32 = crate::bindings::I2C_FUNC_SMBUS_BYTE_DATA;
+    /// SMBus read and write word data.
+    pub const SMBUS_WORD_DATA: u32 = crate::bindings::I2C_FUNC_SMBUS_WORD_DATA;
+    /// SMBus process call.
+    pub const SMBUS_PROC_CALL: u32 = crate::bindings::I2C_FUNC_SMBUS_PROC_CALL;
+    /// SMBus read and write block data.
+    pub const SMBUS_BLOCK_DATA: u32 = crate::bindings::I2C_FUNC_SMBUS_BLOCK_DATA;
+    /// SMBus block process call.
+    pub const SMBUS_BLOCK_PROC_CALL: u32 = crate::bindings::I2C_FUNC_SMBUS_BLOCK_PROC_CALL;
+    /// I2C block reads and writes, i.e. SMBus block transfers without a length byte.
+    pub const SMBUS_I2C_BLOCK: u32 = crate::bindings::I2C_FUNC_SMBUS_I2C_BLOCK;
+}
+
+/// Converts the return value of an SMBus function, a value or a negative error code.
+fn smbus_result(ret: i32) -> Result<u32> {
+    if ret < 0 {
+        Err(Error::from_errno(ret))
+    } else {
+        Ok(ret as u32)
+    }
+}
+
+/// A device on an I2C bus, the kernel's `struct i2c_client`.
+///
+/// The SMBus helpers sleep, and return the error of the adapter if the transfer fails, e.g.
+/// `ENXIO` if the device doesn't acknowledge its address.
+///
+/// # Invariants
+///
+/// The client is valid while references to it exist, and is reference-counted through its
+/// device.
+///
+/// # Examples
+///
+/// Reading an at24-style EEPROM, whose byte address is written before the data is read:
+///
+/// ```
+/// use kernel::{i2c::{functionality, Client}, prelude::*};
+///
+/// fn read_eeprom(client: &Client, offset: u8, buf: &mut [u8]) -> Result {
+///     if !client.check_functionality(functionality::SMBUS_I2C_BLOCK) {
+///         return Err(ENODEV);
+///     }
+///     let mut done = 0;
+///     while done < buf.len() {
+///         let len = (buf.len() - done).min(kernel::i2c::SMBUS_BLOCK_MAX);
+///         let cmd = offset.wrapping_add(done as u8);
+///         done += client.read_i2c_block_data(cmd, &mut buf[done..done + len])?;
+///     }
+///     Ok(())
+/// }
+///
+/// fn read_id(client: &Client) -> Result<u16> {
+///     // The device id is big-endian, unlike SMBus words.
+///     client.read_word_swapped(0xfe)
+/// }
+/// ```
+#[repr(transparent)]
+pub struct Client(Opaque<bindings::i2c_client>);
+
+impl Client {
+    /// Creates a reference to a [`Client`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is valid, non-null, and has a non-zero reference count for
+    /// the entire duration when the returned reference exists.
+    pub unsafe fn as_ref<'a>(ptr: *mut bindings::i2c_client) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct i2c_client` pointer.
+    pub fn as_raw(&self) -> *mut bindings::i2c_client {
+        self.0.get()
+    }
+
+    /// Finds the client of the devicetree node `np`, if it is registered.
+    #[cfg(CONFIG_OF)]
+    pub fn of_find_by_node(np: &DeviceNode) -> Option<ARef<Self>> {
+        // SAFETY: `np` is valid by its type invariants.
+        let client = unsafe { bindings::of_find_i2c_device_by_node(np.as_raw()) };
+        if client.is_null() {
+            None
+        } else {
+            // SAFETY: `of_find_i2c_device_by_node` took a reference to the client, which is
+            // transferred to the returned `ARef`.
+            Some(unsafe { ARef::from_raw(ptr::NonNull::new_unchecked(client.cast())) })
+        }
+    }
+
+    /// Returns the device of the client.
+    pub fn device(&self) -> &Device {
+        // SAFETY: The client is valid by the type invariants, and so is its device.
+        unsafe { Device::as_ref(ptr::addr_of_mut!((*self.as_raw()).dev)) }
+    }
+
+    /// Returns the name of the client, i.e. the type of its device.
+    pub fn name(&self) -> &CStr {
+        // SAFETY: The client is valid by the type invariants, and its name is a string that never
+        // changes.
+        unsafe { CStr::from_char_ptr((*self.as_raw()).name.as_ptr()) }
+    }
+
+    /// Returns the 7-bit or 10-bit address of the client.
+    pub fn addr(&self) -> u16 {
+        // SAFETY: The client is valid by the type invariants, and its address never changes.
+        unsafe { (*self.as_raw()).addr }
+    }
+
+    /// Returns the interrupt of the client, if it has one.
+    pub fn irq(&self) -> Option<u32> {
+        // SAFETY: The client is valid by the type invariants, and its interrupt is set before it
+        // is probed.
+        let irq = unsafe { (*self.as_raw()).irq };
+        (irq > 0).then_some(irq as u32)
+    }
+
+    /// Returns `true` if the adapter of the client supports all the transfers in `func`, a
+    /// combination of [`functionality`] flags.
+    pub fn check_functionality(&self, func: u32) -> bool {
+        // SAFETY: The client is valid by the type invariants, and so is its adapter.
+        let supported = unsafe { bindings::i2c_get_functionality((*self.as_raw()).adapter) };
+        supported & func == func
+    }
+
+    /// Performs an SMBus transfer of kind `size` with the command `command`, from or to `data`.
+    fn smbus_xfer(
+        &self,
+        read: bool,
+        command: u8,
+        size: u32,
+        data: &mut bindings::i2c_smbus_data,
+    ) -> Result {
+        let client = self.as_raw();
+        let read_write = if read {
+            bindings::I2C_SMBUS_READ
+        } else {
+            bindings::I2C_SMBUS_WRITE
+        };
+        // SAFETY: The client and its adapter are valid by the type invariants, and `data` is
+        // valid for the transfer.
+        smbus_result(unsafe {
+            bindings::i2c_smbus_xfer(
+                (*client).adapter,
+                (*client).addr,
+                (*client).flags,
+                read_write as _,
+                command,
+                size as _,
+                data,
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Receives a byte, without a command.
+    pub fn read_byte(&self) -> Result<u8> {
+        // SAFETY: The client is valid by the type invariants.
+        Ok(smbus_result(unsafe { bindings::i2c_smbus_read_byte(self.as_raw()) })? as u8)
+    }
+
+    /// Sends the byte `value`, without a command.
+    pub fn write_byte(&self, value: u8) -> Result {
+        // SAFETY: The client is valid by the type invariants.
+        smbus_result(unsafe { bindings::i2c_smbus_write_byte(self.as_raw(), value) })?;
+        Ok(())
+    }
+
+    /// Reads a byte from the register `command`.
+    pub fn read_byte_data(&self, command: u8) -> Result<u8> {
+        // SAFETY: The client is valid by the type invariants.
+        let ret = unsafe { bindings::i2c_smbus_read_byte_data(self.as_raw(), command) };
+        Ok(smbus_result(ret)? as u8)
+    }
+
+    /// Writes the byte `value` to the register `command`.
+    pub fn write_byte_data(&self, command: u8, value: u8) -> Result {
+        // SAFETY: The client is valid by the type invariants.
+        let ret = unsafe { bindings::i2c_smbus_write_byte_data(self.as_raw(), command, value) };
+        smbus_result(ret)?;
+        Ok(())
+    }
+
+    /// Reads a little-endian word from the register `command`.
+    pub fn read_word_data(&self, command: u8) -> Result<u16> {
+        // SAFETY: The client is valid by the type invariants.
+        let ret = unsafe { bindings::i2c_smbus_read_word_data(self.as_raw(), command) };
+        Ok(smbus_result(ret)? as u16)
+    }
+
+    /// Writes the word `value` to the register `command`, little-endian.
+    pub fn write_word_data(&self, command: u8, value: u16) -> Result {
+        // SAFETY: The client is valid by the type invariants.
+        let ret = unsafe { bindings::i2c_smbus_write_word_data(self.as_raw(), command, value) };
+        smbus_result(ret)?;
+        Ok(())
+    }
+
+    /// Reads a big-endian word from the register `command`, for devices that don't follow the
+    /// SMBus byte order.
+    pub fn read_word_swapped(&self, command: u8) -> Result<u16> {
+        Ok(self.read_word_data(command)?.swap_bytes())
+    }
+
+    /// Writes the word `value` to the register `command`, big-endian.
+    pub fn write_word_swapped(&self, command: u8, value: u16) -> Result {
+        self.write_word_data(command, value.swap_bytes())
+    }
+
+    /// Writes the word `value` to the register `command` and reads the word the device replies
+    /// with, an SMBus process call.
+    pub fn process_call(&self, command: u8, value: u16) -> Result<u16> {
+        // SAFETY: The transfer data is plain data, for which zero is valid.
+        let mut data: bindings::i2c_smbus_data = unsafe { MaybeUninit::zeroed().assume_init() };
+        data.word = value;
+        self.smbus_xfer(false, command, bindings::I2C_SMBUS_PROC_CALL, &mut data)?;
+        // SAFETY: The adapter wrote the reply to the word.
+        Ok(unsafe { data.word })
+    }
+
+    /// Reads a block from the register `command` into `buf`, and returns its length, which the
+    /// device sends first.
+    ///
+    /// The block is at most [`SMBUS_BLOCK_MAX`] bytes long.
+    pub fn read_block_data(&self, command: u8, buf: &mut [u8; SMBUS_BLOCK_MAX]) -> Result<usize> {
+        // SAFETY: The client is valid by the type invariants, and `buf` is as long as the
+        // largest block.
+        let ret = unsafe {
+            bindings::i2c_smbus_read_block_data(self.as_raw(), command, buf.as_mut_ptr())
+        };
+        Ok(smbus_result(ret)? as usize)
+    }
+
+    /// Writes the block `data` to the register `command`, preceded by its length.
+    ///
+    /// Fails with `EINVAL` if it is longer than [`SMBUS_BLOCK_MAX`] bytes.
+    pub fn write_block_data(&self, command: u8, data: &[u8]) -> Result {
+        if data.len() > SMBUS_BLOCK_MAX {
+            return Err(EINVAL);
+        }
+        // SAFETY: The client is valid by the type invariants, and `data` is copied.
+        let ret = unsafe {
+            bindings::i2c_smbus_write_block_data(
+                self.as_raw(),
+                command,
+                data.len() as _,
+                data.as_ptr(),
+            )
+        };
+        smbus_result(ret)?;
+        Ok(())
+    }
+
+    /// Writes the block `data` to the register `command` and reads the block the device replies
+    /// with into `buf`, an SMBus block process call, and returns the length of the reply.
+    ///
+    /// Fails with `EINVAL` if `data` is longer than [`SMBUS_BLOCK_MAX`] bytes.
+    pub fn block_process_call(
+        &self,
+        command: u8,
+        data: &[u8],
+        buf: &mut [u8; SMBUS_BLOCK_MAX],
+    ) -> Result<usize> {
+        if data.len() > SMBUS_BLOCK_MAX {
+            return Err(EINVAL);
+        }
+        // SAFETY: The transfer data is plain data, for which zero is valid.
+        let mut raw: bindings::i2c_smbus_data = unsafe { MaybeUninit::zeroed().assume_init() };
+        // SAFETY: The block is plain data, whose first byte is the length.
+        let block = unsafe { &mut raw.block };
+        block[0] = data.len() as u8;
+        block[1..=data.len()].copy_from_slice(data);
+        self.smbus_xfer(
+            false,
+            command,
+            bindings::I2C_SMBUS_BLOCK_PROC_CALL,
+            &mut raw,
+        )?;
+        // SAFETY: The adapter wrote the reply to the block, and checked its length.
+        let block = unsafe { &raw.block };
+        let len = (block[0] as usize).min(SMBUS_BLOCK_MAX);
+        buf[..len].copy_from_slice(&block[1..=len]);
+        Ok(len)
+    }
+
+    /// Reads `buf.len()` bytes from the register `command`, without a length byte, and returns
+    /// the number of bytes read.
+    ///
+    /// Adapters without I2C block reads are emulated with word or byte reads. Fails with
+    /// `EINVAL` if `buf` is longer than [`SMBUS_BLOCK_MAX`] bytes.
+    pub fn read_i2c_block_data(&self, command: u8, buf: &mut [u8]) -> Result<usize> {
+        if buf.len() > SMBUS_BLOCK_MAX {
+            return Err(EINVAL);
+        }
+        // SAFETY: The client is valid by the type invariants, and `buf` is valid for the
+        // requested length.
+        let ret = unsafe {
+            bindings::i2c_smbus_read_i2c_block_data_or_emulated(
+                self.as_raw(),
+                command,
+                buf.len() as _,
+                buf.as_mut_ptr(),
+            )
+        };
+        Ok(smbus_result(ret)? as usize)
+    }
+
+    /// Writes `data` to the register `command`, without a length byte.
+    ///
+    /// Fails with `EINVAL` if `data` is longer than [`SMBUS_BLOCK_MAX`] bytes.
+    pub fn write_i2c_block_data(&self, command: u8, data: &[u8]) -> Result {
+        if data.len() > SMBUS_BLOCK_MAX {
+            return Err(EINVAL);
+        }
+        // SAFETY: The client is valid by the type invariants, and `data` is copied.
+        let ret = unsafe {
+            bindings::i2c_smbus_write_i2c_block_data(
+                self.as_raw(),
+                command,
+                data.len() as _,
+                data.as_ptr(),
+            )
+        };
+        smbus_result(ret)?;
+        Ok(())
+    }
+}
+
+// SAFETY: Instances of `Client` are always reference-counted through their device.
+unsafe impl AlwaysRefCounted for Client {
+    fn inc_ref(&self) {
+        // SAFETY: The existence of a shared reference guarantees that the refcount is non-zero.
+        unsafe { bindings::get_device(self.device().as_raw()) };
+    }
+
+    unsafe fn dec_ref(obj: ptr::NonNull<Self>) {
+        // SAFETY: The safety requirements guarantee that the refcount is non-zero.
+        unsafe {
+            bindings::put_device(ptr::addr_of_mut!(
+                (*obj.as_ptr().cast::<bindings::i2c_client>()).dev
+            ))
+        }
+    }
+}
+
+// SAFETY: The client is reference-counted, and can be used from any thread.
+unsafe impl Send for Client {}
+
+// SAFETY: The methods that take `&self` either read fields that never change or use the adapter,
+// which serialises transfers with its own lock.
+unsafe impl Sync for Client {}
+
+/// A driver of I2C clients.
+#[cfg(CONFIG_OF)]
+#[vtable]
+pub trait Driver: Sized + 'static {
+    /// The data associated with each client bound to the driver.
+    type Data: ForeignOwnable + Send + Sync;
+
+    /// The name of the driver.
+    const NAME: &'static CStr;
+
+    /// The match table of the devicetree nodes that the driver handles.
+    const OF_MATCH: of::IdTable;
+
+    /// Binds the driver to `client`.
+    fn probe(client: &Client) -> Result<Self::Data>;
+
+    /// Unbinds the driver from `client`, which drops `data` once this returns.
+    fn remove(_client: &Client, _data: Self::Data) {}
+
+    /// Called when the system shuts down, e.g. to turn the device off.
+    fn shutdown(_client: &Client, _data: <Self::Data as ForeignOwnable>::Borrowed<'_>) {}
+}
+
+/// The registration of a driver of I2C clients.
+///
+/// The driver is unregistered when this is dropped, which unbinds it from its clients.
+///
+/// # Invariants
+///
+/// `driver` is registered if `registered` is `true`.
+#[cfg(CONFIG_OF)]
+pub struct Registration<T: Driver> {
+    driver: Opaque<bindings::i2c_driver>,
+    registered: bool,
+    _pin: PhantomPinned,
+    _p: PhantomData<T>,
+}
+
+// SAFETY: The driver can be unregistered from any thread.
+#[cfg(CONFIG_OF)]
+unsafe impl<T: Driver> Send for Registration<T> {}
+
+// SAFETY: `Registration` has no methods that take `&self`.
+#[cfg(CONFIG_OF)]
+unsafe impl<T: Driver> Sync for Registration<T> {}
+
+#[cfg(CONFIG_OF)]
+impl<T: Driver> Registration<T> {
+    /// Registers the driver on behalf of `module`.
+    ///
+    /// The driver is bound to the clients that match [`Driver::OF_MATCH`].
+    pub fn register(module: &'static ThisModule) -> Result<Pin<Box<Self>>> {
+        let mut reg = Pin::from(Box::try_new(Self {
+            driver: Opaque::new(bindings::i2c_driver {
+                driver: bindings::device_driver {
+                    name: T::NAME.as_char_ptr(),
+                    of_match_table: T::OF_MATCH.as_ptr(),
+                    // SAFETY: All other fields are optional, for which zero is valid.
+                    ..unsafe { MaybeUninit::zeroed().assume_init() }
+                },
+                __bindgen_anon_1: bindings::i2c_driver__bindgen_ty_1 {
+                    probe: Some(Self::probe_callback),
+                },
+                remove: Some(Self::remove_callback),
+                shutdown: if T::HAS_SHUTDOWN {
+                    Some(Self::shutdown_callback)
+                } else {
+                    None
+                },
+                // SAFETY: All other fields are optional, for which zero is valid.
+                ..unsafe { MaybeUninit::zeroed().assume_init() }
+            }),
+            registered: false,
+            _pin: PhantomPinned,
+            _p: PhantomData,
+        })?);
+        // SAFETY: `driver` is valid and pinned, its match table is static, and the driver is
+        // unregistered before it is freed.
+        to_result(unsafe { bindings::i2c_register_driver(module.as_ptr(), reg.driver.get()) })?;
+        // INVARIANT: The driver was registered above.
+        // SAFETY: `reg` isn't moved out of.
+        unsafe { reg.as_mut().get_unchecked_mut() }.registered = true;
+        Ok(reg)
+    }
+
+    unsafe extern "C" fn probe_callback(client: *mut bindings::i2c_client) -> c_int {
+        from_result(|| {
+            // SAFETY: The driver core calls this with a valid client, which stays valid until
+            // the driver is unbound.
+            let client = unsafe { Client::as_ref(client) };
+            let data = T::probe(client)?;
+            // SAFETY: The driver data of the device belongs to the driver bound to it, and is
+            // freed in `remove_callback`.
+            unsafe { (*client.device().as_raw()).driver_data = data.into_foreign() as _ };
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn remove_callback(client: *mut bindings::i2c_client) {
+        // SAFETY: The driver core calls this with a client that was bound by `probe_callback`,
+        // so its driver data is set. It isn't used once this returns.
+        unsafe {
+            let client = Client::as_ref(client);
+            let data = T::Data::from_foreign((*client.device().as_raw()).driver_data);
+            T::remove(client, data);
+        }
+    }
+
+    unsafe extern "C" fn shutdown_callback(client: *mut bindings::i2c_client) {
+        // SAFETY: The driver core calls this with a client that was bound by `probe_callback`,
+        // so its driver data is set.
+        unsafe {
+            let client = Client::as_ref(client);
+            T::shutdown(
+                client,
+                T::Data::borrow((*client.device().as_raw()).driver_data),
+            );
+        }
+    }
+}
+
+#[cfg(CONFIG_OF)]
+impl<T: Driver> Drop for Registration<T> {
+    fn drop(&mut self) {
+        if self.registered {
+            // SAFETY: The driver was registered by the type invariants.
+            unsafe { bindings::i2c_del_driver(self.driver.get()) };
+        }
+    }
+}
+
+/// Declares a kernel module that registers a driver of I2C clients, and emits the OF device
+/// table that allows the module to be loaded when a matching client is found.
+///
+/// # Examples
+///
+/// ```ignore
+/// use kernel::{c_str, i2c, of};
+/// use kernel::prelude::*;
+///
+/// kernel::module_i2c_driver! {
+///     type: Sensor,
+///     name: "rust_sensor",
+///     author: "Rust for Linux Contributors",
+///     description: "I2C sensor driver",
+///     license: "GPL",
+/// }
+///
+/// struct Sensor;
+///
+/// #[vtable]
+/// impl i2c::Driver for Sensor {
+///     type Data = ();
+///
+///     const NAME: &'static CStr = c_str!("rust-sensor");
+///     const OF_MATCH: of::IdTable = kernel::of_id_table![c_str!("vendor,rust-sensor")];
+///
+///     fn probe(client: &i2c::Client) -> Result {
+///         let id = client.read_byte_data(0x0f)?;
+///         pr_info!("sensor {:#x} at {:#x}\n", id, client.addr());
+///         Ok(())
+///     }
+/// }
+/// ```
+#[cfg(CONFIG_OF)]
+#[macro_export]
+macro_rules! module_i2c_driver {
+    (type: $type:ty, $($f:tt)*) => {
+        struct Module {
+            _reg: ::core::pin::Pin<$crate::prelude::Box<$crate::i2c::Registration<$type>>>,
+        }
+
+        $crate::prelude::module! {
+            type: Module,
+            $($f)*
+        }
+
+        impl $crate::Module for Module {
+            fn init(module: &'static $crate::ThisModule) -> $crate::error::Result<Self> {
+                Ok(Module {
+                    _reg: $crate::i2c::Registration::register(module)?,
+                })
+            }
+        }
+
+        #[cfg(MODULE)]
+        #[no_mangle]
+        static __mod_of__i2c_of_match_device_table: [
+            $crate::of::DeviceId;
+            <$type as $crate::i2c::Driver>::OF_MATCH.len_with_end()
+        ] = <$type as $crate::i2c::Driver>::OF_MATCH.to_array();
+    };
+}
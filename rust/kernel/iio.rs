@@ -0,0 +1,682 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Industrial I/O devices.
+//!
+//! A sensor driver describes the channels of its device with [`Channel`], and registers a
+//! [`Registration`]. The IIO core creates the sysfs attributes of the channels, which are read
+//! and written through [`Operations::read_raw`] and [`Operations::write_raw`]. Drivers that
+//! implement [`Operations::trigger_handler`] also get a triggered buffer, whose scan elements
+//! are the channels with a scan index.
+//!
+//! C header: [`include/linux/iio/iio.h`](../../../../include/linux/iio/iio.h)
+
+use crate::{
+    bindings, device,
+    error::{code::*, from_result, to_result, Result},
+    str::CString,
+    types::Opaque,
+    ThisModule,
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    ffi::{c_int, c_long, c_void},
+    fmt,
+    marker::{PhantomData, PhantomPinned},
+    mem::MaybeUninit,
+    pin::Pin,
+    ptr,
+};
+use macros::vtable;
+
+/// The type of a channel, the kernel's `enum iio_chan_type`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelType {
+    /// A voltage.
+    Voltage,
+    /// A current.
+    Current,
+    /// An acceleration.
+    Accel,
+    /// An angular velocity.
+    AnglVel,
+    /// A magnetic field.
+    Magn,
+    /// A temperature.
+    Temp,
+    /// An illuminance.
+    Light,
+    /// A pressure.
+    Pressure,
+    /// A proximity.
+    Proximity,
+    /// The timestamp of scans.
+    Timestamp,
+}
+
+impl ChannelType {
+    fn as_raw(self) -> bindings::iio_chan_type {
+        match self {
+            Self::Voltage => bindings::iio_chan_type_IIO_VOLTAGE,
+            Self::Current => bindings::iio_chan_type_IIO_CURRENT,
+            Self::Accel => bindings::iio_chan_type_IIO_ACCEL,
+            Self::AnglVel => bindings::iio_chan_type_IIO_ANGL_VEL,
+            Self::Magn => bindings::iio_chan_type_IIO_MAGN,
+            Self::Temp => bindings::iio_chan_type_IIO_TEMP,
+            Self::Light => bindings::iio_chan_type_IIO_LIGHT,
+            Self::Pressure => bindings::iio_chan_type_IIO_PRESSURE,
+            Self::Proximity => bindings::iio_chan_type_IIO_PROXIMITY,
+            Self::Timestamp => bindings::iio_chan_type_IIO_TIMESTAMP,
+        }
+    }
+}
+
+/// Modifiers of channels, the kernel's `enum iio_modifier` values.
+pub mod modifier {
+    /// The X axis.
+    pub const X: u32 = crate::bindings::iio_modifier_IIO_MOD_X;
+    /// The Y axis.
+    pub const Y: u32 = crate::bindings::iio_modifier_IIO_MOD_Y;
+    /// The Z axis.
+    pub const Z: u32 = crate::bindings::iio_modifier_IIO_MOD_Z;
+}
+
+/// Information of channels, the kernel's `enum iio_chan_info_enum` values.
+///
+/// These are passed to [`super::Operations::read_raw`] and [`super::Operations::write_raw`],
+/// and combined into the masks of [`super::Channel::with_info`] with [`super::info_mask`].
+pub mod info {
+    /// The raw value.
+    pub const RAW: u32 = crate::bindings::iio_chan_info_enum_IIO_CHAN_INFO_RAW;
+    /// The value, in the units of the channel type.
+    pub const PROCESSED: u32 = crate::bindings::iio_chan_info_enum_IIO_CHAN_INFO_PROCESSED;
+    /// The scale of the raw value.
+    pub const SCALE: u32 = crate::bindings::iio_chan_info_enum_IIO_CHAN_INFO_SCALE;
+    /// The offset of the raw value, applied before the scale.
+    pub const OFFSET: u32 = crate::bindings::iio_chan_info_enum_IIO_CHAN_INFO_OFFSET;
+    /// The sampling frequency, in hertz.
+    pub const SAMP_FREQ: u32 = crate::bindings::iio_chan_info_enum_IIO_CHAN_INFO_SAMP_FREQ;
+}
+
+/// Returns the mask of the channel information `infos`, e.g. [`info::RAW`].
+pub const fn info_mask(infos: &[u32]) -> c_long {
+    let mut mask = 0;
+    let mut i = 0;
+    while i < infos.len() {
+        mask |= 1 << infos[i];
+        i += 1;
+    }
+    mask
+}
+
+/// The layout of a channel in the scans of a buffer, the kernel's `struct iio_scan_type`.
+#[derive(Clone, Copy, Debug)]
+pub struct ScanType {
+    /// Whether the value is signed.
+    pub signed: bool,
+    /// The number of bits of the value.
+    pub realbits: u8,
+    /// The number of bits that the value is stored in.
+    pub storagebits: u8,
+    /// The number of bits that the value is shifted left by in its storage.
+    pub shift: u8,
+    /// Whether the value is stored big-endian, instead of in the CPU byte order.
+    pub big_endian: bool,
+}
+
+/// The description of a channel, the kernel's `struct iio_chan_spec`.
+#[derive(Clone, Copy, Debug)]
+pub struct Channel {
+    kind: ChannelType,
+    index: Option<i32>,
+    modifier: Option<u32>,
+    address: u64,
+    output: bool,
+    info_separate: c_long,
+    info_shared_by_type: c_long,
+    scan: Option<(i32, ScanType)>,
+}
+
+impl Channel {
+    /// Creates a channel of type `kind`, which has no attributes nor scan element.
+    pub const fn new(kind: ChannelType) -> Self {
+        Self {
+            kind,
+            index: None,
+            modifier: None,
+            address: 0,
+            output: false,
+            info_separate: 0,
+            info_shared_by_type: 0,
+            scan: None,
+        }
+    }
+
+    /// Creates the timestamp channel of scans, whose scan index must be the last one.
+    pub const fn timestamp(scan_index: i32) -> Self {
+        Self::new(ChannelType::Timestamp).with_scan(
+            scan_index,
+            ScanType {
+                signed: true,
+                realbits: 64,
+                storagebits: 64,
+                shift: 0,
+                big_endian: false,
+            },
+        )
+    }
+
+    /// Numbers the channel, e.g. `in_voltage0`.
+    pub const fn with_index(mut self, index: i32) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    /// Sets the modifier of the channel, e.g. [`modifier::X`] for `in_accel_x`.
+    pub const fn with_modifier(mut self, modifier: u32) -> Self {
+        self.modifier = Some(modifier);
+        self
+    }
+
+    /// Sets the driver-specific address of the channel, e.g. its register.
+    pub const fn with_address(mut self, address: u64) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Makes the channel an output, e.g. of a DAC.
+    pub const fn with_output(mut self) -> Self {
+        self.output = true;
+        self
+    }
+
+    /// Sets the information of the channel, the masks returned by [`info_mask`], which is
+    /// either specific to the channel or shared by the channels of the same type.
+    pub const fn with_info(mut self, separate: c_long, shared_by_type: c_long) -> Self {
+        self.info_separate = separate;
+        self.info_shared_by_type = shared_by_type;
+        self
+    }
+
+    /// Makes the channel a scan element of the buffer, with the position `scan_index` in the
+    /// scans and the layout `scan_type`.
+    pub const fn with_scan(mut self, scan_index: i32, scan_type: ScanType) -> Self {
+        self.scan = Some((scan_index, scan_type));
+        self
+    }
+
+    fn to_raw(self) -> bindings::iio_chan_spec {
+        // SAFETY: All other fields are optional, for which zero is valid.
+        let mut raw: bindings::iio_chan_spec = unsafe { MaybeUninit::zeroed().assume_init() };
+        raw.type_ = self.kind.as_raw();
+        raw.address = self.address as _;
+        raw.info_mask_separate = self.info_separate;
+        raw.info_mask_shared_by_type = self.info_shared_by_type;
+        raw.set_output(self.output as _);
+        if let Some(index) = self.index {
+            raw.channel = index;
+            raw.set_indexed(1);
+        }
+        if let Some(modifier) = self.modifier {
+            raw.channel2 = modifier as _;
+            raw.set_modified(1);
+        }
+        match self.scan {
+            Some((scan_index, scan_type)) => {
+                raw.scan_index = scan_index;
+                raw.scan_type.sign = if scan_type.signed { b's' } else { b'u' } as _;
+                raw.scan_type.realbits = scan_type.realbits;
+                raw.scan_type.storagebits = scan_type.storagebits;
+                raw.scan_type.shift = scan_type.shift;
+                raw.scan_type.endianness = if scan_type.big_endian {
+                    bindings::iio_endian_IIO_BE
+                } else {
+                    bindings::iio_endian_IIO_CPU
+                };
+            }
+            None => raw.scan_index = -1,
+        }
+        raw
+    }
+}
+
+/// A channel of a registered device, the kernel's `struct iio_chan_spec`.
+#[repr(transparent)]
+pub struct ChannelSpec(Opaque<bindings::iio_chan_spec>);
+
+impl ChannelSpec {
+    /// Creates a reference to a [`ChannelSpec`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is valid and doesn't change for the lifetime of the
+    /// returned reference.
+    unsafe fn from_raw<'a>(ptr: *const bindings::iio_chan_spec) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    fn raw(&self) -> &bindings::iio_chan_spec {
+        // SAFETY: The channel doesn't change while references to it exist.
+        unsafe { &*self.0.get() }
+    }
+
+    /// Returns the number of the channel, or -1 if it isn't numbered.
+    pub fn index(&self) -> i32 {
+        if self.raw().indexed() != 0 {
+            self.raw().channel
+        } else {
+            -1
+        }
+    }
+
+    /// Returns the driver-specific address of the channel.
+    pub fn address(&self) -> u64 {
+        self.raw().address as _
+    }
+
+    /// Returns the position of the channel in the scans, or -1 if it isn't a scan element.
+    pub fn scan_index(&self) -> i32 {
+        self.raw().scan_index
+    }
+}
+
+/// A value of channel information, the kernel's `IIO_VAL_*` formats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Value {
+    /// An integer.
+    Int(i32),
+    /// An integer plus millionths.
+    IntPlusMicro(i32, i32),
+    /// An integer plus billionths.
+    IntPlusNano(i32, i32),
+    /// A fraction.
+    Fractional(i32, i32),
+    /// A numerator divided by a power of two.
+    FractionalLog2(i32, i32),
+}
+
+impl Value {
+    fn to_raw(self) -> (c_int, i32, i32) {
+        match self {
+            Self::Int(v) => (bindings::IIO_VAL_INT as _, v, 0),
+            Self::IntPlusMicro(v, v2) => (bindings::IIO_VAL_INT_PLUS_MICRO as _, v, v2),
+            Self::IntPlusNano(v, v2) => (bindings::IIO_VAL_INT_PLUS_NANO as _, v, v2),
+            Self::Fractional(v, v2) => (bindings::IIO_VAL_FRACTIONAL as _, v, v2),
+            Self::FractionalLog2(v, v2) => (bindings::IIO_VAL_FRACTIONAL_LOG2 as _, v, v2),
+        }
+    }
+}
+
+/// The operations of an IIO device, the kernel's `struct iio_info`.
+///
+/// The driver data of the device implements this trait.
+#[vtable]
+pub trait Operations: Send + Sync + Sized + 'static {
+    /// Reads the information `info` of the channel `chan`, e.g. [`info::RAW`].
+    fn read_raw(dev: &Device<Self>, chan: &ChannelSpec, info: u32) -> Result<Value>;
+
+    /// Writes the information `info` of the channel `chan`, which is `val` plus `val2`
+    /// millionths.
+    fn write_raw(
+        _dev: &Device<Self>,
+        _chan: &ChannelSpec,
+        _info: u32,
+        _val: i32,
+        _val2: i32,
+    ) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Reads a scan of the enabled scan elements, and pushes it with
+    /// [`Device::push_to_buffers_with_timestamp`].
+    ///
+    /// This is called in a threaded interrupt handler when the trigger of the device fires, with
+    /// the time that it fired at. Registering a device whose driver implements this fails with
+    /// `EINVAL` unless the kernel has `CONFIG_IIO_TRIGGERED_BUFFER`.
+    fn trigger_handler(_dev: &Device<Self>, _timestamp: i64) {}
+}
+
+/// An IIO device, the kernel's `struct iio_dev`.
+///
+/// # Invariants
+///
+/// The device is valid while references to it exist, and its driver data is a
+/// `Registration<T>`.
+#[repr(transparent)]
+pub struct Device<T: Operations>(Opaque<bindings::iio_dev>, PhantomData<T>);
+
+impl<T: Operations> Device<T> {
+    /// Creates a reference to a [`Device`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is a device set up by a `Registration<T>` for the lifetime
+    /// of the returned reference.
+    unsafe fn from_raw<'a>(ptr: *mut bindings::iio_dev) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct iio_dev` pointer.
+    pub fn as_raw(&self) -> *mut bindings::iio_dev {
+        self.0.get()
+    }
+
+    /// Returns the driver data of the device.
+    pub fn data(&self) -> &T {
+        // SAFETY: The driver data of the device is a `Registration<T>` by the type invariants,
+        // which outlives the device.
+        unsafe { &(*(*self.as_raw()).dev.driver_data.cast::<Registration<T>>()).data }
+    }
+
+    /// Claims the direct mode of the device, which keeps the buffer from being enabled until
+    /// the returned guard is dropped.
+    ///
+    /// Fails with `EBUSY` if the buffer is enabled, e.g. for drivers that can't read single
+    /// values then.
+    pub fn claim_direct_mode(&self) -> Result<DirectModeGuard<'_, T>> {
+        // SAFETY: The device is valid by the type invariants.
+        to_result(unsafe { bindings::iio_device_claim_direct_mode(self.as_raw()) })?;
+        Ok(DirectModeGuard { dev: self })
+    }
+
+    /// Returns whether the scan element with the scan index `scan_index` is enabled.
+    ///
+    /// This is only meaningful while the buffer is enabled, e.g. in
+    /// [`Operations::trigger_handler`].
+    pub fn is_scan_active(&self, scan_index: u32) -> bool {
+        let bits = c_long::BITS;
+        // SAFETY: The device is valid by the type invariants. The active scan mask is either
+        // null or has `masklength` bits, and only changes while the buffer is disabled.
+        unsafe {
+            let raw = self.as_raw();
+            let mask = (*raw).active_scan_mask;
+            if mask.is_null() || scan_index >= (*raw).masklength as u32 {
+                return false;
+            }
+            (*mask.add((scan_index / bits) as _) >> (scan_index % bits)) & 1 != 0
+        }
+    }
+
+    /// Returns the size of a scan of the enabled scan elements, including the timestamp.
+    pub fn scan_bytes(&self) -> usize {
+        // SAFETY: The device is valid by the type invariants.
+        unsafe { (*self.as_raw()).scan_bytes as _ }
+    }
+
+    /// Pushes the scan `data` to the buffers of the device, after filling in the timestamp if
+    /// it is enabled.
+    ///
+    /// Fails with `EINVAL` if `data` is smaller than [`Device::scan_bytes`] or isn't aligned for
+    /// the timestamp.
+    pub fn push_to_buffers_with_timestamp(&self, data: &mut [u8], timestamp: i64) -> Result {
+        if data.len() < self.scan_bytes() || data.as_ptr() as usize % 8 != 0 {
+            return Err(EINVAL);
+        }
+        // SAFETY: The device is valid by the type invariants, and `data` is large enough and
+        // aligned for the timestamp to be written at its end.
+        to_result(unsafe {
+            bindings::iio_push_to_buffers_with_timestamp(
+                self.as_raw(),
+                data.as_mut_ptr().cast(),
+                timestamp,
+            )
+        })
+    }
+}
+
+// SAFETY: The methods that take `&self` are safe to call concurrently, and the driver data is
+// `Sync`.
+unsafe impl<T: Operations> Sync for Device<T> {}
+
+/// A claim of the direct mode of an IIO device, returned by [`Device::claim_direct_mode`].
+///
+/// The claim is released when this is dropped.
+pub struct DirectModeGuard<'a, T: Operations> {
+    dev: &'a Device<T>,
+}
+
+impl<T: Operations> Drop for DirectModeGuard<'_, T> {
+    fn drop(&mut self) {
+        // SAFETY: The direct mode was claimed by `claim_direct_mode`.
+        unsafe { bindings::iio_device_release_direct_mode(self.dev.as_raw()) };
+    }
+}
+
+/// A registered IIO device.
+///
+/// The device is unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `indio` is null until the device is allocated by [`Registration::register`], and then a
+/// device owned by the registration, whose driver data is this `Registration<T>`, whose name is
+/// `name` and whose channels are `channels`. It has a triggered buffer if `buffered` is `true`,
+/// and is registered if `registered` is `true`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{device::Device, fmt, prelude::*, ThisModule};
+/// use kernel::iio::{self, info, info_mask, modifier, Channel, ChannelSpec, ChannelType, Value};
+///
+/// struct Accel;
+///
+/// #[vtable]
+/// impl iio::Operations for Accel {
+///     fn read_raw(_dev: &iio::Device<Self>, chan: &ChannelSpec, info: u32) -> Result<Value> {
+///         match info {
+///             info::RAW => Ok(Value::Int(chan.address() as i32)),
+///             info::SCALE => Ok(Value::IntPlusMicro(0, 9806)),
+///             _ => Err(EINVAL),
+///         }
+///     }
+/// }
+///
+/// fn probe(
+///     dev: &Device,
+///     module: &'static ThisModule,
+/// ) -> Result<Pin<Box<iio::Registration<Accel>>>> {
+///     let accel = Channel::new(ChannelType::Accel)
+///         .with_info(info_mask(&[info::RAW]), info_mask(&[info::SCALE]));
+///     let channels = [
+///         accel.with_modifier(modifier::X).with_address(0),
+///         accel.with_modifier(modifier::Y).with_address(1),
+///         accel.with_modifier(modifier::Z).with_address(2),
+///     ];
+///     iio::Registration::register(dev, module, fmt!("accel"), &channels, Accel)
+/// }
+/// ```
+pub struct Registration<T: Operations> {
+    indio: *mut bindings::iio_dev,
+    #[cfg(CONFIG_IIO_TRIGGERED_BUFFER)]
+    buffered: bool,
+    registered: bool,
+    name: CString,
+    channels: Vec<bindings::iio_chan_spec>,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: Operations> Registration<T> {
+    const INFO: bindings::iio_info = bindings::iio_info {
+        read_raw: Some(Self::read_raw_callback),
+        write_raw: if T::HAS_WRITE_RAW {
+            Some(Self::write_raw_callback)
+        } else {
+            None
+        },
+        // SAFETY: All other fields are optional, for which zero is valid.
+        ..unsafe { MaybeUninit::zeroed().assume_init() }
+    };
+
+    /// Registers an IIO device named `name`, which may be built at runtime, whose parent is
+    /// `parent`, for the module `module`, with the channels `channels` and the driver data
+    /// `data`.
+    ///
+    /// The device gets a triggered buffer if the driver implements
+    /// [`Operations::trigger_handler`].
+    pub fn register(
+        parent: &device::Device,
+        module: &'static ThisModule,
+        name: fmt::Arguments<'_>,
+        channels: &[Channel],
+        data: T,
+    ) -> Result<Pin<Box<Self>>> {
+        crate::might_sleep!();
+        let name = CString::try_from_fmt(name)?;
+        let mut specs = Vec::try_with_capacity(channels.len())?;
+        for chan in channels {
+            specs.try_push(chan.to_raw())?;
+        }
+
+        let mut reg = Pin::from(Box::try_new(Self {
+            indio: ptr::null_mut(),
+            #[cfg(CONFIG_IIO_TRIGGERED_BUFFER)]
+            buffered: false,
+            registered: false,
+            name,
+            channels: specs,
+            data,
+            _pin: PhantomPinned,
+        })?);
+        // SAFETY: `indio`, `buffered` and `registered` aren't structurally pinned.
+        let this = unsafe { reg.as_mut().get_unchecked_mut() };
+
+        // SAFETY: `parent` is valid.
+        let indio = unsafe { bindings::iio_device_alloc(parent.as_raw(), 0) };
+        if indio.is_null() {
+            return Err(ENOMEM);
+        }
+        // INVARIANT: The device was allocated above, and is set up below.
+        this.indio = indio;
+
+        // SAFETY: The device was just allocated, and isn't registered yet. The info is static,
+        // and the name and channels are on the heap, and the driver data is pinned, and the
+        // device is unregistered before they are freed.
+        unsafe {
+            (*indio).name = this.name.as_char_ptr();
+            (*indio).info = &Self::INFO;
+            (*indio).modes = bindings::INDIO_DIRECT_MODE as _;
+            (*indio).channels = this.channels.as_ptr();
+            (*indio).num_channels = this.channels.len() as _;
+            (*indio).dev.driver_data = this as *mut Self as *mut c_void;
+        }
+
+        #[cfg(not(CONFIG_IIO_TRIGGERED_BUFFER))]
+        if T::HAS_TRIGGER_HANDLER {
+            return Err(EINVAL);
+        }
+        #[cfg(CONFIG_IIO_TRIGGERED_BUFFER)]
+        if T::HAS_TRIGGER_HANDLER {
+            // SAFETY: The device is set up and not registered yet.
+            to_result(unsafe {
+                bindings::iio_triggered_buffer_setup_ext(
+                    indio,
+                    Some(bindings::iio_pollfunc_store_time),
+                    Some(Self::trigger_callback),
+                    bindings::iio_buffer_direction_IIO_BUFFER_DIRECTION_IN,
+                    ptr::null(),
+                    ptr::null_mut(),
+                )
+            })?;
+            this.buffered = true;
+        }
+
+        // SAFETY: The device is set up, and the module outlives it.
+        to_result(unsafe { bindings::__iio_device_register(indio, module.as_ptr()) })?;
+        this.registered = true;
+        Ok(reg)
+    }
+
+    /// Returns the IIO device.
+    pub fn device(&self) -> &Device<T> {
+        // SAFETY: The device is set up once this is returned by `register`, by the type
+        // invariants, and its driver data is `self`.
+        unsafe { Device::from_raw(self.indio) }
+    }
+
+    /// Returns the driver data of the device.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    unsafe extern "C" fn read_raw_callback(
+        indio: *mut bindings::iio_dev,
+        chan: *const bindings::iio_chan_spec,
+        val: *mut c_int,
+        val2: *mut c_int,
+        mask: c_long,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The IIO core calls this with a device set up by `register` and one of its
+            // channels, which don't change.
+            let (dev, chan) = unsafe { (Device::from_raw(indio), ChannelSpec::from_raw(chan)) };
+            let (format, v, v2) = T::read_raw(dev, chan, mask as _)?.to_raw();
+            // SAFETY: The IIO core passes valid pointers to write the value to.
+            unsafe {
+                *val = v;
+                *val2 = v2;
+            }
+            Ok(format)
+        })
+    }
+
+    unsafe extern "C" fn write_raw_callback(
+        indio: *mut bindings::iio_dev,
+        chan: *const bindings::iio_chan_spec,
+        val: c_int,
+        val2: c_int,
+        mask: c_long,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The IIO core calls this with a device set up by `register` and one of its
+            // channels, which don't change.
+            let (dev, chan) = unsafe { (Device::from_raw(indio), ChannelSpec::from_raw(chan)) };
+            T::write_raw(dev, chan, mask as _, val, val2)?;
+            Ok(0)
+        })
+    }
+
+    #[cfg(CONFIG_IIO_TRIGGERED_BUFFER)]
+    unsafe extern "C" fn trigger_callback(_irq: c_int, p: *mut c_void) -> bindings::irqreturn_t {
+        let pf = p.cast::<bindings::iio_poll_func>();
+        // SAFETY: The IIO core calls this with the poll function of a device set up by
+        // `register`, whose trigger stays valid while the buffer is enabled.
+        unsafe {
+            let indio = (*pf).indio_dev;
+            T::trigger_handler(Device::from_raw(indio), (*pf).timestamp);
+            bindings::iio_trigger_notify_done((*indio).trig);
+        }
+        bindings::irqreturn_IRQ_HANDLED
+    }
+}
+
+impl<T: Operations> Drop for Registration<T> {
+    fn drop(&mut self) {
+        if self.indio.is_null() {
+            return;
+        }
+
+        // SAFETY: The device is owned by the registration, and registered and buffered as
+        // recorded, by the type invariants.
+        unsafe {
+            if self.registered {
+                bindings::iio_device_unregister(self.indio);
+            }
+            #[cfg(CONFIG_IIO_TRIGGERED_BUFFER)]
+            if self.buffered {
+                bindings::iio_triggered_buffer_cleanup(self.indio);
+            }
+            bindings::iio_device_free(self.indio);
+        }
+    }
+}
+
+// SAFETY: The device can be unregistered from any thread, and the driver data is `Send`. The
+// channels are only read by the IIO core.
+unsafe impl<T: Operations> Send for Registration<T> {}
+
+// SAFETY: The methods that take `&self` only read fields that never change, and the driver data
+// is `Sync`.
+unsafe impl<T: Operations> Sync for Registration<T> {}
@@ -0,0 +1,439 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Input devices.
+//!
+//! A driver declares the capabilities of an input device with a [`Builder`], registers it, and
+//! then reports events through the [`Device`] of the [`Registration`], e.g. from its interrupt
+//! handler. Event codes are the kernel's `KEY_*`, `BTN_*`, `ABS_*` and `REL_*` values, which are
+//! in [`crate::bindings`].
+//!
+//! C header: [`include/linux/input.h`](../../../../include/linux/input.h)
+
+use crate::{
+    bindings, device,
+    error::{code::*, from_result, to_result, Result},
+    str::CString,
+    types::Opaque,
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::{c_int, c_void},
+    fmt,
+    marker::{PhantomData, PhantomPinned},
+    mem::ManuallyDrop,
+    pin::Pin,
+    ptr::{self, NonNull},
+};
+use macros::vtable;
+
+/// Types of events, the kernel's `EV_*` values.
+pub mod ev {
+    /// Synchronisation events, which separate reports.
+    pub const SYN: u32 = crate::bindings::EV_SYN;
+    /// Keys and buttons.
+    pub const KEY: u32 = crate::bindings::EV_KEY;
+    /// Relative axes, e.g. of mice.
+    pub const REL: u32 = crate::bindings::EV_REL;
+    /// Absolute axes, e.g. of touchscreens.
+    pub const ABS: u32 = crate::bindings::EV_ABS;
+    /// Switches, e.g. lid switches.
+    pub const SW: u32 = crate::bindings::EV_SW;
+}
+
+/// Flags of multi-touch slots, the kernel's `INPUT_MT_*` values.
+pub mod mt_flags {
+    /// The device is a touchpad.
+    pub const POINTER: u32 = crate::bindings::INPUT_MT_POINTER;
+    /// The device is a touchscreen.
+    pub const DIRECT: u32 = crate::bindings::INPUT_MT_DIRECT;
+    /// Slots that aren't reported in a frame are released by [`super::Device::mt_sync_frame`].
+    pub const DROP_UNUSED: u32 = crate::bindings::INPUT_MT_DROP_UNUSED;
+}
+
+/// The identity of an input device, the kernel's `struct input_id`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Id {
+    /// The bus of the device, one of the kernel's `BUS_*` values.
+    pub bustype: u16,
+    /// The vendor of the device.
+    pub vendor: u16,
+    /// The product of the device.
+    pub product: u16,
+    /// The version of the device.
+    pub version: u16,
+}
+
+/// The range of an absolute axis, the kernel's `struct input_absinfo`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AbsInfo {
+    /// The minimum value.
+    pub min: i32,
+    /// The maximum value.
+    pub max: i32,
+    /// The noise of the values, which smaller changes are filtered out by.
+    pub fuzz: i32,
+    /// The size of the dead zone around the centre, for joysticks.
+    pub flat: i32,
+    /// The resolution of the axis, in units per millimetre.
+    pub resolution: i32,
+}
+
+/// The operations of an input device, the kernel's `struct input_dev` callbacks.
+///
+/// The driver data of the device implements this trait.
+#[vtable]
+pub trait Operations: Send + Sync + Sized + 'static {
+    /// Called when the device is first opened, e.g. to power the hardware up.
+    fn open(_dev: &Device<Self>) -> Result {
+        Ok(())
+    }
+
+    /// Called when the device is last closed.
+    fn close(_dev: &Device<Self>) {}
+
+    /// Plays a rumble effect, with the magnitudes of the strong and weak motors, which are zero
+    /// to stop it.
+    ///
+    /// Devices whose driver implements this have the `FF_RUMBLE` force-feedback effect. This is
+    /// called in atomic context.
+    fn play_rumble(_dev: &Device<Self>, _strong: u16, _weak: u16) -> Result {
+        Err(EINVAL)
+    }
+}
+
+/// An input device being set up, before it is registered.
+///
+/// The device is freed if this is dropped.
+///
+/// # Invariants
+///
+/// `dev` is an allocated but unregistered input device, owned by the `Builder`, whose name is
+/// `name`.
+pub struct Builder {
+    dev: NonNull<bindings::input_dev>,
+    name: CString,
+}
+
+impl Builder {
+    /// Allocates an input device named `name`, which may be built at runtime, whose parent is
+    /// `parent`.
+    pub fn new(parent: &device::Device, name: fmt::Arguments<'_>, id: Id) -> Result<Self> {
+        let name = CString::try_from_fmt(name)?;
+        // SAFETY: FFI call without safety requirements.
+        let dev = NonNull::new(unsafe { bindings::input_allocate_device() }).ok_or(ENOMEM)?;
+        let raw = dev.as_ptr();
+        // SAFETY: The device was just allocated, and nothing else uses it yet. The name is owned
+        // by the builder, and then the registration, whose heap buffer doesn't move and is only
+        // freed with the device. The device is freed or unregistered before `parent` goes away,
+        // since the driver of `parent` owns it.
+        unsafe {
+            (*raw).name = name.as_char_ptr();
+            (*raw).dev.parent = parent.as_raw();
+            (*raw).id = bindings::input_id {
+                bustype: id.bustype,
+                vendor: id.vendor,
+                product: id.product,
+                version: id.version,
+            };
+        }
+        // INVARIANT: The device was allocated and named above.
+        Ok(Self { dev, name })
+    }
+
+    fn as_raw(&self) -> *mut bindings::input_dev {
+        self.dev.as_ptr()
+    }
+
+    /// Declares that the device reports events of type `ev_type` with the code `code`, see
+    /// [`ev`].
+    pub fn set_capability(&mut self, ev_type: u32, code: u32) {
+        // SAFETY: The device is valid and unregistered by the type invariants.
+        unsafe { bindings::input_set_capability(self.as_raw(), ev_type, code) };
+    }
+
+    /// Declares that the device has the key or button `code`.
+    pub fn set_key(&mut self, code: u32) {
+        self.set_capability(ev::KEY, code);
+    }
+
+    /// Declares that the device has the absolute axis `axis`, with the range `info`.
+    pub fn set_abs(&mut self, axis: u32, info: &AbsInfo) {
+        // SAFETY: The device is valid and unregistered by the type invariants.
+        unsafe {
+            bindings::input_set_abs_params(
+                self.as_raw(),
+                axis,
+                info.min,
+                info.max,
+                info.fuzz,
+                info.flat,
+            );
+            bindings::input_abs_set_res(self.as_raw(), axis, info.resolution);
+        }
+    }
+
+    /// Declares that the device tracks up to `slots` contacts, with the flags `flags`, see
+    /// [`mt_flags`].
+    ///
+    /// The `ABS_MT_*` axes of the contacts must be declared with [`Builder::set_abs`] first.
+    pub fn set_mt_slots(&mut self, slots: u32, flags: u32) -> Result {
+        // SAFETY: The device is valid and unregistered by the type invariants.
+        to_result(unsafe { bindings::input_mt_init_slots(self.as_raw(), slots, flags) })
+    }
+
+    /// Registers the device, with the driver data `data`.
+    pub fn register<T: Operations>(self, data: T) -> Result<Pin<Box<Registration<T>>>> {
+        let this = ManuallyDrop::new(self);
+        let dev = this.dev;
+        // SAFETY: `this` is never dropped, so the name is only moved out of it here.
+        let name = unsafe { ptr::read(&this.name) };
+        // The registration owns the device from here on, and frees it if this fails.
+        let mut reg = match Box::try_new(Registration {
+            dev,
+            _name: name,
+            registered: false,
+            data,
+            _pin: PhantomPinned,
+        }) {
+            Ok(reg) => Pin::from(reg),
+            Err(e) => {
+                // SAFETY: The device is unregistered, and isn't used anymore.
+                unsafe { bindings::input_free_device(dev.as_ptr()) };
+                return Err(e.into());
+            }
+        };
+        let raw = dev.as_ptr();
+        let this: *const Registration<T> = &*reg;
+        // SAFETY: The device is unregistered. The registration is pinned, and unregisters the
+        // device before it is freed.
+        unsafe {
+            (*raw).dev.driver_data = this as *mut c_void;
+            if T::HAS_OPEN {
+                (*raw).open = Some(Registration::<T>::open_callback);
+            }
+            if T::HAS_CLOSE {
+                (*raw).close = Some(Registration::<T>::close_callback);
+            }
+            if T::HAS_PLAY_RUMBLE {
+                bindings::input_set_capability(raw, bindings::EV_FF, bindings::FF_RUMBLE);
+                to_result(bindings::input_ff_create_memless(
+                    raw,
+                    core::ptr::null_mut(),
+                    Some(Registration::<T>::play_effect_callback),
+                ))?;
+            }
+            to_result(bindings::input_register_device(raw))?;
+        }
+        // INVARIANT: The device was registered above.
+        // SAFETY: `registered` isn't structurally pinned.
+        unsafe { reg.as_mut().get_unchecked_mut() }.registered = true;
+        Ok(reg)
+    }
+}
+
+impl Drop for Builder {
+    fn drop(&mut self) {
+        // SAFETY: The device is unregistered and owned by the `Builder` by the type invariants.
+        unsafe { bindings::input_free_device(self.as_raw()) };
+    }
+}
+
+// SAFETY: The device can be set up and freed from any thread.
+unsafe impl Send for Builder {}
+
+/// A registered input device, the kernel's `struct input_dev`.
+///
+/// # Invariants
+///
+/// The device is valid while references to it exist, and its driver data is a
+/// `Registration<T>`.
+#[repr(transparent)]
+pub struct Device<T: Operations>(Opaque<bindings::input_dev>, PhantomData<T>);
+
+impl<T: Operations> Device<T> {
+    /// Creates a reference to a [`Device`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is a device registered by a `Registration<T>` for the
+    /// lifetime of the returned reference.
+    unsafe fn from_raw<'a>(ptr: *mut bindings::input_dev) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct input_dev` pointer.
+    pub fn as_raw(&self) -> *mut bindings::input_dev {
+        self.0.get()
+    }
+
+    /// Returns the driver data of the device.
+    pub fn data(&self) -> &T {
+        // SAFETY: The driver data of the device is a `Registration<T>` by the type invariants,
+        // which outlives the device.
+        unsafe { &(*(*self.as_raw()).dev.driver_data.cast::<Registration<T>>()).data }
+    }
+
+    /// Reports an event of type `ev_type` with the code `code` and the value `value`.
+    ///
+    /// Events are delivered to user space together, once [`Device::sync`] is called. This can be
+    /// called from any context.
+    pub fn event(&self, ev_type: u32, code: u32, value: i32) {
+        // SAFETY: The device is valid by the type invariants, and events are serialised by its
+        // event lock.
+        unsafe { bindings::input_event(self.as_raw(), ev_type, code, value) };
+    }
+
+    /// Reports that the key or button `code` is pressed or released.
+    pub fn report_key(&self, code: u32, pressed: bool) {
+        self.event(ev::KEY, code, pressed as _);
+    }
+
+    /// Reports the value of the absolute axis `axis`.
+    pub fn report_abs(&self, axis: u32, value: i32) {
+        self.event(ev::ABS, axis, value);
+    }
+
+    /// Reports a movement along the relative axis `axis`.
+    pub fn report_rel(&self, axis: u32, value: i32) {
+        self.event(ev::REL, axis, value);
+    }
+
+    /// Reports the state of the switch `code`.
+    pub fn report_switch(&self, code: u32, on: bool) {
+        self.event(ev::SW, code, on as _);
+    }
+
+    /// Selects the multi-touch slot that the following `ABS_MT_*` events are about.
+    pub fn mt_slot(&self, slot: u32) {
+        self.event(ev::ABS, bindings::ABS_MT_SLOT, slot as _);
+    }
+
+    /// Reports whether a finger touches the device in the current slot.
+    pub fn mt_report_finger(&self, active: bool) {
+        // SAFETY: The device is valid by the type invariants, and events are serialised by its
+        // event lock.
+        unsafe {
+            bindings::input_mt_report_slot_state(self.as_raw(), bindings::MT_TOOL_FINGER, active)
+        };
+    }
+
+    /// Ends a multi-touch frame, which releases the unused slots if requested with
+    /// [`mt_flags::DROP_UNUSED`], and reports the pointer emulation events.
+    ///
+    /// This must not be called concurrently with itself.
+    pub fn mt_sync_frame(&self) {
+        // SAFETY: The device is valid by the type invariants.
+        unsafe { bindings::input_mt_sync_frame(self.as_raw()) };
+    }
+
+    /// Delivers the events reported since the last call.
+    pub fn sync(&self) {
+        self.event(ev::SYN, bindings::SYN_REPORT, 0);
+    }
+}
+
+// SAFETY: Events can be reported from any thread concurrently, and the driver data is `Sync`.
+unsafe impl<T: Operations> Sync for Device<T> {}
+
+/// The registration of an input device, created by [`Builder::register`].
+///
+/// The device is unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `dev` is an input device owned by the registration, whose driver data is the registration, and
+/// whose name is `_name`. It is registered if `registered` is `true`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{bindings, device::Device, fmt, prelude::*};
+/// use kernel::input::{AbsInfo, Builder, Id, Operations, Registration};
+///
+/// struct Buttons;
+///
+/// #[vtable]
+/// impl Operations for Buttons {}
+///
+/// fn probe(dev: &Device) -> Result<Pin<Box<Registration<Buttons>>>> {
+///     let mut builder = Builder::new(dev, fmt!("gpio-keys"), Id::default())?;
+///     builder.set_key(bindings::KEY_POWER);
+///     builder.set_key(bindings::KEY_VOLUMEUP);
+///     builder.register(Buttons)
+/// }
+///
+/// fn power_irq(reg: &Registration<Buttons>, pressed: bool) {
+///     reg.device().report_key(bindings::KEY_POWER, pressed);
+///     reg.device().sync();
+/// }
+/// ```
+pub struct Registration<T: Operations> {
+    dev: NonNull<bindings::input_dev>,
+    _name: CString,
+    registered: bool,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: Operations> Registration<T> {
+    /// Returns the input device, to report events.
+    pub fn device(&self) -> &Device<T> {
+        // SAFETY: The device is owned by the registration, and its driver data is the
+        // registration, by the type invariants.
+        unsafe { Device::from_raw(self.dev.as_ptr()) }
+    }
+
+    /// Returns the driver data of the device.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    unsafe extern "C" fn open_callback(dev: *mut bindings::input_dev) -> c_int {
+        from_result(|| {
+            // SAFETY: The input core calls this with a device registered by `register`.
+            T::open(unsafe { Device::from_raw(dev) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn close_callback(dev: *mut bindings::input_dev) {
+        // SAFETY: The input core calls this with a device registered by `register`.
+        T::close(unsafe { Device::from_raw(dev) });
+    }
+
+    unsafe extern "C" fn play_effect_callback(
+        dev: *mut bindings::input_dev,
+        _data: *mut c_void,
+        effect: *mut bindings::ff_effect,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The force-feedback core calls this with a device registered by `register`
+            // and a valid effect, which is a rumble, the only effect of the device.
+            let (dev, rumble) = unsafe { (Device::from_raw(dev), (*effect).u.rumble) };
+            T::play_rumble(dev, rumble.strong_magnitude, rumble.weak_magnitude)?;
+            Ok(0)
+        })
+    }
+}
+
+impl<T: Operations> Drop for Registration<T> {
+    fn drop(&mut self) {
+        // SAFETY: The device is owned by the registration by the type invariants. Unregistering
+        // it also frees it.
+        unsafe {
+            if self.registered {
+                bindings::input_unregister_device(self.dev.as_ptr());
+            } else {
+                bindings::input_free_device(self.dev.as_ptr());
+            }
+        }
+    }
+}
+
+// SAFETY: The device can be unregistered from any thread, and the driver data is `Send`.
+unsafe impl<T: Operations> Send for Registration<T> {}
+
+// SAFETY: The methods that take `&self` are safe to call concurrently, and the driver data is
+// `Sync`.
+unsafe impl<T: Operations> Sync for Registration<T> {}
@@ -0,0 +1,386 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Memory-mapped IO.
+//!
+//! C headers: [`include/asm-generic/io.h`](../../../../include/asm-generic/io.h) and
+//! [`include/linux/io.h`](../../../../include/linux/io.h)
+
+use crate::{bindings, build_assert, error::code::*, error::Result};
+
+/// Represents a memory resource.
+pub struct Resource {
+    offset: bindings::resource_size_t,
+    size: bindings::resource_size_t,
+}
+
+impl Resource {
+    /// Creates a new resource covering `size` bytes starting at physical address `offset`.
+    ///
+    /// Returns `None` if the range is empty or wraps around.
+    pub fn new(offset: bindings::resource_size_t, size: bindings::resource_size_t) -> Option<Self> {
+        if size == 0 {
+            return None;
+        }
+        offset.checked_add(size)?;
+        Some(Self { offset, size })
+    }
+
+    /// Returns the physical address of the start of the resource.
+    pub fn start(&self) -> bindings::resource_size_t {
+        self.offset
+    }
+
+    /// Returns the size of the resource in bytes.
+    pub fn size(&self) -> bindings::resource_size_t {
+        self.size
+    }
+}
+
+/// The memory type (caching attributes) used to map a resource.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapType {
+    /// Uncached device memory, mapped with `ioremap`. This is what registers need.
+    Uncached,
+
+    /// Write-combined device memory, mapped with `ioremap_wc`.
+    ///
+    /// Writes may be merged and reordered by the CPU, which makes this suitable for framebuffer
+    /// apertures and other large memory-like regions, but not for registers.
+    WriteCombine,
+
+    /// Write-through memory, mapped with `memremap(MEMREMAP_WT)`.
+    WriteThrough,
+
+    /// Write-back (cached) memory, mapped with `memremap(MEMREMAP_WB)`.
+    ///
+    /// Only suitable for regions that behave like normal system RAM, for example carveouts shared
+    /// with firmware.
+    WriteBack,
+}
+
+impl MapType {
+    fn is_memremap(self) -> bool {
+        matches!(self, MapType::WriteThrough | MapType::WriteBack)
+    }
+}
+
+/// Represents a memory block of at least `SIZE` bytes.
+///
+/// # Invariants
+///
+/// `ptr` is a non-null and valid address of at least `SIZE` bytes and returned by an `ioremap`
+/// variant (or `memremap` if `map_type` says so). `ptr` is also 8-byte aligned.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::prelude::*;
+/// use kernel::io_mem::{IoMem, MapType, Resource};
+///
+/// fn test(res: Resource) -> Result {
+///     // Create an io mem block of at least 100 bytes.
+///     // SAFETY: No DMA operations are initiated through `mem`.
+///     let mem = unsafe { IoMem::<100>::try_new(res) }?;
+///
+///     // Read one byte from offset 10.
+///     let v = mem.readb(10);
+///
+///     // Write value to offset 20.
+///     mem.writeb(v, 20);
+///
+///     Ok(())
+/// }
+///
+/// fn fill_aperture(res: Resource, pixels: &[u8]) -> Result {
+///     // SAFETY: No DMA operations are initiated through `fb`.
+///     let fb = unsafe { IoMem::<0x10000>::try_new_with(res, MapType::WriteCombine) }?;
+///     fb.try_copy_to(0, pixels)
+/// }
+/// ```
+pub struct IoMem<const SIZE: usize> {
+    ptr: usize,
+    map_type: MapType,
+}
+
+macro_rules! define_read {
+    ($(#[$attr:meta])* $name:ident, $try_name:ident, $type_name:ty) => {
+        /// Reads IO data from the given offset known, at compile time.
+        ///
+        /// If the offset is not known at compile time, the build will fail.
+        $(#[$attr])*
+        #[inline]
+        pub fn $name(&self, offset: usize) -> $type_name {
+            Self::check_offset::<$type_name>(offset);
+            let ptr = self.ptr.wrapping_add(offset);
+            // SAFETY: The type invariants guarantee that `ptr` is a valid pointer. The check above
+            // guarantees that the code won't build if `offset` makes the read go out of bounds
+            // (including the type size).
+            unsafe { bindings::$name(ptr as _) }
+        }
+
+        /// Reads IO data from the given offset.
+        ///
+        /// It fails if/when the offset (plus the type size) is out of bounds.
+        $(#[$attr])*
+        pub fn $try_name(&self, offset: usize) -> Result<$type_name> {
+            if !Self::offset_ok::<$type_name>(offset) {
+                return Err(EINVAL);
+            }
+            let ptr = self.ptr.wrapping_add(offset);
+            // SAFETY: The type invariants guarantee that `ptr` is a valid pointer. The check above
+            // returns an error if `offset` would make the read go out of bounds (including the
+            // type size).
+            Ok(unsafe { bindings::$name(ptr as _) })
+        }
+    };
+}
+
+macro_rules! define_write {
+    ($(#[$attr:meta])* $name:ident, $try_name:ident, $type_name:ty) => {
+        /// Writes IO data to the given offset, known at compile time.
+        ///
+        /// If the offset is not known at compile time, the build will fail.
+        $(#[$attr])*
+        #[inline]
+        pub fn $name(&self, value: $type_name, offset: usize) {
+            Self::check_offset::<$type_name>(offset);
+            let ptr = self.ptr.wrapping_add(offset);
+            // SAFETY: The type invariants guarantee that `ptr` is a valid pointer. The check above
+            // guarantees that the code won't link if `offset` makes the write go out of bounds
+            // (including the type size).
+            unsafe { bindings::$name(value, ptr as _) }
+        }
+
+        /// Writes IO data to the given offset.
+        ///
+        /// It fails if/when the offset (plus the type size) is out of bounds.
+        $(#[$attr])*
+        pub fn $try_name(&self, value: $type_name, offset: usize) -> Result {
+            if !Self::offset_ok::<$type_name>(offset) {
+                return Err(EINVAL);
+            }
+            let ptr = self.ptr.wrapping_add(offset);
+            // SAFETY: The type invariants guarantee that `ptr` is a valid pointer. The check above
+            // returns an error if `offset` would make the write go out of bounds (including the
+            // type size).
+            unsafe { bindings::$name(value, ptr as _) };
+            Ok(())
+        }
+    };
+}
+
+impl<const SIZE: usize> IoMem<SIZE> {
+    /// Tries to create a new instance of an uncached memory block.
+    ///
+    /// The resource described by `res` is mapped into the CPU's address space with `ioremap` so
+    /// that it can be accessed directly. It is also consumed by this function so that it can't be
+    /// mapped again to a different address.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that either (a) the resulting interface cannot be used to initiate DMA
+    /// operations, or (b) that DMA operations initiated via the returned interface use DMA handles
+    /// allocated through the `dma` module.
+    pub unsafe fn try_new(res: Resource) -> Result<Self> {
+        // SAFETY: The safety requirements are the same as ours.
+        unsafe { Self::try_new_with(res, MapType::Uncached) }
+    }
+
+    /// Tries to create a new instance of a memory block mapped with the given memory type.
+    ///
+    /// See [`MapType`] for the mapping function used for each type.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that either (a) the resulting interface cannot be used to initiate DMA
+    /// operations, or (b) that DMA operations initiated via the returned interface use DMA handles
+    /// allocated through the `dma` module.
+    pub unsafe fn try_new_with(res: Resource, map_type: MapType) -> Result<Self> {
+        // Check that the resource has at least `SIZE` bytes in it.
+        if res.size < SIZE as _ {
+            return Err(EINVAL);
+        }
+
+        // To be able to check pointers at compile time based only on offsets, we need to guarantee
+        // that the base pointer is minimally aligned. So we conservatively expect at least 8 bytes.
+        if res.offset % 8 != 0 {
+            crate::pr_err!("Physical address is not 64-bit aligned: {:x}", res.offset);
+            return Err(EDOM);
+        }
+
+        // Try to map the resource.
+        // SAFETY: Just mapping the memory range.
+        let addr = unsafe {
+            match map_type {
+                MapType::Uncached => bindings::ioremap(res.offset, res.size as _),
+                MapType::WriteCombine => bindings::ioremap_wc(res.offset, res.size as _),
+                MapType::WriteThrough => {
+                    bindings::memremap(res.offset, res.size as _, bindings::MEMREMAP_WT as _)
+                }
+                MapType::WriteBack => {
+                    bindings::memremap(res.offset, res.size as _, bindings::MEMREMAP_WB as _)
+                }
+            }
+        };
+        if addr.is_null() {
+            Err(ENOMEM)
+        } else {
+            // INVARIANT: `addr` is non-null and was returned by the mapping function selected by
+            // `map_type`. It is also 8-byte aligned because we checked it above.
+            Ok(Self {
+                ptr: addr as usize,
+                map_type,
+            })
+        }
+    }
+
+    /// Returns the memory type the block was mapped with.
+    pub fn map_type(&self) -> MapType {
+        self.map_type
+    }
+
+    /// Returns the address the block is mapped at.
+    pub(crate) fn as_ptr(&self) -> *mut core::ffi::c_void {
+        self.ptr as _
+    }
+
+    #[inline]
+    const fn offset_ok<T>(offset: usize) -> bool {
+        let type_size = core::mem::size_of::<T>();
+        if let Some(end) = offset.checked_add(type_size) {
+            end <= SIZE && offset % type_size == 0
+        } else {
+            false
+        }
+    }
+
+    #[inline]
+    const fn range_ok(offset: usize, len: usize) -> bool {
+        match offset.checked_add(len) {
+            Some(end) => end <= SIZE,
+            None => false,
+        }
+    }
+
+    #[inline]
+    const fn check_offset<T>(offset: usize) {
+        build_assert!(Self::offset_ok::<T>(offset), "IoMem offset overflow");
+    }
+
+    /// Copies `data` into the memory block, starting at `offset`.
+    ///
+    /// This uses `memcpy_toio` for I/O mappings, so it is suitable for bulk copies into apertures.
+    /// It fails if the destination range is out of bounds.
+    pub fn try_copy_to(&self, offset: usize, data: &[u8]) -> Result {
+        if !Self::range_ok(offset, data.len()) {
+            return Err(EINVAL);
+        }
+        let ptr = self.ptr.wrapping_add(offset);
+        if self.map_type.is_memremap() {
+            // SAFETY: The type invariants guarantee that `ptr` points to normal memory mapped by
+            // `memremap` and the check above ensures that the whole range is within the mapping.
+            unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), ptr as *mut u8, data.len()) };
+        } else {
+            // SAFETY: The type invariants guarantee that `ptr` is a valid I/O pointer and the check
+            // above ensures that the whole range is within the mapping.
+            unsafe { bindings::memcpy_toio(ptr as _, data.as_ptr().cast(), data.len()) };
+        }
+        Ok(())
+    }
+
+    /// Copies from the memory block, starting at `offset`, into `data`.
+    ///
+    /// This uses `memcpy_fromio` for I/O mappings. It fails if the source range is out of bounds.
+    pub fn try_copy_from(&self, offset: usize, data: &mut [u8]) -> Result {
+        if !Self::range_ok(offset, data.len()) {
+            return Err(EINVAL);
+        }
+        let ptr = self.ptr.wrapping_add(offset);
+        if self.map_type.is_memremap() {
+            // SAFETY: The type invariants guarantee that `ptr` points to normal memory mapped by
+            // `memremap` and the check above ensures that the whole range is within the mapping.
+            unsafe {
+                core::ptr::copy_nonoverlapping(ptr as *const u8, data.as_mut_ptr(), data.len())
+            };
+        } else {
+            // SAFETY: The type invariants guarantee that `ptr` is a valid I/O pointer and the check
+            // above ensures that the whole range is within the mapping.
+            unsafe { bindings::memcpy_fromio(data.as_mut_ptr().cast(), ptr as _, data.len()) };
+        }
+        Ok(())
+    }
+
+    /// Sets `len` bytes of the memory block, starting at `offset`, to `value`.
+    ///
+    /// It fails if the range is out of bounds.
+    pub fn try_set(&self, offset: usize, value: u8, len: usize) -> Result {
+        if !Self::range_ok(offset, len) {
+            return Err(EINVAL);
+        }
+        let ptr = self.ptr.wrapping_add(offset);
+        if self.map_type.is_memremap() {
+            // SAFETY: The type invariants guarantee that `ptr` points to normal memory mapped by
+            // `memremap` and the check above ensures that the whole range is within the mapping.
+            unsafe { core::ptr::write_bytes(ptr as *mut u8, value, len) };
+        } else {
+            // SAFETY: The type invariants guarantee that `ptr` is a valid I/O pointer and the check
+            // above ensures that the whole range is within the mapping.
+            unsafe { bindings::memset_io(ptr as _, value.into(), len) };
+        }
+        Ok(())
+    }
+
+    define_read!(readb, try_readb, u8);
+    define_read!(readw, try_readw, u16);
+    define_read!(readl, try_readl, u32);
+    define_read!(
+        #[cfg(CONFIG_64BIT)]
+        readq,
+        try_readq,
+        u64
+    );
+
+    define_read!(readb_relaxed, try_readb_relaxed, u8);
+    define_read!(readw_relaxed, try_readw_relaxed, u16);
+    define_read!(readl_relaxed, try_readl_relaxed, u32);
+    define_read!(
+        #[cfg(CONFIG_64BIT)]
+        readq_relaxed,
+        try_readq_relaxed,
+        u64
+    );
+
+    define_write!(writeb, try_writeb, u8);
+    define_write!(writew, try_writew, u16);
+    define_write!(writel, try_writel, u32);
+    define_write!(
+        #[cfg(CONFIG_64BIT)]
+        writeq,
+        try_writeq,
+        u64
+    );
+
+    define_write!(writeb_relaxed, try_writeb_relaxed, u8);
+    define_write!(writew_relaxed, try_writew_relaxed, u16);
+    define_write!(writel_relaxed, try_writel_relaxed, u32);
+    define_write!(
+        #[cfg(CONFIG_64BIT)]
+        writeq_relaxed,
+        try_writeq_relaxed,
+        u64
+    );
+}
+
+impl<const SIZE: usize> Drop for IoMem<SIZE> {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariant, `self.ptr` is a value returned by a previous successful
+        // call to the mapping function that corresponds to `self.map_type`.
+        unsafe {
+            if self.map_type.is_memremap() {
+                bindings::memunmap(self.ptr as _)
+            } else {
+                bindings::iounmap(self.ptr as _)
+            }
+        };
+    }
+}
@@ -0,0 +1,359 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! IOMMU domains and IO virtual address management.
+//!
+//! Most drivers never need this: the DMA API already manages the IOMMU on their behalf. It is
+//! meant for drivers that manage their own IO address space, for example GPUs that give each
+//! context its own set of page tables.
+//!
+//! C headers: [`include/linux/iommu.h`](../../../../include/linux/iommu.h) and
+//! [`include/linux/iova.h`](../../../../include/linux/iova.h)
+
+use crate::{
+    bindings,
+    device::Device,
+    error::{code::*, to_result, Result},
+    types::ARef,
+};
+use core::ops::{BitOr, BitOrAssign};
+
+#[cfg(CONFIG_IOMMU_IOVA)]
+use crate::{init, init::PinInit, types::Opaque};
+#[cfg(CONFIG_IOMMU_IOVA)]
+use core::{marker::PhantomPinned, pin::Pin};
+#[cfg(CONFIG_IOMMU_IOVA)]
+use macros::{pin_data, pinned_drop};
+
+/// IO virtual address.
+pub type Iova = core::ffi::c_ulong;
+
+/// Physical address.
+pub type PhysAddr = bindings::phys_addr_t;
+
+/// Protection flags for IOMMU mappings.
+///
+/// Flags can be combined with `|`, e.g. `Prot::READ | Prot::WRITE`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Prot(u32);
+
+impl Prot {
+    /// The device may read from the mapping.
+    pub const READ: Prot = Prot(bindings::IOMMU_READ);
+
+    /// The device may write to the mapping.
+    pub const WRITE: Prot = Prot(bindings::IOMMU_WRITE);
+
+    /// The mapping is DMA cache coherent.
+    pub const CACHE: Prot = Prot(bindings::IOMMU_CACHE);
+
+    /// The device may not execute from the mapping.
+    pub const NOEXEC: Prot = Prot(bindings::IOMMU_NOEXEC);
+
+    /// The mapping targets MMIO (e.g. an MSI doorbell) rather than memory.
+    pub const MMIO: Prot = Prot(bindings::IOMMU_MMIO);
+
+    /// The mapping is only accessible by privileged (supervisor) device transactions.
+    pub const PRIV: Prot = Prot(bindings::IOMMU_PRIV);
+
+    /// Returns the raw flags as expected by the C API.
+    pub fn bits(self) -> core::ffi::c_int {
+        self.0 as _
+    }
+}
+
+impl BitOr for Prot {
+    type Output = Prot;
+
+    fn bitor(self, rhs: Prot) -> Prot {
+        Prot(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Prot {
+    fn bitor_assign(&mut self, rhs: Prot) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// An unmanaged IOMMU domain, i.e. an IO address space owned by the driver.
+///
+/// # Invariants
+///
+/// `ptr` is a valid domain returned by `iommu_domain_alloc` and is owned by this instance.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::{device::Device, iommu::{Domain, Prot}, prelude::*};
+/// fn map_buffer(dev: &Device, phys: kernel::iommu::PhysAddr) -> Result {
+///     let domain = Domain::try_new(dev)?;
+///     let _attached = domain.attach_device(dev)?;
+///     domain.map(0x1000_0000, phys, 0x1000, Prot::READ | Prot::WRITE)?;
+///     // ... let the device use the mapping ...
+///     domain.unmap(0x1000_0000, 0x1000);
+///     Ok(())
+/// }
+/// ```
+pub struct Domain {
+    ptr: *mut bindings::iommu_domain,
+}
+
+// SAFETY: IOMMU domains may be used and freed from any thread.
+unsafe impl Send for Domain {}
+
+// SAFETY: The IOMMU core serialises concurrent map/unmap operations on a domain internally.
+unsafe impl Sync for Domain {}
+
+impl Domain {
+    /// Allocates a new unmanaged domain suitable for devices on the same bus as `dev`.
+    ///
+    /// Fails with `ENODEV` if `dev` isn't behind an IOMMU.
+    pub fn try_new(dev: &Device) -> Result<Self> {
+        // SAFETY: `dev.as_raw()` is valid by the type invariants of `Device`.
+        let bus = unsafe { (*dev.as_raw()).bus };
+        if bus.is_null() {
+            return Err(ENODEV);
+        }
+
+        // SAFETY: `bus` was checked to be non-null above and is valid while the device is.
+        let ptr = unsafe { bindings::iommu_domain_alloc(bus) };
+        if ptr.is_null() {
+            return Err(ENOMEM);
+        }
+
+        // INVARIANT: `ptr` was just allocated and is owned by the new instance.
+        Ok(Self { ptr })
+    }
+
+    /// Returns the raw `struct iommu_domain` pointer.
+    pub fn as_raw(&self) -> *mut bindings::iommu_domain {
+        self.ptr
+    }
+
+    /// Attaches `dev` to this domain.
+    ///
+    /// The device is detached again when the returned [`Attachment`] is dropped.
+    pub fn attach_device(&self, dev: &Device) -> Result<Attachment<'_>> {
+        // SAFETY: `self.ptr` is valid by the type invariants, and `dev.as_raw()` is valid by the
+        // type invariants of `Device`.
+        to_result(unsafe { bindings::iommu_attach_device(self.ptr, dev.as_raw()) })?;
+
+        // INVARIANT: The device was just attached.
+        Ok(Attachment {
+            domain: self,
+            dev: dev.into(),
+        })
+    }
+
+    /// Maps `size` bytes of physical memory at `paddr` to the IO virtual address `iova`.
+    ///
+    /// All of `iova`, `paddr` and `size` must be aligned to a page size supported by the IOMMU.
+    pub fn map(&self, iova: Iova, paddr: PhysAddr, size: usize, prot: Prot) -> Result {
+        // SAFETY: `self.ptr` is valid by the type invariants.
+        to_result(unsafe {
+            bindings::iommu_map(
+                self.ptr,
+                iova,
+                paddr,
+                size,
+                prot.bits(),
+                bindings::GFP_KERNEL,
+            )
+        })
+    }
+
+    /// Unmaps `size` bytes starting at the IO virtual address `iova`.
+    ///
+    /// Returns the number of bytes that were actually unmapped.
+    pub fn unmap(&self, iova: Iova, size: usize) -> usize {
+        // SAFETY: `self.ptr` is valid by the type invariants.
+        unsafe { bindings::iommu_unmap(self.ptr, iova, size) }
+    }
+
+    /// Translates an IO virtual address into the physical address it is mapped to.
+    ///
+    /// Returns `None` if `iova` isn't mapped.
+    pub fn iova_to_phys(&self, iova: Iova) -> Option<PhysAddr> {
+        // SAFETY: `self.ptr` is valid by the type invariants.
+        match unsafe { bindings::iommu_iova_to_phys(self.ptr, iova as _) } {
+            0 => None,
+            phys => Some(phys),
+        }
+    }
+
+    /// Returns the bitmap of page sizes supported by the domain.
+    pub fn page_sizes(&self) -> core::ffi::c_ulong {
+        // SAFETY: `self.ptr` is valid by the type invariants.
+        unsafe { (*self.ptr).pgsize_bitmap }
+    }
+}
+
+impl Drop for Domain {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, we own the domain. Attachments borrow the domain, so
+        // none can be alive at this point.
+        unsafe { bindings::iommu_domain_free(self.ptr) };
+    }
+}
+
+/// A device attached to a [`Domain`].
+///
+/// # Invariants
+///
+/// `dev` is attached to `domain` until this instance is dropped.
+pub struct Attachment<'a> {
+    domain: &'a Domain,
+    dev: ARef<Device>,
+}
+
+impl Attachment<'_> {
+    /// Returns the attached device.
+    pub fn device(&self) -> &Device {
+        &self.dev
+    }
+}
+
+impl Drop for Attachment<'_> {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, the device is attached to the domain.
+        unsafe { bindings::iommu_detach_device(self.domain.ptr, self.dev.as_raw()) };
+    }
+}
+
+/// An IO virtual address allocator.
+///
+/// This wraps the kernel's `struct iova_domain`, which hands out ranges of IO page frames. It is
+/// typically used together with a [`Domain`]: allocate a range here, then map memory into it.
+///
+/// # Invariants
+///
+/// `iovad` is initialised by `init_iova_domain` and the IOVA caches are held (via
+/// `iova_cache_get`) for the lifetime of the instance.
+#[cfg(CONFIG_IOMMU_IOVA)]
+#[pin_data(PinnedDrop)]
+pub struct IovaDomain {
+    #[pin]
+    iovad: Opaque<bindings::iova_domain>,
+    #[pin]
+    _pin: PhantomPinned,
+}
+
+#[cfg(CONFIG_IOMMU_IOVA)]
+// SAFETY: `struct iova_domain` has its own internal locking.
+unsafe impl Send for IovaDomain {}
+
+#[cfg(CONFIG_IOMMU_IOVA)]
+// SAFETY: `struct iova_domain` has its own internal locking.
+unsafe impl Sync for IovaDomain {}
+
+#[cfg(CONFIG_IOMMU_IOVA)]
+impl IovaDomain {
+    /// Creates a new allocator with the given granule (the IOMMU page size, a power of two) that
+    /// hands out page frames starting at `start_pfn`.
+    pub fn new(granule: usize, start_pfn: usize) -> impl PinInit<Self, crate::error::Error> {
+        // SAFETY:
+        // - when the closure returns `Ok(())`, the domain has been initialised and the caches are
+        //   held,
+        // - when it returns `Err(e)`, nothing needs cleaning up since the cache reference could not
+        //   be acquired.
+        unsafe {
+            init::pin_init_from_closure(move |slot: *mut Self| {
+                if !granule.is_power_of_two() {
+                    return Err(EINVAL);
+                }
+                to_result(bindings::iova_cache_get())?;
+                let iovad = Opaque::raw_get(core::ptr::addr_of!((*slot).iovad));
+                bindings::init_iova_domain(iovad, granule as _, start_pfn as _);
+                Ok(())
+            })
+        }
+    }
+
+    /// Allocates a range of `pages` IO page frames that ends at or below `limit_pfn`.
+    ///
+    /// If `size_aligned` is `true`, the range is naturally aligned to its (power-of-two rounded)
+    /// size. The range is released when the returned [`IovaRange`] is dropped.
+    pub fn alloc(
+        self: Pin<&Self>,
+        pages: usize,
+        limit_pfn: usize,
+        size_aligned: bool,
+    ) -> Result<IovaRange<'_>> {
+        // SAFETY: `iovad` is initialised by the type invariants.
+        let iova = unsafe {
+            bindings::alloc_iova(self.iovad.get(), pages as _, limit_pfn as _, size_aligned)
+        };
+        if iova.is_null() {
+            return Err(ENOMEM);
+        }
+
+        // INVARIANT: `iova` was just allocated from `self`.
+        Ok(IovaRange {
+            domain: self.get_ref(),
+            iova,
+        })
+    }
+
+    /// Returns the granule (IOMMU page size) of the allocator.
+    pub fn granule(&self) -> usize {
+        // SAFETY: `iovad` is initialised by the type invariants.
+        unsafe { (*self.iovad.get()).granule as _ }
+    }
+}
+
+#[cfg(CONFIG_IOMMU_IOVA)]
+#[pinned_drop]
+impl PinnedDrop for IovaDomain {
+    fn drop(self: Pin<&mut Self>) {
+        // SAFETY: `iovad` is initialised by the type invariants; all ranges borrow `self`, so none
+        // are outstanding.
+        unsafe { bindings::put_iova_domain(self.iovad.get()) };
+        // SAFETY: The type invariants guarantee that we hold a reference on the caches.
+        unsafe { bindings::iova_cache_put() };
+    }
+}
+
+/// A range of IO virtual addresses allocated from an [`IovaDomain`].
+///
+/// # Invariants
+///
+/// `iova` was allocated from `domain` and is freed when this instance is dropped.
+#[cfg(CONFIG_IOMMU_IOVA)]
+pub struct IovaRange<'a> {
+    domain: &'a IovaDomain,
+    iova: *mut bindings::iova,
+}
+
+#[cfg(CONFIG_IOMMU_IOVA)]
+impl IovaRange<'_> {
+    /// Returns the first page frame of the range.
+    pub fn pfn_lo(&self) -> usize {
+        // SAFETY: `iova` is valid by the type invariants.
+        unsafe { (*self.iova).pfn_lo as _ }
+    }
+
+    /// Returns the last page frame of the range.
+    pub fn pfn_hi(&self) -> usize {
+        // SAFETY: `iova` is valid by the type invariants.
+        unsafe { (*self.iova).pfn_hi as _ }
+    }
+
+    /// Returns the IO virtual address of the start of the range.
+    pub fn start(&self) -> Iova {
+        (self.pfn_lo() * self.domain.granule()) as _
+    }
+
+    /// Returns the size of the range in bytes.
+    pub fn size(&self) -> usize {
+        (self.pfn_hi() - self.pfn_lo() + 1) * self.domain.granule()
+    }
+}
+
+#[cfg(CONFIG_IOMMU_IOVA)]
+impl Drop for IovaRange<'_> {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, `iova` was allocated from `domain` and hasn't been freed
+        // yet.
+        unsafe { bindings::__free_iova(self.domain.iovad.get(), self.iova) };
+    }
+}
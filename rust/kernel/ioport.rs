@@ -0,0 +1,178 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Port I/O.
+//!
+//! C headers: [`include/linux/ioport.h`](../../../../include/linux/ioport.h) and
+//! [`include/asm-generic/io.h`](../../../../include/asm-generic/io.h)
+
+use crate::{bindings, build_assert, error::code::*, error::Result, str::CString};
+use core::fmt;
+
+/// A reserved range of I/O ports.
+///
+/// The range is reserved with `request_region` when the object is created and released with
+/// `release_region` when it is dropped. Accesses through the `in*`/`out*` methods are checked to
+/// fall within the reserved range: the plain variants at compile time (so the offset must be known
+/// at build time), the `try_` variants at runtime.
+///
+/// # Invariants
+///
+/// The ports in `[start, start + SIZE)` are reserved by this instance, named `_name`, and remain
+/// so until it is dropped.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::{fmt, ioport::IoPortRegion, prelude::*};
+/// fn probe_legacy_uart(line: u32) -> Result {
+///     let ports = IoPortRegion::<8>::try_new(0x3f8, fmt!("my_uart.{}", line))?;
+///     // Read the line status register.
+///     let lsr = ports.inb(5);
+///     pr_info!("LSR: {:#x}\n", lsr);
+///     Ok(())
+/// }
+/// ```
+pub struct IoPortRegion<const SIZE: usize> {
+    start: core::ffi::c_ulong,
+    _name: CString,
+}
+
+macro_rules! define_in {
+    ($name:ident, $try_name:ident, $type_name:ty) => {
+        /// Reads from the port at the given offset from the start of the region.
+        ///
+        /// If the offset is not known at compile time, the build will fail.
+        #[inline]
+        pub fn $name(&self, offset: usize) -> $type_name {
+            Self::check_offset::<$type_name>(offset);
+            // SAFETY: By the type invariant, the port is reserved by us, and `check_offset`
+            // ensures it lies within the region.
+            unsafe { bindings::$name(self.port(offset)) as _ }
+        }
+
+        /// Reads from the port at the given offset from the start of the region.
+        ///
+        /// It fails if the offset (plus the size of the value) is out of bounds.
+        pub fn $try_name(&self, offset: usize) -> Result<$type_name> {
+            if !Self::offset_ok::<$type_name>(offset) {
+                return Err(EINVAL);
+            }
+            // SAFETY: By the type invariant, the port is reserved by us, and the check above
+            // ensures it lies within the region.
+            Ok(unsafe { bindings::$name(self.port(offset)) as _ })
+        }
+    };
+}
+
+macro_rules! define_out {
+    ($name:ident, $try_name:ident, $type_name:ty) => {
+        /// Writes to the port at the given offset from the start of the region.
+        ///
+        /// If the offset is not known at compile time, the build will fail.
+        #[inline]
+        pub fn $name(&self, value: $type_name, offset: usize) {
+            Self::check_offset::<$type_name>(offset);
+            // SAFETY: By the type invariant, the port is reserved by us, and `check_offset`
+            // ensures it lies within the region.
+            unsafe { bindings::$name(value, self.port(offset)) };
+        }
+
+        /// Writes to the port at the given offset from the start of the region.
+        ///
+        /// It fails if the offset (plus the size of the value) is out of bounds.
+        pub fn $try_name(&self, value: $type_name, offset: usize) -> Result {
+            if !Self::offset_ok::<$type_name>(offset) {
+                return Err(EINVAL);
+            }
+            // SAFETY: By the type invariant, the port is reserved by us, and the check above
+            // ensures it lies within the region.
+            unsafe { bindings::$name(value, self.port(offset)) };
+            Ok(())
+        }
+    };
+}
+
+impl<const SIZE: usize> IoPortRegion<SIZE> {
+    /// Reserves `SIZE` ports starting at `start`.
+    ///
+    /// `name` is shown in `/proc/ioports` as the owner of the range. It fails with `EBUSY` if any
+    /// of the ports is already reserved.
+    pub fn try_new(start: core::ffi::c_ulong, name: fmt::Arguments<'_>) -> Result<Self> {
+        start.checked_add(SIZE as _).ok_or(EINVAL)?;
+        let name = CString::try_from_fmt(name)?;
+
+        // SAFETY: `ioport_resource` is the static root of the port I/O resource tree and `name`
+        // is a valid, `NUL`-terminated string, whose buffer doesn't move and is only freed after
+        // the region is released.
+        let res = unsafe {
+            bindings::__request_region(
+                core::ptr::addr_of_mut!(bindings::ioport_resource),
+                start as _,
+                SIZE as _,
+                name.as_char_ptr(),
+                0,
+            )
+        };
+        if res.is_null() {
+            return Err(EBUSY);
+        }
+
+        // INVARIANT: The region was successfully reserved above.
+        Ok(Self { start, _name: name })
+    }
+
+    /// Returns the first port of the region.
+    pub fn start(&self) -> core::ffi::c_ulong {
+        self.start
+    }
+
+    /// Returns the number of ports in the region.
+    pub const fn len(&self) -> usize {
+        SIZE
+    }
+
+    /// Returns `true` if the region is empty.
+    pub const fn is_empty(&self) -> bool {
+        SIZE == 0
+    }
+
+    #[inline]
+    const fn offset_ok<T>(offset: usize) -> bool {
+        match offset.checked_add(core::mem::size_of::<T>()) {
+            Some(end) => end <= SIZE,
+            None => false,
+        }
+    }
+
+    #[inline]
+    const fn check_offset<T>(offset: usize) {
+        build_assert!(Self::offset_ok::<T>(offset), "IO port offset overflow");
+    }
+
+    #[inline]
+    fn port(&self, offset: usize) -> core::ffi::c_ulong {
+        self.start + offset as core::ffi::c_ulong
+    }
+
+    define_in!(inb, try_inb, u8);
+    define_in!(inw, try_inw, u16);
+    define_in!(inl, try_inl, u32);
+
+    define_out!(outb, try_outb, u8);
+    define_out!(outw, try_outw, u16);
+    define_out!(outl, try_outl, u32);
+}
+
+impl<const SIZE: usize> Drop for IoPortRegion<SIZE> {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariant, the region was reserved by us and hasn't been released
+        // yet.
+        unsafe {
+            bindings::__release_region(
+                core::ptr::addr_of_mut!(bindings::ioport_resource),
+                self.start as _,
+                SIZE as _,
+            )
+        };
+    }
+}
@@ -0,0 +1,133 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! I/O vector iterators.
+//!
+//! Vectored, asynchronous and splice I/O pass the buffers of the caller to
+//! [`file::Operations::read_iter`](crate::file::Operations::read_iter) and
+//! [`file::Operations::write_iter`](crate::file::Operations::write_iter) as an [`IovIter`], which
+//! may describe several user buffers, kernel buffers or pages. It is used like the other I/O
+//! buffers, through [`IoBufferReader`] and [`IoBufferWriter`].
+//!
+//! C header: [`include/linux/uio.h`](../../../../include/linux/uio.h)
+
+use crate::{
+    bindings,
+    error::{code::*, Result},
+    io_buffer::{IoBufferReader, IoBufferWriter},
+    types::Opaque,
+};
+
+/// An iterator over the buffers of an I/O, the kernel's `struct iov_iter`.
+///
+/// Reads and writes advance the iterator.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{file::{self, File}, iov_iter::IovIter, prelude::*};
+///
+/// const VERSION: &[u8] = b"1.0\n";
+///
+/// struct Version;
+///
+/// #[vtable]
+/// impl file::Operations for Version {
+///     type Data = ();
+///     type OpenData = ();
+///
+///     fn open(_context: &(), _file: &File) -> Result {
+///         Ok(())
+///     }
+///
+///     fn read_iter(_data: (), _file: &File, iter: &mut IovIter, offset: u64) -> Result<usize> {
+///         let offset = usize::try_from(offset)?.min(VERSION.len());
+///         Ok(iter.copy_to_iter(&VERSION[offset..]))
+///     }
+/// }
+/// ```
+///
+/// # Invariants
+///
+/// The iterator is valid, and only used through this reference.
+#[repr(transparent)]
+pub struct IovIter(Opaque<bindings::iov_iter>);
+
+impl IovIter {
+    /// Creates a reference to the iterator pointed to by `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for the lifetime `'a`, and only used through the returned reference.
+    pub(crate) unsafe fn from_ptr<'a>(ptr: *mut bindings::iov_iter) -> &'a mut Self {
+        // SAFETY: `IovIter` is a transparent wrapper of `iov_iter`, and the caller guarantees
+        // that `ptr` is valid for `'a`.
+        unsafe { &mut *ptr.cast() }
+    }
+
+    fn count(&self) -> usize {
+        // SAFETY: The iterator is valid by the type invariants.
+        unsafe { bindings::iov_iter_count(self.0.get()) }
+    }
+
+    /// Copies as much of `data` as fits to the buffers, and returns the number of bytes copied.
+    ///
+    /// Fewer bytes than requested are copied if the buffers are full, or if a user buffer isn't
+    /// mapped.
+    pub fn copy_to_iter(&mut self, data: &[u8]) -> usize {
+        // SAFETY: The iterator is valid by the type invariants, and `data` is valid for reads of
+        // its length.
+        unsafe { bindings::copy_to_iter(data.as_ptr().cast(), data.len(), self.0.get()) }
+    }
+
+    /// Fills as much of `data` as possible from the buffers, and returns the number of bytes
+    /// copied.
+    ///
+    /// Fewer bytes than requested are copied if the buffers are exhausted, or if a user buffer
+    /// isn't mapped.
+    pub fn copy_from_iter(&mut self, data: &mut [u8]) -> usize {
+        // SAFETY: The iterator is valid by the type invariants, and `data` is valid for writes of
+        // its length.
+        unsafe { bindings::copy_from_iter(data.as_mut_ptr().cast(), data.len(), self.0.get()) }
+    }
+}
+
+impl IoBufferWriter for IovIter {
+    fn len(&self) -> usize {
+        self.count()
+    }
+
+    fn clear(&mut self, len: usize) -> Result {
+        // SAFETY: The iterator is valid by the type invariants.
+        let cleared = unsafe { bindings::iov_iter_zero(len, self.0.get()) };
+        if cleared != len {
+            return Err(EFAULT);
+        }
+        Ok(())
+    }
+
+    unsafe fn write_raw(&mut self, data: *const u8, len: usize) -> Result {
+        // SAFETY: The iterator is valid by the type invariants, and the caller guarantees that
+        // `data` is valid for reads of `len` bytes.
+        let copied = unsafe { bindings::copy_to_iter(data.cast(), len, self.0.get()) };
+        if copied != len {
+            return Err(EFAULT);
+        }
+        Ok(())
+    }
+}
+
+impl IoBufferReader for IovIter {
+    fn len(&self) -> usize {
+        self.count()
+    }
+
+    unsafe fn read_raw(&mut self, out: *mut u8, len: usize) -> Result {
+        // SAFETY: The iterator is valid by the type invariants, and the caller guarantees that
+        // `out` is valid for writes of `len` bytes.
+        let copied = unsafe { bindings::copy_from_iter(out.cast(), len, self.0.get()) };
+        if copied != len {
+            return Err(EFAULT);
+        }
+        Ok(())
+    }
+}
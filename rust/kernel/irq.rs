@@ -0,0 +1,476 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Interrupts.
+//!
+//! A driver handles the interrupts of its device by requesting them with a [`Registration`], or
+//! a [`ThreadedRegistration`] if handling them may sleep. On machines with many CPUs, drivers of
+//! devices with several interrupts, e.g. one per queue, spread them with
+//! [`Registration::set_affinity_hint`]. PCI devices with MSI or MSI-X allocate a range of
+//! vectors instead, see [`pci::Device::alloc_irq_vectors`](crate::pci::Device::alloc_irq_vectors),
+//! whose affinity the core can manage.
+//!
+//! Devices that multiplex interrupts of other devices, e.g. GPIO expanders and PMICs, provide an
+//! interrupt controller: a [`Domain`] maps the interrupt numbers of the device, its hardware
+//! interrupts, to Linux interrupts, which other drivers request like any other. The controller
+//! masks, unmasks and configures them through the [`Chip`] operations, and dispatches its own
+//! interrupt to them with [`Domain::handle`] or [`Domain::handle_nested`].
+//!
+//! C headers: [`include/linux/interrupt.h`](../../../../include/linux/interrupt.h) and
+//! [`include/linux/irq.h`](../../../../include/linux/irq.h)
+
+use crate::{
+    bindings,
+    context::{AtomicSection, Kind},
+    cpumask::CpuMask,
+    error::{to_result, Error, Result},
+    init::{self, PinInit},
+    str::CString,
+};
+use core::{
+    ffi::{c_int, c_void},
+    fmt,
+    marker::PhantomPinned,
+    ptr,
+};
+
+#[cfg(CONFIG_IRQ_DOMAIN)]
+mod domain;
+
+#[cfg(CONFIG_IRQ_DOMAIN)]
+pub use domain::{Chip, Domain, Flow, IrqData};
+
+/// The trigger of an interrupt, the kernel's `IRQ_TYPE_*`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Type {
+    /// Triggered on rising edges.
+    EdgeRising,
+    /// Triggered on falling edges.
+    EdgeFalling,
+    /// Triggered on both edges.
+    EdgeBoth,
+    /// Triggered while the line is high.
+    LevelHigh,
+    /// Triggered while the line is low.
+    LevelLow,
+}
+
+impl Type {
+    #[cfg(CONFIG_IRQ_DOMAIN)]
+    fn from_raw(flow_type: core::ffi::c_uint) -> Option<Self> {
+        Some(match flow_type & bindings::IRQ_TYPE_SENSE_MASK {
+            bindings::IRQ_TYPE_EDGE_RISING => Self::EdgeRising,
+            bindings::IRQ_TYPE_EDGE_FALLING => Self::EdgeFalling,
+            bindings::IRQ_TYPE_EDGE_BOTH => Self::EdgeBoth,
+            bindings::IRQ_TYPE_LEVEL_HIGH => Self::LevelHigh,
+            bindings::IRQ_TYPE_LEVEL_LOW => Self::LevelLow,
+            _ => return None,
+        })
+    }
+
+    /// Returns `true` if the interrupt is edge-triggered.
+    pub fn is_edge(self) -> bool {
+        matches!(self, Self::EdgeRising | Self::EdgeFalling | Self::EdgeBoth)
+    }
+}
+
+/// Flags used when requesting an interrupt, the kernel's `IRQF_*`.
+pub mod flags {
+    /// The interrupt line is shared with other devices.
+    pub const SHARED: u64 = crate::bindings::IRQF_SHARED as _;
+    /// The interrupt is triggered on rising edges, overriding the firmware description.
+    pub const TRIGGER_RISING: u64 = crate::bindings::IRQF_TRIGGER_RISING as _;
+    /// The interrupt is triggered on falling edges, overriding the firmware description.
+    pub const TRIGGER_FALLING: u64 = crate::bindings::IRQF_TRIGGER_FALLING as _;
+    /// The interrupt is triggered while the line is high, overriding the firmware description.
+    pub const TRIGGER_HIGH: u64 = crate::bindings::IRQF_TRIGGER_HIGH as _;
+    /// The interrupt is triggered while the line is low, overriding the firmware description.
+    pub const TRIGGER_LOW: u64 = crate::bindings::IRQF_TRIGGER_LOW as _;
+    /// The interrupt stays masked until the threaded handler returns, which level-triggered
+    /// interrupts without a primary handler need.
+    pub const ONESHOT: u64 = crate::bindings::IRQF_ONESHOT as _;
+    /// The interrupt isn't enabled when it is requested, but by a later call to `enable_irq`.
+    pub const NO_AUTOEN: u64 = crate::bindings::IRQF_NO_AUTOEN as _;
+    /// The interrupt stays enabled while the system is suspended.
+    pub const NO_SUSPEND: u64 = crate::bindings::IRQF_NO_SUSPEND as _;
+}
+
+/// The return value of an interrupt handler, the kernel's `irqreturn_t`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Return {
+    /// The interrupt wasn't raised by the device, e.g. on a shared line.
+    None,
+    /// The interrupt was handled.
+    Handled,
+}
+
+impl Return {
+    fn as_raw(self) -> bindings::irqreturn_t {
+        match self {
+            Self::None => bindings::irqreturn_IRQ_NONE,
+            Self::Handled => bindings::irqreturn_IRQ_HANDLED,
+        }
+    }
+}
+
+/// The return value of the primary handler of a threaded interrupt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThreadedReturn {
+    /// The interrupt wasn't raised by the device, e.g. on a shared line.
+    None,
+    /// The interrupt was handled, and the threaded handler doesn't need to run.
+    Handled,
+    /// The threaded handler must run.
+    WakeThread,
+}
+
+impl ThreadedReturn {
+    fn as_raw(self) -> bindings::irqreturn_t {
+        match self {
+            Self::None => bindings::irqreturn_IRQ_NONE,
+            Self::Handled => bindings::irqreturn_IRQ_HANDLED,
+            Self::WakeThread => bindings::irqreturn_IRQ_WAKE_THREAD,
+        }
+    }
+}
+
+/// The handler of an interrupt requested with a [`Registration`].
+pub trait Handler: Sync {
+    /// Handles the interrupt.
+    ///
+    /// It is called in hard interrupt context, and must not sleep.
+    fn handle_irq(&self) -> Return;
+}
+
+/// The handlers of an interrupt requested with a [`ThreadedRegistration`].
+pub trait ThreadedHandler: Sync {
+    /// Handles the interrupt in hard interrupt context, and returns whether the threaded handler
+    /// must run.
+    ///
+    /// It must not sleep. By default, it always wakes the thread up, which requires the
+    /// [`flags::ONESHOT`] flag for level-triggered interrupts.
+    fn handle_irq(&self) -> ThreadedReturn {
+        ThreadedReturn::WakeThread
+    }
+
+    /// Handles the interrupt in the thread of the interrupt, where it may sleep.
+    fn handle_threaded_irq(&self) -> Return;
+}
+
+/// Sets the affinity hint of `irq`, the CPU that user space, e.g. `irqbalance`, should route it
+/// to, and routes it there, or clears the hint if `cpu` is `None`.
+fn set_affinity_hint(irq: u32, cpu: Option<u32>) -> Result {
+    let mask = match cpu {
+        // SAFETY: `cpumask_of` returns a static mask for valid CPU ids.
+        Some(cpu) if cpu < crate::cpumask::nr_cpu_ids() => unsafe { bindings::cpumask_of(cpu) },
+        Some(_) => return Err(crate::error::code::EINVAL),
+        None => ptr::null(),
+    };
+    // SAFETY: The mask is static or null, so it outlives the hint.
+    to_result(unsafe { bindings::irq_set_affinity_and_hint(irq, mask) })
+}
+
+/// Routes `irq` to the CPUs in `mask`.
+fn set_affinity(irq: u32, mask: &CpuMask) -> Result {
+    // SAFETY: The mask is valid, and copied.
+    to_result(unsafe { bindings::irq_set_affinity(irq, mask.as_raw()) })
+}
+
+/// A requested interrupt.
+///
+/// The interrupt is freed when this is dropped, which waits for its handler to return.
+///
+/// # Invariants
+///
+/// `irq` was requested with this registration as its device id, and `name` as its name.
+///
+/// # Examples
+///
+/// A device with one interrupt per queue, each of which is handled on its own CPU:
+///
+/// ```
+/// use kernel::{fmt, irq, prelude::*};
+/// use core::sync::atomic::{AtomicU64, Ordering};
+///
+/// struct Queue {
+///     events: AtomicU64,
+/// }
+///
+/// impl irq::Handler for Queue {
+///     fn handle_irq(&self) -> irq::Return {
+///         self.events.fetch_add(1, Ordering::Relaxed);
+///         irq::Return::Handled
+///     }
+/// }
+///
+/// fn request(irq: u32, index: u32) -> Result<Pin<Box<irq::Registration<Queue>>>> {
+///     let queue = Queue {
+///         events: AtomicU64::new(0),
+///     };
+///     let name = fmt!("queue{}", index);
+///     let reg = Box::pin_init(irq::Registration::register(irq, 0, name, queue))?;
+///     reg.set_affinity_hint(Some(index % kernel::cpumask::nr_cpu_ids()))?;
+///     Ok(reg)
+/// }
+/// ```
+pub struct Registration<T: Handler> {
+    irq: u32,
+    name: CString,
+    handler: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: Handler> Registration<T> {
+    /// Returns an initialiser that requests the interrupt `irq` with the flags `flags`, see
+    /// [`flags`], and the handler `handler`.
+    ///
+    /// The interrupt is named `name` in `/proc/interrupts`, and can fire as soon as it is
+    /// requested, unless [`flags::NO_AUTOEN`] is set.
+    pub fn register(
+        irq: u32,
+        flags: u64,
+        name: fmt::Arguments<'_>,
+        handler: T,
+    ) -> impl PinInit<Self, Error> {
+        let name = CString::try_from_fmt(name);
+        // SAFETY: The closure initialises all fields on success, and drops those it initialised
+        // on failure. The registration isn't moved once the interrupt is requested, since it is
+        // pinned.
+        unsafe {
+            init::pin_init_from_closure::<_, Error>(move |slot: *mut Self| {
+                crate::might_sleep!();
+                let name_ptr = ptr::addr_of_mut!((*slot).name);
+                name_ptr.write(name?);
+                // The handler is written before requesting the interrupt, since it can fire as
+                // soon as it is requested.
+                let handler_ptr = ptr::addr_of_mut!((*slot).handler);
+                handler_ptr.write(handler);
+                ptr::addr_of_mut!((*slot).irq).write(irq);
+                // The name is owned by the registration, which outlives the interrupt, and so
+                // does the device id.
+                if let Err(e) = to_result(bindings::request_threaded_irq(
+                    irq,
+                    Some(Self::handler_callback),
+                    None,
+                    flags as _,
+                    (*name_ptr).as_char_ptr(),
+                    slot.cast(),
+                )) {
+                    ptr::drop_in_place(name_ptr);
+                    ptr::drop_in_place(handler_ptr);
+                    return Err(e);
+                }
+                // INVARIANT: The interrupt is only considered initialised if it was requested.
+                Ok(())
+            })
+        }
+    }
+
+    /// Returns the interrupt number.
+    pub fn irq(&self) -> u32 {
+        self.irq
+    }
+
+    /// Returns the handler of the interrupt.
+    pub fn handler(&self) -> &T {
+        &self.handler
+    }
+
+    /// Routes the interrupt to the CPU `cpu`, and hints user space to keep it there, or clears
+    /// the hint if `cpu` is `None`.
+    ///
+    /// Fails with `EINVAL` if `cpu` isn't a valid CPU id.
+    pub fn set_affinity_hint(&self, cpu: Option<u32>) -> Result {
+        set_affinity_hint(self.irq, cpu)
+    }
+
+    /// Routes the interrupt to the CPUs in `mask`.
+    pub fn set_affinity(&self, mask: &CpuMask) -> Result {
+        set_affinity(self.irq, mask)
+    }
+
+    unsafe extern "C" fn handler_callback(
+        _irq: c_int,
+        dev_id: *mut c_void,
+    ) -> bindings::irqreturn_t {
+        // SAFETY: The device id is the registration, which outlives the interrupt by the type
+        // invariants.
+        let this = unsafe { &*dev_id.cast::<Self>() };
+        let _section = AtomicSection::enter(Kind::HardIrq);
+        this.handler.handle_irq().as_raw()
+    }
+}
+
+impl<T: Handler> Drop for Registration<T> {
+    fn drop(&mut self) {
+        // SAFETY: The interrupt was requested with this device id by the type invariants. The
+        // affinity hint must be cleared before it is freed.
+        unsafe {
+            bindings::irq_update_affinity_hint(self.irq, ptr::null());
+            bindings::free_irq(self.irq, self as *mut Self as *mut c_void);
+        }
+    }
+}
+
+// SAFETY: The interrupt can be freed from any thread, and the handler is only used by reference.
+unsafe impl<T: Handler + Send> Send for Registration<T> {}
+
+// SAFETY: The methods that take `&self` only read fields that never change or call functions
+// with their own synchronisation, and the handler is `Sync`.
+unsafe impl<T: Handler> Sync for Registration<T> {}
+
+/// A requested threaded interrupt.
+///
+/// The interrupt is freed when this is dropped, which waits for its handlers to return.
+///
+/// # Invariants
+///
+/// `irq` was requested with this registration as its device id, and `name` as its name.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{fmt, irq, prelude::*};
+///
+/// struct Pmic;
+///
+/// impl Pmic {
+///     fn read_status(&self) -> Result<u8> {
+///         // An I2C transfer, which sleeps.
+///         Ok(0)
+///     }
+/// }
+///
+/// impl irq::ThreadedHandler for Pmic {
+///     fn handle_threaded_irq(&self) -> irq::Return {
+///         match self.read_status() {
+///             Ok(0) | Err(_) => irq::Return::None,
+///             Ok(_) => irq::Return::Handled,
+///         }
+///     }
+/// }
+///
+/// fn request(irq: u32) -> Result<Pin<Box<irq::ThreadedRegistration<Pmic>>>> {
+///     Box::pin_init(irq::ThreadedRegistration::register(
+///         irq,
+///         irq::flags::ONESHOT,
+///         fmt!("pmic"),
+///         Pmic,
+///     ))
+/// }
+/// ```
+pub struct ThreadedRegistration<T: ThreadedHandler> {
+    irq: u32,
+    name: CString,
+    handler: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: ThreadedHandler> ThreadedRegistration<T> {
+    /// Returns an initialiser that requests the threaded interrupt `irq` with the flags `flags`,
+    /// see [`flags`], and the handlers `handler`.
+    ///
+    /// The interrupt is named `name` in `/proc/interrupts` and its thread `irq/<irq>-<name>`, and
+    /// can fire as soon as it is requested, unless [`flags::NO_AUTOEN`] is set.
+    pub fn register(
+        irq: u32,
+        flags: u64,
+        name: fmt::Arguments<'_>,
+        handler: T,
+    ) -> impl PinInit<Self, Error> {
+        let name = CString::try_from_fmt(name);
+        // SAFETY: The closure initialises all fields on success, and drops those it initialised
+        // on failure. The registration isn't moved once the interrupt is requested, since it is
+        // pinned.
+        unsafe {
+            init::pin_init_from_closure::<_, Error>(move |slot: *mut Self| {
+                crate::might_sleep!();
+                let name_ptr = ptr::addr_of_mut!((*slot).name);
+                name_ptr.write(name?);
+                // The handler is written before requesting the interrupt, since it can fire as
+                // soon as it is requested.
+                let handler_ptr = ptr::addr_of_mut!((*slot).handler);
+                handler_ptr.write(handler);
+                ptr::addr_of_mut!((*slot).irq).write(irq);
+                // The name is owned by the registration, which outlives the interrupt, and so
+                // does the device id.
+                if let Err(e) = to_result(bindings::request_threaded_irq(
+                    irq,
+                    Some(Self::handler_callback),
+                    Some(Self::thread_fn_callback),
+                    flags as _,
+                    (*name_ptr).as_char_ptr(),
+                    slot.cast(),
+                )) {
+                    ptr::drop_in_place(name_ptr);
+                    ptr::drop_in_place(handler_ptr);
+                    return Err(e);
+                }
+                // INVARIANT: The interrupt is only considered initialised if it was requested.
+                Ok(())
+            })
+        }
+    }
+
+    /// Returns the interrupt number.
+    pub fn irq(&self) -> u32 {
+        self.irq
+    }
+
+    /// Returns the handlers of the interrupt.
+    pub fn handler(&self) -> &T {
+        &self.handler
+    }
+
+    /// Routes the interrupt to the CPU `cpu`, and hints user space to keep it there, or clears
+    /// the hint if `cpu` is `None`.
+    ///
+    /// Fails with `EINVAL` if `cpu` isn't a valid CPU id.
+    pub fn set_affinity_hint(&self, cpu: Option<u32>) -> Result {
+        set_affinity_hint(self.irq, cpu)
+    }
+
+    /// Routes the interrupt to the CPUs in `mask`.
+    pub fn set_affinity(&self, mask: &CpuMask) -> Result {
+        set_affinity(self.irq, mask)
+    }
+
+    unsafe extern "C" fn handler_callback(
+        _irq: c_int,
+        dev_id: *mut c_void,
+    ) -> bindings::irqreturn_t {
+        // SAFETY: The device id is the registration, which outlives the interrupt by the type
+        // invariants.
+        let this = unsafe { &*dev_id.cast::<Self>() };
+        let _section = AtomicSection::enter(Kind::HardIrq);
+        this.handler.handle_irq().as_raw()
+    }
+
+    unsafe extern "C" fn thread_fn_callback(
+        _irq: c_int,
+        dev_id: *mut c_void,
+    ) -> bindings::irqreturn_t {
+        // SAFETY: The device id is the registration, which outlives the interrupt by the type
+        // invariants.
+        let this = unsafe { &*dev_id.cast::<Self>() };
+        this.handler.handle_threaded_irq().as_raw()
+    }
+}
+
+impl<T: ThreadedHandler> Drop for ThreadedRegistration<T> {
+    fn drop(&mut self) {
+        // SAFETY: The interrupt was requested with this device id by the type invariants. The
+        // affinity hint must be cleared before it is freed.
+        unsafe {
+            bindings::irq_update_affinity_hint(self.irq, ptr::null());
+            bindings::free_irq(self.irq, self as *mut Self as *mut c_void);
+        }
+    }
+}
+
+// SAFETY: The interrupt can be freed from any thread, and the handlers are only used by
+// reference.
+unsafe impl<T: ThreadedHandler + Send> Send for ThreadedRegistration<T> {}
+
+// SAFETY: The methods that take `&self` only read fields that never change or call functions
+// with their own synchronisation, and the handlers are `Sync`.
+unsafe impl<T: ThreadedHandler> Sync for ThreadedRegistration<T> {}
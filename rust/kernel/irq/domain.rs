@@ -0,0 +1,437 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Interrupt domains.
+//!
+//! C header: [`include/linux/irqdomain.h`](../../../../include/linux/irqdomain.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/core-api/irq/irq-domain.html>
+
+use super::Type;
+use crate::{
+    bindings,
+    device::Device,
+    error::{code::*, from_result, to_result, Error, Result},
+    init::{self, PinInit},
+    str::CStr,
+    types::Opaque,
+};
+use core::{
+    ffi::{c_int, c_uint},
+    marker::{PhantomData, PhantomPinned},
+    mem::MaybeUninit,
+    ptr,
+};
+use macros::vtable;
+
+/// How the interrupts of a [`Domain`] are handled, i.e. their flow handler.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    /// Level-triggered interrupts, which are masked while they are handled, with
+    /// `handle_level_irq`.
+    Level,
+    /// Edge-triggered interrupts, which are acknowledged before they are handled, with
+    /// `handle_edge_irq`.
+    Edge,
+    /// Interrupts that need no flow control, with `handle_simple_irq`.
+    Simple,
+    /// Interrupts dispatched from the threaded handler of the controller, e.g. one behind an I2C
+    /// bus, with [`Domain::handle_nested`]. Their handlers run in that thread.
+    Nested,
+}
+
+/// The operations of an interrupt controller, the kernel's `struct irq_chip`.
+///
+/// The driver data of the domain implements this trait. The callbacks are called with the lock of
+/// the interrupt held and interrupts disabled, so they must not sleep, except
+/// [`Chip::bus_lock`] and [`Chip::bus_sync_unlock`].
+///
+/// Controllers behind a slow bus can't access their registers in the other callbacks. They
+/// record the changes instead, and apply them in [`Chip::bus_sync_unlock`].
+#[vtable]
+pub trait Chip: Send + Sync + Sized + 'static {
+    /// The name of the controller, shown in `/proc/interrupts`.
+    const NAME: &'static CStr;
+
+    /// The flow handler of the interrupts.
+    const FLOW: Flow = Flow::Level;
+
+    /// Masks the interrupt.
+    fn mask(data: &IrqData<Self>);
+
+    /// Unmasks the interrupt.
+    fn unmask(data: &IrqData<Self>);
+
+    /// Acknowledges the interrupt, before it is handled.
+    fn ack(_data: &IrqData<Self>) {}
+
+    /// Sets the trigger of the interrupt.
+    fn set_type(_data: &IrqData<Self>, _ty: Type) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Enables or disables the interrupt as a wakeup source of the system.
+    fn set_wake(_data: &IrqData<Self>, _on: bool) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Takes the lock of the bus of the controller, before the other callbacks are called.
+    ///
+    /// This may sleep.
+    fn bus_lock(_data: &IrqData<Self>) {}
+
+    /// Applies the changes of the other callbacks to the hardware, and releases the lock taken by
+    /// [`Chip::bus_lock`].
+    ///
+    /// This may sleep.
+    fn bus_sync_unlock(_data: &IrqData<Self>) {}
+}
+
+/// An interrupt of a [`Domain`], the kernel's `struct irq_data`.
+///
+/// # Invariants
+///
+/// The interrupt is valid while references to it exist, and its chip data is a `Domain<T>`.
+#[repr(transparent)]
+pub struct IrqData<T: Chip>(Opaque<bindings::irq_data>, PhantomData<T>);
+
+impl<T: Chip> IrqData<T> {
+    /// Creates a reference to an [`IrqData`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is an interrupt mapped by a `Domain<T>` for the lifetime of
+    /// the returned reference.
+    unsafe fn from_raw<'a>(ptr: *mut bindings::irq_data) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the Linux interrupt number.
+    pub fn irq(&self) -> u32 {
+        // SAFETY: The interrupt is valid by the type invariants.
+        unsafe { (*self.0.get()).irq }
+    }
+
+    /// Returns the hardware interrupt number, i.e. the interrupt of the controller.
+    pub fn hwirq(&self) -> u64 {
+        // SAFETY: The interrupt is valid by the type invariants.
+        unsafe { (*self.0.get()).hwirq as _ }
+    }
+
+    /// Returns the domain of the interrupt.
+    pub fn domain(&self) -> &Domain<T> {
+        // SAFETY: The chip data of the interrupt is a `Domain<T>` by the type invariants, which
+        // outlives its interrupts.
+        unsafe { &*bindings::irq_data_get_irq_chip_data(self.0.get()).cast::<Domain<T>>() }
+    }
+
+    /// Returns the driver data of the domain.
+    pub fn data(&self) -> &T {
+        self.domain().data()
+    }
+}
+
+/// An interrupt domain, the kernel's `struct irq_domain`, with a linear map of up to `size`
+/// hardware interrupts.
+///
+/// The mappings are disposed of and the domain is removed when this is dropped.
+///
+/// # Invariants
+///
+/// `domain` is a domain whose host data is this domain, and whose interrupts use `chip`.
+///
+/// # Examples
+///
+/// A GPIO expander behind an I2C bus, whose interrupt mask is written once the bus is unlocked:
+///
+/// ```
+/// use kernel::{c_str, device::Device, prelude::*};
+/// use kernel::irq::{self, Domain, Flow, IrqData};
+/// use core::sync::atomic::{AtomicU8, Ordering};
+///
+/// struct Expander {
+///     mask: AtomicU8,
+/// }
+///
+/// impl Expander {
+///     fn write_mask(&self, _mask: u8) {
+///         // An I2C transfer, which sleeps.
+///     }
+///     fn read_status(&self) -> u8 {
+///         0
+///     }
+/// }
+///
+/// #[vtable]
+/// impl irq::Chip for Expander {
+///     const NAME: &'static CStr = c_str!("expander");
+///     const FLOW: Flow = Flow::Nested;
+///
+///     fn mask(data: &IrqData<Self>) {
+///         data.data().mask.fetch_or(1 << data.hwirq(), Ordering::Relaxed);
+///     }
+///
+///     fn unmask(data: &IrqData<Self>) {
+///         data.data().mask.fetch_and(!(1 << data.hwirq()), Ordering::Relaxed);
+///     }
+///
+///     fn bus_sync_unlock(data: &IrqData<Self>) {
+///         let exp = data.data();
+///         exp.write_mask(exp.mask.load(Ordering::Relaxed));
+///     }
+/// }
+///
+/// // Called from the threaded handler of the interrupt of the expander.
+/// fn handle(domain: &Domain<Expander>) {
+///     let status = domain.data().read_status();
+///     for hwirq in 0..8 {
+///         if status & (1 << hwirq) != 0 {
+///             let _ = domain.handle_nested(hwirq);
+///         }
+///     }
+/// }
+///
+/// fn probe(dev: &Device, exp: Expander) -> Result<Pin<Box<Domain<Expander>>>> {
+///     Box::pin_init(Domain::new_linear(dev, 8, exp))
+/// }
+/// ```
+pub struct Domain<T: Chip> {
+    domain: *mut bindings::irq_domain,
+    chip: Opaque<bindings::irq_chip>,
+    size: u32,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: Chip> Domain<T> {
+    const OPS: bindings::irq_domain_ops = bindings::irq_domain_ops {
+        map: Some(Self::map_callback),
+        unmap: Some(Self::unmap_callback),
+        xlate: Some(bindings::irq_domain_xlate_twocell),
+        // SAFETY: All other fields are optional, for which zero is valid.
+        ..unsafe { MaybeUninit::zeroed().assume_init() }
+    };
+
+    /// Returns an initialiser that creates a domain for the interrupt controller `dev`, with up
+    /// to `size` hardware interrupts and the driver data `data`.
+    ///
+    /// The domain is attached to the firmware node of `dev`, so that the interrupts in the
+    /// devicetree of the devices it serves can be mapped, with two cells: the hardware interrupt
+    /// and its trigger.
+    pub fn new_linear(dev: &Device, size: u32, data: T) -> impl PinInit<Self, Error> {
+        let dev = dev.as_raw();
+        // SAFETY: The closure initialises all fields on success, and drops those it initialised
+        // on failure. The domain isn't moved once it is created, since it is pinned.
+        unsafe {
+            init::pin_init_from_closure::<_, Error>(move |slot: *mut Self| {
+                let data_ptr = ptr::addr_of_mut!((*slot).data);
+                data_ptr.write(data);
+                ptr::addr_of_mut!((*slot).size).write(size);
+                let chip = Opaque::raw_get(ptr::addr_of!((*slot).chip));
+                chip.write(bindings::irq_chip {
+                    name: T::NAME.as_char_ptr(),
+                    irq_mask: Some(Self::mask_callback),
+                    irq_unmask: Some(Self::unmask_callback),
+                    irq_ack: if T::HAS_ACK {
+                        Some(Self::ack_callback)
+                    } else {
+                        None
+                    },
+                    irq_set_type: if T::HAS_SET_TYPE {
+                        Some(Self::set_type_callback)
+                    } else {
+                        None
+                    },
+                    irq_set_wake: if T::HAS_SET_WAKE {
+                        Some(Self::set_wake_callback)
+                    } else {
+                        None
+                    },
+                    irq_bus_lock: if T::HAS_BUS_LOCK {
+                        Some(Self::bus_lock_callback)
+                    } else {
+                        None
+                    },
+                    irq_bus_sync_unlock: if T::HAS_BUS_SYNC_UNLOCK {
+                        Some(Self::bus_sync_unlock_callback)
+                    } else {
+                        None
+                    },
+                    // SAFETY: All other fields are optional, for which zero is valid.
+                    ..MaybeUninit::zeroed().assume_init()
+                });
+                // The ops are static, and the host data is pinned and outlives the domain.
+                let domain = bindings::irq_domain_create_linear(
+                    bindings::dev_fwnode(dev),
+                    size,
+                    &Self::OPS,
+                    slot.cast(),
+                );
+                if domain.is_null() {
+                    ptr::drop_in_place(data_ptr);
+                    return Err(ENOMEM);
+                }
+                // INVARIANT: The domain was created above, with this as its host data.
+                ptr::addr_of_mut!((*slot).domain).write(domain);
+                Ok(())
+            })
+        }
+    }
+
+    /// Returns the raw `struct irq_domain` pointer.
+    pub fn as_raw(&self) -> *mut bindings::irq_domain {
+        self.domain
+    }
+
+    /// Returns the driver data of the domain.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    /// Maps the hardware interrupt `hwirq` and returns its Linux interrupt number.
+    ///
+    /// It returns the existing mapping if there is one. Fails with `EINVAL` if `hwirq` is out of
+    /// range.
+    pub fn create_mapping(&self, hwirq: u32) -> Result<u32> {
+        if hwirq >= self.size {
+            return Err(EINVAL);
+        }
+        crate::might_sleep!();
+        // SAFETY: The domain is valid by the type invariants.
+        match unsafe { bindings::irq_create_mapping(self.domain, hwirq as _) } {
+            0 => Err(ENOMEM),
+            irq => Ok(irq),
+        }
+    }
+
+    /// Returns the Linux interrupt number of the hardware interrupt `hwirq`, if it is mapped.
+    ///
+    /// It can be called in atomic context.
+    pub fn find_mapping(&self, hwirq: u32) -> Option<u32> {
+        // SAFETY: The domain is valid by the type invariants.
+        match unsafe { bindings::irq_find_mapping(self.domain, hwirq as _) } {
+            0 => None,
+            irq => Some(irq),
+        }
+    }
+
+    /// Handles the hardware interrupt `hwirq`, from the handler of the interrupt of the
+    /// controller, i.e. in hard interrupt context.
+    ///
+    /// Fails with `EINVAL` if it isn't mapped.
+    pub fn handle(&self, hwirq: u32) -> Result {
+        // SAFETY: The domain is valid by the type invariants.
+        to_result(unsafe { bindings::generic_handle_domain_irq(self.domain, hwirq as _) })
+    }
+
+    /// Handles the hardware interrupt `hwirq` of a [`Flow::Nested`] domain, from the threaded
+    /// handler of the interrupt of the controller.
+    ///
+    /// Fails with `EINVAL` if it isn't mapped.
+    pub fn handle_nested(&self, hwirq: u32) -> Result {
+        let irq = self.find_mapping(hwirq).ok_or(EINVAL)?;
+        // SAFETY: `irq` is a valid interrupt.
+        unsafe { bindings::handle_nested_irq(irq) };
+        Ok(())
+    }
+
+    unsafe extern "C" fn map_callback(
+        d: *mut bindings::irq_domain,
+        virq: c_uint,
+        _hw: bindings::irq_hw_number_t,
+    ) -> c_int {
+        // SAFETY: The core calls this with a domain created by `new_linear`, whose host data is
+        // a `Domain<T>`.
+        let this = unsafe { (*d).host_data.cast::<Self>() };
+        let handler = match T::FLOW {
+            Flow::Level => bindings::handle_level_irq,
+            Flow::Edge => bindings::handle_edge_irq,
+            Flow::Simple | Flow::Nested => bindings::handle_simple_irq,
+        };
+        // SAFETY: `virq` is being mapped, and the chip and the chip data outlive the mapping,
+        // since mappings are disposed of before the domain is dropped.
+        unsafe {
+            bindings::irq_set_chip_data(virq, this.cast());
+            bindings::irq_set_chip_and_handler(virq, (*this).chip.get(), Some(handler));
+            if T::FLOW == Flow::Nested {
+                bindings::irq_set_nested_thread(virq, true);
+            }
+            bindings::irq_set_noprobe(virq);
+        }
+        0
+    }
+
+    unsafe extern "C" fn unmap_callback(_d: *mut bindings::irq_domain, virq: c_uint) {
+        // SAFETY: `virq` is being unmapped.
+        unsafe {
+            if T::FLOW == Flow::Nested {
+                bindings::irq_set_nested_thread(virq, false);
+            }
+            bindings::irq_set_chip_and_handler(virq, ptr::null_mut(), None);
+            bindings::irq_set_chip_data(virq, ptr::null_mut());
+        }
+    }
+
+    unsafe extern "C" fn mask_callback(d: *mut bindings::irq_data) {
+        // SAFETY: The core calls this with an interrupt mapped by this domain.
+        T::mask(unsafe { IrqData::from_raw(d) });
+    }
+
+    unsafe extern "C" fn unmask_callback(d: *mut bindings::irq_data) {
+        // SAFETY: The core calls this with an interrupt mapped by this domain.
+        T::unmask(unsafe { IrqData::from_raw(d) });
+    }
+
+    unsafe extern "C" fn ack_callback(d: *mut bindings::irq_data) {
+        // SAFETY: The core calls this with an interrupt mapped by this domain.
+        T::ack(unsafe { IrqData::from_raw(d) });
+    }
+
+    unsafe extern "C" fn set_type_callback(d: *mut bindings::irq_data, flow_type: c_uint) -> c_int {
+        from_result(|| {
+            let ty = Type::from_raw(flow_type).ok_or(EINVAL)?;
+            // SAFETY: The core calls this with an interrupt mapped by this domain.
+            T::set_type(unsafe { IrqData::from_raw(d) }, ty)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn set_wake_callback(d: *mut bindings::irq_data, on: c_uint) -> c_int {
+        from_result(|| {
+            // SAFETY: The core calls this with an interrupt mapped by this domain.
+            T::set_wake(unsafe { IrqData::from_raw(d) }, on != 0)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn bus_lock_callback(d: *mut bindings::irq_data) {
+        // SAFETY: The core calls this with an interrupt mapped by this domain.
+        T::bus_lock(unsafe { IrqData::from_raw(d) });
+    }
+
+    unsafe extern "C" fn bus_sync_unlock_callback(d: *mut bindings::irq_data) {
+        // SAFETY: The core calls this with an interrupt mapped by this domain.
+        T::bus_sync_unlock(unsafe { IrqData::from_raw(d) });
+    }
+}
+
+impl<T: Chip> Drop for Domain<T> {
+    fn drop(&mut self) {
+        for hwirq in 0..self.size {
+            if let Some(irq) = self.find_mapping(hwirq) {
+                // SAFETY: `irq` is mapped by this domain. Its users must have freed it before the
+                // domain is dropped.
+                unsafe { bindings::irq_dispose_mapping(irq) };
+            }
+        }
+        // SAFETY: The domain is valid by the type invariants, and has no mappings left.
+        unsafe { bindings::irq_domain_remove(self.domain) };
+    }
+}
+
+// SAFETY: The domain can be removed from any thread, and the driver data is `Send`.
+unsafe impl<T: Chip> Send for Domain<T> {}
+
+// SAFETY: The methods that take `&self` call functions with their own synchronisation, and the
+// driver data is `Sync`.
+unsafe impl<T: Chip> Sync for Domain<T> {}
@@ -0,0 +1,282 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! FIFO ring buffers.
+//!
+//! A [`Fifo`] is a kernel `kfifo` of elements of type `T`. With a single producer and a single
+//! consumer, it needs no locking: [`Fifo::split`] returns a [`Producer`] and a [`Consumer`] that
+//! can be used concurrently, e.g. the producer from an interrupt handler and the consumer from
+//! `read` of a file. Fifos of bytes can be copied directly from and to user space.
+//!
+//! C header: [`include/linux/kfifo.h`](../../../../include/linux/kfifo.h)
+
+use crate::{
+    bindings,
+    error::{to_result, Result},
+    sync::Arc,
+    user_ptr::{UserSlicePtrReader, UserSlicePtrWriter},
+};
+use core::{cell::UnsafeCell, marker::PhantomData, mem, ptr};
+
+/// A FIFO ring buffer of elements of type `T`, the equivalent of `struct kfifo`.
+///
+/// # Invariants
+///
+/// `fifo` was allocated by `__kfifo_alloc` with elements of the size of `T`, which it only holds
+/// copies of. Its `in` index is only changed by the producer, and its `out` index by the
+/// consumer.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{
+///     kfifo::{Consumer, Fifo, Producer},
+///     prelude::*,
+///     user_ptr::UserSlicePtrWriter,
+/// };
+///
+/// // Called from the interrupt handler, the only producer.
+/// fn rx_irq(fifo: &mut Producer<u8>, data: &[u8]) {
+///     let n = fifo.in_slice(data);
+///     if n < data.len() {
+///         pr_warn!("dropped {} bytes\n", data.len() - n);
+///     }
+/// }
+///
+/// // Called from `read`, the only consumer.
+/// fn read(fifo: &mut Consumer<u8>, writer: &mut UserSlicePtrWriter) -> Result<usize> {
+///     fifo.copy_to_user(writer)
+/// }
+///
+/// fn new_rx() -> Result<(Producer<u8>, Consumer<u8>)> {
+///     Fifo::try_new(4096)?.split()
+/// }
+/// ```
+pub struct Fifo<T: Copy> {
+    fifo: UnsafeCell<bindings::__kfifo>,
+    _p: PhantomData<T>,
+}
+
+impl<T: Copy> Fifo<T> {
+    /// Allocates a fifo of at least `size` elements, rounded up to a power of two.
+    pub fn try_new(size: usize) -> Result<Self> {
+        // SAFETY: All fields are integers and pointers, for which zero is valid.
+        let mut fifo: bindings::__kfifo = unsafe { mem::zeroed() };
+        // SAFETY: `fifo` is valid for writes.
+        to_result(unsafe {
+            bindings::__kfifo_alloc(
+                &mut fifo,
+                size.try_into()?,
+                mem::size_of::<T>(),
+                bindings::GFP_KERNEL,
+            )
+        })?;
+        // INVARIANT: The fifo was allocated above, and is empty.
+        Ok(Self {
+            fifo: UnsafeCell::new(fifo),
+            _p: PhantomData,
+        })
+    }
+
+    fn as_raw(&self) -> *mut bindings::__kfifo {
+        self.fifo.get()
+    }
+
+    /// Returns the number of elements that the fifo holds when it is full.
+    pub fn capacity(&self) -> usize {
+        // SAFETY: The mask never changes after allocation.
+        unsafe { (*self.as_raw()).mask as usize + 1 }
+    }
+
+    /// Returns the number of elements in the fifo.
+    ///
+    /// When the fifo is used concurrently, the result may be stale by the time it is used.
+    pub fn len(&self) -> usize {
+        let fifo = self.as_raw();
+        // SAFETY: The indices are valid for reads. They may be changed concurrently by the
+        // producer or the consumer, so they are read with volatile accesses, like `kfifo_len`.
+        let (in_, out) = unsafe {
+            (
+                ptr::addr_of!((*fifo).in_).read_volatile(),
+                ptr::addr_of!((*fifo).out).read_volatile(),
+            )
+        };
+        in_.wrapping_sub(out) as usize
+    }
+
+    /// Returns whether the fifo is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of elements that can be added to the fifo.
+    pub fn avail(&self) -> usize {
+        self.capacity().saturating_sub(self.len())
+    }
+
+    /// Adds as many elements of `data` as fit to the fifo, and returns how many were added.
+    pub fn in_slice(&mut self, data: &[T]) -> usize {
+        // SAFETY: The fifo is valid, `data` is valid for reads of its length, and this fifo is
+        // the only producer.
+        unsafe { in_slice(self.as_raw(), data) }
+    }
+
+    /// Removes elements from the fifo into `data`, as many as it holds and fit, and returns how
+    /// many were removed.
+    pub fn out_slice(&mut self, data: &mut [T]) -> usize {
+        // SAFETY: The fifo is valid, `data` is valid for writes of its length, and this fifo is
+        // the only consumer.
+        unsafe { out_slice(self.as_raw(), data) }
+    }
+
+    /// Removes all the elements from the fifo.
+    pub fn reset(&mut self) {
+        // SAFETY: The fifo is valid, and mutably borrowed.
+        unsafe {
+            (*self.as_raw()).in_ = 0;
+            (*self.as_raw()).out = 0;
+        }
+    }
+
+    /// Splits the fifo into a producer and a consumer, which can be used concurrently.
+    pub fn split(self) -> Result<(Producer<T>, Consumer<T>)> {
+        let fifo = Arc::try_new(self)?;
+        Ok((Producer { fifo: fifo.clone() }, Consumer { fifo }))
+    }
+}
+
+impl<T: Copy> Drop for Fifo<T> {
+    fn drop(&mut self) {
+        // SAFETY: The fifo was allocated by `__kfifo_alloc`, and isn't used after this.
+        unsafe { bindings::__kfifo_free(self.as_raw()) };
+    }
+}
+
+// SAFETY: The fifo owns copies of its elements, so it can be sent to another thread if they
+// can.
+unsafe impl<T: Copy + Send> Send for Fifo<T> {}
+
+// SAFETY: The fifo is only modified through mutable references, or through the producer and
+// consumer returned by `split`, which only modify the index they own.
+unsafe impl<T: Copy + Send> Sync for Fifo<T> {}
+
+/// Copies `data` into `fifo`, and returns the number of elements copied.
+///
+/// # Safety
+///
+/// `fifo` must be valid, with elements of type `T`, and the caller must be its only producer.
+unsafe fn in_slice<T: Copy>(fifo: *mut bindings::__kfifo, data: &[T]) -> usize {
+    let len = data.len().min(u32::MAX as usize);
+    // SAFETY: By the safety requirements. `data` is valid for reads of `len` elements.
+    unsafe { bindings::__kfifo_in(fifo, data.as_ptr().cast(), len as _) as usize }
+}
+
+/// Copies elements of `fifo` into `data`, and returns the number of elements copied.
+///
+/// # Safety
+///
+/// `fifo` must be valid, with elements of type `T`, and the caller must be its only consumer.
+unsafe fn out_slice<T: Copy>(fifo: *mut bindings::__kfifo, data: &mut [T]) -> usize {
+    let len = data.len().min(u32::MAX as usize);
+    // SAFETY: By the safety requirements. `data` is valid for writes of `len` elements.
+    unsafe { bindings::__kfifo_out(fifo, data.as_mut_ptr().cast(), len as _) as usize }
+}
+
+/// The producer side of a [`Fifo`], returned by [`Fifo::split`].
+pub struct Producer<T: Copy> {
+    fifo: Arc<Fifo<T>>,
+}
+
+impl<T: Copy> Producer<T> {
+    /// Returns the number of elements that can be added to the fifo.
+    ///
+    /// More may be available by the time it is used, as the consumer removes elements.
+    pub fn avail(&self) -> usize {
+        self.fifo.avail()
+    }
+
+    /// Adds as many elements of `data` as fit to the fifo, and returns how many were added.
+    ///
+    /// This may be called from atomic context.
+    pub fn in_slice(&mut self, data: &[T]) -> usize {
+        // SAFETY: The fifo is valid, `data` is valid for reads of its length, and this is the
+        // only producer, since it isn't `Clone` and the fifo isn't accessible otherwise.
+        unsafe { in_slice(self.fifo.as_raw(), data) }
+    }
+}
+
+impl Producer<u8> {
+    /// Copies as many bytes from `reader` as fit to the fifo, and returns how many were copied.
+    ///
+    /// The bytes that were copied are consumed from `reader`, also on failure.
+    pub fn copy_from_user(&mut self, reader: &mut UserSlicePtrReader) -> Result<usize> {
+        let (ptr, len) = reader.as_raw();
+        let mut copied = 0;
+        // SAFETY: The fifo is valid, with bytes, and this is its only producer.
+        // `__kfifo_from_user` checks the user pointer.
+        let ret =
+            unsafe { bindings::__kfifo_from_user(self.fifo.as_raw(), ptr, len as _, &mut copied) };
+        reader.advance(copied as usize);
+        to_result(ret)?;
+        Ok(copied as usize)
+    }
+}
+
+/// The consumer side of a [`Fifo`], returned by [`Fifo::split`].
+pub struct Consumer<T: Copy> {
+    fifo: Arc<Fifo<T>>,
+}
+
+impl<T: Copy> Consumer<T> {
+    /// Returns the number of elements in the fifo.
+    ///
+    /// More may be available by the time it is used, as the producer adds elements.
+    pub fn len(&self) -> usize {
+        self.fifo.len()
+    }
+
+    /// Returns whether the fifo is empty.
+    pub fn is_empty(&self) -> bool {
+        self.fifo.is_empty()
+    }
+
+    /// Removes elements from the fifo into `data`, as many as it holds and fit, and returns how
+    /// many were removed.
+    ///
+    /// This may be called from atomic context.
+    pub fn out_slice(&mut self, data: &mut [T]) -> usize {
+        // SAFETY: The fifo is valid, `data` is valid for writes of its length, and this is the
+        // only consumer, since it isn't `Clone` and the fifo isn't accessible otherwise.
+        unsafe { out_slice(self.fifo.as_raw(), data) }
+    }
+
+    /// Copies elements of the fifo into `data`, as many as it holds and fit, without removing
+    /// them, and returns how many were copied.
+    pub fn peek_slice(&self, data: &mut [T]) -> usize {
+        let len = data.len().min(u32::MAX as usize);
+        // SAFETY: The fifo is valid, `data` is valid for writes of `len` elements, and this is
+        // the only consumer, which `__kfifo_out_peek` doesn't change the index of.
+        unsafe {
+            bindings::__kfifo_out_peek(self.fifo.as_raw(), data.as_mut_ptr().cast(), len as _)
+                as usize
+        }
+    }
+}
+
+impl Consumer<u8> {
+    /// Copies bytes from the fifo to `writer`, as many as it holds and fit, and returns how many
+    /// were copied.
+    ///
+    /// The bytes that were copied are removed from the fifo and consumed from `writer`, also on
+    /// failure.
+    pub fn copy_to_user(&mut self, writer: &mut UserSlicePtrWriter) -> Result<usize> {
+        let (ptr, len) = writer.as_raw();
+        let mut copied = 0;
+        // SAFETY: The fifo is valid, with bytes, and this is its only consumer.
+        // `__kfifo_to_user` checks the user pointer.
+        let ret =
+            unsafe { bindings::__kfifo_to_user(self.fifo.as_raw(), ptr, len as _, &mut copied) };
+        writer.advance(copied as usize);
+        to_result(ret)?;
+        Ok(copied as usize)
+    }
+}
@@ -0,0 +1,215 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Kernel log dumpers.
+//!
+//! A driver of persistent storage, e.g. of a RAM region that survives a reboot, registers a
+//! [`Dumper`] to save the kernel log when the kernel panics or oopses, so that it can be read
+//! after the next boot.
+//!
+//! C header: [`include/linux/kmsg_dump.h`](../../../../include/linux/kmsg_dump.h)
+
+use crate::{
+    bindings,
+    error::{to_result, Result},
+    types::Opaque,
+};
+use alloc::boxed::Box;
+use core::{marker::PhantomPinned, mem::MaybeUninit, pin::Pin};
+
+/// The reason of a dump, the kernel's `enum kmsg_dump_reason`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Reason {
+    /// The kernel panicked.
+    Panic = bindings::kmsg_dump_reason_KMSG_DUMP_PANIC,
+    /// The kernel oopsed, and goes on running.
+    Oops = bindings::kmsg_dump_reason_KMSG_DUMP_OOPS,
+    /// The system is restarted, halted or powered off by an emergency, e.g. a SysRq.
+    Emerg = bindings::kmsg_dump_reason_KMSG_DUMP_EMERG,
+    /// The system is restarted, halted or powered off normally.
+    Shutdown = bindings::kmsg_dump_reason_KMSG_DUMP_SHUTDOWN,
+}
+
+impl Reason {
+    fn from_raw(reason: bindings::kmsg_dump_reason) -> Option<Self> {
+        match reason {
+            bindings::kmsg_dump_reason_KMSG_DUMP_PANIC => Some(Self::Panic),
+            bindings::kmsg_dump_reason_KMSG_DUMP_OOPS => Some(Self::Oops),
+            bindings::kmsg_dump_reason_KMSG_DUMP_EMERG => Some(Self::Emerg),
+            bindings::kmsg_dump_reason_KMSG_DUMP_SHUTDOWN => Some(Self::Shutdown),
+            _ => None,
+        }
+    }
+}
+
+/// A dumper of the kernel log.
+pub trait Dumper: Send + Sync + 'static {
+    /// Saves the kernel log, which is read with `log`.
+    ///
+    /// On panics, this is called with interrupts disabled and the other CPUs stopped, so it must
+    /// neither sleep, allocate nor take locks that the interrupted code may hold.
+    fn dump(&self, reason: Reason, log: &mut Log);
+}
+
+/// A reader of the kernel log, which reads the log from its oldest to its newest records.
+///
+/// # Invariants
+///
+/// `iter` is an iterator over the kernel log.
+pub struct Log {
+    iter: bindings::kmsg_dump_iter,
+}
+
+impl Log {
+    /// Copies the next record of the log into `buf`, terminated by a newline, and returns the
+    /// copied bytes, or `None` if there are no more records.
+    ///
+    /// Records that don't fit are truncated. If `syslog` is `true`, the record is prefixed with
+    /// its syslog priority, e.g. `<4>`.
+    pub fn next_line<'a>(&mut self, buf: &'a mut [u8], syslog: bool) -> Option<&'a [u8]> {
+        let mut len = 0;
+        // SAFETY: The iterator is valid by the type invariants, and `buf` is valid for writes
+        // of its length.
+        let found = unsafe {
+            bindings::kmsg_dump_get_line(
+                &mut self.iter,
+                syslog,
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+                &mut len,
+            )
+        };
+        found.then(|| &buf[..len])
+    }
+
+    /// Copies the newest records of the log that fit into `buf`, in order, and returns the
+    /// copied bytes, or `None` if there are no more records.
+    ///
+    /// The records are removed from the end of the log that is read, so calling this again
+    /// copies the records before them, which dumpers that store the log in chunks use.
+    pub fn read_buffer<'a>(&mut self, buf: &'a mut [u8], syslog: bool) -> Option<&'a [u8]> {
+        let mut len = 0;
+        // SAFETY: The iterator is valid by the type invariants, and `buf` is valid for writes
+        // of its length.
+        let found = unsafe {
+            bindings::kmsg_dump_get_buffer(
+                &mut self.iter,
+                syslog,
+                buf.as_mut_ptr().cast(),
+                buf.len(),
+                &mut len,
+            )
+        };
+        found.then(|| &buf[..len])
+    }
+
+    /// Restarts reading the log from its oldest records.
+    pub fn rewind(&mut self) {
+        // SAFETY: The iterator is valid by the type invariants.
+        unsafe { bindings::kmsg_dump_rewind(&mut self.iter) };
+    }
+}
+
+#[repr(C)]
+struct Inner<T> {
+    // Must be the first field, see `Registration::dump_callback`.
+    dumper: Opaque<bindings::kmsg_dumper>,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+/// A registered kernel log dumper.
+///
+/// The dumper is unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `inner.dumper` is registered, with the callback of `T`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::kmsg::{self, Dumper, Log, Reason};
+/// use kernel::prelude::*;
+///
+/// struct Ramoops {
+///     // The persistent RAM region.
+///     base: usize,
+///     size: usize,
+/// }
+///
+/// impl Dumper for Ramoops {
+///     fn dump(&self, _reason: Reason, log: &mut Log) {
+///         let base = self.base as *mut u8;
+///         // SAFETY: The region is mapped and reserved for the dumper.
+///         let region = unsafe { core::slice::from_raw_parts_mut(base, self.size) };
+///         let _ = log.read_buffer(region, true);
+///     }
+/// }
+///
+/// fn register(base: usize, size: usize) -> Result<kmsg::Registration<Ramoops>> {
+///     kmsg::Registration::register(Reason::Oops, Ramoops { base, size })
+/// }
+/// ```
+pub struct Registration<T: Dumper> {
+    inner: Pin<Box<Inner<T>>>,
+}
+
+impl<T: Dumper> Registration<T> {
+    /// Registers a dumper with the driver data `data`, which is called for the reasons up to and
+    /// including `max_reason`.
+    pub fn register(max_reason: Reason, data: T) -> Result<Self> {
+        // SAFETY: All-zeroes is a valid, unregistered dumper.
+        let mut raw: bindings::kmsg_dumper = unsafe { MaybeUninit::zeroed().assume_init() };
+        raw.dump = Some(Self::dump_callback);
+        raw.max_reason = max_reason as _;
+        let inner = Pin::from(Box::try_new(Inner {
+            dumper: Opaque::new(raw),
+            data,
+            _pin: PhantomPinned,
+        })?);
+        // SAFETY: The dumper is pinned, and it is unregistered before it is freed.
+        to_result(unsafe { bindings::kmsg_dump_register(inner.dumper.get()) })?;
+        // INVARIANT: The dumper was registered above.
+        Ok(Self { inner })
+    }
+
+    /// Returns the driver data.
+    pub fn data(&self) -> &T {
+        &self.inner.data
+    }
+
+    unsafe extern "C" fn dump_callback(
+        dumper: *mut bindings::kmsg_dumper,
+        reason: bindings::kmsg_dump_reason,
+    ) {
+        let reason = match Reason::from_raw(reason) {
+            Some(reason) => reason,
+            None => return,
+        };
+        // SAFETY: `dumper` is the first field of an `Inner<T>`, which is `repr(C)`, and is
+        // registered, so the `Inner<T>` is alive.
+        let data = unsafe { &(*dumper.cast::<Inner<T>>()).data };
+        let mut log = Log {
+            // SAFETY: All-zeroes is a valid iterator, which is rewound below.
+            iter: unsafe { MaybeUninit::zeroed().assume_init() },
+        };
+        // INVARIANT: The iterator is rewound to the start of the log.
+        log.rewind();
+        data.dump(reason, &mut log);
+    }
+}
+
+impl<T: Dumper> Drop for Registration<T> {
+    fn drop(&mut self) {
+        // SAFETY: The dumper is registered by the type invariants. Unregistering it waits for
+        // the dumpers that are being called to return.
+        unsafe { bindings::kmsg_dump_unregister(self.inner.dumper.get()) };
+    }
+}
+
+// SAFETY: The dumper can be unregistered from any thread, and the driver data is `Send`.
+unsafe impl<T: Dumper> Send for Registration<T> {}
+
+// SAFETY: The methods that take `&self` only read the driver data, which is `Sync`.
+unsafe impl<T: Dumper> Sync for Registration<T> {}
@@ -0,0 +1,222 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Kernel objects and uevents.
+//!
+//! C header: [`include/linux/kobject.h`](../../../../include/linux/kobject.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/core-api/kobject.html>
+
+use crate::{
+    bindings,
+    error::{code::*, to_result, Result},
+    fmt,
+    str::{CStr, CString},
+    sysfs::AttributeGroups,
+    types::{ARef, AlwaysRefCounted, Opaque},
+};
+use alloc::vec::Vec;
+use core::{fmt::Display, ptr};
+
+/// Wraps the kernel's `struct kobject`.
+///
+/// # Invariants
+///
+/// Instances of this type are always ref-counted, that is, a call to `kobject_get` ensures that
+/// the allocation remains valid at least until the matching call to `kobject_put`.
+#[repr(transparent)]
+pub struct Kobject(Opaque<bindings::kobject>);
+
+// SAFETY: Kobjects are reference-counted and can be released from any thread.
+unsafe impl Send for Kobject {}
+
+// SAFETY: The methods of `Kobject` that take `&self` are safe to call concurrently, the C side
+// synchronises them.
+unsafe impl Sync for Kobject {}
+
+impl Kobject {
+    /// Creates a kobject named `name` and adds it to sysfs under `parent`.
+    ///
+    /// Without a parent, the kobject appears at the top of `/sys`. The kobject is removed from
+    /// sysfs when the last reference to it is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kernel::{c_str, kobject::Kobject};
+    /// # use kernel::prelude::*;
+    ///
+    /// // Creates `/sys/kernel/my_driver`.
+    /// let kobj = Kobject::try_new(c_str!("my_driver"), Some(Kobject::kernel()))?;
+    /// # Ok::<(), Error>(())
+    /// ```
+    pub fn try_new(name: &CStr, parent: Option<&Kobject>) -> Result<ARef<Self>> {
+        let parent = parent.map_or(ptr::null_mut(), |p| p.as_raw());
+        // SAFETY: `name` is a valid C string and `parent` is either null or a valid kobject.
+        let kobj = unsafe { bindings::kobject_create_and_add(name.as_char_ptr(), parent) };
+        let kobj = ptr::NonNull::new(kobj).ok_or(ENOMEM)?;
+        // SAFETY: `kobject_create_and_add` returns a kobject with a reference that we now own.
+        Ok(unsafe { ARef::from_raw(kobj.cast()) })
+    }
+
+    /// Returns the `/sys/kernel` kobject.
+    pub fn kernel() -> &'static Kobject {
+        // SAFETY: `kernel_kobj` is created early during boot and never released.
+        unsafe { Self::as_ref(bindings::kernel_kobj) }
+    }
+
+    /// Returns the `/sys/firmware` kobject.
+    pub fn firmware() -> &'static Kobject {
+        // SAFETY: `firmware_kobj` is created early during boot and never released.
+        unsafe { Self::as_ref(bindings::firmware_kobj) }
+    }
+
+    /// Creates a reference to a [`Kobject`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is valid, non-null, and has a non-zero reference count for
+    /// the entire duration when the returned reference exists.
+    pub unsafe fn as_ref<'a>(ptr: *mut bindings::kobject) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct kobject` pointer.
+    pub fn as_raw(&self) -> *mut bindings::kobject {
+        self.0.get()
+    }
+
+    /// Returns the name of the kobject.
+    pub fn name(&self) -> &CStr {
+        // SAFETY: By the type invariants, the kobject is valid. Added kobjects always have a name,
+        // which lives until they are renamed or released.
+        unsafe { CStr::from_char_ptr(bindings::kobject_name(self.as_raw())) }
+    }
+
+    /// Adds `groups` to the kobject.
+    ///
+    /// The groups' attributes must be [`KobjAttr`] unless the kobject is embedded in another
+    /// kind of object, e.g. a device.
+    ///
+    /// [`KobjAttr`]: crate::sysfs::KobjAttr
+    pub fn add_groups(&self, groups: &'static AttributeGroups) -> Result<Groups> {
+        // SAFETY: The kobject is valid by the type invariants, and `groups` is a static, valid
+        // array.
+        to_result(unsafe { bindings::sysfs_create_groups(self.as_raw(), groups.as_ptr()) })?;
+        Ok(Groups {
+            kobj: self.into(),
+            groups,
+        })
+    }
+
+    /// Sends a uevent with no additional environment variables.
+    pub fn uevent(&self, action: Action) -> Result {
+        // SAFETY: The kobject is valid by the type invariants.
+        to_result(unsafe { bindings::kobject_uevent(self.as_raw(), action as _) })
+    }
+
+    /// Sends a uevent with the environment variables in `env`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use kernel::{c_str, kobject::{Action, Kobject, UeventEnv}};
+    /// # use kernel::prelude::*;
+    ///
+    /// fn report_overcurrent(kobj: &Kobject, port: u32) -> Result {
+    ///     let mut env = UeventEnv::new();
+    ///     env.add(c_str!("EVENT"), "overcurrent")?;
+    ///     env.add(c_str!("PORT"), port)?;
+    ///     kobj.uevent_env(Action::Change, &env)
+    /// }
+    /// ```
+    pub fn uevent_env(&self, action: Action, env: &UeventEnv) -> Result {
+        let mut envp = Vec::try_with_capacity(env.vars.len() + 1)?;
+        for var in &env.vars {
+            envp.try_push(var.as_char_ptr() as *mut core::ffi::c_char)?;
+        }
+        envp.try_push(ptr::null_mut())?;
+        // SAFETY: The kobject is valid by the type invariants, and `envp` is a `NULL`-terminated
+        // array of C strings that outlives the call.
+        to_result(unsafe {
+            bindings::kobject_uevent_env(self.as_raw(), action as _, envp.as_mut_ptr())
+        })
+    }
+}
+
+// SAFETY: The type invariants guarantee that `Kobject` is always ref-counted.
+unsafe impl AlwaysRefCounted for Kobject {
+    fn inc_ref(&self) {
+        // SAFETY: The existence of a shared reference means that the refcount is nonzero.
+        unsafe { bindings::kobject_get(self.as_raw()) };
+    }
+
+    unsafe fn dec_ref(obj: ptr::NonNull<Self>) {
+        // SAFETY: The safety requirements guarantee that the refcount is nonzero.
+        unsafe { bindings::kobject_put(obj.cast().as_ptr()) }
+    }
+}
+
+/// Attribute groups added to a kobject with [`Kobject::add_groups`].
+///
+/// The groups are removed when this is dropped.
+pub struct Groups {
+    kobj: ARef<Kobject>,
+    groups: &'static AttributeGroups,
+}
+
+impl Drop for Groups {
+    fn drop(&mut self) {
+        // SAFETY: The groups were added to the kobject in `Kobject::add_groups`.
+        unsafe { bindings::sysfs_remove_groups(self.kobj.as_raw(), self.groups.as_ptr()) };
+    }
+}
+
+/// The action reported by a uevent.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// An object was added.
+    Add = bindings::kobject_action_KOBJ_ADD,
+    /// An object was removed.
+    Remove = bindings::kobject_action_KOBJ_REMOVE,
+    /// The state of an object changed.
+    Change = bindings::kobject_action_KOBJ_CHANGE,
+    /// An object was moved or renamed.
+    Move = bindings::kobject_action_KOBJ_MOVE,
+    /// An object went online.
+    Online = bindings::kobject_action_KOBJ_ONLINE,
+    /// An object went offline.
+    Offline = bindings::kobject_action_KOBJ_OFFLINE,
+    /// A driver was bound to a device.
+    Bind = bindings::kobject_action_KOBJ_BIND,
+    /// A driver was unbound from a device.
+    Unbind = bindings::kobject_action_KOBJ_UNBIND,
+}
+
+/// Environment variables sent along with a uevent.
+///
+/// See [`Kobject::uevent_env`] for an example.
+#[derive(Default)]
+pub struct UeventEnv {
+    vars: Vec<CString>,
+}
+
+impl UeventEnv {
+    /// Creates an empty environment.
+    pub fn new() -> Self {
+        Self { vars: Vec::new() }
+    }
+
+    /// Adds the variable `key` with the given value.
+    ///
+    /// Keys are conventionally upper case and must not contain `=`.
+    pub fn add(&mut self, key: &CStr, value: impl Display) -> Result {
+        if key.is_empty() || key.as_bytes().contains(&b'=') {
+            return Err(EINVAL);
+        }
+        let var = CString::try_from_fmt(fmt!("{}={}", key, value))?;
+        self.vars.try_push(var)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,402 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Kernel probes.
+//!
+//! A [`Probe`] calls a handler whenever the CPU executes an instruction of the kernel, e.g. the
+//! start of a function, and a [`ReturnProbe`] whenever a function returns, with the registers of
+//! the CPU at that point. They are meant for debugging and diagnostics.
+//!
+//! The handlers are called in atomic context, with preemption and possibly interrupts disabled.
+//! They must not sleep, and must not call functions that are probed themselves, which are then
+//! missed.
+//!
+//! C header: [`include/linux/kprobes.h`](../../../../include/linux/kprobes.h)
+
+use crate::{
+    bindings,
+    error::{to_result, Result},
+    str::CStr,
+    types::Opaque,
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::{c_int, c_ulong},
+    marker::PhantomPinned,
+    mem::MaybeUninit,
+    pin::Pin,
+};
+use macros::vtable;
+
+/// The registers of a CPU when a probe was hit, the kernel's `struct pt_regs`.
+///
+/// # Invariants
+///
+/// The registers are valid while references to them exist.
+#[repr(transparent)]
+pub struct Registers(Opaque<bindings::pt_regs>);
+
+impl Registers {
+    /// Creates a reference to [`Registers`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is valid for the lifetime of the returned reference.
+    unsafe fn from_raw<'a>(ptr: *mut bindings::pt_regs) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct pt_regs` pointer, e.g. to read architecture-specific registers.
+    pub fn as_raw(&self) -> *mut bindings::pt_regs {
+        self.0.get()
+    }
+
+    /// Returns the instruction pointer.
+    pub fn instruction_pointer(&self) -> usize {
+        // SAFETY: The registers are valid by the type invariants.
+        unsafe { bindings::instruction_pointer(self.as_raw()) as _ }
+    }
+
+    /// Returns the stack pointer.
+    pub fn stack_pointer(&self) -> usize {
+        // SAFETY: The registers are valid by the type invariants.
+        unsafe { bindings::kernel_stack_pointer(self.as_raw()) as _ }
+    }
+
+    /// Returns the return value of the function, in return probes.
+    pub fn return_value(&self) -> usize {
+        // SAFETY: The registers are valid by the type invariants.
+        unsafe { bindings::regs_return_value(self.as_raw()) as _ }
+    }
+
+    /// Returns the argument `n` of the function, counting from 0, at the start of a function.
+    ///
+    /// Arguments that are passed on the stack are read from there, and are 0 if the stack doesn't
+    /// reach that far.
+    #[cfg(CONFIG_HAVE_FUNCTION_ARG_ACCESS_API)]
+    pub fn argument(&self, n: u32) -> usize {
+        // SAFETY: The registers are valid by the type invariants.
+        unsafe { bindings::regs_get_kernel_argument(self.as_raw(), n) as _ }
+    }
+}
+
+/// Where a probe is placed.
+#[derive(Clone, Copy, Debug)]
+pub enum Location {
+    /// At the given offset from the start of the symbol, e.g. of a function.
+    Symbol(&'static CStr, usize),
+    /// At the given address, which must be that of an instruction in the kernel's text.
+    Address(usize),
+}
+
+impl Location {
+    fn apply(self, kp: &mut bindings::kprobe) {
+        match self {
+            Location::Symbol(name, offset) => {
+                kp.symbol_name = name.as_char_ptr();
+                kp.offset = offset as _;
+            }
+            Location::Address(addr) => kp.addr = addr as _,
+        }
+    }
+}
+
+/// The handlers of a [`Probe`].
+///
+/// The driver data of the probe implements this trait.
+#[vtable]
+pub trait Handler: Send + Sync + Sized + 'static {
+    /// Called before the probed instruction is executed.
+    fn pre(_data: &Self, _regs: &Registers) {}
+
+    /// Called after the probed instruction was executed, unless it was emulated.
+    fn post(_data: &Self, _regs: &Registers) {}
+}
+
+#[repr(C)]
+struct ProbeInner<T> {
+    // Must be the first field, see `Probe::data_of`.
+    kp: Opaque<bindings::kprobe>,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+/// A registered kernel probe.
+///
+/// The probe is unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `inner.kp` is registered, with the handlers of `T`.
+///
+/// # Examples
+///
+/// ```
+/// use core::sync::atomic::{AtomicUsize, Ordering};
+/// use kernel::kprobes::{self, Location, Probe, Registers};
+/// use kernel::{c_str, prelude::*, trace_printk};
+///
+/// struct Counter(AtomicUsize);
+///
+/// #[vtable]
+/// impl kprobes::Handler for Counter {
+///     fn pre(data: &Self, regs: &Registers) {
+///         data.0.fetch_add(1, Ordering::Relaxed);
+///         trace_printk!("kfree at {:#x}\n", regs.instruction_pointer());
+///     }
+/// }
+///
+/// fn probe() -> Result<Probe<Counter>> {
+///     let location = Location::Symbol(c_str!("kfree"), 0);
+///     Probe::register(location, Counter(AtomicUsize::new(0)))
+/// }
+/// ```
+pub struct Probe<T: Handler> {
+    inner: Pin<Box<ProbeInner<T>>>,
+}
+
+impl<T: Handler> Probe<T> {
+    /// Registers a probe at `location`, with the driver data `data`.
+    ///
+    /// Fails with `EINVAL` if the location can't be probed, e.g. because it isn't the start of
+    /// an instruction, or is in code that kprobes itself uses.
+    pub fn register(location: Location, data: T) -> Result<Self> {
+        crate::might_sleep!();
+        // SAFETY: All-zeroes is a valid, unregistered probe.
+        let mut kp: bindings::kprobe = unsafe { MaybeUninit::zeroed().assume_init() };
+        location.apply(&mut kp);
+        if T::HAS_PRE {
+            kp.pre_handler = Some(Self::pre_callback);
+        }
+        if T::HAS_POST {
+            kp.post_handler = Some(Self::post_callback);
+        }
+        let inner = Pin::from(Box::try_new(ProbeInner {
+            kp: Opaque::new(kp),
+            data,
+            _pin: PhantomPinned,
+        })?);
+        // SAFETY: The probe is pinned, and it is unregistered before it is freed. The symbol
+        // name is static.
+        to_result(unsafe { bindings::register_kprobe(inner.kp.get()) })?;
+        // INVARIANT: The probe was registered above.
+        Ok(Self { inner })
+    }
+
+    /// Returns the driver data.
+    pub fn data(&self) -> &T {
+        &self.inner.data
+    }
+
+    /// Returns the number of hits that were missed, because the probe was hit again while its
+    /// handlers were running.
+    pub fn missed(&self) -> usize {
+        // SAFETY: The probe is registered by the type invariants, and the count is only read.
+        unsafe { (*self.inner.kp.get()).nmissed as _ }
+    }
+
+    /// Returns the driver data of the probe `kp`.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `kp` is a probe registered by a `Probe<T>`.
+    unsafe fn data_of<'a>(kp: *mut bindings::kprobe) -> &'a T {
+        // SAFETY: `kp` is the first field of a `ProbeInner<T>`, which is `repr(C)`, and outlives
+        // the probe by the safety requirements.
+        unsafe { &(*kp.cast::<ProbeInner<T>>()).data }
+    }
+
+    unsafe extern "C" fn pre_callback(
+        kp: *mut bindings::kprobe,
+        regs: *mut bindings::pt_regs,
+    ) -> c_int {
+        // SAFETY: The kprobes core calls this with a probe registered by `register`, and the
+        // registers of the CPU, which are valid during the call.
+        let (data, regs) = unsafe { (Self::data_of(kp), Registers::from_raw(regs)) };
+        T::pre(data, regs);
+        // The instruction must still be single-stepped, as the handler didn't change the
+        // instruction pointer.
+        0
+    }
+
+    unsafe extern "C" fn post_callback(
+        kp: *mut bindings::kprobe,
+        regs: *mut bindings::pt_regs,
+        _flags: c_ulong,
+    ) {
+        // SAFETY: The kprobes core calls this with a probe registered by `register`, and the
+        // registers of the CPU, which are valid during the call.
+        let (data, regs) = unsafe { (Self::data_of(kp), Registers::from_raw(regs)) };
+        T::post(data, regs);
+    }
+}
+
+impl<T: Handler> Drop for Probe<T> {
+    fn drop(&mut self) {
+        // SAFETY: The probe is registered by the type invariants. Unregistering it waits for the
+        // handlers that are running to return.
+        unsafe { bindings::unregister_kprobe(self.inner.kp.get()) };
+    }
+}
+
+// SAFETY: The probe can be unregistered from any thread, and the driver data is `Send`.
+unsafe impl<T: Handler> Send for Probe<T> {}
+
+// SAFETY: The methods that take `&self` only read the driver data, which is `Sync`, and the
+// count of missed hits.
+unsafe impl<T: Handler> Sync for Probe<T> {}
+
+/// The handlers of a [`ReturnProbe`].
+///
+/// The driver data of the probe implements this trait.
+#[cfg(CONFIG_KRETPROBES)]
+#[vtable]
+pub trait ReturnHandler: Send + Sync + Sized + 'static {
+    /// Called when the probed function is entered, and returns whether [`ReturnHandler::ret`] is
+    /// called when it returns.
+    fn entry(_data: &Self, _regs: &Registers) -> bool {
+        true
+    }
+
+    /// Called when the probed function returns.
+    fn ret(data: &Self, regs: &Registers);
+}
+
+#[cfg(CONFIG_KRETPROBES)]
+#[repr(C)]
+struct ReturnProbeInner<T> {
+    // Must be the first field, see `ReturnProbe::data_of`.
+    rp: Opaque<bindings::kretprobe>,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+/// A registered return probe.
+///
+/// The probe is unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `inner.rp` is registered, with the handlers of `T`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::kprobes::{self, Location, Registers, ReturnProbe};
+/// use kernel::{c_str, prelude::*, trace_printk};
+///
+/// struct Failures;
+///
+/// #[vtable]
+/// impl kprobes::ReturnHandler for Failures {
+///     fn ret(_data: &Self, regs: &Registers) {
+///         let ret = regs.return_value() as isize;
+///         if ret < 0 {
+///             trace_printk!("clk_prepare failed: {}\n", ret);
+///         }
+///     }
+/// }
+///
+/// fn probe() -> Result<ReturnProbe<Failures>> {
+///     let location = Location::Symbol(c_str!("clk_prepare"), 0);
+///     ReturnProbe::register(location, 0, Failures)
+/// }
+/// ```
+#[cfg(CONFIG_KRETPROBES)]
+pub struct ReturnProbe<T: ReturnHandler> {
+    inner: Pin<Box<ReturnProbeInner<T>>>,
+}
+
+#[cfg(CONFIG_KRETPROBES)]
+impl<T: ReturnHandler> ReturnProbe<T> {
+    /// Registers a return probe of the function at `location`, with the driver data `data`.
+    ///
+    /// Up to `max_active` calls of the function can be tracked at once, or a default that depends
+    /// on the number of CPUs if it is 0. The returns of further calls are missed.
+    pub fn register(location: Location, max_active: u32, data: T) -> Result<Self> {
+        crate::might_sleep!();
+        // SAFETY: All-zeroes is a valid, unregistered probe.
+        let mut rp: bindings::kretprobe = unsafe { MaybeUninit::zeroed().assume_init() };
+        location.apply(&mut rp.kp);
+        rp.maxactive = max_active as _;
+        rp.handler = Some(Self::ret_callback);
+        if T::HAS_ENTRY {
+            rp.entry_handler = Some(Self::entry_callback);
+        }
+        let inner = Pin::from(Box::try_new(ReturnProbeInner {
+            rp: Opaque::new(rp),
+            data,
+            _pin: PhantomPinned,
+        })?);
+        // SAFETY: The probe is pinned, and it is unregistered before it is freed. The symbol
+        // name is static.
+        to_result(unsafe { bindings::register_kretprobe(inner.rp.get()) })?;
+        // INVARIANT: The probe was registered above.
+        Ok(Self { inner })
+    }
+
+    /// Returns the driver data.
+    pub fn data(&self) -> &T {
+        &self.inner.data
+    }
+
+    /// Returns the number of returns that were missed, because too many calls were tracked.
+    pub fn missed(&self) -> usize {
+        // SAFETY: The probe is registered by the type invariants, and the count is only read.
+        unsafe { (*self.inner.rp.get()).nmissed as _ }
+    }
+
+    /// Returns the driver data of the probe of the instance `ri`.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ri` is an instance of a probe registered by a `ReturnProbe<T>`.
+    unsafe fn data_of<'a>(ri: *mut bindings::kretprobe_instance) -> &'a T {
+        // SAFETY: The instance is valid by the safety requirements.
+        let rp = unsafe { bindings::get_kretprobe(ri) };
+        // SAFETY: `rp` is the first field of a `ReturnProbeInner<T>`, which is `repr(C)`, and
+        // outlives the probe by the safety requirements.
+        unsafe { &(*rp.cast::<ReturnProbeInner<T>>()).data }
+    }
+
+    unsafe extern "C" fn entry_callback(
+        ri: *mut bindings::kretprobe_instance,
+        regs: *mut bindings::pt_regs,
+    ) -> c_int {
+        // SAFETY: The kprobes core calls this with an instance of a probe registered by
+        // `register`, and the registers of the CPU, which are valid during the call.
+        let (data, regs) = unsafe { (Self::data_of(ri), Registers::from_raw(regs)) };
+        let track = T::entry(data, regs);
+        // A non-zero value makes the core skip the return.
+        (!track) as c_int
+    }
+
+    unsafe extern "C" fn ret_callback(
+        ri: *mut bindings::kretprobe_instance,
+        regs: *mut bindings::pt_regs,
+    ) -> c_int {
+        // SAFETY: The kprobes core calls this with an instance of a probe registered by
+        // `register`, and the registers of the CPU, which are valid during the call.
+        let (data, regs) = unsafe { (Self::data_of(ri), Registers::from_raw(regs)) };
+        T::ret(data, regs);
+        0
+    }
+}
+
+#[cfg(CONFIG_KRETPROBES)]
+impl<T: ReturnHandler> Drop for ReturnProbe<T> {
+    fn drop(&mut self) {
+        // SAFETY: The probe is registered by the type invariants. Unregistering it waits for the
+        // handlers that are running to return, and detaches the pending instances.
+        unsafe { bindings::unregister_kretprobe(self.inner.rp.get()) };
+    }
+}
+
+// SAFETY: The probe can be unregistered from any thread, and the driver data is `Send`.
+#[cfg(CONFIG_KRETPROBES)]
+unsafe impl<T: ReturnHandler> Send for ReturnProbe<T> {}
+
+// SAFETY: The methods that take `&self` only read the driver data, which is `Sync`, and the
+// count of missed returns.
+#[cfg(CONFIG_KRETPROBES)]
+unsafe impl<T: ReturnHandler> Sync for ReturnProbe<T> {}
@@ -0,0 +1,156 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Kernel threads.
+//!
+//! C header: [`include/linux/kthread.h`](../../../../include/linux/kthread.h)
+
+use crate::{
+    bindings,
+    error::{from_err_ptr, Result},
+    freezer,
+    str::CString,
+    task::Task,
+    types::{ARef, ForeignOwnable},
+};
+use alloc::boxed::Box;
+use core::fmt;
+
+/// A kernel thread running a Rust closure.
+///
+/// The thread is started as soon as it is created. It should periodically check
+/// [`should_stop`] (or [`freezable_should_stop`] for freezable threads) and return once it is
+/// asked to stop. The thread is stopped, waiting for it to return, when the [`Thread`] is dropped
+/// or [`Thread::stop`] is called.
+///
+/// # Invariants
+///
+/// `task` is a kthread created by `kthread_create_on_node` that hasn't been passed to
+/// `kthread_stop` yet.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{fmt, kthread::{self, Thread}, delay};
+/// # use kernel::prelude::*;
+///
+/// fn start_poller() -> Result<Thread> {
+///     Thread::try_new_freezable(fmt!("poller/{}", 0), || {
+///         while !kthread::freezable_should_stop() {
+///             // Poll the hardware...
+///             delay::msleep(100);
+///         }
+///     })
+/// }
+/// ```
+pub struct Thread {
+    task: ARef<Task>,
+}
+
+impl Thread {
+    /// Creates and starts a new kernel thread named `name` that runs `f`.
+    ///
+    /// Note that if the thread is stopped before it gets a chance to run, `f` is never called and
+    /// is leaked along with everything it captured.
+    pub fn try_new<F>(name: fmt::Arguments<'_>, f: F) -> Result<Self>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        // `kthread_create_on_node` formats the name itself, so pass it pre-formatted as `%s`.
+        let name = CString::try_from_fmt(name)?;
+        let data = Box::try_new(f)?.into_foreign();
+
+        // SAFETY: `bridge::<F>` matches the signature expected by kthreads and `data` came from
+        // `into_foreign` above. `name` is valid for the duration of the call, which copies it.
+        let task = from_err_ptr(unsafe {
+            bindings::kthread_create_on_node(
+                Some(bridge::<F>),
+                data as _,
+                bindings::NUMA_NO_NODE,
+                b"%s\0".as_ptr().cast(),
+                name.as_char_ptr(),
+            )
+        });
+        let task = match task {
+            Ok(t) => t,
+            Err(e) => {
+                // SAFETY: The thread wasn't created, so `data` is still owned by us.
+                drop(unsafe { Box::<F>::from_foreign(data) });
+                return Err(e);
+            }
+        };
+
+        // SAFETY: `task` is a valid, newly-created task. Taking a reference keeps it alive past
+        // the end of the thread function, so that it can still be passed to `kthread_stop`.
+        let task: ARef<Task> = unsafe { &*task.cast::<Task>() }.into();
+        task.wake_up();
+
+        // INVARIANT: The thread was just created and not stopped yet.
+        Ok(Self { task })
+    }
+
+    /// Like [`Thread::try_new`], but the thread is marked as freezable before `f` runs.
+    ///
+    /// Freezable threads are frozen during system suspend, at the points where they call
+    /// [`freezable_should_stop`] or [`freezer::try_to_freeze`]. Threads that access hardware
+    /// should usually be freezable so that they don't touch devices while they are suspended.
+    pub fn try_new_freezable<F>(name: fmt::Arguments<'_>, f: F) -> Result<Self>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        Self::try_new(name, move || {
+            freezer::set_freezable();
+            f()
+        })
+    }
+
+    /// Returns the task the thread runs on.
+    pub fn task(&self) -> &Task {
+        &self.task
+    }
+
+    /// Asks the thread to stop and waits for it to exit.
+    ///
+    /// Returns the exit code of the thread, which is `-EINTR` if it never ran.
+    pub fn stop(self) -> i32 {
+        let this = core::mem::ManuallyDrop::new(self);
+        // SAFETY: By the type invariants, the task is a kthread that hasn't been stopped yet, and
+        // it is still valid since we hold a reference to it.
+        let ret = unsafe { bindings::kthread_stop(this.task.0.get()) };
+        // SAFETY: `this` is never used again.
+        drop(unsafe { core::ptr::read(&this.task) });
+        ret
+    }
+}
+
+impl Drop for Thread {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, the task is a kthread that hasn't been stopped yet.
+        unsafe { bindings::kthread_stop(self.task.0.get()) };
+    }
+}
+
+unsafe extern "C" fn bridge<F: FnOnce() + Send + 'static>(data: *mut core::ffi::c_void) -> i32 {
+    // SAFETY: `data` was returned by `into_foreign` in `Thread::try_new` and, since the thread
+    // function only runs once, this is the only call to `from_foreign` for it.
+    let f = unsafe { Box::<F>::from_foreign(data) };
+    f();
+    0
+}
+
+/// Returns `true` if the current kthread has been asked to stop.
+///
+/// Must only be called from a kthread.
+pub fn should_stop() -> bool {
+    // SAFETY: FFI call with no additional requirements.
+    unsafe { bindings::kthread_should_stop() }
+}
+
+/// Freezes the current kthread if a freeze is pending, then returns `true` if it has been asked to
+/// stop.
+///
+/// This is the freezer-aware variant of [`should_stop`], to be used by threads created with
+/// [`Thread::try_new_freezable`].
+pub fn freezable_should_stop() -> bool {
+    // SAFETY: FFI call; a null `was_frozen` is allowed.
+    unsafe { bindings::kthread_freezable_should_stop(core::ptr::null_mut()) }
+}
@@ -0,0 +1,168 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! LED class devices.
+//!
+//! A driver exposes each LED of its device, or any other output with a brightness, e.g. a
+//! vibration motor, as a class device in `/sys/class/leds` by registering a [`Registration`].
+//! User space and triggers set the brightness through the [`Operations`] of the LED.
+//!
+//! Outputs that are turned on for a given time, e.g. vibrators, use the `transient` trigger as
+//! their default trigger, which replaces the `timed_output` class of Android.
+//!
+//! C header: [`include/linux/leds.h`](../../../../include/linux/leds.h)
+
+use crate::{
+    bindings,
+    device::Device,
+    error::{from_result, to_result, Error, Result},
+    init::{self, PinInit},
+    str::{CStr, CString},
+    types::Opaque,
+};
+use core::{ffi::c_int, marker::PhantomPinned, ptr};
+
+/// The operations of an LED.
+pub trait Operations: Sync {
+    /// Sets the brightness of the LED, from zero, which turns it off, to its maximum brightness.
+    ///
+    /// This runs in process context, and may sleep.
+    fn brightness_set(&self, brightness: u32) -> Result;
+}
+
+/// The configuration of an LED, used by [`Registration::register`].
+#[derive(Debug)]
+pub struct Config {
+    /// The name of the LED, usually `devicename:color:function`, which may be built at runtime.
+    pub name: CString,
+    /// The maximum brightness of the LED.
+    pub max_brightness: u32,
+    /// The trigger that is activated when the LED is registered, if any.
+    pub default_trigger: Option<&'static CStr>,
+}
+
+/// A registered LED class device, the kernel's `struct led_classdev`.
+///
+/// The LED is turned off and unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `cdev` is registered, with [`Registration::brightness_set_callback`] as its blocking
+/// brightness callback, and `name` as its name.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{device::Device, fmt, leds, prelude::*, str::CString};
+/// use core::sync::atomic::{AtomicU32, Ordering};
+///
+/// struct Backlight {
+///     level: AtomicU32,
+/// }
+///
+/// impl leds::Operations for Backlight {
+///     fn brightness_set(&self, brightness: u32) -> Result {
+///         self.level.store(brightness, Ordering::Relaxed);
+///         Ok(())
+///     }
+/// }
+///
+/// fn register(dev: &Device) -> Result<Pin<Box<leds::Registration<Backlight>>>> {
+///     let config = leds::Config {
+///         name: CString::try_from_fmt(fmt!("keyboard:white:backlight"))?,
+///         max_brightness: 15,
+///         default_trigger: None,
+///     };
+///     let ops = Backlight {
+///         level: AtomicU32::new(0),
+///     };
+///     Box::pin_init(leds::Registration::register(dev, config, ops))
+/// }
+/// ```
+#[repr(C)]
+pub struct Registration<T: Operations> {
+    cdev: Opaque<bindings::led_classdev>,
+    ops: T,
+    name: CString,
+    _pin: PhantomPinned,
+}
+
+impl<T: Operations> Registration<T> {
+    /// Returns an initialiser that registers an LED of `parent` with the configuration `config`
+    /// and the operations `ops`.
+    ///
+    /// The LED is turned off when the system suspends.
+    pub fn register(parent: &Device, config: Config, ops: T) -> impl PinInit<Self, Error> {
+        let parent = parent.as_raw();
+        // SAFETY: The closure initialises all fields on success, and drops those it initialised
+        // on failure. The registration isn't moved once the LED is registered, since it is
+        // pinned.
+        unsafe {
+            init::pin_init_from_closure::<_, Error>(move |slot: *mut Self| {
+                crate::might_sleep!();
+                // The operations are written before registering the LED, since a trigger may
+                // set its brightness as soon as it is registered.
+                let ops_ptr = ptr::addr_of_mut!((*slot).ops);
+                ops_ptr.write(ops);
+                let name_ptr = ptr::addr_of_mut!((*slot).name);
+                name_ptr.write(config.name);
+                let cdev = Opaque::raw_get(ptr::addr_of!((*slot).cdev));
+                cdev.write(bindings::led_classdev {
+                    name: (*name_ptr).as_char_ptr(),
+                    max_brightness: config.max_brightness,
+                    default_trigger: config
+                        .default_trigger
+                        .map_or(ptr::null(), |trigger| trigger.as_char_ptr()),
+                    flags: bindings::LED_CORE_SUSPENDRESUME as _,
+                    brightness_set_blocking: Some(Self::brightness_set_callback),
+                    // SAFETY: All other fields are optional, for which zero is valid.
+                    ..core::mem::MaybeUninit::zeroed().assume_init()
+                });
+                // The parent outlives the LED, which is unregistered when its driver is unbound.
+                if let Err(e) = to_result(bindings::led_classdev_register_ext(
+                    parent,
+                    cdev,
+                    ptr::null_mut(),
+                )) {
+                    ptr::drop_in_place(ops_ptr);
+                    ptr::drop_in_place(name_ptr);
+                    return Err(e);
+                }
+                // INVARIANT: The LED was registered with `brightness_set_callback`.
+                Ok(())
+            })
+        }
+    }
+
+    /// Returns the operations of the LED.
+    pub fn ops(&self) -> &T {
+        &self.ops
+    }
+
+    unsafe extern "C" fn brightness_set_callback(
+        cdev: *mut bindings::led_classdev,
+        brightness: bindings::led_brightness,
+    ) -> c_int {
+        // SAFETY: The class device is the first field of `Self`, which is `repr(C)`, and it is
+        // registered until `Self` is dropped.
+        let this = unsafe { &*cdev.cast::<Self>() };
+        from_result(|| {
+            this.ops.brightness_set(brightness as _)?;
+            Ok(0)
+        })
+    }
+}
+
+impl<T: Operations> Drop for Registration<T> {
+    fn drop(&mut self) {
+        // SAFETY: The LED is registered by the type invariants. Unregistering it turns it off
+        // through the operations, which, like the name, are only dropped afterwards.
+        unsafe { bindings::led_classdev_unregister(self.cdev.get()) };
+    }
+}
+
+// SAFETY: The LED can be unregistered from any thread, and the operations are only accessed by
+// shared reference.
+unsafe impl<T: Operations + Send> Send for Registration<T> {}
+
+// SAFETY: The registration only gives shared access to the operations, which are `Sync`.
+unsafe impl<T: Operations> Sync for Registration<T> {}
@@ -14,6 +14,7 @@
 #![no_std]
 #![feature(allocator_api)]
 #![feature(coerce_unsized)]
+#![feature(const_maybe_uninit_zeroed)]
 #![feature(dispatch_from_dyn)]
 #![feature(new_uninit)]
 #![feature(receiver_trait)]
@@ -30,19 +31,156 @@
 #[cfg(not(test))]
 #[cfg(not(testlib))]
 mod allocator;
+#[cfg(CONFIG_BACKLIGHT_CLASS_DEVICE)]
+pub mod backlight;
+pub mod barrier;
+pub mod bitmap;
+#[cfg(CONFIG_BLOCK)]
+pub mod block;
+pub mod buffer;
 mod build_assert;
+pub mod cache;
+#[cfg(any(CONFIG_CRC32, CONFIG_CRC16, CONFIG_CRC_ITU_T, CONFIG_CRC_CCITT))]
+pub mod checksum;
+pub mod class;
+#[cfg(CONFIG_GENERIC_CLOCKEVENTS)]
+pub mod clockevents;
+pub mod clocksource;
+pub mod cmdline;
+pub mod collections;
+pub mod console;
+pub mod context;
+#[cfg(CONFIG_CPU_IDLE)]
+pub mod cpuidle;
+pub mod cpumask;
+pub mod cred;
+#[cfg(CONFIG_CRYPTO)]
+pub mod crypto;
+#[cfg(CONFIG_DEBUG_FS)]
+pub mod debugfs;
+pub mod delay;
+#[cfg(CONFIG_PM_DEVFREQ)]
+pub mod devfreq;
+pub mod device;
+pub mod dma;
+#[cfg(CONFIG_DRM)]
+pub mod drm;
+#[cfg(CONFIG_EFI)]
+pub mod efi;
+pub mod endian;
 pub mod error;
+#[cfg(CONFIG_EXTCON)]
+pub mod extcon;
+pub mod fault_inject;
+#[cfg(CONFIG_FB)]
+pub mod fb;
+pub mod file;
+pub mod firmware;
+pub mod freezer;
+pub mod fs;
+#[cfg(CONFIG_PM_GENERIC_DOMAINS)]
+pub mod genpd;
+#[cfg(CONFIG_GPIOLIB)]
+pub mod gpio;
+pub mod hashtable;
+#[cfg(CONFIG_TEGRA_HOST1X)]
+pub mod host1x;
+#[cfg(CONFIG_HWMON)]
+pub mod hwmon;
+#[cfg(CONFIG_HWSPINLOCK)]
+pub mod hwspinlock;
+#[cfg(CONFIG_I2C)]
+pub mod i2c;
+#[cfg(CONFIG_IIO)]
+pub mod iio;
 pub mod init;
+#[cfg(CONFIG_INPUT)]
+pub mod input;
+pub mod io_buffer;
+#[cfg(CONFIG_HAS_IOMEM)]
+pub mod io_mem;
 pub mod ioctl;
+#[cfg(CONFIG_IOMMU_API)]
+pub mod iommu;
+#[cfg(CONFIG_HAS_IOPORT)]
+pub mod ioport;
+pub mod iov_iter;
+pub mod irq;
+pub mod kfifo;
+#[cfg(CONFIG_PRINTK)]
+pub mod kmsg;
+pub mod kobject;
+#[cfg(CONFIG_KPROBES)]
+pub mod kprobes;
+pub mod kthread;
+#[cfg(CONFIG_LEDS_CLASS)]
+pub mod leds;
+pub mod linked_list;
+pub mod miscdev;
+pub mod mm;
+#[cfg(CONFIG_NET)]
+pub mod net;
+pub mod notifier;
+#[cfg(CONFIG_OF)]
+pub mod of;
+pub mod panic;
+#[cfg(CONFIG_PCI)]
+pub mod pci;
+#[cfg(CONFIG_PERF_EVENTS)]
+pub mod perf_event;
+#[cfg(CONFIG_OF)]
+pub mod platform;
+#[cfg(CONFIG_PM_SLEEP)]
+pub mod pm;
+#[cfg(CONFIG_POWER_SUPPLY)]
+pub mod power_supply;
 pub mod prelude;
 pub mod print;
+#[cfg(CONFIG_PROC_FS)]
+pub mod proc;
+#[cfg(CONFIG_PWM)]
+pub mod pwm;
+pub mod random;
+pub mod rbtree;
+pub mod reboot;
+#[cfg(CONFIG_REGMAP)]
+pub mod regmap;
+#[cfg(CONFIG_REMOTEPROC)]
+pub mod remoteproc;
+#[cfg(CONFIG_RTC_CLASS)]
+pub mod rtc;
+pub mod sched;
+pub mod seq_file;
+#[cfg(CONFIG_SERIAL_CORE)]
+pub mod serial;
+pub mod signal;
+#[cfg(CONFIG_SOC_BUS)]
+pub mod soc;
+#[cfg(CONFIG_SND)]
+pub mod sound;
 mod static_assert;
 #[doc(hidden)]
 pub mod std_vendor;
 pub mod str;
 pub mod sync;
+pub mod syscore;
+#[cfg(CONFIG_SYSCTL)]
+pub mod sysctl;
+pub mod sysfs;
 pub mod task;
+#[cfg(CONFIG_THERMAL)]
+pub mod thermal;
+pub mod trace;
+#[cfg(CONFIG_TTY)]
+pub mod tty;
 pub mod types;
+#[cfg(CONFIG_UIO)]
+pub mod uio;
+#[cfg(CONFIG_USB_SUPPORT)]
+pub mod usb;
+pub mod user_ptr;
+pub mod workqueue;
+pub mod xarray;
 
 #[doc(hidden)]
 pub use bindings;
@@ -85,6 +223,130 @@ impl ThisModule {
     pub const unsafe fn from_ptr(ptr: *mut bindings::module) -> ThisModule {
         ThisModule(ptr)
     }
+
+    /// Returns the `THIS_MODULE` pointer.
+    pub fn as_ptr(&self) -> *mut bindings::module {
+        self.0
+    }
+
+    /// Takes a reference on the module, which prevents it from being unloaded until the returned
+    /// [`ModuleRef`] is dropped.
+    ///
+    /// Returns [`None`] if the module is being unloaded. This is the equivalent of
+    /// `try_module_get(THIS_MODULE)`.
+    pub fn try_get(&self) -> Option<ModuleRef> {
+        // SAFETY: `THIS_MODULE` is valid while code of the module runs, or null for built-in code.
+        unsafe { ModuleRef::try_get_raw(self.0) }
+    }
+}
+
+/// A reference on a module, which can't be unloaded while it exists.
+///
+/// Subsystems that call back into other modules keep one for each module they hold callbacks of,
+/// like the C code keeps the reference taken with `try_module_get` on the `owner` of operations.
+/// The reference is released with `module_put` when this is dropped.
+///
+/// # Invariants
+///
+/// `ptr` is null, for built-in code, or a module that the instance owns a reference on.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{bindings, ModuleRef};
+///
+/// /// Callbacks registered by another module.
+/// struct Ops {
+///     notify: fn(u32),
+///     _owner: ModuleRef,
+/// }
+///
+/// /// # Safety
+/// ///
+/// /// `owner` must be null or a valid module, e.g. the `owner` of a registration that is being
+/// /// added under a lock that the module's unregistration takes too.
+/// unsafe fn register(owner: *mut bindings::module, notify: fn(u32)) -> Option<Ops> {
+///     // SAFETY: By the safety requirements.
+///     let owner = unsafe { ModuleRef::try_get_raw(owner) }?;
+///     Some(Ops {
+///         notify,
+///         _owner: owner,
+///     })
+/// }
+/// ```
+pub struct ModuleRef {
+    ptr: *mut bindings::module,
+}
+
+// SAFETY: Module references can be taken and released from any thread.
+unsafe impl Send for ModuleRef {}
+
+// SAFETY: `ModuleRef` has no methods that take `&self` other than accessors and `clone`, which can
+// be called concurrently.
+unsafe impl Sync for ModuleRef {}
+
+impl ModuleRef {
+    /// Takes a reference on the module `ptr`, which is looked up by pointer, e.g. the `owner` of
+    /// a C structure.
+    ///
+    /// Returns [`None`] if the module is being unloaded. This is the equivalent of
+    /// `try_module_get`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be null or a module that remains valid for the duration of the call.
+    pub unsafe fn try_get_raw(ptr: *mut bindings::module) -> Option<Self> {
+        // SAFETY: By the safety requirements, `ptr` is null or valid, both of which
+        // `try_module_get` accepts.
+        if unsafe { bindings::try_module_get(ptr) } {
+            // INVARIANT: The reference was taken above.
+            Some(Self { ptr })
+        } else {
+            None
+        }
+    }
+
+    /// Creates a [`ModuleRef`] from a reference that was already taken, e.g. by the C side.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be null or a module that the caller owns a reference on, which it hands over to
+    /// the returned [`ModuleRef`].
+    pub unsafe fn from_raw(ptr: *mut bindings::module) -> Self {
+        // INVARIANT: By the safety requirements.
+        Self { ptr }
+    }
+
+    /// Consumes the [`ModuleRef`] without releasing the reference, and returns the module.
+    ///
+    /// The caller then owns the reference, and must release it with `module_put`, or turn it back
+    /// into a [`ModuleRef`] with [`ModuleRef::from_raw`].
+    pub fn into_raw(self) -> *mut bindings::module {
+        core::mem::ManuallyDrop::new(self).ptr
+    }
+
+    /// Returns the raw `struct module` pointer, which is null for built-in code.
+    pub fn as_ptr(&self) -> *mut bindings::module {
+        self.ptr
+    }
+}
+
+impl Clone for ModuleRef {
+    fn clone(&self) -> Self {
+        // SAFETY: By the type invariants, `ptr` is null or a module that we have a reference on,
+        // so it can't be unloaded and another reference can be taken unconditionally.
+        unsafe { bindings::__module_get(self.ptr) };
+        // INVARIANT: The reference was taken above.
+        Self { ptr: self.ptr }
+    }
+}
+
+impl Drop for ModuleRef {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, we own a reference on the module, or `ptr` is null,
+        // which `module_put` ignores.
+        unsafe { bindings::module_put(self.ptr) };
+    }
 }
 
 #[cfg(not(any(testlib, test)))]
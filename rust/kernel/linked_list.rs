@@ -0,0 +1,455 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Intrusive doubly-linked lists.
+//!
+//! This is the Rust counterpart of `struct list_head`: the links are embedded in the entries, so
+//! adding an entry to a [`List`] doesn't allocate memory. The list owns its entries through a
+//! [`ForeignOwnable`] pointer, e.g. a [`Box`], or an [`Arc`] for entries that are reference
+//! counted and also used elsewhere.
+//!
+//! An entry can only be on one list through each of its [`Links`]; entries that are on several
+//! lists at once embed one [`Links`] per list, and implement [`GetLinks`] for a different type
+//! for each of them.
+//!
+//! C header: [`include/linux/list.h`](../../../../include/linux/list.h)
+
+use crate::{sync::Arc, types::ForeignOwnable};
+use alloc::boxed::Box;
+use core::{
+    cell::UnsafeCell,
+    ffi::c_void,
+    marker::PhantomData,
+    ops::Deref,
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Gives access to the [`Links`] of the entries of a list.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{
+///     linked_list::{GetLinks, Links, List},
+///     prelude::*,
+///     sync::Arc,
+/// };
+///
+/// struct Request {
+///     id: u32,
+///     links: Links<Request>,
+/// }
+///
+/// impl GetLinks for Request {
+///     type EntryType = Request;
+///
+///     fn get_links(data: &Request) -> &Links<Request> {
+///         &data.links
+///     }
+/// }
+///
+/// fn queue(list: &mut List<Arc<Request>>, id: u32) -> Result<Arc<Request>> {
+///     let req = Arc::try_new(Request { id, links: Links::new() })?;
+///     // The request was just created, so it isn't on a list yet.
+///     let _ = list.push_back(req.clone());
+///     Ok(req)
+/// }
+///
+/// fn cancel(list: &mut List<Arc<Request>>, id: u32) -> Option<Arc<Request>> {
+///     let mut cursor = list.cursor_front_mut();
+///     while let Some(req) = cursor.current() {
+///         if req.id == id {
+///             return cursor.remove_current();
+///         }
+///         cursor.move_next();
+///     }
+///     None
+/// }
+/// ```
+pub trait GetLinks {
+    /// The type of the entries of the list.
+    type EntryType;
+
+    /// Returns the links of `data` that the list uses.
+    fn get_links(data: &Self::EntryType) -> &Links<Self::EntryType>;
+}
+
+/// A [`GetLinks`] implementation that also specifies how the list owns its entries.
+///
+/// It is implemented for [`Box<T>`] and [`Arc<T>`], for all `T` that implement [`GetLinks`] for
+/// themselves.
+pub trait GetLinksWrapped: GetLinks {
+    /// The pointer through which the list owns its entries.
+    type Wrapped: ForeignOwnable + Deref<Target = Self::EntryType>;
+}
+
+impl<T: GetLinks<EntryType = T>> GetLinks for Box<T> {
+    type EntryType = T;
+
+    fn get_links(data: &T) -> &Links<T> {
+        T::get_links(data)
+    }
+}
+
+impl<T: GetLinks<EntryType = T> + 'static> GetLinksWrapped for Box<T> {
+    type Wrapped = Box<T>;
+}
+
+impl<T: GetLinks<EntryType = T>> GetLinks for Arc<T> {
+    type EntryType = T;
+
+    fn get_links(data: &T) -> &Links<T> {
+        T::get_links(data)
+    }
+}
+
+impl<T: GetLinks<EntryType = T> + 'static> GetLinksWrapped for Arc<T> {
+    type Wrapped = Arc<T>;
+}
+
+/// The pointers of an entry to its neighbours, and to the pointer that owns it.
+struct ListEntry<T> {
+    next: Option<NonNull<T>>,
+    prev: Option<NonNull<T>>,
+    owner: *const c_void,
+}
+
+/// The links of a list entry, the equivalent of an embedded `struct list_head`.
+///
+/// # Invariants
+///
+/// `entry` is only accessed by the list that the entry is on, while `inserted` is set.
+pub struct Links<T> {
+    inserted: AtomicBool,
+    entry: UnsafeCell<ListEntry<T>>,
+}
+
+impl<T> Links<T> {
+    /// Creates the links of an entry that isn't on a list.
+    pub const fn new() -> Self {
+        Self {
+            inserted: AtomicBool::new(false),
+            entry: UnsafeCell::new(ListEntry {
+                next: None,
+                prev: None,
+                owner: ptr::null(),
+            }),
+        }
+    }
+
+    /// Returns whether the entry is on a list.
+    pub fn is_inserted(&self) -> bool {
+        self.inserted.load(Ordering::Relaxed)
+    }
+
+    fn acquire_for_insertion(&self) -> bool {
+        self.inserted
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    fn release_after_removal(&self) {
+        self.inserted.store(false, Ordering::Release);
+    }
+}
+
+impl<T> Default for Links<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// SAFETY: The links are only accessed by the list that the entry is on, which is only accessed
+// through mutable references to modify them.
+unsafe impl<T> Send for Links<T> {}
+
+// SAFETY: Shared references to the links only allow checking whether they are on a list.
+unsafe impl<T> Sync for Links<T> {}
+
+/// An intrusive, circular, doubly-linked list.
+///
+/// # Invariants
+///
+/// `first` is `None` if the list is empty, or otherwise the first entry of the list, whose
+/// entries are linked circularly through their [`Links`], which are all inserted. The list owns
+/// the entries through the [`GetLinksWrapped::Wrapped`] pointers recorded in their links.
+pub struct List<G: GetLinksWrapped> {
+    first: Option<NonNull<G::EntryType>>,
+    _p: PhantomData<G::Wrapped>,
+}
+
+impl<G: GetLinksWrapped> List<G> {
+    /// Creates a new, empty list.
+    pub const fn new() -> Self {
+        Self {
+            first: None,
+            _p: PhantomData,
+        }
+    }
+
+    /// Returns whether the list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.first.is_none()
+    }
+
+    /// Returns the links of the entry at `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be an entry on the list, and the returned reference must not outlive a
+    /// borrow of the list.
+    unsafe fn entry<'a>(ptr: NonNull<G::EntryType>) -> &'a mut ListEntry<G::EntryType> {
+        // SAFETY: The entry is valid, and its links are only accessed by the list, through a
+        // borrow that the returned reference doesn't outlive, by the safety requirements.
+        unsafe { &mut *G::get_links(&*ptr.as_ptr()).entry.get() }
+    }
+
+    /// Links `data` to the end of the list, returning a pointer to it, or gives it back if it's
+    /// already on a list.
+    fn link_back(&mut self, data: G::Wrapped) -> Result<NonNull<G::EntryType>, G::Wrapped> {
+        let links = G::get_links(&data);
+        if !links.acquire_for_insertion() {
+            return Err(data);
+        }
+        let ptr = NonNull::from(&*data);
+        let owner = data.into_foreign();
+        // SAFETY: The entry was just inserted, so its links belong to this list, which is
+        // mutably borrowed. Its neighbours are on the list, by the type invariants.
+        unsafe {
+            let new = Self::entry(ptr);
+            new.owner = owner;
+            match self.first {
+                None => {
+                    new.next = Some(ptr);
+                    new.prev = Some(ptr);
+                    self.first = Some(ptr);
+                }
+                Some(first) => {
+                    let last = Self::entry(first).prev.unwrap_unchecked();
+                    new.next = Some(first);
+                    new.prev = Some(last);
+                    Self::entry(last).next = Some(ptr);
+                    Self::entry(first).prev = Some(ptr);
+                }
+            }
+        }
+        Ok(ptr)
+    }
+
+    /// Adds `data` to the end of the list.
+    ///
+    /// If `data` is already on a list, it is returned in the error.
+    pub fn push_back(&mut self, data: G::Wrapped) -> Result<(), G::Wrapped> {
+        self.link_back(data).map(drop)
+    }
+
+    /// Adds `data` to the start of the list.
+    ///
+    /// If `data` is already on a list, it is returned in the error.
+    pub fn push_front(&mut self, data: G::Wrapped) -> Result<(), G::Wrapped> {
+        // INVARIANT: The entry was added after the last one, so before the first one in a
+        // circular list.
+        self.first = Some(self.link_back(data)?);
+        Ok(())
+    }
+
+    /// Removes the first entry of the list, and returns it, if any.
+    pub fn pop_front(&mut self) -> Option<G::Wrapped> {
+        let first = self.first?;
+        // SAFETY: `first` is on the list.
+        Some(unsafe { self.unlink(first) })
+    }
+
+    /// Removes the entry at `ptr` from the list, and returns it.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be an entry on the list.
+    unsafe fn unlink(&mut self, ptr: NonNull<G::EntryType>) -> G::Wrapped {
+        // SAFETY: `ptr` and its neighbours are on the list, by the safety requirements and the
+        // type invariants.
+        let owner = unsafe {
+            let entry = Self::entry(ptr);
+            let (next, prev) = (entry.next.unwrap_unchecked(), entry.prev.unwrap_unchecked());
+            if next == ptr {
+                self.first = None;
+            } else {
+                Self::entry(prev).next = Some(next);
+                Self::entry(next).prev = Some(prev);
+                if self.first == Some(ptr) {
+                    self.first = Some(next);
+                }
+            }
+            entry.next = None;
+            entry.prev = None;
+            entry.owner
+        };
+        // SAFETY: The entry is valid, as it was on the list.
+        unsafe { G::get_links(&*ptr.as_ptr()) }.release_after_removal();
+        // SAFETY: `owner` was returned by `into_foreign` when the entry was added, and the list
+        // gives up its ownership.
+        unsafe { G::Wrapped::from_foreign(owner) }
+    }
+
+    /// Removes `data` from the list, and returns it, if it was on a list.
+    ///
+    /// # Safety
+    ///
+    /// `data` must be on this list, or on no list, through the links returned by
+    /// [`GetLinks::get_links`].
+    pub unsafe fn remove(&mut self, data: &G::EntryType) -> Option<G::Wrapped> {
+        if !G::get_links(data).is_inserted() {
+            return None;
+        }
+        // SAFETY: `data` is on this list, by the safety requirements.
+        Some(unsafe { self.unlink(NonNull::from(data)) })
+    }
+
+    /// Returns the first entry of the list, if any.
+    pub fn front(&self) -> Option<&G::EntryType> {
+        // SAFETY: The entry is owned by the list, which is borrowed for as long as the reference.
+        self.first.map(|ptr| unsafe { &*ptr.as_ptr() })
+    }
+
+    /// Returns the last entry of the list, if any.
+    pub fn back(&self) -> Option<&G::EntryType> {
+        // SAFETY: The first entry is on the list, as is the one before it, and they are owned by
+        // the list, which is borrowed for as long as the reference.
+        self.first
+            .map(|ptr| unsafe { &*Self::entry(ptr).prev.unwrap_unchecked().as_ptr() })
+    }
+
+    /// Returns a cursor at the first entry of the list.
+    pub fn cursor_front(&self) -> Cursor<'_, G> {
+        Cursor {
+            list: self,
+            cur: self.first,
+        }
+    }
+
+    /// Returns a cursor at the first entry of the list, which can remove entries.
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, G> {
+        CursorMut {
+            cur: self.first,
+            list: self,
+        }
+    }
+
+    /// Returns an iterator over the entries of the list.
+    pub fn iter(&self) -> Iter<'_, G> {
+        Iter {
+            cursor: self.cursor_front(),
+        }
+    }
+
+    /// Returns the entry after `ptr`, or `None` if `ptr` is the last entry.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be an entry on the list.
+    unsafe fn next(&self, ptr: NonNull<G::EntryType>) -> Option<NonNull<G::EntryType>> {
+        // SAFETY: `ptr` is on the list, by the safety requirements.
+        let next = unsafe { Self::entry(ptr).next };
+        if next == self.first {
+            None
+        } else {
+            next
+        }
+    }
+}
+
+impl<G: GetLinksWrapped> Default for List<G> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G: GetLinksWrapped> Drop for List<G> {
+    fn drop(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+}
+
+// SAFETY: The list owns its entries, so it can be sent to another thread if they can.
+unsafe impl<G: GetLinksWrapped> Send for List<G> where G::Wrapped: Send {}
+
+// SAFETY: Shared references to the list only give out shared references to the entries.
+unsafe impl<G: GetLinksWrapped> Sync for List<G> where G::EntryType: Sync {}
+
+/// A cursor over the entries of a [`List`], returned by [`List::cursor_front`].
+pub struct Cursor<'a, G: GetLinksWrapped> {
+    list: &'a List<G>,
+    cur: Option<NonNull<G::EntryType>>,
+}
+
+impl<'a, G: GetLinksWrapped> Cursor<'a, G> {
+    /// Returns the entry at the cursor, or `None` if the cursor is past the end of the list.
+    pub fn current(&self) -> Option<&'a G::EntryType> {
+        // SAFETY: The entry is owned by the list, which is borrowed for `'a`.
+        self.cur.map(|ptr| unsafe { &*ptr.as_ptr() })
+    }
+
+    /// Moves the cursor to the next entry.
+    pub fn move_next(&mut self) {
+        if let Some(cur) = self.cur {
+            // SAFETY: `cur` is on the list.
+            self.cur = unsafe { self.list.next(cur) };
+        }
+    }
+}
+
+/// A cursor over the entries of a [`List`] that can remove entries, returned by
+/// [`List::cursor_front_mut`].
+pub struct CursorMut<'a, G: GetLinksWrapped> {
+    list: &'a mut List<G>,
+    cur: Option<NonNull<G::EntryType>>,
+}
+
+impl<G: GetLinksWrapped> CursorMut<'_, G> {
+    /// Returns the entry at the cursor, or `None` if the cursor is past the end of the list.
+    pub fn current(&mut self) -> Option<&G::EntryType> {
+        // SAFETY: The entry is owned by the list, which is borrowed for as long as the cursor.
+        self.cur.map(|ptr| unsafe { &*ptr.as_ptr() })
+    }
+
+    /// Moves the cursor to the next entry.
+    pub fn move_next(&mut self) {
+        if let Some(cur) = self.cur {
+            // SAFETY: `cur` is on the list.
+            self.cur = unsafe { self.list.next(cur) };
+        }
+    }
+
+    /// Removes the entry at the cursor, if any, and moves the cursor to the next entry.
+    pub fn remove_current(&mut self) -> Option<G::Wrapped> {
+        let cur = self.cur?;
+        // SAFETY: `cur` is on the list.
+        self.cur = unsafe { self.list.next(cur) };
+        // SAFETY: `cur` is on the list.
+        Some(unsafe { self.list.unlink(cur) })
+    }
+}
+
+/// An iterator over the entries of a [`List`], returned by [`List::iter`].
+pub struct Iter<'a, G: GetLinksWrapped> {
+    cursor: Cursor<'a, G>,
+}
+
+impl<'a, G: GetLinksWrapped> Iterator for Iter<'a, G> {
+    type Item = &'a G::EntryType;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cur = self.cursor.current()?;
+        self.cursor.move_next();
+        Some(cur)
+    }
+}
+
+impl<'a, G: GetLinksWrapped> IntoIterator for &'a List<G> {
+    type Item = &'a G::EntryType;
+    type IntoIter = Iter<'a, G>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
@@ -0,0 +1,229 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Miscellaneous devices.
+//!
+//! C header: [`include/linux/miscdevice.h`](../../../../include/linux/miscdevice.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/driver-api/misc_devices.html>
+
+use crate::{
+    bindings,
+    device::Device,
+    error::{to_result, Error, Result},
+    file,
+    init::{self, InPlaceInit, PinInit},
+    str::CString,
+    sysfs::AttributeGroups,
+    types::Opaque,
+    ThisModule,
+};
+use alloc::boxed::Box;
+use core::{fmt, marker::PhantomPinned, mem::MaybeUninit, pin::Pin, ptr};
+
+/// Options which can be used to configure how a misc device is registered.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::{device::Device, file, fmt, miscdev, prelude::*, sysfs::AttributeGroups};
+/// # use kernel::ThisModule;
+/// fn example<T: file::Operations<OpenData = ()>>(
+///     module: &'static ThisModule,
+///     parent: &Device,
+///     groups: &'static AttributeGroups,
+/// ) -> Result<Pin<Box<miscdev::Registration<T>>>> {
+///     miscdev::Options::new()
+///         .mode(0o600)
+///         .parent(parent)
+///         .groups(groups)
+///         .register_new(module, fmt!("sample"), ())
+/// }
+/// ```
+#[derive(Default)]
+pub struct Options<'a> {
+    minor: Option<i32>,
+    mode: Option<u16>,
+    parent: Option<&'a Device>,
+    groups: Option<&'static AttributeGroups>,
+}
+
+impl<'a> Options<'a> {
+    /// Creates new [`Options`] instance with the required fields.
+    pub const fn new() -> Self {
+        Self {
+            minor: None,
+            mode: None,
+            parent: None,
+            groups: None,
+        }
+    }
+
+    /// Sets the minor device number.
+    pub fn minor(&mut self, v: i32) -> &mut Self {
+        self.minor = Some(v);
+        self
+    }
+
+    /// Sets the device mode.
+    ///
+    /// This is usually an octal number and describes who can perform read/write/execute
+    /// operations on the device.
+    pub fn mode(&mut self, m: u16) -> &mut Self {
+        self.mode = Some(m);
+        self
+    }
+
+    /// Sets the device parent.
+    pub fn parent(&mut self, p: &'a Device) -> &mut Self {
+        self.parent = Some(p);
+        self
+    }
+
+    /// Sets the sysfs attribute groups of the device.
+    ///
+    /// The groups are created along with the device, before user space is notified about it.
+    pub fn groups(&mut self, groups: &'static AttributeGroups) -> &mut Self {
+        self.groups = Some(groups);
+        self
+    }
+
+    /// Returns an initialiser that registers a misc device on behalf of `module` using the
+    /// configured options.
+    ///
+    /// The device is registered once the registration is initialised in place, e.g. with
+    /// [`Box::pin_init`](crate::init::InPlaceInit::pin_init), and unregistered when it is dropped.
+    /// `module` can't be unloaded while the device is open.
+    pub fn register<T: file::Operations>(
+        &self,
+        module: &'static ThisModule,
+        name: fmt::Arguments<'_>,
+        open_data: T::OpenData,
+    ) -> impl PinInit<Registration<T>, Error> {
+        let name = CString::try_from_fmt(name);
+        let minor = self.minor.unwrap_or(bindings::MISC_DYNAMIC_MINOR as i32);
+        let mode = self.mode.unwrap_or(0);
+        let parent = self.parent.map_or(ptr::null_mut(), |p| p.as_raw());
+        let groups = self.groups.map_or(ptr::null_mut(), |g| g.as_ptr());
+        // SAFETY: The closure initialises all fields on success, and drops those it initialised
+        // on failure. The registration isn't moved once the device is registered, since it is
+        // pinned.
+        unsafe {
+            init::pin_init_from_closure::<_, Error>(move |slot: *mut Registration<T>| {
+                // We write to `open_data` before registering because as soon as `misc_register`
+                // succeeds, the file can be opened.
+                let name_ptr = ptr::addr_of_mut!((*slot).name);
+                name_ptr.write(name?);
+                let open_data_ptr = ptr::addr_of_mut!((*slot).open_data);
+                open_data_ptr.write(open_data);
+                // The adapter is compatible with `misc_register`.
+                let fops_ptr = ptr::addr_of_mut!((*slot).fops);
+                fops_ptr.write(file::OperationsVtable::<Registration<T>, T>::build_owned(
+                    module,
+                ));
+                let mdev = Opaque::raw_get(ptr::addr_of!((*slot).mdev));
+                // The file operations and the name are owned by the registration, which outlives
+                // the device.
+                mdev.write(bindings::miscdevice {
+                    fops: fops_ptr,
+                    name: (*name_ptr).as_char_ptr(),
+                    minor,
+                    mode,
+                    parent,
+                    groups,
+                    // SAFETY: All other fields are optional or filled in on registration.
+                    ..MaybeUninit::zeroed().assume_init()
+                });
+                if let Err(e) = to_result(bindings::misc_register(mdev)) {
+                    ptr::drop_in_place(name_ptr);
+                    ptr::drop_in_place(open_data_ptr);
+                    return Err(e);
+                }
+                // INVARIANT: The device is only considered initialised if it was registered.
+                Ok(())
+            })
+        }
+    }
+
+    /// Allocates a new registration of a misc device and registers it using the configured
+    /// options.
+    pub fn register_new<T: file::Operations>(
+        &self,
+        module: &'static ThisModule,
+        name: fmt::Arguments<'_>,
+        open_data: T::OpenData,
+    ) -> Result<Pin<Box<Registration<T>>>> {
+        Box::pin_init(self.register(module, name, open_data))
+    }
+}
+
+/// A registration of a miscellaneous device.
+///
+/// It can only be created registered, with [`Options::register`], [`Options::register_new`] or
+/// [`Registration::new_pinned`], and it is unregistered when dropped, so a registration can be
+/// neither registered twice nor used unregistered.
+///
+/// # Invariants
+///
+/// `mdev` is registered, named by `name`, and has `fops` as its file operations.
+#[repr(C)]
+pub struct Registration<T: file::Operations> {
+    // Must be the first field, see `OpenAdapter::convert`.
+    mdev: Opaque<bindings::miscdevice>,
+    fops: bindings::file_operations,
+    name: CString,
+    _pin: PhantomPinned,
+
+    /// Context initialised on construction and made available to all file instances on
+    /// [`file::Operations::open`].
+    open_data: T::OpenData,
+}
+
+impl<T: file::Operations> Registration<T> {
+    /// Registers a miscellaneous device on behalf of `module`.
+    ///
+    /// Returns a pinned heap-allocated representation of the registration.
+    pub fn new_pinned(
+        module: &'static ThisModule,
+        name: fmt::Arguments<'_>,
+        open_data: T::OpenData,
+    ) -> Result<Pin<Box<Self>>> {
+        Options::new().register_new(module, name, open_data)
+    }
+
+    /// Returns the device created for the registration.
+    pub fn device(&self) -> &Device {
+        // SAFETY: By the type invariants, the misc device is registered, so `this_device` is
+        // valid.
+        unsafe { Device::as_ref((*self.mdev.get()).this_device) }
+    }
+}
+
+impl<T: file::Operations> file::OpenAdapter<T::OpenData> for Registration<T> {
+    unsafe fn convert(
+        _inode: *mut bindings::inode,
+        file: *mut bindings::file,
+    ) -> *const T::OpenData {
+        // SAFETY: `misc_open` stores the `struct miscdevice` in `private_data` before calling the
+        // driver's `open`. It is the first field of `Registration`, which is `repr(C)`.
+        let reg = unsafe { (*file).private_data as *const Self };
+        // SAFETY: The registration is alive while its files are open.
+        unsafe { ptr::addr_of!((*reg).open_data) }
+    }
+}
+
+// SAFETY: The only method is `device()`, which returns a device that can be used from any thread,
+// and `open_data` is only shared with the file instances, which `file::Operations` requires to
+// be usable from any thread.
+unsafe impl<T: file::Operations> Sync for Registration<T> {}
+
+// SAFETY: All functions work from any thread. So as long as the `Registration::open_data` is
+// `Send`, so is `Registration<T>`.
+unsafe impl<T: file::Operations> Send for Registration<T> where T::OpenData: Send {}
+
+impl<T: file::Operations> Drop for Registration<T> {
+    /// Removes the registration from the kernel.
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, the device is registered.
+        unsafe { bindings::misc_deregister(self.mdev.get()) };
+    }
+}
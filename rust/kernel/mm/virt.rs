@@ -0,0 +1,233 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Virtual memory areas.
+//!
+//! A driver maps its memory into a process when the process calls `mmap` on one of its files, in
+//! [`file::Operations::mmap`](crate::file::Operations::mmap), which is given the [`Area`] to
+//! fill.
+//!
+//! C header: [`include/linux/mm.h`](../../../../include/linux/mm.h)
+
+use crate::{
+    bindings,
+    error::{code::*, to_result, Result},
+    types::Opaque,
+};
+use core::ptr::{self, NonNull};
+
+/// Flags of virtual memory areas, the kernel's `VM_*` values.
+pub mod flags {
+    /// The area is readable.
+    pub const READ: usize = crate::bindings::VM_READ as _;
+    /// The area is writable.
+    pub const WRITE: usize = crate::bindings::VM_WRITE as _;
+    /// The area is executable.
+    pub const EXEC: usize = crate::bindings::VM_EXEC as _;
+    /// The area is shared with the file, and writes are visible to other mappings.
+    pub const SHARED: usize = crate::bindings::VM_SHARED as _;
+    /// The area may be made readable with `mprotect`.
+    pub const MAYREAD: usize = crate::bindings::VM_MAYREAD as _;
+    /// The area may be made writable with `mprotect`.
+    pub const MAYWRITE: usize = crate::bindings::VM_MAYWRITE as _;
+    /// The area may be made executable with `mprotect`.
+    pub const MAYEXEC: usize = crate::bindings::VM_MAYEXEC as _;
+    /// The area may be shared.
+    pub const MAYSHARE: usize = crate::bindings::VM_MAYSHARE as _;
+    /// The area maps I/O memory.
+    pub const IO: usize = crate::bindings::VM_IO as _;
+    /// The area isn't copied on `fork`.
+    pub const DONTCOPY: usize = crate::bindings::VM_DONTCOPY as _;
+    /// The area can't be grown with `mremap`.
+    pub const DONTEXPAND: usize = crate::bindings::VM_DONTEXPAND as _;
+    /// The area isn't included in core dumps.
+    pub const DONTDUMP: usize = crate::bindings::VM_DONTDUMP as _;
+}
+
+/// A virtual memory area of a process, the kernel's `struct vm_area_struct`.
+///
+/// # Invariants
+///
+/// `vma` is a valid area, which is being set up by `mmap`.
+#[repr(transparent)]
+pub struct Area {
+    vma: Opaque<bindings::vm_area_struct>,
+}
+
+impl Area {
+    /// Creates a reference to the area pointed to by `vma`.
+    ///
+    /// # Safety
+    ///
+    /// `vma` must be valid, and being set up by `mmap`, for the lifetime `'a`, with the mmap lock
+    /// of its process held for writing.
+    pub(crate) unsafe fn from_ptr<'a>(vma: *mut bindings::vm_area_struct) -> &'a mut Self {
+        // SAFETY: `Area` is a transparent wrapper of `vm_area_struct`, and the caller guarantees
+        // that `vma` is valid for `'a`.
+        unsafe { &mut *vma.cast() }
+    }
+
+    fn as_raw(&self) -> *mut bindings::vm_area_struct {
+        self.vma.get()
+    }
+
+    /// Returns the flags of the area, a combination of [`flags`] values.
+    pub fn flags(&self) -> usize {
+        // SAFETY: The area is valid by the type invariants.
+        unsafe { (*self.as_raw()).__bindgen_anon_1.vm_flags as _ }
+    }
+
+    /// Sets the flags in `flags`, e.g. [`flags::DONTCOPY`].
+    pub fn set_flags(&mut self, flags: usize) {
+        // SAFETY: The area is valid, and the mmap lock is held for writing, by the type
+        // invariants.
+        unsafe { bindings::vm_flags_set(self.as_raw(), flags as _) };
+    }
+
+    /// Clears the flags in `flags`, e.g. [`flags::MAYWRITE`] to forbid making a read-only
+    /// mapping writable.
+    pub fn clear_flags(&mut self, flags: usize) {
+        // SAFETY: The area is valid, and the mmap lock is held for writing, by the type
+        // invariants.
+        unsafe { bindings::vm_flags_clear(self.as_raw(), flags as _) };
+    }
+
+    /// Returns the start address of the area.
+    pub fn start(&self) -> usize {
+        // SAFETY: The area is valid by the type invariants.
+        unsafe { (*self.as_raw()).vm_start as _ }
+    }
+
+    /// Returns the end address of the area, which is excluded from it.
+    pub fn end(&self) -> usize {
+        // SAFETY: The area is valid by the type invariants.
+        unsafe { (*self.as_raw()).vm_end as _ }
+    }
+
+    /// Returns the offset in the file that the area maps, in pages.
+    pub fn page_offset(&self) -> usize {
+        // SAFETY: The area is valid by the type invariants.
+        unsafe { (*self.as_raw()).vm_pgoff as _ }
+    }
+
+    /// Maps `buf` in the area, starting at the page of `buf` at the offset of the area.
+    ///
+    /// Fails with `EINVAL` if the area extends past the end of `buf`.
+    pub fn map_user_buffer(&mut self, buf: &UserBuffer) -> Result {
+        // SAFETY: The area is valid by the type invariants, and `buf` was allocated with
+        // `vmalloc_user`. `remap_vmalloc_range` checks that the area is within `buf`, and keeps
+        // references to its pages, so it can be freed while it is mapped.
+        to_result(unsafe {
+            bindings::remap_vmalloc_range(
+                self.as_raw(),
+                buf.ptr.as_ptr().cast(),
+                self.page_offset() as _,
+            )
+        })
+    }
+}
+
+/// A zeroed kernel buffer that can be mapped in processes with [`Area::map_user_buffer`], e.g.
+/// a ring buffer shared with userspace.
+///
+/// The buffer is allocated with `vmalloc_user`, and freed when this is dropped. Since processes
+/// may write to it at any time, it is only accessed by copies, which the kernel must validate.
+///
+/// # Invariants
+///
+/// `ptr` was allocated by `vmalloc_user` with the size `len`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{file, mm::virt, prelude::*};
+///
+/// struct Ring;
+///
+/// #[vtable]
+/// impl file::Operations for Ring {
+///     type Data = Box<virt::UserBuffer>;
+///     type OpenData = ();
+///
+///     fn open(_context: &(), _file: &file::File) -> Result<Self::Data> {
+///         Ok(Box::try_new(virt::UserBuffer::new(16 * 4096)?)?)
+///     }
+///
+///     fn mmap(buf: &virt::UserBuffer, _file: &file::File, vma: &mut virt::Area) -> Result {
+///         // The ring isn't inherited by children.
+///         vma.set_flags(virt::flags::DONTCOPY);
+///         vma.map_user_buffer(buf)
+///     }
+/// }
+/// ```
+pub struct UserBuffer {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+impl UserBuffer {
+    /// Allocates a zeroed buffer of `len` bytes, rounded up to whole pages.
+    pub fn new(len: usize) -> Result<Self> {
+        crate::might_sleep!();
+        // SAFETY: `vmalloc_user` may be called with any size.
+        let ptr = NonNull::new(unsafe { bindings::vmalloc_user(len as _) }.cast()).ok_or(ENOMEM)?;
+        // INVARIANT: `ptr` was just allocated with the size `len`.
+        Ok(Self { ptr, len })
+    }
+
+    /// Returns the size of the buffer.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether the buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Copies the bytes of the buffer at `offset` to `data`.
+    ///
+    /// Fails with `EINVAL` if the range is out of the buffer.
+    pub fn read(&self, offset: usize, data: &mut [u8]) -> Result {
+        self.check(offset, data.len())?;
+        // SAFETY: The range is within the buffer, which is valid by the type invariants, and
+        // `data` can't be a part of it, since it is only accessed by copies.
+        unsafe {
+            ptr::copy_nonoverlapping(self.ptr.as_ptr().add(offset), data.as_mut_ptr(), data.len())
+        };
+        Ok(())
+    }
+
+    /// Copies `data` to the buffer at `offset`.
+    ///
+    /// Fails with `EINVAL` if the range is out of the buffer.
+    pub fn write(&self, offset: usize, data: &[u8]) -> Result {
+        self.check(offset, data.len())?;
+        // SAFETY: The range is within the buffer, which is valid by the type invariants, and
+        // `data` can't be a part of it, since it is only accessed by copies.
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), self.ptr.as_ptr().add(offset), data.len())
+        };
+        Ok(())
+    }
+
+    fn check(&self, offset: usize, len: usize) -> Result {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.len => Ok(()),
+            _ => Err(EINVAL),
+        }
+    }
+}
+
+impl Drop for UserBuffer {
+    fn drop(&mut self) {
+        // SAFETY: `ptr` was allocated by `vmalloc_user` by the type invariants. The mappings of
+        // the buffer hold references to its pages, which are freed once they are unmapped.
+        unsafe { bindings::vfree(self.ptr.as_ptr().cast()) };
+    }
+}
+
+// SAFETY: The buffer is only accessed by copies, and may be freed from any thread.
+unsafe impl Send for UserBuffer {}
+
+// SAFETY: See above.
+unsafe impl Sync for UserBuffer {}
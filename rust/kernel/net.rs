@@ -0,0 +1,643 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Networking.
+//!
+//! Network device drivers implement [`NetDeviceOperations`] and create a [`Registration`], which
+//! allocates an Ethernet `struct net_device` and registers it with the networking core. Packets
+//! are held in socket buffers, see [`SkBuff`].
+//!
+//! C headers: [`include/linux/netdevice.h`](../../../../include/linux/netdevice.h) and
+//! [`include/linux/etherdevice.h`](../../../../include/linux/etherdevice.h)
+
+use crate::{
+    bindings, device,
+    error::{code::*, from_result, to_result, Result},
+    str::CStr,
+    sync::rcu,
+    types::{ARef, AlwaysRefCounted, ForeignOwnable, Opaque},
+};
+use core::{
+    ffi::{c_int, c_void},
+    marker::PhantomData,
+    ptr::{self, NonNull},
+};
+use macros::vtable;
+
+pub mod ethtool;
+mod namespace;
+pub mod napi;
+#[cfg(CONFIG_PHYLIB)]
+pub mod phy;
+mod skbuff;
+pub mod socket;
+
+pub use namespace::{Namespace, PernetId, PernetOperations, PernetRegistration};
+pub use skbuff::{Checksum, SkBuff, SkBuffRef};
+
+/// The length of an Ethernet hardware address.
+pub const ETH_ALEN: usize = bindings::ETH_ALEN as usize;
+
+/// Returns `true` if `addr` can be assigned to a device, i.e. it is neither a multicast nor the
+/// all-zeroes address, like `is_valid_ether_addr` in C.
+pub fn is_valid_ether_addr(addr: &[u8; ETH_ALEN]) -> bool {
+    addr[0] & 1 == 0 && addr.iter().any(|&b| b != 0)
+}
+
+/// A reference-counted network device, the kernel's `struct net_device`.
+///
+/// # Invariants
+///
+/// Instances of this type are always ref-counted, that is, a call to `dev_hold` ensures that the
+/// allocation remains valid at least until the matching call to `dev_put`.
+#[repr(transparent)]
+pub struct Device(Opaque<bindings::net_device>);
+
+// SAFETY: Network devices are reference-counted and can be released from any thread.
+unsafe impl Send for Device {}
+
+// SAFETY: The methods of `Device` that take `&self` are protected by the synchronisation in the
+// networking core.
+unsafe impl Sync for Device {}
+
+impl Device {
+    /// Creates a reference to a [`Device`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is valid, non-null, and has a non-zero reference count for
+    /// the entire duration when the returned reference exists.
+    pub unsafe fn as_ref<'a>(ptr: *mut bindings::net_device) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct net_device` pointer.
+    pub fn as_raw(&self) -> *mut bindings::net_device {
+        self.0.get()
+    }
+
+    /// Returns the name of the interface, e.g. `eth0`.
+    pub fn name(&self) -> &CStr {
+        // SAFETY: By the type invariants, the device is valid. `name` is always `NUL`-terminated.
+        unsafe { CStr::from_char_ptr(ptr::addr_of!((*self.as_raw()).name).cast()) }
+    }
+
+    /// Returns the network namespace of the device, or `None` if it is being torn down.
+    pub fn net(&self) -> Option<ARef<Namespace>> {
+        let _guard = rcu::read_lock();
+        // SAFETY: By the type invariants, the device is valid. Its namespace is valid while the
+        // device is registered in it, and a namespace is only freed after an RCU grace period
+        // that follows the unregistration or move of all its devices, so it is valid here.
+        let ns = unsafe { bindings::maybe_get_net(bindings::dev_net(self.as_raw())) };
+        // SAFETY: `maybe_get_net` returns either null or the namespace with an incremented
+        // reference count, which is now owned by the `ARef`.
+        NonNull::new(ns).map(|ns| unsafe { ARef::from_raw(ns.cast()) })
+    }
+
+    /// Returns the interface index.
+    pub fn ifindex(&self) -> i32 {
+        // SAFETY: By the type invariants, the device is valid.
+        unsafe { ptr::addr_of!((*self.as_raw()).ifindex).read_volatile() }
+    }
+
+    /// Returns the current MTU.
+    pub fn mtu(&self) -> u32 {
+        // SAFETY: By the type invariants, the device is valid. The MTU can change concurrently, so
+        // it's read once, like `READ_ONCE` in C.
+        unsafe { ptr::addr_of!((*self.as_raw()).mtu).read_volatile() }
+    }
+
+    /// Returns the current hardware address.
+    pub fn mac_addr(&self) -> [u8; ETH_ALEN] {
+        let mut addr = [0; ETH_ALEN];
+        // SAFETY: By the type invariants, the device is valid. Ethernet devices always have an
+        // address of `ETH_ALEN` bytes.
+        unsafe { ptr::copy_nonoverlapping((*self.as_raw()).dev_addr, addr.as_mut_ptr(), ETH_ALEN) };
+        addr
+    }
+
+    /// Reports that the link is up.
+    pub fn carrier_on(&self) {
+        // SAFETY: By the type invariants, the device is valid.
+        unsafe { bindings::netif_carrier_on(self.as_raw()) };
+    }
+
+    /// Reports that the link is down.
+    pub fn carrier_off(&self) {
+        // SAFETY: By the type invariants, the device is valid.
+        unsafe { bindings::netif_carrier_off(self.as_raw()) };
+    }
+
+    /// Returns `true` if the link is up.
+    pub fn carrier_ok(&self) -> bool {
+        // SAFETY: By the type invariants, the device is valid.
+        unsafe { bindings::netif_carrier_ok(self.as_raw()) }
+    }
+
+    /// Allows the networking core to call [`NetDeviceOperations::start_xmit`].
+    pub fn start_queue(&self) {
+        // SAFETY: By the type invariants, the device is valid.
+        unsafe { bindings::netif_start_queue(self.as_raw()) };
+    }
+
+    /// Stops the networking core from calling [`NetDeviceOperations::start_xmit`], e.g. when the
+    /// transmit ring is full.
+    pub fn stop_queue(&self) {
+        // SAFETY: By the type invariants, the device is valid.
+        unsafe { bindings::netif_stop_queue(self.as_raw()) };
+    }
+
+    /// Restarts a stopped transmit queue and schedules pending packets for transmission.
+    pub fn wake_queue(&self) {
+        // SAFETY: By the type invariants, the device is valid.
+        unsafe { bindings::netif_wake_queue(self.as_raw()) };
+    }
+
+    /// Returns `true` if the transmit queue is stopped.
+    pub fn queue_stopped(&self) -> bool {
+        // SAFETY: By the type invariants, the device is valid.
+        unsafe { bindings::netif_queue_stopped(self.as_raw()) }
+    }
+
+    /// Passes a received packet to the networking core.
+    ///
+    /// The packet must have been prepared with [`SkBuff::eth_type_trans`]. Returns `false` if it
+    /// was dropped, e.g. because of congestion. This may be called from any context.
+    pub fn rx(&self, skb: SkBuff) -> bool {
+        // SAFETY: `into_raw` passes our ownership of the buffer to `netif_rx`.
+        unsafe { bindings::netif_rx(skb.into_raw()) == bindings::NET_RX_SUCCESS as c_int }
+    }
+}
+
+// SAFETY: The type invariants guarantee that `Device` is always ref-counted.
+unsafe impl AlwaysRefCounted for Device {
+    fn inc_ref(&self) {
+        // SAFETY: The existence of a shared reference means that the refcount is nonzero.
+        unsafe { bindings::dev_hold(self.as_raw()) };
+    }
+
+    unsafe fn dec_ref(obj: NonNull<Self>) {
+        // SAFETY: The safety requirements guarantee that the refcount is nonzero.
+        unsafe { bindings::dev_put(obj.cast().as_ptr()) };
+    }
+}
+
+/// The result of [`NetDeviceOperations::start_xmit`].
+pub enum NetdevTx {
+    /// The driver took care of the packet.
+    Ok,
+    /// The driver couldn't queue the packet, which is handed back to the networking core to be
+    /// retried later. The queue should have been stopped before returning this.
+    Busy(SkBuff),
+}
+
+/// Interface statistics, the kernel's `struct rtnl_link_stats64`.
+#[repr(transparent)]
+pub struct Stats(bindings::rtnl_link_stats64);
+
+macro_rules! stats_setters {
+    ($($(#[$meta:meta])* $name:ident => $field:ident),* $(,)?) => {
+        impl Stats {
+            $(
+                $(#[$meta])*
+                pub fn $name(&mut self, value: u64) -> &mut Self {
+                    self.0.$field = value;
+                    self
+                }
+            )*
+        }
+    };
+}
+
+stats_setters! {
+    /// Sets the number of received packets.
+    set_rx_packets => rx_packets,
+    /// Sets the number of transmitted packets.
+    set_tx_packets => tx_packets,
+    /// Sets the number of received bytes.
+    set_rx_bytes => rx_bytes,
+    /// Sets the number of transmitted bytes.
+    set_tx_bytes => tx_bytes,
+    /// Sets the number of bad received packets.
+    set_rx_errors => rx_errors,
+    /// Sets the number of packets that couldn't be transmitted.
+    set_tx_errors => tx_errors,
+    /// Sets the number of received packets dropped by the driver, e.g. for lack of memory.
+    set_rx_dropped => rx_dropped,
+    /// Sets the number of packets dropped by the driver on transmission.
+    set_tx_dropped => tx_dropped,
+}
+
+/// Operations of a network device, the kernel's `struct net_device_ops`.
+///
+/// All callbacks except [`NetDeviceOperations::start_xmit`] and
+/// [`NetDeviceOperations::get_stats64`] are called with the RTNL lock held and may sleep.
+#[vtable]
+pub trait NetDeviceOperations: Sized + 'static {
+    /// The driver's data associated with the device.
+    type Data: ForeignOwnable + Send + Sync;
+
+    /// Brings the interface up.
+    ///
+    /// Drivers usually turn on the carrier and start the transmit queue here.
+    fn open(_dev: &Device, _data: <Self::Data as ForeignOwnable>::Borrowed<'_>) -> Result {
+        Ok(())
+    }
+
+    /// Brings the interface down.
+    fn stop(_dev: &Device, _data: <Self::Data as ForeignOwnable>::Borrowed<'_>) -> Result {
+        Ok(())
+    }
+
+    /// Transmits a packet.
+    ///
+    /// This is called in atomic context (with bottom halves disabled) and must not sleep.
+    fn start_xmit(
+        skb: SkBuff,
+        dev: &Device,
+        data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+    ) -> NetdevTx;
+
+    /// Fills in the interface statistics.
+    ///
+    /// This may be called from atomic context.
+    fn get_stats64(
+        _dev: &Device,
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _stats: &mut Stats,
+    ) {
+    }
+
+    /// Prepares the hardware for a new MTU, which is within the range set with
+    /// [`Registration::set_mtu_range`].
+    ///
+    /// The device's MTU is updated when this returns successfully. Without this callback, the MTU
+    /// is changed without involving the driver.
+    fn change_mtu(
+        _dev: &Device,
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _new_mtu: u32,
+    ) -> Result {
+        Err(EOPNOTSUPP)
+    }
+
+    /// Programs a new hardware address into the device.
+    ///
+    /// The address was checked with [`is_valid_ether_addr`], and the device's address is updated
+    /// when this returns successfully. Without this callback, the address is changed without
+    /// involving the driver.
+    fn set_mac_address(
+        _dev: &Device,
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _addr: &[u8; ETH_ALEN],
+    ) -> Result {
+        Err(EOPNOTSUPP)
+    }
+}
+
+/// An Ethernet network device.
+///
+/// The device is unregistered (if it was registered) and freed when this is dropped.
+///
+/// # Invariants
+///
+/// `dev` was allocated by `alloc_etherdev_mqs` with room for a pointer in its private area, which
+/// holds a pointer returned by `T::Data::into_foreign`. `registered` is `true` if and only if
+/// `dev` is registered. If `ns` is set, it is the namespace of `dev` before it was registered.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::net::{self, NetDeviceOperations, NetdevTx, SkBuff};
+/// # use kernel::prelude::*;
+///
+/// struct Dummy;
+///
+/// #[vtable]
+/// impl NetDeviceOperations for Dummy {
+///     type Data = ();
+///
+///     fn open(dev: &net::Device, _data: ()) -> Result {
+///         dev.carrier_on();
+///         dev.start_queue();
+///         Ok(())
+///     }
+///
+///     fn start_xmit(skb: SkBuff, _dev: &net::Device, _data: ()) -> NetdevTx {
+///         skb.consume();
+///         NetdevTx::Ok
+///     }
+/// }
+///
+/// fn probe() -> Result<net::Registration<Dummy>> {
+///     let mut reg = net::Registration::try_new(())?;
+///     reg.random_mac_addr()?;
+///     reg.register()?;
+///     Ok(reg)
+/// }
+/// ```
+pub struct Registration<T: NetDeviceOperations> {
+    dev: NonNull<bindings::net_device>,
+    ns: Option<ARef<Namespace>>,
+    registered: bool,
+    _p: PhantomData<T>,
+}
+
+impl<T: NetDeviceOperations> Registration<T> {
+    /// Allocates a new Ethernet device with the given driver data, but doesn't register it yet.
+    ///
+    /// The device is set up with the defaults of Ethernet devices, and is named `eth%d`.
+    pub fn try_new(data: T::Data) -> Result<Self> {
+        // SAFETY: FFI call with valid arguments.
+        let dev = unsafe {
+            bindings::alloc_etherdev_mqs(core::mem::size_of::<*const c_void>() as _, 1, 1)
+        };
+        let dev = NonNull::new(dev).ok_or(ENOMEM)?;
+        // SAFETY: The device was just allocated with room for a pointer in its private area, and
+        // it isn't registered yet so no callbacks can run.
+        unsafe {
+            (*dev.as_ptr()).netdev_ops = NetDeviceOperationsVtable::<T>::build();
+            bindings::netdev_priv(dev.as_ptr())
+                .cast::<*const c_void>()
+                .write(data.into_foreign());
+        }
+        // INVARIANT: The device was allocated and its private area initialised above.
+        Ok(Self {
+            dev,
+            ns: None,
+            registered: false,
+            _p: PhantomData,
+        })
+    }
+
+    /// Returns the network device.
+    pub fn dev(&self) -> &Device {
+        // SAFETY: The device is valid by the type invariants, and we hold the initial reference.
+        unsafe { Device::as_ref(self.dev.as_ptr()) }
+    }
+
+    fn check_unregistered(&self) -> Result {
+        if self.registered {
+            Err(EBUSY)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Sets the parent of the device in the device model, like `SET_NETDEV_DEV` in C.
+    ///
+    /// Fails with `EBUSY` if the device is already registered.
+    pub fn set_parent(&mut self, parent: &device::Device) -> Result {
+        self.check_unregistered()?;
+        // SAFETY: The device is valid and not registered, so we have exclusive access to it.
+        unsafe { (*self.dev.as_ptr()).dev.parent = parent.as_raw() };
+        Ok(())
+    }
+
+    /// Sets the network namespace that the device is registered in, instead of the initial one.
+    ///
+    /// `dev_net_set` doesn't take a reference to the namespace, so the registration holds on to
+    /// `ns` until the device is freed.
+    ///
+    /// Fails with `EBUSY` if the device is already registered.
+    pub fn set_net(&mut self, ns: ARef<Namespace>) -> Result {
+        self.check_unregistered()?;
+        // SAFETY: The device is valid and not registered, so we have exclusive access to it. `ns`
+        // is kept alive by `self.ns` until the device is freed.
+        unsafe { bindings::dev_net_set(self.dev.as_ptr(), ns.as_raw()) };
+        self.ns = Some(ns);
+        Ok(())
+    }
+
+    /// Sets the hardware address of the device.
+    ///
+    /// Fails with `EBUSY` if the device is already registered, and with `EADDRNOTAVAIL` if the
+    /// address is not a valid unicast address.
+    pub fn set_mac_addr(&mut self, addr: &[u8; ETH_ALEN]) -> Result {
+        self.check_unregistered()?;
+        if !is_valid_ether_addr(addr) {
+            return Err(EADDRNOTAVAIL);
+        }
+        // SAFETY: The device is valid and not registered, so we have exclusive access to it.
+        unsafe { bindings::eth_hw_addr_set(self.dev.as_ptr(), addr.as_ptr()) };
+        Ok(())
+    }
+
+    /// Assigns a random, locally administered hardware address to the device.
+    ///
+    /// Fails with `EBUSY` if the device is already registered.
+    pub fn random_mac_addr(&mut self) -> Result {
+        self.check_unregistered()?;
+        // SAFETY: The device is valid and not registered, so we have exclusive access to it.
+        unsafe { bindings::eth_hw_addr_random(self.dev.as_ptr()) };
+        Ok(())
+    }
+
+    /// Sets the range of MTUs that the device accepts, and makes `max` the current MTU if it is
+    /// lower than the default.
+    ///
+    /// Fails with `EBUSY` if the device is already registered, and with `EINVAL` if the range is
+    /// empty.
+    pub fn set_mtu_range(&mut self, min: u32, max: u32) -> Result {
+        self.check_unregistered()?;
+        if min > max {
+            return Err(EINVAL);
+        }
+        let dev = self.dev.as_ptr();
+        // SAFETY: The device is valid and not registered, so we have exclusive access to it.
+        unsafe {
+            (*dev).min_mtu = min;
+            (*dev).max_mtu = max;
+            (*dev).mtu = (*dev).mtu.clamp(min, max);
+        }
+        Ok(())
+    }
+
+    /// Sets the ethtool operations of the device.
+    ///
+    /// Fails with `EBUSY` if the device is already registered.
+    pub fn set_ethtool_ops(&mut self) -> Result
+    where
+        T: ethtool::EthtoolOperations,
+    {
+        self.check_unregistered()?;
+        // SAFETY: The device is valid and not registered, so we have exclusive access to it.
+        unsafe {
+            (*self.dev.as_ptr()).ethtool_ops = ethtool::EthtoolOperationsVtable::<T>::build()
+        };
+        Ok(())
+    }
+
+    /// Registers the device with the networking core, which makes it visible to user space.
+    ///
+    /// Fails with `EINVAL` if the device is already registered.
+    pub fn register(&mut self) -> Result {
+        if self.registered {
+            return Err(EINVAL);
+        }
+        crate::might_sleep!();
+        // SAFETY: The device is valid and fully set up.
+        to_result(unsafe { bindings::register_netdev(self.dev.as_ptr()) })?;
+        // INVARIANT: The device was registered above.
+        self.registered = true;
+        Ok(())
+    }
+}
+
+impl<T: NetDeviceOperations> Drop for Registration<T> {
+    fn drop(&mut self) {
+        let dev = self.dev.as_ptr();
+        if self.registered {
+            // SAFETY: The device is registered by the type invariants. Once this returns, no more
+            // callbacks can run.
+            unsafe { bindings::unregister_netdev(dev) };
+        }
+        // SAFETY: The private area holds a pointer returned by `into_foreign` by the type
+        // invariants, and the device isn't registered anymore, so nothing else uses it.
+        unsafe { T::Data::from_foreign(bindings::netdev_priv(dev).cast::<*const c_void>().read()) };
+        // SAFETY: The device was allocated by `alloc_etherdev_mqs` and is not registered. `ns`
+        // is only released after this.
+        unsafe { bindings::free_netdev(dev) };
+    }
+}
+
+// SAFETY: The registration owns the device and its data, which is `Send`, and both can be released
+// from any thread.
+unsafe impl<T: NetDeviceOperations> Send for Registration<T> {}
+
+// SAFETY: Shared references to the registration only give access to the device, which is `Sync`.
+unsafe impl<T: NetDeviceOperations> Sync for Registration<T> {}
+
+struct NetDeviceOperationsVtable<T>(PhantomData<T>);
+
+impl<T: NetDeviceOperations> NetDeviceOperationsVtable<T> {
+    /// Returns the device and driver data of a device allocated by [`Registration`].
+    ///
+    /// # Safety
+    ///
+    /// `dev` must be a device allocated by a [`Registration<T>`] that is still alive.
+    unsafe fn get<'a>(
+        dev: *mut bindings::net_device,
+    ) -> (&'a Device, <T::Data as ForeignOwnable>::Borrowed<'a>) {
+        // SAFETY: The caller guarantees that `dev` is valid and that its private area holds a
+        // pointer returned by `T::Data::into_foreign`, which is only freed after the device is
+        // unregistered.
+        unsafe {
+            let data = T::Data::borrow(bindings::netdev_priv(dev).cast::<*const c_void>().read());
+            (Device::as_ref(dev), data)
+        }
+    }
+
+    unsafe extern "C" fn open_callback(dev: *mut bindings::net_device) -> c_int {
+        from_result(|| {
+            // SAFETY: The networking core only calls this for registered devices.
+            let (dev, data) = unsafe { Self::get(dev) };
+            T::open(dev, data)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn stop_callback(dev: *mut bindings::net_device) -> c_int {
+        from_result(|| {
+            // SAFETY: The networking core only calls this for registered devices.
+            let (dev, data) = unsafe { Self::get(dev) };
+            T::stop(dev, data)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn start_xmit_callback(
+        skb: *mut bindings::sk_buff,
+        dev: *mut bindings::net_device,
+    ) -> bindings::netdev_tx_t {
+        // SAFETY: The networking core only calls this for registered devices.
+        let (dev, data) = unsafe { Self::get(dev) };
+        // SAFETY: The networking core passes ownership of `skb` to the driver.
+        let skb = unsafe { SkBuff::from_raw(skb) };
+        match T::start_xmit(skb, dev, data) {
+            NetdevTx::Ok => bindings::netdev_tx_NETDEV_TX_OK,
+            NetdevTx::Busy(skb) => {
+                // The networking core keeps ownership of the buffer when the driver is busy.
+                skb.into_raw();
+                bindings::netdev_tx_NETDEV_TX_BUSY
+            }
+        }
+    }
+
+    unsafe extern "C" fn get_stats64_callback(
+        dev: *mut bindings::net_device,
+        stats: *mut bindings::rtnl_link_stats64,
+    ) {
+        // SAFETY: The networking core only calls this for registered devices.
+        let (dev, data) = unsafe { Self::get(dev) };
+        // SAFETY: `stats` is valid and exclusively ours for the duration of the call, and `Stats`
+        // is transparent over `struct rtnl_link_stats64`.
+        T::get_stats64(dev, data, unsafe { &mut *stats.cast() });
+    }
+
+    unsafe extern "C" fn change_mtu_callback(
+        dev: *mut bindings::net_device,
+        new_mtu: c_int,
+    ) -> c_int {
+        from_result(|| {
+            let raw = dev;
+            // SAFETY: The networking core only calls this for registered devices.
+            let (dev, data) = unsafe { Self::get(dev) };
+            T::change_mtu(dev, data, new_mtu as u32)?;
+            // SAFETY: The RTNL lock is held, so we can update the MTU. Readers may access it
+            // concurrently, so it's written once, like `WRITE_ONCE` in C.
+            unsafe { ptr::addr_of_mut!((*raw).mtu).write_volatile(new_mtu as u32) };
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn set_mac_address_callback(
+        dev: *mut bindings::net_device,
+        addr: *mut c_void,
+    ) -> c_int {
+        from_result(|| {
+            let raw = dev;
+            // SAFETY: `addr` points to a `struct sockaddr`, and `eth_prepare_mac_addr_change`
+            // checks that the device can change its address now and that the address is valid.
+            to_result(unsafe { bindings::eth_prepare_mac_addr_change(raw, addr) })?;
+            // SAFETY: `addr` points to a `struct sockaddr`, whose data holds at least
+            // `ETH_ALEN` bytes.
+            let mac =
+                unsafe { &*ptr::addr_of!((*addr.cast::<bindings::sockaddr>()).sa_data).cast() };
+            // SAFETY: The networking core only calls this for registered devices.
+            let (dev, data) = unsafe { Self::get(dev) };
+            T::set_mac_address(dev, data, mac)?;
+            // SAFETY: The RTNL lock is held, and the address was validated above.
+            unsafe { bindings::eth_commit_mac_addr_change(raw, addr) };
+            Ok(0)
+        })
+    }
+
+    const VTABLE: bindings::net_device_ops = bindings::net_device_ops {
+        ndo_open: Some(Self::open_callback),
+        ndo_stop: Some(Self::stop_callback),
+        ndo_start_xmit: Some(Self::start_xmit_callback),
+        ndo_get_stats64: if T::HAS_GET_STATS64 {
+            Some(Self::get_stats64_callback)
+        } else {
+            None
+        },
+        ndo_change_mtu: if T::HAS_CHANGE_MTU {
+            Some(Self::change_mtu_callback)
+        } else {
+            None
+        },
+        ndo_set_mac_address: if T::HAS_SET_MAC_ADDRESS {
+            Some(Self::set_mac_address_callback)
+        } else {
+            Some(bindings::eth_mac_addr)
+        },
+        ndo_validate_addr: Some(bindings::eth_validate_addr),
+        // SAFETY: All other fields are optional callbacks, for which `None` (all zeroes) is valid.
+        ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+    };
+
+    const fn build() -> &'static bindings::net_device_ops {
+        &Self::VTABLE
+    }
+}
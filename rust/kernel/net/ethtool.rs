@@ -0,0 +1,407 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Ethtool support for network drivers.
+//!
+//! Drivers implement [`EthtoolOperations`] on their [`NetDeviceOperations`] type and call
+//! [`Registration::set_ethtool_ops`] before registering the device.
+//!
+//! C header: [`include/linux/ethtool.h`](../../../../include/linux/ethtool.h)
+//!
+//! [`Registration::set_ethtool_ops`]: super::Registration::set_ethtool_ops
+
+use super::{Device, NetDeviceOperations, NetDeviceOperationsVtable};
+use crate::{
+    bindings,
+    error::{code::*, from_result, Result},
+    str::CStr,
+    types::ForeignOwnable,
+};
+use core::{
+    ffi::{c_char, c_int, c_ulong},
+    marker::PhantomData,
+    slice,
+};
+use macros::vtable;
+
+/// Copies `src` into the fixed-size C string `dst`, truncating it if needed.
+fn copy_str(dst: &mut [c_char], src: &CStr) {
+    let len = src.len().min(dst.len() - 1);
+    for (d, s) in dst.iter_mut().zip(&src.as_bytes()[..len]) {
+        *d = *s as c_char;
+    }
+    dst[len] = 0;
+}
+
+/// Driver information reported by `ethtool -i`, the kernel's `struct ethtool_drvinfo`.
+///
+/// Strings that don't fit are truncated.
+#[repr(transparent)]
+pub struct DrvInfo(bindings::ethtool_drvinfo);
+
+impl DrvInfo {
+    /// Sets the name of the driver.
+    ///
+    /// If this is not set, the name of the driver bound to the parent device is used.
+    pub fn set_driver(&mut self, name: &CStr) -> &mut Self {
+        copy_str(&mut self.0.driver, name);
+        self
+    }
+
+    /// Sets the version of the driver.
+    pub fn set_version(&mut self, version: &CStr) -> &mut Self {
+        copy_str(&mut self.0.version, version);
+        self
+    }
+
+    /// Sets the version of the firmware of the device.
+    pub fn set_fw_version(&mut self, version: &CStr) -> &mut Self {
+        copy_str(&mut self.0.fw_version, version);
+        self
+    }
+
+    /// Sets the location of the device on its bus, e.g. its PCI address.
+    ///
+    /// If this is not set, the name of the parent device is used.
+    pub fn set_bus_info(&mut self, info: &CStr) -> &mut Self {
+        copy_str(&mut self.0.bus_info, info);
+        self
+    }
+}
+
+/// The duplex mode of a link.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Duplex {
+    /// Half duplex.
+    Half,
+    /// Full duplex.
+    Full,
+    /// Unknown, e.g. because the link is down.
+    Unknown,
+}
+
+/// Link settings reported by `ethtool` and changed by `ethtool -s`, the kernel's
+/// `struct ethtool_link_ksettings`.
+///
+/// Link modes are identified by their `ETHTOOL_LINK_MODE_*_BIT` index.
+#[repr(transparent)]
+pub struct LinkSettings(bindings::ethtool_link_ksettings);
+
+const BITS_PER_LONG: u32 = c_ulong::BITS;
+
+fn set_mode(map: &mut [c_ulong], bit: u32) {
+    if let Some(word) = map.get_mut((bit / BITS_PER_LONG) as usize) {
+        *word |= 1 << (bit % BITS_PER_LONG);
+    }
+}
+
+fn test_mode(map: &[c_ulong], bit: u32) -> bool {
+    map.get((bit / BITS_PER_LONG) as usize)
+        .map_or(false, |word| word & (1 << (bit % BITS_PER_LONG)) != 0)
+}
+
+impl LinkSettings {
+    /// Returns the speed of the link in Mb/s, or `None` if it is unknown.
+    pub fn speed(&self) -> Option<u32> {
+        match self.0.base.speed {
+            bindings::SPEED_UNKNOWN => None,
+            speed => Some(speed),
+        }
+    }
+
+    /// Sets the speed of the link in Mb/s, `None` meaning unknown.
+    pub fn set_speed(&mut self, speed: Option<u32>) -> &mut Self {
+        self.0.base.speed = speed.unwrap_or(bindings::SPEED_UNKNOWN);
+        self
+    }
+
+    /// Returns the duplex mode of the link.
+    pub fn duplex(&self) -> Duplex {
+        match self.0.base.duplex as u32 {
+            bindings::DUPLEX_HALF => Duplex::Half,
+            bindings::DUPLEX_FULL => Duplex::Full,
+            _ => Duplex::Unknown,
+        }
+    }
+
+    /// Sets the duplex mode of the link.
+    pub fn set_duplex(&mut self, duplex: Duplex) -> &mut Self {
+        self.0.base.duplex = match duplex {
+            Duplex::Half => bindings::DUPLEX_HALF,
+            Duplex::Full => bindings::DUPLEX_FULL,
+            Duplex::Unknown => bindings::DUPLEX_UNKNOWN,
+        } as _;
+        self
+    }
+
+    /// Returns `true` if autonegotiation is enabled.
+    pub fn autoneg(&self) -> bool {
+        self.0.base.autoneg as u32 == bindings::AUTONEG_ENABLE
+    }
+
+    /// Enables or disables autonegotiation.
+    pub fn set_autoneg(&mut self, enable: bool) -> &mut Self {
+        self.0.base.autoneg = if enable {
+            bindings::AUTONEG_ENABLE
+        } else {
+            bindings::AUTONEG_DISABLE
+        } as _;
+        self
+    }
+
+    /// Sets the connector type, a `PORT_*` value.
+    pub fn set_port(&mut self, port: u8) -> &mut Self {
+        self.0.base.port = port;
+        self
+    }
+
+    /// Adds a link mode supported by the device.
+    pub fn add_supported(&mut self, mode: u32) -> &mut Self {
+        set_mode(&mut self.0.link_modes.supported, mode);
+        self
+    }
+
+    /// Adds a link mode advertised by the device.
+    pub fn add_advertising(&mut self, mode: u32) -> &mut Self {
+        set_mode(&mut self.0.link_modes.advertising, mode);
+        self
+    }
+
+    /// Returns `true` if the device is (to be) advertising the given link mode.
+    pub fn is_advertising(&self, mode: u32) -> bool {
+        test_mode(&self.0.link_modes.advertising, mode)
+    }
+}
+
+/// Ethtool operations of a network device, the kernel's `struct ethtool_ops`.
+///
+/// The link state reported by `ethtool` is the carrier state of the device. All callbacks are
+/// called with the RTNL lock held and may sleep.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, net::{self, ethtool}};
+/// # use kernel::prelude::*;
+///
+/// struct Dummy;
+///
+/// #[vtable]
+/// impl net::NetDeviceOperations for Dummy {
+///     type Data = ();
+///
+///     fn start_xmit(skb: net::SkBuff, _dev: &net::Device, _data: ()) -> net::NetdevTx {
+///         skb.consume();
+///         net::NetdevTx::Ok
+///     }
+/// }
+///
+/// #[vtable]
+/// impl ethtool::EthtoolOperations for Dummy {
+///     const STATS_NAMES: &'static [&'static CStr] = &[c_str!("rx_packets"), c_str!("tx_packets")];
+///
+///     fn get_drvinfo(_dev: &net::Device, _data: (), info: &mut ethtool::DrvInfo) {
+///         info.set_driver(c_str!("dummy")).set_version(c_str!("1.0"));
+///     }
+///
+///     fn get_stats(_dev: &net::Device, _data: (), stats: &mut [u64]) {
+///         stats[0] = 0;
+///         stats[1] = 0;
+///     }
+/// }
+///
+/// fn probe() -> Result<net::Registration<Dummy>> {
+///     let mut reg = net::Registration::try_new(())?;
+///     reg.set_ethtool_ops()?;
+///     reg.register()?;
+///     Ok(reg)
+/// }
+/// ```
+#[vtable]
+pub trait EthtoolOperations: NetDeviceOperations {
+    /// The names of the statistics reported by `ethtool -S`, in the order in which
+    /// [`EthtoolOperations::get_stats`] reports them.
+    ///
+    /// Names longer than 31 bytes are truncated.
+    const STATS_NAMES: &'static [&'static CStr] = &[];
+
+    /// Reports information about the driver and device (`ethtool -i`).
+    fn get_drvinfo(
+        _dev: &Device,
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _info: &mut DrvInfo,
+    ) {
+    }
+
+    /// Reports the link settings (`ethtool`).
+    fn get_link_settings(
+        _dev: &Device,
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _settings: &mut LinkSettings,
+    ) -> Result {
+        Err(EOPNOTSUPP)
+    }
+
+    /// Changes the link settings (`ethtool -s`).
+    fn set_link_settings(
+        _dev: &Device,
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _settings: &LinkSettings,
+    ) -> Result {
+        Err(EOPNOTSUPP)
+    }
+
+    /// Restarts autonegotiation (`ethtool -r`).
+    fn nway_reset(_dev: &Device, _data: <Self::Data as ForeignOwnable>::Borrowed<'_>) -> Result {
+        Err(EOPNOTSUPP)
+    }
+
+    /// Reports the statistics (`ethtool -S`).
+    ///
+    /// `stats` has one entry per name in [`EthtoolOperations::STATS_NAMES`].
+    fn get_stats(
+        _dev: &Device,
+        _data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        _stats: &mut [u64],
+    ) {
+    }
+}
+
+pub(super) struct EthtoolOperationsVtable<T>(PhantomData<T>);
+
+impl<T: EthtoolOperations> EthtoolOperationsVtable<T> {
+    unsafe extern "C" fn get_drvinfo_callback(
+        dev: *mut bindings::net_device,
+        info: *mut bindings::ethtool_drvinfo,
+    ) {
+        // SAFETY: The networking core only calls this for registered devices.
+        let (dev, data) = unsafe { NetDeviceOperationsVtable::<T>::get(dev) };
+        // SAFETY: `info` is valid and exclusively ours for the duration of the call, and
+        // `DrvInfo` is transparent over `struct ethtool_drvinfo`.
+        T::get_drvinfo(dev, data, unsafe { &mut *info.cast() });
+    }
+
+    unsafe extern "C" fn get_link_ksettings_callback(
+        dev: *mut bindings::net_device,
+        settings: *mut bindings::ethtool_link_ksettings,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The networking core only calls this for registered devices.
+            let (dev, data) = unsafe { NetDeviceOperationsVtable::<T>::get(dev) };
+            // SAFETY: `settings` is valid and exclusively ours for the duration of the call, and
+            // `LinkSettings` is transparent over `struct ethtool_link_ksettings`.
+            T::get_link_settings(dev, data, unsafe { &mut *settings.cast() })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn set_link_ksettings_callback(
+        dev: *mut bindings::net_device,
+        settings: *const bindings::ethtool_link_ksettings,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The networking core only calls this for registered devices.
+            let (dev, data) = unsafe { NetDeviceOperationsVtable::<T>::get(dev) };
+            // SAFETY: `settings` is valid for the duration of the call, and `LinkSettings` is
+            // transparent over `struct ethtool_link_ksettings`.
+            T::set_link_settings(dev, data, unsafe { &*settings.cast() })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn nway_reset_callback(dev: *mut bindings::net_device) -> c_int {
+        from_result(|| {
+            // SAFETY: The networking core only calls this for registered devices.
+            let (dev, data) = unsafe { NetDeviceOperationsVtable::<T>::get(dev) };
+            T::nway_reset(dev, data)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn get_sset_count_callback(
+        _dev: *mut bindings::net_device,
+        sset: c_int,
+    ) -> c_int {
+        if sset as u32 == bindings::ethtool_stringset_ETH_SS_STATS {
+            T::STATS_NAMES.len() as _
+        } else {
+            EOPNOTSUPP.to_errno()
+        }
+    }
+
+    unsafe extern "C" fn get_strings_callback(
+        _dev: *mut bindings::net_device,
+        sset: u32,
+        buf: *mut u8,
+    ) {
+        if sset != bindings::ethtool_stringset_ETH_SS_STATS {
+            return;
+        }
+        let len = bindings::ETH_GSTRING_LEN as usize;
+        // SAFETY: The core allocates `ETH_GSTRING_LEN` bytes for each of the strings reported by
+        // `get_sset_count_callback`.
+        let buf =
+            unsafe { slice::from_raw_parts_mut(buf.cast::<c_char>(), T::STATS_NAMES.len() * len) };
+        for (name, dst) in T::STATS_NAMES.iter().zip(buf.chunks_exact_mut(len)) {
+            copy_str(dst, name);
+        }
+    }
+
+    unsafe extern "C" fn get_ethtool_stats_callback(
+        dev: *mut bindings::net_device,
+        _stats: *mut bindings::ethtool_stats,
+        buf: *mut u64,
+    ) {
+        // SAFETY: The networking core only calls this for registered devices.
+        let (dev, data) = unsafe { NetDeviceOperationsVtable::<T>::get(dev) };
+        // SAFETY: The core allocates one entry for each of the strings reported by
+        // `get_sset_count_callback`, and zeroes them.
+        let stats = unsafe { slice::from_raw_parts_mut(buf, T::STATS_NAMES.len()) };
+        T::get_stats(dev, data, stats);
+    }
+
+    const VTABLE: bindings::ethtool_ops = bindings::ethtool_ops {
+        get_drvinfo: if T::HAS_GET_DRVINFO {
+            Some(Self::get_drvinfo_callback)
+        } else {
+            None
+        },
+        get_link: Some(bindings::ethtool_op_get_link),
+        get_link_ksettings: if T::HAS_GET_LINK_SETTINGS {
+            Some(Self::get_link_ksettings_callback)
+        } else {
+            None
+        },
+        set_link_ksettings: if T::HAS_SET_LINK_SETTINGS {
+            Some(Self::set_link_ksettings_callback)
+        } else {
+            None
+        },
+        nway_reset: if T::HAS_NWAY_RESET {
+            Some(Self::nway_reset_callback)
+        } else {
+            None
+        },
+        get_sset_count: if T::HAS_GET_STATS {
+            Some(Self::get_sset_count_callback)
+        } else {
+            None
+        },
+        get_strings: if T::HAS_GET_STATS {
+            Some(Self::get_strings_callback)
+        } else {
+            None
+        },
+        get_ethtool_stats: if T::HAS_GET_STATS {
+            Some(Self::get_ethtool_stats_callback)
+        } else {
+            None
+        },
+        // SAFETY: All other fields are optional callbacks or capabilities, for which zero means
+        // "not supported".
+        ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+    };
+
+    pub(super) const fn build() -> &'static bindings::ethtool_ops {
+        &Self::VTABLE
+    }
+}
@@ -0,0 +1,328 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Network namespaces.
+//!
+//! Each network namespace has its own devices, sockets and routing tables. Drivers that need
+//! state per namespace (e.g. virtual devices created in every namespace) implement
+//! [`PernetOperations`] and register it with [`PernetRegistration`].
+//!
+//! C header: [`include/net/net_namespace.h`](../../../../include/net/net_namespace.h)
+
+use super::Device;
+use crate::{
+    bindings,
+    error::{code::*, from_result, to_result, Result},
+    str::CStr,
+    types::{ARef, AlwaysRefCounted, ForeignOwnable, Opaque},
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::{c_int, c_uint, c_void},
+    marker::{PhantomData, PhantomPinned},
+    pin::Pin,
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// A reference-counted network namespace, the kernel's `struct net`.
+///
+/// # Invariants
+///
+/// Instances of this type are always ref-counted, that is, a call to `get_net` ensures that the
+/// namespace remains alive at least until the matching call to `put_net`.
+#[repr(transparent)]
+pub struct Namespace(Opaque<bindings::net>);
+
+// SAFETY: Namespaces are reference-counted and can be released from any thread.
+unsafe impl Send for Namespace {}
+
+// SAFETY: The methods of `Namespace` that take `&self` only use functions that have their own
+// synchronisation.
+unsafe impl Sync for Namespace {}
+
+impl Namespace {
+    /// Creates a reference to a [`Namespace`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is valid, non-null, and has a non-zero reference count for
+    /// the entire duration when the returned reference exists.
+    pub unsafe fn as_ref<'a>(ptr: *mut bindings::net) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct net` pointer.
+    pub fn as_raw(&self) -> *mut bindings::net {
+        self.0.get()
+    }
+
+    /// Returns the initial network namespace, which is never destroyed.
+    pub fn init() -> &'static Self {
+        // SAFETY: `init_net` is statically allocated and its reference count never drops to zero.
+        unsafe { Self::as_ref(ptr::addr_of_mut!(bindings::init_net)) }
+    }
+
+    /// Looks up a network device of the namespace by its name.
+    pub fn dev_get_by_name(&self, name: &CStr) -> Option<ARef<Device>> {
+        // SAFETY: The namespace is valid by the type invariants, and `name` is `NUL`-terminated.
+        let dev = unsafe { bindings::dev_get_by_name(self.as_raw(), name.as_char_ptr()) };
+        // SAFETY: `dev_get_by_name` returns a device with an incremented reference count, which
+        // is now owned by the `ARef`.
+        NonNull::new(dev).map(|dev| unsafe { ARef::from_raw(dev.cast()) })
+    }
+
+    /// Looks up a network device of the namespace by its interface index.
+    pub fn dev_get_by_index(&self, ifindex: i32) -> Option<ARef<Device>> {
+        // SAFETY: The namespace is valid by the type invariants.
+        let dev = unsafe { bindings::dev_get_by_index(self.as_raw(), ifindex) };
+        // SAFETY: `dev_get_by_index` returns a device with an incremented reference count, which
+        // is now owned by the `ARef`.
+        NonNull::new(dev).map(|dev| unsafe { ARef::from_raw(dev.cast()) })
+    }
+}
+
+impl PartialEq for Namespace {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self.as_raw(), other.as_raw())
+    }
+}
+
+impl Eq for Namespace {}
+
+// SAFETY: The type invariants guarantee that `Namespace` is always ref-counted.
+unsafe impl AlwaysRefCounted for Namespace {
+    fn inc_ref(&self) {
+        // SAFETY: The existence of a shared reference means that the refcount is nonzero.
+        unsafe { bindings::get_net(self.as_raw()) };
+    }
+
+    unsafe fn dec_ref(obj: NonNull<Self>) {
+        // SAFETY: The safety requirements guarantee that the refcount is nonzero.
+        unsafe { bindings::put_net(obj.cast().as_ptr()) };
+    }
+}
+
+/// The ID of the per-namespace data of a [`PernetOperations`] implementation.
+///
+/// The networking core allocates the ID when the operations are registered, and stores it here.
+/// Each implementation must have its own, declared as a `static`.
+pub struct PernetId {
+    id: Opaque<c_uint>,
+    in_use: AtomicBool,
+}
+
+// SAFETY: The ID is only written by the networking core while registering the operations, before
+// it is read, and `in_use` is atomic.
+unsafe impl Sync for PernetId {}
+
+impl PernetId {
+    /// Creates a new, unallocated ID.
+    pub const fn new() -> Self {
+        Self {
+            id: Opaque::new(0),
+            in_use: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Default for PernetId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Operations called for each network namespace, the kernel's `struct pernet_operations`.
+///
+/// Both callbacks may sleep, and are called with the `pernet_ops_rwsem` held.
+pub trait PernetOperations: Sized + 'static {
+    /// The data associated with each namespace.
+    type Data: ForeignOwnable + Send + Sync;
+
+    /// Returns the ID of the per-namespace data.
+    ///
+    /// The same ID must be returned every time, and must not be used by other implementations.
+    fn id() -> &'static PernetId;
+
+    /// Sets up a namespace, either when it is created or, for namespaces that already exist,
+    /// when the operations are registered.
+    fn init(ns: &Namespace) -> Result<Self::Data>;
+
+    /// Tears down a namespace, either when it is destroyed or when the operations are
+    /// unregistered.
+    ///
+    /// The namespace's devices may still exist at this point; devices created in
+    /// [`PernetOperations::init`] must be unregistered here.
+    fn exit(_ns: &Namespace, _data: Self::Data) {}
+}
+
+/// A registration of [`PernetOperations`].
+///
+/// The operations are unregistered when this is dropped, which tears down all namespaces with
+/// [`PernetOperations::exit`].
+///
+/// # Invariants
+///
+/// `ops` is registered with the networking core, and `T::id()` holds its ID.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::net::{Namespace, PernetId, PernetOperations, PernetRegistration};
+/// # use kernel::prelude::*;
+///
+/// struct Counter;
+///
+/// static COUNTER_ID: PernetId = PernetId::new();
+///
+/// impl PernetOperations for Counter {
+///     type Data = Box<u32>;
+///
+///     fn id() -> &'static PernetId {
+///         &COUNTER_ID
+///     }
+///
+///     fn init(_ns: &Namespace) -> Result<Box<u32>> {
+///         Ok(Box::try_new(0)?)
+///     }
+/// }
+///
+/// fn count(reg: &PernetRegistration<Counter>) -> Option<u32> {
+///     reg.data(Namespace::init()).map(|count| *count)
+/// }
+///
+/// fn setup() -> Result<PernetRegistration<Counter>> {
+///     PernetRegistration::register()
+/// }
+/// ```
+pub struct PernetRegistration<T: PernetOperations> {
+    ops: Pin<Box<PernetOps>>,
+    _p: PhantomData<T>,
+}
+
+struct PernetOps {
+    ops: Opaque<bindings::pernet_operations>,
+    _pin: PhantomPinned,
+}
+
+impl<T: PernetOperations> PernetRegistration<T> {
+    /// Registers the operations, calling [`PernetOperations::init`] for all existing namespaces.
+    ///
+    /// Fails with `EBUSY` if the ID returned by `T::id()` is already in use.
+    pub fn register() -> Result<Self> {
+        crate::might_sleep!();
+        let id = T::id();
+        if id.in_use.swap(true, Ordering::Acquire) {
+            return Err(EBUSY);
+        }
+
+        let ops = match Box::try_new(PernetOps {
+            ops: Opaque::new(bindings::pernet_operations {
+                init: Some(Self::init_callback),
+                exit: Some(Self::exit_callback),
+                id: id.id.get(),
+                size: core::mem::size_of::<*const c_void>(),
+                // SAFETY: All other fields are optional or private to the networking core, for
+                // which zero is valid.
+                ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+            }),
+            _pin: PhantomPinned,
+        }) {
+            Ok(ops) => Pin::from(ops),
+            Err(e) => {
+                id.in_use.store(false, Ordering::Release);
+                return Err(e.into());
+            }
+        };
+
+        // SAFETY: `ops` is valid and pinned, and is unregistered before it is freed.
+        if let Err(e) = to_result(unsafe { bindings::register_pernet_subsys(ops.ops.get()) }) {
+            id.in_use.store(false, Ordering::Release);
+            return Err(e);
+        }
+        // INVARIANT: The operations were registered above, with `T::id()` as their ID.
+        Ok(Self {
+            ops,
+            _p: PhantomData,
+        })
+    }
+
+    /// Returns the data associated with `ns`.
+    ///
+    /// Returns `None` if [`PernetOperations::init`] failed for `ns`, which can only happen while
+    /// the namespace is being created.
+    pub fn data<'a>(
+        &'a self,
+        ns: &'a Namespace,
+    ) -> Option<<T::Data as ForeignOwnable>::Borrowed<'a>> {
+        // SAFETY: The operations are registered by the type invariants, so `ns` has room for a
+        // pointer at this ID, written by `init_callback`.
+        let ptr = unsafe { Self::slot(ns.as_raw()).read() };
+        if ptr.is_null() {
+            return None;
+        }
+        // SAFETY: The pointer was returned by `into_foreign` in `init_callback`, and it is only
+        // freed by `exit_callback`, which doesn't run before the namespace's refcount drops to
+        // zero or the operations are unregistered.
+        Some(unsafe { T::Data::borrow(ptr) })
+    }
+
+    /// Returns the location of the pointer to the data of `net`.
+    ///
+    /// # Safety
+    ///
+    /// `net` must be valid, and the operations must be registered.
+    unsafe fn slot(net: *mut bindings::net) -> *mut *const c_void {
+        // SAFETY: The caller guarantees that the operations are registered, so the ID is valid,
+        // and the networking core allocated room for a pointer in `net` at this ID.
+        unsafe { bindings::net_generic(net, *T::id().id.get()).cast() }
+    }
+
+    unsafe extern "C" fn init_callback(net: *mut bindings::net) -> c_int {
+        from_result(|| {
+            // SAFETY: The networking core calls this with a valid namespace that holds a
+            // reference, after allocating its data for our ID.
+            let ns = unsafe { Namespace::as_ref(net) };
+            let data = T::init(ns)?;
+            // SAFETY: The data area was allocated before calling this, see above.
+            unsafe { Self::slot(net).write(data.into_foreign()) };
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn exit_callback(net: *mut bindings::net) {
+        // SAFETY: The networking core calls this with a valid namespace, before freeing its data.
+        let slot = unsafe { Self::slot(net) };
+        // SAFETY: See above.
+        let ptr = unsafe { slot.read() };
+        if ptr.is_null() {
+            return;
+        }
+        // SAFETY: The pointer was returned by `into_foreign` in `init_callback`, and the slot is
+        // cleared below so it isn't freed twice.
+        let data = unsafe { T::Data::from_foreign(ptr) };
+        // SAFETY: See above.
+        unsafe { slot.write(ptr::null()) };
+        // SAFETY: The namespace is valid while it is being torn down.
+        T::exit(unsafe { Namespace::as_ref(net) }, data);
+    }
+}
+
+impl<T: PernetOperations> Drop for PernetRegistration<T> {
+    fn drop(&mut self) {
+        // SAFETY: The operations are registered by the type invariants. This calls the exit
+        // callback for all namespaces.
+        unsafe { bindings::unregister_pernet_subsys(self.ops.ops.get()) };
+        let id = T::id();
+        // SAFETY: The ID was released by the networking core, and nothing else uses it until
+        // `in_use` is cleared.
+        unsafe { *id.id.get() = 0 };
+        id.in_use.store(false, Ordering::Release);
+    }
+}
+
+// SAFETY: The operations can be unregistered from any thread, and the data is `Send`.
+unsafe impl<T: PernetOperations> Send for PernetRegistration<T> {}
+
+// SAFETY: Shared references only give access to the data of namespaces, which is `Sync`.
+unsafe impl<T: PernetOperations> Sync for PernetRegistration<T> {}
@@ -0,0 +1,200 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! NAPI, the interrupt mitigation interface of network drivers.
+//!
+//! Instead of processing each received packet in its interrupt handler, a driver masks the
+//! interrupt and calls [`Napi::schedule`]. The networking core then calls
+//! [`Poller::poll`] from softirq context, which processes up to `budget` packets and calls
+//! [`Napi::complete_done`] (and unmasks the interrupt) once it runs out of work.
+//!
+//! C header: [`include/linux/netdevice.h`](../../../../include/linux/netdevice.h)
+
+use super::{Device, SkBuff};
+use crate::{
+    bindings, device,
+    error::Result,
+    types::{ARef, Opaque},
+};
+use alloc::boxed::Box;
+use core::{ffi::c_int, marker::PhantomPinned, pin::Pin, ptr};
+
+/// The poll function of a NAPI instance.
+pub trait Poller: Send + Sync + Sized + 'static {
+    /// Processes up to `budget` received packets (and may also clean up completed transmissions,
+    /// which don't count against the budget), and returns the number of packets processed.
+    ///
+    /// If fewer than `budget` packets were processed, the implementation must call
+    /// [`Napi::complete_done`] before re-enabling its interrupts. If it returns `budget`, it will
+    /// be polled again.
+    ///
+    /// This is called from softirq context and must not sleep. `budget` may be zero, in which
+    /// case no packets must be received.
+    fn poll(napi: &Napi<Self>, budget: u32) -> u32;
+}
+
+/// A NAPI instance, the kernel's `struct napi_struct`.
+///
+/// The instance is removed from its device when this is dropped. It must be disabled before.
+///
+/// # Invariants
+///
+/// `napi` was added to the network device that `_dev` is embedded in, with `poll_callback` as its
+/// poll function. The device memory is kept alive by the `_dev` reference.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::net::{self, napi::{Napi, Poller}};
+/// # use kernel::prelude::*;
+///
+/// struct RxRing;
+///
+/// impl RxRing {
+///     fn receive(&self, napi: &Napi<Self>, budget: u32) -> u32 {
+///         // Pass up to `budget` packets to `napi.gro_receive()`.
+///         0
+///     }
+///
+///     fn unmask_irq(&self) {}
+/// }
+///
+/// impl Poller for RxRing {
+///     fn poll(napi: &Napi<Self>, budget: u32) -> u32 {
+///         let done = napi.poller().receive(napi, budget);
+///         if done < budget && napi.complete_done(done) {
+///             napi.poller().unmask_irq();
+///         }
+///         done
+///     }
+/// }
+///
+/// fn setup(dev: &net::Device) -> Result<Pin<Box<Napi<RxRing>>>> {
+///     let napi = Napi::try_new(dev, RxRing)?;
+///     napi.enable();
+///     Ok(napi)
+/// }
+/// ```
+#[repr(C)]
+pub struct Napi<T: Poller> {
+    // Must be the first field, see `poll_callback`.
+    napi: Opaque<bindings::napi_struct>,
+    _dev: ARef<device::Device>,
+    poller: T,
+    _pin: PhantomPinned,
+}
+
+// SAFETY: NAPI instances have their own synchronisation, and the poller is `Send`.
+unsafe impl<T: Poller> Send for Napi<T> {}
+
+// SAFETY: NAPI instances have their own synchronisation, and the poller is `Sync`.
+unsafe impl<T: Poller> Sync for Napi<T> {}
+
+impl<T: Poller> Napi<T> {
+    /// Creates a NAPI instance for `dev`, initially disabled.
+    pub fn try_new(dev: &Device, poller: T) -> Result<Pin<Box<Self>>> {
+        // SAFETY: `dev` is valid, and so is the `struct device` embedded in it. Holding a
+        // reference to it keeps the memory of the network device alive, without preventing it
+        // from being unregistered.
+        let ddev = unsafe { device::Device::from_raw(ptr::addr_of_mut!((*dev.as_raw()).dev)) };
+        let napi = Pin::from(Box::try_new(Self {
+            // SAFETY: All-zeroes is the expected initial state of a `struct napi_struct`.
+            napi: Opaque::new(unsafe { core::mem::MaybeUninit::zeroed().assume_init() }),
+            _dev: ddev,
+            poller,
+            _pin: PhantomPinned,
+        })?);
+        // SAFETY: `dev` is valid, and `napi` is pinned. `netif_napi_del` is called when it is
+        // dropped, which is fine even if the device was freed first since `free_netdev` already
+        // removes it from the device.
+        unsafe {
+            bindings::netif_napi_add_weight(
+                dev.as_raw(),
+                napi.napi.get(),
+                Some(Self::poll_callback),
+                bindings::NAPI_POLL_WEIGHT as _,
+            )
+        };
+        // INVARIANT: The instance was added to `dev` above.
+        Ok(napi)
+    }
+
+    /// Returns the poller.
+    pub fn poller(&self) -> &T {
+        &self.poller
+    }
+
+    /// Enables polling, usually when the device is opened.
+    pub fn enable(&self) {
+        // SAFETY: The instance is valid by the type invariants.
+        unsafe { bindings::napi_enable(self.napi.get()) };
+    }
+
+    /// Disables polling, waiting for a running poll to complete. This is usually done when the
+    /// device is stopped.
+    pub fn disable(&self) {
+        crate::might_sleep!();
+        // SAFETY: The instance is valid by the type invariants.
+        unsafe { bindings::napi_disable(self.napi.get()) };
+    }
+
+    /// Schedules a poll, usually from the interrupt handler after masking the device's
+    /// interrupts.
+    ///
+    /// Returns `false` if a poll was already scheduled or polling is disabled. This may be called
+    /// from any context.
+    pub fn schedule(&self) -> bool {
+        // SAFETY: The instance is valid by the type invariants.
+        unsafe {
+            if bindings::napi_schedule_prep(self.napi.get()) {
+                bindings::__napi_schedule(self.napi.get());
+                true
+            } else {
+                false
+            }
+        }
+    }
+
+    /// Reports that polling is complete after processing `work_done` packets, which were fewer
+    /// than the budget.
+    ///
+    /// Returns `false` if the instance must keep polling (e.g. because it is busy-polled), in
+    /// which case the device's interrupts must stay masked. Must only be called from
+    /// [`Poller::poll`].
+    pub fn complete_done(&self, work_done: u32) -> bool {
+        // SAFETY: The instance is valid by the type invariants.
+        unsafe { bindings::napi_complete_done(self.napi.get(), work_done as _) }
+    }
+
+    /// Passes a received packet to the networking core, merging it with others of the same flow
+    /// when possible (GRO).
+    ///
+    /// The packet must have been prepared with [`SkBuff::eth_type_trans`]. Must only be called from
+    /// [`Poller::poll`].
+    pub fn gro_receive(&self, skb: SkBuff) {
+        // SAFETY: The instance is valid by the type invariants, and `into_raw` passes our
+        // ownership of the buffer.
+        unsafe { bindings::napi_gro_receive(self.napi.get(), skb.into_raw()) };
+    }
+
+    /// Returns the network device of the instance.
+    pub fn device(&self) -> &Device {
+        // SAFETY: The reference to the embedded `struct device` keeps the network device alive.
+        unsafe { Device::as_ref((*self.napi.get()).dev) }
+    }
+
+    unsafe extern "C" fn poll_callback(napi: *mut bindings::napi_struct, budget: c_int) -> c_int {
+        // SAFETY: `napi` is the first field of a `Napi<T>`, which is `repr(C)`, and is alive while
+        // the instance is added to the device.
+        let this = unsafe { &*(napi as *const Self) };
+        let budget = budget as u32;
+        T::poll(this, budget).min(budget) as _
+    }
+}
+
+impl<T: Poller> Drop for Napi<T> {
+    fn drop(&mut self) {
+        // SAFETY: The instance was added to a device by the type invariants. This is a no-op if
+        // it was already removed by `free_netdev`.
+        unsafe { bindings::netif_napi_del(self.napi.get()) };
+    }
+}
@@ -0,0 +1,666 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Network PHY devices.
+//!
+//! PHY drivers implement [`Driver`] and are registered with [`module_phy_driver!`], which also
+//! emits the MDIO device table used to load the module automatically.
+//!
+//! C headers: [`include/linux/phy.h`](../../../../include/linux/phy.h) and
+//! [`include/linux/mdio.h`](../../../../include/linux/mdio.h)
+//!
+//! [`module_phy_driver!`]: crate::module_phy_driver
+
+use crate::{
+    bindings,
+    error::{code::*, from_result, to_result, Error, Result},
+    str::CStr,
+    types::Opaque,
+    ThisModule,
+};
+use core::{ffi::c_int, marker::PhantomData, pin::Pin};
+use macros::vtable;
+
+/// The state of a PHY device, the kernel's `enum phy_state`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceState {
+    /// The device is being attached, or was detached.
+    Down,
+    /// The device is attached and ready to be started.
+    Ready,
+    /// The device was stopped.
+    Halted,
+    /// The state machine encountered an error.
+    Error,
+    /// The device was started and autonegotiation is being performed.
+    Up,
+    /// The link is up.
+    Running,
+    /// The link is down.
+    NoLink,
+    /// A cable test is running.
+    CableTest,
+}
+
+/// The duplex mode of a link.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DuplexMode {
+    /// Half duplex.
+    Half,
+    /// Full duplex.
+    Full,
+    /// Unknown.
+    Unknown,
+}
+
+/// A PHY device, the kernel's `struct phy_device`.
+///
+/// The callbacks of [`Driver`] receive a mutable reference to the device because they are called
+/// with `phydev->lock` held (or before the device is made available), which serialises them.
+///
+/// # Invariants
+///
+/// The pointer is valid, and the caller holds exclusive access to the device while the reference
+/// exists.
+#[repr(transparent)]
+pub struct Device(Opaque<bindings::phy_device>);
+
+impl Device {
+    /// Creates a new [`Device`] instance from a raw pointer.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for the lifetime `'a`, and the caller must have exclusive access to
+    /// the device (usually by holding `phydev->lock`) for that long.
+    unsafe fn from_raw<'a>(ptr: *mut bindings::phy_device) -> &'a mut Self {
+        // SAFETY: `Self` is transparent over `struct phy_device`, and the safety requirements
+        // guarantee exclusive access for `'a`.
+        unsafe { &mut *ptr.cast() }
+    }
+
+    fn as_raw(&self) -> *mut bindings::phy_device {
+        self.0.get()
+    }
+
+    /// Returns the ID read from the device's ID registers.
+    pub fn phy_id(&self) -> u32 {
+        // SAFETY: The device is valid by the type invariants.
+        unsafe { (*self.as_raw()).phy_id }
+    }
+
+    /// Returns the state of the PHY state machine.
+    pub fn state(&self) -> DeviceState {
+        // SAFETY: The device is valid by the type invariants.
+        match unsafe { (*self.as_raw()).state } {
+            bindings::phy_state_PHY_DOWN => DeviceState::Down,
+            bindings::phy_state_PHY_READY => DeviceState::Ready,
+            bindings::phy_state_PHY_HALTED => DeviceState::Halted,
+            bindings::phy_state_PHY_UP => DeviceState::Up,
+            bindings::phy_state_PHY_RUNNING => DeviceState::Running,
+            bindings::phy_state_PHY_NOLINK => DeviceState::NoLink,
+            bindings::phy_state_PHY_CABLETEST => DeviceState::CableTest,
+            _ => DeviceState::Error,
+        }
+    }
+
+    /// Returns `true` if the link is up.
+    pub fn is_link_up(&self) -> bool {
+        // SAFETY: The device is valid by the type invariants.
+        unsafe { (*self.as_raw()).link() != 0 }
+    }
+
+    /// Sets whether the link is up, usually from [`Driver::read_status`].
+    pub fn set_link(&mut self, up: bool) {
+        // SAFETY: The device is valid, and we have exclusive access to it by the type invariants.
+        unsafe { (*self.as_raw()).set_link(up.into()) };
+    }
+
+    /// Returns `true` if autonegotiation is enabled.
+    pub fn is_autoneg_enabled(&self) -> bool {
+        // SAFETY: The device is valid by the type invariants.
+        unsafe { (*self.as_raw()).autoneg() == bindings::AUTONEG_ENABLE }
+    }
+
+    /// Returns `true` if autonegotiation has completed.
+    pub fn is_autoneg_completed(&self) -> bool {
+        // SAFETY: The device is valid by the type invariants.
+        unsafe { (*self.as_raw()).autoneg_complete() != 0 }
+    }
+
+    /// Sets the speed of the link in Mb/s.
+    pub fn set_speed(&mut self, speed: u32) {
+        // SAFETY: The device is valid, and we have exclusive access to it by the type invariants.
+        unsafe { (*self.as_raw()).speed = speed as _ };
+    }
+
+    /// Sets the duplex mode of the link.
+    pub fn set_duplex(&mut self, mode: DuplexMode) {
+        let duplex = match mode {
+            DuplexMode::Half => bindings::DUPLEX_HALF,
+            DuplexMode::Full => bindings::DUPLEX_FULL,
+            DuplexMode::Unknown => bindings::DUPLEX_UNKNOWN,
+        };
+        // SAFETY: The device is valid, and we have exclusive access to it by the type invariants.
+        unsafe { (*self.as_raw()).duplex = duplex as _ };
+    }
+
+    /// Reads a register of the device over MDIO.
+    pub fn read(&mut self, regnum: u16) -> Result<u16> {
+        let phydev = self.as_raw();
+        // SAFETY: The device is valid by the type invariants, and so is its bus.
+        let ret = unsafe {
+            bindings::mdiobus_read((*phydev).mdio.bus, (*phydev).mdio.addr, regnum.into())
+        };
+        if ret < 0 {
+            Err(Error::from_errno(ret))
+        } else {
+            Ok(ret as u16)
+        }
+    }
+
+    /// Writes a register of the device over MDIO.
+    pub fn write(&mut self, regnum: u16, val: u16) -> Result {
+        let phydev = self.as_raw();
+        // SAFETY: The device is valid by the type invariants, and so is its bus.
+        to_result(unsafe {
+            bindings::mdiobus_write((*phydev).mdio.bus, (*phydev).mdio.addr, regnum.into(), val)
+        })
+    }
+
+    /// Atomically (with respect to other MDIO accesses) clears the bits of `mask` and sets those
+    /// of `set` in a register.
+    pub fn modify(&mut self, regnum: u16, mask: u16, set: u16) -> Result {
+        // SAFETY: The device is valid by the type invariants.
+        to_result(unsafe { bindings::phy_modify(self.as_raw(), regnum.into(), mask, set) })
+    }
+
+    /// Reads a register of the device on a given page, for devices with paged registers.
+    pub fn read_paged(&mut self, page: u16, regnum: u16) -> Result<u16> {
+        // SAFETY: The device is valid by the type invariants.
+        let ret = unsafe { bindings::phy_read_paged(self.as_raw(), page.into(), regnum.into()) };
+        if ret < 0 {
+            Err(Error::from_errno(ret))
+        } else {
+            Ok(ret as u16)
+        }
+    }
+
+    /// Reads a register of an MDIO manageable device (Clause 45).
+    pub fn read_mmd(&mut self, devad: u8, regnum: u16) -> Result<u16> {
+        // SAFETY: The device is valid by the type invariants.
+        let ret = unsafe { bindings::phy_read_mmd(self.as_raw(), devad.into(), regnum.into()) };
+        if ret < 0 {
+            Err(Error::from_errno(ret))
+        } else {
+            Ok(ret as u16)
+        }
+    }
+
+    /// Writes a register of an MDIO manageable device (Clause 45).
+    pub fn write_mmd(&mut self, devad: u8, regnum: u16, val: u16) -> Result {
+        // SAFETY: The device is valid by the type invariants.
+        to_result(unsafe {
+            bindings::phy_write_mmd(self.as_raw(), devad.into(), regnum.into(), val)
+        })
+    }
+
+    /// Resets the device with the generic procedure (setting `BMCR_RESET`).
+    pub fn genphy_soft_reset(&mut self) -> Result {
+        // SAFETY: The device is valid by the type invariants.
+        to_result(unsafe { bindings::genphy_soft_reset(self.as_raw()) })
+    }
+
+    /// Initialises the device: resets it, then calls [`Driver::config_init`].
+    pub fn init_hw(&mut self) -> Result {
+        // SAFETY: The device is valid by the type invariants.
+        to_result(unsafe { bindings::phy_init_hw(self.as_raw()) })
+    }
+
+    /// Starts autonegotiation with the current settings.
+    pub fn start_aneg(&mut self) -> Result {
+        // SAFETY: The device is valid by the type invariants.
+        to_result(unsafe { bindings::_phy_start_aneg(self.as_raw()) })
+    }
+
+    /// Configures autonegotiation with the generic procedure.
+    pub fn genphy_config_aneg(&mut self) -> Result {
+        // SAFETY: The device is valid by the type invariants.
+        to_result(unsafe { bindings::__genphy_config_aneg(self.as_raw(), false) })
+    }
+
+    /// Updates the link state, speed and duplex with the generic procedure.
+    pub fn genphy_read_status(&mut self) -> Result {
+        // SAFETY: The device is valid by the type invariants.
+        to_result(unsafe { bindings::genphy_read_status(self.as_raw()) })
+    }
+
+    /// Updates the link state with the generic procedure.
+    pub fn genphy_update_link(&mut self) -> Result {
+        // SAFETY: The device is valid by the type invariants.
+        to_result(unsafe { bindings::genphy_update_link(self.as_raw()) })
+    }
+
+    /// Reads the link partner's abilities with the generic procedure.
+    pub fn genphy_read_lpa(&mut self) -> Result {
+        // SAFETY: The device is valid by the type invariants.
+        to_result(unsafe { bindings::genphy_read_lpa(self.as_raw()) })
+    }
+
+    /// Reads the supported link modes with the generic procedure.
+    pub fn genphy_read_abilities(&mut self) -> Result {
+        // SAFETY: The device is valid by the type invariants.
+        to_result(unsafe { bindings::genphy_read_abilities(self.as_raw()) })
+    }
+
+    /// Suspends the device with the generic procedure (setting `BMCR_PDOWN`).
+    pub fn genphy_suspend(&mut self) -> Result {
+        // SAFETY: The device is valid by the type invariants.
+        to_result(unsafe { bindings::genphy_suspend(self.as_raw()) })
+    }
+
+    /// Resumes the device with the generic procedure (clearing `BMCR_PDOWN`).
+    pub fn genphy_resume(&mut self) -> Result {
+        // SAFETY: The device is valid by the type invariants.
+        to_result(unsafe { bindings::genphy_resume(self.as_raw()) })
+    }
+}
+
+/// Flags of a PHY driver.
+pub mod flags {
+    /// The PHY is internal to the MAC.
+    pub const IS_INTERNAL: u32 = bindings::PHY_IS_INTERNAL;
+    /// The PHY must be reset after its clock is enabled.
+    pub const RST_AFTER_CLK_EN: u32 = bindings::PHY_RST_AFTER_CLK_EN;
+    /// The PHY must be polled while a cable test is running.
+    pub const POLL_CABLE_TEST: u32 = bindings::PHY_POLL_CABLE_TEST;
+    /// [`super::Driver::suspend`] must be called even if Wake-on-LAN is enabled.
+    pub const ALWAYS_CALL_SUSPEND: u32 = bindings::PHY_ALWAYS_CALL_SUSPEND;
+}
+
+/// A PHY driver, the kernel's `struct phy_driver`.
+///
+/// All callbacks are optional. Without [`Driver::read_status`] and [`Driver::config_aneg`], the
+/// generic procedures are used.
+#[vtable]
+pub trait Driver {
+    /// The flags of the driver, see [`flags`].
+    const FLAGS: u32 = 0;
+
+    /// The name of the driver.
+    const NAME: &'static CStr;
+
+    /// The IDs of the devices handled by the driver. Without one, the driver must implement
+    /// [`Driver::match_phy_device`].
+    const PHY_DEVICE_ID: DeviceId = DeviceId::new_with_custom_mask(0, 0);
+
+    /// Resets the device.
+    fn soft_reset(_dev: &mut Device) -> Result {
+        Err(EOPNOTSUPP)
+    }
+
+    /// Probes the supported link modes of the device.
+    fn get_features(_dev: &mut Device) -> Result {
+        Err(EOPNOTSUPP)
+    }
+
+    /// Configures the device after it was reset, e.g. to apply quirks.
+    fn config_init(_dev: &mut Device) -> Result {
+        Err(EOPNOTSUPP)
+    }
+
+    /// Configures the advertisement and restarts autonegotiation, or forces the link settings if
+    /// autonegotiation is disabled.
+    fn config_aneg(_dev: &mut Device) -> Result {
+        Err(EOPNOTSUPP)
+    }
+
+    /// Updates the link state, speed and duplex of the device.
+    fn read_status(_dev: &mut Device) -> Result {
+        Err(EOPNOTSUPP)
+    }
+
+    /// Returns `true` if the driver handles the device.
+    fn match_phy_device(_dev: &Device) -> bool {
+        false
+    }
+
+    /// Suspends the device.
+    fn suspend(_dev: &mut Device) -> Result {
+        Err(EOPNOTSUPP)
+    }
+
+    /// Resumes the device.
+    fn resume(_dev: &mut Device) -> Result {
+        Err(EOPNOTSUPP)
+    }
+}
+
+struct Adapter<T: Driver>(PhantomData<T>);
+
+impl<T: Driver> Adapter<T> {
+    unsafe extern "C" fn soft_reset_callback(phydev: *mut bindings::phy_device) -> c_int {
+        from_result(|| {
+            // SAFETY: The PHY core calls this with a valid device, serialised by `phydev->lock`.
+            T::soft_reset(unsafe { Device::from_raw(phydev) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn get_features_callback(phydev: *mut bindings::phy_device) -> c_int {
+        from_result(|| {
+            // SAFETY: The PHY core calls this with a valid device while probing it.
+            T::get_features(unsafe { Device::from_raw(phydev) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn config_init_callback(phydev: *mut bindings::phy_device) -> c_int {
+        from_result(|| {
+            // SAFETY: The PHY core calls this with a valid device, serialised by `phydev->lock`.
+            T::config_init(unsafe { Device::from_raw(phydev) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn config_aneg_callback(phydev: *mut bindings::phy_device) -> c_int {
+        from_result(|| {
+            // SAFETY: The PHY core calls this with a valid device, with `phydev->lock` held.
+            T::config_aneg(unsafe { Device::from_raw(phydev) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn read_status_callback(phydev: *mut bindings::phy_device) -> c_int {
+        from_result(|| {
+            // SAFETY: The PHY core calls this with a valid device, with `phydev->lock` held.
+            T::read_status(unsafe { Device::from_raw(phydev) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn match_phy_device_callback(phydev: *mut bindings::phy_device) -> c_int {
+        // SAFETY: The PHY core calls this with a valid device while matching drivers, before
+        // anything else can access it.
+        T::match_phy_device(unsafe { Device::from_raw(phydev) }).into()
+    }
+
+    unsafe extern "C" fn suspend_callback(phydev: *mut bindings::phy_device) -> c_int {
+        from_result(|| {
+            // SAFETY: The PHY core calls this with a valid device, serialised by `phydev->lock`.
+            T::suspend(unsafe { Device::from_raw(phydev) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn resume_callback(phydev: *mut bindings::phy_device) -> c_int {
+        from_result(|| {
+            // SAFETY: The PHY core calls this with a valid device, serialised by `phydev->lock`.
+            T::resume(unsafe { Device::from_raw(phydev) })?;
+            Ok(0)
+        })
+    }
+}
+
+/// A `struct phy_driver` built for an implementation of [`Driver`].
+///
+/// Use [`create_phy_driver`] to build one; [`module_phy_driver!`] does it for each driver.
+///
+/// [`module_phy_driver!`]: crate::module_phy_driver
+#[repr(transparent)]
+pub struct DriverVTable(Opaque<bindings::phy_driver>);
+
+// SAFETY: The table is only modified by the PHY core when the drivers are registered and
+// unregistered, which is serialised.
+unsafe impl Sync for DriverVTable {}
+
+/// Creates the [`DriverVTable`] of a [`Driver`].
+pub const fn create_phy_driver<T: Driver>() -> DriverVTable {
+    DriverVTable(Opaque::new(bindings::phy_driver {
+        name: T::NAME.as_char_ptr().cast_mut(),
+        flags: T::FLAGS,
+        phy_id: T::PHY_DEVICE_ID.id,
+        phy_id_mask: T::PHY_DEVICE_ID.mask_as_int(),
+        soft_reset: if T::HAS_SOFT_RESET {
+            Some(Adapter::<T>::soft_reset_callback)
+        } else {
+            None
+        },
+        get_features: if T::HAS_GET_FEATURES {
+            Some(Adapter::<T>::get_features_callback)
+        } else {
+            None
+        },
+        config_init: if T::HAS_CONFIG_INIT {
+            Some(Adapter::<T>::config_init_callback)
+        } else {
+            None
+        },
+        config_aneg: if T::HAS_CONFIG_ANEG {
+            Some(Adapter::<T>::config_aneg_callback)
+        } else {
+            None
+        },
+        read_status: if T::HAS_READ_STATUS {
+            Some(Adapter::<T>::read_status_callback)
+        } else {
+            None
+        },
+        match_phy_device: if T::HAS_MATCH_PHY_DEVICE {
+            Some(Adapter::<T>::match_phy_device_callback)
+        } else {
+            None
+        },
+        suspend: if T::HAS_SUSPEND {
+            Some(Adapter::<T>::suspend_callback)
+        } else {
+            None
+        },
+        resume: if T::HAS_RESUME {
+            Some(Adapter::<T>::resume_callback)
+        } else {
+            None
+        },
+        // SAFETY: All other fields are optional callbacks or filled in on registration, for
+        // which zero is valid.
+        ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+    }))
+}
+
+/// Registered PHY drivers.
+///
+/// The drivers are unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `drivers` are registered with the PHY core.
+pub struct Registration {
+    drivers: Pin<&'static mut [DriverVTable]>,
+}
+
+impl Registration {
+    /// Registers `drivers` on behalf of `module`.
+    pub fn register(
+        module: &'static ThisModule,
+        drivers: Pin<&'static mut [DriverVTable]>,
+    ) -> Result<Self> {
+        if drivers.is_empty() {
+            return Err(EINVAL);
+        }
+        // SAFETY: `drivers` are valid, pinned and unregistered on drop. `DriverVTable` is
+        // transparent over `struct phy_driver`.
+        to_result(unsafe {
+            bindings::phy_drivers_register(drivers[0].0.get(), drivers.len() as _, module.as_ptr())
+        })?;
+        // INVARIANT: The drivers were registered above.
+        Ok(Self { drivers })
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        // SAFETY: The drivers are registered by the type invariants.
+        unsafe {
+            bindings::phy_drivers_unregister(self.drivers[0].0.get(), self.drivers.len() as _)
+        };
+    }
+}
+
+// SAFETY: Drivers can be unregistered from any thread.
+unsafe impl Send for Registration {}
+
+// SAFETY: `Registration` has no methods that take `&self`.
+unsafe impl Sync for Registration {}
+
+/// How much of a [`DeviceId`] must match the ID of a device.
+#[derive(Clone, Copy)]
+enum DeviceMask {
+    Exact,
+    Model,
+    Vendor,
+    Custom(u32),
+}
+
+/// An MDIO device ID matched against the ID registers of PHY devices, the kernel's
+/// `struct mdio_device_id`.
+#[derive(Clone, Copy)]
+pub struct DeviceId {
+    id: u32,
+    mask: DeviceMask,
+}
+
+impl DeviceId {
+    /// Creates an ID that must match exactly.
+    pub const fn new_with_exact_mask(id: u32) -> Self {
+        Self {
+            id,
+            mask: DeviceMask::Exact,
+        }
+    }
+
+    /// Creates an ID that matches any revision of the model (ignores the lowest 4 bits).
+    pub const fn new_with_model_mask(id: u32) -> Self {
+        Self {
+            id,
+            mask: DeviceMask::Model,
+        }
+    }
+
+    /// Creates an ID that matches any device of the vendor (ignores the lowest 10 bits).
+    pub const fn new_with_vendor_mask(id: u32) -> Self {
+        Self {
+            id,
+            mask: DeviceMask::Vendor,
+        }
+    }
+
+    /// Creates an ID that matches the bits of `mask`.
+    pub const fn new_with_custom_mask(id: u32, mask: u32) -> Self {
+        Self {
+            id,
+            mask: DeviceMask::Custom(mask),
+        }
+    }
+
+    /// Creates an ID that matches devices handled by the driver `T`.
+    pub const fn new_with_driver<T: Driver>() -> Self {
+        T::PHY_DEVICE_ID
+    }
+
+    const fn mask_as_int(&self) -> u32 {
+        match self.mask {
+            DeviceMask::Exact => !0,
+            DeviceMask::Model => !0 << 4,
+            DeviceMask::Vendor => !0 << 10,
+            DeviceMask::Custom(mask) => mask,
+        }
+    }
+
+    /// Returns the entry of the ID in an MDIO device table.
+    pub const fn mdio_device_id(&self) -> bindings::mdio_device_id {
+        bindings::mdio_device_id {
+            phy_id: self.id,
+            phy_id_mask: self.mask_as_int(),
+        }
+    }
+}
+
+/// Declares a kernel module that registers PHY drivers, and emits the MDIO device table that
+/// allows the module to be loaded when a matching device is found.
+///
+/// # Examples
+///
+/// ```ignore
+/// use kernel::{c_str, net::phy::{self, DeviceId}};
+/// use kernel::prelude::*;
+///
+/// kernel::module_phy_driver! {
+///     drivers: [PhyQuirk],
+///     device_table: [DeviceId::new_with_driver::<PhyQuirk>()],
+///     name: "rust_phy_quirk",
+///     author: "Rust for Linux Contributors",
+///     description: "PHY quirk driver",
+///     license: "GPL",
+/// }
+///
+/// struct PhyQuirk;
+///
+/// #[vtable]
+/// impl phy::Driver for PhyQuirk {
+///     const NAME: &'static CStr = c_str!("PHY quirk");
+///     const PHY_DEVICE_ID: DeviceId = DeviceId::new_with_model_mask(0x001cc910);
+///
+///     fn config_init(dev: &mut phy::Device) -> Result {
+///         // Disable the broken energy detect mode.
+///         dev.modify(0x10, 1 << 14, 0)
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! module_phy_driver {
+    (@replace_expr $_t:tt $sub:expr) => {$sub};
+
+    (@count_devices $($x:expr),*) => {
+        0usize $(+ $crate::module_phy_driver!(@replace_expr $x 1usize))*
+    };
+
+    (@device_table [$($dev:expr),+]) => {
+        // The table is terminated by an all-zeroes entry.
+        #[cfg(MODULE)]
+        #[no_mangle]
+        static __mod_mdio__phydev_device_table: [$crate::bindings::mdio_device_id;
+            $crate::module_phy_driver!(@count_devices $($dev),+) + 1] = [
+            $($dev.mdio_device_id()),+,
+            $crate::bindings::mdio_device_id {
+                phy_id: 0,
+                phy_id_mask: 0,
+            },
+        ];
+    };
+
+    (drivers: [$($driver:ident),+ $(,)?], device_table: [$($dev:expr),+ $(,)?], $($f:tt)*) => {
+        struct Module {
+            _reg: $crate::net::phy::Registration,
+        }
+
+        $crate::prelude::module! {
+            type: Module,
+            $($f)*
+        }
+
+        impl $crate::Module for Module {
+            fn init(module: &'static $crate::ThisModule) -> $crate::error::Result<Self> {
+                static mut DRIVERS: [
+                    $crate::net::phy::DriverVTable;
+                    $crate::module_phy_driver!(@count_devices $($driver),+)
+                ] = [$($crate::net::phy::create_phy_driver::<$driver>()),+];
+
+                // SAFETY: `DRIVERS` is only visible in this function, which is called once when
+                // the module is loaded, and is never moved since it is static.
+                let drivers = unsafe { ::core::pin::Pin::new_unchecked(&mut DRIVERS[..]) };
+                let reg = $crate::net::phy::Registration::register(module, drivers)?;
+                Ok(Module { _reg: reg })
+            }
+        }
+
+        $crate::module_phy_driver!(@device_table [$($dev),+]);
+    }
+}
@@ -0,0 +1,376 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Socket buffers.
+//!
+//! A socket buffer holds a packet and its metadata. Owned buffers are represented by [`SkBuff`],
+//! which frees the buffer when dropped and is the only way to modify its layout. Buffers owned by
+//! someone else (e.g. the networking core) are accessed through a shared [`SkBuffRef`], which
+//! [`SkBuff`] also dereferences to.
+//!
+//! Data is added to a buffer with [`SkBuff::put`] (at the end) and [`SkBuff::push`] (at the
+//! start, for headers), which need [tailroom] and [headroom] respectively; headroom is set aside
+//! on an empty buffer with [`SkBuff::reserve`]. Headers are consumed with [`SkBuff::pull`]. All of
+//! these check their bounds instead of panicking like their C counterparts.
+//!
+//! C header: [`include/linux/skbuff.h`](../../../../include/linux/skbuff.h)
+//!
+//! [tailroom]: SkBuffRef::tailroom
+//! [headroom]: SkBuffRef::headroom
+
+use super::Device;
+use crate::{
+    bindings,
+    error::{code::*, Result},
+    types::Opaque,
+};
+use core::{ops::Deref, ptr::NonNull, slice};
+
+/// The checksum status of a socket buffer, the kernel's `CHECKSUM_*` values for `ip_summed`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Checksum {
+    /// The checksum of received packets was not verified by the device, and the checksum of
+    /// transmitted packets was already computed by software.
+    None,
+    /// The device verified the checksums of the received packet.
+    Unnecessary,
+    /// The device computed the checksum of the whole received packet, which is in `csum`.
+    Complete,
+    /// The checksum of the transmitted packet must be computed by the device, from `csum_start`
+    /// and stored at `csum_offset`.
+    Partial,
+}
+
+impl Checksum {
+    fn from_raw(ip_summed: u8) -> Self {
+        match ip_summed as u32 {
+            bindings::CHECKSUM_UNNECESSARY => Self::Unnecessary,
+            bindings::CHECKSUM_COMPLETE => Self::Complete,
+            bindings::CHECKSUM_PARTIAL => Self::Partial,
+            _ => Self::None,
+        }
+    }
+}
+
+/// A shared reference to a socket buffer, the kernel's `struct sk_buff`.
+///
+/// # Invariants
+///
+/// The buffer is valid, and its layout and data are not modified while it is shared.
+#[repr(transparent)]
+pub struct SkBuffRef(Opaque<bindings::sk_buff>);
+
+// SAFETY: Shared references to a socket buffer only allow reading it, which can be done from any
+// thread.
+unsafe impl Sync for SkBuffRef {}
+
+impl SkBuffRef {
+    /// Creates a reference to a [`SkBuffRef`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is valid, and that nothing modifies the buffer for the
+    /// duration of the lifetime `'a`.
+    pub unsafe fn as_ref<'a>(ptr: *mut bindings::sk_buff) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct sk_buff` pointer.
+    pub fn as_raw(&self) -> *mut bindings::sk_buff {
+        self.0.get()
+    }
+
+    /// Returns the length of the data in the buffer, including paged data.
+    pub fn len(&self) -> u32 {
+        // SAFETY: The buffer is valid by the type invariants.
+        unsafe { (*self.as_raw()).len }
+    }
+
+    /// Returns `true` if the buffer contains no data.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if some of the data is held in pages instead of the linear part.
+    pub fn is_nonlinear(&self) -> bool {
+        // SAFETY: The buffer is valid by the type invariants.
+        unsafe { (*self.as_raw()).data_len != 0 }
+    }
+
+    /// Returns the length of the linear part of the data.
+    pub fn head_len(&self) -> u32 {
+        // SAFETY: The buffer is valid by the type invariants.
+        unsafe { bindings::skb_headlen(self.as_raw()) }
+    }
+
+    /// Returns the data in the linear part of the buffer.
+    pub fn head_data(&self) -> &[u8] {
+        // SAFETY: The buffer is valid by the type invariants, and its first `head_len` bytes at
+        // `data` are linear. They are not modified while the buffer is shared.
+        unsafe { slice::from_raw_parts((*self.as_raw()).data, self.head_len() as usize) }
+    }
+
+    /// Returns the free space before the data, where headers can be pushed.
+    pub fn headroom(&self) -> u32 {
+        // SAFETY: The buffer is valid by the type invariants.
+        unsafe { bindings::skb_headroom(self.as_raw()) }
+    }
+
+    /// Returns the free space after the data, where data can be put.
+    pub fn tailroom(&self) -> u32 {
+        // SAFETY: The buffer is valid by the type invariants.
+        unsafe { bindings::skb_tailroom(self.as_raw()) as u32 }
+    }
+
+    /// Returns the protocol of the packet, an `ETH_P_*` value in host byte order.
+    pub fn protocol(&self) -> u16 {
+        // SAFETY: The buffer is valid by the type invariants.
+        u16::from_be(unsafe { (*self.as_raw()).protocol })
+    }
+
+    /// Returns the checksum status of the packet.
+    pub fn checksum(&self) -> Checksum {
+        // SAFETY: The buffer is valid by the type invariants.
+        Checksum::from_raw(unsafe { (*self.as_raw()).ip_summed() })
+    }
+}
+
+/// An owned socket buffer.
+///
+/// The buffer is freed when this is dropped, and accounted as a drop. Use [`SkBuff::consume`] when
+/// the buffer was processed successfully instead.
+///
+/// # Invariants
+///
+/// The pointer is valid and we own the buffer.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::net::{self, SkBuff};
+/// # use kernel::prelude::*;
+///
+/// fn build_frame(dev: &net::Device, payload: &[u8]) -> Result<SkBuff> {
+///     let mut skb = SkBuff::alloc(dev, payload.len() as u32 + 16)?;
+///     skb.reserve(16)?;
+///     skb.put_data(payload)?;
+///     Ok(skb)
+/// }
+/// ```
+pub struct SkBuff(NonNull<bindings::sk_buff>);
+
+// SAFETY: Socket buffers can be passed to and freed from any thread.
+unsafe impl Send for SkBuff {}
+
+// SAFETY: `SkBuff` has no interior mutability, shared references only allow reading the buffer.
+unsafe impl Sync for SkBuff {}
+
+impl SkBuff {
+    /// Allocates a buffer with room for `size` bytes of data.
+    ///
+    /// This may sleep.
+    pub fn try_new(size: u32) -> Result<Self> {
+        crate::might_sleep!();
+        // SAFETY: FFI call with valid arguments.
+        let skb =
+            unsafe { bindings::__alloc_skb(size, bindings::GFP_KERNEL, 0, bindings::NUMA_NO_NODE) };
+        // INVARIANT: The buffer was just allocated, so we own it.
+        Ok(Self(NonNull::new(skb).ok_or(ENOMEM)?))
+    }
+
+    /// Allocates a buffer to receive `len` bytes of data on `dev`, like `netdev_alloc_skb` in C.
+    ///
+    /// The buffer has some headroom reserved, and this may be called from atomic context.
+    pub fn alloc(dev: &Device, len: u32) -> Result<Self> {
+        // SAFETY: FFI call with valid arguments.
+        let skb = unsafe { bindings::__netdev_alloc_skb(dev.as_raw(), len, bindings::GFP_ATOMIC) };
+        // INVARIANT: The buffer was just allocated, so we own it.
+        Ok(Self(NonNull::new(skb).ok_or(ENOMEM)?))
+    }
+
+    /// Takes ownership of a raw socket buffer.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a valid socket buffer, and the caller must own it: it will be freed when the
+    /// returned value is dropped.
+    pub unsafe fn from_raw(ptr: *mut bindings::sk_buff) -> Self {
+        // INVARIANT: The safety requirements guarantee that `ptr` is valid and owned.
+        // SAFETY: The safety requirements guarantee that `ptr` is not null.
+        Self(unsafe { NonNull::new_unchecked(ptr) })
+    }
+
+    /// Gives up ownership of the buffer and returns the raw pointer to it.
+    pub fn into_raw(self) -> *mut bindings::sk_buff {
+        let ptr = self.0.as_ptr();
+        core::mem::forget(self);
+        ptr
+    }
+
+    /// Sets aside `len` bytes of headroom in an empty buffer.
+    ///
+    /// Fails with `EINVAL` if the buffer is not empty, and with `ENOSPC` if it is too small.
+    pub fn reserve(&mut self, len: u32) -> Result {
+        if !self.is_empty() {
+            return Err(EINVAL);
+        }
+        if len > self.tailroom() {
+            return Err(ENOSPC);
+        }
+        // SAFETY: We own the buffer, which is empty and has enough tailroom.
+        unsafe { bindings::skb_reserve(self.as_raw(), len as _) };
+        Ok(())
+    }
+
+    /// Extends the data at the end of the buffer by `len` bytes and returns the new area, which is
+    /// not initialised to any particular value.
+    ///
+    /// Fails with `EINVAL` if the buffer is nonlinear, and with `ENOSPC` if the tailroom is too
+    /// small.
+    pub fn put(&mut self, len: u32) -> Result<&mut [u8]> {
+        if self.is_nonlinear() {
+            return Err(EINVAL);
+        }
+        if len > self.tailroom() {
+            return Err(ENOSPC);
+        }
+        // SAFETY: We own the buffer, which is linear and has enough tailroom.
+        let data = unsafe { bindings::skb_put(self.as_raw(), len) };
+        // SAFETY: `skb_put` returns the start of the new `len` bytes, which we own.
+        Ok(unsafe { slice::from_raw_parts_mut(data.cast(), len as usize) })
+    }
+
+    /// Appends `data` to the buffer.
+    ///
+    /// Fails like [`SkBuff::put`].
+    pub fn put_data(&mut self, data: &[u8]) -> Result {
+        let len = data.len().try_into().map_err(|_| ENOSPC)?;
+        self.put(len)?.copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Extends the data at the start of the buffer by `len` bytes and returns the new area, which
+    /// is not initialised to any particular value.
+    ///
+    /// Fails with `ENOSPC` if the headroom is too small.
+    pub fn push(&mut self, len: u32) -> Result<&mut [u8]> {
+        if len > self.headroom() {
+            return Err(ENOSPC);
+        }
+        // SAFETY: We own the buffer, which has enough headroom.
+        let data = unsafe { bindings::skb_push(self.as_raw(), len) };
+        // SAFETY: `skb_push` returns the start of the new `len` bytes, which we own.
+        Ok(unsafe { slice::from_raw_parts_mut(data.cast(), len as usize) })
+    }
+
+    /// Removes `len` bytes from the start of the data, e.g. a header that was processed.
+    ///
+    /// Paged data is moved to the linear part if needed. Fails with `EINVAL` if the buffer holds
+    /// less than `len` bytes, and with `ENOMEM` if the data couldn't be moved.
+    pub fn pull(&mut self, len: u32) -> Result {
+        if len > self.len() {
+            return Err(EINVAL);
+        }
+        // SAFETY: We own the buffer.
+        if !unsafe { bindings::pskb_may_pull(self.as_raw(), len) } {
+            return Err(ENOMEM);
+        }
+        // SAFETY: We own the buffer, and `pskb_may_pull` made at least `len` bytes linear.
+        unsafe { bindings::skb_pull(self.as_raw(), len) };
+        Ok(())
+    }
+
+    /// Removes data from the end of the buffer so that it is `len` bytes long.
+    ///
+    /// Does nothing if the buffer is already shorter. Fails with `EINVAL` if the buffer is
+    /// nonlinear.
+    pub fn trim(&mut self, len: u32) -> Result {
+        if self.is_nonlinear() {
+            return Err(EINVAL);
+        }
+        // SAFETY: We own the buffer, which is linear.
+        unsafe { bindings::skb_trim(self.as_raw(), len) };
+        Ok(())
+    }
+
+    /// Sets the protocol of the packet, an `ETH_P_*` value in host byte order.
+    pub fn set_protocol(&mut self, protocol: u16) {
+        // SAFETY: We own the buffer.
+        unsafe { (*self.as_raw()).protocol = protocol.to_be() };
+    }
+
+    /// Sets the checksum status of a received packet to [`Checksum::None`] or
+    /// [`Checksum::Unnecessary`].
+    ///
+    /// Fails with `EINVAL` for other values, use [`SkBuff::set_checksum_complete`] or
+    /// [`SkBuff::set_checksum_partial`] instead.
+    pub fn set_checksum(&mut self, checksum: Checksum) -> Result {
+        let value = match checksum {
+            Checksum::None => bindings::CHECKSUM_NONE,
+            Checksum::Unnecessary => bindings::CHECKSUM_UNNECESSARY,
+            _ => return Err(EINVAL),
+        };
+        // SAFETY: We own the buffer.
+        unsafe { (*self.as_raw()).set_ip_summed(value as _) };
+        Ok(())
+    }
+
+    /// Records the checksum computed by the device over the whole received packet.
+    pub fn set_checksum_complete(&mut self, csum: u32) {
+        // SAFETY: We own the buffer.
+        unsafe {
+            (*self.as_raw()).csum = csum;
+            (*self.as_raw()).set_ip_summed(bindings::CHECKSUM_COMPLETE as _);
+        }
+    }
+
+    /// Requests that the checksum of the packet be computed from offset `start` of the data, and
+    /// stored at `offset` bytes after `start`.
+    ///
+    /// Fails with `EINVAL` if the checksum doesn't fit in the linear part of the buffer.
+    pub fn set_checksum_partial(&mut self, start: u16, offset: u16) -> Result {
+        // SAFETY: We own the buffer.
+        if unsafe { bindings::skb_partial_csum_set(self.as_raw(), start, offset) } {
+            Ok(())
+        } else {
+            Err(EINVAL)
+        }
+    }
+
+    /// Prepares a received Ethernet frame for [`Device::rx`]: sets the receiving device, removes
+    /// the Ethernet header and sets the protocol accordingly, which is also returned.
+    ///
+    /// Fails with `EINVAL` if the frame is too short.
+    pub fn eth_type_trans(&mut self, dev: &Device) -> Result<u16> {
+        if self.head_len() < bindings::ETH_HLEN {
+            return Err(EINVAL);
+        }
+        // SAFETY: We own the buffer, whose linear part holds at least an Ethernet header.
+        let protocol = unsafe { bindings::eth_type_trans(self.as_raw(), dev.as_raw()) };
+        Ok(u16::from_be(protocol))
+    }
+
+    /// Frees the buffer after it was processed successfully, e.g. once it has been transmitted.
+    ///
+    /// This may be called from any context.
+    pub fn consume(self) {
+        // SAFETY: We own the buffer, and `into_raw` gives up our ownership.
+        unsafe { bindings::dev_consume_skb_any(self.into_raw()) };
+    }
+}
+
+impl Deref for SkBuff {
+    type Target = SkBuffRef;
+
+    fn deref(&self) -> &SkBuffRef {
+        // SAFETY: We own the buffer, and it can't be modified while it is borrowed.
+        unsafe { SkBuffRef::as_ref(self.0.as_ptr()) }
+    }
+}
+
+impl Drop for SkBuff {
+    fn drop(&mut self) {
+        // SAFETY: We own the buffer by the type invariants. This may be called from any context.
+        unsafe { bindings::dev_kfree_skb_any(self.as_raw()) };
+    }
+}
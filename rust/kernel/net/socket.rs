@@ -0,0 +1,640 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! In-kernel sockets.
+//!
+//! [`Socket`] wraps the `kernel_*` socket API, which lets kernel code act as a network client or
+//! server. Addresses are represented by [`SocketAddr`], which covers IPv4 and IPv6.
+//!
+//! C header: [`include/linux/net.h`](../../../../include/linux/net.h)
+
+use super::Namespace;
+use crate::{
+    bindings,
+    error::{code::*, to_result, Error, Result},
+    types::ARef,
+};
+use core::{
+    ffi::c_int,
+    fmt,
+    mem::{size_of, MaybeUninit},
+    ptr::{self, NonNull},
+    time::Duration,
+};
+
+/// An IPv4 address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ipv4Addr(pub [u8; 4]);
+
+impl Ipv4Addr {
+    /// The unspecified address, `0.0.0.0`.
+    pub const ANY: Self = Self([0; 4]);
+
+    /// The loopback address, `127.0.0.1`.
+    pub const LOOPBACK: Self = Self([127, 0, 0, 1]);
+
+    /// The broadcast address, `255.255.255.255`.
+    pub const BROADCAST: Self = Self([255; 4]);
+
+    /// Creates an address from its four octets.
+    pub const fn new(a: u8, b: u8, c: u8, d: u8) -> Self {
+        Self([a, b, c, d])
+    }
+}
+
+impl fmt::Display for Ipv4Addr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d] = self.0;
+        write!(f, "{a}.{b}.{c}.{d}")
+    }
+}
+
+/// An IPv6 address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ipv6Addr(pub [u8; 16]);
+
+impl Ipv6Addr {
+    /// The unspecified address, `::`.
+    pub const ANY: Self = Self([0; 16]);
+
+    /// The loopback address, `::1`.
+    pub const LOOPBACK: Self = Self([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+
+    /// Creates an address from its eight 16-bit segments.
+    pub const fn new(segments: [u16; 8]) -> Self {
+        let mut octets = [0; 16];
+        let mut i = 0;
+        while i < 8 {
+            octets[2 * i] = (segments[i] >> 8) as u8;
+            octets[2 * i + 1] = segments[i] as u8;
+            i += 1;
+        }
+        Self(octets)
+    }
+}
+
+/// An IPv4 socket address: an address and a port.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SocketAddrV4 {
+    /// The IP address.
+    pub addr: Ipv4Addr,
+    /// The port, in host byte order.
+    pub port: u16,
+}
+
+/// An IPv6 socket address: an address, a port and the scope of link-local addresses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SocketAddrV6 {
+    /// The IP address.
+    pub addr: Ipv6Addr,
+    /// The port, in host byte order.
+    pub port: u16,
+    /// The flow information.
+    pub flowinfo: u32,
+    /// The scope of link-local addresses, usually an interface index.
+    pub scope_id: u32,
+}
+
+/// A socket address of a supported family.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SocketAddr {
+    /// An IPv4 socket address.
+    V4(SocketAddrV4),
+    /// An IPv6 socket address.
+    V6(SocketAddrV6),
+}
+
+impl SocketAddr {
+    /// Creates an IPv4 socket address.
+    pub const fn v4(addr: Ipv4Addr, port: u16) -> Self {
+        Self::V4(SocketAddrV4 { addr, port })
+    }
+
+    /// Creates an IPv6 socket address with no flow information or scope.
+    pub const fn v6(addr: Ipv6Addr, port: u16) -> Self {
+        Self::V6(SocketAddrV6 {
+            addr,
+            port,
+            flowinfo: 0,
+            scope_id: 0,
+        })
+    }
+
+    /// Returns the address family of the address.
+    pub fn family(&self) -> AddressFamily {
+        match self {
+            Self::V4(_) => AddressFamily::Inet,
+            Self::V6(_) => AddressFamily::Inet6,
+        }
+    }
+
+    /// Returns the port of the address, in host byte order.
+    pub fn port(&self) -> u16 {
+        match self {
+            Self::V4(a) => a.port,
+            Self::V6(a) => a.port,
+        }
+    }
+
+    /// Converts the address to a `struct sockaddr_storage` and returns its length.
+    fn to_raw(self) -> (bindings::__kernel_sockaddr_storage, c_int) {
+        // SAFETY: All-zeroes is a valid `struct sockaddr_storage`.
+        let mut storage: bindings::__kernel_sockaddr_storage =
+            unsafe { MaybeUninit::zeroed().assume_init() };
+        let ptr = ptr::addr_of_mut!(storage);
+        match self {
+            Self::V4(a) => {
+                let sin = ptr.cast::<bindings::sockaddr_in>();
+                // SAFETY: `sockaddr_storage` is large and aligned enough for any address.
+                unsafe {
+                    (*sin).sin_family = bindings::AF_INET as _;
+                    (*sin).sin_port = a.port.to_be();
+                    (*sin).sin_addr.s_addr = u32::from_ne_bytes(a.addr.0);
+                }
+                (storage, size_of::<bindings::sockaddr_in>() as _)
+            }
+            Self::V6(a) => {
+                let sin6 = ptr.cast::<bindings::sockaddr_in6>();
+                // SAFETY: `sockaddr_storage` is large and aligned enough for any address.
+                unsafe {
+                    (*sin6).sin6_family = bindings::AF_INET6 as _;
+                    (*sin6).sin6_port = a.port.to_be();
+                    (*sin6).sin6_flowinfo = a.flowinfo.to_be();
+                    (*sin6).sin6_addr.in6_u.u6_addr8 = a.addr.0;
+                    (*sin6).sin6_scope_id = a.scope_id;
+                }
+                (storage, size_of::<bindings::sockaddr_in6>() as _)
+            }
+        }
+    }
+
+    /// Converts a `struct sockaddr_storage` filled in by the kernel into an address.
+    ///
+    /// Fails with `EAFNOSUPPORT` for addresses of other families.
+    fn from_raw(storage: &bindings::__kernel_sockaddr_storage, len: c_int) -> Result<Self> {
+        let ptr = (storage as *const bindings::__kernel_sockaddr_storage).cast::<u8>();
+        // SAFETY: All socket addresses start like a `struct sockaddr`, and the storage is large
+        // enough for it.
+        let family = unsafe { (*ptr.cast::<bindings::sockaddr>()).sa_family } as u32;
+        match family {
+            bindings::AF_INET if len as usize >= size_of::<bindings::sockaddr_in>() => {
+                // SAFETY: The storage holds an IPv4 address.
+                let sin = unsafe { &*ptr.cast::<bindings::sockaddr_in>() };
+                Ok(Self::v4(
+                    Ipv4Addr(sin.sin_addr.s_addr.to_ne_bytes()),
+                    u16::from_be(sin.sin_port),
+                ))
+            }
+            bindings::AF_INET6 if len as usize >= size_of::<bindings::sockaddr_in6>() => {
+                // SAFETY: The storage holds an IPv6 address.
+                let sin6 = unsafe { &*ptr.cast::<bindings::sockaddr_in6>() };
+                Ok(Self::V6(SocketAddrV6 {
+                    // SAFETY: All variants of the union are plain bytes.
+                    addr: Ipv6Addr(unsafe { sin6.sin6_addr.in6_u.u6_addr8 }),
+                    port: u16::from_be(sin6.sin6_port),
+                    flowinfo: u32::from_be(sin6.sin6_flowinfo),
+                    scope_id: sin6.sin6_scope_id,
+                }))
+            }
+            _ => Err(EAFNOSUPPORT),
+        }
+    }
+}
+
+impl fmt::Display for SocketAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::V4(a) => write!(f, "{}:{}", a.addr, a.port),
+            Self::V6(a) => {
+                let octets = a.addr.0;
+                f.write_str("[")?;
+                for (i, pair) in octets.chunks(2).enumerate() {
+                    if i != 0 {
+                        f.write_str(":")?;
+                    }
+                    write!(f, "{:x}", u16::from_be_bytes([pair[0], pair[1]]))?;
+                }
+                write!(f, "]:{}", a.port)
+            }
+        }
+    }
+}
+
+/// The address family of a socket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressFamily {
+    /// IPv4.
+    Inet,
+    /// IPv6.
+    Inet6,
+}
+
+impl AddressFamily {
+    fn as_raw(self) -> c_int {
+        match self {
+            Self::Inet => bindings::AF_INET as _,
+            Self::Inet6 => bindings::AF_INET6 as _,
+        }
+    }
+}
+
+/// The type of a socket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SockType {
+    /// A reliable, connection-oriented byte stream, e.g. TCP.
+    Stream,
+    /// Connectionless, unreliable datagrams, e.g. UDP.
+    Datagram,
+    /// Raw network protocol access.
+    Raw,
+}
+
+impl SockType {
+    fn as_raw(self) -> c_int {
+        match self {
+            Self::Stream => bindings::sock_type_SOCK_STREAM as _,
+            Self::Datagram => bindings::sock_type_SOCK_DGRAM as _,
+            Self::Raw => bindings::sock_type_SOCK_RAW as _,
+        }
+    }
+}
+
+/// The transport protocol of a socket.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// The default protocol of the socket type.
+    Default,
+    /// TCP.
+    Tcp,
+    /// UDP.
+    Udp,
+}
+
+impl Protocol {
+    fn as_raw(self) -> c_int {
+        match self {
+            Self::Default => 0,
+            Self::Tcp => bindings::IPPROTO_TCP as _,
+            Self::Udp => bindings::IPPROTO_UDP as _,
+        }
+    }
+}
+
+/// Which directions of a connection to shut down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shutdown {
+    /// No more data will be received.
+    Read,
+    /// No more data will be sent.
+    Write,
+    /// Both of the above.
+    Both,
+}
+
+/// A kernel socket, the kernel's `struct socket`.
+///
+/// The socket is released when this is dropped.
+///
+/// # Invariants
+///
+/// `sock` is a valid socket created by `sock_create_kern` or `kernel_accept`, and we own it. It
+/// belongs to the network namespace `ns`, which we hold a reference to.
+///
+/// # Examples
+///
+/// A TCP echo server handling a single client:
+///
+/// ```
+/// use kernel::net::socket::{AddressFamily, Ipv4Addr, Socket, SocketAddr};
+/// # use kernel::prelude::*;
+///
+/// fn echo_once() -> Result {
+///     let listener = Socket::new_tcp(AddressFamily::Inet)?;
+///     listener.bind(&SocketAddr::v4(Ipv4Addr::ANY, 8000))?;
+///     listener.listen(16)?;
+///     let client = listener.accept(false)?;
+///     client.set_recv_timeout(Some(core::time::Duration::from_secs(5)))?;
+///     let mut buf = [0u8; 256];
+///     loop {
+///         let n = client.recv(&mut buf, false)?;
+///         if n == 0 {
+///             return Ok(());
+///         }
+///         client.send(&buf[..n], false)?;
+///     }
+/// }
+/// ```
+pub struct Socket {
+    sock: NonNull<bindings::socket>,
+    ns: ARef<Namespace>,
+}
+
+// SAFETY: Sockets have their own locking and can be used and released from any thread.
+unsafe impl Send for Socket {}
+
+// SAFETY: Sockets have their own locking, so the methods that take `&self` can be called
+// concurrently.
+unsafe impl Sync for Socket {}
+
+impl Socket {
+    /// Creates a socket in the initial network namespace.
+    pub fn new(family: AddressFamily, ty: SockType, protocol: Protocol) -> Result<Self> {
+        Self::new_in(Namespace::init().into(), family, ty, protocol)
+    }
+
+    /// Creates a socket in the network namespace `ns`.
+    ///
+    /// Kernel sockets don't pin their namespace, so the socket holds on to `ns` until it is
+    /// released. The namespace is thus not torn down while the socket exists.
+    pub fn new_in(
+        ns: ARef<Namespace>,
+        family: AddressFamily,
+        ty: SockType,
+        protocol: Protocol,
+    ) -> Result<Self> {
+        crate::might_sleep!();
+        let mut sock = ptr::null_mut();
+        // SAFETY: `ns` is valid, and `sock` is a valid location for the result.
+        to_result(unsafe {
+            bindings::sock_create_kern(
+                ns.as_raw(),
+                family.as_raw(),
+                ty.as_raw(),
+                protocol.as_raw(),
+                &mut sock,
+            )
+        })?;
+        // INVARIANT: `sock_create_kern` succeeded, so `sock` is a valid socket of `ns` that we own.
+        Ok(Self {
+            sock: NonNull::new(sock).ok_or(ENOMEM)?,
+            ns,
+        })
+    }
+
+    /// Creates a TCP socket.
+    pub fn new_tcp(family: AddressFamily) -> Result<Self> {
+        Self::new(family, SockType::Stream, Protocol::Tcp)
+    }
+
+    /// Creates a UDP socket.
+    pub fn new_udp(family: AddressFamily) -> Result<Self> {
+        Self::new(family, SockType::Datagram, Protocol::Udp)
+    }
+
+    /// Returns the network namespace of the socket.
+    pub fn net(&self) -> &Namespace {
+        &self.ns
+    }
+
+    /// Returns the raw `struct socket` pointer.
+    pub fn as_raw(&self) -> *mut bindings::socket {
+        self.sock.as_ptr()
+    }
+
+    /// Binds the socket to a local address.
+    pub fn bind(&self, addr: &SocketAddr) -> Result {
+        let (mut raw, len) = addr.to_raw();
+        // SAFETY: The socket is valid by the type invariants, and `raw` holds an address of
+        // length `len`.
+        to_result(unsafe {
+            bindings::kernel_bind(self.as_raw(), ptr::addr_of_mut!(raw).cast(), len)
+        })
+    }
+
+    /// Starts listening for connections, with at most `backlog` pending ones.
+    pub fn listen(&self, backlog: i32) -> Result {
+        // SAFETY: The socket is valid by the type invariants.
+        to_result(unsafe { bindings::kernel_listen(self.as_raw(), backlog) })
+    }
+
+    /// Accepts a connection on a listening socket.
+    ///
+    /// Unless `nonblocking` is `true`, this waits for a connection (interruptibly, and subject to
+    /// the receive timeout). Otherwise it fails with `EAGAIN` if none is pending.
+    pub fn accept(&self, nonblocking: bool) -> Result<Socket> {
+        if !nonblocking {
+            crate::might_sleep!();
+        }
+        let flags = if nonblocking { bindings::O_NONBLOCK } else { 0 };
+        let mut new = ptr::null_mut();
+        // SAFETY: The socket is valid by the type invariants, and `new` is a valid location for
+        // the result.
+        to_result(unsafe { bindings::kernel_accept(self.as_raw(), &mut new, flags as _) })?;
+        // INVARIANT: `kernel_accept` succeeded, so `new` is a valid socket that we own, in the
+        // namespace of the listening socket.
+        Ok(Self {
+            sock: NonNull::new(new).ok_or(ENOMEM)?,
+            ns: self.ns.clone(),
+        })
+    }
+
+    /// Connects the socket to a remote address.
+    ///
+    /// Unless `nonblocking` is `true`, this waits for the connection to be established.
+    /// Otherwise it may fail with `EINPROGRESS`.
+    pub fn connect(&self, addr: &SocketAddr, nonblocking: bool) -> Result {
+        if !nonblocking {
+            crate::might_sleep!();
+        }
+        let flags = if nonblocking { bindings::O_NONBLOCK } else { 0 };
+        let (mut raw, len) = addr.to_raw();
+        // SAFETY: The socket is valid by the type invariants, and `raw` holds an address of
+        // length `len`.
+        to_result(unsafe {
+            bindings::kernel_connect(
+                self.as_raw(),
+                ptr::addr_of_mut!(raw).cast(),
+                len,
+                flags as _,
+            )
+        })
+    }
+
+    /// Shuts down one or both directions of a connection.
+    pub fn shutdown(&self, how: Shutdown) -> Result {
+        let how = match how {
+            Shutdown::Read => bindings::sock_shutdown_cmd_SHUT_RD,
+            Shutdown::Write => bindings::sock_shutdown_cmd_SHUT_WR,
+            Shutdown::Both => bindings::sock_shutdown_cmd_SHUT_RDWR,
+        };
+        // SAFETY: The socket is valid by the type invariants.
+        to_result(unsafe { bindings::kernel_sock_shutdown(self.as_raw(), how) })
+    }
+
+    /// Returns the local address of the socket.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        // SAFETY: All-zeroes is a valid `struct sockaddr_storage`.
+        let mut raw = unsafe { MaybeUninit::zeroed().assume_init() };
+        // SAFETY: The socket is valid by the type invariants, and `raw` is large enough for any
+        // address.
+        let len =
+            unsafe { bindings::kernel_getsockname(self.as_raw(), ptr::addr_of_mut!(raw).cast()) };
+        if len < 0 {
+            return Err(Error::from_errno(len));
+        }
+        SocketAddr::from_raw(&raw, len)
+    }
+
+    /// Returns the address of the peer of a connected socket.
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        // SAFETY: All-zeroes is a valid `struct sockaddr_storage`.
+        let mut raw = unsafe { MaybeUninit::zeroed().assume_init() };
+        // SAFETY: The socket is valid by the type invariants, and `raw` is large enough for any
+        // address.
+        let len =
+            unsafe { bindings::kernel_getpeername(self.as_raw(), ptr::addr_of_mut!(raw).cast()) };
+        if len < 0 {
+            return Err(Error::from_errno(len));
+        }
+        SocketAddr::from_raw(&raw, len)
+    }
+
+    fn set_timeout(&self, timeout: Option<Duration>, send: bool) -> Result {
+        let jiffies = match timeout {
+            None => bindings::MAX_SCHEDULE_TIMEOUT as _,
+            Some(t) if t.is_zero() => return Err(EINVAL),
+            Some(t) => {
+                // SAFETY: FFI call with no preconditions.
+                let j =
+                    unsafe { bindings::usecs_to_jiffies(t.as_micros().min(u32::MAX as _) as _) };
+                j.max(1) as _
+            }
+        };
+        // SAFETY: The socket is valid by the type invariants, and kernel sockets always have a
+        // `struct sock`.
+        let sk = unsafe { (*self.as_raw()).sk };
+        // SAFETY: `sk` is valid, and the timeouts are protected by the socket lock.
+        unsafe {
+            bindings::lock_sock_nested(sk, 0);
+            if send {
+                (*sk).sk_sndtimeo = jiffies;
+            } else {
+                (*sk).sk_rcvtimeo = jiffies;
+            }
+            bindings::release_sock(sk);
+        }
+        Ok(())
+    }
+
+    /// Sets how long blocking receive operations (including [`Socket::accept`]) wait before
+    /// failing with `EAGAIN`. `None` means forever, which is the default.
+    ///
+    /// Fails with `EINVAL` for a zero duration.
+    pub fn set_recv_timeout(&self, timeout: Option<Duration>) -> Result {
+        self.set_timeout(timeout, false)
+    }
+
+    /// Sets how long blocking send operations wait before failing with `EAGAIN`. `None` means
+    /// forever, which is the default.
+    ///
+    /// Fails with `EINVAL` for a zero duration.
+    pub fn set_send_timeout(&self, timeout: Option<Duration>) -> Result {
+        self.set_timeout(timeout, true)
+    }
+
+    fn msg_flags(nonblocking: bool) -> c_int {
+        let mut flags = bindings::MSG_NOSIGNAL;
+        if nonblocking {
+            flags |= bindings::MSG_DONTWAIT;
+        }
+        flags as _
+    }
+
+    fn sendmsg(&self, buf: &[u8], addr: Option<&SocketAddr>, nonblocking: bool) -> Result<usize> {
+        if !nonblocking {
+            crate::might_sleep!();
+        }
+        // SAFETY: All-zeroes is a valid `struct msghdr` without a destination address.
+        let mut msg: bindings::msghdr = unsafe { MaybeUninit::zeroed().assume_init() };
+        let mut raw = addr.map(|a| a.to_raw());
+        if let Some((raw, len)) = raw.as_mut() {
+            msg.msg_name = ptr::addr_of_mut!(*raw).cast();
+            msg.msg_namelen = *len;
+        }
+        msg.msg_flags = Self::msg_flags(nonblocking) as _;
+        let mut vec = bindings::kvec {
+            iov_base: buf.as_ptr() as *mut _,
+            iov_len: buf.len(),
+        };
+        // SAFETY: The socket is valid by the type invariants, and `msg` and `vec` describe valid
+        // buffers. `kernel_sendmsg` only reads from `buf`.
+        let ret =
+            unsafe { bindings::kernel_sendmsg(self.as_raw(), &mut msg, &mut vec, 1, buf.len()) };
+        if ret < 0 {
+            return Err(Error::from_errno(ret));
+        }
+        Ok(ret as usize)
+    }
+
+    /// Sends data on a connected socket, and returns how many bytes were sent.
+    ///
+    /// Unless `nonblocking` is `true`, this waits for buffer space (subject to the send timeout).
+    pub fn send(&self, buf: &[u8], nonblocking: bool) -> Result<usize> {
+        self.sendmsg(buf, None, nonblocking)
+    }
+
+    /// Sends a datagram to `addr`, and returns how many bytes were sent.
+    pub fn send_to(&self, buf: &[u8], addr: &SocketAddr, nonblocking: bool) -> Result<usize> {
+        self.sendmsg(buf, Some(addr), nonblocking)
+    }
+
+    fn recvmsg(
+        &self,
+        buf: &mut [u8],
+        addr: Option<&mut bindings::__kernel_sockaddr_storage>,
+        nonblocking: bool,
+    ) -> Result<(usize, c_int)> {
+        if !nonblocking {
+            crate::might_sleep!();
+        }
+        // SAFETY: All-zeroes is a valid `struct msghdr` without a source address.
+        let mut msg: bindings::msghdr = unsafe { MaybeUninit::zeroed().assume_init() };
+        if let Some(addr) = addr {
+            msg.msg_name = (addr as *mut bindings::__kernel_sockaddr_storage).cast();
+            msg.msg_namelen = size_of::<bindings::__kernel_sockaddr_storage>() as _;
+        }
+        let mut vec = bindings::kvec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
+        };
+        // SAFETY: The socket is valid by the type invariants, and `msg` and `vec` describe valid
+        // buffers.
+        let ret = unsafe {
+            bindings::kernel_recvmsg(
+                self.as_raw(),
+                &mut msg,
+                &mut vec,
+                1,
+                buf.len(),
+                Self::msg_flags(nonblocking),
+            )
+        };
+        if ret < 0 {
+            return Err(Error::from_errno(ret));
+        }
+        Ok((ret as usize, msg.msg_namelen))
+    }
+
+    /// Receives data from a connected socket, and returns how many bytes were received.
+    ///
+    /// A return value of zero means that the peer closed the connection. Unless `nonblocking` is
+    /// `true`, this waits for data (subject to the receive timeout).
+    pub fn recv(&self, buf: &mut [u8], nonblocking: bool) -> Result<usize> {
+        Ok(self.recvmsg(buf, None, nonblocking)?.0)
+    }
+
+    /// Receives a datagram, and returns how many bytes were received and where they came from.
+    pub fn recv_from(&self, buf: &mut [u8], nonblocking: bool) -> Result<(usize, SocketAddr)> {
+        // SAFETY: All-zeroes is a valid `struct sockaddr_storage`.
+        let mut raw = unsafe { MaybeUninit::zeroed().assume_init() };
+        let (len, addr_len) = self.recvmsg(buf, Some(&mut raw), nonblocking)?;
+        Ok((len, SocketAddr::from_raw(&raw, addr_len)?))
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        // SAFETY: We own the socket by the type invariants. Its namespace is only released after
+        // this, when `ns` is dropped.
+        unsafe { bindings::sock_release(self.as_raw()) };
+    }
+}
@@ -0,0 +1,401 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Notifier chains.
+//!
+//! Notifier chains are lists of callbacks that a subsystem calls when an event happens, e.g. on
+//! reboot, on panic or when the rate of a clock changes. Drivers add a [`Handler`] to an existing
+//! chain with a [`Registration`], and subsystems written in Rust can create their own chains with
+//! [`AtomicHead`], [`BlockingHead`] or [`SrcuHead`].
+//!
+//! C header: [`include/linux/notifier.h`](../../../../include/linux/notifier.h)
+
+use crate::{
+    bindings,
+    error::{to_result, Error, Result},
+    init::{self, PinInit},
+    str::CStr,
+    sync::LockClassKey,
+    types::Opaque,
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::{c_int, c_ulong, c_void},
+    marker::PhantomPinned,
+    pin::Pin,
+    ptr,
+};
+
+/// The result of a notifier callback.
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Notify {
+    /// The event is of no interest to the callback.
+    Done = bindings::NOTIFY_DONE as i32,
+    /// The event was handled.
+    Ok = bindings::NOTIFY_OK as i32,
+    /// The event was handled, and callbacks further down the chain must not be called.
+    Stop = bindings::NOTIFY_STOP as i32,
+    /// The callback vetoes the action, callbacks further down the chain are not called.
+    Bad = bindings::NOTIFY_BAD as i32,
+}
+
+/// Converts the result of a callback into a `NOTIFY_*` value, like `notifier_from_errno` in C.
+fn notifier_from_result(r: Result<Notify>) -> c_int {
+    match r {
+        Ok(n) => n as c_int,
+        Err(e) => {
+            (bindings::NOTIFY_STOP_MASK as c_int) | (bindings::NOTIFY_OK as c_int - e.to_errno())
+        }
+    }
+}
+
+/// Converts the value returned by a chain into an error, like `notifier_to_errno` in C.
+fn notifier_to_result(ret: c_int) -> Result {
+    let ret = ret & !(bindings::NOTIFY_STOP_MASK as c_int);
+    if ret > bindings::NOTIFY_OK as c_int {
+        Err(Error::from_errno(bindings::NOTIFY_OK as c_int - ret))
+    } else {
+        Ok(())
+    }
+}
+
+/// A callback that can be added to a notifier chain.
+///
+/// The meaning of `action` and `data` is defined by the chain the handler is registered with.
+pub trait Handler: Send + Sync + 'static {
+    /// Called when an event is reported on the chain.
+    ///
+    /// Handlers on an [`AtomicHead`] are called in atomic context and must not sleep.
+    ///
+    /// Returning an error stops the chain and reports the error to the caller of the chain.
+    fn notify(&self, action: c_ulong, data: *mut c_void) -> Result<Notify>;
+}
+
+/// The head of a notifier chain.
+pub trait Head: Sync {
+    /// Adds `nb` to the chain.
+    ///
+    /// # Safety
+    ///
+    /// `nb` must be valid and remain at the same address until it is removed with
+    /// [`Head::unregister`].
+    unsafe fn register(&self, nb: *mut bindings::notifier_block) -> Result;
+
+    /// Removes `nb` from the chain.
+    ///
+    /// # Safety
+    ///
+    /// `nb` must have been added to the chain with [`Head::register`].
+    unsafe fn unregister(&self, nb: *mut bindings::notifier_block);
+}
+
+macro_rules! impl_head {
+    ($(#[$meta:meta])* $name:ident, $raw:ident, $register:ident, $unregister:ident) => {
+        $(#[$meta])*
+        #[repr(transparent)]
+        pub struct $name {
+            head: Opaque<bindings::$raw>,
+            _pin: PhantomPinned,
+        }
+
+        // SAFETY: The chain is protected by its own lock.
+        unsafe impl Send for $name {}
+
+        // SAFETY: The chain is protected by its own lock.
+        unsafe impl Sync for $name {}
+
+        impl $name {
+            #[doc = concat!(
+                "Creates a reference to a chain from a raw `struct ", stringify!($raw), "`."
+            )]
+            ///
+            /// This is used to register handlers with chains owned by C code.
+            ///
+            /// # Safety
+            ///
+            /// Callers must ensure that `ptr` points to an initialised chain head that remains
+            /// valid for the lifetime `'a`.
+            pub unsafe fn from_raw<'a>(ptr: *mut bindings::$raw) -> &'a Self {
+                // SAFETY: `Self` is transparent over the raw head, and the caller guarantees that
+                // it is valid for `'a`.
+                unsafe { &*ptr.cast() }
+            }
+
+            /// Returns the raw chain head.
+            pub fn as_raw(&self) -> *mut bindings::$raw {
+                self.head.get()
+            }
+        }
+
+        impl Head for $name {
+            unsafe fn register(&self, nb: *mut bindings::notifier_block) -> Result {
+                // SAFETY: The head is initialised, and the caller guarantees that `nb` is valid.
+                to_result(unsafe { bindings::$register(self.as_raw(), nb) })
+            }
+
+            unsafe fn unregister(&self, nb: *mut bindings::notifier_block) {
+                // SAFETY: The head is initialised, and the caller guarantees that `nb` is on it.
+                unsafe { bindings::$unregister(self.as_raw(), nb) };
+            }
+        }
+    };
+}
+
+impl_head!(
+    /// A notifier chain whose callbacks run in atomic context, the equivalent of
+    /// `struct atomic_notifier_head`.
+    ///
+    /// Use [`new_atomic_notifier_head!`] to create one.
+    ///
+    /// [`new_atomic_notifier_head!`]: crate::new_atomic_notifier_head
+    AtomicHead,
+    atomic_notifier_head,
+    atomic_notifier_chain_register,
+    atomic_notifier_chain_unregister
+);
+
+impl_head!(
+    /// A notifier chain whose callbacks run in process context and may sleep, the equivalent of
+    /// `struct blocking_notifier_head`.
+    ///
+    /// Use [`new_blocking_notifier_head!`] to create one.
+    ///
+    /// [`new_blocking_notifier_head!`]: crate::new_blocking_notifier_head
+    BlockingHead,
+    blocking_notifier_head,
+    blocking_notifier_chain_register,
+    blocking_notifier_chain_unregister
+);
+
+impl_head!(
+    /// A notifier chain protected by SRCU, the equivalent of `struct srcu_notifier_head`.
+    ///
+    /// Callbacks run in process context and may sleep; calling the chain is cheaper than with a
+    /// [`BlockingHead`], at the expense of slower registration.
+    SrcuHead,
+    srcu_notifier_head,
+    srcu_notifier_chain_register,
+    srcu_notifier_chain_unregister
+);
+
+impl AtomicHead {
+    /// Creates a new, empty chain.
+    ///
+    /// The chain's spinlock is given the lockdep `name` and class `key`.
+    pub fn new(name: &'static CStr, key: &'static LockClassKey) -> impl PinInit<Self> {
+        // SAFETY: The closure initialises all fields of the head and never fails.
+        unsafe {
+            init::pin_init_from_closure::<_, core::convert::Infallible>(move |slot: *mut Self| {
+                let head = Opaque::raw_get(ptr::addr_of!((*slot).head));
+                ptr::addr_of_mut!((*head).head).write(ptr::null_mut());
+                bindings::__spin_lock_init(
+                    ptr::addr_of_mut!((*head).lock),
+                    name.as_char_ptr(),
+                    key.as_ptr(),
+                );
+                Ok(())
+            })
+        }
+    }
+
+    /// Calls the handlers on the chain with the given `action` and `data`.
+    ///
+    /// This may be called from atomic context.
+    pub fn call_chain(&self, action: c_ulong, data: *mut c_void) -> Result {
+        // SAFETY: The head is initialised.
+        notifier_to_result(unsafe {
+            bindings::atomic_notifier_call_chain(self.as_raw(), action, data)
+        })
+    }
+}
+
+impl BlockingHead {
+    /// Creates a new, empty chain.
+    ///
+    /// The chain's semaphore is given the lockdep `name` and class `key`.
+    pub fn new(name: &'static CStr, key: &'static LockClassKey) -> impl PinInit<Self> {
+        // SAFETY: The closure initialises all fields of the head and never fails.
+        unsafe {
+            init::pin_init_from_closure::<_, core::convert::Infallible>(move |slot: *mut Self| {
+                let head = Opaque::raw_get(ptr::addr_of!((*slot).head));
+                ptr::addr_of_mut!((*head).head).write(ptr::null_mut());
+                bindings::__init_rwsem(
+                    ptr::addr_of_mut!((*head).rwsem),
+                    name.as_char_ptr(),
+                    key.as_ptr(),
+                );
+                Ok(())
+            })
+        }
+    }
+
+    /// Calls the handlers on the chain with the given `action` and `data`.
+    pub fn call_chain(&self, action: c_ulong, data: *mut c_void) -> Result {
+        crate::might_sleep!();
+        // SAFETY: The head is initialised.
+        notifier_to_result(unsafe {
+            bindings::blocking_notifier_call_chain(self.as_raw(), action, data)
+        })
+    }
+}
+
+impl SrcuHead {
+    /// Creates a new, empty chain.
+    pub fn new() -> impl PinInit<Self> {
+        // SAFETY: The closure initialises the head and never fails.
+        unsafe {
+            init::pin_init_from_closure::<_, core::convert::Infallible>(move |slot: *mut Self| {
+                let head = Opaque::raw_get(ptr::addr_of!((*slot).head));
+                bindings::srcu_init_notifier_head(head);
+                Ok(())
+            })
+        }
+    }
+
+    /// Calls the handlers on the chain with the given `action` and `data`.
+    pub fn call_chain(&self, action: c_ulong, data: *mut c_void) -> Result {
+        crate::might_sleep!();
+        // SAFETY: The head is initialised.
+        notifier_to_result(unsafe {
+            bindings::srcu_notifier_call_chain(self.as_raw(), action, data)
+        })
+    }
+}
+
+impl Drop for SrcuHead {
+    fn drop(&mut self) {
+        // SAFETY: The head was initialised with `srcu_init_notifier_head`, and handlers borrow the
+        // head, so none are registered anymore.
+        unsafe { bindings::cleanup_srcu_struct(ptr::addr_of_mut!((*self.as_raw()).srcu)) };
+    }
+}
+
+/// Creates an [`AtomicHead`] initialiser with the given name and a newly-created lock class.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{new_atomic_notifier_head, notifier::AtomicHead};
+/// # use kernel::prelude::*;
+///
+/// let chain = Box::pin_init(new_atomic_notifier_head!("my_driver::events"))?;
+/// chain.call_chain(0, core::ptr::null_mut())?;
+/// # Ok::<(), Error>(())
+/// ```
+#[macro_export]
+macro_rules! new_atomic_notifier_head {
+    ($($name:literal)?) => {
+        $crate::notifier::AtomicHead::new(
+            $crate::optional_name!($($name)?),
+            $crate::static_lock_class!(),
+        )
+    };
+}
+
+/// Creates a [`BlockingHead`] initialiser with the given name and a newly-created lock class.
+#[macro_export]
+macro_rules! new_blocking_notifier_head {
+    ($($name:literal)?) => {
+        $crate::notifier::BlockingHead::new(
+            $crate::optional_name!($($name)?),
+            $crate::static_lock_class!(),
+        )
+    };
+}
+
+#[repr(C)]
+struct Block<T> {
+    // Must be the first field, see `notifier_call`.
+    nb: Opaque<bindings::notifier_block>,
+    handler: T,
+    _pin: PhantomPinned,
+}
+
+/// A handler registered with a notifier chain.
+///
+/// The handler is removed from the chain when this is dropped.
+///
+/// # Invariants
+///
+/// `block.nb` is on the chain `head`.
+///
+/// # Examples
+///
+/// ```
+/// use core::ffi::{c_ulong, c_void};
+/// use kernel::notifier::{self, Handler, Notify};
+/// # use kernel::prelude::*;
+///
+/// struct Logger;
+///
+/// impl Handler for Logger {
+///     fn notify(&self, action: c_ulong, _data: *mut c_void) -> Result<Notify> {
+///         pr_info!("event {}\n", action);
+///         Ok(Notify::Ok)
+///     }
+/// }
+///
+/// fn listen(
+///     chain: &notifier::BlockingHead,
+/// ) -> Result<notifier::Registration<'_, notifier::BlockingHead, Logger>> {
+///     notifier::Registration::try_new(chain, Logger, 0)
+/// }
+/// ```
+pub struct Registration<'a, H: Head, T: Handler> {
+    block: Pin<Box<Block<T>>>,
+    head: &'a H,
+}
+
+impl<'a, H: Head, T: Handler> Registration<'a, H, T> {
+    /// Adds `handler` to the chain `head`.
+    ///
+    /// Handlers with a higher `priority` are called first.
+    pub fn try_new(head: &'a H, handler: T, priority: i32) -> Result<Self> {
+        let block = Box::try_new(Block {
+            nb: Opaque::new(bindings::notifier_block {
+                notifier_call: Some(Self::notifier_call),
+                next: ptr::null_mut(),
+                priority,
+            }),
+            handler,
+            _pin: PhantomPinned,
+        })?;
+        let block = Pin::from(block);
+        // SAFETY: The block is pinned, and it is removed from the chain on drop before it is
+        // freed.
+        unsafe { head.register(block.nb.get()) }?;
+        // INVARIANT: The block was added to the chain above.
+        Ok(Self { block, head })
+    }
+
+    /// Returns the registered handler.
+    pub fn handler(&self) -> &T {
+        &self.block.handler
+    }
+
+    unsafe extern "C" fn notifier_call(
+        nb: *mut bindings::notifier_block,
+        action: c_ulong,
+        data: *mut c_void,
+    ) -> c_int {
+        // SAFETY: `nb` is the first field of a `Block<T>`, which is `repr(C)`, and the block is
+        // alive while it is on the chain.
+        let block = unsafe { &*(nb as *const Block<T>) };
+        notifier_from_result(block.handler.notify(action, data))
+    }
+}
+
+impl<H: Head, T: Handler> Drop for Registration<'_, H, T> {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, the block is on the chain. Removing it waits for
+        // concurrent calls of the chain to complete, so the handler can be freed afterwards.
+        unsafe { self.head.unregister(self.block.nb.get()) };
+    }
+}
+
+// SAFETY: The registration only hands out shared references to the handler, which is `Send`, and
+// it can be dropped from any thread.
+unsafe impl<H: Head, T: Handler> Send for Registration<'_, H, T> {}
+
+// SAFETY: The registration only hands out shared references to the handler, which is `Sync`.
+unsafe impl<H: Head, T: Handler> Sync for Registration<'_, H, T> {}
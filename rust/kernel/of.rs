@@ -0,0 +1,293 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Devicetree and Open Firmware abstractions.
+//!
+//! C headers: [`include/linux/of.h`](../../../../include/linux/of.h) and
+//! [`include/linux/of_reserved_mem.h`](../../../../include/linux/of_reserved_mem.h)
+
+use crate::{
+    bindings,
+    error::{to_result, Result},
+    str::CStr,
+    types::{ARef, AlwaysRefCounted, Opaque},
+};
+use core::{ffi::c_char, mem::MaybeUninit, ptr};
+
+/// A reference-counted devicetree node.
+///
+/// Wraps the kernel's `struct device_node`.
+///
+/// # Invariants
+///
+/// Instances are always ref-counted, that is, a call to `of_node_get` ensures that the allocation
+/// remains valid at least until the matching call to `of_node_put`.
+#[repr(transparent)]
+pub struct DeviceNode(Opaque<bindings::device_node>);
+
+// SAFETY: Devicetree nodes are reference-counted and can be released from any thread.
+unsafe impl Send for DeviceNode {}
+
+// SAFETY: The accessors below only read properties, which are protected by the devicetree locks
+// on the C side.
+unsafe impl Sync for DeviceNode {}
+
+impl DeviceNode {
+    /// Creates a reference to a [`DeviceNode`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is valid, non-null, and has a non-zero reference count for
+    /// the entire duration when the returned reference exists.
+    pub unsafe fn as_ref<'a>(ptr: *mut bindings::device_node) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Takes over a reference to a node returned by a C function.
+    ///
+    /// Returns `None` if `ptr` is null.
+    ///
+    /// # Safety
+    ///
+    /// If non-null, `ptr` must be valid and the caller must own a reference to it which is
+    /// transferred to the returned object.
+    pub(crate) unsafe fn from_raw_owned(ptr: *mut bindings::device_node) -> Option<ARef<Self>> {
+        let ptr = ptr::NonNull::new(ptr)?;
+        // SAFETY: The safety requirements guarantee that we own a reference to the non-null `ptr`,
+        // and `DeviceNode` is `repr(transparent)`.
+        Some(unsafe { ARef::from_raw(ptr.cast()) })
+    }
+
+    /// Returns the raw `struct device_node` pointer.
+    pub fn as_raw(&self) -> *mut bindings::device_node {
+        self.0.get()
+    }
+
+    /// Returns the name of the node.
+    pub fn name(&self) -> &CStr {
+        // SAFETY: By the type invariants the node is valid, and nodes always have a name.
+        unsafe { CStr::from_char_ptr((*self.as_raw()).name) }
+    }
+
+    /// Returns the full path of the node (its `full_name`).
+    pub fn full_name(&self) -> &CStr {
+        // SAFETY: By the type invariants the node is valid, and nodes always have a full name.
+        unsafe { CStr::from_char_ptr((*self.as_raw()).full_name) }
+    }
+
+    /// Returns `true` if the node's `compatible` property contains `compat`.
+    pub fn is_compatible(&self, compat: &CStr) -> bool {
+        // SAFETY: The node is valid by the type invariants and `compat` is `NUL`-terminated.
+        unsafe { bindings::of_device_is_compatible(self.as_raw(), compat.as_char_ptr()) > 0 }
+    }
+
+    /// Returns `true` if the node has a property called `name`.
+    pub fn has_property(&self, name: &CStr) -> bool {
+        // SAFETY: The node is valid by the type invariants and `name` is `NUL`-terminated.
+        !unsafe { bindings::of_find_property(self.as_raw(), name.as_char_ptr(), ptr::null_mut()) }
+            .is_null()
+    }
+
+    /// Reads the `u32` value of the property called `name`.
+    pub fn read_u32(&self, name: &CStr) -> Result<u32> {
+        let mut value = 0u32;
+        // SAFETY: The node is valid by the type invariants, `name` is `NUL`-terminated and
+        // `value` is valid for writes of one element.
+        to_result(unsafe {
+            bindings::of_property_read_variable_u32_array(
+                self.as_raw(),
+                name.as_char_ptr(),
+                &mut value,
+                1,
+                0,
+            )
+        })?;
+        Ok(value)
+    }
+
+    /// Resolves the `index`-th phandle in the property called `name`.
+    pub fn parse_phandle(&self, name: &CStr, index: u32) -> Option<ARef<DeviceNode>> {
+        // SAFETY: The node is valid by the type invariants and `name` is `NUL`-terminated.
+        let np =
+            unsafe { bindings::of_parse_phandle(self.as_raw(), name.as_char_ptr(), index as _) };
+        // SAFETY: `of_parse_phandle` returns either null or a node with its refcount incremented.
+        unsafe { Self::from_raw_owned(np) }
+    }
+}
+
+// SAFETY: Instances of `DeviceNode` are always ref-counted.
+unsafe impl AlwaysRefCounted for DeviceNode {
+    fn inc_ref(&self) {
+        // SAFETY: The existence of a shared reference means that the refcount is nonzero.
+        unsafe { bindings::of_node_get(self.as_raw()) };
+    }
+
+    unsafe fn dec_ref(obj: ptr::NonNull<Self>) {
+        // SAFETY: The safety requirements guarantee that the refcount is nonzero.
+        unsafe { bindings::of_node_put(obj.cast().as_ptr()) }
+    }
+}
+
+/// An entry of a devicetree match table, the kernel's `struct of_device_id`.
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+pub struct DeviceId(bindings::of_device_id);
+
+impl DeviceId {
+    /// The all-zeroes entry that terminates a match table.
+    // SAFETY: All fields are arrays of integers or pointers, for which zero is valid.
+    pub const END: Self = Self(unsafe { MaybeUninit::zeroed().assume_init() });
+
+    /// Creates an entry that matches the nodes compatible with `compatible`.
+    ///
+    /// Fails to build if `compatible` doesn't fit in an entry.
+    pub const fn new(compatible: &'static CStr) -> Self {
+        let mut id = Self::END;
+        let bytes = compatible.as_bytes_with_nul();
+        if bytes.len() > id.0.compatible.len() {
+            panic!("compatible string too long");
+        }
+        let mut i = 0;
+        while i < bytes.len() {
+            id.0.compatible[i] = bytes[i] as c_char;
+            i += 1;
+        }
+        id
+    }
+
+    const fn is_end(&self) -> bool {
+        self.0.name[0] == 0 && self.0.type_[0] == 0 && self.0.compatible[0] == 0
+    }
+}
+
+// SAFETY: The `data` pointer of entries is always null, so they can be shared between threads.
+unsafe impl Sync for DeviceId {}
+
+/// The `of_match_table` of a driver, an array of [`DeviceId`] terminated by [`DeviceId::END`].
+///
+/// Tables are built at compile time, usually with [`of_id_table`](crate::of_id_table).
+///
+/// # Invariants
+///
+/// The last entry of the table is [`DeviceId::END`].
+#[derive(Clone, Copy)]
+pub struct IdTable(&'static [DeviceId]);
+
+impl IdTable {
+    /// Creates a table from `ids`.
+    ///
+    /// Fails to build if the last entry of `ids` isn't [`DeviceId::END`].
+    pub const fn new(ids: &'static [DeviceId]) -> Self {
+        if ids.is_empty() || !ids[ids.len() - 1].is_end() {
+            panic!("match table must be terminated by `DeviceId::END`");
+        }
+        // INVARIANT: The last entry was checked above.
+        Self(ids)
+    }
+
+    /// Returns the number of entries of the table, including the terminating one.
+    pub const fn len_with_end(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns a copy of the table as an array, e.g. for the OF device table of a module, which
+    /// has the layout of the kernel's `struct of_device_id` array.
+    ///
+    /// Fails to build if `N` isn't the length of the table.
+    pub const fn to_array<const N: usize>(&self) -> [DeviceId; N] {
+        if N != self.0.len() {
+            panic!("wrong match table length");
+        }
+        let mut ids = [DeviceId::END; N];
+        let mut i = 0;
+        while i < N {
+            ids[i] = self.0[i];
+            i += 1;
+        }
+        ids
+    }
+
+    /// Returns a pointer to the table, which is valid for the lifetime of the kernel.
+    pub(crate) const fn as_ptr(&self) -> *const bindings::of_device_id {
+        self.0.as_ptr().cast()
+    }
+}
+
+/// Builds an [`IdTable`] at compile time that matches the nodes compatible with one of the given
+/// strings.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, of};
+///
+/// const TABLE: of::IdTable = kernel::of_id_table![c_str!("vendor,a"), c_str!("vendor,b")];
+/// assert_eq!(TABLE.len_with_end(), 3);
+/// ```
+#[macro_export]
+macro_rules! of_id_table {
+    ($($compatible:expr),+ $(,)?) => {{
+        const IDS: &[$crate::of::DeviceId] = &[
+            $($crate::of::DeviceId::new($compatible),)+
+            $crate::of::DeviceId::END,
+        ];
+        $crate::of::IdTable::new(IDS)
+    }};
+}
+
+/// A reserved memory region declared under the devicetree's `/reserved-memory` node.
+///
+/// Wraps the kernel's `struct reserved_mem`. Regions are set up during early boot and are never
+/// freed, so references to them are `'static`.
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::{c_str, of::{DeviceNode, ReservedMem}, prelude::*};
+/// fn carveout(np: &DeviceNode) -> Result<(u64, usize)> {
+///     let node = np.parse_phandle(c_str!("memory-region"), 0).ok_or(ENODEV)?;
+///     let rmem = ReservedMem::lookup(&node).ok_or(ENODEV)?;
+///     Ok((rmem.base() as u64, rmem.size()))
+/// }
+/// ```
+#[cfg(CONFIG_OF_RESERVED_MEM)]
+#[repr(transparent)]
+pub struct ReservedMem(Opaque<bindings::reserved_mem>);
+
+#[cfg(CONFIG_OF_RESERVED_MEM)]
+// SAFETY: Reserved memory descriptors are immutable after early boot.
+unsafe impl Sync for ReservedMem {}
+
+#[cfg(CONFIG_OF_RESERVED_MEM)]
+impl ReservedMem {
+    /// Looks up the reserved memory region described by the node `np`.
+    pub fn lookup(np: &DeviceNode) -> Option<&'static ReservedMem> {
+        // SAFETY: `np` is valid by the type invariants.
+        let rmem = unsafe { bindings::of_reserved_mem_lookup(np.as_raw()) };
+        if rmem.is_null() {
+            None
+        } else {
+            // SAFETY: `rmem` points into the static array of reserved regions, which is never
+            // freed, and `ReservedMem` is `repr(transparent)`.
+            Some(unsafe { &*rmem.cast() })
+        }
+    }
+
+    /// Returns the name of the region.
+    pub fn name(&self) -> &CStr {
+        // SAFETY: The region is valid and its name was set when it was reserved.
+        unsafe { CStr::from_char_ptr((*self.0.get()).name) }
+    }
+
+    /// Returns the physical base address of the region.
+    pub fn base(&self) -> bindings::phys_addr_t {
+        // SAFETY: The region is valid and immutable.
+        unsafe { (*self.0.get()).base }
+    }
+
+    /// Returns the size of the region in bytes.
+    pub fn size(&self) -> usize {
+        // SAFETY: The region is valid and immutable.
+        unsafe { (*self.0.get()).size as _ }
+    }
+}
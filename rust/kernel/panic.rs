@@ -0,0 +1,37 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Kernel panics.
+//!
+//! C header: [`include/linux/panic_notifier.h`](../../../../include/linux/panic_notifier.h)
+
+use crate::{bindings, notifier::AtomicHead};
+
+/// Returns the notifier chain that is called when the kernel panics.
+///
+/// The handlers are called with the panic message, a C string, as the data, after the other CPUs
+/// were stopped, and in atomic context. They must neither sleep, allocate nor take locks that the
+/// interrupted code may hold.
+///
+/// # Examples
+///
+/// ```
+/// use core::ffi::{c_ulong, c_void};
+/// use kernel::{notifier, panic, prelude::*};
+///
+/// struct Watchdog;
+///
+/// impl notifier::Handler for Watchdog {
+///     fn notify(&self, _action: c_ulong, _data: *mut c_void) -> Result<notifier::Notify> {
+///         // Stop the watchdog, so that the panic message can be read before the reboot.
+///         Ok(notifier::Notify::Done)
+///     }
+/// }
+///
+/// fn listen() -> Result<notifier::Registration<'static, notifier::AtomicHead, Watchdog>> {
+///     notifier::Registration::try_new(panic::notifier_chain(), Watchdog, 0)
+/// }
+/// ```
+pub fn notifier_chain() -> &'static AtomicHead {
+    // SAFETY: `panic_notifier_list` is a static chain that is initialised at build time.
+    unsafe { AtomicHead::from_raw(core::ptr::addr_of_mut!(bindings::panic_notifier_list)) }
+}
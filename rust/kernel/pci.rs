@@ -0,0 +1,228 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! PCI devices and their interrupt vectors.
+//!
+//! Devices with MSI or MSI-X allocate a range of interrupt vectors with
+//! [`Device::alloc_irq_vectors`], usually one per queue plus a few for the rest of the device, and
+//! request the Linux interrupt of each vector, [`IrqVectors::vector`], with an
+//! [`irq::Registration`](crate::irq::Registration). An [`Affinity`] has the vectors of the queues
+//! spread over the CPUs by the core, so that no affinity hints are needed.
+//!
+//! C header: [`include/linux/pci.h`](../../../../include/linux/pci.h)
+
+use crate::{
+    bindings, device,
+    types::{AlwaysRefCounted, Opaque},
+};
+use core::ptr;
+
+#[cfg(CONFIG_PCI_MSI)]
+use crate::{
+    error::{Error, Result},
+    types::ARef,
+};
+#[cfg(CONFIG_PCI_MSI)]
+use core::mem::MaybeUninit;
+
+/// The kinds of interrupt vectors, the kernel's `PCI_IRQ_*`, see [`Device::alloc_irq_vectors`].
+pub mod irq_type {
+    /// The legacy INTx interrupt, a single vector that may be shared with other devices.
+    pub const LEGACY: u32 = crate::bindings::PCI_IRQ_LEGACY;
+    /// Message signalled interrupts.
+    pub const MSI: u32 = crate::bindings::PCI_IRQ_MSI;
+    /// Extended message signalled interrupts.
+    pub const MSIX: u32 = crate::bindings::PCI_IRQ_MSIX;
+    /// Any of the above, MSI-X preferred over MSI, and MSI over the legacy interrupt.
+    pub const ALL_TYPES: u32 = crate::bindings::PCI_IRQ_ALL_TYPES;
+}
+
+/// A device on a PCI bus, the kernel's `struct pci_dev`.
+///
+/// # Invariants
+///
+/// The device is valid while references to it exist, and is reference-counted through its
+/// device.
+#[repr(transparent)]
+pub struct Device(Opaque<bindings::pci_dev>);
+
+impl Device {
+    /// Creates a reference to a [`Device`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is valid, non-null, and has a non-zero reference count for
+    /// the entire duration when the returned reference exists.
+    pub unsafe fn as_ref<'a>(ptr: *mut bindings::pci_dev) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct pci_dev` pointer.
+    pub fn as_raw(&self) -> *mut bindings::pci_dev {
+        self.0.get()
+    }
+
+    /// Returns the generic device of the PCI device.
+    pub fn device(&self) -> &device::Device {
+        // SAFETY: The device is valid by the type invariants, and so is its generic device.
+        unsafe { device::Device::as_ref(ptr::addr_of_mut!((*self.as_raw()).dev)) }
+    }
+
+    /// Returns the vendor id of the device.
+    pub fn vendor_id(&self) -> u16 {
+        // SAFETY: The device is valid by the type invariants, and its vendor id never changes.
+        unsafe { (*self.as_raw()).vendor }
+    }
+
+    /// Returns the device id of the device.
+    pub fn device_id(&self) -> u16 {
+        // SAFETY: The device is valid by the type invariants, and its device id never changes.
+        unsafe { (*self.as_raw()).device }
+    }
+
+    /// Allocates between `min` and `max` interrupt vectors of one of the kinds in `types`, a
+    /// combination of [`irq_type`] flags.
+    ///
+    /// The core tries MSI-X first, then MSI, then the legacy interrupt, and allocates as many
+    /// vectors as the device and the platform support, failing with `ENOSPC` if that is fewer
+    /// than `min`. With an `affinity`, the vectors that aren't reserved by it are spread over the
+    /// CPUs and their affinity is managed by the core.
+    ///
+    /// This may sleep.
+    #[cfg(CONFIG_PCI_MSI)]
+    pub fn alloc_irq_vectors(
+        &self,
+        min: u32,
+        max: u32,
+        types: u32,
+        affinity: Option<&Affinity>,
+    ) -> Result<IrqVectors> {
+        crate::might_sleep!();
+        let mut affd;
+        let (flags, affd_ptr) = match affinity {
+            Some(affinity) => {
+                affd = affinity.to_raw();
+                (types | bindings::PCI_IRQ_AFFINITY, ptr::addr_of_mut!(affd))
+            }
+            None => (types, ptr::null_mut()),
+        };
+        // SAFETY: The device is valid by the type invariants, and `affd_ptr` is either null or
+        // points to an affinity descriptor that the function only reads.
+        let ret = unsafe {
+            bindings::pci_alloc_irq_vectors_affinity(self.as_raw(), min, max, flags, affd_ptr)
+        };
+        if ret < 0 {
+            return Err(Error::from_errno(ret));
+        }
+        // INVARIANT: `ret` vectors were just allocated.
+        Ok(IrqVectors {
+            dev: self.into(),
+            count: ret as u32,
+        })
+    }
+}
+
+// SAFETY: Instances of `Device` are always reference-counted.
+unsafe impl AlwaysRefCounted for Device {
+    fn inc_ref(&self) {
+        // SAFETY: The existence of a shared reference guarantees that the refcount is non-zero.
+        unsafe { bindings::pci_dev_get(self.as_raw()) };
+    }
+
+    unsafe fn dec_ref(obj: ptr::NonNull<Self>) {
+        // SAFETY: The safety requirements guarantee that the refcount is non-zero.
+        unsafe { bindings::pci_dev_put(obj.cast().as_ptr()) }
+    }
+}
+
+// SAFETY: The device is reference-counted, and can be used from any thread.
+unsafe impl Send for Device {}
+
+// SAFETY: The methods that take `&self` only read fields that never change once the device is
+// added.
+unsafe impl Sync for Device {}
+
+/// How the core spreads interrupt vectors over the CPUs, the kernel's `struct irq_affinity`.
+///
+/// The first `pre_vectors` and the last `post_vectors` vectors are left alone, e.g. for the
+/// configuration or admin interrupts, and the others are spread over the CPUs.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Affinity {
+    /// The number of vectors at the start of the range that aren't spread.
+    pub pre_vectors: u32,
+    /// The number of vectors at the end of the range that aren't spread.
+    pub post_vectors: u32,
+}
+
+impl Affinity {
+    #[cfg(CONFIG_PCI_MSI)]
+    fn to_raw(self) -> bindings::irq_affinity {
+        // SAFETY: The descriptor is plain data, for which zero is valid and means a single set
+        // of spread vectors.
+        let mut affd: bindings::irq_affinity = unsafe { MaybeUninit::zeroed().assume_init() };
+        affd.pre_vectors = self.pre_vectors;
+        affd.post_vectors = self.post_vectors;
+        affd
+    }
+}
+
+/// The interrupt vectors of a PCI device, allocated by [`Device::alloc_irq_vectors`].
+///
+/// The vectors are freed when the object is dropped, so the interrupts requested on them must be
+/// freed first, i.e. their registrations must be dropped before this object.
+///
+/// # Invariants
+///
+/// `count` vectors are allocated for `dev`.
+///
+/// # Examples
+///
+/// A device with an admin interrupt and one interrupt per queue:
+///
+/// ```
+/// use kernel::{pci::{self, irq_type, Affinity, IrqVectors}, prelude::*};
+///
+/// fn alloc_queue_vectors(dev: &pci::Device, queues: u32) -> Result<IrqVectors> {
+///     let affinity = Affinity {
+///         pre_vectors: 1,
+///         post_vectors: 0,
+///     };
+///     let vectors = dev.alloc_irq_vectors(2, queues + 1, irq_type::ALL_TYPES, Some(&affinity))?;
+///     pr_info!("{} queues, admin irq {}\n", vectors.count() - 1, vectors.vector(0)?);
+///     Ok(vectors)
+/// }
+/// ```
+#[cfg(CONFIG_PCI_MSI)]
+pub struct IrqVectors {
+    dev: ARef<Device>,
+    count: u32,
+}
+
+#[cfg(CONFIG_PCI_MSI)]
+impl IrqVectors {
+    /// Returns the number of allocated vectors.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Returns the Linux interrupt of the vector `index`.
+    ///
+    /// Fails with `EINVAL` if `index` is out of the range of the vectors.
+    pub fn vector(&self, index: u32) -> Result<u32> {
+        // SAFETY: The device is valid by the type invariants of `ARef`.
+        let irq = unsafe { bindings::pci_irq_vector(self.dev.as_raw(), index) };
+        if irq < 0 {
+            Err(Error::from_errno(irq))
+        } else {
+            Ok(irq as u32)
+        }
+    }
+}
+
+#[cfg(CONFIG_PCI_MSI)]
+impl Drop for IrqVectors {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, vectors are allocated for the device.
+        unsafe { bindings::pci_free_irq_vectors(self.dev.as_raw()) };
+    }
+}
@@ -0,0 +1,363 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Kernel performance counters.
+//!
+//! A driver creates a [`Counter`] on a CPU or a task to count hardware events, e.g. the cycles or
+//! cache misses of a hot path, or software events. Counters that sample, i.e. that have a
+//! sample period, call an [`Overflow`] handler each time the period elapses.
+//!
+//! C header: [`include/linux/perf_event.h`](../../../../include/linux/perf_event.h)
+
+use crate::{
+    bindings,
+    error::{code::*, from_err_ptr, to_result, Result},
+    task::Task,
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::c_void,
+    mem::{size_of, MaybeUninit},
+    ptr::{self, NonNull},
+};
+
+/// A generic hardware event, the kernel's `enum perf_hw_id`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hardware {
+    /// CPU cycles.
+    CpuCycles = bindings::perf_hw_id_PERF_COUNT_HW_CPU_CYCLES,
+    /// Retired instructions.
+    Instructions = bindings::perf_hw_id_PERF_COUNT_HW_INSTRUCTIONS,
+    /// Accesses to the last level cache.
+    CacheReferences = bindings::perf_hw_id_PERF_COUNT_HW_CACHE_REFERENCES,
+    /// Misses of the last level cache.
+    CacheMisses = bindings::perf_hw_id_PERF_COUNT_HW_CACHE_MISSES,
+    /// Retired branch instructions.
+    BranchInstructions = bindings::perf_hw_id_PERF_COUNT_HW_BRANCH_INSTRUCTIONS,
+    /// Mispredicted branches.
+    BranchMisses = bindings::perf_hw_id_PERF_COUNT_HW_BRANCH_MISSES,
+    /// Bus cycles.
+    BusCycles = bindings::perf_hw_id_PERF_COUNT_HW_BUS_CYCLES,
+}
+
+/// A software event, the kernel's `enum perf_sw_ids`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Software {
+    /// The time spent on a CPU, in nanoseconds.
+    CpuClock = bindings::perf_sw_ids_PERF_COUNT_SW_CPU_CLOCK,
+    /// The time spent running the task, in nanoseconds.
+    TaskClock = bindings::perf_sw_ids_PERF_COUNT_SW_TASK_CLOCK,
+    /// Page faults.
+    PageFaults = bindings::perf_sw_ids_PERF_COUNT_SW_PAGE_FAULTS,
+    /// Context switches.
+    ContextSwitches = bindings::perf_sw_ids_PERF_COUNT_SW_CONTEXT_SWITCHES,
+    /// Migrations of the task to another CPU.
+    CpuMigrations = bindings::perf_sw_ids_PERF_COUNT_SW_CPU_MIGRATIONS,
+}
+
+/// The event counted by a counter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// A generic hardware event.
+    Hardware(Hardware),
+    /// A software event.
+    Software(Software),
+    /// A raw event of the PMU of the CPU, whose encoding is specific to the PMU.
+    Raw(u64),
+}
+
+/// The attributes of a counter, the kernel's `struct perf_event_attr`.
+///
+/// Counters count in user and kernel mode by default, and start enabled.
+pub struct Attr(bindings::perf_event_attr);
+
+impl Attr {
+    /// Creates the attributes of a counter of `event`.
+    pub fn new(event: Event) -> Self {
+        // SAFETY: The attributes are plain data, for which zero is valid.
+        let mut attr: bindings::perf_event_attr = unsafe { MaybeUninit::zeroed().assume_init() };
+        let (type_, config) = match event {
+            Event::Hardware(hw) => (bindings::perf_type_id_PERF_TYPE_HARDWARE, hw as u64),
+            Event::Software(sw) => (bindings::perf_type_id_PERF_TYPE_SOFTWARE, sw as u64),
+            Event::Raw(raw) => (bindings::perf_type_id_PERF_TYPE_RAW, raw),
+        };
+        attr.type_ = type_;
+        attr.size = size_of::<bindings::perf_event_attr>() as _;
+        attr.config = config;
+        Self(attr)
+    }
+
+    /// Makes the counter sample every `period` events, calling its [`Overflow`] handler.
+    pub fn sample_period(mut self, period: u64) -> Self {
+        self.0.__bindgen_anon_1.sample_period = period;
+        self
+    }
+
+    /// Makes the counter start disabled, until [`Counter::enable`] is called.
+    pub fn disabled(mut self) -> Self {
+        self.0.set_disabled(1);
+        self
+    }
+
+    /// Makes the counter always on the PMU, rather than multiplexed with other counters.
+    ///
+    /// If the PMU has no free counter, the counter goes in an error state, and reads return
+    /// zero.
+    pub fn pinned(mut self) -> Self {
+        self.0.set_pinned(1);
+        self
+    }
+
+    /// Makes the counter not count in user mode.
+    pub fn exclude_user(mut self) -> Self {
+        self.0.set_exclude_user(1);
+        self
+    }
+
+    /// Makes the counter not count in kernel mode.
+    pub fn exclude_kernel(mut self) -> Self {
+        self.0.set_exclude_kernel(1);
+        self
+    }
+
+    /// Makes the counter not count in the hypervisor.
+    pub fn exclude_hv(mut self) -> Self {
+        self.0.set_exclude_hv(1);
+        self
+    }
+
+    /// Makes the counter not count when the CPU is idle.
+    pub fn exclude_idle(mut self) -> Self {
+        self.0.set_exclude_idle(1);
+        self
+    }
+}
+
+/// What a counter counts.
+pub enum Target<'a> {
+    /// All tasks, while they run on the given CPU.
+    Cpu(u32),
+    /// The given task, on any CPU.
+    Task(&'a Task),
+    /// The given task, only while it runs on the given CPU.
+    TaskOnCpu(&'a Task, u32),
+}
+
+/// The handler of the overflows of a sampling counter.
+///
+/// # Examples
+///
+/// ```
+/// use core::sync::atomic::{AtomicU64, Ordering};
+/// use kernel::perf_event::{Attr, Counter, Event, Hardware, Overflow, Target};
+/// use kernel::prelude::*;
+///
+/// struct Samples(AtomicU64);
+///
+/// impl Overflow for Samples {
+///     fn overflow(&self) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// fn sample_misses(cpu: u32) -> Result<Counter<Samples>> {
+///     let attr = Attr::new(Event::Hardware(Hardware::CacheMisses)).sample_period(10_000);
+///     Counter::with_overflow(&attr, Target::Cpu(cpu), Samples(AtomicU64::new(0)))
+/// }
+/// ```
+pub trait Overflow: Send + Sync + 'static {
+    /// Called each time the sample period of the counter elapses.
+    ///
+    /// It is called in NMI context on most PMUs, so it must neither sleep nor take locks, and
+    /// should only update atomics or per-CPU data.
+    fn overflow(&self);
+}
+
+/// The value of a counter.
+#[derive(Clone, Copy, Debug)]
+pub struct Value {
+    /// The number of events counted.
+    pub count: u64,
+    /// The time the counter was enabled, in nanoseconds.
+    pub enabled: u64,
+    /// The time the counter was on the PMU, in nanoseconds.
+    ///
+    /// It is smaller than `enabled` if the counter was multiplexed with others, in which case
+    /// `count` only covers that part.
+    pub running: u64,
+}
+
+/// A kernel counter, the kernel's `struct perf_event`.
+///
+/// The counter is released when this is dropped, and its overflow handler, if any, is dropped
+/// afterwards.
+///
+/// # Invariants
+///
+/// `event` is a valid counter created by `perf_event_create_kernel_counter`, whose overflow
+/// context is `handler`, if any.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::perf_event::{Attr, Counter, Event, Hardware, Target};
+/// use kernel::prelude::*;
+///
+/// fn hot_path() {}
+///
+/// fn profile(cpu: u32) -> Result<u64> {
+///     let attr = Attr::new(Event::Hardware(Hardware::CpuCycles))
+///         .exclude_user()
+///         .disabled();
+///     let counter = Counter::new(&attr, Target::Cpu(cpu))?;
+///     counter.enable();
+///     hot_path();
+///     counter.disable();
+///     Ok(counter.read().count)
+/// }
+/// ```
+pub struct Counter<T = ()> {
+    event: NonNull<bindings::perf_event>,
+    _handler: Option<Box<T>>,
+}
+
+impl Counter {
+    /// Creates a counter of `target` with the attributes `attr`.
+    ///
+    /// Fails with `ENOENT` or `EOPNOTSUPP` if the PMU doesn't support the event.
+    pub fn new(attr: &Attr, target: Target<'_>) -> Result<Self> {
+        // SAFETY: There is no overflow handler.
+        unsafe { Self::create(attr, target, None, ptr::null_mut(), None) }
+    }
+}
+
+impl<T> Counter<T> {
+    /// Creates a counter with the overflow handler `callback` and its context `context`.
+    ///
+    /// # Safety
+    ///
+    /// `callback` must be valid to call with `context` until the counter is released.
+    unsafe fn create(
+        attr: &Attr,
+        target: Target<'_>,
+        callback: bindings::perf_overflow_handler_t,
+        context: *mut c_void,
+        handler: Option<Box<T>>,
+    ) -> Result<Self> {
+        crate::might_sleep!();
+        let (cpu, task) = match target {
+            Target::Cpu(cpu) => (cpu as i32, ptr::null_mut()),
+            Target::Task(task) => (-1, task.0.get()),
+            Target::TaskOnCpu(task, cpu) => (cpu as i32, task.0.get()),
+        };
+        // The attributes are copied by the core.
+        let mut attr = attr.0;
+        // SAFETY: The attributes are valid, and the task, if any, is valid for the duration of the
+        // call; the counter takes a reference to it. `callback` is valid with `context` by the
+        // safety requirements.
+        let event = from_err_ptr(unsafe {
+            bindings::perf_event_create_kernel_counter(&mut attr, cpu, task, callback, context)
+        })?;
+        // INVARIANT: The counter was just created with `handler` as overflow context.
+        Ok(Self {
+            event: NonNull::new(event).ok_or(EINVAL)?,
+            _handler: handler,
+        })
+    }
+
+    fn as_raw(&self) -> *mut bindings::perf_event {
+        self.event.as_ptr()
+    }
+
+    /// Enables the counter.
+    pub fn enable(&self) {
+        // SAFETY: The counter is valid by the type invariants.
+        unsafe { bindings::perf_event_enable(self.as_raw()) };
+    }
+
+    /// Disables the counter, which keeps its value.
+    pub fn disable(&self) {
+        // SAFETY: The counter is valid by the type invariants.
+        unsafe { bindings::perf_event_disable(self.as_raw()) };
+    }
+
+    /// Reads the value of the counter.
+    ///
+    /// This may sleep.
+    pub fn read(&self) -> Value {
+        crate::might_sleep!();
+        let (mut enabled, mut running) = (0, 0);
+        // SAFETY: The counter is valid by the type invariants.
+        let count =
+            unsafe { bindings::perf_event_read_value(self.as_raw(), &mut enabled, &mut running) };
+        Value {
+            count,
+            enabled,
+            running,
+        }
+    }
+
+    /// Reads the value of the counter without sleeping, e.g. with interrupts disabled.
+    ///
+    /// Fails with `EINVAL` unless the counter counts the current task, or counts on the current
+    /// CPU.
+    pub fn read_local(&self) -> Result<Value> {
+        let (mut count, mut enabled, mut running) = (0, 0, 0);
+        // SAFETY: The counter is valid by the type invariants.
+        to_result(unsafe {
+            bindings::perf_event_read_local(self.as_raw(), &mut count, &mut enabled, &mut running)
+        })?;
+        Ok(Value {
+            count,
+            enabled,
+            running,
+        })
+    }
+}
+
+impl<T: Overflow> Counter<T> {
+    /// Creates a sampling counter of `target` with the attributes `attr`, whose overflows are
+    /// handled by `handler`.
+    ///
+    /// The attributes should set a sample period with [`Attr::sample_period`].
+    pub fn with_overflow(attr: &Attr, target: Target<'_>, handler: T) -> Result<Self> {
+        let handler = Box::try_new(handler)?;
+        let context = (&*handler as *const T).cast_mut().cast::<c_void>();
+        // SAFETY: The handler is boxed, and only dropped after the counter is released.
+        unsafe {
+            Self::create(
+                attr,
+                target,
+                Some(Self::overflow_callback),
+                context,
+                Some(handler),
+            )
+        }
+    }
+
+    unsafe extern "C" fn overflow_callback(
+        event: *mut bindings::perf_event,
+        _data: *mut bindings::perf_sample_data,
+        _regs: *mut bindings::pt_regs,
+    ) {
+        // SAFETY: The core calls this with a counter created by `with_overflow`, whose overflow
+        // context is its handler.
+        let handler = unsafe { &*(*event).overflow_handler_context.cast::<T>() };
+        handler.overflow();
+    }
+}
+
+impl<T> Drop for Counter<T> {
+    fn drop(&mut self) {
+        // SAFETY: The counter is valid by the type invariants. Once it is released, its overflow
+        // handler isn't called anymore.
+        unsafe { bindings::perf_event_release_kernel(self.as_raw()) };
+    }
+}
+
+// SAFETY: Counters can be used and released from any thread, and the handler is `Send`.
+unsafe impl<T: Send> Send for Counter<T> {}
+
+// SAFETY: The methods that take `&self` are serialised by the core, and the handler is `Sync`.
+unsafe impl<T: Sync> Sync for Counter<T> {}
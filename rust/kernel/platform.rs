@@ -0,0 +1,311 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Platform devices and drivers.
+//!
+//! Platform devices are the devices of a system that aren't on a discoverable bus, e.g. the
+//! controllers of a SoC, and are usually described in the devicetree. They are handled by
+//! drivers that implement [`Driver`], which map the registers of their device, see
+//! [`Device::resource`], and request its interrupts, see [`Device::irq`].
+//!
+//! C header: [`include/linux/platform_device.h`](../../../../include/linux/platform_device.h)
+
+use crate::{
+    bindings, device,
+    error::{code::*, from_result, to_result, Error, Result},
+    io_mem::Resource,
+    of,
+    str::CStr,
+    types::{ForeignOwnable, Opaque},
+    ThisModule,
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::c_int,
+    marker::{PhantomData, PhantomPinned},
+    pin::Pin,
+    ptr,
+};
+use macros::vtable;
+
+/// A platform device, the kernel's `struct platform_device`.
+///
+/// # Invariants
+///
+/// The device is valid while references to it exist.
+#[repr(transparent)]
+pub struct Device(Opaque<bindings::platform_device>);
+
+impl Device {
+    /// Creates a reference to a [`Device`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is valid for the lifetime of the returned reference.
+    pub unsafe fn as_ref<'a>(ptr: *mut bindings::platform_device) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct platform_device` pointer.
+    pub fn as_raw(&self) -> *mut bindings::platform_device {
+        self.0.get()
+    }
+
+    /// Returns the generic device of the platform device.
+    pub fn device(&self) -> &device::Device {
+        // SAFETY: The device is valid by the type invariants, and embeds its generic device.
+        unsafe { device::Device::as_ref(ptr::addr_of_mut!((*self.as_raw()).dev)) }
+    }
+
+    /// Returns the memory resource `index` of the device, e.g. the `index`-th entry of the `reg`
+    /// property of its devicetree node.
+    ///
+    /// Fails with `ENODEV` if the device has no such resource.
+    pub fn resource(&self, index: u32) -> Result<Resource> {
+        // SAFETY: The device is valid by the type invariants.
+        let res = unsafe {
+            bindings::platform_get_resource(self.as_raw(), bindings::IORESOURCE_MEM, index)
+        };
+        if res.is_null() {
+            return Err(ENODEV);
+        }
+        // SAFETY: The resources of the device live as long as the device, and never change once
+        // it is added.
+        let (start, end) = unsafe { ((*res).start, (*res).end) };
+        Resource::new(start, end - start + 1).ok_or(EINVAL)
+    }
+
+    /// Returns the interrupt `index` of the device.
+    ///
+    /// This may fail with `EPROBE_DEFER` if the interrupt controller isn't probed yet.
+    pub fn irq(&self, index: u32) -> Result<u32> {
+        // SAFETY: The device is valid by the type invariants.
+        let irq = unsafe { bindings::platform_get_irq(self.as_raw(), index) };
+        if irq < 0 {
+            Err(Error::from_errno(irq))
+        } else {
+            Ok(irq as u32)
+        }
+    }
+}
+
+// SAFETY: The platform device can be used from any thread.
+unsafe impl Send for Device {}
+
+// SAFETY: The methods that take `&self` only read fields that never change once the device is
+// added.
+unsafe impl Sync for Device {}
+
+/// A driver of platform devices.
+#[vtable]
+pub trait Driver: Sized + 'static {
+    /// The data associated with each device bound to the driver.
+    type Data: ForeignOwnable + Send + Sync;
+
+    /// The name of the driver.
+    const NAME: &'static CStr;
+
+    /// The match table of the devicetree nodes that the driver handles.
+    const OF_MATCH: of::IdTable;
+
+    /// Binds the driver to `pdev`.
+    fn probe(pdev: &Device) -> Result<Self::Data>;
+
+    /// Unbinds the driver from `pdev`, which drops `data` once this returns.
+    fn remove(_pdev: &Device, _data: Self::Data) {}
+
+    /// Suspends the device, when the system suspends or hibernates.
+    fn suspend(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>) -> Result {
+        Ok(())
+    }
+
+    /// Resumes the device, when the system resumes from suspend or hibernation.
+    fn resume(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>) -> Result {
+        Ok(())
+    }
+}
+
+/// The registration of a driver of platform devices.
+///
+/// The driver is unregistered when this is dropped, which unbinds it from its devices.
+///
+/// # Invariants
+///
+/// `driver` is registered if `registered` is `true`.
+pub struct Registration<T: Driver> {
+    driver: Opaque<bindings::platform_driver>,
+    registered: bool,
+    _pin: PhantomPinned,
+    _p: PhantomData<T>,
+}
+
+// SAFETY: The driver can be unregistered from any thread.
+unsafe impl<T: Driver> Send for Registration<T> {}
+
+// SAFETY: `Registration` has no methods that take `&self`.
+unsafe impl<T: Driver> Sync for Registration<T> {}
+
+impl<T: Driver> Registration<T> {
+    const PM_OPS: bindings::dev_pm_ops = bindings::dev_pm_ops {
+        suspend: Some(Self::suspend_callback),
+        resume: Some(Self::resume_callback),
+        freeze: Some(Self::suspend_callback),
+        thaw: Some(Self::resume_callback),
+        poweroff: Some(Self::suspend_callback),
+        restore: Some(Self::resume_callback),
+        // SAFETY: All other fields are optional, for which zero is valid.
+        ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+    };
+
+    /// Registers the driver on behalf of `module`.
+    ///
+    /// The driver is bound to the devices that match [`Driver::OF_MATCH`].
+    pub fn register(module: &'static ThisModule) -> Result<Pin<Box<Self>>> {
+        let pm = if T::HAS_SUSPEND || T::HAS_RESUME {
+            &Self::PM_OPS as *const _
+        } else {
+            ptr::null()
+        };
+        let mut reg = Pin::from(Box::try_new(Self {
+            driver: Opaque::new(bindings::platform_driver {
+                driver: bindings::device_driver {
+                    name: T::NAME.as_char_ptr(),
+                    of_match_table: T::OF_MATCH.as_ptr(),
+                    pm,
+                    // SAFETY: All other fields are optional, for which zero is valid.
+                    ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+                },
+                probe: Some(Self::probe_callback),
+                remove_new: Some(Self::remove_callback),
+                // SAFETY: All other fields are optional, for which zero is valid.
+                ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+            }),
+            registered: false,
+            _pin: PhantomPinned,
+            _p: PhantomData,
+        })?);
+        // SAFETY: `driver` is valid and pinned, its match table and PM operations are static,
+        // and the driver is unregistered before it is freed.
+        to_result(unsafe {
+            bindings::__platform_driver_register(reg.driver.get(), module.as_ptr())
+        })?;
+        // INVARIANT: The driver was registered above.
+        // SAFETY: `reg` isn't moved out of.
+        unsafe { reg.as_mut().get_unchecked_mut() }.registered = true;
+        Ok(reg)
+    }
+
+    unsafe extern "C" fn probe_callback(pdev: *mut bindings::platform_device) -> c_int {
+        from_result(|| {
+            // SAFETY: The driver core calls this with a valid device, which stays valid until
+            // the driver is unbound.
+            let pdev = unsafe { Device::as_ref(pdev) };
+            let data = T::probe(pdev)?;
+            // SAFETY: The driver data of the device belongs to the driver bound to it, and is
+            // freed in `remove_callback`.
+            unsafe { (*pdev.device().as_raw()).driver_data = data.into_foreign() as _ };
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn remove_callback(pdev: *mut bindings::platform_device) {
+        // SAFETY: The driver core calls this with a device that was bound by `probe_callback`,
+        // so its driver data is set. It isn't used once this returns.
+        unsafe {
+            let pdev = Device::as_ref(pdev);
+            let data = T::Data::from_foreign((*pdev.device().as_raw()).driver_data);
+            T::remove(pdev, data);
+        }
+    }
+
+    unsafe extern "C" fn suspend_callback(dev: *mut bindings::device) -> c_int {
+        // SAFETY: The PM core only calls this for devices bound by `probe_callback`, so their
+        // driver data is set.
+        let data = unsafe { T::Data::borrow((*dev).driver_data) };
+        from_result(|| {
+            T::suspend(data)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn resume_callback(dev: *mut bindings::device) -> c_int {
+        // SAFETY: The PM core only calls this for devices bound by `probe_callback`, so their
+        // driver data is set.
+        let data = unsafe { T::Data::borrow((*dev).driver_data) };
+        from_result(|| {
+            T::resume(data)?;
+            Ok(0)
+        })
+    }
+}
+
+impl<T: Driver> Drop for Registration<T> {
+    fn drop(&mut self) {
+        if self.registered {
+            // SAFETY: The driver was registered by the type invariants.
+            unsafe { bindings::platform_driver_unregister(self.driver.get()) };
+        }
+    }
+}
+
+/// Declares a kernel module that registers a driver of platform devices, and emits the OF device
+/// table that allows the module to be loaded when a matching device is found.
+///
+/// # Examples
+///
+/// ```ignore
+/// use kernel::{c_str, of, platform};
+/// use kernel::prelude::*;
+///
+/// kernel::module_platform_driver! {
+///     type: Controller,
+///     name: "rust_controller",
+///     author: "Rust for Linux Contributors",
+///     description: "Platform driver",
+///     license: "GPL",
+/// }
+///
+/// struct Controller;
+///
+/// #[vtable]
+/// impl platform::Driver for Controller {
+///     type Data = ();
+///
+///     const NAME: &'static CStr = c_str!("rust-controller");
+///     const OF_MATCH: of::IdTable = kernel::of_id_table![c_str!("vendor,rust-controller")];
+///
+///     fn probe(pdev: &platform::Device) -> Result {
+///         let irq = pdev.irq(0)?;
+///         pr_info!("{} probed, irq {}\n", pdev.device().name(), irq);
+///         Ok(())
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! module_platform_driver {
+    (type: $type:ty, $($f:tt)*) => {
+        struct Module {
+            _reg: ::core::pin::Pin<$crate::prelude::Box<$crate::platform::Registration<$type>>>,
+        }
+
+        $crate::prelude::module! {
+            type: Module,
+            $($f)*
+        }
+
+        impl $crate::Module for Module {
+            fn init(module: &'static $crate::ThisModule) -> $crate::error::Result<Self> {
+                Ok(Module {
+                    _reg: $crate::platform::Registration::register(module)?,
+                })
+            }
+        }
+
+        #[cfg(MODULE)]
+        #[no_mangle]
+        static __mod_of__platform_of_match_device_table: [
+            $crate::of::DeviceId;
+            <$type as $crate::platform::Driver>::OF_MATCH.len_with_end()
+        ] = <$type as $crate::platform::Driver>::OF_MATCH.to_array();
+    };
+}
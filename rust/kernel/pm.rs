@@ -0,0 +1,159 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! System power management.
+//!
+//! A driver whose device can wake the system up keeps it from suspending while it handles a
+//! wakeup event, either with the wakeup source of its device, see [`device::Device::init_wakeup`],
+//! or with a [`WakeupSource`] of its own, e.g. one per queue of events that user space drains.
+//! Drivers that need to prepare for system suspend as a whole, rather than for the suspend of
+//! their device, add a handler to the [`SuspendChain`].
+//!
+//! C header: [`include/linux/pm_wakeup.h`](../../../../include/linux/pm_wakeup.h)
+
+use crate::{
+    bindings, device,
+    error::{code::*, to_result, Result},
+    notifier,
+    str::CStr,
+};
+use core::ptr::{self, NonNull};
+
+/// A wakeup source, the kernel's `struct wakeup_source`.
+///
+/// While a wakeup source is active, the system doesn't suspend, and suspending is aborted. The
+/// source is unregistered when this is dropped.
+///
+/// The methods don't sleep, and can be called from atomic context, e.g. from interrupt handlers.
+///
+/// # Invariants
+///
+/// `ws` is a registered wakeup source.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, pm::WakeupSource, prelude::*};
+///
+/// fn queue_event(ws: &WakeupSource) {
+///     // Keep the system awake until user space has read the event.
+///     ws.stay_awake();
+/// }
+///
+/// fn drain_events(ws: &WakeupSource) {
+///     ws.relax();
+/// }
+///
+/// let ws = WakeupSource::register(None, c_str!("my_events"))?;
+/// queue_event(&ws);
+/// drain_events(&ws);
+/// # Ok::<(), Error>(())
+/// ```
+pub struct WakeupSource {
+    ws: NonNull<bindings::wakeup_source>,
+}
+
+impl WakeupSource {
+    /// Registers a wakeup source named `name`, which is shown in sysfs under `dev` if it is
+    /// given.
+    pub fn register(dev: Option<&device::Device>, name: &CStr) -> Result<Self> {
+        crate::might_sleep!();
+        let dev = dev.map_or(ptr::null_mut(), |dev| dev.as_raw());
+        // SAFETY: `dev` is valid or null, and the name is copied.
+        let ws = unsafe { bindings::wakeup_source_register(dev, name.as_char_ptr()) };
+        // INVARIANT: The wakeup source was registered if it isn't null.
+        Ok(Self {
+            ws: NonNull::new(ws).ok_or(ENOMEM)?,
+        })
+    }
+
+    /// Activates the wakeup source until [`WakeupSource::relax`] is called.
+    pub fn stay_awake(&self) {
+        // SAFETY: The wakeup source is registered by the type invariants.
+        unsafe { bindings::__pm_stay_awake(self.ws.as_ptr()) };
+    }
+
+    /// Deactivates the wakeup source.
+    pub fn relax(&self) {
+        // SAFETY: The wakeup source is registered by the type invariants.
+        unsafe { bindings::__pm_relax(self.ws.as_ptr()) };
+    }
+
+    /// Activates the wakeup source for `msec` milliseconds, or reports a wakeup event without
+    /// activating it if `msec` is zero.
+    pub fn wakeup_event(&self, msec: u32) {
+        // SAFETY: The wakeup source is registered by the type invariants.
+        unsafe { bindings::pm_wakeup_ws_event(self.ws.as_ptr(), msec, false) };
+    }
+}
+
+impl Drop for WakeupSource {
+    fn drop(&mut self) {
+        // SAFETY: The wakeup source is registered by the type invariants.
+        unsafe { bindings::wakeup_source_unregister(self.ws.as_ptr()) };
+    }
+}
+
+// SAFETY: The wakeup source can be unregistered from any thread.
+unsafe impl Send for WakeupSource {}
+
+// SAFETY: The wakeup source is protected by its own lock.
+unsafe impl Sync for WakeupSource {}
+
+/// Actions of the [`SuspendChain`], the kernel's `PM_*` notification values.
+pub mod action {
+    /// The system is about to suspend, before tasks are frozen.
+    pub const SUSPEND_PREPARE: u32 = crate::bindings::PM_SUSPEND_PREPARE;
+    /// The system resumed from suspend, or failed to suspend, after tasks are thawed.
+    pub const POST_SUSPEND: u32 = crate::bindings::PM_POST_SUSPEND;
+    /// The system is about to hibernate, before tasks are frozen.
+    pub const HIBERNATION_PREPARE: u32 = crate::bindings::PM_HIBERNATION_PREPARE;
+    /// The system resumed from hibernation, or failed to hibernate.
+    pub const POST_HIBERNATION: u32 = crate::bindings::PM_POST_HIBERNATION;
+    /// The system is about to restore a hibernation image.
+    pub const RESTORE_PREPARE: u32 = crate::bindings::PM_RESTORE_PREPARE;
+    /// The system failed to restore a hibernation image.
+    pub const POST_RESTORE: u32 = crate::bindings::PM_POST_RESTORE;
+}
+
+/// The notifier chain of system suspend and hibernation, see `register_pm_notifier` in
+/// [`include/linux/suspend.h`](../../../../include/linux/suspend.h).
+///
+/// The handlers are called in process context with an [`action`], and may sleep. Returning an
+/// error from a `*_PREPARE` action aborts the transition.
+///
+/// # Examples
+///
+/// ```
+/// use core::ffi::{c_ulong, c_void};
+/// use kernel::{notifier, pm, prelude::*};
+///
+/// struct Modem;
+///
+/// impl notifier::Handler for Modem {
+///     fn notify(&self, action: c_ulong, _data: *mut c_void) -> Result<notifier::Notify> {
+///         match action as u32 {
+///             pm::action::SUSPEND_PREPARE => pr_info!("flushing the modem\n"),
+///             pm::action::POST_SUSPEND => pr_info!("restarting the modem\n"),
+///             _ => return Ok(notifier::Notify::Done),
+///         }
+///         Ok(notifier::Notify::Ok)
+///     }
+/// }
+///
+/// fn listen() -> Result<notifier::Registration<'static, pm::SuspendChain, Modem>> {
+///     notifier::Registration::try_new(&pm::SuspendChain, Modem, 0)
+/// }
+/// ```
+pub struct SuspendChain;
+
+impl notifier::Head for SuspendChain {
+    unsafe fn register(&self, nb: *mut bindings::notifier_block) -> Result {
+        // SAFETY: The caller guarantees that `nb` is valid.
+        to_result(unsafe { bindings::register_pm_notifier(nb) })
+    }
+
+    unsafe fn unregister(&self, nb: *mut bindings::notifier_block) {
+        // SAFETY: The caller guarantees that `nb` is on the chain.
+        unsafe { bindings::unregister_pm_notifier(nb) };
+    }
+}
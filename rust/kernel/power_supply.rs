@@ -0,0 +1,308 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Power supplies, as seen by their consumers.
+//!
+//! Charger and fuel-gauge drivers look up the supplies they depend on, e.g. the USB charger that
+//! feeds a battery charger, with [`PowerSupply::get_by_name`] or [`PowerSupply::get_by_phandle`],
+//! read their properties, and follow their changes by adding a [`notifier::Handler`] to
+//! [`EVENTS`].
+//!
+//! C header: [`include/linux/power_supply.h`](../../../../include/linux/power_supply.h)
+
+use crate::{
+    bindings,
+    device::Device,
+    error::{to_result, Result},
+    notifier,
+    str::CStr,
+    types::{ARef, AlwaysRefCounted, Opaque},
+};
+use core::{ffi::c_void, mem::MaybeUninit, ptr};
+
+#[cfg(CONFIG_OF)]
+use crate::{error::code::*, of::DeviceNode};
+
+/// An integer property of a power supply, the kernel's `enum power_supply_property`.
+///
+/// Voltages are in µV, currents in µA, charges in µAh, energies in µWh, powers in µW,
+/// temperatures in tenths of °C and capacities in percent.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Property {
+    /// The charging status, see [`status`].
+    Status = bindings::power_supply_property_POWER_SUPPLY_PROP_STATUS,
+    /// The charge type, the kernel's `POWER_SUPPLY_CHARGE_TYPE_*`.
+    ChargeType = bindings::power_supply_property_POWER_SUPPLY_PROP_CHARGE_TYPE,
+    /// The health, the kernel's `POWER_SUPPLY_HEALTH_*`.
+    Health = bindings::power_supply_property_POWER_SUPPLY_PROP_HEALTH,
+    /// Whether the supply is present, e.g. whether a battery is inserted.
+    Present = bindings::power_supply_property_POWER_SUPPLY_PROP_PRESENT,
+    /// Whether the supply is online, e.g. whether a charger is plugged in.
+    Online = bindings::power_supply_property_POWER_SUPPLY_PROP_ONLINE,
+    /// The maximum voltage.
+    VoltageMax = bindings::power_supply_property_POWER_SUPPLY_PROP_VOLTAGE_MAX,
+    /// The current voltage.
+    VoltageNow = bindings::power_supply_property_POWER_SUPPLY_PROP_VOLTAGE_NOW,
+    /// The maximum current, e.g. the input current limit of a charger.
+    CurrentMax = bindings::power_supply_property_POWER_SUPPLY_PROP_CURRENT_MAX,
+    /// The current current, positive while charging.
+    CurrentNow = bindings::power_supply_property_POWER_SUPPLY_PROP_CURRENT_NOW,
+    /// The charge when full.
+    ChargeFull = bindings::power_supply_property_POWER_SUPPLY_PROP_CHARGE_FULL,
+    /// The current charge.
+    ChargeNow = bindings::power_supply_property_POWER_SUPPLY_PROP_CHARGE_NOW,
+    /// The charging current.
+    ConstantChargeCurrent =
+        bindings::power_supply_property_POWER_SUPPLY_PROP_CONSTANT_CHARGE_CURRENT,
+    /// The charging voltage.
+    ConstantChargeVoltage =
+        bindings::power_supply_property_POWER_SUPPLY_PROP_CONSTANT_CHARGE_VOLTAGE,
+    /// The input current limit.
+    InputCurrentLimit = bindings::power_supply_property_POWER_SUPPLY_PROP_INPUT_CURRENT_LIMIT,
+    /// The capacity.
+    Capacity = bindings::power_supply_property_POWER_SUPPLY_PROP_CAPACITY,
+    /// The temperature.
+    Temp = bindings::power_supply_property_POWER_SUPPLY_PROP_TEMP,
+    /// The type of the USB port the supply is connected to, the kernel's
+    /// `POWER_SUPPLY_USB_TYPE_*`.
+    UsbType = bindings::power_supply_property_POWER_SUPPLY_PROP_USB_TYPE,
+}
+
+/// The values of [`Property::Status`], the kernel's `POWER_SUPPLY_STATUS_*`.
+pub mod status {
+    /// The status is unknown.
+    pub const UNKNOWN: i32 = crate::bindings::POWER_SUPPLY_STATUS_UNKNOWN as _;
+    /// The supply is charging.
+    pub const CHARGING: i32 = crate::bindings::POWER_SUPPLY_STATUS_CHARGING as _;
+    /// The supply is discharging.
+    pub const DISCHARGING: i32 = crate::bindings::POWER_SUPPLY_STATUS_DISCHARGING as _;
+    /// The supply is neither charging nor discharging.
+    pub const NOT_CHARGING: i32 = crate::bindings::POWER_SUPPLY_STATUS_NOT_CHARGING as _;
+    /// The supply is fully charged.
+    pub const FULL: i32 = crate::bindings::POWER_SUPPLY_STATUS_FULL as _;
+}
+
+/// A power supply, the kernel's `struct power_supply`.
+///
+/// # Invariants
+///
+/// The supply is registered while references to it exist, and is reference-counted through its
+/// device.
+#[repr(transparent)]
+pub struct PowerSupply(Opaque<bindings::power_supply>);
+
+impl PowerSupply {
+    /// Creates a reference to a [`PowerSupply`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is a registered supply for the lifetime of the returned
+    /// reference.
+    pub unsafe fn as_ref<'a>(ptr: *mut bindings::power_supply) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Creates a reference to the supply reported by an event of [`EVENTS`].
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `data` is the data of an event of [`EVENTS`], and that the
+    /// returned reference is only used during the handling of the event.
+    pub unsafe fn from_event_data<'a>(data: *mut c_void) -> &'a Self {
+        // SAFETY: The events of the chain report the supply that changed, which is registered
+        // while they are handled.
+        unsafe { Self::as_ref(data.cast()) }
+    }
+
+    /// Returns the raw `struct power_supply` pointer.
+    pub fn as_raw(&self) -> *mut bindings::power_supply {
+        self.0.get()
+    }
+
+    /// Finds the supply named `name`, if it is registered.
+    pub fn get_by_name(name: &CStr) -> Option<ARef<Self>> {
+        // SAFETY: `name` is a valid string.
+        let psy = unsafe { bindings::power_supply_get_by_name(name.as_char_ptr()) };
+        // SAFETY: `power_supply_get_by_name` took a reference to the supply, which is transferred
+        // to the returned `ARef`.
+        ptr::NonNull::new(psy).map(|psy| unsafe { ARef::from_raw(psy.cast()) })
+    }
+
+    /// Finds the supply referred to by the phandle property `property` of `np`, e.g.
+    /// `power-supplies`.
+    ///
+    /// Fails with `EPROBE_DEFER` if the supply isn't registered yet, and with `ENODEV` if the
+    /// property doesn't exist.
+    #[cfg(CONFIG_OF)]
+    pub fn get_by_phandle(np: &DeviceNode, property: &CStr) -> Result<ARef<Self>> {
+        // SAFETY: `np` is valid by its type invariants, and `property` is a valid string.
+        let psy = crate::error::from_err_ptr(unsafe {
+            bindings::power_supply_get_by_phandle(np.as_raw(), property.as_char_ptr())
+        })?;
+        let psy = ptr::NonNull::new(psy).ok_or(EPROBE_DEFER)?;
+        // SAFETY: `power_supply_get_by_phandle` took a reference to the supply, which is
+        // transferred to the returned `ARef`.
+        Ok(unsafe { ARef::from_raw(psy.cast()) })
+    }
+
+    /// Returns the device of the supply.
+    pub fn device(&self) -> &Device {
+        // SAFETY: The supply is registered by the type invariants, and so is its device.
+        unsafe { Device::as_ref(ptr::addr_of_mut!((*self.as_raw()).dev)) }
+    }
+
+    /// Returns the name of the supply.
+    pub fn name(&self) -> &CStr {
+        // SAFETY: The supply is registered by the type invariants, and its description and name
+        // never change.
+        unsafe { CStr::from_char_ptr((*(*self.as_raw()).desc).name) }
+    }
+
+    /// Reads the property `prop`.
+    ///
+    /// Fails with `EINVAL` if the supply doesn't have it, and with `ENODATA` or `EAGAIN` if it
+    /// can't be read right now.
+    pub fn get_property(&self, prop: Property) -> Result<i32> {
+        // SAFETY: The value is plain data, for which zero is valid.
+        let mut val: bindings::power_supply_propval =
+            unsafe { MaybeUninit::zeroed().assume_init() };
+        // SAFETY: The supply is registered by the type invariants.
+        to_result(unsafe {
+            bindings::power_supply_get_property(self.as_raw(), prop as _, &mut val)
+        })?;
+        // SAFETY: All the properties of `Property` are integers.
+        Ok(unsafe { val.intval })
+    }
+
+    /// Writes the property `prop`, e.g. the input current limit of a charger.
+    ///
+    /// Fails with `EINVAL` if the supply doesn't have it or if it isn't writeable.
+    pub fn set_property(&self, prop: Property, value: i32) -> Result {
+        // SAFETY: The value is plain data, for which zero is valid.
+        let mut val: bindings::power_supply_propval =
+            unsafe { MaybeUninit::zeroed().assume_init() };
+        val.intval = value;
+        // SAFETY: The supply is registered by the type invariants, and `val` is copied.
+        to_result(unsafe { bindings::power_supply_set_property(self.as_raw(), prop as _, &val) })
+    }
+
+    /// Reads the property `prop` of the first supply this one is supplied from that has it,
+    /// e.g. the online state of the charger of a battery.
+    ///
+    /// Fails with `ENODEV` if no supplier has it.
+    pub fn get_property_from_supplier(&self, prop: Property) -> Result<i32> {
+        // SAFETY: The value is plain data, for which zero is valid.
+        let mut val: bindings::power_supply_propval =
+            unsafe { MaybeUninit::zeroed().assume_init() };
+        // SAFETY: The supply is registered by the type invariants.
+        to_result(unsafe {
+            bindings::power_supply_get_property_from_supplier(self.as_raw(), prop as _, &mut val)
+        })?;
+        // SAFETY: All the properties of `Property` are integers.
+        Ok(unsafe { val.intval })
+    }
+
+    /// Returns whether any of the supplies this one is supplied from is online.
+    ///
+    /// Fails with `ENODEV` if it isn't supplied from any supply.
+    pub fn am_i_supplied(&self) -> Result<bool> {
+        // SAFETY: The supply is registered by the type invariants.
+        let ret = unsafe { bindings::power_supply_am_i_supplied(self.as_raw()) };
+        to_result(ret)?;
+        Ok(ret != 0)
+    }
+
+    /// Notifies the consumers, the supplies it supplies and user space that the properties of
+    /// the supply changed.
+    ///
+    /// This may be called in atomic context; the notifications are sent from a work item.
+    pub fn changed(&self) {
+        // SAFETY: The supply is registered by the type invariants.
+        unsafe { bindings::power_supply_changed(self.as_raw()) };
+    }
+}
+
+// SAFETY: Instances of `PowerSupply` are always reference-counted through their device.
+unsafe impl AlwaysRefCounted for PowerSupply {
+    fn inc_ref(&self) {
+        // SAFETY: The existence of a shared reference guarantees that the refcount is non-zero.
+        unsafe { bindings::get_device(self.device().as_raw()) };
+    }
+
+    unsafe fn dec_ref(obj: ptr::NonNull<Self>) {
+        // SAFETY: The safety requirements guarantee that the refcount is non-zero.
+        unsafe { bindings::power_supply_put(obj.cast().as_ptr()) };
+    }
+}
+
+// SAFETY: The properties of the supply are read and written by its driver, which does its own
+// locking, and the supply can be released from any thread.
+unsafe impl Send for PowerSupply {}
+
+// SAFETY: See above.
+unsafe impl Sync for PowerSupply {}
+
+/// The events reported on [`EVENTS`], the kernel's `enum power_supply_notifier_events`.
+pub mod event {
+    use core::ffi::c_ulong;
+
+    /// The properties of the supply changed.
+    pub const PROP_CHANGED: c_ulong =
+        crate::bindings::power_supply_notifier_events_PSY_EVENT_PROP_CHANGED as _;
+}
+
+/// The type of [`EVENTS`].
+pub struct Events(());
+
+/// The notifier chain of all power supplies, the kernel's `power_supply_notifier`.
+///
+/// The handlers are called with an action of [`event`] and the supply that changed as data, see
+/// [`PowerSupply::from_event_data`]. They are called in atomic context, so they must defer the
+/// reading of properties, which may sleep, e.g. to a work item.
+///
+/// # Examples
+///
+/// ```
+/// use core::ffi::{c_ulong, c_void};
+/// use core::sync::atomic::{AtomicBool, Ordering};
+/// use kernel::power_supply::{self, PowerSupply};
+/// use kernel::{c_str, notifier, prelude::*};
+///
+/// struct Charger {
+///     usb_changed: AtomicBool,
+/// }
+///
+/// impl notifier::Handler for Charger {
+///     fn notify(&self, action: c_ulong, data: *mut c_void) -> Result<notifier::Notify> {
+///         if action != power_supply::event::PROP_CHANGED {
+///             return Ok(notifier::Notify::Done);
+///         }
+///         // SAFETY: The chain reports the supply that changed.
+///         let psy = unsafe { PowerSupply::from_event_data(data) };
+///         if psy.name() != c_str!("usb") {
+///             return Ok(notifier::Notify::Done);
+///         }
+///         // The input current limit is updated later, in process context.
+///         self.usb_changed.store(true, Ordering::Release);
+///         Ok(notifier::Notify::Ok)
+///     }
+/// }
+///
+/// fn listen() -> Result<notifier::Registration<'static, power_supply::Events, Charger>> {
+///     notifier::Registration::try_new(&power_supply::EVENTS, Charger, 0)
+/// }
+/// ```
+pub static EVENTS: Events = Events(());
+
+impl notifier::Head for Events {
+    unsafe fn register(&self, nb: *mut bindings::notifier_block) -> Result {
+        // SAFETY: The caller guarantees that `nb` is valid.
+        to_result(unsafe { bindings::power_supply_reg_notifier(nb) })
+    }
+
+    unsafe fn unregister(&self, nb: *mut bindings::notifier_block) {
+        // SAFETY: The caller guarantees that `nb` is on the chain.
+        unsafe { bindings::power_supply_unreg_notifier(nb) };
+    }
+}
@@ -33,7 +33,14 @@
 
 pub use super::error::{code::*, Error, Result};
 
-pub use super::{str::CStr, ThisModule};
+pub use super::{c_str, fmt, try_vec};
+
+pub use super::collections::TryClone;
+
+pub use super::{
+    str::{CStr, CString},
+    ThisModule,
+};
 
 pub use super::init::{InPlaceInit, Init, PinInit};
 
@@ -0,0 +1,312 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! procfs entries.
+//!
+//! New interfaces should use sysfs or debugfs instead; this module exists for drivers that must
+//! stay compatible with existing user space that reads files such as `/proc/driver/...`.
+//!
+//! C header: [`include/linux/proc_fs.h`](../../../../include/linux/proc_fs.h)
+
+use crate::{
+    bindings,
+    error::{code::*, from_result, Result},
+    io_buffer::IoBufferReader,
+    seq_file::{self, SeqFile, SeqOperationsVtable},
+    str::CStr,
+    user_ptr::UserSlicePtr,
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{any::Any, ffi::c_int, marker::PhantomData, ptr};
+use macros::vtable;
+
+/// Operations of a procfs file.
+///
+/// Reads are served through a [`SeqFile`], so [`Operations::show`] is called to produce the whole
+/// contents of the file whenever user space reads it from the start.
+#[vtable]
+pub trait Operations: Send + Sync + 'static {
+    /// Produces the contents of the file.
+    fn show(&self, m: &mut SeqFile) -> Result;
+
+    /// Handles a write to the file.
+    ///
+    /// Returns the number of bytes consumed from `reader`. Files that don't implement this are
+    /// read-only.
+    fn write(&self, _reader: &mut impl IoBufferReader) -> Result<usize> {
+        Err(EIO)
+    }
+}
+
+/// A procfs directory tree owned by a driver.
+///
+/// All the directories and files created through the registration live inside a single
+/// directory, which is removed recursively (together with everything in it) when the
+/// registration is dropped. The data backing the files is kept alive by the registration until
+/// then.
+///
+/// # Invariants
+///
+/// `root` is a valid procfs directory that is owned by the registration.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, proc, seq_file::SeqFile, seq_print};
+/// # use kernel::prelude::*;
+///
+/// struct Version;
+///
+/// #[vtable]
+/// impl proc::Operations for Version {
+///     fn show(&self, m: &mut SeqFile) -> Result {
+///         seq_print!(m, "1.0\n");
+///         Ok(())
+///     }
+/// }
+///
+/// fn add_proc() -> Result<proc::Registration> {
+///     let mut reg = proc::Registration::try_new(c_str!("driver/my_driver"))?;
+///     reg.create_file(reg.root(), c_str!("version"), 0o444, Version)?;
+///     Ok(reg)
+/// }
+/// ```
+pub struct Registration {
+    root: *mut bindings::proc_dir_entry,
+    resources: Vec<Box<dyn Any + Send + Sync>>,
+}
+
+// SAFETY: procfs entries can be created and removed from any thread, and the resources are `Send`
+// themselves.
+unsafe impl Send for Registration {}
+
+// SAFETY: `Registration` has no methods that take `&self` other than accessors of the root
+// directory.
+unsafe impl Sync for Registration {}
+
+/// A directory in the tree of a [`Registration`].
+///
+/// This is only a handle: the directory is owned by the registration it was created from, and
+/// it can only be used to create entries with that same registration.
+#[derive(Clone, Copy)]
+pub struct Dir {
+    root: *mut bindings::proc_dir_entry,
+    entry: *mut bindings::proc_dir_entry,
+}
+
+impl Registration {
+    /// Creates a new directory at `path`, relative to `/proc`.
+    ///
+    /// All but the last component of `path` must already exist, e.g. `driver/foo` creates `foo`
+    /// in the existing `/proc/driver` directory.
+    pub fn try_new(path: &CStr) -> Result<Self> {
+        // SAFETY: `path` is a valid C string, and a null parent means the procfs root.
+        let root = unsafe { bindings::proc_mkdir(path.as_char_ptr(), ptr::null_mut()) };
+        if root.is_null() {
+            return Err(ENOMEM);
+        }
+        // INVARIANT: The directory was just created and is owned by us.
+        Ok(Self {
+            root,
+            resources: Vec::new(),
+        })
+    }
+
+    /// Returns the top-level directory of the registration.
+    pub fn root(&self) -> Dir {
+        Dir {
+            root: self.root,
+            entry: self.root,
+        }
+    }
+
+    /// Checks that `dir` belongs to this registration and returns its entry.
+    fn parent(&self, dir: Dir) -> Result<*mut bindings::proc_dir_entry> {
+        if dir.root != self.root {
+            return Err(EINVAL);
+        }
+        Ok(dir.entry)
+    }
+
+    /// Creates a subdirectory named `name` in `parent`.
+    pub fn create_dir(&mut self, parent: Dir, name: &CStr) -> Result<Dir> {
+        let parent = self.parent(parent)?;
+        // SAFETY: `name` is a valid C string and `parent` is a directory of our tree.
+        let entry = unsafe { bindings::proc_mkdir(name.as_char_ptr(), parent) };
+        if entry.is_null() {
+            return Err(ENOMEM);
+        }
+        Ok(Dir {
+            root: self.root,
+            entry,
+        })
+    }
+
+    /// Creates a file implemented by `data`.
+    pub fn create_file<T: Operations>(
+        &mut self,
+        parent: Dir,
+        name: &CStr,
+        mode: u16,
+        data: T,
+    ) -> Result {
+        let parent = self.parent(parent)?;
+        let data = Box::try_new(data)?;
+        let ptr = &*data as *const T as *mut core::ffi::c_void;
+        self.resources.try_push(data)?;
+        // SAFETY: `name` is a valid C string and `parent` is a directory of our tree. `data` is
+        // kept alive until the file is removed, and `ProcOpsVtable<T>` expects a `T` as the entry
+        // data.
+        let entry = unsafe {
+            bindings::proc_create_data(
+                name.as_char_ptr(),
+                mode,
+                parent,
+                ProcOpsVtable::<T>::build(),
+                ptr,
+            )
+        };
+        if entry.is_null() {
+            return Err(ENOMEM);
+        }
+        Ok(())
+    }
+
+    /// Creates a file listing the records produced by `data`.
+    ///
+    /// Unlike files created with [`Registration::create_file`], the contents don't need to fit in
+    /// a single buffer.
+    pub fn create_seq_file<T: seq_file::Operations>(
+        &mut self,
+        parent: Dir,
+        name: &CStr,
+        mode: u16,
+        data: T,
+    ) -> Result {
+        let parent = self.parent(parent)?;
+        let data = Box::try_new(data)?;
+        let ptr = &*data as *const T as *mut core::ffi::c_void;
+        self.resources.try_push(data)?;
+        // SAFETY: `name` is a valid C string and `parent` is a directory of our tree. `data` is
+        // kept alive until the file is removed, and `ProcSeqOpsVtable<T>` expects a `T` as the
+        // entry data.
+        let entry = unsafe {
+            bindings::proc_create_data(
+                name.as_char_ptr(),
+                mode,
+                parent,
+                ProcSeqOpsVtable::<T>::build(),
+                ptr,
+            )
+        };
+        if entry.is_null() {
+            return Err(ENOMEM);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, `root` is a valid directory owned by us. Removal is
+        // recursive and waits for in-flight file operations to finish, so the resources can be
+        // freed afterwards.
+        unsafe { bindings::proc_remove(self.root) };
+    }
+}
+
+struct ProcOpsVtable<T>(PhantomData<T>);
+
+impl<T: Operations> ProcOpsVtable<T> {
+    unsafe extern "C" fn open_callback(
+        inode: *mut bindings::inode,
+        file: *mut bindings::file,
+    ) -> c_int {
+        // SAFETY: The inode belongs to an entry created by `Registration::create_file`, whose data
+        // is a `T` that outlives the entry. `file` is being opened.
+        unsafe { bindings::single_open(file, Some(Self::show_callback), bindings::pde_data(inode)) }
+    }
+
+    unsafe extern "C" fn show_callback(
+        m: *mut bindings::seq_file,
+        _v: *mut core::ffi::c_void,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: `single_open` stored the entry data, a valid `T`, in `private`.
+            let data = unsafe { &*((*m).private as *const T) };
+            // SAFETY: `m` is valid for the duration of the call.
+            data.show(unsafe { SeqFile::from_raw(m) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn write_callback(
+        file: *mut bindings::file,
+        buf: *const core::ffi::c_char,
+        len: usize,
+        _offset: *mut bindings::loff_t,
+    ) -> isize {
+        from_result(|| {
+            // SAFETY: The file's inode belongs to an entry created by `Registration::create_file`,
+            // whose data is a valid `T`.
+            let data = unsafe { &*(bindings::pde_data((*file).f_inode) as *const T) };
+            // SAFETY: The caller guarantees that `buf` and `len` describe a user buffer.
+            let mut reader = unsafe { UserSlicePtr::new(buf as _, len).reader() };
+            Ok(data.write(&mut reader)? as _)
+        })
+    }
+
+    const VTABLE: bindings::proc_ops = bindings::proc_ops {
+        proc_open: Some(Self::open_callback),
+        proc_read: Some(bindings::seq_read),
+        proc_lseek: Some(bindings::seq_lseek),
+        proc_release: Some(bindings::single_release),
+        proc_write: if T::HAS_WRITE {
+            Some(Self::write_callback)
+        } else {
+            None
+        },
+        // SAFETY: All other fields are either pointers, for which `NULL` means "not implemented",
+        // or plain integers, for which zero is the correct default.
+        ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+    };
+
+    const fn build() -> &'static bindings::proc_ops {
+        &Self::VTABLE
+    }
+}
+
+struct ProcSeqOpsVtable<T>(PhantomData<T>);
+
+impl<T: seq_file::Operations> ProcSeqOpsVtable<T> {
+    unsafe extern "C" fn open_callback(
+        inode: *mut bindings::inode,
+        file: *mut bindings::file,
+    ) -> c_int {
+        // SAFETY: `file` is being opened, and the seq operations are static.
+        let ret = unsafe { bindings::seq_open(file, SeqOperationsVtable::<T>::build()) };
+        if ret != 0 {
+            return ret;
+        }
+        // SAFETY: `seq_open` stored a valid `struct seq_file` in `private_data`. The inode belongs
+        // to an entry created by `Registration::create_seq_file`, whose data is a valid `T`.
+        unsafe {
+            let m = (*file).private_data as *mut bindings::seq_file;
+            (*m).private = bindings::pde_data(inode);
+        }
+        0
+    }
+
+    const VTABLE: bindings::proc_ops = bindings::proc_ops {
+        proc_open: Some(Self::open_callback),
+        proc_read: Some(bindings::seq_read),
+        proc_lseek: Some(bindings::seq_lseek),
+        proc_release: Some(bindings::seq_release),
+        // SAFETY: All other fields are either pointers, for which `NULL` means "not implemented",
+        // or plain integers, for which zero is the correct default.
+        ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+    };
+
+    const fn build() -> &'static bindings::proc_ops {
+        &Self::VTABLE
+    }
+}
@@ -0,0 +1,143 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! PWM consumers.
+//!
+//! Drivers get the PWM outputs wired to their devices with [`Pwm::get`], and configure them with
+//! [`Pwm::apply`].
+//!
+//! C header: [`include/linux/pwm.h`](../../../../include/linux/pwm.h)
+
+use crate::{
+    bindings,
+    device::Device,
+    error::{from_err_ptr, to_result, Result},
+    str::CStr,
+};
+use core::ptr::{self, NonNull};
+
+/// The state of a PWM output, the kernel's `struct pwm_state`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct State {
+    /// The period, in nanoseconds.
+    pub period: u64,
+    /// The active time of each period, in nanoseconds.
+    pub duty_cycle: u64,
+    /// Whether the output is low, instead of high, during the active time.
+    pub inversed: bool,
+    /// Whether the output is enabled.
+    pub enabled: bool,
+}
+
+impl State {
+    fn from_raw(raw: &bindings::pwm_state) -> Self {
+        Self {
+            period: raw.period,
+            duty_cycle: raw.duty_cycle,
+            inversed: raw.polarity == bindings::pwm_polarity_PWM_POLARITY_INVERSED,
+            enabled: raw.enabled,
+        }
+    }
+
+    fn to_raw(self) -> bindings::pwm_state {
+        bindings::pwm_state {
+            period: self.period,
+            duty_cycle: self.duty_cycle,
+            polarity: if self.inversed {
+                bindings::pwm_polarity_PWM_POLARITY_INVERSED
+            } else {
+                bindings::pwm_polarity_PWM_POLARITY_NORMAL
+            },
+            enabled: self.enabled,
+            usage_power: false,
+        }
+    }
+}
+
+/// A PWM output used by a driver, the kernel's `struct pwm_device`.
+///
+/// The output is released when this is dropped.
+///
+/// # Invariants
+///
+/// `pwm` is a valid PWM output, requested by `Pwm`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{device::Device, prelude::*, pwm::Pwm};
+///
+/// fn set_half_duty(dev: &Device) -> Result<Pwm> {
+///     let mut pwm = Pwm::get(dev, None)?;
+///     let mut state = pwm.init_state();
+///     state.duty_cycle = state.period / 2;
+///     state.enabled = true;
+///     pwm.apply(&state)?;
+///     Ok(pwm)
+/// }
+/// ```
+pub struct Pwm {
+    pwm: NonNull<bindings::pwm_device>,
+}
+
+impl Pwm {
+    /// Requests the PWM output of `dev` named `con_id` in the devicetree, or its first one if
+    /// `con_id` is `None`.
+    pub fn get(dev: &Device, con_id: Option<&CStr>) -> Result<Self> {
+        let con_id = con_id.map_or(ptr::null(), |id| id.as_char_ptr());
+        // SAFETY: `dev` is valid, and `con_id` is either null or a valid string.
+        let pwm = from_err_ptr(unsafe { bindings::pwm_get(dev.as_raw(), con_id) })?;
+        // INVARIANT: The output was requested above.
+        Ok(Self {
+            // SAFETY: `pwm_get` never returns null on success.
+            pwm: unsafe { NonNull::new_unchecked(pwm) },
+        })
+    }
+
+    fn as_raw(&self) -> *mut bindings::pwm_device {
+        self.pwm.as_ptr()
+    }
+
+    /// Returns the current state of the output.
+    pub fn state(&self) -> State {
+        // SAFETY: The output is valid by the type invariants. Its state only changes when it is
+        // applied, which requires a mutable reference.
+        State::from_raw(unsafe { &(*self.as_raw()).state })
+    }
+
+    /// Returns a disabled state with the period and polarity given in the devicetree, and no
+    /// active time, like the kernel's `pwm_init_state()`.
+    pub fn init_state(&self) -> State {
+        // SAFETY: The output is valid by the type invariants, and its arguments never change.
+        let args = unsafe { &(*self.as_raw()).args };
+        State {
+            period: args.period,
+            duty_cycle: 0,
+            inversed: args.polarity == bindings::pwm_polarity_PWM_POLARITY_INVERSED,
+            enabled: false,
+        }
+    }
+
+    /// Applies `state` to the output.
+    ///
+    /// This may sleep.
+    pub fn apply(&mut self, state: &State) -> Result {
+        crate::might_sleep!();
+        let raw = state.to_raw();
+        // SAFETY: The output is valid by the type invariants, and `raw` is copied.
+        to_result(unsafe { bindings::pwm_apply_state(self.as_raw(), &raw) })
+    }
+}
+
+impl Drop for Pwm {
+    fn drop(&mut self) {
+        // SAFETY: The output was requested by `Pwm` by the type invariants.
+        unsafe { bindings::pwm_put(self.as_raw()) };
+    }
+}
+
+// SAFETY: The output can be used and released from any thread.
+unsafe impl Send for Pwm {}
+
+// SAFETY: The methods that take `&self` only read the output, whose state is only changed by
+// methods that take `&mut self`.
+unsafe impl Sync for Pwm {}
@@ -0,0 +1,73 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Random numbers.
+//!
+//! The random numbers come from the kernel's cryptographically secure random number generator,
+//! and are suitable for keys and nonces. Drivers of devices with unique data, e.g. serial numbers
+//! or MAC addresses, add it to the generator with [`add_device_randomness`].
+//!
+//! C header: [`include/linux/random.h`](../../../../include/linux/random.h)
+
+use crate::{
+    bindings,
+    error::{to_result, Result},
+};
+
+/// Fills `buf` with random bytes.
+///
+/// This doesn't block, even if the generator isn't seeded yet early during boot. Use
+/// [`wait_for_random_bytes`] first where that matters.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::random;
+///
+/// let mut nonce = [0u8; 12];
+/// random::fill_bytes(&mut nonce);
+/// ```
+pub fn fill_bytes(buf: &mut [u8]) {
+    // SAFETY: `buf` is valid for writes of its length.
+    unsafe { bindings::get_random_bytes(buf.as_mut_ptr().cast(), buf.len()) };
+}
+
+/// Returns a random `u32`.
+///
+/// This is faster than [`fill_bytes`] for small amounts of randomness, and doesn't block.
+pub fn u32() -> u32 {
+    // SAFETY: FFI call.
+    unsafe { bindings::get_random_u32() }
+}
+
+/// Returns a random `u64`.
+///
+/// This is faster than [`fill_bytes`] for small amounts of randomness, and doesn't block.
+pub fn u64() -> u64 {
+    // SAFETY: FFI call.
+    unsafe { bindings::get_random_u64() }
+}
+
+/// Returns a uniformly distributed random number that is less than `ceil`, which must not be 0.
+pub fn u32_below(ceil: u32) -> u32 {
+    // SAFETY: FFI call.
+    unsafe { bindings::get_random_u32_below(ceil) }
+}
+
+/// Waits until the generator is seeded.
+///
+/// This may sleep, and fails with `ERESTARTSYS` if the task is interrupted by a signal.
+pub fn wait_for_random_bytes() -> Result {
+    crate::might_sleep!();
+    // SAFETY: FFI call.
+    to_result(unsafe { bindings::wait_for_random_bytes() })
+}
+
+/// Adds `data`, which is unique to the device but not secret, e.g. a serial number, to the
+/// generator.
+///
+/// This doesn't credit the generator with entropy, but makes the random numbers differ between
+/// devices that boot identically.
+pub fn add_device_randomness(data: &[u8]) {
+    // SAFETY: `data` is valid for reads of its length.
+    unsafe { bindings::add_device_randomness(data.as_ptr().cast(), data.len()) };
+}
@@ -0,0 +1,482 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Red-black trees.
+//!
+//! An [`RBTree`] is an ordered map built on the kernel's `struct rb_root`, whose nodes are
+//! allocated separately. Nodes can be allocated ahead of time, with [`RBTree::try_reserve_node`]
+//! or [`RBTree::try_allocate_node`], so that values can be inserted while holding a spinlock.
+//!
+//! C header: [`include/linux/rbtree.h`](../../../../include/linux/rbtree.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/core-api/rbtree.html>
+
+use crate::{bindings, error::Result};
+use alloc::boxed::Box;
+use core::{
+    cmp::{Ord, Ordering},
+    marker::PhantomData,
+    mem::MaybeUninit,
+    ptr::{addr_of_mut, NonNull},
+};
+
+/// A node of an [`RBTree`].
+///
+/// The links come first, so that a pointer to them is a pointer to the node.
+#[repr(C)]
+struct Node<K, V> {
+    links: bindings::rb_node,
+    key: K,
+    value: V,
+}
+
+/// A red-black tree, which maps keys of type `K` to values of type `V`.
+///
+/// Lookups, insertions and removals are O(log n), and the entries are iterated in increasing
+/// order of their keys.
+///
+/// # Invariants
+///
+/// The nodes of `root` are links of [`Node<K, V>`] allocated with [`Box`], and owned by the
+/// tree. The nodes are ordered by their keys, which are unique.
+///
+/// # Examples
+///
+/// A map of the IOVA ranges mapped for a device, indexed by their start:
+///
+/// ```
+/// use kernel::{prelude::*, rbtree::RBTree};
+///
+/// struct Mapping {
+///     size: u64,
+///     phys: u64,
+/// }
+///
+/// fn map(tree: &mut RBTree<u64, Mapping>, iova: u64, size: u64, phys: u64) -> Result {
+///     tree.try_create_and_insert(iova, Mapping { size, phys })?;
+///     Ok(())
+/// }
+///
+/// fn translate(tree: &RBTree<u64, Mapping>, iova: u64) -> Option<u64> {
+///     let (start, m) = tree.floor(&iova)?;
+///     (iova - start < m.size).then(|| m.phys + (iova - start))
+/// }
+///
+/// fn dump(tree: &RBTree<u64, Mapping>) {
+///     for (iova, m) in tree {
+///         pr_info!("{:#x}+{:#x} -> {:#x}\n", iova, m.size, m.phys);
+///     }
+/// }
+/// ```
+///
+/// Inserting under a spinlock, with a node reserved beforehand:
+///
+/// ```
+/// use kernel::{prelude::*, rbtree::RBTree, sync::SpinLock};
+///
+/// fn insert(tree: &SpinLock<RBTree<u32, u32>>, key: u32, value: u32) -> Result {
+///     let reservation = RBTree::try_reserve_node()?;
+///     let mut guard = tree.lock();
+///     guard.insert(reservation.into_node(key, value));
+///     Ok(())
+/// }
+/// ```
+pub struct RBTree<K, V> {
+    root: bindings::rb_root,
+    _p: PhantomData<Node<K, V>>,
+}
+
+impl<K, V> RBTree<K, V> {
+    /// Creates a new, empty tree.
+    pub fn new() -> Self {
+        // INVARIANT: The tree has no nodes.
+        Self {
+            root: bindings::rb_root {
+                rb_node: core::ptr::null_mut(),
+            },
+            _p: PhantomData,
+        }
+    }
+
+    /// Returns whether the tree is empty.
+    pub fn is_empty(&self) -> bool {
+        self.root.rb_node.is_null()
+    }
+
+    /// Allocates memory for a node, to be inserted later.
+    ///
+    /// This may sleep, but the node can then be inserted from atomic context.
+    pub fn try_reserve_node() -> Result<RBTreeNodeReservation<K, V>> {
+        Ok(RBTreeNodeReservation {
+            node: Box::try_new(MaybeUninit::uninit())?,
+        })
+    }
+
+    /// Allocates a node holding `key` and `value`, to be inserted later.
+    ///
+    /// See [`RBTree::try_reserve_node`].
+    pub fn try_allocate_node(key: K, value: V) -> Result<RBTreeNode<K, V>> {
+        Ok(Self::try_reserve_node()?.into_node(key, value))
+    }
+
+    /// Returns an iterator over the entries of the tree, in increasing order of their keys.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            // SAFETY: The root is valid.
+            next: unsafe { bindings::rb_first(&self.root) },
+            _p: PhantomData,
+        }
+    }
+
+    /// Returns an iterator over the entries of the tree, in increasing order of their keys, with
+    /// mutable references to the values.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            // SAFETY: The root is valid.
+            next: unsafe { bindings::rb_first(&self.root) },
+            _p: PhantomData,
+        }
+    }
+
+    /// Returns an iterator over the keys of the tree, in increasing order.
+    pub fn keys(&self) -> impl Iterator<Item = &'_ K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// Returns an iterator over the values of the tree, in increasing order of their keys.
+    pub fn values(&self) -> impl Iterator<Item = &'_ V> {
+        self.iter().map(|(_, v)| v)
+    }
+
+    /// Returns an iterator over mutable references to the values of the tree, in increasing
+    /// order of their keys.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &'_ mut V> {
+        self.iter_mut().map(|(_, v)| v)
+    }
+
+    /// Returns the entry with the smallest key, if any.
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.iter().next()
+    }
+
+    /// Returns the entry with the largest key, if any.
+    pub fn last(&self) -> Option<(&K, &V)> {
+        // SAFETY: The root is valid.
+        let links = unsafe { bindings::rb_last(&self.root) };
+        // SAFETY: By the type invariants, non-null links are those of a node owned by the tree,
+        // which is borrowed for as long as the returned references.
+        NonNull::new(links).map(|links| unsafe { entry(links.as_ptr()) })
+    }
+}
+
+impl<K: Ord, V> RBTree<K, V> {
+    /// Allocates a node holding `key` and `value`, and inserts it into the tree.
+    ///
+    /// Returns the node that was replaced, if the tree already had an entry for `key`.
+    pub fn try_create_and_insert(&mut self, key: K, value: V) -> Result<Option<RBTreeNode<K, V>>> {
+        Ok(self.insert(Self::try_allocate_node(key, value)?))
+    }
+
+    /// Inserts `node` into the tree.
+    ///
+    /// Returns the node that was replaced, if the tree already had an entry for its key. This
+    /// doesn't allocate memory, so it may be called from atomic context.
+    pub fn insert(&mut self, node: RBTreeNode<K, V>) -> Option<RBTreeNode<K, V>> {
+        let node = Box::into_raw(node.node);
+        // SAFETY: `node` is valid, as it was just converted from a `Box`.
+        let node_links = unsafe { addr_of_mut!((*node).links) };
+        let mut parent = core::ptr::null_mut();
+        let mut child = &mut self.root.rb_node as *mut *mut bindings::rb_node;
+        // SAFETY: `child` points to the root of the tree, or to a child of one of its nodes, and
+        // the nodes are valid by the type invariants.
+        unsafe {
+            while !(*child).is_null() {
+                parent = *child;
+                let this = parent as *mut Node<K, V>;
+                child = match (*node).key.cmp(&(*this).key) {
+                    Ordering::Less => addr_of_mut!((*parent).rb_left),
+                    Ordering::Greater => addr_of_mut!((*parent).rb_right),
+                    Ordering::Equal => {
+                        // INVARIANT: The new node takes the place of the old one, which had the
+                        // same key, and the tree gives up the old one.
+                        bindings::rb_replace_node(parent, node_links, &mut self.root);
+                        return Some(RBTreeNode {
+                            node: Box::from_raw(this),
+                        });
+                    }
+                };
+            }
+        }
+        // SAFETY: `parent` is null or a node of the tree, whose free child is `child`, where the
+        // new node belongs. The tree owns the node from then on, which is valid.
+        unsafe {
+            bindings::rb_link_node(node_links, parent, child);
+            bindings::rb_insert_color(node_links, &mut self.root);
+        }
+        None
+    }
+
+    /// Returns the node with the given key, if any.
+    fn find(&self, key: &K) -> Option<NonNull<Node<K, V>>> {
+        let mut links = self.root.rb_node;
+        while !links.is_null() {
+            let this = links as *mut Node<K, V>;
+            // SAFETY: By the type invariants, non-null links are those of a node owned by the
+            // tree.
+            links = unsafe {
+                match key.cmp(&(*this).key) {
+                    Ordering::Less => (*links).rb_left,
+                    Ordering::Greater => (*links).rb_right,
+                    Ordering::Equal => return NonNull::new(this),
+                }
+            };
+        }
+        None
+    }
+
+    /// Returns a reference to the value for `key`, if any.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        // SAFETY: The node is owned by the tree, which is borrowed for as long as the reference.
+        self.find(key)
+            .map(|node| unsafe { &(*node.as_ptr()).value })
+    }
+
+    /// Returns a mutable reference to the value for `key`, if any.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        // SAFETY: The node is owned by the tree, which is mutably borrowed for as long as the
+        // reference.
+        self.find(key)
+            .map(|node| unsafe { &mut (*node.as_ptr()).value })
+    }
+
+    /// Returns whether the tree has an entry for `key`.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.find(key).is_some()
+    }
+
+    /// Returns the entry with the largest key that is smaller than or equal to `key`, if any.
+    ///
+    /// For a tree indexed by the start of ranges, this is the range that may contain `key`.
+    pub fn floor(&self, key: &K) -> Option<(&K, &V)> {
+        let mut links = self.root.rb_node;
+        let mut best = None;
+        while !links.is_null() {
+            let this = links as *mut Node<K, V>;
+            // SAFETY: By the type invariants, non-null links are those of a node owned by the
+            // tree.
+            links = unsafe {
+                match key.cmp(&(*this).key) {
+                    Ordering::Less => (*links).rb_left,
+                    Ordering::Greater => {
+                        best = Some(links);
+                        (*links).rb_right
+                    }
+                    Ordering::Equal => {
+                        best = Some(links);
+                        break;
+                    }
+                }
+            };
+        }
+        // SAFETY: The node is owned by the tree, which is borrowed for as long as the references.
+        best.map(|links| unsafe { entry(links) })
+    }
+
+    /// Returns the entry with the smallest key that is larger than or equal to `key`, if any.
+    pub fn ceiling(&self, key: &K) -> Option<(&K, &V)> {
+        let mut links = self.root.rb_node;
+        let mut best = None;
+        while !links.is_null() {
+            let this = links as *mut Node<K, V>;
+            // SAFETY: By the type invariants, non-null links are those of a node owned by the
+            // tree.
+            links = unsafe {
+                match key.cmp(&(*this).key) {
+                    Ordering::Greater => (*links).rb_right,
+                    Ordering::Less => {
+                        best = Some(links);
+                        (*links).rb_left
+                    }
+                    Ordering::Equal => {
+                        best = Some(links);
+                        break;
+                    }
+                }
+            };
+        }
+        // SAFETY: The node is owned by the tree, which is borrowed for as long as the references.
+        best.map(|links| unsafe { entry(links) })
+    }
+
+    /// Removes the node with the given key from the tree, and returns it, if any.
+    ///
+    /// The node can be reused for another insertion, without allocating memory.
+    pub fn remove_node(&mut self, key: &K) -> Option<RBTreeNode<K, V>> {
+        let node = self.find(key)?.as_ptr();
+        // SAFETY: The node is in the tree. The tree gives up its ownership, and the node was
+        // allocated with `Box` by the type invariants.
+        unsafe {
+            bindings::rb_erase(addr_of_mut!((*node).links), &mut self.root);
+            Some(RBTreeNode {
+                node: Box::from_raw(node),
+            })
+        }
+    }
+
+    /// Removes the entry with the given key from the tree, and returns its value, if any.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove_node(key).map(|node| node.node.value)
+    }
+}
+
+impl<K, V> Default for RBTree<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Drop for RBTree<K, V> {
+    fn drop(&mut self) {
+        // SAFETY: The root is valid.
+        let mut next = unsafe { bindings::rb_first_postorder(&self.root) };
+        while !next.is_null() {
+            let this = next as *mut Node<K, V>;
+            // SAFETY: `next` is a node of the tree. Its successor in postorder is found before
+            // it is freed, and the nodes that come after it in postorder aren't freed yet.
+            next = unsafe { bindings::rb_next_postorder(next) };
+            // SAFETY: By the type invariants, the node was allocated with `Box` and is owned by
+            // the tree, which doesn't access it anymore.
+            drop(unsafe { Box::from_raw(this) });
+        }
+    }
+}
+
+// SAFETY: The tree owns its keys and values, so it can be sent to another thread if they can.
+unsafe impl<K: Send, V: Send> Send for RBTree<K, V> {}
+
+// SAFETY: Shared references to the tree only give out shared references to its keys and values.
+unsafe impl<K: Sync, V: Sync> Sync for RBTree<K, V> {}
+
+/// Returns the key and value of the node with the given links.
+///
+/// # Safety
+///
+/// `links` must be those of a valid node, which outlives `'a` and isn't modified meanwhile.
+unsafe fn entry<'a, K, V>(links: *mut bindings::rb_node) -> (&'a K, &'a V) {
+    let node = links as *mut Node<K, V>;
+    // SAFETY: The node is valid for `'a`, by the safety requirements.
+    unsafe { (&(*node).key, &(*node).value) }
+}
+
+/// Memory for a node of an [`RBTree`], returned by [`RBTree::try_reserve_node`].
+pub struct RBTreeNodeReservation<K, V> {
+    node: Box<MaybeUninit<Node<K, V>>>,
+}
+
+impl<K, V> RBTreeNodeReservation<K, V> {
+    /// Initialises the node with `key` and `value`, without allocating memory.
+    pub fn into_node(self, key: K, value: V) -> RBTreeNode<K, V> {
+        let mut node = self.node;
+        node.write(Node {
+            // SAFETY: All fields are pointers, for which zero is valid. The links are set when the
+            // node is inserted.
+            links: unsafe { core::mem::zeroed() },
+            key,
+            value,
+        });
+        RBTreeNode {
+            // SAFETY: The node was initialised above.
+            node: unsafe { node.assume_init() },
+        }
+    }
+}
+
+/// A node of an [`RBTree`] that isn't in a tree, holding a key and a value.
+///
+/// It is returned by [`RBTree::try_allocate_node`], or by the tree when the node is replaced or
+/// removed.
+pub struct RBTreeNode<K, V> {
+    node: Box<Node<K, V>>,
+}
+
+impl<K, V> RBTreeNode<K, V> {
+    /// Returns the key and value of the node.
+    pub fn to_key_value(self) -> (K, V) {
+        (self.node.key, self.node.value)
+    }
+}
+
+/// An iterator over the entries of an [`RBTree`], returned by [`RBTree::iter`].
+pub struct Iter<'a, K, V> {
+    next: *mut bindings::rb_node,
+    _p: PhantomData<&'a RBTree<K, V>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next.is_null() {
+            return None;
+        }
+        let cur = self.next;
+        // SAFETY: `cur` is a node of the tree, which is borrowed for `'a`.
+        unsafe {
+            self.next = bindings::rb_next(cur);
+            Some(entry(cur))
+        }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a RBTree<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// SAFETY: The iterator only gives out shared references to the keys and values.
+unsafe impl<K: Sync, V: Sync> Send for Iter<'_, K, V> {}
+
+// SAFETY: The iterator has no methods that take `&self`.
+unsafe impl<K: Sync, V: Sync> Sync for Iter<'_, K, V> {}
+
+/// An iterator over the entries of an [`RBTree`], with mutable references to the values,
+/// returned by [`RBTree::iter_mut`].
+pub struct IterMut<'a, K, V> {
+    next: *mut bindings::rb_node,
+    _p: PhantomData<&'a mut RBTree<K, V>>,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next.is_null() {
+            return None;
+        }
+        let cur = self.next as *mut Node<K, V>;
+        // SAFETY: `cur` is a node of the tree, which is mutably borrowed for `'a`. Each node is
+        // returned once, so the mutable references to the values don't alias.
+        unsafe {
+            self.next = bindings::rb_next(self.next);
+            Some((&(*cur).key, &mut (*cur).value))
+        }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a mut RBTree<K, V> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+// SAFETY: The iterator gives out shared references to the keys and mutable ones to the values.
+unsafe impl<K: Sync, V: Send> Send for IterMut<'_, K, V> {}
+
+// SAFETY: The iterator has no methods that take `&self`.
+unsafe impl<K: Sync, V: Sync> Sync for IterMut<'_, K, V> {}
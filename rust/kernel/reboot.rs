@@ -0,0 +1,66 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! System reboot and power off.
+//!
+//! Drivers whose devices must be quiesced before the system restarts or powers off, and which
+//! can't do so from the `shutdown` callback of their bus, add a handler to the [`RebootChain`].
+//!
+//! C header: [`include/linux/reboot.h`](../../../../include/linux/reboot.h)
+
+use crate::{
+    bindings,
+    error::{to_result, Result},
+    notifier,
+};
+
+/// Actions of the [`RebootChain`], the kernel's `SYS_*` values.
+pub mod action {
+    /// The system restarts.
+    pub const RESTART: u32 = crate::bindings::SYS_RESTART;
+    /// The system halts.
+    pub const HALT: u32 = crate::bindings::SYS_HALT;
+    /// The system powers off.
+    pub const POWER_OFF: u32 = crate::bindings::SYS_POWER_OFF;
+}
+
+/// The notifier chain of system reboot, halt and power off, the kernel's reboot notifier list.
+///
+/// The handlers are called in process context with an [`action`], and may sleep. The data is
+/// the command passed to `reboot(2)`, a C string, or null. They are called before the devices are
+/// shut down, and can't stop the reboot.
+///
+/// # Examples
+///
+/// ```
+/// use core::ffi::{c_ulong, c_void};
+/// use kernel::{notifier, prelude::*, reboot};
+///
+/// struct Charger;
+///
+/// impl notifier::Handler for Charger {
+///     fn notify(&self, action: c_ulong, _data: *mut c_void) -> Result<notifier::Notify> {
+///         if action as u32 != reboot::action::POWER_OFF {
+///             return Ok(notifier::Notify::Done);
+///         }
+///         pr_info!("switching the charger to standalone mode\n");
+///         Ok(notifier::Notify::Ok)
+///     }
+/// }
+///
+/// fn listen() -> Result<notifier::Registration<'static, reboot::RebootChain, Charger>> {
+///     notifier::Registration::try_new(&reboot::RebootChain, Charger, 0)
+/// }
+/// ```
+pub struct RebootChain;
+
+impl notifier::Head for RebootChain {
+    unsafe fn register(&self, nb: *mut bindings::notifier_block) -> Result {
+        // SAFETY: The caller guarantees that `nb` is valid.
+        to_result(unsafe { bindings::register_reboot_notifier(nb) })
+    }
+
+    unsafe fn unregister(&self, nb: *mut bindings::notifier_block) {
+        // SAFETY: The caller guarantees that `nb` is on the chain.
+        unsafe { bindings::unregister_reboot_notifier(nb) };
+    }
+}
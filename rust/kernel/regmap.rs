@@ -0,0 +1,460 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Register maps.
+//!
+//! A register map ([`Regmap`]) gives access to the registers of a device through its bus, with
+//! an optional cache of their values, so that drivers don't need to know how the bus transfers
+//! them.
+//!
+//! C header: [`include/linux/regmap.h`](../../../../include/linux/regmap.h)
+
+use crate::{
+    bindings,
+    device::Device,
+    error::{code::*, from_err_ptr, to_result, Result},
+    str::CStr,
+};
+use core::{
+    mem::{size_of, MaybeUninit},
+    ptr::NonNull,
+};
+
+/// The kind of cache of a register map, the kernel's `enum regcache_type`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CacheType {
+    /// The registers are not cached.
+    None,
+    /// The registers are cached in a flat array, for small and dense register maps.
+    Flat,
+    /// The registers are cached in a red-black tree.
+    RbTree,
+    /// The registers are cached in a maple tree.
+    Maple,
+}
+
+impl CacheType {
+    const fn as_raw(self) -> bindings::regcache_type {
+        match self {
+            Self::None => bindings::regcache_type_REGCACHE_NONE,
+            Self::Flat => bindings::regcache_type_REGCACHE_FLAT,
+            Self::RbTree => bindings::regcache_type_REGCACHE_RBTREE,
+            Self::Maple => bindings::regcache_type_REGCACHE_MAPLE,
+        }
+    }
+}
+
+/// A range of registers, from `min` to `max` inclusive, the kernel's `struct regmap_range`.
+#[repr(transparent)]
+pub struct Range(bindings::regmap_range);
+
+impl Range {
+    /// Creates a range from `min` to `max` inclusive.
+    pub const fn new(min: u32, max: u32) -> Self {
+        Self(bindings::regmap_range {
+            range_min: min,
+            range_max: max,
+        })
+    }
+}
+
+/// A table of registers, the kernel's `struct regmap_access_table`.
+///
+/// A register is in the table if it is in one of the `yes` ranges and none of the `no` ranges,
+/// or if there are no `yes` ranges and it isn't in the `no` ranges.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::regmap::{AccessTable, Range};
+///
+/// // The status and interrupt registers change behind the cache's back.
+/// static VOLATILE_RANGES: [Range; 2] = [Range::new(0x00, 0x01), Range::new(0x10, 0x13)];
+/// static VOLATILE: AccessTable = AccessTable::new(&VOLATILE_RANGES, &[]);
+/// ```
+#[repr(transparent)]
+pub struct AccessTable(bindings::regmap_access_table);
+
+impl AccessTable {
+    /// Creates a table with the registers in `yes` but not in `no`.
+    pub const fn new(yes: &'static [Range], no: &'static [Range]) -> Self {
+        Self(bindings::regmap_access_table {
+            yes_ranges: yes.as_ptr().cast(),
+            n_yes_ranges: yes.len() as _,
+            no_ranges: no.as_ptr().cast(),
+            n_no_ranges: no.len() as _,
+        })
+    }
+}
+
+// SAFETY: The table only points to static ranges, which are never modified.
+unsafe impl Sync for AccessTable {}
+
+/// The value of a register after reset, the kernel's `struct reg_default`.
+#[repr(transparent)]
+pub struct RegDefault(bindings::reg_default);
+
+impl RegDefault {
+    /// Creates the default `value` of the register `reg`.
+    pub const fn new(reg: u32, value: u32) -> Self {
+        Self(bindings::reg_default { reg, def: value })
+    }
+}
+
+/// The configuration of a register map, the kernel's `struct regmap_config`.
+///
+/// The configuration is copied when the register map is created, but the tables it refers to
+/// are used for the lifetime of the map, so they are static.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::regmap::{AccessTable, CacheType, Config, Range, RegDefault};
+///
+/// static VOLATILE_RANGES: [Range; 1] = [Range::new(0x00, 0x01)];
+/// static VOLATILE: AccessTable = AccessTable::new(&VOLATILE_RANGES, &[]);
+/// static DEFAULTS: [RegDefault; 2] = [RegDefault::new(0x02, 0x80), RegDefault::new(0x03, 0x10)];
+///
+/// const CONFIG: Config = Config::new(8, 8)
+///     .with_max_register(0x3f)
+///     .with_cache_type(CacheType::Maple)
+///     .with_defaults(&DEFAULTS)
+///     .with_volatile_table(&VOLATILE);
+/// ```
+pub struct Config {
+    raw: bindings::regmap_config,
+}
+
+impl Config {
+    /// Creates a configuration with registers addressed by `reg_bits` bits and values of
+    /// `val_bits` bits, without cache.
+    pub const fn new(reg_bits: u32, val_bits: u32) -> Self {
+        // SAFETY: All fields are optional, for which zero is valid.
+        let mut raw: bindings::regmap_config = unsafe { MaybeUninit::zeroed().assume_init() };
+        raw.reg_bits = reg_bits as _;
+        raw.val_bits = val_bits as _;
+        Self { raw }
+    }
+
+    /// Sets the name of the register map, to tell apart the maps of a device in debugfs.
+    pub const fn with_name(mut self, name: &'static CStr) -> Self {
+        self.raw.name = name.as_char_ptr();
+        self
+    }
+
+    /// Sets the distance between the addresses of consecutive registers.
+    pub const fn with_reg_stride(mut self, stride: u32) -> Self {
+        self.raw.reg_stride = stride as _;
+        self
+    }
+
+    /// Sets the address of the last register, which is required for caches.
+    pub const fn with_max_register(mut self, max: u32) -> Self {
+        self.raw.max_register = max;
+        self
+    }
+
+    /// Sets the kind of cache of the register map.
+    pub const fn with_cache_type(mut self, cache_type: CacheType) -> Self {
+        self.raw.cache_type = cache_type.as_raw();
+        self
+    }
+
+    /// Sets the values of the registers after reset, which the cache starts with.
+    pub const fn with_defaults(mut self, defaults: &'static [RegDefault]) -> Self {
+        self.raw.reg_defaults = defaults.as_ptr().cast();
+        self.raw.num_reg_defaults = defaults.len() as _;
+        self
+    }
+
+    /// Sets the registers that can be written.
+    pub const fn with_write_table(mut self, table: &'static AccessTable) -> Self {
+        self.raw.wr_table = &table.0;
+        self
+    }
+
+    /// Sets the registers that can be read.
+    pub const fn with_read_table(mut self, table: &'static AccessTable) -> Self {
+        self.raw.rd_table = &table.0;
+        self
+    }
+
+    /// Sets the registers that the hardware changes, which are never cached.
+    pub const fn with_volatile_table(mut self, table: &'static AccessTable) -> Self {
+        self.raw.volatile_table = &table.0;
+        self
+    }
+
+    /// Sets the registers that change when they are read, e.g. clear-on-read status registers,
+    /// which are only read when requested.
+    pub const fn with_precious_table(mut self, table: &'static AccessTable) -> Self {
+        self.raw.precious_table = &table.0;
+        self
+    }
+
+    /// Protects the register map with a spinlock instead of a mutex, for MMIO register maps that
+    /// are used in atomic context.
+    pub const fn with_fast_io(mut self) -> Self {
+        self.raw.fast_io = true;
+        self
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+
+    impl Sealed for u8 {}
+    impl Sealed for u16 {}
+    impl Sealed for u32 {}
+}
+
+/// The type of the values of a register map, for bulk accesses.
+///
+/// Implemented for `u8`, `u16` and `u32`, which must match the size of the values of the map.
+pub trait Value: Copy + private::Sealed {}
+
+impl Value for u8 {}
+impl Value for u16 {}
+impl Value for u32 {}
+
+/// A register map, the kernel's `struct regmap`.
+///
+/// The register map is freed when this is dropped.
+///
+/// # Invariants
+///
+/// `map` is a valid register map, owned by `Regmap`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{device::Device, prelude::*, regmap::{Config, Regmap}};
+///
+/// const REG_ID: u32 = 0x00;
+/// const REG_CTRL: u32 = 0x01;
+/// const CTRL_ENABLE: u32 = 1 << 0;
+///
+/// fn probe(dev: &Device) -> Result<Regmap> {
+///     let map = Regmap::new_i2c(dev, &Config::new(8, 8).with_max_register(0x3f))?;
+///     if map.read(REG_ID)? != 0x5a {
+///         return Err(ENODEV);
+///     }
+///     map.set_bits(REG_CTRL, CTRL_ENABLE)?;
+///     let mut coeffs = [0u8; 16];
+///     map.bulk_read(0x20, &mut coeffs)?;
+///     Ok(map)
+/// }
+/// ```
+pub struct Regmap {
+    map: NonNull<bindings::regmap>,
+}
+
+impl Regmap {
+    fn from_raw(map: *mut bindings::regmap) -> Result<Self> {
+        let map = from_err_ptr(map)?;
+        // INVARIANT: The register map was just created, and is owned by the new `Regmap`.
+        Ok(Self {
+            // SAFETY: The register map constructors never return null on success.
+            map: unsafe { NonNull::new_unchecked(map) },
+        })
+    }
+
+    /// Creates a register map for `dev`, which must be an I2C client.
+    ///
+    /// Fails with `EINVAL` if `dev` isn't an I2C client.
+    #[cfg(CONFIG_REGMAP_I2C)]
+    pub fn new_i2c(dev: &Device, config: &Config) -> Result<Self> {
+        // SAFETY: `dev` is valid.
+        let client = unsafe { bindings::i2c_verify_client(dev.as_raw()) };
+        if client.is_null() {
+            return Err(EINVAL);
+        }
+        // SAFETY: `client` is valid since `dev` is, and the configuration is copied.
+        Self::from_raw(unsafe {
+            bindings::__regmap_init_i2c(
+                client,
+                &config.raw,
+                crate::static_lock_class!().as_ptr(),
+                crate::c_str!("regmap_i2c").as_char_ptr(),
+            )
+        })
+    }
+
+    /// Creates a register map for `dev`, which must be an SPI device.
+    ///
+    /// Fails with `EINVAL` if `dev` isn't an SPI device.
+    #[cfg(CONFIG_REGMAP_SPI)]
+    pub fn new_spi(dev: &Device, config: &Config) -> Result<Self> {
+        // SAFETY: `dev` is valid, and `spi_bus_type` is only used for its address.
+        if unsafe { (*dev.as_raw()).bus } != unsafe { core::ptr::addr_of!(bindings::spi_bus_type) }
+        {
+            return Err(EINVAL);
+        }
+        // SAFETY: `dev` is embedded in an SPI device, since it is on the SPI bus, and the
+        // configuration is copied.
+        Self::from_raw(unsafe {
+            bindings::__regmap_init_spi(
+                bindings::to_spi_device(dev.as_raw()),
+                &config.raw,
+                crate::static_lock_class!().as_ptr(),
+                crate::c_str!("regmap_spi").as_char_ptr(),
+            )
+        })
+    }
+
+    /// Creates a register map for the memory-mapped registers `regs` of `dev`.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `regs` outlives the register map, and that the registers of
+    /// `config` are within it.
+    #[cfg(all(CONFIG_REGMAP_MMIO, CONFIG_HAS_IOMEM))]
+    pub unsafe fn new_mmio<const SIZE: usize>(
+        dev: &Device,
+        regs: &crate::io_mem::IoMem<SIZE>,
+        config: &Config,
+    ) -> Result<Self> {
+        // SAFETY: `dev` is valid, `regs` is valid for the lifetime of the register map by the
+        // safety requirements, and the configuration is copied.
+        Self::from_raw(unsafe {
+            bindings::__regmap_init_mmio_clk(
+                dev.as_raw(),
+                core::ptr::null(),
+                regs.as_ptr(),
+                &config.raw,
+                crate::static_lock_class!().as_ptr(),
+                crate::c_str!("regmap_mmio").as_char_ptr(),
+            )
+        })
+    }
+
+    /// Returns the raw `struct regmap` pointer.
+    pub fn as_raw(&self) -> *mut bindings::regmap {
+        self.map.as_ptr()
+    }
+
+    /// Reads the register `reg`.
+    pub fn read(&self, reg: u32) -> Result<u32> {
+        let mut val = 0;
+        // SAFETY: The register map is valid by the type invariants.
+        to_result(unsafe { bindings::regmap_read(self.as_raw(), reg, &mut val) })?;
+        Ok(val)
+    }
+
+    /// Writes `val` to the register `reg`.
+    pub fn write(&self, reg: u32, val: u32) -> Result {
+        // SAFETY: The register map is valid by the type invariants.
+        to_result(unsafe { bindings::regmap_write(self.as_raw(), reg, val) })
+    }
+
+    /// Replaces the bits in `mask` of the register `reg` with the ones of `val`.
+    ///
+    /// Returns whether the register changed. The register isn't written if it wouldn't change.
+    pub fn update_bits(&self, reg: u32, mask: u32, val: u32) -> Result<bool> {
+        let mut change = false;
+        // SAFETY: The register map is valid by the type invariants.
+        to_result(unsafe {
+            bindings::regmap_update_bits_base(
+                self.as_raw(),
+                reg,
+                mask,
+                val,
+                &mut change,
+                false,
+                false,
+            )
+        })?;
+        Ok(change)
+    }
+
+    /// Sets the bits in `bits` of the register `reg`.
+    pub fn set_bits(&self, reg: u32, bits: u32) -> Result {
+        self.update_bits(reg, bits, bits)?;
+        Ok(())
+    }
+
+    /// Clears the bits in `bits` of the register `reg`.
+    pub fn clear_bits(&self, reg: u32, bits: u32) -> Result {
+        self.update_bits(reg, bits, 0)?;
+        Ok(())
+    }
+
+    fn check_value_size<T: Value>(&self) -> Result {
+        // SAFETY: The register map is valid by the type invariants.
+        if unsafe { bindings::regmap_get_val_bytes(self.as_raw()) } != size_of::<T>() as _ {
+            return Err(EINVAL);
+        }
+        Ok(())
+    }
+
+    /// Reads the consecutive registers starting at `reg` into `buf`.
+    ///
+    /// Fails with `EINVAL` if `T` doesn't have the size of the values of the register map.
+    pub fn bulk_read<T: Value>(&self, reg: u32, buf: &mut [T]) -> Result {
+        self.check_value_size::<T>()?;
+        // SAFETY: The register map is valid by the type invariants, and `buf` is valid for writes
+        // of its length, in values of the size of the values of the register map.
+        to_result(unsafe {
+            bindings::regmap_bulk_read(self.as_raw(), reg, buf.as_mut_ptr().cast(), buf.len())
+        })
+    }
+
+    /// Writes `buf` to the consecutive registers starting at `reg`.
+    ///
+    /// Fails with `EINVAL` if `T` doesn't have the size of the values of the register map.
+    pub fn bulk_write<T: Value>(&self, reg: u32, buf: &[T]) -> Result {
+        self.check_value_size::<T>()?;
+        // SAFETY: The register map is valid by the type invariants, and `buf` is valid for reads
+        // of its length, in values of the size of the values of the register map.
+        to_result(unsafe {
+            bindings::regmap_bulk_write(self.as_raw(), reg, buf.as_ptr().cast(), buf.len())
+        })
+    }
+
+    /// Reads the consecutive registers starting at `reg` into `buf`, in the byte order of the
+    /// bus, bypassing the cache.
+    pub fn raw_read(&self, reg: u32, buf: &mut [u8]) -> Result {
+        // SAFETY: The register map is valid by the type invariants, and `buf` is valid for writes
+        // of its length.
+        to_result(unsafe {
+            bindings::regmap_raw_read(self.as_raw(), reg, buf.as_mut_ptr().cast(), buf.len())
+        })
+    }
+
+    /// Makes writes only update the cache, e.g. while the device is powered off.
+    pub fn cache_only(&self, enable: bool) {
+        // SAFETY: The register map is valid by the type invariants.
+        unsafe { bindings::regcache_cache_only(self.as_raw(), enable) };
+    }
+
+    /// Makes accesses bypass the cache.
+    pub fn cache_bypass(&self, enable: bool) {
+        // SAFETY: The register map is valid by the type invariants.
+        unsafe { bindings::regcache_cache_bypass(self.as_raw(), enable) };
+    }
+
+    /// Marks the cache as different from the hardware, e.g. after the device was reset, so that
+    /// [`Regmap::cache_sync`] writes all the registers that differ from their defaults.
+    pub fn mark_dirty(&self) {
+        // SAFETY: The register map is valid by the type invariants.
+        unsafe { bindings::regcache_mark_dirty(self.as_raw()) };
+    }
+
+    /// Writes the cached values that differ from the hardware to it.
+    pub fn cache_sync(&self) -> Result {
+        // SAFETY: The register map is valid by the type invariants.
+        to_result(unsafe { bindings::regcache_sync(self.as_raw()) })
+    }
+}
+
+impl Drop for Regmap {
+    fn drop(&mut self) {
+        // SAFETY: The register map is owned by `Regmap` by the type invariants.
+        unsafe { bindings::regmap_exit(self.as_raw()) };
+    }
+}
+
+// SAFETY: The register map can be used and freed from any thread.
+unsafe impl Send for Regmap {}
+
+// SAFETY: The register map has its own lock, which serialises accesses.
+unsafe impl Sync for Regmap {}
@@ -0,0 +1,467 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Remote processors.
+//!
+//! A remoteproc driver controls an auxiliary processor, e.g. the AVP of Tegra SoCs, by
+//! registering a [`Registration`]. The remoteproc core loads its firmware, an ELF image by
+//! default, into the carveouts the driver declares in [`Operations::prepare`] and those the
+//! firmware's resource table requests, then boots the processor with [`Operations::start`].
+//!
+//! The virtio devices declared in the resource table, e.g. the rpmsg bus, are created by the core
+//! once the processor runs. The driver only signals new messages to the processor in
+//! [`Operations::kick`], and forwards the processor's notifications to the core with
+//! [`Rproc::vq_interrupt`].
+//!
+//! C header: [`include/linux/remoteproc.h`](../../../../include/linux/remoteproc.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/staging/remoteproc.html>
+
+use crate::{
+    bindings, c_str,
+    device::Device,
+    error::{code::*, from_result, to_result, Error, Result},
+    init::{self, InPlaceInit, PinInit},
+    str::CStr,
+    types::Opaque,
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::{c_int, c_void},
+    marker::{PhantomData, PhantomPinned},
+    mem::MaybeUninit,
+    pin::Pin,
+    ptr, slice,
+};
+use macros::vtable;
+
+/// The kind of a crash of a remote processor, the kernel's `enum rproc_crash_type`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrashType {
+    /// The processor's MMU reported a fault.
+    MmuFault,
+    /// The processor's watchdog fired.
+    Watchdog,
+    /// The processor reported an unrecoverable error.
+    FatalError,
+}
+
+impl CrashType {
+    fn as_raw(self) -> bindings::rproc_crash_type {
+        match self {
+            Self::MmuFault => bindings::rproc_crash_type_RPROC_MMUFAULT,
+            Self::Watchdog => bindings::rproc_crash_type_RPROC_WATCHDOG,
+            Self::FatalError => bindings::rproc_crash_type_RPROC_FATAL_ERROR,
+        }
+    }
+}
+
+/// The operations of a remote processor, the kernel's `struct rproc_ops`.
+///
+/// The driver data of the processor implements this trait. All callbacks but
+/// [`Operations::kick`] are called with the processor's lock held, and may sleep.
+#[vtable]
+pub trait Operations: Send + Sync + Sized + 'static {
+    /// Prepares the processor before its firmware is loaded, e.g. by powering it, and declares
+    /// its carveouts with [`Rproc::add_carveout`].
+    fn prepare(_rproc: &Rproc<Self>) -> Result {
+        Ok(())
+    }
+
+    /// Undoes [`Operations::prepare`] once the processor is stopped.
+    fn unprepare(_rproc: &Rproc<Self>) -> Result {
+        Ok(())
+    }
+
+    /// Starts the processor, once its firmware is loaded.
+    fn start(rproc: &Rproc<Self>) -> Result;
+
+    /// Stops the processor.
+    fn stop(rproc: &Rproc<Self>) -> Result;
+
+    /// Notifies the processor that the virtqueue `vqid` has new buffers.
+    ///
+    /// It can be called in atomic context, and must not sleep.
+    fn kick(_rproc: &Rproc<Self>, _vqid: u32) {}
+
+    /// Loads the firmware image `fw` into the memory of the processor.
+    ///
+    /// Processors that don't implement it are loaded with the ELF loader, which also parses the
+    /// resource table of the firmware. Custom loaders don't have a resource table, so neither
+    /// virtio devices nor carveouts requested by the firmware are supported with them.
+    fn load(_rproc: &Rproc<Self>, _fw: &[u8]) -> Result {
+        Err(EINVAL)
+    }
+}
+
+/// A remote processor, the kernel's `struct rproc`.
+///
+/// # Invariants
+///
+/// The processor is valid while references to it exist, and its private data is a
+/// `Registration<T>`.
+#[repr(transparent)]
+pub struct Rproc<T: Operations>(Opaque<bindings::rproc>, PhantomData<T>);
+
+impl<T: Operations> Rproc<T> {
+    /// Creates a reference to an [`Rproc`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is a processor registered by a `Registration<T>` for the
+    /// lifetime of the returned reference.
+    unsafe fn from_raw<'a>(ptr: *mut bindings::rproc) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct rproc` pointer.
+    pub fn as_raw(&self) -> *mut bindings::rproc {
+        self.0.get()
+    }
+
+    /// Returns the driver data of the processor.
+    pub fn data(&self) -> &T {
+        // SAFETY: The private data of the processor is a `Registration<T>` by the type
+        // invariants, which outlives the processor.
+        unsafe { &(*(*self.as_raw()).priv_.cast::<Registration<T>>()).data }
+    }
+
+    /// Returns the device of the processor, whose parent is the device of the driver.
+    pub fn device(&self) -> &Device {
+        // SAFETY: The processor is valid by the type invariants, and so is its device.
+        unsafe { Device::as_ref(ptr::addr_of_mut!((*self.as_raw()).dev)) }
+    }
+
+    /// Adds a carveout of `len` bytes at the physical address `phys`, which the processor sees
+    /// at its device address `da`, e.g. a `no-map` reserved memory region.
+    ///
+    /// The carveout is mapped write-combined when the firmware is loaded, and removed when the
+    /// processor is stopped, so it is usually added in [`Operations::prepare`]. It is named
+    /// `name` to match the carveouts of the resource table.
+    pub fn add_carveout(
+        &self,
+        name: &CStr,
+        phys: bindings::phys_addr_t,
+        len: usize,
+        da: u32,
+    ) -> Result {
+        // SAFETY: The processor and its device are valid by the type invariants, and the name is
+        // copied. The callbacks don't use anything but the entry.
+        let mem = unsafe {
+            bindings::rproc_mem_entry_init(
+                self.device().as_raw(),
+                ptr::null_mut(),
+                phys as _,
+                len,
+                da,
+                Some(carveout_alloc_callback),
+                Some(carveout_release_callback),
+                c_str!("%s").as_char_ptr(),
+                name.as_char_ptr(),
+            )
+        };
+        if mem.is_null() {
+            return Err(ENOMEM);
+        }
+        // SAFETY: The processor is valid, and the entry was allocated above. It is owned and
+        // freed by the processor from now on.
+        unsafe { bindings::rproc_add_carveout(self.as_raw(), mem) };
+        Ok(())
+    }
+
+    /// Forwards a notification of the processor about the virtqueue `notifyid` to the virtio
+    /// devices, and returns whether such a virtqueue exists.
+    ///
+    /// It is usually called from the handler of the interrupt or mailbox message the processor
+    /// uses, and can be called in atomic context.
+    pub fn vq_interrupt(&self, notifyid: u32) -> bool {
+        // SAFETY: The processor is valid by the type invariants.
+        let ret = unsafe { bindings::rproc_vq_interrupt(self.as_raw(), notifyid as _) };
+        ret == bindings::irqreturn_IRQ_HANDLED
+    }
+
+    /// Reports a crash of the processor, which the core recovers from by restarting it, unless
+    /// recovery is disabled in debugfs.
+    ///
+    /// It can be called in atomic context.
+    pub fn report_crash(&self, kind: CrashType) {
+        // SAFETY: The processor is valid by the type invariants.
+        unsafe { bindings::rproc_report_crash(self.as_raw(), kind.as_raw()) };
+    }
+
+    /// Powers the processor up, loading its firmware and starting it if it isn't running yet.
+    ///
+    /// Each successful call must be balanced by a call to [`Rproc::shutdown`].
+    pub fn boot(&self) -> Result {
+        // SAFETY: The processor is valid by the type invariants.
+        to_result(unsafe { bindings::rproc_boot(self.as_raw()) })
+    }
+
+    /// Powers the processor down once it has been shut down as many times as it was booted.
+    pub fn shutdown(&self) -> Result {
+        // SAFETY: The processor is valid by the type invariants.
+        to_result(unsafe { bindings::rproc_shutdown(self.as_raw()) })
+    }
+}
+
+// SAFETY: The methods that take `&self` call functions with their own synchronisation, and the
+// driver data is `Sync`.
+unsafe impl<T: Operations> Sync for Rproc<T> {}
+
+unsafe extern "C" fn carveout_alloc_callback(
+    _rproc: *mut bindings::rproc,
+    mem: *mut bindings::rproc_mem_entry,
+) -> c_int {
+    // SAFETY: The core calls this with an entry added by `Rproc::add_carveout`, whose physical
+    // address and length are valid.
+    unsafe {
+        let va = bindings::ioremap_wc((*mem).dma as _, (*mem).len);
+        if va.is_null() {
+            return ENOMEM.to_errno();
+        }
+        (*mem).va = va.cast();
+        (*mem).is_iomem = true;
+    }
+    0
+}
+
+unsafe extern "C" fn carveout_release_callback(
+    _rproc: *mut bindings::rproc,
+    mem: *mut bindings::rproc_mem_entry,
+) -> c_int {
+    // SAFETY: The core calls this with an entry that was mapped by `carveout_alloc_callback`.
+    unsafe { bindings::iounmap((*mem).va.cast()) };
+    0
+}
+
+/// The configuration of a [`Registration`].
+#[derive(Clone, Copy)]
+pub struct Config<'a> {
+    /// The name of the processor.
+    pub name: &'a CStr,
+    /// The name of the firmware file, or `None` for `rproc-<name>-fw`.
+    pub firmware: Option<&'a CStr>,
+    /// Whether the processor is booted as soon as it is registered and its firmware is
+    /// available, rather than when a user boots it.
+    pub auto_boot: bool,
+}
+
+/// A registered remote processor.
+///
+/// The processor is shut down and unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `rproc` is registered, and its private data is this registration.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, device::Device, of::ReservedMem, prelude::*};
+/// use kernel::remoteproc::{self, Config, Rproc};
+///
+/// struct Mailbox;
+///
+/// impl Mailbox {
+///     fn send(&self, _msg: u32) {}
+///     fn set_reset(&self, _asserted: bool) {}
+/// }
+///
+/// struct Avp {
+///     mbox: Mailbox,
+///     carveout: &'static ReservedMem,
+/// }
+///
+/// #[vtable]
+/// impl remoteproc::Operations for Avp {
+///     fn prepare(rproc: &Rproc<Self>) -> Result {
+///         let rmem = rproc.data().carveout;
+///         rproc.add_carveout(rmem.name(), rmem.base(), rmem.size(), 0)
+///     }
+///
+///     fn start(rproc: &Rproc<Self>) -> Result {
+///         rproc.data().mbox.set_reset(false);
+///         Ok(())
+///     }
+///
+///     fn stop(rproc: &Rproc<Self>) -> Result {
+///         rproc.data().mbox.set_reset(true);
+///         Ok(())
+///     }
+///
+///     fn kick(rproc: &Rproc<Self>, vqid: u32) {
+///         rproc.data().mbox.send(vqid);
+///     }
+/// }
+///
+/// fn probe(dev: &Device, data: Avp) -> Result<Pin<Box<remoteproc::Registration<Avp>>>> {
+///     let config = Config {
+///         name: c_str!("avp"),
+///         firmware: Some(c_str!("nvidia/tegra20/avp.elf")),
+///         auto_boot: false,
+///     };
+///     remoteproc::Registration::register_new(dev, &config, data)
+/// }
+/// ```
+pub struct Registration<T: Operations> {
+    rproc: *mut bindings::rproc,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: Operations> Registration<T> {
+    const OPS: bindings::rproc_ops = bindings::rproc_ops {
+        prepare: if T::HAS_PREPARE {
+            Some(Self::prepare_callback)
+        } else {
+            None
+        },
+        unprepare: if T::HAS_UNPREPARE {
+            Some(Self::unprepare_callback)
+        } else {
+            None
+        },
+        start: Some(Self::start_callback),
+        stop: Some(Self::stop_callback),
+        kick: if T::HAS_KICK {
+            Some(Self::kick_callback)
+        } else {
+            None
+        },
+        load: if T::HAS_LOAD {
+            Some(Self::load_callback)
+        } else {
+            None
+        },
+        // SAFETY: All other fields are optional, for which zero is valid. The core fills in
+        // those of the ELF loader if `load` isn't set.
+        ..unsafe { MaybeUninit::zeroed().assume_init() }
+    };
+
+    /// Returns an initialiser that allocates and registers a remote processor whose parent is
+    /// `parent`, with the configuration `config` and the driver data `data`.
+    ///
+    /// If [`Config::auto_boot`] is set, the processor is booted once its firmware is available,
+    /// which may happen before the initialiser returns.
+    pub fn register(parent: &Device, config: &Config<'_>, data: T) -> impl PinInit<Self, Error> {
+        let parent = parent.as_raw();
+        let name = config.name.as_char_ptr();
+        let firmware = config.firmware.map_or(ptr::null(), |fw| fw.as_char_ptr());
+        let auto_boot = config.auto_boot;
+        // SAFETY: The closure initialises all fields on success, and drops those it initialised
+        // on failure. The registration isn't moved once the processor is registered, since it is
+        // pinned.
+        unsafe {
+            init::pin_init_from_closure::<_, Error>(move |slot: *mut Self| {
+                // The names are copied, and the ops are copied by the core, which fills in the
+                // defaults.
+                let rproc = bindings::rproc_alloc(parent, name, &Self::OPS, firmware, 0);
+                if rproc.is_null() {
+                    return Err(ENOMEM);
+                }
+                // The data is written before registering, since the callbacks can be called as
+                // soon as the processor is registered.
+                let data_ptr = ptr::addr_of_mut!((*slot).data);
+                data_ptr.write(data);
+                ptr::addr_of_mut!((*slot).rproc).write(rproc);
+                (*rproc).priv_ = slot.cast::<c_void>();
+                (*rproc).auto_boot = auto_boot;
+                if let Err(e) = to_result(bindings::rproc_add(rproc)) {
+                    bindings::rproc_free(rproc);
+                    ptr::drop_in_place(data_ptr);
+                    return Err(e);
+                }
+                // INVARIANT: The processor is only considered initialised if it was registered.
+                Ok(())
+            })
+        }
+    }
+
+    /// Allocates a registration and registers the remote processor, see
+    /// [`Registration::register`].
+    pub fn register_new(parent: &Device, config: &Config<'_>, data: T) -> Result<Pin<Box<Self>>> {
+        Box::pin_init(Self::register(parent, config, data))
+    }
+
+    /// Returns the remote processor.
+    pub fn rproc(&self) -> &Rproc<T> {
+        // SAFETY: The processor is registered by the type invariants, and its private data is
+        // `self`.
+        unsafe { Rproc::from_raw(self.rproc) }
+    }
+
+    /// Returns the driver data of the processor.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    unsafe extern "C" fn prepare_callback(rproc: *mut bindings::rproc) -> c_int {
+        from_result(|| {
+            // SAFETY: The core calls this with a processor registered by `register`.
+            T::prepare(unsafe { Rproc::from_raw(rproc) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn unprepare_callback(rproc: *mut bindings::rproc) -> c_int {
+        from_result(|| {
+            // SAFETY: The core calls this with a processor registered by `register`.
+            T::unprepare(unsafe { Rproc::from_raw(rproc) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn start_callback(rproc: *mut bindings::rproc) -> c_int {
+        from_result(|| {
+            // SAFETY: The core calls this with a processor registered by `register`.
+            T::start(unsafe { Rproc::from_raw(rproc) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn stop_callback(rproc: *mut bindings::rproc) -> c_int {
+        from_result(|| {
+            // SAFETY: The core calls this with a processor registered by `register`.
+            T::stop(unsafe { Rproc::from_raw(rproc) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn kick_callback(rproc: *mut bindings::rproc, vqid: c_int) {
+        // SAFETY: The core calls this with a processor registered by `register`.
+        T::kick(unsafe { Rproc::from_raw(rproc) }, vqid as _);
+    }
+
+    unsafe extern "C" fn load_callback(
+        rproc: *mut bindings::rproc,
+        fw: *const bindings::firmware,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The core calls this with a valid firmware image, which isn't released
+            // before the callback returns.
+            let fw = unsafe { slice::from_raw_parts((*fw).data, (*fw).size) };
+            // SAFETY: The core calls this with a processor registered by `register`.
+            T::load(unsafe { Rproc::from_raw(rproc) }, fw)?;
+            Ok(0)
+        })
+    }
+}
+
+impl<T: Operations> Drop for Registration<T> {
+    fn drop(&mut self) {
+        // SAFETY: The processor is registered by the type invariants. Unregistering it shuts it
+        // down, after which the callbacks aren't called anymore, so the driver data can be freed
+        // even if the processor itself is kept alive by other references.
+        unsafe {
+            bindings::rproc_del(self.rproc);
+            bindings::rproc_free(self.rproc);
+        }
+    }
+}
+
+// SAFETY: The processor can be unregistered from any thread, and the driver data is `Send`.
+unsafe impl<T: Operations> Send for Registration<T> {}
+
+// SAFETY: The methods that take `&self` only read fields that never change or call functions
+// with their own synchronisation, and the driver data is `Sync`.
+unsafe impl<T: Operations> Sync for Registration<T> {}
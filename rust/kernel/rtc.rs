@@ -0,0 +1,470 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Real-time clocks.
+//!
+//! A driver of a real-time clock registers a [`Registration`], which makes it available to user
+//! space as `/dev/rtcN`, and to the kernel to set the system time at boot and to wake the system
+//! up with alarms.
+//!
+//! C header: [`include/linux/rtc.h`](../../../../include/linux/rtc.h)
+
+use crate::{
+    bindings, c_str, device,
+    error::{code::*, from_err_ptr, from_result, to_result, Result},
+    str::CStr,
+    types::Opaque,
+    ThisModule,
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::{c_int, c_uint, c_void},
+    marker::{PhantomData, PhantomPinned},
+    mem::MaybeUninit,
+    ops::RangeInclusive,
+    pin::Pin,
+    ptr,
+};
+use macros::vtable;
+
+/// A broken-down time, the kernel's `struct rtc_time`.
+///
+/// Unlike in the C structure, months are numbered from 1 and years are complete.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Time {
+    /// The seconds, from 0 to 59.
+    pub second: u32,
+    /// The minutes, from 0 to 59.
+    pub minute: u32,
+    /// The hours, from 0 to 23.
+    pub hour: u32,
+    /// The day of the month, from 1 to 31.
+    pub day: u32,
+    /// The month, from 1 to 12.
+    pub month: u32,
+    /// The year.
+    pub year: i32,
+    /// The day of the week, from 0 for Sunday to 6.
+    pub weekday: u32,
+    /// The day of the year, from 0 to 365.
+    pub yearday: u32,
+}
+
+impl Time {
+    /// Returns the time that is `secs` seconds after the Unix epoch.
+    pub fn from_timestamp(secs: i64) -> Self {
+        // SAFETY: All fields are integers, for which zero is valid.
+        let mut raw: bindings::rtc_time = unsafe { MaybeUninit::zeroed().assume_init() };
+        // SAFETY: `raw` is valid for writes.
+        unsafe { bindings::rtc_time64_to_tm(secs, &mut raw) };
+        Self::from_raw(&raw)
+    }
+
+    /// Returns the number of seconds between the Unix epoch and the time.
+    ///
+    /// The day of the week and of the year are ignored.
+    pub fn timestamp(&self) -> i64 {
+        let raw = self.to_raw();
+        // SAFETY: `raw` is valid for reads.
+        unsafe { bindings::rtc_tm_to_time64(&raw) }
+    }
+
+    /// Checks that the time is valid, and fails with `EINVAL` otherwise.
+    ///
+    /// The day of the week and of the year are ignored.
+    pub fn validate(&self) -> Result {
+        let raw = self.to_raw();
+        // SAFETY: `raw` is valid for reads.
+        to_result(unsafe { bindings::rtc_valid_tm(&raw) })
+    }
+
+    fn from_raw(raw: &bindings::rtc_time) -> Self {
+        Self {
+            second: raw.tm_sec as _,
+            minute: raw.tm_min as _,
+            hour: raw.tm_hour as _,
+            day: raw.tm_mday as _,
+            month: (raw.tm_mon + 1) as _,
+            year: raw.tm_year + 1900,
+            weekday: raw.tm_wday as _,
+            yearday: raw.tm_yday as _,
+        }
+    }
+
+    fn to_raw(self) -> bindings::rtc_time {
+        bindings::rtc_time {
+            tm_sec: self.second as _,
+            tm_min: self.minute as _,
+            tm_hour: self.hour as _,
+            tm_mday: self.day as _,
+            tm_mon: self.month as c_int - 1,
+            tm_year: self.year - 1900,
+            tm_wday: self.weekday as _,
+            tm_yday: self.yearday as _,
+            tm_isdst: 0,
+        }
+    }
+}
+
+/// A wakeup alarm, the kernel's `struct rtc_wkalrm`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Alarm {
+    /// The time that the alarm fires at.
+    pub time: Time,
+    /// Whether the alarm interrupt is enabled.
+    pub enabled: bool,
+    /// Whether the alarm has fired, but its interrupt hasn't been handled yet.
+    pub pending: bool,
+}
+
+/// The operations of a real-time clock, the kernel's `struct rtc_class_ops`.
+///
+/// The driver data of the clock implements this trait. The operations are serialised by the RTC
+/// core, and may sleep.
+#[vtable]
+pub trait Operations: Send + Sync + Sized + 'static {
+    /// Returns the current time of the clock.
+    fn read_time(rtc: &Device<Self>) -> Result<Time>;
+
+    /// Sets the time of the clock.
+    fn set_time(_rtc: &Device<Self>, _time: &Time) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Returns the alarm of the clock.
+    fn read_alarm(_rtc: &Device<Self>) -> Result<Alarm> {
+        Err(EINVAL)
+    }
+
+    /// Sets the alarm of the clock, and enables its interrupt if `alarm.enabled` is `true`.
+    ///
+    /// Clocks whose driver doesn't implement this have no alarm.
+    fn set_alarm(_rtc: &Device<Self>, _alarm: &Alarm) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Enables or disables the alarm interrupt.
+    fn alarm_irq_enable(_rtc: &Device<Self>, _enabled: bool) -> Result {
+        Err(EINVAL)
+    }
+}
+
+/// A real-time clock, the kernel's `struct rtc_device`.
+///
+/// # Invariants
+///
+/// The clock is valid while references to it exist, and its driver data is a
+/// `Registration<T>`.
+#[repr(transparent)]
+pub struct Device<T: Operations>(Opaque<bindings::rtc_device>, PhantomData<T>);
+
+impl<T: Operations> Device<T> {
+    /// Creates a reference to a [`Device`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is a clock set up by a `Registration<T>` for the lifetime
+    /// of the returned reference.
+    unsafe fn from_raw<'a>(ptr: *mut bindings::rtc_device) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct rtc_device` pointer.
+    pub fn as_raw(&self) -> *mut bindings::rtc_device {
+        self.0.get()
+    }
+
+    /// Returns the device of the clock, i.e. the one that its driver is bound to.
+    pub fn parent(&self) -> &device::Device {
+        // SAFETY: The clock is valid by the type invariants, and holds a reference to its
+        // parent.
+        unsafe { device::Device::as_ref((*self.as_raw()).dev.parent) }
+    }
+
+    /// Returns the driver data of the clock.
+    pub fn data(&self) -> &T {
+        // SAFETY: The driver data of the clock is a `Registration<T>` by the type invariants,
+        // which outlives the clock.
+        unsafe { &(*(*self.as_raw()).dev.driver_data.cast::<Registration<T>>()).data }
+    }
+
+    /// Reports that the alarm fired, from the alarm interrupt handler.
+    ///
+    /// This can be called from any context.
+    pub fn report_alarm(&self) {
+        // SAFETY: The clock is valid by the type invariants.
+        unsafe {
+            bindings::rtc_update_irq(
+                self.as_raw(),
+                1,
+                (bindings::RTC_AF | bindings::RTC_IRQF) as _,
+            )
+        };
+    }
+}
+
+// SAFETY: The methods that take `&self` are safe to call concurrently, and the driver data is
+// `Sync`.
+unsafe impl<T: Operations> Sync for Device<T> {}
+
+/// A registered real-time clock.
+///
+/// The clock is unregistered when this is dropped.
+///
+/// The RTC core calls the operations with the parent device of the clock, which it is looked up
+/// from, so the alarm of the hardware is only read once the clock is registered.
+///
+/// # Invariants
+///
+/// `rtc` is null until the clock is allocated by [`Registration::register`], and then a clock
+/// that `Registration<T>` holds a reference to, whose driver data is this `Registration<T>`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{device::Device, prelude::*, rtc, ThisModule};
+///
+/// struct Clock;
+///
+/// #[vtable]
+/// impl rtc::Operations for Clock {
+///     fn read_time(_rtc: &rtc::Device<Self>) -> Result<rtc::Time> {
+///         Ok(rtc::Time::from_timestamp(0))
+///     }
+/// }
+///
+/// fn probe(
+///     dev: &Device,
+///     module: &'static ThisModule,
+/// ) -> Result<Pin<Box<rtc::Registration<Clock>>>> {
+///     rtc::Registration::register(dev, module, 0..=u32::MAX as i64, Clock)
+/// }
+/// ```
+pub struct Registration<T: Operations> {
+    rtc: *mut bindings::rtc_device,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: Operations> Registration<T> {
+    const OPS: bindings::rtc_class_ops = bindings::rtc_class_ops {
+        read_time: Some(Self::read_time_callback),
+        set_time: if T::HAS_SET_TIME {
+            Some(Self::set_time_callback)
+        } else {
+            None
+        },
+        read_alarm: if T::HAS_READ_ALARM {
+            Some(Self::read_alarm_callback)
+        } else {
+            None
+        },
+        set_alarm: if T::HAS_SET_ALARM {
+            Some(Self::set_alarm_callback)
+        } else {
+            None
+        },
+        alarm_irq_enable: if T::HAS_ALARM_IRQ_ENABLE {
+            Some(Self::alarm_irq_enable_callback)
+        } else {
+            None
+        },
+        // SAFETY: All other fields are optional, for which zero is valid.
+        ..unsafe { MaybeUninit::zeroed().assume_init() }
+    };
+
+    /// Registers a real-time clock of the device `parent`, for the module `module`, which keeps
+    /// the times from `range` seconds after the Unix epoch, with the driver data `data`.
+    ///
+    /// The clock stays allocated until `parent` is unbound from its driver.
+    pub fn register(
+        parent: &device::Device,
+        module: &'static ThisModule,
+        range: RangeInclusive<i64>,
+        data: T,
+    ) -> Result<Pin<Box<Self>>> {
+        crate::might_sleep!();
+        let mut reg = Pin::from(Box::try_new(Self {
+            rtc: ptr::null_mut(),
+            data,
+            _pin: PhantomPinned,
+        })?);
+
+        // SAFETY: `parent` is valid, and the clock is freed when it is unbound.
+        let rtc = from_err_ptr(unsafe { bindings::devm_rtc_allocate_device(parent.as_raw()) })?;
+        let this: *const Self = &*reg;
+        // SAFETY: The clock was just allocated, and isn't registered yet. The ops are static, and
+        // the driver data is pinned and outlives the ops, which are cleared on drop. The
+        // reference is dropped on drop too.
+        unsafe {
+            bindings::get_device(&mut (*rtc).dev);
+            (*rtc).ops = &Self::OPS;
+            (*rtc).dev.driver_data = this as *mut c_void;
+            (*rtc).range_min = *range.start();
+            (*rtc).range_max = *range.end() as _;
+        }
+        // INVARIANT: The clock was allocated and referenced above, with this as its driver data.
+        // SAFETY: `rtc` isn't structurally pinned.
+        unsafe { reg.as_mut().get_unchecked_mut() }.rtc = rtc;
+
+        // SAFETY: The clock was set up above, and the module outlives it.
+        to_result(unsafe { bindings::__devm_rtc_register_device(module.as_ptr(), rtc) })?;
+        Ok(reg)
+    }
+
+    /// Returns the real-time clock.
+    pub fn device(&self) -> &Device<T> {
+        // SAFETY: The clock is allocated once this is returned by `register`, by the type
+        // invariants, and its driver data is `self`.
+        unsafe { Device::from_raw(self.rtc) }
+    }
+
+    /// Returns the driver data of the clock.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    /// Sets whether the alarm of the clock can wake the system up, which the interrupt of the
+    /// alarm must be set up for.
+    pub fn init_wakeup(&self, enable: bool) -> Result {
+        // SAFETY: The parent of the clock is valid.
+        to_result(unsafe { bindings::device_init_wakeup(self.device().parent().as_raw(), enable) })
+    }
+
+    unsafe extern "C" fn match_callback(dev: *mut bindings::device, _data: *mut c_void) -> c_int {
+        // SAFETY: The driver core calls this with a valid child device. Devices of the `rtc`
+        // class are clocks, whose ops are valid or null.
+        unsafe {
+            let class = (*dev).class;
+            if class.is_null() || CStr::from_char_ptr((*class).name) != c_str!("rtc") {
+                return 0;
+            }
+            let ops = (*dev.cast::<bindings::rtc_device>()).ops;
+            (!ops.is_null() && (*ops).read_time == Self::OPS.read_time) as _
+        }
+    }
+
+    /// Calls `f` with the clock of `parent` that is set up by a `Registration<T>`.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `parent` is a valid device.
+    unsafe fn with_device<R>(
+        parent: *mut bindings::device,
+        f: impl FnOnce(&Device<T>) -> Result<R>,
+    ) -> Result<R> {
+        // SAFETY: `parent` is valid by the safety requirements. The clock is returned with a
+        // reference, which is dropped below.
+        let dev = unsafe {
+            bindings::device_find_child(parent, ptr::null_mut(), Some(Self::match_callback))
+        };
+        if dev.is_null() {
+            return Err(ENODEV);
+        }
+        // SAFETY: The clock has the ops of a `Registration<T>`, which set it up, and the device
+        // is the first field of the clock.
+        let ret = f(unsafe { Device::from_raw(dev.cast()) });
+        // SAFETY: The reference was taken by `device_find_child`.
+        unsafe { bindings::put_device(dev) };
+        ret
+    }
+
+    unsafe extern "C" fn read_time_callback(
+        dev: *mut bindings::device,
+        tm: *mut bindings::rtc_time,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The RTC core calls this with the parent of the clock.
+            let time = unsafe { Self::with_device(dev, T::read_time) }?;
+            // SAFETY: The RTC core passes a valid pointer to write the time to.
+            unsafe { *tm = time.to_raw() };
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn set_time_callback(
+        dev: *mut bindings::device,
+        tm: *mut bindings::rtc_time,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The RTC core passes a valid time.
+            let time = Time::from_raw(unsafe { &*tm });
+            // SAFETY: The RTC core calls this with the parent of the clock.
+            unsafe { Self::with_device(dev, |rtc| T::set_time(rtc, &time)) }?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn read_alarm_callback(
+        dev: *mut bindings::device,
+        alrm: *mut bindings::rtc_wkalrm,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The RTC core calls this with the parent of the clock.
+            let alarm = unsafe { Self::with_device(dev, T::read_alarm) }?;
+            // SAFETY: The RTC core passes a valid alarm to fill in.
+            unsafe {
+                (*alrm).time = alarm.time.to_raw();
+                (*alrm).enabled = alarm.enabled as _;
+                (*alrm).pending = alarm.pending as _;
+            }
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn set_alarm_callback(
+        dev: *mut bindings::device,
+        alrm: *mut bindings::rtc_wkalrm,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The RTC core passes a valid alarm.
+            let alarm = unsafe {
+                Alarm {
+                    time: Time::from_raw(&(*alrm).time),
+                    enabled: (*alrm).enabled != 0,
+                    pending: (*alrm).pending != 0,
+                }
+            };
+            // SAFETY: The RTC core calls this with the parent of the clock.
+            unsafe { Self::with_device(dev, |rtc| T::set_alarm(rtc, &alarm)) }?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn alarm_irq_enable_callback(
+        dev: *mut bindings::device,
+        enabled: c_uint,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The RTC core calls this with the parent of the clock.
+            unsafe { Self::with_device(dev, |rtc| T::alarm_irq_enable(rtc, enabled != 0)) }?;
+            Ok(0)
+        })
+    }
+}
+
+impl<T: Operations> Drop for Registration<T> {
+    fn drop(&mut self) {
+        if self.rtc.is_null() {
+            return;
+        }
+
+        // SAFETY: The clock is referenced by the type invariants. Clearing its ops under the ops
+        // lock, like unregistering it does, waits for running operations and makes the RTC core
+        // fail further ones, so the driver data isn't used anymore. The clock itself is
+        // unregistered and freed when its parent is unbound.
+        unsafe {
+            bindings::mutex_lock(&mut (*self.rtc).ops_lock);
+            (*self.rtc).ops = ptr::null();
+            bindings::mutex_unlock(&mut (*self.rtc).ops_lock);
+            bindings::put_device(&mut (*self.rtc).dev);
+        }
+    }
+}
+
+// SAFETY: The clock can be released from any thread, and the driver data is `Send`.
+unsafe impl<T: Operations> Send for Registration<T> {}
+
+// SAFETY: The methods that take `&self` only read fields that never change, and the driver data
+// is `Sync`.
+unsafe impl<T: Operations> Sync for Registration<T> {}
@@ -0,0 +1,102 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Scheduling control.
+//!
+//! Functions to adjust the scheduling policy and CPU affinity of tasks (typically kthreads created
+//! by the caller), and to voluntarily give up the CPU.
+//!
+//! C headers: [`include/linux/sched.h`](../../../../include/linux/sched.h) and
+//! [`include/uapi/linux/sched.h`](../../../../include/uapi/linux/sched.h)
+
+use crate::{
+    bindings,
+    cpumask::CpuMask,
+    error::{code::*, to_result, Result},
+    task::Task,
+};
+
+/// The highest priority usable with [`set_fifo_priority`].
+pub const MAX_USER_RT_PRIO: u32 = bindings::MAX_RT_PRIO - 1;
+
+/// Switches `task` to the `SCHED_FIFO` real-time policy with the given priority.
+///
+/// `prio` must be in `1..=MAX_USER_RT_PRIO`; higher values preempt lower ones. Like its C
+/// counterpart `sched_setattr_nocheck`, this bypasses the permission checks that apply to
+/// userspace, so it is meant for kthreads owned by the caller.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{sched, task::Task};
+/// # use kernel::prelude::*;
+///
+/// fn make_realtime(worker: &Task) -> Result {
+///     sched::set_fifo_priority(worker, 50)?;
+///     sched::set_cpu(worker, 0)
+/// }
+/// ```
+pub fn set_fifo_priority(task: &Task, prio: u32) -> Result {
+    crate::might_sleep!();
+    if prio == 0 || prio > MAX_USER_RT_PRIO {
+        return Err(EINVAL);
+    }
+
+    // SAFETY: `sched_attr` is a plain C struct for which all zeroes is a valid value.
+    let mut attr: bindings::sched_attr = unsafe { core::mem::zeroed() };
+    attr.size = core::mem::size_of::<bindings::sched_attr>() as _;
+    attr.sched_policy = bindings::SCHED_FIFO;
+    attr.sched_priority = prio;
+
+    // SAFETY: `task.0` is valid by the type invariants of `Task` and `attr` is fully initialised.
+    to_result(unsafe { bindings::sched_setattr_nocheck(task.0.get(), &attr) })
+}
+
+/// Switches `task` back to the `SCHED_NORMAL` policy with the given nice value.
+///
+/// `nice` must be in `-20..=19`.
+pub fn set_normal(task: &Task, nice: i32) -> Result {
+    crate::might_sleep!();
+    if !(bindings::MIN_NICE..=bindings::MAX_NICE).contains(&nice) {
+        return Err(EINVAL);
+    }
+
+    // SAFETY: `task.0` is valid by the type invariants of `Task`.
+    unsafe { bindings::sched_set_normal(task.0.get(), nice) };
+    Ok(())
+}
+
+/// Restricts `task` to run only on the CPUs in `mask`.
+///
+/// Fails with `EINVAL` if `mask` contains no online CPU the task is allowed to run on.
+pub fn set_cpus_allowed(task: &Task, mask: &CpuMask) -> Result {
+    crate::might_sleep!();
+    // SAFETY: `task.0` is valid by the type invariants of `Task` and `mask` is a valid mask.
+    to_result(unsafe { bindings::set_cpus_allowed_ptr(task.0.get(), mask.as_raw()) })
+}
+
+/// Pins `task` to the single CPU `cpu`.
+pub fn set_cpu(task: &Task, cpu: u32) -> Result {
+    set_cpus_allowed(task, &CpuMask::try_of_cpu(cpu)?)
+}
+
+/// Gives the scheduler a chance to run other tasks if a reschedule is pending.
+///
+/// Long-running loops in process context should call this periodically so that they don't hog
+/// the CPU on non-preemptible kernels. It must not be called from atomic context.
+///
+/// Returns `true` if the caller was rescheduled.
+#[inline]
+pub fn cond_resched() -> bool {
+    // SAFETY: FFI call with no additional requirements.
+    unsafe { bindings::cond_resched() != 0 }
+}
+
+/// Yields the CPU to other runnable tasks.
+///
+/// Unlike [`cond_resched`], this always goes through the scheduler. Note that, as in C, this is
+/// rarely what you want: waiting for an event is almost always better than a busy loop around
+/// `yield_now`.
+pub fn yield_now() {
+    // SAFETY: FFI call with no additional requirements.
+    unsafe { bindings::yield_() };
+}
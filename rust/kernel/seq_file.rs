@@ -0,0 +1,319 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Seq file bindings.
+//!
+//! C header: [`include/linux/seq_file.h`](../../../../include/linux/seq_file.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/filesystems/seq_file.html>
+
+use crate::{
+    bindings, c_str,
+    error::{code::*, Result},
+    file::OpenAdapter,
+    types::Opaque,
+    ThisModule,
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::{c_int, c_void},
+    fmt,
+    marker::PhantomData,
+    mem::MaybeUninit,
+    ptr,
+};
+
+/// A utility for generating the contents of a seq file.
+///
+/// Seq files hide the buffering needed to produce large virtual files: output that doesn't fit
+/// in the current buffer is silently dropped, and the seq file core calls back again with a larger
+/// buffer. Functions producing the output must therefore be idempotent.
+///
+/// Output can be produced with the [`seq_print!`] macro, or with [`write!`] through the
+/// [`fmt::Write`] implementation.
+///
+/// [`seq_print!`]: crate::seq_print
+#[repr(transparent)]
+pub struct SeqFile {
+    inner: Opaque<bindings::seq_file>,
+}
+
+impl SeqFile {
+    /// Creates a new [`SeqFile`] from a raw pointer.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that, for the duration of `'a`, `ptr` points at a valid `seq_file`
+    /// and that it will not be accessed via anything other than the returned reference.
+    pub unsafe fn from_raw<'a>(ptr: *mut bindings::seq_file) -> &'a mut SeqFile {
+        // SAFETY: The safety requirements guarantee the validity of the dereference, while the
+        // `SeqFile` type being transparent makes the cast ok.
+        unsafe { &mut *ptr.cast() }
+    }
+
+    /// Returns the raw `struct seq_file` pointer.
+    pub fn as_raw(&self) -> *mut bindings::seq_file {
+        self.inner.get()
+    }
+
+    /// Used by the [`seq_print`] macro.
+    ///
+    /// [`seq_print`]: crate::seq_print
+    pub fn call_printf(&self, args: core::fmt::Arguments<'_>) {
+        // SAFETY: Passing a void pointer to `Arguments` is valid for `%pA`.
+        unsafe {
+            bindings::seq_printf(
+                self.inner.get(),
+                c_str!("%pA").as_char_ptr(),
+                &args as *const _ as *const c_void,
+            );
+        }
+    }
+
+    /// Writes a single byte.
+    pub fn putc(&mut self, c: u8) {
+        // SAFETY: By the safety requirements of `from_raw`, `self.inner` is a valid seq file.
+        unsafe { bindings::seq_putc(self.inner.get(), c as _) };
+    }
+
+    /// Writes raw bytes.
+    pub fn write_bytes(&mut self, data: &[u8]) {
+        // SAFETY: By the safety requirements of `from_raw`, `self.inner` is a valid seq file, and
+        // `data` is valid for `data.len()` bytes.
+        unsafe { bindings::seq_write(self.inner.get(), data.as_ptr().cast(), data.len()) };
+    }
+
+    /// Returns `true` if the buffer has overflowed.
+    ///
+    /// The output is going to be discarded and produced again with a larger buffer, so callers
+    /// generating expensive output may want to stop early.
+    pub fn has_overflowed(&self) -> bool {
+        // SAFETY: By the safety requirements of `from_raw`, `self.inner` is a valid seq file.
+        unsafe { (*self.inner.get()).count == (*self.inner.get()).size }
+    }
+}
+
+impl fmt::Write for SeqFile {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // Overflows are not errors: the seq file core retries with a larger buffer.
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        if c.is_ascii() {
+            self.putc(c as u8);
+            Ok(())
+        } else {
+            self.write_str(c.encode_utf8(&mut [0; 4]))
+        }
+    }
+}
+
+/// Write to a [`SeqFile`] with the ordinary Rust formatting syntax.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{seq_file::SeqFile, seq_print};
+///
+/// fn show_stats(m: &SeqFile, rx: u64, tx: u64) {
+///     seq_print!(m, "rx: {}\ntx: {}\n", rx, tx);
+/// }
+/// ```
+#[macro_export]
+macro_rules! seq_print {
+    ($m:expr, $($arg:tt)+) => (
+        $m.call_printf(format_args!($($arg)+))
+    );
+}
+
+/// Produces the contents of a seq file one record at a time.
+///
+/// This is the equivalent of `struct seq_operations`, for listings that may not fit in a single
+/// buffer: the seq file core iterates over the records from [`Operations::start`] and through
+/// [`Operations::next`], calling [`Operations::show`] for each of them until the buffer is full
+/// or the iteration ends. It then hands the buffer to user space and may later resume at the
+/// position it stopped at, so records are identified by their position.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{seq_file::{self, SeqFile}, seq_print};
+/// # use kernel::prelude::*;
+///
+/// struct Channels {
+///     names: [&'static str; 4],
+/// }
+///
+/// impl seq_file::Operations for Channels {
+///     type Item = usize;
+///
+///     fn start(&self, pos: u64) -> Option<usize> {
+///         let index = pos as usize;
+///         (index < self.names.len()).then_some(index)
+///     }
+///
+///     fn next(&self, _index: usize, pos: u64) -> Option<usize> {
+///         self.start(pos)
+///     }
+///
+///     fn show(&self, m: &mut SeqFile, index: &usize) -> Result {
+///         seq_print!(m, "{}: {}\n", index, self.names[*index]);
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait Operations: Send + Sync + 'static {
+    /// A cursor designating a record.
+    type Item;
+
+    /// Returns the record at position `pos`, or `None` at the end of the listing.
+    fn start(&self, pos: u64) -> Option<Self::Item>;
+
+    /// Returns the record that follows `item`, now at position `pos`, or `None` at the end of the
+    /// listing.
+    fn next(&self, item: Self::Item, pos: u64) -> Option<Self::Item>;
+
+    /// Writes the record designated by `item` to `m`.
+    fn show(&self, m: &mut SeqFile, item: &Self::Item) -> Result;
+
+    /// Called when the iteration stops, either at the end of the listing or because the buffer is
+    /// full.
+    ///
+    /// This is where resources acquired in [`Operations::start`] should be released.
+    fn stop(&self) {}
+}
+
+/// Builds the `struct seq_operations` table for an implementation of [`Operations`].
+///
+/// The seq file's `private` field must point to the `T` the records are produced from.
+pub(crate) struct SeqOperationsVtable<T>(PhantomData<T>);
+
+impl<T: Operations> SeqOperationsVtable<T> {
+    unsafe extern "C" fn start_callback(
+        m: *mut bindings::seq_file,
+        pos: *mut bindings::loff_t,
+    ) -> *mut c_void {
+        // SAFETY: The caller guarantees that `private` points to a valid `T`, and `pos` is valid.
+        let (data, pos) = unsafe { (&*((*m).private as *const T), *pos) };
+        match data.start(pos as _) {
+            None => ptr::null_mut(),
+            Some(item) => match Box::try_new(item) {
+                Ok(item) => Box::into_raw(item).cast(),
+                Err(_) => ENOMEM.to_ptr(),
+            },
+        }
+    }
+
+    unsafe extern "C" fn next_callback(
+        m: *mut bindings::seq_file,
+        v: *mut c_void,
+        pos: *mut bindings::loff_t,
+    ) -> *mut c_void {
+        // SAFETY: The caller guarantees that `private` points to a valid `T`, and `pos` is valid.
+        let data = unsafe { &*((*m).private as *const T) };
+        // SAFETY: `pos` is valid for the duration of the call.
+        let pos = unsafe {
+            *pos += 1;
+            *pos
+        };
+        let slot = v as *mut T::Item;
+        // SAFETY: `v` was returned by `start_callback` or `next_callback`, so it is a boxed item.
+        // Its ownership is transferred to us, and the allocation is reused for the next item.
+        let item = unsafe { ptr::read(slot) };
+        match data.next(item, pos as _) {
+            Some(next) => {
+                // SAFETY: The previous item was moved out of the slot above.
+                unsafe { ptr::write(slot, next) };
+                v
+            }
+            None => {
+                // SAFETY: The slot was allocated as a box in `start_callback`, and its contents
+                // were moved out above.
+                drop(unsafe { Box::from_raw(slot as *mut MaybeUninit<T::Item>) });
+                ptr::null_mut()
+            }
+        }
+    }
+
+    unsafe extern "C" fn stop_callback(m: *mut bindings::seq_file, v: *mut c_void) {
+        // SAFETY: The caller guarantees that `private` points to a valid `T`.
+        let data = unsafe { &*((*m).private as *const T) };
+        // SAFETY: FFI call with no additional requirements.
+        if !v.is_null() && !unsafe { bindings::IS_ERR(v) } {
+            // SAFETY: `v` is a boxed item returned by `start_callback` or `next_callback`.
+            drop(unsafe { Box::from_raw(v as *mut T::Item) });
+        }
+        data.stop();
+    }
+
+    unsafe extern "C" fn show_callback(m: *mut bindings::seq_file, v: *mut c_void) -> c_int {
+        // SAFETY: The caller guarantees that `private` points to a valid `T`.
+        let data = unsafe { &*((*m).private as *const T) };
+        // SAFETY: `v` is a boxed item returned by `start_callback` or `next_callback`.
+        let item = unsafe { &*(v as *const T::Item) };
+        // SAFETY: `m` is valid for the duration of the call.
+        match data.show(unsafe { SeqFile::from_raw(m) }, item) {
+            Ok(()) => 0,
+            Err(e) => e.to_errno(),
+        }
+    }
+
+    const VTABLE: bindings::seq_operations = bindings::seq_operations {
+        start: Some(Self::start_callback),
+        next: Some(Self::next_callback),
+        stop: Some(Self::stop_callback),
+        show: Some(Self::show_callback),
+    };
+
+    pub(crate) const fn build() -> &'static bindings::seq_operations {
+        &Self::VTABLE
+    }
+}
+
+/// Builds a `struct file_operations` table for seq files implemented by `T`, whose `T` is
+/// obtained with the open adapter `A`.
+pub(crate) struct SeqFileVtable<A, T>(PhantomData<A>, PhantomData<T>);
+
+impl<A: OpenAdapter<T>, T: Operations> SeqFileVtable<A, T> {
+    unsafe extern "C" fn open_callback(
+        inode: *mut bindings::inode,
+        file: *mut bindings::file,
+    ) -> c_int {
+        // SAFETY: `file` is being opened, and the seq operations are static.
+        let ret = unsafe { bindings::seq_open(file, SeqOperationsVtable::<T>::build()) };
+        if ret != 0 {
+            return ret;
+        }
+        // SAFETY: `seq_open` stored a valid `struct seq_file` in `private_data`. The caller
+        // guarantees that `inode` and `file` come from a registration of the adapter's kind.
+        unsafe {
+            let m = (*file).private_data as *mut bindings::seq_file;
+            (*m).private = A::convert(inode, file) as *mut c_void;
+        }
+        0
+    }
+
+    const VTABLE: bindings::file_operations = bindings::file_operations {
+        open: Some(Self::open_callback),
+        read: Some(bindings::seq_read),
+        llseek: Some(bindings::seq_lseek),
+        release: Some(bindings::seq_release),
+        // SAFETY: All other fields are either pointers, for which `NULL` means "not implemented",
+        // or plain integers, for which zero is the correct default.
+        ..unsafe { MaybeUninit::zeroed().assume_init() }
+    };
+
+    /// Builds an instance of [`struct file_operations`] owned by `module`, which can't be unloaded
+    /// while files using it are open.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the adapter is compatible with the way the file is created.
+    pub(crate) unsafe fn build_owned(module: &'static ThisModule) -> bindings::file_operations {
+        bindings::file_operations {
+            owner: module.as_ptr(),
+            ..Self::VTABLE
+        }
+    }
+}
@@ -0,0 +1,865 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Serial port drivers.
+//!
+//! A serial driver registers a [`UartDriver`], which reserves the TTY devices of its ports, then
+//! adds a [`UartPort`] for each port that it finds. The serial core calls the port's
+//! [`UartOperations`] when the TTY is opened, configured and written to.
+//!
+//! Most operations are called with the port's lock held, and receive a [`LockedPort`], which
+//! gives access to the transmit buffer and to the receive path. Interrupt handlers take the same
+//! lock with [`UartPort::lock`].
+//!
+//! Ports of drivers registered with [`UartDriver::register_with_console`] can also print kernel
+//! messages, see [`UartConsole`].
+//!
+//! C header: [`include/linux/serial_core.h`](../../../../include/linux/serial_core.h)
+
+use crate::{
+    bindings, console,
+    device::Device,
+    error::{code::*, from_result, to_result, Error, Result},
+    init,
+    str::CStr,
+    sync::Arc,
+    types::{ARef, Opaque},
+    ThisModule,
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::{c_char, c_int, c_uchar, c_uint, c_ulong},
+    marker::{PhantomData, PhantomPinned},
+    ops::Deref,
+    pin::Pin,
+    ptr,
+};
+use macros::vtable;
+
+/// The size of the transmit buffer of a port, which is a power of two.
+const UART_XMIT_SIZE: usize = bindings::PAGE_SIZE as usize;
+
+/// A serial driver, the kernel's `struct uart_driver`.
+///
+/// The driver is unregistered when the last reference to it is dropped, which happens after all
+/// its ports are removed since they hold a reference to it.
+///
+/// # Invariants
+///
+/// `inner` was registered with `uart_register_driver`.
+pub struct UartDriver {
+    inner: Opaque<bindings::uart_driver>,
+    console: Option<Pin<Box<console::Registration>>>,
+    _pin: PhantomPinned,
+}
+
+// SAFETY: The driver can be unregistered from any thread.
+unsafe impl Send for UartDriver {}
+
+// SAFETY: The driver is only used by the serial core, which has its own synchronisation.
+unsafe impl Sync for UartDriver {}
+
+impl UartDriver {
+    /// Registers a serial driver with up to `nr` ports, whose TTY devices are named `dev_name`
+    /// followed by the line number (e.g. `ttyRS0`).
+    ///
+    /// A major number is allocated dynamically.
+    pub fn register(
+        module: &'static ThisModule,
+        driver_name: &'static CStr,
+        dev_name: &'static CStr,
+        nr: u32,
+    ) -> Result<Arc<Self>> {
+        Self::register_inner(module, driver_name, dev_name, nr, None)
+    }
+
+    /// Registers a serial driver like [`UartDriver::register`], with a console that writes
+    /// through its ports with [`UartConsole::console_putchar`].
+    ///
+    /// The console is registered by the serial core when the port with its index is added, if it
+    /// is selected on the command line (e.g. with `console=myrustcon0` if
+    /// [`UartConsole::CONSOLE_NAME`] is `myrustcon`).
+    pub fn register_with_console<T: UartConsole>(
+        module: &'static ThisModule,
+        driver_name: &'static CStr,
+        dev_name: &'static CStr,
+        nr: u32,
+    ) -> Result<Arc<Self>> {
+        // SAFETY: The callbacks only use the console's data, which is set to the driver when it
+        // is registered below, and the ports of the driver, which are `UartPort<T>`.
+        let console = unsafe {
+            console::Registration::new_raw(
+                T::CONSOLE_NAME,
+                T::CONSOLE_FLAGS,
+                T::CONSOLE_INDEX,
+                bindings::console {
+                    write: Some(ConsoleAdapter::<T>::write_callback),
+                    setup: Some(ConsoleAdapter::<T>::setup_callback),
+                    device: Some(bindings::uart_console_device),
+                    // SAFETY: All other fields are optional, for which zero is valid.
+                    ..core::mem::MaybeUninit::zeroed().assume_init()
+                },
+            )?
+        };
+        Self::register_inner(module, driver_name, dev_name, nr, Some(console))
+    }
+
+    fn register_inner(
+        module: &'static ThisModule,
+        driver_name: &'static CStr,
+        dev_name: &'static CStr,
+        nr: u32,
+        console: Option<Pin<Box<console::Registration>>>,
+    ) -> Result<Arc<Self>> {
+        if nr == 0 {
+            return Err(EINVAL);
+        }
+        // SAFETY: The closure initialises all fields on success, and drops the console on
+        // failure.
+        let init = unsafe {
+            init::pin_init_from_closure::<_, Error>(move |slot: *mut Self| {
+                let drv = Opaque::raw_get(ptr::addr_of!((*slot).inner));
+                let cons = console.as_ref().map_or(ptr::null_mut(), |con| con.as_raw());
+                if !cons.is_null() {
+                    (*cons).data = drv.cast();
+                }
+                drv.write(bindings::uart_driver {
+                    owner: module.as_ptr(),
+                    driver_name: driver_name.as_char_ptr(),
+                    dev_name: dev_name.as_char_ptr(),
+                    nr: nr as _,
+                    cons,
+                    // SAFETY: All other fields are optional or filled in on registration.
+                    ..core::mem::MaybeUninit::zeroed().assume_init()
+                });
+                to_result(bindings::uart_register_driver(drv))?;
+                // INVARIANT: The driver is only considered initialised if it was registered.
+                ptr::addr_of_mut!((*slot).console).write(console);
+                Ok(())
+            })
+        };
+        Arc::pin_init(init)
+    }
+
+    fn as_raw(&self) -> *mut bindings::uart_driver {
+        self.inner.get()
+    }
+}
+
+impl Drop for UartDriver {
+    fn drop(&mut self) {
+        // SAFETY: The driver is registered by the type invariants, and has no ports left. Its
+        // console is unregistered when its last port is removed, so it can be freed afterwards.
+        unsafe { bindings::uart_unregister_driver(self.as_raw()) };
+    }
+}
+
+/// The parity of a serial line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parity {
+    /// No parity bit.
+    None,
+    /// Odd parity.
+    Odd,
+    /// Even parity.
+    Even,
+}
+
+/// The settings of a TTY, the kernel's `struct ktermios`.
+#[repr(transparent)]
+pub struct Termios(bindings::ktermios);
+
+impl Termios {
+    /// Returns the control flags, a combination of `CSIZE`, `CSTOPB`, `PARENB`, etc.
+    pub fn cflag(&self) -> u32 {
+        self.0.c_cflag
+    }
+
+    /// Sets the control flags, e.g. to clear settings that the hardware doesn't support, which
+    /// are then reported to user space.
+    pub fn set_cflag(&mut self, cflag: u32) {
+        self.0.c_cflag = cflag;
+    }
+
+    /// Returns the number of data bits per character, from 5 to 8.
+    pub fn char_size(&self) -> u32 {
+        match self.0.c_cflag & bindings::CSIZE {
+            bindings::CS5 => 5,
+            bindings::CS6 => 6,
+            bindings::CS7 => 7,
+            _ => 8,
+        }
+    }
+
+    /// Returns the number of stop bits, 1 or 2.
+    pub fn stop_bits(&self) -> u32 {
+        if self.0.c_cflag & bindings::CSTOPB != 0 {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Returns the parity.
+    pub fn parity(&self) -> Parity {
+        let cflag = self.0.c_cflag;
+        if cflag & bindings::PARENB == 0 {
+            Parity::None
+        } else if cflag & bindings::PARODD != 0 {
+            Parity::Odd
+        } else {
+            Parity::Even
+        }
+    }
+
+    /// Returns `true` if RTS/CTS hardware flow control is enabled.
+    pub fn hw_flow_control(&self) -> bool {
+        self.0.c_cflag & bindings::CRTSCTS != 0
+    }
+}
+
+/// The status of a received character, the kernel's `TTY_*` flags.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RxFlag {
+    /// The character was received correctly.
+    Normal,
+    /// A break condition was detected.
+    Break,
+    /// The character has a framing error.
+    Frame,
+    /// The character has a parity error.
+    Parity,
+    /// Characters were lost before this one.
+    Overrun,
+}
+
+impl RxFlag {
+    fn as_raw(self) -> c_uint {
+        match self {
+            Self::Normal => bindings::TTY_NORMAL,
+            Self::Break => bindings::TTY_BREAK,
+            Self::Frame => bindings::TTY_FRAME,
+            Self::Parity => bindings::TTY_PARITY,
+            Self::Overrun => bindings::TTY_OVERRUN,
+        }
+    }
+}
+
+/// Operations of a serial port, the kernel's `struct uart_ops`.
+///
+/// Callbacks that receive a [`LockedPort`] are called with the port's lock held and interrupts
+/// disabled, and must not sleep.
+#[vtable]
+pub trait UartOperations: Send + Sync + Sized + 'static {
+    /// The name of the port type, shown in `/proc/tty/driver`.
+    const TYPE_NAME: &'static CStr;
+
+    /// Returns `true` if the transmitter is empty, i.e. all characters were sent.
+    fn tx_empty(port: &UartPort<Self>) -> bool;
+
+    /// Sets the modem control lines, a combination of `TIOCM_RTS`, `TIOCM_DTR`, etc.
+    fn set_mctrl(_port: &LockedPort<Self>, _mctrl: u32) {}
+
+    /// Returns the state of the modem control inputs, a combination of `TIOCM_CAR`, `TIOCM_CTS`,
+    /// etc.
+    ///
+    /// Ports without modem control lines report that carrier, CTS and DSR are asserted.
+    fn get_mctrl(_port: &LockedPort<Self>) -> u32 {
+        bindings::TIOCM_CAR | bindings::TIOCM_CTS | bindings::TIOCM_DSR
+    }
+
+    /// Starts transmitting the characters in the transmit buffer, see [`LockedPort::tx_pop`].
+    ///
+    /// This is usually done by enabling the transmit interrupt.
+    fn start_tx(port: &LockedPort<Self>);
+
+    /// Stops transmitting as soon as possible, e.g. for flow control.
+    fn stop_tx(port: &LockedPort<Self>);
+
+    /// Stops receiving, e.g. because the port is being closed.
+    fn stop_rx(port: &LockedPort<Self>);
+
+    /// Starts or stops sending a break condition.
+    fn break_ctl(_port: &UartPort<Self>, _on: bool) {}
+
+    /// Prepares the port for use when its TTY is opened, e.g. by requesting its interrupt.
+    ///
+    /// This may sleep.
+    fn startup(port: &UartPort<Self>) -> Result;
+
+    /// Undoes [`UartOperations::startup`] when the TTY is closed.
+    ///
+    /// This may sleep.
+    fn shutdown(port: &UartPort<Self>);
+
+    /// Applies new line settings.
+    ///
+    /// The baud rate is usually computed with [`UartPort::get_baud_rate`], followed by
+    /// [`LockedPort::update_timeout`]. This may sleep.
+    fn set_termios(port: &UartPort<Self>, new: &mut Termios, old: Option<&Termios>);
+}
+
+/// A serial port that can be used as a console, see [`UartDriver::register_with_console`].
+pub trait UartConsole: UartOperations {
+    /// The name of the console, without its index. It must be shorter than 16 bytes.
+    const CONSOLE_NAME: &'static CStr;
+
+    /// The flags of the console, see [`console::flags`].
+    const CONSOLE_FLAGS: i16 = console::flags::PRINTBUFFER;
+
+    /// The index of the console, i.e. the line of the port it writes to, or `-1` to use the one
+    /// from the command line.
+    const CONSOLE_INDEX: i16 = -1;
+
+    /// Writes a character of a kernel message, busy-waiting until the transmitter has room for
+    /// it.
+    ///
+    /// This is called with the port's lock held, except while the kernel is crashing, when the
+    /// lock might never be released.
+    fn console_putchar(port: &LockedPort<Self>, c: u8);
+}
+
+/// The configuration of a [`UartPort`].
+#[derive(Clone, Copy, Default)]
+pub struct PortConfig {
+    /// The line number of the port, less than the number of ports of the driver.
+    pub line: u32,
+    /// The type of the port, one of the `PORT_*` constants. It must not be `PORT_UNKNOWN`.
+    pub port_type: u32,
+    /// The interrupt of the port, only used for information.
+    pub irq: u32,
+    /// The frequency of the clock of the port, in Hz.
+    pub uartclk: u32,
+    /// The size of the transmit FIFO.
+    pub fifosize: u32,
+    /// The physical address of the registers of the port, only used for information.
+    pub mapbase: u64,
+}
+
+/// A serial port, the kernel's `struct uart_port`.
+///
+/// The port is removed from its driver when this is dropped.
+///
+/// # Invariants
+///
+/// `port` has operations `T`, and was added to `driver` if `added` is `true`, which it always is
+/// once the port is returned by [`UartPort::try_new`].
+///
+/// # Examples
+///
+/// The transmit path of a port with a FIFO, called from [`UartOperations::start_tx`] and from
+/// the interrupt handler:
+///
+/// ```
+/// use kernel::serial::{LockedPort, UartOperations};
+///
+/// trait Fifo {
+///     fn full(&self) -> bool;
+///     fn write(&self, c: u8);
+///     fn disable_tx_irq(&self);
+/// }
+///
+/// fn fill_fifo<T: UartOperations + Fifo>(port: &LockedPort<T>) {
+///     if let Some(c) = port.take_x_char() {
+///         port.data().write(c);
+///     }
+///     while !port.data().full() && !port.tx_stopped() {
+///         match port.tx_pop() {
+///             Some(c) => port.data().write(c),
+///             None => break,
+///         }
+///     }
+///     port.tx_wakeup();
+///     if port.tx_empty() {
+///         port.data().disable_tx_irq();
+///     }
+/// }
+/// ```
+#[repr(C)]
+pub struct UartPort<T: UartOperations> {
+    // Must be the first field, see `Adapter::port`.
+    port: Opaque<bindings::uart_port>,
+    driver: Arc<UartDriver>,
+    _dev: ARef<Device>,
+    added: bool,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+// SAFETY: The port can be removed from any thread, and the driver data is `Send`.
+unsafe impl<T: UartOperations> Send for UartPort<T> {}
+
+// SAFETY: The methods that take `&self` either use functions with their own synchronisation or
+// require the port's lock, and the driver data is `Sync`.
+unsafe impl<T: UartOperations> Sync for UartPort<T> {}
+
+impl<T: UartOperations> UartPort<T> {
+    /// Adds a port of `dev` to `driver`, which creates its TTY device.
+    ///
+    /// Fails with `EINVAL` if the line number is out of range for the driver, or the port type is
+    /// `PORT_UNKNOWN`.
+    pub fn try_new(
+        driver: &Arc<UartDriver>,
+        dev: &Device,
+        config: PortConfig,
+        data: T,
+    ) -> Result<Pin<Box<Self>>> {
+        crate::might_sleep!();
+        // SAFETY: The driver is valid while we hold a reference to it.
+        let nr = unsafe { (*driver.as_raw()).nr };
+        if config.line >= nr as u32 || config.port_type == bindings::PORT_UNKNOWN {
+            return Err(EINVAL);
+        }
+
+        let mut port = Pin::from(Box::try_new(Self {
+            port: Opaque::new(bindings::uart_port {
+                ops: Adapter::<T>::build(),
+                line: config.line,
+                type_: config.port_type,
+                irq: config.irq,
+                uartclk: config.uartclk,
+                fifosize: config.fifosize,
+                mapbase: config.mapbase as _,
+                iotype: bindings::UPIO_MEM as _,
+                dev: dev.as_raw(),
+                // SAFETY: All other fields are optional or filled in when the port is added.
+                ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+            }),
+            driver: driver.clone(),
+            _dev: dev.into(),
+            added: false,
+            data,
+            _pin: PhantomPinned,
+        })?);
+        // SAFETY: The driver is registered, and the port is pinned and removed before it is
+        // freed.
+        to_result(unsafe { bindings::uart_add_one_port(driver.as_raw(), port.as_raw()) })?;
+        // INVARIANT: The port was added above.
+        // SAFETY: `port` isn't moved out of.
+        unsafe { port.as_mut().get_unchecked_mut() }.added = true;
+        Ok(port)
+    }
+
+    fn as_raw(&self) -> *mut bindings::uart_port {
+        self.port.get()
+    }
+
+    /// Returns the driver data of the port.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    /// Returns the line number of the port.
+    pub fn line(&self) -> u32 {
+        // SAFETY: The port is valid by the type invariants, and the line never changes.
+        unsafe { (*self.as_raw()).line }
+    }
+
+    /// Returns the frequency of the clock of the port, in Hz.
+    pub fn uartclk(&self) -> u32 {
+        // SAFETY: The port is valid by the type invariants.
+        unsafe { (*self.as_raw()).uartclk }
+    }
+
+    /// Takes the port's lock, disabling interrupts.
+    ///
+    /// This must be used by the driver's interrupt handler before handling received characters
+    /// or filling the transmitter.
+    pub fn lock(&self) -> PortGuard<'_, T> {
+        // SAFETY: The port is valid, and its lock was initialised when it was added.
+        let flags =
+            unsafe { bindings::spin_lock_irqsave(ptr::addr_of_mut!((*self.as_raw()).lock)) };
+        // INVARIANT: The lock was taken above.
+        PortGuard {
+            // SAFETY: The lock is held for the lifetime of the guard.
+            port: unsafe { LockedPort::from_port(self) },
+            flags,
+        }
+    }
+
+    /// Returns the baud rate requested by `termios`, within `min` and `max`, like
+    /// `uart_get_baud_rate` in C.
+    ///
+    /// If the rate is out of range, `termios` is updated to the previous or the default rate.
+    pub fn get_baud_rate(
+        &self,
+        termios: &mut Termios,
+        old: Option<&Termios>,
+        min: u32,
+        max: u32,
+    ) -> u32 {
+        let old = old.map_or(ptr::null(), |old| &old.0 as *const _);
+        // SAFETY: The port is valid by the type invariants, and the settings are valid.
+        unsafe { bindings::uart_get_baud_rate(self.as_raw(), &mut termios.0, old, min, max) }
+    }
+}
+
+impl<T: UartOperations> Drop for UartPort<T> {
+    fn drop(&mut self) {
+        // The line may belong to another port if adding this one failed.
+        if self.added {
+            // SAFETY: The port was added to the driver by the type invariants. Once this returns,
+            // no more callbacks run.
+            unsafe { bindings::uart_remove_one_port(self.driver.as_raw(), self.as_raw()) };
+        }
+    }
+}
+
+/// A serial port whose lock is held.
+///
+/// # Invariants
+///
+/// The port's lock is held while references to this exist.
+#[repr(transparent)]
+pub struct LockedPort<T: UartOperations>(UartPort<T>);
+
+impl<T: UartOperations> LockedPort<T> {
+    /// Creates a reference to a [`LockedPort`].
+    ///
+    /// # Safety
+    ///
+    /// The caller must hold the port's lock for the lifetime of the returned reference.
+    unsafe fn from_port(port: &UartPort<T>) -> &Self {
+        // SAFETY: `LockedPort` is transparent over `UartPort`.
+        unsafe { &*(port as *const UartPort<T>).cast() }
+    }
+
+    fn xmit(&self) -> *mut bindings::circ_buf {
+        // SAFETY: The port is valid, and its state is set while the TTY is open, which is when
+        // the callbacks that receive a `LockedPort` run.
+        unsafe { ptr::addr_of_mut!((*(*self.as_raw()).state).xmit) }
+    }
+
+    /// Returns the number of characters waiting in the transmit buffer.
+    pub fn tx_pending(&self) -> usize {
+        let xmit = self.xmit();
+        // SAFETY: The buffer is only modified with the lock held.
+        let (head, tail) = unsafe { ((*xmit).head, (*xmit).tail) };
+        (head - tail) as usize & (UART_XMIT_SIZE - 1)
+    }
+
+    /// Returns `true` if the transmit buffer is empty.
+    pub fn tx_empty(&self) -> bool {
+        self.tx_pending() == 0
+    }
+
+    /// Returns `true` if transmission is stopped by flow control, like `uart_tx_stopped` in C.
+    pub fn tx_stopped(&self) -> bool {
+        // SAFETY: The port is valid and its lock is held.
+        unsafe { bindings::uart_tx_stopped(self.as_raw()) }
+    }
+
+    /// Returns the high-priority flow control character (XON/XOFF) to send before the
+    /// characters of the transmit buffer, if any, and clears it.
+    pub fn take_x_char(&self) -> Option<u8> {
+        // SAFETY: The port is valid and its lock is held.
+        unsafe {
+            let x_char = ptr::addr_of_mut!((*self.as_raw()).x_char);
+            match *x_char {
+                0 => None,
+                c => {
+                    *x_char = 0;
+                    (*self.as_raw()).icount.tx += 1;
+                    Some(c as u8)
+                }
+            }
+        }
+    }
+
+    /// Removes the next character from the transmit buffer, to be written to the transmitter.
+    pub fn tx_pop(&self) -> Option<u8> {
+        if self.tx_empty() {
+            return None;
+        }
+        let xmit = self.xmit();
+        // SAFETY: The buffer holds at least one character, and is only modified with the lock
+        // held.
+        unsafe {
+            let tail = (*xmit).tail;
+            let c = *(*xmit).buf.add(tail as usize) as u8;
+            (*xmit).tail = (tail + 1) & (UART_XMIT_SIZE as c_int - 1);
+            (*self.as_raw()).icount.tx += 1;
+            Some(c)
+        }
+    }
+
+    /// Wakes up writers of the TTY if the transmit buffer is running low. This should be called
+    /// after removing characters from the buffer.
+    pub fn tx_wakeup(&self) {
+        if self.tx_pending() < bindings::WAKEUP_CHARS as usize {
+            // SAFETY: The port is valid and its lock is held.
+            unsafe { bindings::uart_write_wakeup(self.as_raw()) };
+        }
+    }
+
+    /// Passes a received character to the TTY, with its status.
+    ///
+    /// The characters are only seen by the TTY after [`LockedPort::rx_push`] is called.
+    pub fn rx_insert(&self, c: u8, flag: RxFlag) {
+        // SAFETY: The port is valid and its lock is held.
+        unsafe {
+            (*self.as_raw()).icount.rx += 1;
+            bindings::uart_insert_char(self.as_raw(), 0, 0, c.into(), flag.as_raw());
+        }
+    }
+
+    /// Pushes the received characters to the TTY.
+    pub fn rx_push(&self) {
+        // SAFETY: The port is valid, and so is its state while the TTY is open.
+        unsafe {
+            bindings::tty_flip_buffer_push(ptr::addr_of_mut!((*(*self.as_raw()).state).port))
+        };
+    }
+
+    /// Updates the timeout used to wait for the transmitter to drain, from the new settings.
+    pub fn update_timeout(&self, cflag: u32, baud: u32) {
+        // SAFETY: The port is valid and its lock is held.
+        unsafe { bindings::uart_update_timeout(self.as_raw(), cflag, baud) };
+    }
+}
+
+impl<T: UartOperations> Deref for LockedPort<T> {
+    type Target = UartPort<T>;
+
+    fn deref(&self) -> &UartPort<T> {
+        &self.0
+    }
+}
+
+/// A guard holding the lock of a serial port, see [`UartPort::lock`].
+///
+/// # Invariants
+///
+/// The lock of `port` was taken with `spin_lock_irqsave`, which returned `flags`.
+pub struct PortGuard<'a, T: UartOperations> {
+    port: &'a LockedPort<T>,
+    flags: c_ulong,
+}
+
+impl<T: UartOperations> Deref for PortGuard<'_, T> {
+    type Target = LockedPort<T>;
+
+    fn deref(&self) -> &LockedPort<T> {
+        self.port
+    }
+}
+
+impl<T: UartOperations> Drop for PortGuard<'_, T> {
+    fn drop(&mut self) {
+        // SAFETY: The lock is held by the type invariants.
+        unsafe {
+            bindings::spin_unlock_irqrestore(
+                ptr::addr_of_mut!((*self.port.as_raw()).lock),
+                self.flags,
+            )
+        };
+    }
+}
+
+struct Adapter<T: UartOperations>(PhantomData<T>);
+
+impl<T: UartOperations> Adapter<T> {
+    /// Returns the Rust port of a C port.
+    ///
+    /// # Safety
+    ///
+    /// `port` must be the port of a live [`UartPort<T>`].
+    unsafe fn port<'a>(port: *mut bindings::uart_port) -> &'a UartPort<T> {
+        // SAFETY: `port` is the first field of a `UartPort<T>`, which is `repr(C)`.
+        unsafe { &*(port as *const UartPort<T>) }
+    }
+
+    /// Returns the Rust port of a C port whose lock is held.
+    ///
+    /// # Safety
+    ///
+    /// `port` must be the port of a live [`UartPort<T>`], and its lock must be held for `'a`.
+    unsafe fn locked<'a>(port: *mut bindings::uart_port) -> &'a LockedPort<T> {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { LockedPort::from_port(Self::port(port)) }
+    }
+
+    unsafe extern "C" fn tx_empty_callback(port: *mut bindings::uart_port) -> c_uint {
+        // SAFETY: The serial core only calls this for added ports.
+        if T::tx_empty(unsafe { Self::port(port) }) {
+            bindings::TIOCSER_TEMT
+        } else {
+            0
+        }
+    }
+
+    unsafe extern "C" fn set_mctrl_callback(port: *mut bindings::uart_port, mctrl: c_uint) {
+        // SAFETY: The serial core only calls this for added ports, with their lock held.
+        T::set_mctrl(unsafe { Self::locked(port) }, mctrl);
+    }
+
+    unsafe extern "C" fn get_mctrl_callback(port: *mut bindings::uart_port) -> c_uint {
+        // SAFETY: The serial core only calls this for added ports, with their lock held.
+        T::get_mctrl(unsafe { Self::locked(port) })
+    }
+
+    unsafe extern "C" fn start_tx_callback(port: *mut bindings::uart_port) {
+        // SAFETY: The serial core only calls this for added ports, with their lock held.
+        T::start_tx(unsafe { Self::locked(port) });
+    }
+
+    unsafe extern "C" fn stop_tx_callback(port: *mut bindings::uart_port) {
+        // SAFETY: The serial core only calls this for added ports, with their lock held.
+        T::stop_tx(unsafe { Self::locked(port) });
+    }
+
+    unsafe extern "C" fn stop_rx_callback(port: *mut bindings::uart_port) {
+        // SAFETY: The serial core only calls this for added ports, with their lock held.
+        T::stop_rx(unsafe { Self::locked(port) });
+    }
+
+    unsafe extern "C" fn break_ctl_callback(port: *mut bindings::uart_port, ctl: c_int) {
+        // SAFETY: The serial core only calls this for added ports.
+        T::break_ctl(unsafe { Self::port(port) }, ctl != 0);
+    }
+
+    unsafe extern "C" fn startup_callback(port: *mut bindings::uart_port) -> c_int {
+        from_result(|| {
+            // SAFETY: The serial core only calls this for added ports.
+            T::startup(unsafe { Self::port(port) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn shutdown_callback(port: *mut bindings::uart_port) {
+        // SAFETY: The serial core only calls this for added ports.
+        T::shutdown(unsafe { Self::port(port) });
+    }
+
+    unsafe extern "C" fn set_termios_callback(
+        port: *mut bindings::uart_port,
+        new: *mut bindings::ktermios,
+        old: *const bindings::ktermios,
+    ) {
+        // SAFETY: The serial core only calls this for added ports, with valid settings. `old` is
+        // null if there are no previous settings. `Termios` is transparent over `ktermios`.
+        unsafe {
+            let old = (!old.is_null()).then(|| &*old.cast::<Termios>());
+            T::set_termios(Self::port(port), &mut *new.cast::<Termios>(), old);
+        }
+    }
+
+    unsafe extern "C" fn type_callback(_port: *mut bindings::uart_port) -> *const c_char {
+        T::TYPE_NAME.as_char_ptr()
+    }
+
+    unsafe extern "C" fn release_port_callback(_port: *mut bindings::uart_port) {}
+
+    unsafe extern "C" fn request_port_callback(_port: *mut bindings::uart_port) -> c_int {
+        0
+    }
+
+    unsafe extern "C" fn config_port_callback(_port: *mut bindings::uart_port, _flags: c_int) {
+        // The port type is set when the port is added, so there is nothing to probe.
+    }
+
+    const VTABLE: bindings::uart_ops = bindings::uart_ops {
+        tx_empty: Some(Self::tx_empty_callback),
+        set_mctrl: Some(Self::set_mctrl_callback),
+        get_mctrl: Some(Self::get_mctrl_callback),
+        stop_tx: Some(Self::stop_tx_callback),
+        start_tx: Some(Self::start_tx_callback),
+        stop_rx: Some(Self::stop_rx_callback),
+        break_ctl: Some(Self::break_ctl_callback),
+        startup: Some(Self::startup_callback),
+        shutdown: Some(Self::shutdown_callback),
+        set_termios: Some(Self::set_termios_callback),
+        type_: Some(Self::type_callback),
+        release_port: Some(Self::release_port_callback),
+        request_port: Some(Self::request_port_callback),
+        config_port: Some(Self::config_port_callback),
+        // SAFETY: All other fields are optional callbacks, for which zero is valid.
+        ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+    };
+
+    const fn build() -> &'static bindings::uart_ops {
+        &Self::VTABLE
+    }
+}
+
+struct ConsoleAdapter<T: UartConsole>(PhantomData<T>);
+
+impl<T: UartConsole> ConsoleAdapter<T> {
+    /// Returns the C port of the line of a console, if it was added.
+    ///
+    /// # Safety
+    ///
+    /// `co` must be a console created by [`UartDriver::register_with_console`], whose driver is
+    /// still registered.
+    unsafe fn port(co: *mut bindings::console) -> Option<*mut bindings::uart_port> {
+        // SAFETY: The caller guarantees that the data of the console is a registered driver,
+        // whose state has `nr` entries.
+        unsafe {
+            let drv = (*co).data.cast::<bindings::uart_driver>();
+            let index = (*co).index;
+            if index < 0 || index as c_int >= (*drv).nr {
+                return None;
+            }
+            let port = (*(*drv).state.add(index as usize)).uart_port;
+            (!port.is_null()).then_some(port)
+        }
+    }
+
+    unsafe extern "C" fn putchar_callback(port: *mut bindings::uart_port, c: c_uchar) {
+        // SAFETY: This is only called by `uart_console_write` from `write_callback`, with a port
+        // of the driver whose lock is held (or can't be taken).
+        T::console_putchar(unsafe { Adapter::<T>::locked(port) }, c);
+    }
+
+    unsafe extern "C" fn write_callback(
+        co: *mut bindings::console,
+        s: *const c_char,
+        count: c_uint,
+    ) {
+        // SAFETY: The printk core only calls this while the console is registered, which is when
+        // its driver is.
+        let port = match unsafe { Self::port(co) } {
+            Some(port) => port,
+            None => return,
+        };
+        // SAFETY: The port was added, so its lock is initialised. While the kernel is crashing,
+        // the lock may be held by a CPU that will never release it, so it is only tried.
+        unsafe {
+            let lock = ptr::addr_of_mut!((*port).lock);
+            let mut flags = 0;
+            let locked = if bindings::oops_in_progress != 0 {
+                bindings::spin_trylock_irqsave(lock, &mut flags)
+            } else {
+                flags = bindings::spin_lock_irqsave(lock);
+                true
+            };
+            bindings::uart_console_write(port, s, count, Some(Self::putchar_callback));
+            if locked {
+                bindings::spin_unlock_irqrestore(lock, flags);
+            }
+        }
+    }
+
+    unsafe extern "C" fn setup_callback(co: *mut bindings::console, options: *mut c_char) -> c_int {
+        // SAFETY: The printk core calls this with a console that is being registered, which
+        // happens while its driver is.
+        unsafe {
+            let drv = (*co).data.cast::<bindings::uart_driver>();
+            if (*co).index < 0 || (*co).index as c_int >= (*drv).nr {
+                (*co).index = 0;
+            }
+        }
+        // SAFETY: As above.
+        let port = match unsafe { Self::port(co) } {
+            Some(port) => port,
+            None => return ENODEV.to_errno(),
+        };
+        let (mut baud, mut parity, mut bits, mut flow) = (115200, b'n' as c_int, 8, b'n' as c_int);
+        // SAFETY: The port was added, and `options` is either null or a `NUL`-terminated string.
+        unsafe {
+            if !options.is_null() {
+                bindings::uart_parse_options(options, &mut baud, &mut parity, &mut bits, &mut flow);
+            }
+            bindings::uart_set_options(port, co, baud, parity, bits, flow)
+        }
+    }
+}
@@ -0,0 +1,115 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Signals.
+//!
+//! C headers: [`include/linux/signal.h`](../../../../include/linux/signal.h) and
+//! [`include/uapi/asm-generic/signal.h`](../../../../include/uapi/asm-generic/signal.h)
+
+use crate::{
+    bindings,
+    error::{code::*, Error},
+};
+
+/// A standard (non real-time) signal.
+///
+/// See `signal(7)` for the meaning and default action of each of them.
+///
+/// # Examples
+///
+/// Notifying the process that registered itself with a driver about a hardware fault:
+///
+/// ```
+/// use kernel::{signal::Signal, task::Task, types::ARef};
+/// # use kernel::prelude::*;
+///
+/// fn report_fault(owner: &ARef<Task>) -> Result {
+///     owner.send_group_signal(Signal::Usr1)
+/// }
+/// ```
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum Signal {
+    Hup = bindings::SIGHUP,
+    Int = bindings::SIGINT,
+    Quit = bindings::SIGQUIT,
+    Ill = bindings::SIGILL,
+    Trap = bindings::SIGTRAP,
+    Abrt = bindings::SIGABRT,
+    Bus = bindings::SIGBUS,
+    Fpe = bindings::SIGFPE,
+    Kill = bindings::SIGKILL,
+    Usr1 = bindings::SIGUSR1,
+    Segv = bindings::SIGSEGV,
+    Usr2 = bindings::SIGUSR2,
+    Pipe = bindings::SIGPIPE,
+    Alrm = bindings::SIGALRM,
+    Term = bindings::SIGTERM,
+    Chld = bindings::SIGCHLD,
+    Cont = bindings::SIGCONT,
+    Stop = bindings::SIGSTOP,
+    Tstp = bindings::SIGTSTP,
+    Ttin = bindings::SIGTTIN,
+    Ttou = bindings::SIGTTOU,
+    Urg = bindings::SIGURG,
+    Xcpu = bindings::SIGXCPU,
+    Xfsz = bindings::SIGXFSZ,
+    Vtalrm = bindings::SIGVTALRM,
+    Prof = bindings::SIGPROF,
+    Winch = bindings::SIGWINCH,
+    Io = bindings::SIGIO,
+    Pwr = bindings::SIGPWR,
+    Sys = bindings::SIGSYS,
+}
+
+impl Signal {
+    const ALL: [Signal; 30] = [
+        Signal::Hup,
+        Signal::Int,
+        Signal::Quit,
+        Signal::Ill,
+        Signal::Trap,
+        Signal::Abrt,
+        Signal::Bus,
+        Signal::Fpe,
+        Signal::Kill,
+        Signal::Usr1,
+        Signal::Segv,
+        Signal::Usr2,
+        Signal::Pipe,
+        Signal::Alrm,
+        Signal::Term,
+        Signal::Chld,
+        Signal::Cont,
+        Signal::Stop,
+        Signal::Tstp,
+        Signal::Ttin,
+        Signal::Ttou,
+        Signal::Urg,
+        Signal::Xcpu,
+        Signal::Xfsz,
+        Signal::Vtalrm,
+        Signal::Prof,
+        Signal::Winch,
+        Signal::Io,
+        Signal::Pwr,
+        Signal::Sys,
+    ];
+
+    /// Returns the signal number as expected by the C API.
+    pub fn to_raw(self) -> core::ffi::c_int {
+        self as _
+    }
+}
+
+impl TryFrom<core::ffi::c_int> for Signal {
+    type Error = Error;
+
+    fn try_from(sig: core::ffi::c_int) -> Result<Self, Error> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|s| s.to_raw() == sig)
+            .ok_or(EINVAL)
+    }
+}
@@ -0,0 +1,259 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! SoC identification.
+//!
+//! The driver of the chip-id registers of an SoC registers a [`Registration`], which describes the
+//! SoC in `/sys/devices/soc0`. Other drivers then apply errata of specific revisions by matching
+//! the description against a table of [`Match`] entries with [`match_table`].
+//!
+//! C header: [`include/linux/sys_soc.h`](../../../../include/linux/sys_soc.h)
+
+use crate::{
+    bindings,
+    device::Device,
+    error::{from_err_ptr, Result},
+    str::{CStr, CString},
+};
+use alloc::boxed::Box;
+use core::{mem::MaybeUninit, ptr};
+
+/// An entry of a table of SoCs, the kernel's `struct soc_device_attribute`.
+///
+/// Each attribute that is set is a glob pattern, e.g. `"A0*"`, which the attribute of the SoC
+/// must match. At least one attribute must be set.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, soc};
+///
+/// struct Quirks {
+///     broken_dma: bool,
+/// }
+///
+/// static TABLE: [soc::Match<Quirks>; 2] = [
+///     soc::Match::new(Quirks { broken_dma: true })
+///         .soc_id(c_str!("20"))
+///         .revision(c_str!("A0[23]")),
+///     soc::Match::new(Quirks { broken_dma: false }).soc_id(c_str!("30")),
+/// ];
+///
+/// fn broken_dma() -> bool {
+///     soc::match_table(&TABLE).map_or(false, |m| m.data.broken_dma)
+/// }
+/// ```
+pub struct Match<T> {
+    /// The pattern of the machine, i.e. the model of the board.
+    pub machine: Option<&'static CStr>,
+    /// The pattern of the family of the SoC.
+    pub family: Option<&'static CStr>,
+    /// The pattern of the revision of the SoC.
+    pub revision: Option<&'static CStr>,
+    /// The pattern of the id of the SoC within its family.
+    pub soc_id: Option<&'static CStr>,
+    /// The data of the entry.
+    pub data: T,
+}
+
+impl<T> Match<T> {
+    /// Creates an entry with the data `data`, which matches nothing until an attribute is set.
+    pub const fn new(data: T) -> Self {
+        Self {
+            machine: None,
+            family: None,
+            revision: None,
+            soc_id: None,
+            data,
+        }
+    }
+
+    /// Sets the pattern of the machine.
+    pub const fn machine(mut self, pattern: &'static CStr) -> Self {
+        self.machine = Some(pattern);
+        self
+    }
+
+    /// Sets the pattern of the family.
+    pub const fn family(mut self, pattern: &'static CStr) -> Self {
+        self.family = Some(pattern);
+        self
+    }
+
+    /// Sets the pattern of the revision.
+    pub const fn revision(mut self, pattern: &'static CStr) -> Self {
+        self.revision = Some(pattern);
+        self
+    }
+
+    /// Sets the pattern of the id.
+    pub const fn soc_id(mut self, pattern: &'static CStr) -> Self {
+        self.soc_id = Some(pattern);
+        self
+    }
+
+    fn to_raw(&self) -> bindings::soc_device_attribute {
+        let pattern = |p: Option<&CStr>| p.map_or(ptr::null(), |p| p.as_char_ptr());
+        bindings::soc_device_attribute {
+            machine: pattern(self.machine),
+            family: pattern(self.family),
+            revision: pattern(self.revision),
+            soc_id: pattern(self.soc_id),
+            // SAFETY: All other fields are optional, for which zero is valid.
+            ..unsafe { MaybeUninit::zeroed().assume_init() }
+        }
+    }
+}
+
+/// Returns the first entry of `table` that matches a registered SoC, if any.
+///
+/// Entries without any attribute never match. The SoC is usually registered early, but drivers
+/// probed before it is may want to defer their probe if nothing matches.
+pub fn match_table<T>(table: &[Match<T>]) -> Option<&Match<T>> {
+    table.iter().find(|m| {
+        if m.machine.is_none() && m.family.is_none() && m.revision.is_none() && m.soc_id.is_none() {
+            return false;
+        }
+        // The table is terminated by an empty entry.
+        // SAFETY: All other fields are optional, for which zero is valid.
+        let raw = [m.to_raw(), unsafe { MaybeUninit::zeroed().assume_init() }];
+        // SAFETY: The table is terminated, and the patterns are valid strings.
+        !unsafe { bindings::soc_device_match(raw.as_ptr()) }.is_null()
+    })
+}
+
+/// The description of an SoC.
+#[derive(Clone, Copy, Default)]
+pub struct Attributes<'a> {
+    /// The model of the board, or `None` to use the `model` property of the devicetree.
+    pub machine: Option<&'a CStr>,
+    /// The family of the SoC, e.g. `Tegra`.
+    pub family: Option<&'a CStr>,
+    /// The revision of the SoC, e.g. `A03`.
+    pub revision: Option<&'a CStr>,
+    /// The id of the SoC within its family, e.g. `20`.
+    pub soc_id: Option<&'a CStr>,
+    /// The serial number of the SoC.
+    pub serial_number: Option<&'a CStr>,
+}
+
+/// A registered SoC, the kernel's `struct soc_device`.
+///
+/// The SoC is unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `soc` is registered, and described by `attr`, whose strings are owned by `_strings`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, fmt, prelude::*, soc, str::CString};
+///
+/// fn register(chip_id: u32) -> Result<soc::Registration> {
+///     let soc_id = CString::try_from_fmt(fmt!("{}", (chip_id >> 8) & 0xff))?;
+///     let revision = CString::try_from_fmt(fmt!("A{:02}", (chip_id >> 16) & 0xf))?;
+///     soc::Registration::register(&soc::Attributes {
+///         family: Some(c_str!("Tegra")),
+///         soc_id: Some(&*soc_id),
+///         revision: Some(&*revision),
+///         ..Default::default()
+///     })
+/// }
+/// ```
+pub struct Registration {
+    soc: *mut bindings::soc_device,
+    attr: Box<bindings::soc_device_attribute>,
+    _strings: [Option<CString>; 5],
+}
+
+impl Registration {
+    /// Registers an SoC described by `attrs`, whose strings are copied.
+    pub fn register(attrs: &Attributes<'_>) -> Result<Self> {
+        crate::might_sleep!();
+        let copy = |s: Option<&CStr>| s.map(CString::try_from).transpose();
+        let strings = [
+            copy(attrs.machine)?,
+            copy(attrs.family)?,
+            copy(attrs.revision)?,
+            copy(attrs.soc_id)?,
+            copy(attrs.serial_number)?,
+        ];
+        let raw = |i: usize| strings[i].as_ref().map_or(ptr::null(), |s| s.as_char_ptr());
+        let mut attr = Box::try_new(bindings::soc_device_attribute {
+            machine: raw(0),
+            family: raw(1),
+            revision: raw(2),
+            soc_id: raw(3),
+            serial_number: raw(4),
+            // SAFETY: All other fields are optional, for which zero is valid.
+            ..unsafe { MaybeUninit::zeroed().assume_init() }
+        })?;
+        // SAFETY: The attributes and their strings are owned by the registration, which outlives
+        // the SoC, and don't move since they are on the heap.
+        let soc = from_err_ptr(unsafe { bindings::soc_device_register(&mut *attr) })?;
+        // INVARIANT: The SoC was registered above.
+        Ok(Self {
+            soc,
+            attr,
+            _strings: strings,
+        })
+    }
+
+    /// Returns the device of the SoC, e.g. to be the parent of the devices of the SoC.
+    pub fn device(&self) -> &Device {
+        // SAFETY: The SoC is registered by the type invariants, so its device is valid.
+        unsafe { Device::as_ref(bindings::soc_device_to_device(self.soc)) }
+    }
+
+    /// Returns the model of the board, which is read from the devicetree if it wasn't given.
+    pub fn machine(&self) -> Option<&CStr> {
+        // SAFETY: The attributes of the SoC are valid by the type invariants, and the core only
+        // sets the machine on registration.
+        unsafe { opt_str(self.attr.machine) }
+    }
+
+    /// Returns the family of the SoC.
+    pub fn family(&self) -> Option<&CStr> {
+        // SAFETY: The attributes of the SoC are valid by the type invariants.
+        unsafe { opt_str(self.attr.family) }
+    }
+
+    /// Returns the revision of the SoC.
+    pub fn revision(&self) -> Option<&CStr> {
+        // SAFETY: The attributes of the SoC are valid by the type invariants.
+        unsafe { opt_str(self.attr.revision) }
+    }
+
+    /// Returns the id of the SoC.
+    pub fn soc_id(&self) -> Option<&CStr> {
+        // SAFETY: The attributes of the SoC are valid by the type invariants.
+        unsafe { opt_str(self.attr.soc_id) }
+    }
+}
+
+/// Returns the string at `s`, or `None` if it is null.
+///
+/// # Safety
+///
+/// `s` must be null or point to a valid string that outlives the returned reference.
+unsafe fn opt_str<'a>(s: *const core::ffi::c_char) -> Option<&'a CStr> {
+    if s.is_null() {
+        None
+    } else {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        Some(unsafe { CStr::from_char_ptr(s) })
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        // SAFETY: The SoC is registered by the type invariants.
+        unsafe { bindings::soc_device_unregister(self.soc) };
+    }
+}
+
+// SAFETY: The SoC can be unregistered from any thread.
+unsafe impl Send for Registration {}
+
+// SAFETY: The methods that take `&self` only read fields that never change after registration.
+unsafe impl Sync for Registration {}
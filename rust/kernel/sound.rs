@@ -0,0 +1,129 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Sound cards (ALSA).
+//!
+//! A sound card ([`Card`]) groups the devices of a sound chip, such as its PCM devices
+//! ([`pcm::Pcm`]), which are created before the card is registered.
+//!
+//! C header: [`include/sound/core.h`](../../../../include/sound/core.h)
+
+use crate::{bindings, device::Device, error::to_result, error::Result, str::CStr, ThisModule};
+use core::{ffi::c_char, ptr, ptr::NonNull};
+
+pub mod pcm;
+#[cfg(CONFIG_SND_SOC)]
+pub mod soc;
+
+/// Copies `src` into the fixed-size string `dst`, truncating it if needed.
+pub(crate) fn copy_name(dst: &mut [c_char], src: &CStr) {
+    let len = src.len().min(dst.len().saturating_sub(1));
+    for (d, &s) in dst.iter_mut().zip(&src.as_bytes()[..len]) {
+        *d = s as c_char;
+    }
+    if let Some(nul) = dst.get_mut(len) {
+        *nul = 0;
+    }
+}
+
+/// A sound card, the kernel's `struct snd_card`.
+///
+/// The card and all its devices are freed when this is dropped, once user space has closed them.
+///
+/// # Invariants
+///
+/// `card` is a valid card, owned by `Card`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, device::Device, sound::Card};
+/// # use kernel::prelude::*;
+///
+/// fn probe(dev: &Device, module: &'static ThisModule) -> Result<Card> {
+///     let card = Card::new(
+///         dev,
+///         module,
+///         c_str!("rustsnd"),
+///         c_str!("Rust sound"),
+///         c_str!("Rust sound card"),
+///     )?;
+///     // Create the devices of the card here.
+///     card.register()?;
+///     Ok(card)
+/// }
+/// ```
+pub struct Card {
+    card: NonNull<bindings::snd_card>,
+}
+
+// SAFETY: The card can be freed from any thread.
+unsafe impl Send for Card {}
+
+// SAFETY: The methods that take `&self` use functions with their own synchronisation.
+unsafe impl Sync for Card {}
+
+impl Card {
+    /// Creates a card whose parent is `parent`, with the next free index.
+    ///
+    /// `driver` is the name of the driver, `shortname` and `longname` describe the card, and are
+    /// truncated to 31 and 79 bytes.
+    pub fn new(
+        parent: &Device,
+        module: &'static ThisModule,
+        driver: &CStr,
+        shortname: &CStr,
+        longname: &CStr,
+    ) -> Result<Self> {
+        crate::might_sleep!();
+        let mut card = ptr::null_mut();
+        // SAFETY: `parent` and `module` are valid. The card has no extra data.
+        to_result(unsafe {
+            bindings::snd_card_new(
+                parent.as_raw(),
+                bindings::SNDRV_DEFAULT_IDX1,
+                ptr::null(),
+                module.as_ptr(),
+                0,
+                &mut card,
+            )
+        })?;
+        // SAFETY: The card was just created, and nothing else uses it yet.
+        unsafe {
+            copy_name(&mut (*card).driver, driver);
+            copy_name(&mut (*card).shortname, shortname);
+            copy_name(&mut (*card).longname, longname);
+        }
+        // INVARIANT: The card was created above, and is owned by the new `Card`.
+        Ok(Self {
+            // SAFETY: `snd_card_new` succeeded, so `card` isn't null.
+            card: unsafe { NonNull::new_unchecked(card) },
+        })
+    }
+
+    /// Returns the raw `struct snd_card` pointer.
+    pub fn as_raw(&self) -> *mut bindings::snd_card {
+        self.card.as_ptr()
+    }
+
+    /// Returns the index of the card, the `N` in `/dev/snd/controlCN`.
+    pub fn number(&self) -> i32 {
+        // SAFETY: The card is valid by the type invariants, and its number never changes.
+        unsafe { (*self.as_raw()).number }
+    }
+
+    /// Registers the card and its devices, which makes them available to user space.
+    pub fn register(&self) -> Result {
+        crate::might_sleep!();
+        // SAFETY: The card is valid by the type invariants. Registering it again only registers
+        // the devices that were added since.
+        to_result(unsafe { bindings::snd_card_register(self.as_raw()) })
+    }
+}
+
+impl Drop for Card {
+    fn drop(&mut self) {
+        // SAFETY: The card is owned by `Card` by the type invariants. This disconnects it and
+        // waits for user space to close it before freeing it.
+        unsafe { bindings::snd_card_free(self.as_raw()) };
+    }
+}
@@ -0,0 +1,687 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! PCM devices, which play and capture digital audio.
+//!
+//! A PCM device has playback and capture substreams ([`Substream`]), which user space opens and
+//! configures. Samples are exchanged through a buffer, which is usually managed by the ALSA core
+//! (see [`Pcm::set_managed_buffer`]), and that the hardware accesses with DMA. The driver tells
+//! the ALSA core how far the hardware got with [`Operations::pointer`], and calls
+//! [`Substream::period_elapsed`] at each period boundary.
+//!
+//! C header: [`include/sound/pcm.h`](../../../../include/sound/pcm.h)
+
+use crate::{
+    bindings,
+    device::Device,
+    error::{code::*, from_result, to_result, Result},
+    sound::{copy_name, Card},
+    str::CStr,
+    types::Opaque,
+};
+use alloc::boxed::Box;
+use core::{ffi::c_int, marker::PhantomData, ptr};
+use macros::vtable;
+
+/// Capabilities of substreams, the kernel's `SNDRV_PCM_INFO_*` values.
+pub mod info {
+    /// The buffer can be mapped by user space.
+    pub const MMAP: u32 = crate::bindings::SNDRV_PCM_INFO_MMAP;
+    /// The position in the buffer is accurate when mapped.
+    pub const MMAP_VALID: u32 = crate::bindings::SNDRV_PCM_INFO_MMAP_VALID;
+    /// Samples of the channels are interleaved.
+    pub const INTERLEAVED: u32 = crate::bindings::SNDRV_PCM_INFO_INTERLEAVED;
+    /// Samples are transferred by blocks.
+    pub const BLOCK_TRANSFER: u32 = crate::bindings::SNDRV_PCM_INFO_BLOCK_TRANSFER;
+    /// The stream can be paused.
+    pub const PAUSE: u32 = crate::bindings::SNDRV_PCM_INFO_PAUSE;
+    /// The stream can be resumed after a suspend.
+    pub const RESUME: u32 = crate::bindings::SNDRV_PCM_INFO_RESUME;
+}
+
+/// Sample rates, the kernel's `SNDRV_PCM_RATE_*` values.
+pub mod rates {
+    /// 8 kHz.
+    pub const RATE_8000: u32 = crate::bindings::SNDRV_PCM_RATE_8000;
+    /// 11.025 kHz.
+    pub const RATE_11025: u32 = crate::bindings::SNDRV_PCM_RATE_11025;
+    /// 16 kHz.
+    pub const RATE_16000: u32 = crate::bindings::SNDRV_PCM_RATE_16000;
+    /// 22.05 kHz.
+    pub const RATE_22050: u32 = crate::bindings::SNDRV_PCM_RATE_22050;
+    /// 32 kHz.
+    pub const RATE_32000: u32 = crate::bindings::SNDRV_PCM_RATE_32000;
+    /// 44.1 kHz.
+    pub const RATE_44100: u32 = crate::bindings::SNDRV_PCM_RATE_44100;
+    /// 48 kHz.
+    pub const RATE_48000: u32 = crate::bindings::SNDRV_PCM_RATE_48000;
+    /// 88.2 kHz.
+    pub const RATE_88200: u32 = crate::bindings::SNDRV_PCM_RATE_88200;
+    /// 96 kHz.
+    pub const RATE_96000: u32 = crate::bindings::SNDRV_PCM_RATE_96000;
+    /// 192 kHz.
+    pub const RATE_192000: u32 = crate::bindings::SNDRV_PCM_RATE_192000;
+    /// Any rate between the minimum and maximum rates.
+    pub const CONTINUOUS: u32 = crate::bindings::SNDRV_PCM_RATE_CONTINUOUS;
+    /// The standard rates from 8 kHz to 48 kHz.
+    pub const RATE_8000_48000: u32 = crate::bindings::SNDRV_PCM_RATE_8000_48000;
+    /// The standard rates from 8 kHz to 192 kHz.
+    pub const RATE_8000_192000: u32 = crate::bindings::SNDRV_PCM_RATE_8000_192000;
+}
+
+/// A sample format, the kernel's `SNDRV_PCM_FORMAT_*` values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Signed 8-bit.
+    S8 = 0,
+    /// Unsigned 8-bit.
+    U8 = 1,
+    /// Signed 16-bit, little-endian.
+    S16Le = 2,
+    /// Signed 16-bit, big-endian.
+    S16Be = 3,
+    /// Signed 24-bit in 32 bits, little-endian.
+    S24Le = 6,
+    /// Signed 32-bit, little-endian.
+    S32Le = 10,
+    /// 32-bit floating point, little-endian.
+    FloatLe = 14,
+    /// Signed 24-bit in 3 bytes, little-endian.
+    S24_3Le = 32,
+}
+
+impl Format {
+    /// Returns the bit of the format, to build [`Hardware::formats`].
+    pub const fn bit(self) -> u64 {
+        1 << self as u32
+    }
+
+    fn from_raw(format: c_int) -> Option<Self> {
+        Some(match format {
+            0 => Self::S8,
+            1 => Self::U8,
+            2 => Self::S16Le,
+            3 => Self::S16Be,
+            6 => Self::S24Le,
+            10 => Self::S32Le,
+            14 => Self::FloatLe,
+            32 => Self::S24_3Le,
+            _ => return None,
+        })
+    }
+}
+
+/// The direction of a substream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// The substream plays samples.
+    Playback,
+    /// The substream captures samples.
+    Capture,
+}
+
+impl Direction {
+    pub(crate) fn from_raw(stream: c_int) -> Self {
+        if stream == bindings::SNDRV_PCM_STREAM_CAPTURE as c_int {
+            Self::Capture
+        } else {
+            Self::Playback
+        }
+    }
+
+    pub(crate) fn as_raw(self) -> c_int {
+        match self {
+            Self::Playback => bindings::SNDRV_PCM_STREAM_PLAYBACK as _,
+            Self::Capture => bindings::SNDRV_PCM_STREAM_CAPTURE as _,
+        }
+    }
+}
+
+/// A command passed to [`Operations::trigger`], the kernel's `SNDRV_PCM_TRIGGER_*` values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerCommand {
+    /// Starts the stream.
+    Start,
+    /// Stops the stream.
+    Stop,
+    /// Pauses the stream.
+    PausePush,
+    /// Resumes the stream after a pause.
+    PauseRelease,
+    /// Stops the stream because the system is suspending.
+    Suspend,
+    /// Restarts the stream after the system resumed.
+    Resume,
+}
+
+impl TriggerCommand {
+    fn from_raw(cmd: c_int) -> Option<Self> {
+        Some(match cmd as u32 {
+            bindings::SNDRV_PCM_TRIGGER_START => Self::Start,
+            bindings::SNDRV_PCM_TRIGGER_STOP => Self::Stop,
+            bindings::SNDRV_PCM_TRIGGER_PAUSE_PUSH => Self::PausePush,
+            bindings::SNDRV_PCM_TRIGGER_PAUSE_RELEASE => Self::PauseRelease,
+            bindings::SNDRV_PCM_TRIGGER_SUSPEND => Self::Suspend,
+            bindings::SNDRV_PCM_TRIGGER_RESUME => Self::Resume,
+            _ => return None,
+        })
+    }
+}
+
+/// The capabilities of a substream, the kernel's `struct snd_pcm_hardware`.
+///
+/// They are set by [`Operations::open`], and restrict the parameters that user space can choose.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Hardware {
+    /// The capabilities of the substream, see [`info`].
+    pub info: u32,
+    /// The supported formats, as [`Format::bit`]s.
+    pub formats: u64,
+    /// The supported rates, see [`rates`].
+    pub rates: u32,
+    /// The minimum rate, in Hz.
+    pub rate_min: u32,
+    /// The maximum rate, in Hz.
+    pub rate_max: u32,
+    /// The minimum number of channels.
+    pub channels_min: u32,
+    /// The maximum number of channels.
+    pub channels_max: u32,
+    /// The maximum size of the buffer, in bytes.
+    pub buffer_bytes_max: usize,
+    /// The minimum size of a period, in bytes.
+    pub period_bytes_min: usize,
+    /// The maximum size of a period, in bytes.
+    pub period_bytes_max: usize,
+    /// The minimum number of periods in the buffer.
+    pub periods_min: u32,
+    /// The maximum number of periods in the buffer.
+    pub periods_max: u32,
+}
+
+impl Hardware {
+    fn as_raw(&self) -> bindings::snd_pcm_hardware {
+        bindings::snd_pcm_hardware {
+            info: self.info,
+            formats: self.formats,
+            rates: self.rates,
+            rate_min: self.rate_min,
+            rate_max: self.rate_max,
+            channels_min: self.channels_min,
+            channels_max: self.channels_max,
+            buffer_bytes_max: self.buffer_bytes_max,
+            period_bytes_min: self.period_bytes_min,
+            period_bytes_max: self.period_bytes_max,
+            periods_min: self.periods_min,
+            periods_max: self.periods_max,
+            // SAFETY: All other fields are integers, for which zero is valid.
+            ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+        }
+    }
+}
+
+/// The parameters chosen by user space for a substream, the kernel's `struct snd_pcm_hw_params`.
+#[repr(transparent)]
+pub struct HwParams(Opaque<bindings::snd_pcm_hw_params>);
+
+impl HwParams {
+    fn as_raw(&self) -> *mut bindings::snd_pcm_hw_params {
+        self.0.get()
+    }
+
+    /// Returns the rate, in Hz.
+    pub fn rate(&self) -> u32 {
+        // SAFETY: The parameters are valid while references to them exist.
+        unsafe { bindings::params_rate(self.as_raw()) }
+    }
+
+    /// Returns the number of channels.
+    pub fn channels(&self) -> u32 {
+        // SAFETY: The parameters are valid while references to them exist.
+        unsafe { bindings::params_channels(self.as_raw()) }
+    }
+
+    /// Returns the sample format, or `None` if it isn't one of [`Format`].
+    pub fn format(&self) -> Option<Format> {
+        // SAFETY: The parameters are valid while references to them exist.
+        Format::from_raw(unsafe { bindings::params_format(self.as_raw()) } as _)
+    }
+
+    /// Returns the size of a period, in frames.
+    pub fn period_size(&self) -> usize {
+        // SAFETY: The parameters are valid while references to them exist.
+        unsafe { bindings::params_period_size(self.as_raw()) as _ }
+    }
+
+    /// Returns the number of periods in the buffer.
+    pub fn periods(&self) -> u32 {
+        // SAFETY: The parameters are valid while references to them exist.
+        unsafe { bindings::params_periods(self.as_raw()) }
+    }
+
+    /// Returns the size of the buffer, in bytes.
+    pub fn buffer_bytes(&self) -> usize {
+        // SAFETY: The parameters are valid while references to them exist.
+        unsafe { bindings::params_buffer_bytes(self.as_raw()) as _ }
+    }
+}
+
+/// The operations of the substreams of a PCM device, the kernel's `struct snd_pcm_ops`.
+///
+/// The driver data of the PCM device implements this trait. The callbacks other than
+/// [`Operations::trigger`] and [`Operations::pointer`] may sleep.
+#[vtable]
+pub trait Operations: Send + Sync + Sized + 'static {
+    /// Opens a substream, and sets its capabilities with [`Substream::set_hardware`].
+    fn open(substream: &Substream<Self>) -> Result;
+
+    /// Closes a substream.
+    fn close(_substream: &Substream<Self>) {}
+
+    /// Configures the hardware for the parameters chosen by user space.
+    ///
+    /// The buffer is allocated by the ALSA core before this is called, if it is managed.
+    fn hw_params(_substream: &Substream<Self>, _params: &HwParams) -> Result {
+        Ok(())
+    }
+
+    /// Releases what [`Operations::hw_params`] set up.
+    fn hw_free(_substream: &Substream<Self>) -> Result {
+        Ok(())
+    }
+
+    /// Prepares the substream to start, e.g. after an underrun.
+    fn prepare(_substream: &Substream<Self>) -> Result {
+        Ok(())
+    }
+
+    /// Starts, stops, pauses or resumes the substream.
+    ///
+    /// This is called in atomic context.
+    fn trigger(substream: &Substream<Self>, cmd: TriggerCommand) -> Result;
+
+    /// Returns the position of the hardware in the buffer, in frames.
+    ///
+    /// This is called in atomic context.
+    fn pointer(substream: &Substream<Self>) -> usize;
+}
+
+/// The kind of memory of a managed buffer.
+pub enum BufferKind<'a> {
+    /// Memory that `dev` accesses with DMA.
+    Dma(&'a Device),
+    /// Memory that isn't accessed with DMA, e.g. by drivers that copy samples to the hardware.
+    Vmalloc,
+}
+
+/// A PCM device, the kernel's `struct snd_pcm`.
+///
+/// PCM devices belong to their card, and are freed with it.
+///
+/// # Invariants
+///
+/// The PCM device is valid while references to it exist, and its `private_data` holds its driver
+/// data, a `Box<T>` that is freed with the device.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, device::Device, sound::{pcm, Card}};
+/// use kernel::sound::pcm::{Format, Hardware, Operations, Substream, TriggerCommand};
+/// # use kernel::prelude::*;
+///
+/// struct Dac;
+///
+/// const HW: Hardware = Hardware {
+///     info: pcm::info::MMAP | pcm::info::MMAP_VALID | pcm::info::INTERLEAVED,
+///     formats: Format::S16Le.bit(),
+///     rates: pcm::rates::RATE_48000,
+///     rate_min: 48000,
+///     rate_max: 48000,
+///     channels_min: 2,
+///     channels_max: 2,
+///     buffer_bytes_max: 64 * 1024,
+///     period_bytes_min: 1024,
+///     period_bytes_max: 16 * 1024,
+///     periods_min: 2,
+///     periods_max: 64,
+/// };
+///
+/// #[vtable]
+/// impl Operations for Dac {
+///     fn open(substream: &Substream<Self>) -> Result {
+///         substream.set_hardware(&HW);
+///         Ok(())
+///     }
+///
+///     fn trigger(_substream: &Substream<Self>, cmd: TriggerCommand) -> Result {
+///         match cmd {
+///             TriggerCommand::Start | TriggerCommand::Stop => Ok(()),
+///             _ => Err(EINVAL),
+///         }
+///     }
+///
+///     fn pointer(_substream: &Substream<Self>) -> usize {
+///         0
+///     }
+/// }
+///
+/// fn add_pcm(card: &Card, dev: &Device) -> Result {
+///     let pcm = pcm::Pcm::new(card, c_str!("Rust DAC"), 0, 1, 0, Dac)?;
+///     pcm.set_managed_buffer(pcm::BufferKind::Dma(dev), 64 * 1024, 64 * 1024)
+/// }
+/// ```
+#[repr(transparent)]
+pub struct Pcm<T: Operations>(Opaque<bindings::snd_pcm>, PhantomData<T>);
+
+impl<T: Operations> Pcm<T> {
+    const OPS: bindings::snd_pcm_ops = bindings::snd_pcm_ops {
+        open: Some(Self::open_callback),
+        close: Some(Self::close_callback),
+        hw_params: if T::HAS_HW_PARAMS {
+            Some(Self::hw_params_callback)
+        } else {
+            None
+        },
+        hw_free: if T::HAS_HW_FREE {
+            Some(Self::hw_free_callback)
+        } else {
+            None
+        },
+        prepare: if T::HAS_PREPARE {
+            Some(Self::prepare_callback)
+        } else {
+            None
+        },
+        trigger: Some(Self::trigger_callback),
+        pointer: Some(Self::pointer_callback),
+        // SAFETY: All other fields are optional, for which zero is valid.
+        ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+    };
+
+    /// Creates a PCM device of `card` with the given number of playback and capture substreams.
+    ///
+    /// `device` is the index of the PCM device in the card, the `D` in `/dev/snd/pcmCxDy`. The
+    /// device is registered with the card.
+    pub fn new<'a>(
+        card: &'a Card,
+        name: &CStr,
+        device: i32,
+        playback_count: i32,
+        capture_count: i32,
+        data: T,
+    ) -> Result<&'a Self> {
+        crate::might_sleep!();
+        let data = Box::try_new(data)?;
+        let mut pcm = ptr::null_mut();
+        // SAFETY: The card is valid, and the name is copied by the ALSA core.
+        to_result(unsafe {
+            bindings::snd_pcm_new(
+                card.as_raw(),
+                name.as_char_ptr(),
+                device,
+                playback_count,
+                capture_count,
+                &mut pcm,
+            )
+        })?;
+        // SAFETY: The PCM device was just created, and can't be opened until the card is
+        // registered. The driver data is freed by `private_free_callback`.
+        unsafe {
+            copy_name(&mut (*pcm).name, name);
+            (*pcm).private_data = Box::into_raw(data).cast();
+            (*pcm).private_free = Some(Self::private_free_callback);
+            if playback_count > 0 {
+                bindings::snd_pcm_set_ops(pcm, Direction::Playback.as_raw(), &Self::OPS);
+            }
+            if capture_count > 0 {
+                bindings::snd_pcm_set_ops(pcm, Direction::Capture.as_raw(), &Self::OPS);
+            }
+        }
+        // INVARIANT: The driver data was set above. The PCM device is freed with the card.
+        // SAFETY: `pcm` is valid for the lifetime of the card.
+        Ok(unsafe { &*pcm.cast() })
+    }
+
+    fn as_raw(&self) -> *mut bindings::snd_pcm {
+        self.0.get()
+    }
+
+    /// Returns the driver data of the PCM device.
+    pub fn data(&self) -> &T {
+        // SAFETY: `private_data` holds the driver data by the type invariants.
+        unsafe { &*(*self.as_raw()).private_data.cast::<T>() }
+    }
+
+    /// Makes the ALSA core allocate the buffers of all the substreams, when their parameters are
+    /// set, with the given default and maximum size in bytes.
+    pub fn set_managed_buffer(&self, kind: BufferKind<'_>, size: usize, max: usize) -> Result {
+        let (ty, dev) = match kind {
+            BufferKind::Dma(dev) => (bindings::SNDRV_DMA_TYPE_DEV, dev.as_raw()),
+            BufferKind::Vmalloc => (bindings::SNDRV_DMA_TYPE_VMALLOC, ptr::null_mut()),
+        };
+        // SAFETY: The PCM device is valid, and `dev` outlives it since it is a parent of the card.
+        to_result(unsafe {
+            bindings::snd_pcm_set_managed_buffer_all(self.as_raw(), ty as _, dev, size, max)
+        })
+    }
+
+    unsafe extern "C" fn private_free_callback(pcm: *mut bindings::snd_pcm) {
+        // SAFETY: The ALSA core calls this when the PCM device is freed, so the driver data isn't
+        // used anymore.
+        drop(unsafe { Box::from_raw((*pcm).private_data.cast::<T>()) });
+    }
+
+    unsafe extern "C" fn open_callback(substream: *mut bindings::snd_pcm_substream) -> c_int {
+        from_result(|| {
+            // SAFETY: The ALSA core calls this with a substream of a PCM device of `T`.
+            T::open(unsafe { Substream::from_raw(substream) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn close_callback(substream: *mut bindings::snd_pcm_substream) -> c_int {
+        // SAFETY: The ALSA core calls this with a substream of a PCM device of `T`.
+        T::close(unsafe { Substream::from_raw(substream) });
+        0
+    }
+
+    unsafe extern "C" fn hw_params_callback(
+        substream: *mut bindings::snd_pcm_substream,
+        params: *mut bindings::snd_pcm_hw_params,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The ALSA core calls this with a substream of a PCM device of `T` and valid
+            // parameters. `HwParams` is transparent.
+            let (substream, params) =
+                unsafe { (Substream::from_raw(substream), &*params.cast::<HwParams>()) };
+            T::hw_params(substream, params)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn hw_free_callback(substream: *mut bindings::snd_pcm_substream) -> c_int {
+        from_result(|| {
+            // SAFETY: The ALSA core calls this with a substream of a PCM device of `T`.
+            T::hw_free(unsafe { Substream::from_raw(substream) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn prepare_callback(substream: *mut bindings::snd_pcm_substream) -> c_int {
+        from_result(|| {
+            // SAFETY: The ALSA core calls this with a substream of a PCM device of `T`.
+            T::prepare(unsafe { Substream::from_raw(substream) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn trigger_callback(
+        substream: *mut bindings::snd_pcm_substream,
+        cmd: c_int,
+    ) -> c_int {
+        from_result(|| {
+            let cmd = TriggerCommand::from_raw(cmd).ok_or(EINVAL)?;
+            // SAFETY: The ALSA core calls this with a substream of a PCM device of `T`.
+            T::trigger(unsafe { Substream::from_raw(substream) }, cmd)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn pointer_callback(
+        substream: *mut bindings::snd_pcm_substream,
+    ) -> bindings::snd_pcm_uframes_t {
+        // SAFETY: The ALSA core calls this with a substream of a PCM device of `T`.
+        T::pointer(unsafe { Substream::from_raw(substream) }) as _
+    }
+}
+
+// SAFETY: The PCM device is freed with its card, from any thread, and the driver data is `Send`.
+unsafe impl<T: Operations> Send for Pcm<T> {}
+
+// SAFETY: The PCM device is only accessed through functions with their own synchronisation, and
+// the driver data is `Sync`.
+unsafe impl<T: Operations> Sync for Pcm<T> {}
+
+/// An open substream of a PCM device of `T`, the kernel's `struct snd_pcm_substream`.
+///
+/// # Invariants
+///
+/// The substream is open while references to it exist, and belongs to a PCM device of `T`.
+#[repr(transparent)]
+pub struct Substream<T: Operations>(Opaque<bindings::snd_pcm_substream>, PhantomData<T>);
+
+impl<T: Operations> Substream<T> {
+    /// Creates a reference to a [`Substream`] from a valid pointer.
+    ///
+    /// This lets drivers access the substream outside of its callbacks, e.g. to call
+    /// [`Substream::period_elapsed`] from their interrupt handler.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is an open substream of a PCM device of `T` for the lifetime
+    /// of the returned reference, e.g. by only using it between the start and the stop of the
+    /// substream.
+    pub unsafe fn from_raw<'a>(ptr: *mut bindings::snd_pcm_substream) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct snd_pcm_substream` pointer.
+    pub fn as_raw(&self) -> *mut bindings::snd_pcm_substream {
+        self.0.get()
+    }
+
+    fn runtime(&self) -> *mut bindings::snd_pcm_runtime {
+        // SAFETY: The substream is open by the type invariants, so it has a runtime.
+        unsafe { (*self.as_raw()).runtime }
+    }
+
+    /// Returns the driver data of the PCM device of the substream.
+    pub fn pcm_data(&self) -> &T {
+        // SAFETY: The substream belongs to a PCM device of `T` by the type invariants, which
+        // outlives it.
+        unsafe { Pcm::<T>::data(&*(*self.as_raw()).pcm.cast()) }
+    }
+
+    /// Returns the direction of the substream.
+    pub fn direction(&self) -> Direction {
+        // SAFETY: The substream is valid by the type invariants, and its direction never
+        // changes.
+        Direction::from_raw(unsafe { (*self.as_raw()).stream })
+    }
+
+    /// Returns the index of the substream in its direction.
+    pub fn number(&self) -> i32 {
+        // SAFETY: The substream is valid by the type invariants, and its number never changes.
+        unsafe { (*self.as_raw()).number }
+    }
+
+    /// Sets the capabilities of the substream, from [`Operations::open`].
+    pub fn set_hardware(&self, hw: &Hardware) {
+        // SAFETY: The runtime is valid while the substream is open. The capabilities are only
+        // used by the ALSA core once `open` returns.
+        unsafe { (*self.runtime()).hw = hw.as_raw() };
+    }
+
+    /// Returns the DMA address of the buffer.
+    pub fn dma_addr(&self) -> u64 {
+        // SAFETY: The runtime is valid while the substream is open.
+        unsafe { (*self.runtime()).dma_addr as _ }
+    }
+
+    /// Returns the size of the buffer, in bytes.
+    pub fn buffer_bytes(&self) -> usize {
+        // SAFETY: The runtime is valid while the substream is open.
+        unsafe { (*self.runtime()).dma_bytes }
+    }
+
+    /// Returns the size of the buffer, in frames.
+    pub fn buffer_size(&self) -> usize {
+        // SAFETY: The runtime is valid while the substream is open.
+        unsafe { (*self.runtime()).buffer_size as _ }
+    }
+
+    /// Returns the size of a period, in frames.
+    pub fn period_size(&self) -> usize {
+        // SAFETY: The runtime is valid while the substream is open.
+        unsafe { (*self.runtime()).period_size as _ }
+    }
+
+    /// Converts a number of bytes in the buffer to a number of frames.
+    pub fn bytes_to_frames(&self, bytes: usize) -> usize {
+        // SAFETY: The runtime is valid while the substream is open.
+        unsafe { bindings::bytes_to_frames(self.runtime(), bytes as _) as _ }
+    }
+
+    /// Converts a number of frames to a number of bytes in the buffer.
+    pub fn frames_to_bytes(&self, frames: usize) -> usize {
+        // SAFETY: The runtime is valid while the substream is open.
+        unsafe { bindings::frames_to_bytes(self.runtime(), frames as _) as _ }
+    }
+
+    /// Copies `data` to the buffer at `offset`, e.g. to feed hardware without DMA.
+    ///
+    /// Fails with `EINVAL` if the range is out of bounds or the buffer isn't allocated.
+    pub fn write_buffer(&self, offset: usize, data: &[u8]) -> Result {
+        let area = self.buffer_area(offset, data.len())?;
+        // SAFETY: The range is within the buffer, which doesn't overlap with `data`.
+        unsafe { ptr::copy_nonoverlapping(data.as_ptr(), area, data.len()) };
+        Ok(())
+    }
+
+    /// Copies the buffer at `offset` to `data`, e.g. to drain hardware without DMA.
+    ///
+    /// Fails with `EINVAL` if the range is out of bounds or the buffer isn't allocated.
+    pub fn read_buffer(&self, offset: usize, data: &mut [u8]) -> Result {
+        let area = self.buffer_area(offset, data.len())?;
+        // SAFETY: The range is within the buffer, which doesn't overlap with `data`.
+        unsafe { ptr::copy_nonoverlapping(area, data.as_mut_ptr(), data.len()) };
+        Ok(())
+    }
+
+    fn buffer_area(&self, offset: usize, len: usize) -> Result<*mut u8> {
+        // SAFETY: The runtime is valid while the substream is open.
+        let (area, bytes) = unsafe { ((*self.runtime()).dma_area, (*self.runtime()).dma_bytes) };
+        let end = offset.checked_add(len).ok_or(EINVAL)?;
+        if area.is_null() || end > bytes {
+            return Err(EINVAL);
+        }
+        // SAFETY: `offset` is within the buffer, checked above.
+        Ok(unsafe { area.cast::<u8>().add(offset) })
+    }
+
+    /// Tells the ALSA core that the hardware went past a period boundary.
+    ///
+    /// This may be called from any context, including interrupt handlers.
+    pub fn period_elapsed(&self) {
+        // SAFETY: The substream is open by the type invariants.
+        unsafe { bindings::snd_pcm_period_elapsed(self.as_raw()) };
+    }
+}
+
+// SAFETY: Substreams are synchronised by the ALSA core, and the driver data is `Send`.
+unsafe impl<T: Operations> Send for Substream<T> {}
+
+// SAFETY: The methods that take `&self` are either safe to call concurrently or only read the
+// runtime, and the driver data is `Sync`.
+unsafe impl<T: Operations> Sync for Substream<T> {}
@@ -0,0 +1,621 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! ASoC components, such as audio codecs.
+//!
+//! A component ([`Component`]) has digital audio interfaces ([`Dai`]), which machine drivers link
+//! to the DAIs of other components, and audio paths described with DAPM ([`dapm`]).
+//!
+//! The registers of a component are accessed through [`Operations::read`] and
+//! [`Operations::write`] if the component implements them, and otherwise through the regmap of its
+//! device, e.g. the one of an I2C codec.
+//!
+//! C header: [`include/sound/soc.h`](../../../../include/sound/soc.h)
+
+use crate::{
+    bindings,
+    device::Device,
+    error::{code::*, from_result, to_result, Result},
+    sound::pcm::{Direction, HwParams},
+    str::CStr,
+    types::{ARef, Opaque},
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    ffi::{c_int, c_uint},
+    marker::{PhantomData, PhantomPinned},
+    mem::MaybeUninit,
+    pin::Pin,
+};
+use macros::vtable;
+
+pub mod dapm;
+
+/// The format of a DAI, the kernel's `SND_SOC_DAIFMT_*` values.
+///
+/// The format passed to [`Operations::set_fmt`] combines a value of each mask.
+pub mod dai_fmt {
+    /// The mask of the frame format.
+    pub const FORMAT_MASK: u32 = crate::bindings::SND_SOC_DAIFMT_FORMAT_MASK;
+    /// I2S.
+    pub const I2S: u32 = crate::bindings::SND_SOC_DAIFMT_I2S;
+    /// Left-justified.
+    pub const LEFT_J: u32 = crate::bindings::SND_SOC_DAIFMT_LEFT_J;
+    /// Right-justified.
+    pub const RIGHT_J: u32 = crate::bindings::SND_SOC_DAIFMT_RIGHT_J;
+    /// DSP mode A, with the data one bit clock after the frame sync.
+    pub const DSP_A: u32 = crate::bindings::SND_SOC_DAIFMT_DSP_A;
+    /// DSP mode B, with the data at the frame sync.
+    pub const DSP_B: u32 = crate::bindings::SND_SOC_DAIFMT_DSP_B;
+
+    /// The mask of the clock polarities.
+    pub const INV_MASK: u32 = crate::bindings::SND_SOC_DAIFMT_INV_MASK;
+    /// Normal bit clock and frame sync.
+    pub const NB_NF: u32 = crate::bindings::SND_SOC_DAIFMT_NB_NF;
+    /// Normal bit clock, inverted frame sync.
+    pub const NB_IF: u32 = crate::bindings::SND_SOC_DAIFMT_NB_IF;
+    /// Inverted bit clock, normal frame sync.
+    pub const IB_NF: u32 = crate::bindings::SND_SOC_DAIFMT_IB_NF;
+    /// Inverted bit clock and frame sync.
+    pub const IB_IF: u32 = crate::bindings::SND_SOC_DAIFMT_IB_IF;
+
+    /// The mask of the clock providers.
+    pub const CLOCK_PROVIDER_MASK: u32 = crate::bindings::SND_SOC_DAIFMT_CLOCK_PROVIDER_MASK;
+    /// The component provides the bit clock and the frame sync.
+    pub const CBP_CFP: u32 = crate::bindings::SND_SOC_DAIFMT_CBP_CFP;
+    /// The component provides the bit clock, but not the frame sync.
+    pub const CBP_CFC: u32 = crate::bindings::SND_SOC_DAIFMT_CBP_CFC;
+    /// The component provides the frame sync, but not the bit clock.
+    pub const CBC_CFP: u32 = crate::bindings::SND_SOC_DAIFMT_CBC_CFP;
+    /// The component provides neither the bit clock nor the frame sync.
+    pub const CBC_CFC: u32 = crate::bindings::SND_SOC_DAIFMT_CBC_CFC;
+}
+
+/// The bias level of a component, the kernel's `enum snd_soc_bias_level`.
+///
+/// The levels are ordered, from the lowest power to the highest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BiasLevel {
+    /// The component is powered off.
+    Off,
+    /// The component is idle, with the minimum power to keep its state.
+    Standby,
+    /// The component is about to be used, or just was.
+    Prepare,
+    /// The component is used.
+    On,
+}
+
+impl BiasLevel {
+    fn from_raw(level: bindings::snd_soc_bias_level) -> Self {
+        match level {
+            bindings::snd_soc_bias_level_SND_SOC_BIAS_STANDBY => Self::Standby,
+            bindings::snd_soc_bias_level_SND_SOC_BIAS_PREPARE => Self::Prepare,
+            bindings::snd_soc_bias_level_SND_SOC_BIAS_ON => Self::On,
+            _ => Self::Off,
+        }
+    }
+}
+
+/// A stream of a DAI, the kernel's `struct snd_soc_pcm_stream`.
+pub struct PcmStream {
+    /// The name of the stream, which DAPM widgets refer to.
+    pub name: &'static CStr,
+    /// The supported formats, as [`crate::sound::pcm::Format::bit`]s.
+    pub formats: u64,
+    /// The supported rates, see [`crate::sound::pcm::rates`].
+    pub rates: u32,
+    /// The minimum number of channels.
+    pub channels_min: u32,
+    /// The maximum number of channels.
+    pub channels_max: u32,
+}
+
+impl PcmStream {
+    fn to_raw(stream: &Option<Self>) -> bindings::snd_soc_pcm_stream {
+        // SAFETY: All fields are optional, for which zero is valid, and a zeroed stream has no
+        // channels, which means that the DAI doesn't support the direction.
+        let mut raw: bindings::snd_soc_pcm_stream = unsafe { MaybeUninit::zeroed().assume_init() };
+        if let Some(stream) = stream {
+            raw.stream_name = stream.name.as_char_ptr();
+            raw.formats = stream.formats;
+            raw.rates = stream.rates;
+            raw.channels_min = stream.channels_min;
+            raw.channels_max = stream.channels_max;
+        }
+        raw
+    }
+}
+
+/// The definition of a DAI of a component, the kernel's `struct snd_soc_dai_driver`.
+pub struct DaiDriver {
+    /// The name of the DAI, which machine drivers refer to.
+    pub name: &'static CStr,
+    /// The identifier of the DAI, returned by [`Dai::id`].
+    pub id: u32,
+    /// The playback stream of the DAI, if any.
+    pub playback: Option<PcmStream>,
+    /// The capture stream of the DAI, if any.
+    pub capture: Option<PcmStream>,
+}
+
+/// The operations of a component and its DAIs, the kernel's `struct snd_soc_component_driver`
+/// and `struct snd_soc_dai_ops`.
+///
+/// The driver data of the component implements this trait.
+#[vtable]
+pub trait Operations: Send + Sync + Sized + 'static {
+    /// The name of the component.
+    const NAME: &'static CStr;
+
+    /// The DAIs of the component.
+    const DAIS: &'static [DaiDriver];
+
+    /// The DAPM widgets of the component, declared with [`crate::declare_dapm_widgets`].
+    const WIDGETS: &'static [dapm::Widget] = &[];
+
+    /// The DAPM routes between the widgets, declared with [`crate::declare_dapm_routes`].
+    const ROUTES: &'static [dapm::Route] = &[];
+
+    /// Keeps the bias level at [`BiasLevel::Standby`] when the component is idle, instead of
+    /// [`BiasLevel::Off`], e.g. for components that take long to power up.
+    const IDLE_BIAS_ON: bool = false;
+
+    /// Called when a sound card with the component is created.
+    fn probe(_component: &Component<Self>) -> Result {
+        Ok(())
+    }
+
+    /// Called when a sound card with the component is removed.
+    fn remove(_component: &Component<Self>) {}
+
+    /// Reads the register `reg`.
+    fn read(_component: &Component<Self>, _reg: u32) -> u32 {
+        0
+    }
+
+    /// Writes `value` to the register `reg`.
+    fn write(_component: &Component<Self>, _reg: u32, _value: u32) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Changes the bias level of the component.
+    ///
+    /// The ASoC core tracks the level, so this only needs to program the hardware.
+    fn set_bias_level(_component: &Component<Self>, _level: BiasLevel) -> Result {
+        Ok(())
+    }
+
+    /// Sets the format of `dai`, a combination of [`dai_fmt`] values.
+    fn set_fmt(_dai: &Dai<Self>, _fmt: u32) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Sets the frequency of the system clock `clk_id` of `dai`, in Hz.
+    fn set_sysclk(_dai: &Dai<Self>, _clk_id: i32, _freq: u32, _dir: i32) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Configures `dai` for the parameters of a stream in `direction`.
+    fn hw_params(_dai: &Dai<Self>, _direction: Direction, _params: &HwParams) -> Result {
+        Ok(())
+    }
+
+    /// Mutes or unmutes the stream of `dai` in `direction`.
+    fn mute_stream(_dai: &Dai<Self>, _mute: bool, _direction: Direction) -> Result {
+        Ok(())
+    }
+}
+
+/// A registered component, the kernel's `struct snd_soc_component`.
+///
+/// # Invariants
+///
+/// The component is valid while references to it exist, and its driver is the `driver` field of
+/// a `Registration<T>`.
+#[repr(transparent)]
+pub struct Component<T: Operations>(Opaque<bindings::snd_soc_component>, PhantomData<T>);
+
+impl<T: Operations> Component<T> {
+    /// Creates a reference to a [`Component`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is a component registered by a `Registration<T>` for the
+    /// lifetime of the returned reference.
+    unsafe fn from_raw<'a>(ptr: *mut bindings::snd_soc_component) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct snd_soc_component` pointer.
+    pub fn as_raw(&self) -> *mut bindings::snd_soc_component {
+        self.0.get()
+    }
+
+    /// Returns the driver data of the component.
+    pub fn data(&self) -> &T {
+        // SAFETY: The driver of the component is the first field of a `Registration<T>` by the
+        // type invariants, which outlives the component.
+        unsafe { &(*(*self.as_raw()).driver.cast::<Registration<T>>()).data }
+    }
+
+    /// Returns the device of the component.
+    pub fn dev(&self) -> &Device {
+        // SAFETY: The component is valid by the type invariants, and holds its device.
+        unsafe { Device::as_ref((*self.as_raw()).dev) }
+    }
+
+    /// Reads the register `reg`.
+    pub fn read(&self, reg: u32) -> u32 {
+        // SAFETY: The component is valid by the type invariants.
+        unsafe { bindings::snd_soc_component_read(self.as_raw(), reg) }
+    }
+
+    /// Writes `value` to the register `reg`.
+    pub fn write(&self, reg: u32, value: u32) -> Result {
+        // SAFETY: The component is valid by the type invariants.
+        to_result(unsafe { bindings::snd_soc_component_write(self.as_raw(), reg, value) })
+    }
+
+    /// Replaces the bits in `mask` of the register `reg` with the ones of `value`.
+    ///
+    /// Returns whether the register changed.
+    pub fn update_bits(&self, reg: u32, mask: u32, value: u32) -> Result<bool> {
+        // SAFETY: The component is valid by the type invariants.
+        let ret =
+            unsafe { bindings::snd_soc_component_update_bits(self.as_raw(), reg, mask, value) };
+        to_result(ret)?;
+        Ok(ret > 0)
+    }
+
+    /// Returns the current bias level of the component.
+    pub fn bias_level(&self) -> BiasLevel {
+        // SAFETY: The component is valid by the type invariants.
+        BiasLevel::from_raw(unsafe { (*self.as_raw()).dapm.bias_level })
+    }
+}
+
+/// A DAI of a component, the kernel's `struct snd_soc_dai`.
+///
+/// # Invariants
+///
+/// The DAI is valid while references to it exist, and belongs to a component registered by a
+/// `Registration<T>`.
+#[repr(transparent)]
+pub struct Dai<T: Operations>(Opaque<bindings::snd_soc_dai>, PhantomData<T>);
+
+impl<T: Operations> Dai<T> {
+    /// Creates a reference to a [`Dai`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is a DAI of a component registered by a `Registration<T>`
+    /// for the lifetime of the returned reference.
+    unsafe fn from_raw<'a>(ptr: *mut bindings::snd_soc_dai) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct snd_soc_dai` pointer.
+    pub fn as_raw(&self) -> *mut bindings::snd_soc_dai {
+        self.0.get()
+    }
+
+    /// Returns the identifier of the DAI, [`DaiDriver::id`].
+    pub fn id(&self) -> u32 {
+        // SAFETY: The DAI is valid by the type invariants, and its identifier never changes.
+        unsafe { (*self.as_raw()).id as _ }
+    }
+
+    /// Returns the component of the DAI.
+    pub fn component(&self) -> &Component<T> {
+        // SAFETY: The DAI belongs to a component registered by a `Registration<T>` by the type
+        // invariants, which outlives it.
+        unsafe { Component::from_raw((*self.as_raw()).component) }
+    }
+
+    /// Returns the driver data of the component of the DAI.
+    pub fn data(&self) -> &T {
+        self.component().data()
+    }
+}
+
+/// The registration of a component with its DAIs.
+///
+/// The component is unregistered when this is dropped, which removes the sound cards that use it.
+///
+/// # Invariants
+///
+/// `dais` holds the definitions of [`Operations::DAIS`], and the component is registered with
+/// `driver` and `dais` if `registered` is `true`.
+///
+/// # Examples
+///
+/// ```ignore
+/// use kernel::{c_str, device::Device, prelude::*};
+/// use kernel::sound::{pcm, soc};
+///
+/// const REG_POWER: i32 = 0x02;
+/// const REG_DAC: u32 = 0x06;
+///
+/// struct Codec;
+///
+/// #[vtable]
+/// impl soc::Operations for Codec {
+///     const NAME: &'static CStr = c_str!("rust-codec");
+///     const DAIS: &'static [soc::DaiDriver] = &[soc::DaiDriver {
+///         name: c_str!("rust-codec-hifi"),
+///         id: 0,
+///         playback: Some(soc::PcmStream {
+///             name: c_str!("Playback"),
+///             formats: pcm::Format::S16Le.bit() | pcm::Format::S24Le.bit(),
+///             rates: pcm::rates::RATE_8000_48000,
+///             channels_min: 2,
+///             channels_max: 2,
+///         }),
+///         capture: None,
+///     }];
+///
+///     kernel::declare_dapm_widgets! {
+///         dac("DAC", "Playback", REG_POWER, 3, false),
+///         output("HPOUT"),
+///     }
+///
+///     kernel::declare_dapm_routes! {
+///         ("HPOUT", "DAC"),
+///     }
+///
+///     fn mute_stream(dai: &soc::Dai<Self>, mute: bool, _dir: pcm::Direction) -> Result {
+///         dai.component().update_bits(REG_DAC, 1 << 3, (mute as u32) << 3)?;
+///         Ok(())
+///     }
+/// }
+///
+/// fn probe(dev: &Device) -> Result<Pin<Box<soc::Registration<Codec>>>> {
+///     soc::Registration::register(dev, Codec)
+/// }
+/// ```
+#[repr(C)]
+pub struct Registration<T: Operations> {
+    // Must be the first field, so that components can find the driver data from their driver.
+    driver: bindings::snd_soc_component_driver,
+    dais: Vec<bindings::snd_soc_dai_driver>,
+    dev: ARef<Device>,
+    registered: bool,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: Operations> Registration<T> {
+    const DAI_OPS: bindings::snd_soc_dai_ops = bindings::snd_soc_dai_ops {
+        set_fmt: if T::HAS_SET_FMT {
+            Some(Self::set_fmt_callback)
+        } else {
+            None
+        },
+        set_sysclk: if T::HAS_SET_SYSCLK {
+            Some(Self::set_sysclk_callback)
+        } else {
+            None
+        },
+        hw_params: if T::HAS_HW_PARAMS {
+            Some(Self::hw_params_callback)
+        } else {
+            None
+        },
+        mute_stream: if T::HAS_MUTE_STREAM {
+            Some(Self::mute_stream_callback)
+        } else {
+            None
+        },
+        // SAFETY: All other fields are optional, for which zero is valid.
+        ..unsafe { MaybeUninit::zeroed().assume_init() }
+    };
+
+    /// Registers a component of `dev` with the driver data `data`.
+    pub fn register(dev: &Device, data: T) -> Result<Pin<Box<Self>>> {
+        crate::might_sleep!();
+        let mut dais = Vec::try_with_capacity(T::DAIS.len())?;
+        for dai in T::DAIS {
+            // SAFETY: All other fields are optional, for which zero is valid.
+            let mut raw: bindings::snd_soc_dai_driver =
+                unsafe { MaybeUninit::zeroed().assume_init() };
+            raw.name = dai.name.as_char_ptr();
+            raw.id = dai.id;
+            raw.ops = &Self::DAI_OPS;
+            raw.playback = PcmStream::to_raw(&dai.playback);
+            raw.capture = PcmStream::to_raw(&dai.capture);
+            dais.try_push(raw)?;
+        }
+
+        // SAFETY: All other fields are optional, for which zero is valid.
+        let mut driver: bindings::snd_soc_component_driver =
+            unsafe { MaybeUninit::zeroed().assume_init() };
+        driver.name = T::NAME.as_char_ptr();
+        driver.probe = if T::HAS_PROBE {
+            Some(Self::probe_callback)
+        } else {
+            None
+        };
+        driver.remove = if T::HAS_REMOVE {
+            Some(Self::remove_callback)
+        } else {
+            None
+        };
+        driver.read = if T::HAS_READ {
+            Some(Self::read_callback)
+        } else {
+            None
+        };
+        driver.write = if T::HAS_WRITE {
+            Some(Self::write_callback)
+        } else {
+            None
+        };
+        driver.set_bias_level = if T::HAS_SET_BIAS_LEVEL {
+            Some(Self::set_bias_level_callback)
+        } else {
+            None
+        };
+        driver.dapm_widgets = T::WIDGETS.as_ptr().cast();
+        driver.num_dapm_widgets = T::WIDGETS.len() as _;
+        driver.dapm_routes = T::ROUTES.as_ptr().cast();
+        driver.num_dapm_routes = T::ROUTES.len() as _;
+        driver.set_idle_bias_on(T::IDLE_BIAS_ON as _);
+
+        let mut reg = Pin::from(Box::try_new(Self {
+            driver,
+            dais,
+            dev: dev.into(),
+            registered: false,
+            data,
+            _pin: PhantomPinned,
+        })?);
+
+        // SAFETY: `dev` is valid, and the driver and DAIs live in the pinned registration, which
+        // unregisters the component before they are freed.
+        to_result(unsafe {
+            bindings::snd_soc_register_component(
+                dev.as_raw(),
+                &reg.driver,
+                reg.dais.as_ptr() as *mut _,
+                reg.dais.len() as _,
+            )
+        })?;
+        // INVARIANT: The component was registered above.
+        // SAFETY: `registered` isn't structurally pinned.
+        unsafe { reg.as_mut().get_unchecked_mut() }.registered = true;
+        Ok(reg)
+    }
+
+    /// Returns the driver data of the component.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    unsafe extern "C" fn probe_callback(component: *mut bindings::snd_soc_component) -> c_int {
+        from_result(|| {
+            // SAFETY: The ASoC core calls this with a component of `T`.
+            T::probe(unsafe { Component::from_raw(component) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn remove_callback(component: *mut bindings::snd_soc_component) {
+        // SAFETY: The ASoC core calls this with a component of `T`.
+        T::remove(unsafe { Component::from_raw(component) });
+    }
+
+    unsafe extern "C" fn read_callback(
+        component: *mut bindings::snd_soc_component,
+        reg: c_uint,
+    ) -> c_uint {
+        // SAFETY: The ASoC core calls this with a component of `T`.
+        T::read(unsafe { Component::from_raw(component) }, reg)
+    }
+
+    unsafe extern "C" fn write_callback(
+        component: *mut bindings::snd_soc_component,
+        reg: c_uint,
+        value: c_uint,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The ASoC core calls this with a component of `T`.
+            T::write(unsafe { Component::from_raw(component) }, reg, value)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn set_bias_level_callback(
+        component: *mut bindings::snd_soc_component,
+        level: bindings::snd_soc_bias_level,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The ASoC core calls this with a component of `T`.
+            let component = unsafe { Component::from_raw(component) };
+            T::set_bias_level(component, BiasLevel::from_raw(level))?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn set_fmt_callback(dai: *mut bindings::snd_soc_dai, fmt: c_uint) -> c_int {
+        from_result(|| {
+            // SAFETY: The ASoC core calls this with a DAI of a component of `T`.
+            T::set_fmt(unsafe { Dai::from_raw(dai) }, fmt)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn set_sysclk_callback(
+        dai: *mut bindings::snd_soc_dai,
+        clk_id: c_int,
+        freq: c_uint,
+        dir: c_int,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The ASoC core calls this with a DAI of a component of `T`.
+            T::set_sysclk(unsafe { Dai::from_raw(dai) }, clk_id, freq, dir)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn hw_params_callback(
+        substream: *mut bindings::snd_pcm_substream,
+        params: *mut bindings::snd_pcm_hw_params,
+        dai: *mut bindings::snd_soc_dai,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The ASoC core calls this with a valid substream and parameters, and a DAI
+            // of a component of `T`. `HwParams` is transparent.
+            let (direction, params, dai) = unsafe {
+                (
+                    Direction::from_raw((*substream).stream),
+                    &*params.cast::<HwParams>(),
+                    Dai::from_raw(dai),
+                )
+            };
+            T::hw_params(dai, direction, params)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn mute_stream_callback(
+        dai: *mut bindings::snd_soc_dai,
+        mute: c_int,
+        stream: c_int,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The ASoC core calls this with a DAI of a component of `T`.
+            let dai = unsafe { Dai::from_raw(dai) };
+            T::mute_stream(dai, mute != 0, Direction::from_raw(stream))?;
+            Ok(0)
+        })
+    }
+}
+
+impl<T: Operations> Drop for Registration<T> {
+    fn drop(&mut self) {
+        if self.registered {
+            // SAFETY: The component was registered with `driver` by the type invariants.
+            unsafe {
+                bindings::snd_soc_unregister_component_by_driver(self.dev.as_raw(), &self.driver)
+            };
+        }
+    }
+}
+
+// SAFETY: The registration only holds pointers to static data, and can be dropped from any
+// thread. The driver data is `Send`.
+unsafe impl<T: Operations> Send for Registration<T> {}
+
+// SAFETY: The registration has no methods that take `&self` other than `data`, and the driver
+// data is `Sync`.
+unsafe impl<T: Operations> Sync for Registration<T> {}
+
+// SAFETY: The component is only accessed through functions with their own synchronisation, and
+// the driver data is `Sync`.
+unsafe impl<T: Operations> Sync for Component<T> {}
+
+// SAFETY: The DAI is only accessed through functions with their own synchronisation, and the
+// driver data is `Sync`.
+unsafe impl<T: Operations> Sync for Dai<T> {}
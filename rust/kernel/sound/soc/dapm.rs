@@ -0,0 +1,246 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Dynamic audio power management (DAPM).
+//!
+//! The audio paths of a component are described by widgets ([`Widget`]), such as inputs, DACs
+//! and amplifiers, connected by routes ([`Route`]). The ASoC core powers the widgets on an active
+//! path up, and the others down, through the register bits given in their declaration.
+//!
+//! The widgets and routes of a component are declared with [`crate::declare_dapm_widgets`] and
+//! [`crate::declare_dapm_routes`].
+//!
+//! C header: [`include/sound/soc-dapm.h`](../../../../include/sound/soc-dapm.h)
+
+use crate::{bindings, str::CStr};
+use core::{mem::MaybeUninit, ptr};
+
+/// The register of widgets without power control, the kernel's `SND_SOC_NOPM`.
+pub const NOPM: i32 = -1;
+
+/// A DAPM widget, the kernel's `struct snd_soc_dapm_widget`.
+///
+/// Widgets are created by the constructors below, which match the kernel's `SND_SOC_DAPM_*`
+/// macros. Widgets with register bits are powered up by setting `reg`'s bit `shift`, or clearing
+/// it if `invert` is `true`.
+#[repr(transparent)]
+pub struct Widget(bindings::snd_soc_dapm_widget);
+
+impl Widget {
+    const fn new(id: bindings::snd_soc_dapm_type, name: &'static CStr) -> Self {
+        // SAFETY: All fields other than the ones set below are optional, for which zero is valid.
+        let mut w: bindings::snd_soc_dapm_widget = unsafe { MaybeUninit::zeroed().assume_init() };
+        w.id = id;
+        w.name = name.as_char_ptr();
+        w.reg = NOPM;
+        Self(w)
+    }
+
+    const fn with_reg(mut self, reg: i32, shift: u8, invert: bool) -> Self {
+        self.0.reg = reg;
+        self.0.shift = shift as _;
+        self.0.mask = 1;
+        self.0.on_val = if invert { 0 } else { 1 };
+        self.0.off_val = if invert { 1 } else { 0 };
+        self
+    }
+
+    const fn with_stream(mut self, stream: &'static CStr) -> Self {
+        self.0.sname = stream.as_char_ptr();
+        self
+    }
+
+    /// An input pin of the component, the kernel's `SND_SOC_DAPM_INPUT`.
+    pub const fn input(name: &'static CStr) -> Self {
+        Self::new(bindings::snd_soc_dapm_type_snd_soc_dapm_input, name)
+    }
+
+    /// An output pin of the component, the kernel's `SND_SOC_DAPM_OUTPUT`.
+    pub const fn output(name: &'static CStr) -> Self {
+        Self::new(bindings::snd_soc_dapm_type_snd_soc_dapm_output, name)
+    }
+
+    /// A DAC fed by the DAI stream `stream`, the kernel's `SND_SOC_DAPM_DAC`.
+    pub const fn dac(
+        name: &'static CStr,
+        stream: &'static CStr,
+        reg: i32,
+        shift: u8,
+        invert: bool,
+    ) -> Self {
+        Self::new(bindings::snd_soc_dapm_type_snd_soc_dapm_dac, name)
+            .with_stream(stream)
+            .with_reg(reg, shift, invert)
+    }
+
+    /// An ADC feeding the DAI stream `stream`, the kernel's `SND_SOC_DAPM_ADC`.
+    pub const fn adc(
+        name: &'static CStr,
+        stream: &'static CStr,
+        reg: i32,
+        shift: u8,
+        invert: bool,
+    ) -> Self {
+        Self::new(bindings::snd_soc_dapm_type_snd_soc_dapm_adc, name)
+            .with_stream(stream)
+            .with_reg(reg, shift, invert)
+    }
+
+    /// An amplifier, the kernel's `SND_SOC_DAPM_PGA` without controls.
+    pub const fn pga(name: &'static CStr, reg: i32, shift: u8, invert: bool) -> Self {
+        Self::new(bindings::snd_soc_dapm_type_snd_soc_dapm_pga, name).with_reg(reg, shift, invert)
+    }
+
+    /// A supply of other widgets, e.g. a clock or a bias voltage, the kernel's
+    /// `SND_SOC_DAPM_SUPPLY` without event.
+    pub const fn supply(name: &'static CStr, reg: i32, shift: u8, invert: bool) -> Self {
+        Self::new(bindings::snd_soc_dapm_type_snd_soc_dapm_supply, name)
+            .with_reg(reg, shift, invert)
+    }
+
+    /// An audio interface receiving the DAI stream `stream`, the kernel's `SND_SOC_DAPM_AIF_IN`.
+    pub const fn aif_in(
+        name: &'static CStr,
+        stream: &'static CStr,
+        channel: i32,
+        reg: i32,
+        shift: u8,
+        invert: bool,
+    ) -> Self {
+        let mut w = Self::new(bindings::snd_soc_dapm_type_snd_soc_dapm_aif_in, name)
+            .with_stream(stream)
+            .with_reg(reg, shift, invert);
+        w.0.channel = channel;
+        w
+    }
+
+    /// An audio interface sending the DAI stream `stream`, the kernel's `SND_SOC_DAPM_AIF_OUT`.
+    pub const fn aif_out(
+        name: &'static CStr,
+        stream: &'static CStr,
+        channel: i32,
+        reg: i32,
+        shift: u8,
+        invert: bool,
+    ) -> Self {
+        let mut w = Self::new(bindings::snd_soc_dapm_type_snd_soc_dapm_aif_out, name)
+            .with_stream(stream)
+            .with_reg(reg, shift, invert);
+        w.0.channel = channel;
+        w
+    }
+}
+
+/// A DAPM route from the widget `source` to the widget `sink`, the kernel's
+/// `struct snd_soc_dapm_route`.
+#[repr(transparent)]
+pub struct Route(bindings::snd_soc_dapm_route);
+
+impl Route {
+    /// A route that is always connected.
+    pub const fn new(sink: &'static CStr, source: &'static CStr) -> Self {
+        Self(bindings::snd_soc_dapm_route {
+            sink: sink.as_char_ptr(),
+            control: ptr::null(),
+            source: source.as_char_ptr(),
+            connected: None,
+        })
+    }
+
+    /// A route through the control `control` of `sink`, e.g. a mixer switch.
+    pub const fn with_control(
+        sink: &'static CStr,
+        control: &'static CStr,
+        source: &'static CStr,
+    ) -> Self {
+        Self(bindings::snd_soc_dapm_route {
+            sink: sink.as_char_ptr(),
+            control: control.as_char_ptr(),
+            source: source.as_char_ptr(),
+            connected: None,
+        })
+    }
+}
+
+/// Declares the DAPM widgets of a component, [`super::Operations::WIDGETS`].
+///
+/// Each entry is the name of a [`Widget`] constructor followed by its arguments, with string
+/// literals for the names.
+///
+/// # Examples
+///
+/// ```ignore
+/// kernel::declare_dapm_widgets! {
+///     input("LINEIN"),
+///     output("HPOUT"),
+///     supply("MICBIAS", REG_POWER, 4, false),
+///     adc("ADC", "Capture", REG_POWER, 2, false),
+///     dac("DAC", "Playback", REG_POWER, 3, false),
+///     pga("HP Amp", REG_POWER, 5, true),
+/// }
+/// ```
+#[macro_export]
+macro_rules! declare_dapm_widgets {
+    (@widget $kind:ident($name:literal)) => {
+        $crate::sound::soc::dapm::Widget::$kind($crate::c_str!($name))
+    };
+    (@widget $kind:ident($name:literal, $reg:expr, $shift:expr, $invert:expr)) => {
+        $crate::sound::soc::dapm::Widget::$kind($crate::c_str!($name), $reg, $shift, $invert)
+    };
+    (@widget $kind:ident($name:literal, $stream:literal, $reg:expr, $shift:expr, $invert:expr)) => {
+        $crate::sound::soc::dapm::Widget::$kind(
+            $crate::c_str!($name),
+            $crate::c_str!($stream),
+            $reg,
+            $shift,
+            $invert,
+        )
+    };
+    (@widget $kind:ident(
+        $name:literal, $stream:literal, $channel:expr, $reg:expr, $shift:expr, $invert:expr
+    )) => {
+        $crate::sound::soc::dapm::Widget::$kind(
+            $crate::c_str!($name),
+            $crate::c_str!($stream),
+            $channel,
+            $reg,
+            $shift,
+            $invert,
+        )
+    };
+    ($($kind:ident($($arg:tt)*)),* $(,)?) => {
+        const WIDGETS: &'static [$crate::sound::soc::dapm::Widget] =
+            &[$($crate::declare_dapm_widgets!(@widget $kind($($arg)*))),*];
+    };
+}
+
+/// Declares the DAPM routes of a component, [`super::Operations::ROUTES`].
+///
+/// Each entry is either `(sink, source)` or `(sink, control, source)`, see [`Route`].
+///
+/// # Examples
+///
+/// ```ignore
+/// kernel::declare_dapm_routes! {
+///     ("ADC", "LINEIN"),
+///     ("ADC", "MICBIAS"),
+///     ("HP Amp", "DAC"),
+///     ("HPOUT", "HP Amp"),
+/// }
+/// ```
+#[macro_export]
+macro_rules! declare_dapm_routes {
+    (@route $sink:literal, $source:literal) => {
+        $crate::sound::soc::dapm::Route::new($crate::c_str!($sink), $crate::c_str!($source))
+    };
+    (@route $sink:literal, $control:literal, $source:literal) => {
+        $crate::sound::soc::dapm::Route::with_control(
+            $crate::c_str!($sink),
+            $crate::c_str!($control),
+            $crate::c_str!($source),
+        )
+    };
+    ($(($($route:tt)*)),* $(,)?) => {
+        const ROUTES: &'static [$crate::sound::soc::dapm::Route] =
+            &[$($crate::declare_dapm_routes!(@route $($route)*)),*];
+    };
+}
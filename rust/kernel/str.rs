@@ -4,7 +4,9 @@
 
 use alloc::alloc::AllocError;
 use alloc::vec::Vec;
+use core::cmp::Ordering;
 use core::fmt::{self, Write};
+use core::hash::{Hash, Hasher};
 use core::ops::{self, Deref, Index};
 
 use crate::{
@@ -266,6 +268,68 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     }
 }
 
+impl PartialEq for CStr {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl Eq for CStr {}
+
+impl PartialOrd for CStr {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CStr {
+    /// Compares the strings byte by byte, like `strcmp` does.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kernel::c_str;
+    /// assert!(c_str!("abc") < c_str!("abd"));
+    /// assert!(c_str!("ab") < c_str!("abc"));
+    /// assert_eq!(c_str!("abc"), c_str!("abc"));
+    /// ```
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_bytes().cmp(other.as_bytes())
+    }
+}
+
+impl Hash for CStr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_bytes_with_nul().hash(state);
+    }
+}
+
+impl PartialEq<str> for CStr {
+    /// Compares the string with a Rust one, which has no `NUL` terminator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kernel::c_str;
+    /// assert!(*c_str!("eth0") == *"eth0");
+    /// assert!(*c_str!("eth0") != *"eth0\0");
+    /// ```
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl PartialEq<BStr> for CStr {
+    #[inline]
+    fn eq(&self, other: &BStr) -> bool {
+        self.as_bytes() == other
+    }
+}
+
 impl AsRef<BStr> for CStr {
     #[inline]
     fn as_ref(&self) -> &BStr {
@@ -331,7 +395,9 @@ fn index(&self, index: Idx) -> &Self::Output {
 
 /// Creates a new [`CStr`] from a string literal.
 ///
-/// The string literal should not contain any `NUL` bytes.
+/// The `NUL` terminator is appended by the macro, and the string literal must not contain any
+/// `NUL` bytes, which is checked at compile time. Non-ASCII characters can be included, they are
+/// encoded in UTF-8.
 ///
 /// # Examples
 ///
@@ -339,6 +405,16 @@ fn index(&self, index: Idx) -> &Self::Output {
 /// # use kernel::c_str;
 /// # use kernel::str::CStr;
 /// const MY_CSTR: &CStr = c_str!("My awesome CStr!");
+/// const PENGUIN: &CStr = c_str!("🐧");
+/// assert_eq!(PENGUIN.len(), 4);
+/// ```
+///
+/// Embedded `NUL` bytes are rejected when building:
+///
+/// ```compile_fail
+/// # use kernel::c_str;
+/// # use kernel::str::CStr;
+/// const BAD: &CStr = c_str!("a\0b");
 /// ```
 #[macro_export]
 macro_rules! c_str {
@@ -352,6 +428,196 @@ macro_rules! c_str {
     }};
 }
 
+/// A type that can be parsed like the kernel's `kstrto*` functions do, see [`kstrto`].
+pub trait FromKstr: Sized {
+    /// Parses `s` as an integer in base `base`, see [`kstrto`].
+    fn from_kstr(s: &BStr, base: u32) -> Result<Self, Error>;
+}
+
+/// Parses the magnitude of an integer, without sign, like `_parse_integer` does in C.
+///
+/// Base 0 means base 16 with a `0x` prefix, base 8 with a `0` prefix and base 10 otherwise, and
+/// base 16 accepts an optional `0x` prefix.
+fn parse_magnitude(s: &BStr, base: u32) -> Result<u64, Error> {
+    let hex_prefix = |s: &BStr| matches!(s, [b'0', b'x' | b'X', c, ..] if c.is_ascii_hexdigit());
+    let (s, radix) = match base {
+        0 if hex_prefix(s) => (&s[2..], 16),
+        0 if s.first() == Some(&b'0') => (s, 8),
+        0 => (s, 10),
+        16 if hex_prefix(s) => (&s[2..], 16),
+        2..=36 => (s, base),
+        _ => return Err(EINVAL),
+    };
+    if s.is_empty() {
+        return Err(EINVAL);
+    }
+    s.iter().try_fold(0u64, |acc, &c| {
+        let digit = (c as char).to_digit(radix).ok_or(EINVAL)?;
+        acc.checked_mul(radix.into())
+            .and_then(|acc| acc.checked_add(digit.into()))
+            .ok_or(ERANGE)
+    })
+}
+
+/// Strips the single trailing newline that `kstrto*` accept, e.g. from sysfs writes.
+fn strip_newline(s: &BStr) -> &BStr {
+    s.strip_suffix(b"\n").unwrap_or(s)
+}
+
+macro_rules! impl_from_kstr_unsigned {
+    ($($t:ty),*) => {
+        $(
+            impl FromKstr for $t {
+                fn from_kstr(s: &BStr, base: u32) -> Result<Self, Error> {
+                    let s = strip_newline(s);
+                    let s = s.strip_prefix(b"+").unwrap_or(s);
+                    <$t>::try_from(parse_magnitude(s, base)?).map_err(|_| ERANGE)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_from_kstr_signed {
+    ($($t:ty),*) => {
+        $(
+            impl FromKstr for $t {
+                fn from_kstr(s: &BStr, base: u32) -> Result<Self, Error> {
+                    let s = strip_newline(s);
+                    let (s, negative) = match s {
+                        [b'-', rest @ ..] => (rest, true),
+                        [b'+', rest @ ..] => (rest, false),
+                        _ => (s, false),
+                    };
+                    let magnitude = parse_magnitude(s, base)?;
+                    let value = if negative {
+                        // `i64::MIN` has no positive counterpart, hence the wrapping negation.
+                        if magnitude > i64::MIN.unsigned_abs() {
+                            return Err(ERANGE);
+                        }
+                        (magnitude as i64).wrapping_neg()
+                    } else {
+                        i64::try_from(magnitude).map_err(|_| ERANGE)?
+                    };
+                    <$t>::try_from(value).map_err(|_| ERANGE)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_kstr_unsigned!(u8, u16, u32, u64, usize);
+impl_from_kstr_signed!(i8, i16, i32, i64, isize);
+
+/// Parses `s` as an integer in base `base`, like the `kstrto*` functions do in C, e.g.
+/// `kstrtouint` for a `u32`.
+///
+/// `s` may have a sign, a single trailing newline, and, in base 16, a `0x` prefix. Base 0 means
+/// base 16 with a `0x` prefix, base 8 with a `0` prefix and base 10 otherwise. Fails with
+/// `ERANGE` if the value doesn't fit in a `T`, or `EINVAL` if `s` isn't an integer.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::str::kstrto;
+///
+/// assert_eq!(kstrto::<u32>(b"0x1f\n", 0), Ok(31));
+/// assert_eq!(kstrto::<i8>(b"-128", 10), Ok(-128));
+/// assert!(kstrto::<u8>(b"256", 10).is_err());
+/// ```
+pub fn kstrto<T: FromKstr>(s: &BStr, base: u32) -> Result<T, Error> {
+    T::from_kstr(s, base)
+}
+
+/// Parses `s` as a boolean, like `kstrtobool` does in C.
+///
+/// Only the first characters are checked: `y`, `t` and `1` mean `true`, `n`, `f` and `0` mean
+/// `false`, as do `on` and `off`, in any case.
+pub fn kstrtobool(s: &BStr) -> Result<bool, Error> {
+    match s {
+        [b'y' | b'Y' | b't' | b'T' | b'1', ..] | [b'o' | b'O', b'n' | b'N', ..] => Ok(true),
+        [b'n' | b'N' | b'f' | b'F' | b'0', ..] | [b'o' | b'O', b'f' | b'F', ..] => Ok(false),
+        _ => Err(EINVAL),
+    }
+}
+
+/// Parses `s` as a size with an optional binary suffix, like `memparse` does in C.
+///
+/// The number is parsed in base 0, see [`kstrto`], and may be followed by `K`, `M`, `G`, `T`,
+/// `P` or `E`, in any case, which multiply it by the corresponding power of 1024. As in C, the
+/// longest prefix that is a number is parsed first, so a hexadecimal number may end in `E`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::str::memparse;
+///
+/// assert_eq!(memparse(b"64K"), Ok(65536));
+/// assert_eq!(memparse(b"0x100000\n"), Ok(1 << 20));
+/// assert_eq!(memparse(b"0x1E"), Ok(30));
+/// ```
+pub fn memparse(s: &BStr) -> Result<u64, Error> {
+    let s = strip_newline(s);
+    let sign = matches!(s, [b'+', ..]) as usize;
+    let (radix, prefix) = match &s[sign..] {
+        [b'0', b'x' | b'X', ..] => (16, 2),
+        [b'0', ..] => (8, 0),
+        _ => (10, 0),
+    };
+    let digits = s[sign + prefix..]
+        .iter()
+        .take_while(|c| char::from(**c).is_digit(radix))
+        .count();
+    let (number, suffix) = s.split_at(sign + prefix + digits);
+    let shift = match suffix {
+        [] => 0,
+        [c] => match c.to_ascii_uppercase() {
+            b'K' => 10,
+            b'M' => 20,
+            b'G' => 30,
+            b'T' => 40,
+            b'P' => 50,
+            b'E' => 60,
+            _ => return Err(EINVAL),
+        },
+        _ => return Err(EINVAL),
+    };
+    let value: u64 = kstrto(number, 0)?;
+    if value.leading_zeros() < shift {
+        return Err(ERANGE);
+    }
+    Ok(value << shift)
+}
+
+/// Returns whether `a` and `b` are equal, ignoring a single trailing newline on either, like
+/// `sysfs_streq` does in C.
+pub fn sysfs_streq(a: &BStr, b: &BStr) -> bool {
+    strip_newline(a) == strip_newline(b)
+}
+
+/// Returns the index of `s` in `table`, like `match_string` does in C.
+pub fn match_string(table: &[&BStr], s: &BStr) -> Option<usize> {
+    table.iter().position(|t| *t == s)
+}
+
+/// Returns the index of `s` in `table`, ignoring a trailing newline, like `sysfs_match_string`
+/// does in C.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{b_str, prelude::*, str::{sysfs_match_string, BStr}};
+///
+/// const MODES: [&BStr; 3] = [b_str!("off"), b_str!("auto"), b_str!("manual")];
+///
+/// fn parse_mode(buf: &BStr) -> Result<usize> {
+///     sysfs_match_string(&MODES, buf).ok_or(EINVAL)
+/// }
+/// ```
+pub fn sysfs_match_string(table: &[&BStr], s: &BStr) -> Option<usize> {
+    table.iter().position(|t| sysfs_streq(t, s))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -379,6 +645,41 @@ fn test_cstr_as_str_unchecked() {
         let unchecked_str = unsafe { checked_cstr.as_str_unchecked() };
         assert_eq!(unchecked_str, "🐧");
     }
+
+    #[test]
+    fn test_cstr_cmp() {
+        let a = CStr::from_bytes_with_nul(b"abc\0").unwrap();
+        let b = CStr::from_bytes_with_nul(b"abd\0").unwrap();
+        assert!(a < b);
+        assert!(a == a);
+        assert!(*a == *"abc");
+        assert!(*a == b"abc"[..]);
+        assert!(*a != *"ab");
+    }
+
+    #[test]
+    fn test_kstrto() {
+        assert_eq!(kstrto::<u32>(b"42\n", 10), Ok(42));
+        assert_eq!(kstrto::<u32>(b"0x2a", 0), Ok(42));
+        assert_eq!(kstrto::<u32>(b"2a", 16), Ok(42));
+        assert_eq!(kstrto::<u32>(b"052", 0), Ok(42));
+        assert_eq!(kstrto::<i64>(b"-9223372036854775808", 0), Ok(i64::MIN));
+        assert_eq!(kstrto::<u8>(b"256", 0), Err(ERANGE));
+        assert_eq!(kstrto::<u32>(b"42\n\n", 10), Err(EINVAL));
+        assert_eq!(kstrto::<u32>(b" 42", 10), Err(EINVAL));
+        assert_eq!(kstrto::<u32>(b"", 10), Err(EINVAL));
+    }
+
+    #[test]
+    fn test_memparse() {
+        assert_eq!(memparse(b"4k"), Ok(4096));
+        assert_eq!(memparse(b"1G\n"), Ok(1 << 30));
+        assert_eq!(memparse(b"16E"), Err(ERANGE));
+        assert_eq!(memparse(b"0x1E"), Ok(30));
+        assert_eq!(memparse(b"0x1EK"), Ok(30 << 10));
+        assert_eq!(memparse(b"2e"), Ok(2 << 60));
+        assert_eq!(memparse(b"1KB"), Err(EINVAL));
+    }
 }
 
 /// Allows formatting of [`fmt::Arguments`] into a raw buffer.
@@ -591,6 +892,70 @@ fn deref(&self) -> &Self::Target {
     }
 }
 
+impl AsRef<CStr> for CString {
+    #[inline]
+    fn as_ref(&self) -> &CStr {
+        self
+    }
+}
+
+impl fmt::Display for CString {
+    /// Formats the string like a [`CStr`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kernel::fmt;
+    /// # use kernel::str::CString;
+    /// let name = CString::try_from_fmt(fmt!("gpio{}", 3)).unwrap();
+    /// let s = CString::try_from_fmt(fmt!("{}", name)).unwrap();
+    /// assert_eq!(s.as_bytes_with_nul(), "gpio3\0".as_bytes());
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl fmt::Debug for CString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl PartialEq for CString {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for CString {}
+
+impl PartialEq<CStr> for CString {
+    #[inline]
+    fn eq(&self, other: &CStr) -> bool {
+        **self == *other
+    }
+}
+
+impl PartialEq<str> for CString {
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        **self == *other
+    }
+}
+
+impl<'a> TryFrom<&'a str> for CString {
+    type Error = Error;
+
+    /// Copies `s` and appends a `NUL` terminator.
+    ///
+    /// It fails with `EINVAL` if `s` contains a `NUL` byte.
+    fn try_from(s: &'a str) -> Result<CString, Error> {
+        Self::try_from_fmt(format_args!("{s}"))
+    }
+}
+
 impl<'a> TryFrom<&'a CStr> for CString {
     type Error = AllocError;
 
@@ -11,6 +11,8 @@
 mod condvar;
 pub mod lock;
 mod locked_by;
+pub mod poll;
+pub mod rcu;
 
 pub use arc::{Arc, ArcBorrow, UniqueArc};
 pub use condvar::CondVar;
@@ -0,0 +1,166 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Readiness notification for `poll`, `select` and `epoll`.
+//!
+//! A file that supports polling implements
+//! [`file::Operations::poll`](crate::file::Operations::poll), which registers the [`PollCondVar`]s
+//! that signal changes of its readiness in the [`PollTable`] of the caller, and returns the
+//! [`events`] that are ready. Waiters are woken up by notifying the condition variables.
+//!
+//! C header: [`include/linux/poll.h`](../../../../include/linux/poll.h)
+
+use crate::{
+    bindings,
+    file::File,
+    init::PinInit,
+    pin_init,
+    str::CStr,
+    sync::{rcu, CondVar, LockClassKey},
+    types::Opaque,
+};
+use core::{ops::Deref, pin::Pin};
+use macros::{pin_data, pinned_drop};
+
+/// Events reported by `poll`, the kernel's `POLL*` values.
+pub mod events {
+    /// Data can be read.
+    pub const POLLIN: u32 = crate::bindings::POLLIN as _;
+    /// Urgent data can be read.
+    pub const POLLPRI: u32 = crate::bindings::POLLPRI as _;
+    /// Data can be written.
+    pub const POLLOUT: u32 = crate::bindings::POLLOUT as _;
+    /// An error occurred. It is always reported, even if it wasn't requested.
+    pub const POLLERR: u32 = crate::bindings::POLLERR as _;
+    /// The other end hung up, e.g. the device was unplugged. It is always reported.
+    pub const POLLHUP: u32 = crate::bindings::POLLHUP as _;
+    /// Normal data can be read, reported along with [`POLLIN`].
+    pub const POLLRDNORM: u32 = crate::bindings::POLLRDNORM as _;
+    /// Normal data can be written, reported along with [`POLLOUT`].
+    pub const POLLWRNORM: u32 = crate::bindings::POLLWRNORM as _;
+}
+
+/// Creates a [`PollCondVar`] initialiser with the given name and a newly-created lock class.
+#[macro_export]
+macro_rules! new_poll_condvar {
+    ($($name:literal)?) => {
+        $crate::sync::poll::PollCondVar::new(
+            $crate::optional_name!($($name)?),
+            $crate::static_lock_class!(),
+        )
+    };
+}
+
+/// The table of the caller of `poll`, the kernel's `poll_table`.
+///
+/// Callers that only check the readiness, without waiting, pass no table.
+///
+/// # Invariants
+///
+/// The table is valid for the duration of the `poll` callback it was passed to.
+#[repr(transparent)]
+pub struct PollTable(Opaque<bindings::poll_table>);
+
+impl PollTable {
+    /// Creates a reference to the table pointed to by `ptr`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be null, or a valid table for the lifetime `'a`, which is only used through the
+    /// returned reference.
+    pub(crate) unsafe fn from_ptr<'a>(ptr: *mut bindings::poll_table) -> Option<&'a mut Self> {
+        // SAFETY: `PollTable` is a transparent wrapper of `poll_table`, and the caller guarantees
+        // that `ptr` is valid for `'a` if it isn't null.
+        unsafe { ptr.cast::<Self>().as_mut() }
+    }
+
+    /// Registers `cv` in the table, so that the caller is woken up when it is notified.
+    ///
+    /// The condition variable must outlive the file, which [`PollCondVar`] ensures by waking up
+    /// and removing the remaining waiters when it is dropped.
+    pub fn register_wait(&mut self, file: &File, cv: &PollCondVar) {
+        // SAFETY: The table is valid by the type invariants.
+        if let Some(qproc) = unsafe { (*self.0.get())._qproc } {
+            // SAFETY: The table, the file and the wait queue are valid. The waiter is removed
+            // from the queue when the caller is done polling, or when the queue is dropped.
+            unsafe { qproc(file.as_ptr(), cv.inner.wait_list.get(), self.0.get()) };
+        }
+    }
+}
+
+/// A condition variable that can be polled, with [`PollTable::register_wait`].
+///
+/// It is used like a [`CondVar`], which it dereferences to.
+///
+/// # Examples
+///
+/// ```
+/// use core::sync::atomic::{AtomicU32, Ordering};
+/// use kernel::sync::poll::{events, PollCondVar, PollTable};
+/// use kernel::{file, new_poll_condvar, prelude::*, sync::Arc};
+///
+/// #[pin_data]
+/// struct Events {
+///     pending: AtomicU32,
+///     #[pin]
+///     ready: PollCondVar,
+/// }
+///
+/// fn new_events() -> Result<Arc<Events>> {
+///     Arc::pin_init(pin_init!(Events {
+///         pending: AtomicU32::new(0),
+///         ready <- new_poll_condvar!("Events::ready"),
+///     }))
+/// }
+///
+/// fn signal(events: &Events) {
+///     events.pending.fetch_add(1, Ordering::Release);
+///     events.ready.notify_all();
+/// }
+///
+/// fn poll(events: &Events, file: &file::File, table: Option<&mut PollTable>) -> u32 {
+///     if let Some(table) = table {
+///         table.register_wait(file, &events.ready);
+///     }
+///     if events.pending.load(Ordering::Acquire) != 0 {
+///         events::POLLIN | events::POLLRDNORM
+///     } else {
+///         0
+///     }
+/// }
+/// ```
+#[pin_data(PinnedDrop)]
+pub struct PollCondVar {
+    #[pin]
+    inner: CondVar,
+}
+
+impl PollCondVar {
+    /// Constructs a new condition variable initialiser.
+    ///
+    /// It's recommended to use the [`new_poll_condvar`](crate::new_poll_condvar) macro instead.
+    pub fn new(name: &'static CStr, key: &'static LockClassKey) -> impl PinInit<Self> {
+        pin_init!(Self {
+            inner <- CondVar::new(name, key),
+        })
+    }
+}
+
+impl Deref for PollCondVar {
+    type Target = CondVar;
+
+    fn deref(&self) -> &CondVar {
+        &self.inner
+    }
+}
+
+#[pinned_drop]
+impl PinnedDrop for PollCondVar {
+    fn drop(self: Pin<&mut Self>) {
+        // Pollers may still be registered, e.g. by an epoll instance that outlives the file.
+        // SAFETY: The wait queue is valid, and `__wake_up_pollfree` removes all its waiters.
+        unsafe { bindings::__wake_up_pollfree(self.inner.wait_list.get()) };
+        // The waiters are removed under RCU by epoll, so wait for them to be done with the queue
+        // before it is freed.
+        rcu::synchronize();
+    }
+}
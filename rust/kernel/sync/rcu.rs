@@ -0,0 +1,71 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Read-copy-update.
+//!
+//! Readers of RCU-protected data hold a [`Guard`], returned by [`read_lock`], which is cheap and
+//! never blocks writers. Writers that unlink data call [`synchronize`] before freeing it, to wait
+//! for the readers that may still see it.
+//!
+//! C header: [`include/linux/rcupdate.h`](../../../../include/linux/rcupdate.h)
+
+use crate::{
+    bindings,
+    context::{AtomicSection, Kind},
+};
+use core::marker::PhantomData;
+
+/// An RCU read-side critical section, which ends when the guard is dropped.
+///
+/// It must not be held across anything that sleeps.
+///
+/// # Invariants
+///
+/// The RCU read-side lock is held by the current thread, which is why the guard isn't [`Send`].
+pub struct Guard {
+    _section: AtomicSection,
+    _not_send: PhantomData<*mut ()>,
+}
+
+impl Guard {
+    /// Enters an RCU read-side critical section.
+    #[track_caller]
+    pub fn new() -> Self {
+        let section = AtomicSection::enter(Kind::Rcu);
+        // SAFETY: FFI call.
+        unsafe { bindings::rcu_read_lock() };
+        // INVARIANT: The read-side lock was taken above.
+        Self {
+            _section: section,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl Default for Guard {
+    #[track_caller]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        // SAFETY: The read-side lock is held by the current thread, by the type invariants.
+        unsafe { bindings::rcu_read_unlock() };
+    }
+}
+
+/// Enters an RCU read-side critical section, which ends when the returned guard is dropped.
+#[track_caller]
+pub fn read_lock() -> Guard {
+    Guard::new()
+}
+
+/// Waits for all the RCU read-side critical sections that have started to end.
+///
+/// This sleeps, so it must not be called from atomic context nor with a [`Guard`] held.
+pub fn synchronize() {
+    crate::might_sleep!();
+    // SAFETY: FFI call.
+    unsafe { bindings::synchronize_rcu() };
+}
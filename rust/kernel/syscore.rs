@@ -0,0 +1,151 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! System core operations.
+//!
+//! Syscore operations are called on system suspend, resume and shutdown after all devices were
+//! suspended or shut down, and before they are resumed, on the last online CPU with interrupts
+//! disabled. They are meant for core hardware that devices depend on, e.g. interrupt controllers,
+//! timers and clock controllers, which have no device of their own or must outlive all others.
+//!
+//! C header: [`include/linux/syscore_ops.h`](../../../../include/linux/syscore_ops.h)
+
+use crate::{
+    bindings,
+    error::{code::*, from_result, Result},
+    types::Opaque,
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::c_int,
+    marker::{PhantomData, PhantomPinned},
+    mem::MaybeUninit,
+    pin::Pin,
+};
+use macros::vtable;
+
+/// The syscore operations, the kernel's `struct syscore_ops` callbacks.
+///
+/// The callbacks take no arguments, so the state that they save and restore is usually in
+/// statics. They are called in atomic context, with interrupts disabled and only one CPU online,
+/// and must not sleep nor enable interrupts.
+#[vtable]
+pub trait Operations: 'static {
+    /// Called on system suspend, after the devices were suspended.
+    ///
+    /// Returning an error aborts the suspend, and the operations that were already called are
+    /// resumed.
+    fn suspend() -> Result {
+        Err(EINVAL)
+    }
+
+    /// Called on system resume, before the devices are resumed.
+    fn resume() {}
+
+    /// Called on system shutdown, reboot and kexec, after the devices were shut down.
+    fn shutdown() {}
+}
+
+/// A registration of syscore operations.
+///
+/// The operations are unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `ops` is registered, with the callbacks of `T`.
+///
+/// # Examples
+///
+/// ```
+/// use core::sync::atomic::{AtomicU32, Ordering};
+/// use kernel::{prelude::*, syscore};
+///
+/// static SAVED_MASK: AtomicU32 = AtomicU32::new(0);
+///
+/// struct Intc;
+///
+/// #[vtable]
+/// impl syscore::Operations for Intc {
+///     fn suspend() -> Result {
+///         // Save the state that the controller loses when it is powered off.
+///         SAVED_MASK.store(0xffff, Ordering::Relaxed);
+///         Ok(())
+///     }
+///
+///     fn resume() {
+///         let _mask = SAVED_MASK.load(Ordering::Relaxed);
+///         // Restore the state of the controller.
+///     }
+/// }
+///
+/// fn register() -> Result<Pin<Box<syscore::Registration<Intc>>>> {
+///     syscore::Registration::register()
+/// }
+/// ```
+pub struct Registration<T: Operations> {
+    ops: Opaque<bindings::syscore_ops>,
+    _pin: PhantomPinned,
+    _p: PhantomData<T>,
+}
+
+impl<T: Operations> Registration<T> {
+    /// Registers the syscore operations of `T`.
+    pub fn register() -> Result<Pin<Box<Self>>> {
+        crate::might_sleep!();
+        let reg = Pin::from(Box::try_new(Self {
+            ops: Opaque::new(bindings::syscore_ops {
+                suspend: if T::HAS_SUSPEND {
+                    Some(Self::suspend_callback)
+                } else {
+                    None
+                },
+                resume: if T::HAS_RESUME {
+                    Some(Self::resume_callback)
+                } else {
+                    None
+                },
+                shutdown: if T::HAS_SHUTDOWN {
+                    Some(Self::shutdown_callback)
+                } else {
+                    None
+                },
+                // SAFETY: All-zeroes is a valid list node, which is initialised on registration.
+                node: unsafe { MaybeUninit::zeroed().assume_init() },
+            }),
+            _pin: PhantomPinned,
+            _p: PhantomData,
+        })?);
+        // SAFETY: The operations are pinned, and they are unregistered before they are freed.
+        unsafe { bindings::register_syscore_ops(reg.ops.get()) };
+        // INVARIANT: The operations were registered above.
+        Ok(reg)
+    }
+
+    unsafe extern "C" fn suspend_callback() -> c_int {
+        from_result(|| {
+            T::suspend()?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn resume_callback() {
+        T::resume();
+    }
+
+    unsafe extern "C" fn shutdown_callback() {
+        T::shutdown();
+    }
+}
+
+impl<T: Operations> Drop for Registration<T> {
+    fn drop(&mut self) {
+        // SAFETY: The operations are registered by the type invariants. Unregistering them takes
+        // the lock that is held while they are called.
+        unsafe { bindings::unregister_syscore_ops(self.ops.get()) };
+    }
+}
+
+// SAFETY: The operations can be unregistered from any thread.
+unsafe impl<T: Operations> Send for Registration<T> {}
+
+// SAFETY: The registration has no methods that take `&self`.
+unsafe impl<T: Operations> Sync for Registration<T> {}
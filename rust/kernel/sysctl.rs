@@ -0,0 +1,629 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! System control tunables, the files of `/proc/sys`.
+//!
+//! A subsystem fills a [`Table`] with named values, and registers it at a path under `/proc/sys`
+//! with a [`Registration`]. The path may have several levels, whose directories are created as
+//! needed and shared with other tables registered under them, so a tree of tunables is built by
+//! registering a table for each of its directories. Tables of network namespaces are registered
+//! with [`Registration::register_net`], usually from [`crate::net::PernetOperations::init`].
+//!
+//! The values implement [`Value`]: integers, with an optional range that writes are clamped to,
+//! in [`Integer`], booleans in [`Bool`], fixed-size vectors of integers in [`Vector`], and
+//! strings in [`Text`]. [`Notify`] wraps any of them to be told when user space changes it.
+//!
+//! C header: [`include/linux/sysctl.h`](../../../../include/linux/sysctl.h)
+
+use crate::{
+    bindings,
+    error::{code::*, from_result, Error, Result},
+    init::PinInit,
+    str::{self, BStr, CString, FromKstr, RawFormatter},
+    sync::{Arc, Mutex},
+    try_pin_init,
+};
+use alloc::vec::Vec;
+use core::{
+    any::Any,
+    ffi::{c_int, c_void},
+    fmt::{self, Write},
+    mem::MaybeUninit,
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+use macros::pin_data;
+
+/// A value that is exposed as a sysctl file.
+///
+/// Reads of the file show the value formatted with [`fmt::Display`], followed by a newline.
+/// Writes must replace the whole value at once, they fail with `EINVAL` at a non-zero offset.
+pub trait Value: fmt::Display + Send + Sync + 'static {
+    /// Parses `data`, written by user space, and updates the value.
+    ///
+    /// `data` may end with a newline. The value must be left unchanged on failure.
+    fn store(&self, data: &BStr) -> Result;
+}
+
+/// An integer type that can be stored in an [`Integer`] or a [`Vector`].
+pub trait Number: FromKstr + Copy + PartialOrd + fmt::Display + Send + Sync + 'static {
+    /// The smallest value of the type.
+    const MIN: Self;
+
+    /// The largest value of the type.
+    const MAX: Self;
+
+    /// Converts the value to the bits it is stored as.
+    #[doc(hidden)]
+    fn to_bits(self) -> u64;
+
+    /// Converts bits returned by [`Number::to_bits`] back to a value.
+    #[doc(hidden)]
+    fn from_bits(bits: u64) -> Self;
+}
+
+macro_rules! impl_number {
+    ($($t:ty),*) => {
+        $(
+            impl Number for $t {
+                const MIN: Self = <$t>::MIN;
+                const MAX: Self = <$t>::MAX;
+
+                fn to_bits(self) -> u64 {
+                    self as u64
+                }
+
+                fn from_bits(bits: u64) -> Self {
+                    bits as Self
+                }
+            }
+        )*
+    };
+}
+
+impl_number!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+/// The range that the values of an [`Integer`] or a [`Vector`] are clamped to.
+#[derive(Clone, Copy)]
+struct Range<T> {
+    min: T,
+    max: T,
+}
+
+impl<T: Number> Range<T> {
+    const FULL: Self = Self {
+        min: T::MIN,
+        max: T::MAX,
+    };
+
+    fn clamp(&self, value: T) -> T {
+        if value < self.min {
+            self.min
+        } else if value > self.max {
+            self.max
+        } else {
+            value
+        }
+    }
+}
+
+/// An integer value, the equivalent of `proc_dointvec_minmax` and its siblings.
+///
+/// Numbers written by user space are parsed like [`str::kstrto`] does with base 0, so they may
+/// be decimal, octal with a `0` prefix or hexadecimal with a `0x` prefix. Unlike in C, numbers
+/// out of the range of the value aren't rejected, but clamped to it.
+pub struct Integer<T: Number> {
+    bits: AtomicU64,
+    range: Range<T>,
+}
+
+impl<T: Number> Integer<T> {
+    /// Creates an integer with the initial value `value`, which can be set to any value of `T`.
+    pub fn new(value: T) -> Self {
+        Self {
+            bits: AtomicU64::new(value.to_bits()),
+            range: Range::FULL,
+        }
+    }
+
+    /// Restricts the values of the integer to `min..=max`, and clamps the current one to it.
+    pub fn with_range(mut self, min: T, max: T) -> Self {
+        self.range = Range { min, max };
+        let value = self.range.clamp(self.get());
+        self.bits = AtomicU64::new(value.to_bits());
+        self
+    }
+
+    /// Returns the value.
+    pub fn get(&self) -> T {
+        T::from_bits(self.bits.load(Ordering::Relaxed))
+    }
+
+    /// Sets the value to `value`, clamped to the range of the integer.
+    pub fn set(&self, value: T) {
+        let value = self.range.clamp(value);
+        self.bits.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl<T: Number> fmt::Display for Integer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.get(), f)
+    }
+}
+
+impl<T: Number> Value for Integer<T> {
+    fn store(&self, data: &BStr) -> Result {
+        self.set(str::kstrto(data, 0)?);
+        Ok(())
+    }
+}
+
+/// A boolean value, the equivalent of `proc_dobool`.
+///
+/// It reads as `0` or `1`, and accepts what [`str::kstrtobool`] does.
+pub struct Bool(AtomicBool);
+
+impl Bool {
+    /// Creates a boolean with the initial value `value`.
+    pub const fn new(value: bool) -> Self {
+        Self(AtomicBool::new(value))
+    }
+
+    /// Returns the value.
+    pub fn get(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Sets the value to `value`.
+    pub fn set(&self, value: bool) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+}
+
+impl fmt::Display for Bool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&(self.get() as u8), f)
+    }
+}
+
+impl Value for Bool {
+    fn store(&self, data: &BStr) -> Result {
+        self.set(str::kstrtobool(data)?);
+        Ok(())
+    }
+}
+
+/// A vector of `N` integers, the equivalent of `proc_dointvec` on an array.
+///
+/// It reads as the integers separated by tabs. Writes are whitespace-separated integers, parsed
+/// and clamped like those of an [`Integer`]. They may have fewer than `N` integers, which then
+/// replace the first elements of the vector only.
+pub struct Vector<T: Number, const N: usize> {
+    bits: [AtomicU64; N],
+    range: Range<T>,
+}
+
+impl<T: Number, const N: usize> Vector<T, N> {
+    /// Creates a vector with the initial values `values`, which can be set to any value of `T`.
+    pub fn new(values: [T; N]) -> Self {
+        Self {
+            bits: values.map(|v| AtomicU64::new(v.to_bits())),
+            range: Range::FULL,
+        }
+    }
+
+    /// Restricts the values of the elements to `min..=max`, and clamps the current ones to it.
+    pub fn with_range(mut self, min: T, max: T) -> Self {
+        self.range = Range { min, max };
+        for i in 0..N {
+            self.set(i, self.get(i));
+        }
+        self
+    }
+
+    /// Returns the value of element `i`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is not less than `N`.
+    pub fn get(&self, i: usize) -> T {
+        T::from_bits(self.bits[i].load(Ordering::Relaxed))
+    }
+
+    /// Sets element `i` to `value`, clamped to the range of the vector.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is not less than `N`.
+    pub fn set(&self, i: usize, value: T) {
+        let value = self.range.clamp(value);
+        self.bits[i].store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Returns the values of all the elements.
+    ///
+    /// They are read one after the other, so they may not be consistent with each other if the
+    /// vector is written concurrently.
+    pub fn to_array(&self) -> [T; N] {
+        core::array::from_fn(|i| self.get(i))
+    }
+}
+
+impl<T: Number, const N: usize> fmt::Display for Vector<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for i in 0..N {
+            if i > 0 {
+                f.write_char('\t')?;
+            }
+            fmt::Display::fmt(&self.get(i), f)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Number, const N: usize> Value for Vector<T, N> {
+    fn store(&self, data: &BStr) -> Result {
+        let mut values = [T::MIN; N];
+        let mut len = 0;
+        for word in data
+            .split(u8::is_ascii_whitespace)
+            .filter(|w| !w.is_empty())
+        {
+            *values.get_mut(len).ok_or(EINVAL)? = str::kstrto(word, 0)?;
+            len += 1;
+        }
+        if len == 0 {
+            return Err(EINVAL);
+        }
+        for (i, value) in values[..len].iter().enumerate() {
+            self.set(i, *value);
+        }
+        Ok(())
+    }
+}
+
+struct TextBuf<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> TextBuf<N> {
+    fn try_new(s: &[u8]) -> Result<Self> {
+        let s = s.strip_suffix(b"\n").unwrap_or(s);
+        core::str::from_utf8(s)?;
+        if s.len() > N || s.contains(&0) {
+            return Err(EINVAL);
+        }
+        let mut bytes = [0; N];
+        bytes[..s.len()].copy_from_slice(s);
+        Ok(Self {
+            bytes,
+            len: s.len(),
+        })
+    }
+
+    fn as_str(&self) -> &str {
+        // SAFETY: The bytes were checked to be UTF-8 in `try_new`.
+        unsafe { core::str::from_utf8_unchecked(&self.bytes[..self.len]) }
+    }
+}
+
+/// A string of up to `N` bytes, the equivalent of `proc_dostring`.
+///
+/// Strings written by user space must be valid UTF-8 without `NUL` bytes, and may end with a
+/// newline, which isn't stored.
+#[pin_data]
+pub struct Text<const N: usize> {
+    #[pin]
+    buf: Mutex<TextBuf<N>>,
+}
+
+impl<const N: usize> Text<N> {
+    /// Creates a string with the initial value `s`.
+    ///
+    /// It fails with `EINVAL` if `s` doesn't fit in `N` bytes or contains a `NUL` byte.
+    pub fn new(s: &str) -> impl PinInit<Self, Error> {
+        let buf = TextBuf::try_new(s.as_bytes());
+        try_pin_init!(Self {
+            buf <- crate::new_mutex!(buf?, "sysctl::Text::buf"),
+        })
+    }
+
+    /// Copies the string into a new [`CString`].
+    pub fn to_cstring(&self) -> Result<CString> {
+        CString::try_from(self.buf.lock().as_str())
+    }
+
+    /// Calls `f` with the string, which can't be changed until `f` returns.
+    pub fn with<R>(&self, f: impl FnOnce(&str) -> R) -> R {
+        f(self.buf.lock().as_str())
+    }
+
+    /// Sets the string to `s`.
+    ///
+    /// It fails with `EINVAL` if `s` doesn't fit in `N` bytes or contains a `NUL` byte.
+    pub fn set(&self, s: &str) -> Result {
+        self.store(s.as_bytes())
+    }
+}
+
+impl<const N: usize> fmt::Display for Text<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.buf.lock().as_str())
+    }
+}
+
+impl<const N: usize> Value for Text<N> {
+    fn store(&self, data: &BStr) -> Result {
+        let buf = TextBuf::try_new(data)?;
+        *self.buf.lock() = buf;
+        Ok(())
+    }
+}
+
+/// A value whose changes by user space are notified to a callback.
+///
+/// The callback is called with the value after every successful write, in the context of the
+/// writing task, which may sleep.
+#[pin_data]
+pub struct Notify<V, F> {
+    #[pin]
+    value: V,
+    notify: F,
+}
+
+impl<V: Value, F: Fn(&V) + Send + Sync + 'static> Notify<V, F> {
+    /// Creates a value initialised by `value`, whose changes are notified to `notify`.
+    pub fn new(value: impl PinInit<V, Error>, notify: F) -> impl PinInit<Self, Error> {
+        try_pin_init!(Self {
+            value <- value,
+            notify,
+        })
+    }
+
+    /// Returns the wrapped value.
+    pub fn value(&self) -> &V {
+        &self.value
+    }
+}
+
+impl<V: Value, F: Fn(&V) + Send + Sync + 'static> fmt::Display for Notify<V, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.value, f)
+    }
+}
+
+impl<V: Value, F: Fn(&V) + Send + Sync + 'static> Value for Notify<V, F> {
+    fn store(&self, data: &BStr) -> Result {
+        self.value.store(data)?;
+        (self.notify)(&self.value);
+        Ok(())
+    }
+}
+
+/// The files of a sysctl directory, to be registered with a [`Registration`].
+///
+/// The values are shared with the caller through [`Arc`], so that it can read them, and change
+/// them too, while the table is registered.
+///
+/// # Invariants
+///
+/// Each entry of `entries` is named by a string of `names`, and has a value of `values` as data,
+/// which its handler expects.
+pub struct Table {
+    entries: Vec<bindings::ctl_table>,
+    names: Vec<CString>,
+    values: Vec<Arc<dyn Any + Send + Sync>>,
+}
+
+// SAFETY: The entries only point to the names and values, which are owned by the table, and the
+// values are `Send` and `Sync`.
+unsafe impl Send for Table {}
+
+// SAFETY: `Table` has no methods that take `&self`.
+unsafe impl Sync for Table {}
+
+impl Table {
+    /// Creates an empty table.
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            names: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+
+    /// Adds a file named `name`, with the permissions `mode`, whose contents are `value`.
+    ///
+    /// `mode` is usually `0o644`, or `0o444` for read-only values.
+    pub fn add<V: Value>(&mut self, name: fmt::Arguments<'_>, mode: u16, value: Arc<V>) -> Result {
+        let name = CString::try_from_fmt(name)?;
+        // Keep room for the terminating entry, so that registration only fails in the C core.
+        self.entries.try_reserve(2)?;
+        self.names.try_reserve(1)?;
+        self.values.try_reserve(1)?;
+
+        // SAFETY: All-zeroes is a valid, empty entry.
+        let mut entry: bindings::ctl_table = unsafe { MaybeUninit::zeroed().assume_init() };
+        entry.procname = name.as_char_ptr();
+        entry.data = &*value as *const V as *mut c_void;
+        entry.mode = mode;
+        entry.proc_handler = Some(proc_handler::<V>);
+
+        // INVARIANT: The entry is named by `name` and has `value` as data, which `proc_handler`
+        // expects. Neither moves when the vectors grow, since they are heap-allocated.
+        self.entries.try_push(entry)?;
+        self.names.try_push(name)?;
+        self.values.try_push(value)?;
+        Ok(())
+    }
+
+    /// Terminates the entries with an empty one, as the sysctl core expects, and returns them.
+    fn terminated(&mut self) -> Result<*mut bindings::ctl_table> {
+        // SAFETY: All-zeroes is a valid, empty entry.
+        self.entries
+            .try_push(unsafe { MaybeUninit::zeroed().assume_init() })?;
+        Ok(self.entries.as_mut_ptr())
+    }
+}
+
+impl Default for Table {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Handles reads and writes of a file of a [`Table`].
+///
+/// # Safety
+///
+/// The data of `table` must be a `V` that outlives the call.
+unsafe extern "C" fn proc_handler<V: Value>(
+    table: *mut bindings::ctl_table,
+    write: c_int,
+    buffer: *mut c_void,
+    lenp: *mut usize,
+    ppos: *mut bindings::loff_t,
+) -> c_int {
+    from_result(|| {
+        // SAFETY: By the safety requirements, the data of `table` is a valid `V`.
+        let value = unsafe { &*((*table).data as *const V) };
+        // SAFETY: The sysctl core passes valid pointers to the length and position, which nothing
+        // else uses during the call.
+        let (len, pos) = unsafe { (&mut *lenp, &mut *ppos) };
+        if write != 0 {
+            if *pos != 0 {
+                return Err(EINVAL);
+            }
+            // SAFETY: The sysctl core passes a kernel buffer holding `*len` bytes from user space.
+            let data = unsafe { core::slice::from_raw_parts(buffer as *const u8, *len) };
+            value.store(data)?;
+        } else {
+            if *pos != 0 {
+                *len = 0;
+                return Ok(0);
+            }
+            // SAFETY: The sysctl core passes a kernel buffer valid for writes of `*len` bytes.
+            let mut f = unsafe { RawFormatter::from_buffer(buffer.cast(), *len) };
+            // `RawFormatter` truncates what doesn't fit in the buffer, like `proc_dostring` does.
+            writeln!(f, "{value}")?;
+            *len = f.bytes_written().min(*len);
+        }
+        *pos += *len as bindings::loff_t;
+        Ok(0)
+    })
+}
+
+/// A registered sysctl [`Table`].
+///
+/// The table is unregistered when this is dropped, which waits for the reads and writes of its
+/// files to complete.
+///
+/// # Invariants
+///
+/// `header` is the header of `table`, which is registered.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{fmt, prelude::*, sync::Arc, sysctl};
+///
+/// struct Tunables {
+///     debug: Arc<sysctl::Bool>,
+///     weights: Arc<sysctl::Vector<u8, 4>>,
+///     _queues: sysctl::Registration,
+///     _top: sysctl::Registration,
+/// }
+///
+/// fn register() -> Result<Tunables> {
+///     let debug = Arc::try_new(sysctl::Bool::new(false))?;
+///     let mut top = sysctl::Table::new();
+///     top.add(fmt!("debug"), 0o644, debug.clone())?;
+///     top.add(fmt!("name"), 0o644, Arc::pin_init(sysctl::Text::<32>::new("default"))?)?;
+///     let _top = sysctl::Registration::register(fmt!("dev/my_driver"), top)?;
+///
+///     let weights = Arc::try_new(sysctl::Vector::new([1u8, 1, 2, 4]))?;
+///     let mut queues = sysctl::Table::new();
+///     for i in 0..4 {
+///         let depth = sysctl::Integer::new(64u32).with_range(1, 1024);
+///         let depth = sysctl::Notify::new(depth, move |depth| {
+///             pr_info!("queue {} depth set to {}\n", i, depth.get());
+///         });
+///         queues.add(fmt!("depth{}", i), 0o644, Arc::pin_init(depth)?)?;
+///     }
+///     queues.add(fmt!("weights"), 0o644, weights.clone())?;
+///     let _queues = sysctl::Registration::register(fmt!("dev/my_driver/queues"), queues)?;
+///
+///     Ok(Tunables {
+///         debug,
+///         weights,
+///         _queues,
+///         _top,
+///     })
+/// }
+/// ```
+pub struct Registration {
+    header: NonNull<bindings::ctl_table_header>,
+    _table: Table,
+}
+
+// SAFETY: Tables can be unregistered from any thread, and `Table` is `Send`.
+unsafe impl Send for Registration {}
+
+// SAFETY: `Registration` has no methods that take `&self`.
+unsafe impl Sync for Registration {}
+
+impl Registration {
+    /// Registers `table` at `path`, relative to `/proc/sys`.
+    ///
+    /// `path` is a `/`-separated list of directories, e.g. `dev/my_driver`, which are created if
+    /// they don't exist yet. This may sleep.
+    pub fn register(path: fmt::Arguments<'_>, mut table: Table) -> Result<Self> {
+        crate::might_sleep!();
+        let path = CString::try_from_fmt(path)?;
+        let entries = table.terminated()?;
+        // SAFETY: `path` is a valid C string, which the sysctl core copies, and `entries` is a
+        // terminated array of entries whose names and data are owned by `table`, which is only
+        // dropped after the table is unregistered.
+        let header = unsafe { bindings::register_sysctl(path.as_char_ptr(), entries) };
+        // INVARIANT: The table was registered if `header` isn't null.
+        Ok(Self {
+            header: NonNull::new(header).ok_or(ENOMEM)?,
+            _table: table,
+        })
+    }
+
+    /// Registers `table` at `path`, relative to `/proc/sys`, in the network namespace `ns`.
+    ///
+    /// `path` is usually below `net`, e.g. `net/my_proto`. The table is only visible to the tasks
+    /// of `ns`, so each namespace registers its own table, with its own values, usually in
+    /// [`crate::net::PernetOperations::init`] and keeps it in its data. This may sleep.
+    #[cfg(CONFIG_NET)]
+    pub fn register_net(
+        ns: &crate::net::Namespace,
+        path: fmt::Arguments<'_>,
+        mut table: Table,
+    ) -> Result<Self> {
+        crate::might_sleep!();
+        let path = CString::try_from_fmt(path)?;
+        let entries = table.terminated()?;
+        // SAFETY: `ns` is a valid namespace, `path` is a valid C string, which the sysctl core
+        // copies, and `entries` is a terminated array of entries whose names and data are owned
+        // by `table`, which is only dropped after the table is unregistered.
+        let header =
+            unsafe { bindings::register_net_sysctl(ns.as_raw(), path.as_char_ptr(), entries) };
+        // INVARIANT: The table was registered if `header` isn't null.
+        Ok(Self {
+            header: NonNull::new(header).ok_or(ENOMEM)?,
+            _table: table,
+        })
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, the table is registered. This is also how
+        // `unregister_net_sysctl_table` unregisters tables of network namespaces.
+        unsafe { bindings::unregister_sysctl_table(self.header.as_ptr()) };
+    }
+}
@@ -0,0 +1,639 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! sysfs attributes and attribute groups.
+//!
+//! Attributes are declared as types implementing [`DeviceAttribute`], [`ClassAttribute`] or
+//! [`KobjAttribute`], which produce and consume typed values: formatting and parsing (with the
+//! `kstrto*` family) is done by [`AttributeValue`]. Attributes are then collected in statically
+//! allocated groups with the [`attribute_group!`] and [`attribute_groups!`] macros, which can be
+//! attached to devices, classes, misc devices and kobjects.
+//!
+//! C headers: [`include/linux/sysfs.h`](../../../../include/linux/sysfs.h) and
+//! [`include/linux/device.h`](../../../../include/linux/device.h)
+//!
+//! [`attribute_group!`]: crate::attribute_group
+//! [`attribute_groups!`]: crate::attribute_groups
+
+use crate::{
+    bindings, c_str,
+    device::Device,
+    error::{code::*, from_result, to_result, Result},
+    kobject::Kobject,
+    str::CStr,
+    types::{ARef, Opaque},
+};
+use core::{ffi::c_char, fmt, marker::PhantomData, ptr};
+use macros::vtable;
+
+/// A value that can be shown and stored through a sysfs attribute.
+pub trait AttributeValue: Sized {
+    /// Parses a value written by user space.
+    ///
+    /// `buf` holds what was written, usually including a trailing newline.
+    fn parse(buf: &CStr) -> Result<Self>;
+
+    /// Formats the value when user space reads the attribute.
+    ///
+    /// A newline is appended after the value, as is conventional in sysfs.
+    fn format(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result;
+}
+
+macro_rules! impl_int_value {
+    ($($t:ty => $kstrto:ident),* $(,)?) => {
+        $(
+            impl AttributeValue for $t {
+                fn parse(buf: &CStr) -> Result<Self> {
+                    let mut res = 0;
+                    // SAFETY: `buf` is a valid C string and `res` is a valid integer of the type
+                    // expected by the function. A base of 0 auto-detects the `0x` and `0`
+                    // prefixes.
+                    to_result(unsafe { bindings::$kstrto(buf.as_char_ptr(), 0, &mut res) })?;
+                    Ok(res as _)
+                }
+
+                fn format(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    fmt::Display::fmt(self, f)
+                }
+            }
+        )*
+    };
+}
+
+impl_int_value! {
+    u8 => kstrtou8,
+    u16 => kstrtou16,
+    u32 => kstrtouint,
+    u64 => kstrtoull,
+    i8 => kstrtos8,
+    i16 => kstrtos16,
+    i32 => kstrtoint,
+    i64 => kstrtoll,
+}
+
+impl AttributeValue for bool {
+    /// Accepts the spellings understood by `kstrtobool`, e.g. `1`/`0`, `y`/`n` and `on`/`off`.
+    fn parse(buf: &CStr) -> Result<Self> {
+        let mut res = false;
+        // SAFETY: `buf` is a valid C string and `res` is a valid `bool`.
+        to_result(unsafe { bindings::kstrtobool(buf.as_char_ptr(), &mut res) })?;
+        Ok(res)
+    }
+
+    /// Shows the value as `1` or `0`.
+    fn format(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&(*self as u8), f)
+    }
+}
+
+struct Show<'a, T: AttributeValue>(&'a T);
+
+impl<T: AttributeValue> fmt::Display for Show<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.format(f)
+    }
+}
+
+/// Writes `value` followed by a newline to the page-sized buffer passed to `show` callbacks.
+///
+/// # Safety
+///
+/// `buf` must be the buffer passed to a sysfs `show` callback.
+unsafe fn emit<T: AttributeValue>(buf: *mut c_char, value: &T) -> isize {
+    let value = Show(value);
+    let args = format_args!("{}", value);
+    // SAFETY: The caller guarantees that `buf` is a sysfs buffer, and passing a pointer to
+    // `Arguments` is valid for `%pA`.
+    unsafe {
+        bindings::sysfs_emit(
+            buf,
+            c_str!("%pA\n").as_char_ptr(),
+            &args as *const _ as *const core::ffi::c_void,
+        ) as _
+    }
+}
+
+/// Returns the conventional mode of an attribute, like `DEVICE_ATTR_RW`, `DEVICE_ATTR_RO` and
+/// `DEVICE_ATTR_WO` do in C.
+pub const fn default_mode(readable: bool, writable: bool) -> u16 {
+    match (readable, writable) {
+        (true, true) => 0o644,
+        (true, false) => 0o444,
+        (false, true) => 0o200,
+        (false, false) => 0,
+    }
+}
+
+/// Wraps the kernel's `struct attribute`.
+///
+/// This is the part common to all kinds of attributes, which is what attribute groups refer to.
+#[repr(transparent)]
+pub struct Attribute(Opaque<bindings::attribute>);
+
+// SAFETY: Attributes are never modified after they are initialised.
+unsafe impl Sync for Attribute {}
+
+/// Wraps the kernel's `struct attribute_group`.
+///
+/// Use the [`attribute_group!`] macro to declare one.
+///
+/// [`attribute_group!`]: crate::attribute_group
+#[repr(transparent)]
+pub struct AttributeGroup(Opaque<bindings::attribute_group>);
+
+// SAFETY: Attribute groups are never modified after they are initialised.
+unsafe impl Sync for AttributeGroup {}
+
+impl AttributeGroup {
+    /// Creates a new attribute group.
+    ///
+    /// The attributes appear in a subdirectory named `name` or, if it is `None`, directly in the
+    /// directory of the object the group is attached to. `attrs` must be terminated by `None`.
+    pub const fn new(
+        name: Option<&'static CStr>,
+        attrs: &'static [Option<&'static Attribute>],
+    ) -> Self {
+        if attrs.is_empty() || attrs[attrs.len() - 1].is_some() {
+            panic!("attribute list must be terminated by `None`");
+        }
+        Self(Opaque::new(bindings::attribute_group {
+            name: match name {
+                Some(name) => name.as_char_ptr(),
+                None => ptr::null(),
+            },
+            // `Option<&Attribute>` has the same layout as a nullable `*mut attribute`.
+            attrs: attrs.as_ptr() as *mut *mut bindings::attribute,
+            // SAFETY: The remaining fields are optional callbacks and arrays, for which `NULL`
+            // means "not present".
+            ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+        }))
+    }
+}
+
+/// A `NULL`-terminated array of attribute groups, as expected by `struct device`, `struct class`
+/// and `struct miscdevice`.
+///
+/// Use the [`attribute_groups!`] macro to declare one.
+///
+/// [`attribute_groups!`]: crate::attribute_groups
+pub struct AttributeGroups(&'static [Option<&'static AttributeGroup>]);
+
+impl AttributeGroups {
+    /// Creates a new array of attribute groups.
+    ///
+    /// `groups` must be terminated by `None`.
+    pub const fn new(groups: &'static [Option<&'static AttributeGroup>]) -> Self {
+        if groups.is_empty() || groups[groups.len() - 1].is_some() {
+            panic!("attribute group list must be terminated by `None`");
+        }
+        Self(groups)
+    }
+
+    /// Returns the raw `NULL`-terminated array.
+    pub fn as_ptr(&self) -> *mut *const bindings::attribute_group {
+        // `Option<&AttributeGroup>` has the same layout as a nullable `*const attribute_group`.
+        self.0.as_ptr() as _
+    }
+}
+
+/// Declares a static [`AttributeGroup`].
+///
+/// The group's name, if any, follows the group's identifier.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{attribute_group, c_str, device_attr};
+/// # use kernel::{device::Device, prelude::*, sysfs::DeviceAttribute};
+/// # struct Enable;
+/// # #[vtable]
+/// # impl DeviceAttribute for Enable {
+/// #     const NAME: &'static CStr = c_str!("enable");
+/// #     type Value = bool;
+/// #     fn show(_dev: &Device) -> Result<bool> { Ok(true) }
+/// # }
+///
+/// device_attr!(static DEV_ATTR_ENABLE = Enable);
+/// attribute_group!(static POWER_GROUP(c_str!("power")) = [DEV_ATTR_ENABLE]);
+/// ```
+#[macro_export]
+macro_rules! attribute_group {
+    ($vis:vis static $name:ident $(($group_name:expr))? = [$($attr:expr),* $(,)?]) => {
+        $vis static $name: $crate::sysfs::AttributeGroup = {
+            static ATTRS: &[::core::option::Option<&'static $crate::sysfs::Attribute>] =
+                &[$(::core::option::Option::Some($attr.attr()),)* ::core::option::Option::None];
+            #[allow(unused_mut, unused_assignments)]
+            let mut name = ::core::option::Option::None;
+            $(name = ::core::option::Option::Some($group_name);)?
+            $crate::sysfs::AttributeGroup::new(name, ATTRS)
+        };
+    };
+}
+
+/// Declares a static [`AttributeGroups`] array.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{attribute_group, attribute_groups};
+///
+/// attribute_group!(static EMPTY_GROUP = []);
+/// attribute_groups!(static GROUPS = [EMPTY_GROUP]);
+/// ```
+#[macro_export]
+macro_rules! attribute_groups {
+    ($vis:vis static $name:ident = [$($group:expr),* $(,)?]) => {
+        $vis static $name: $crate::sysfs::AttributeGroups = {
+            static GROUPS: &[::core::option::Option<&'static $crate::sysfs::AttributeGroup>] =
+                &[$(::core::option::Option::Some(&$group),)* ::core::option::Option::None];
+            $crate::sysfs::AttributeGroups::new(GROUPS)
+        };
+    };
+}
+
+/// An attribute of a device, the equivalent of `DEVICE_ATTR` in C.
+///
+/// The mode of the attribute follows from the implemented methods unless overridden, e.g.
+/// attributes with both [`DeviceAttribute::show`] and [`DeviceAttribute::store`] are `0644`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, device::Device, device_attr, sysfs::DeviceAttribute};
+/// # use kernel::prelude::*;
+/// # fn read_limit(_dev: &Device) -> u32 { 0 }
+/// # fn set_limit(_dev: &Device, _limit: u32) -> Result { Ok(()) }
+///
+/// struct CurrentLimit;
+///
+/// #[vtable]
+/// impl DeviceAttribute for CurrentLimit {
+///     const NAME: &'static CStr = c_str!("current_limit_ma");
+///     type Value = u32;
+///
+///     fn show(dev: &Device) -> Result<u32> {
+///         Ok(read_limit(dev))
+///     }
+///
+///     fn store(dev: &Device, limit: u32) -> Result {
+///         if limit > 1500 {
+///             return Err(EINVAL);
+///         }
+///         set_limit(dev, limit)
+///     }
+/// }
+///
+/// device_attr!(static DEV_ATTR_CURRENT_LIMIT = CurrentLimit);
+/// ```
+#[vtable]
+pub trait DeviceAttribute: 'static {
+    /// The name of the attribute file.
+    const NAME: &'static CStr;
+
+    /// The permissions of the attribute file.
+    const MODE: u16 = default_mode(Self::HAS_SHOW, Self::HAS_STORE);
+
+    /// The type of the value of the attribute.
+    type Value: AttributeValue;
+
+    /// Returns the current value of the attribute.
+    fn show(_dev: &Device) -> Result<Self::Value> {
+        Err(EIO)
+    }
+
+    /// Updates the value of the attribute with a value written by user space.
+    fn store(_dev: &Device, _value: Self::Value) -> Result {
+        Err(EIO)
+    }
+}
+
+/// Wraps the kernel's `struct device_attribute`.
+///
+/// Use the [`device_attr!`] macro to declare one.
+///
+/// [`device_attr!`]: crate::device_attr
+#[repr(transparent)]
+pub struct DeviceAttr(Opaque<bindings::device_attribute>);
+
+// SAFETY: Attributes are never modified after they are initialised.
+unsafe impl Sync for DeviceAttr {}
+
+impl DeviceAttr {
+    /// Creates a new device attribute implemented by `T`.
+    #[allow(clippy::needless_update)]
+    pub const fn new<T: DeviceAttribute>() -> Self {
+        Self(Opaque::new(bindings::device_attribute {
+            attr: bindings::attribute {
+                name: T::NAME.as_char_ptr(),
+                mode: T::MODE,
+                // SAFETY: The remaining fields are only used by lockdep, for which zero means
+                // "use the static key".
+                ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+            },
+            show: if T::HAS_SHOW {
+                Some(DeviceAttrVtable::<T>::show_callback)
+            } else {
+                None
+            },
+            store: if T::HAS_STORE {
+                Some(DeviceAttrVtable::<T>::store_callback)
+            } else {
+                None
+            },
+        }))
+    }
+
+    /// Returns the generic part of the attribute, to be added to an [`AttributeGroup`].
+    pub const fn attr(&'static self) -> &'static Attribute {
+        // SAFETY: `attr` is the first field of `struct device_attribute`, and `Attribute` is
+        // transparent over `struct attribute`.
+        unsafe { &*(Opaque::raw_get(&self.0) as *const Attribute) }
+    }
+}
+
+/// Declares a static [`DeviceAttr`] implemented by the given [`DeviceAttribute`].
+///
+/// See [`DeviceAttribute`] for an example.
+#[macro_export]
+macro_rules! device_attr {
+    ($vis:vis static $name:ident = $t:ty) => {
+        $vis static $name: $crate::sysfs::DeviceAttr = $crate::sysfs::DeviceAttr::new::<$t>();
+    };
+}
+
+struct DeviceAttrVtable<T>(PhantomData<T>);
+
+impl<T: DeviceAttribute> DeviceAttrVtable<T> {
+    unsafe extern "C" fn show_callback(
+        dev: *mut bindings::device,
+        _attr: *mut bindings::device_attribute,
+        buf: *mut c_char,
+    ) -> isize {
+        from_result(|| {
+            // SAFETY: The device is alive while its attributes are being accessed.
+            let value = T::show(unsafe { Device::as_ref(dev) })?;
+            // SAFETY: `buf` is the buffer passed to the `show` callback.
+            Ok(unsafe { emit(buf, &value) })
+        })
+    }
+
+    unsafe extern "C" fn store_callback(
+        dev: *mut bindings::device,
+        _attr: *mut bindings::device_attribute,
+        buf: *const c_char,
+        count: usize,
+    ) -> isize {
+        from_result(|| {
+            // SAFETY: sysfs guarantees that the buffer passed to `store` is `NUL`-terminated.
+            let value = T::Value::parse(unsafe { CStr::from_char_ptr(buf) })?;
+            // SAFETY: The device is alive while its attributes are being accessed.
+            T::store(unsafe { Device::as_ref(dev) }, value)?;
+            Ok(count as _)
+        })
+    }
+}
+
+/// An attribute of a device class, the equivalent of `CLASS_ATTR` in C.
+///
+/// Class attributes appear in `/sys/class/<class>/` and apply to the class as a whole.
+#[vtable]
+pub trait ClassAttribute: 'static {
+    /// The name of the attribute file.
+    const NAME: &'static CStr;
+
+    /// The permissions of the attribute file.
+    const MODE: u16 = default_mode(Self::HAS_SHOW, Self::HAS_STORE);
+
+    /// The type of the value of the attribute.
+    type Value: AttributeValue;
+
+    /// Returns the current value of the attribute.
+    fn show() -> Result<Self::Value> {
+        Err(EIO)
+    }
+
+    /// Updates the value of the attribute with a value written by user space.
+    fn store(_value: Self::Value) -> Result {
+        Err(EIO)
+    }
+}
+
+/// Wraps the kernel's `struct class_attribute`.
+///
+/// Use the [`class_attr!`] macro to declare one.
+///
+/// [`class_attr!`]: crate::class_attr
+#[repr(transparent)]
+pub struct ClassAttr(Opaque<bindings::class_attribute>);
+
+// SAFETY: Attributes are never modified after they are initialised.
+unsafe impl Sync for ClassAttr {}
+
+impl ClassAttr {
+    /// Creates a new class attribute implemented by `T`.
+    #[allow(clippy::needless_update)]
+    pub const fn new<T: ClassAttribute>() -> Self {
+        Self(Opaque::new(bindings::class_attribute {
+            attr: bindings::attribute {
+                name: T::NAME.as_char_ptr(),
+                mode: T::MODE,
+                // SAFETY: The remaining fields are only used by lockdep, for which zero means
+                // "use the static key".
+                ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+            },
+            show: if T::HAS_SHOW {
+                Some(ClassAttrVtable::<T>::show_callback)
+            } else {
+                None
+            },
+            store: if T::HAS_STORE {
+                Some(ClassAttrVtable::<T>::store_callback)
+            } else {
+                None
+            },
+        }))
+    }
+
+    /// Returns the generic part of the attribute, to be added to an [`AttributeGroup`].
+    pub const fn attr(&'static self) -> &'static Attribute {
+        // SAFETY: `attr` is the first field of `struct class_attribute`, and `Attribute` is
+        // transparent over `struct attribute`.
+        unsafe { &*(Opaque::raw_get(&self.0) as *const Attribute) }
+    }
+}
+
+/// Declares a static [`ClassAttr`] implemented by the given [`ClassAttribute`].
+#[macro_export]
+macro_rules! class_attr {
+    ($vis:vis static $name:ident = $t:ty) => {
+        $vis static $name: $crate::sysfs::ClassAttr = $crate::sysfs::ClassAttr::new::<$t>();
+    };
+}
+
+struct ClassAttrVtable<T>(PhantomData<T>);
+
+impl<T: ClassAttribute> ClassAttrVtable<T> {
+    unsafe extern "C" fn show_callback(
+        _class: *const bindings::class,
+        _attr: *const bindings::class_attribute,
+        buf: *mut c_char,
+    ) -> isize {
+        from_result(|| {
+            let value = T::show()?;
+            // SAFETY: `buf` is the buffer passed to the `show` callback.
+            Ok(unsafe { emit(buf, &value) })
+        })
+    }
+
+    unsafe extern "C" fn store_callback(
+        _class: *const bindings::class,
+        _attr: *const bindings::class_attribute,
+        buf: *const c_char,
+        count: usize,
+    ) -> isize {
+        from_result(|| {
+            // SAFETY: sysfs guarantees that the buffer passed to `store` is `NUL`-terminated.
+            T::store(T::Value::parse(unsafe { CStr::from_char_ptr(buf) })?)?;
+            Ok(count as _)
+        })
+    }
+}
+
+/// An attribute of a kobject, the equivalent of `__ATTR` with a `struct kobj_attribute` in C.
+///
+/// These are the attributes of kobjects created by [`Kobject::try_new`].
+///
+/// [`Kobject::try_new`]: crate::kobject::Kobject::try_new
+#[vtable]
+pub trait KobjAttribute: 'static {
+    /// The name of the attribute file.
+    const NAME: &'static CStr;
+
+    /// The permissions of the attribute file.
+    const MODE: u16 = default_mode(Self::HAS_SHOW, Self::HAS_STORE);
+
+    /// The type of the value of the attribute.
+    type Value: AttributeValue;
+
+    /// Returns the current value of the attribute.
+    fn show(_kobj: &Kobject) -> Result<Self::Value> {
+        Err(EIO)
+    }
+
+    /// Updates the value of the attribute with a value written by user space.
+    fn store(_kobj: &Kobject, _value: Self::Value) -> Result {
+        Err(EIO)
+    }
+}
+
+/// Wraps the kernel's `struct kobj_attribute`.
+///
+/// Use the [`kobj_attr!`] macro to declare one.
+///
+/// [`kobj_attr!`]: crate::kobj_attr
+#[repr(transparent)]
+pub struct KobjAttr(Opaque<bindings::kobj_attribute>);
+
+// SAFETY: Attributes are never modified after they are initialised.
+unsafe impl Sync for KobjAttr {}
+
+impl KobjAttr {
+    /// Creates a new kobject attribute implemented by `T`.
+    #[allow(clippy::needless_update)]
+    pub const fn new<T: KobjAttribute>() -> Self {
+        Self(Opaque::new(bindings::kobj_attribute {
+            attr: bindings::attribute {
+                name: T::NAME.as_char_ptr(),
+                mode: T::MODE,
+                // SAFETY: The remaining fields are only used by lockdep, for which zero means
+                // "use the static key".
+                ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+            },
+            show: if T::HAS_SHOW {
+                Some(KobjAttrVtable::<T>::show_callback)
+            } else {
+                None
+            },
+            store: if T::HAS_STORE {
+                Some(KobjAttrVtable::<T>::store_callback)
+            } else {
+                None
+            },
+        }))
+    }
+
+    /// Returns the generic part of the attribute, to be added to an [`AttributeGroup`].
+    pub const fn attr(&'static self) -> &'static Attribute {
+        // SAFETY: `attr` is the first field of `struct kobj_attribute`, and `Attribute` is
+        // transparent over `struct attribute`.
+        unsafe { &*(Opaque::raw_get(&self.0) as *const Attribute) }
+    }
+}
+
+/// Declares a static [`KobjAttr`] implemented by the given [`KobjAttribute`].
+#[macro_export]
+macro_rules! kobj_attr {
+    ($vis:vis static $name:ident = $t:ty) => {
+        $vis static $name: $crate::sysfs::KobjAttr = $crate::sysfs::KobjAttr::new::<$t>();
+    };
+}
+
+struct KobjAttrVtable<T>(PhantomData<T>);
+
+impl<T: KobjAttribute> KobjAttrVtable<T> {
+    unsafe extern "C" fn show_callback(
+        kobj: *mut bindings::kobject,
+        _attr: *mut bindings::kobj_attribute,
+        buf: *mut c_char,
+    ) -> isize {
+        from_result(|| {
+            // SAFETY: The kobject is alive while its attributes are being accessed.
+            let value = T::show(unsafe { Kobject::as_ref(kobj) })?;
+            // SAFETY: `buf` is the buffer passed to the `show` callback.
+            Ok(unsafe { emit(buf, &value) })
+        })
+    }
+
+    unsafe extern "C" fn store_callback(
+        kobj: *mut bindings::kobject,
+        _attr: *mut bindings::kobj_attribute,
+        buf: *const c_char,
+        count: usize,
+    ) -> isize {
+        from_result(|| {
+            // SAFETY: sysfs guarantees that the buffer passed to `store` is `NUL`-terminated.
+            let value = T::Value::parse(unsafe { CStr::from_char_ptr(buf) })?;
+            // SAFETY: The kobject is alive while its attributes are being accessed.
+            T::store(unsafe { Kobject::as_ref(kobj) }, value)?;
+            Ok(count as _)
+        })
+    }
+}
+
+/// Attribute groups added to an existing device.
+///
+/// The groups are removed when this is dropped. Prefer passing the groups when the device is
+/// created (e.g. with [`crate::miscdev::Options::groups`]) where possible, so that they are
+/// present when the `KOBJ_ADD` uevent is sent.
+pub struct DeviceGroups {
+    dev: ARef<Device>,
+    groups: &'static AttributeGroups,
+}
+
+impl DeviceGroups {
+    /// Adds `groups` to `dev`.
+    pub fn try_new(dev: &Device, groups: &'static AttributeGroups) -> Result<Self> {
+        // SAFETY: `dev` is valid by the type invariants, and `groups` is a static, valid array.
+        to_result(unsafe { bindings::device_add_groups(dev.as_raw(), groups.as_ptr()) })?;
+        Ok(Self {
+            dev: dev.into(),
+            groups,
+        })
+    }
+}
+
+impl Drop for DeviceGroups {
+    fn drop(&mut self) {
+        // SAFETY: The groups were added to the device in `try_new`.
+        unsafe { bindings::device_remove_groups(self.dev.as_raw(), self.groups.as_ptr()) };
+    }
+}
@@ -4,10 +4,23 @@
 //!
 //! C header: [`include/linux/sched.h`](../../../../include/linux/sched.h).
 
-use crate::{bindings, types::Opaque};
-use core::{marker::PhantomData, ops::Deref, ptr};
+use crate::{
+    bindings,
+    cred::UserNamespace,
+    error::{code::*, to_result, Result},
+    signal::Signal,
+    str::CStr,
+    types::{AlwaysRefCounted, Opaque},
+};
+use core::{cmp::Ordering, fmt, marker::PhantomData, ops::Deref, ptr};
 
 /// Returns the currently running task.
+///
+/// This is the safe way of getting hold of the current task: the returned reference cannot
+/// outlive the caller, so it is guaranteed to remain valid. Use [`ARef`] (via `.into()`) to keep
+/// a reference to the task beyond that, e.g. past the end of a syscall.
+///
+/// [`ARef`]: crate::types::ARef
 #[macro_export]
 macro_rules! current {
     () => {
@@ -17,6 +30,48 @@ macro_rules! current {
     };
 }
 
+/// Annotates a function that may sleep.
+///
+/// This is the equivalent of `might_sleep()` in C: with `CONFIG_DEBUG_ATOMIC_SLEEP` enabled, it
+/// warns (with the caller's file and line) if invoked from atomic context, e.g. from an interrupt
+/// handler or with a spinlock held, even on paths where the function doesn't actually end up
+/// sleeping. It is also a voluntary preemption point on `CONFIG_PREEMPT_VOLUNTARY` kernels.
+///
+/// All sleeping abstractions in the `kernel` crate call this on entry.
+///
+/// # Examples
+///
+/// ```
+/// fn wait_for_hardware() {
+///     kernel::might_sleep!();
+///     // ...
+/// }
+/// ```
+#[macro_export]
+macro_rules! might_sleep {
+    () => {
+        $crate::task::might_sleep_at($crate::c_str!(::core::file!()), ::core::line!())
+    };
+}
+
+/// Implementation of [`might_sleep`].
+///
+/// Public but hidden since it should only be used from the [`might_sleep`] macro.
+#[doc(hidden)]
+#[inline]
+#[cfg_attr(not(CONFIG_DEBUG_ATOMIC_SLEEP), allow(unused_variables))]
+pub fn might_sleep_at(file: &'static CStr, line: u32) {
+    #[cfg(CONFIG_DEBUG_ATOMIC_SLEEP)]
+    {
+        // SAFETY: `file` is a `NUL`-terminated string that lives forever.
+        unsafe { bindings::__might_sleep(file.as_char_ptr(), line as _) };
+        crate::context::report_section();
+    }
+
+    // SAFETY: FFI call with no additional requirements.
+    unsafe { bindings::might_resched() };
+}
+
 /// Wraps the kernel's `struct task_struct`.
 ///
 /// # Invariants
@@ -41,6 +96,14 @@ macro_rules! current {
 /// let pid = current!().group_leader().pid();
 /// ```
 ///
+/// Recording who issued a request, e.g. for bookkeeping or access control:
+///
+/// ```
+/// let task = current!();
+/// pr_info!("request from {} (pid {}, tgid {})\n", task.comm(), task.pid(), task.tgid());
+/// let owner = task.euid();
+/// ```
+///
 /// Getting the current task and storing it in some struct. The reference count is automatically
 /// incremented when creating `State` and decremented when it is dropped:
 ///
@@ -76,7 +139,215 @@ unsafe impl Send for Task {}
 unsafe impl Sync for Task {}
 
 /// The type of process identifiers (PIDs).
-type Pid = bindings::pid_t;
+pub type Pid = bindings::pid_t;
+
+/// The type of user identifiers (UIDs) as seen from the kernel.
+///
+/// Wraps the kernel's `kuid_t`, which is the UID in the initial user namespace. It must be
+/// translated before being reported to userspace.
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+pub struct Kuid {
+    kuid: bindings::kuid_t,
+}
+
+impl Kuid {
+    /// Creates a `Kuid` from the raw `kuid_t`.
+    pub fn from_raw(kuid: bindings::kuid_t) -> Self {
+        Self { kuid }
+    }
+
+    /// Returns the raw `kuid_t`.
+    pub fn into_raw(self) -> bindings::kuid_t {
+        self.kuid
+    }
+
+    /// Maps a UID as seen from the user namespace `ns` to a `Kuid`.
+    ///
+    /// Returns `None` if `uid` has no mapping in `ns`.
+    pub fn from_uid_in(ns: &UserNamespace, uid: bindings::uid_t) -> Option<Self> {
+        // SAFETY: `ns` is valid by its type invariants.
+        let kuid = unsafe { bindings::make_kuid(ns.as_ptr(), uid) };
+        // SAFETY: FFI call with no additional requirements.
+        if unsafe { bindings::uid_valid(kuid) } {
+            Some(Self { kuid })
+        } else {
+            None
+        }
+    }
+
+    /// Translates the `Kuid` into the UID seen from the user namespace `ns`.
+    ///
+    /// Returns `None` if the `Kuid` has no mapping in `ns`.
+    pub fn into_uid_in(self, ns: &UserNamespace) -> Option<bindings::uid_t> {
+        // SAFETY: `ns` is valid by its type invariants.
+        let uid = unsafe { bindings::from_kuid(ns.as_ptr(), self.kuid) };
+        if uid == bindings::uid_t::MAX {
+            None
+        } else {
+            Some(uid)
+        }
+    }
+
+    /// Translates the `Kuid` into the UID seen from the current task's user namespace.
+    ///
+    /// This is the value to report to userspace (e.g. in ioctl replies or fdinfo). Unmapped IDs
+    /// are reported as the overflow UID (usually `65534`), as `from_kuid_munged` does.
+    pub fn into_uid_in_current(self) -> bindings::uid_t {
+        // SAFETY: `current_user_ns` always returns a valid namespace.
+        unsafe { bindings::from_kuid_munged(bindings::current_user_ns(), self.kuid) }
+    }
+}
+
+impl PartialEq for Kuid {
+    fn eq(&self, other: &Kuid) -> bool {
+        // Equivalent to `uid_eq`.
+        self.kuid.val == other.kuid.val
+    }
+}
+
+impl Eq for Kuid {}
+
+impl PartialOrd for Kuid {
+    fn partial_cmp(&self, other: &Kuid) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Kuid {
+    fn cmp(&self, other: &Kuid) -> Ordering {
+        // Equivalent to `uid_gt`/`uid_lt`.
+        self.kuid.val.cmp(&other.kuid.val)
+    }
+}
+
+/// The type of group identifiers (GIDs) as seen from the kernel.
+///
+/// Wraps the kernel's `kgid_t`, which is the GID in the initial user namespace. It must be
+/// translated before being reported to userspace.
+#[repr(transparent)]
+#[derive(Clone, Copy)]
+pub struct Kgid {
+    kgid: bindings::kgid_t,
+}
+
+impl Kgid {
+    /// Creates a `Kgid` from the raw `kgid_t`.
+    pub fn from_raw(kgid: bindings::kgid_t) -> Self {
+        Self { kgid }
+    }
+
+    /// Returns the raw `kgid_t`.
+    pub fn into_raw(self) -> bindings::kgid_t {
+        self.kgid
+    }
+
+    /// Maps a GID as seen from the user namespace `ns` to a `Kgid`.
+    ///
+    /// Returns `None` if `gid` has no mapping in `ns`.
+    pub fn from_gid_in(ns: &UserNamespace, gid: bindings::gid_t) -> Option<Self> {
+        // SAFETY: `ns` is valid by its type invariants.
+        let kgid = unsafe { bindings::make_kgid(ns.as_ptr(), gid) };
+        // SAFETY: FFI call with no additional requirements.
+        if unsafe { bindings::gid_valid(kgid) } {
+            Some(Self { kgid })
+        } else {
+            None
+        }
+    }
+
+    /// Translates the `Kgid` into the GID seen from the user namespace `ns`.
+    ///
+    /// Returns `None` if the `Kgid` has no mapping in `ns`.
+    pub fn into_gid_in(self, ns: &UserNamespace) -> Option<bindings::gid_t> {
+        // SAFETY: `ns` is valid by its type invariants.
+        let gid = unsafe { bindings::from_kgid(ns.as_ptr(), self.kgid) };
+        if gid == bindings::gid_t::MAX {
+            None
+        } else {
+            Some(gid)
+        }
+    }
+
+    /// Translates the `Kgid` into the GID seen from the current task's user namespace.
+    ///
+    /// Unmapped IDs are reported as the overflow GID, as `from_kgid_munged` does.
+    pub fn into_gid_in_current(self) -> bindings::gid_t {
+        // SAFETY: `current_user_ns` always returns a valid namespace.
+        unsafe { bindings::from_kgid_munged(bindings::current_user_ns(), self.kgid) }
+    }
+}
+
+impl PartialEq for Kgid {
+    fn eq(&self, other: &Kgid) -> bool {
+        // Equivalent to `gid_eq`.
+        self.kgid.val == other.kgid.val
+    }
+}
+
+impl Eq for Kgid {}
+
+/// Wraps the kernel's `struct pid_namespace`.
+///
+/// # Invariants
+///
+/// Instances are always ref-counted, that is, a call to `get_pid_ns` ensures that the allocation
+/// remains valid at least until the matching call to `put_pid_ns`.
+#[repr(transparent)]
+pub struct PidNamespace(Opaque<bindings::pid_namespace>);
+
+// SAFETY: PID namespaces are reference-counted and can be released from any thread.
+unsafe impl Send for PidNamespace {}
+
+// SAFETY: The PID translation functions that use `PidNamespace` are safe to call concurrently.
+unsafe impl Sync for PidNamespace {}
+
+impl PidNamespace {
+    /// Returns the raw `struct pid_namespace` pointer.
+    pub fn as_ptr(&self) -> *mut bindings::pid_namespace {
+        self.0.get()
+    }
+}
+
+// SAFETY: The type invariants guarantee that `PidNamespace` is always ref-counted.
+unsafe impl AlwaysRefCounted for PidNamespace {
+    fn inc_ref(&self) {
+        // SAFETY: The existence of a shared reference means that the refcount is nonzero.
+        unsafe { bindings::get_pid_ns(self.0.get()) };
+    }
+
+    unsafe fn dec_ref(obj: ptr::NonNull<Self>) {
+        // SAFETY: The safety requirements guarantee that the refcount is nonzero.
+        unsafe { bindings::put_pid_ns(obj.cast().as_ptr()) }
+    }
+}
+
+/// The name of the executable of a task, as stored in `task_struct::comm`.
+///
+/// This is a snapshot: the name may change (e.g. via `prctl(PR_SET_NAME)`) after it is taken.
+pub struct Comm {
+    buf: [u8; bindings::TASK_COMM_LEN as usize],
+}
+
+impl Comm {
+    /// Returns the name as a C string.
+    pub fn as_cstr(&self) -> &CStr {
+        // `__get_task_comm` always `NUL`-terminates the buffer, so `len` is in bounds.
+        let len = self
+            .buf
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(self.buf.len() - 1);
+        // SAFETY: `buf[len]` is the first `NUL` in the buffer.
+        unsafe { CStr::from_bytes_with_nul_unchecked(&self.buf[..=len]) }
+    }
+}
+
+impl fmt::Display for Comm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_cstr(), f)
+    }
+}
 
 impl Task {
     /// Returns a task reference for the currently executing task/thread.
@@ -132,12 +403,121 @@ pub fn pid(&self) -> Pid {
         unsafe { *ptr::addr_of!((*self.0.get()).pid) }
     }
 
+    /// Returns the thread group ID (the PID of the process) of the given task.
+    pub fn tgid(&self) -> Pid {
+        // SAFETY: By the type invariant, we know that `self.0` is a valid task. Valid tasks always
+        // have a valid tgid.
+        unsafe { *ptr::addr_of!((*self.0.get()).tgid) }
+    }
+
+    /// Returns the PID of the given task as seen from the current task's PID namespace.
+    ///
+    /// This is the value to report to userspace. It is zero if the task isn't visible from the
+    /// current namespace.
+    pub fn pid_vnr(&self) -> Pid {
+        // SAFETY: By the type invariant, we know that `self.0` is valid.
+        unsafe { bindings::task_pid_vnr(self.0.get()) }
+    }
+
+    /// Returns the TGID of the given task as seen from the current task's PID namespace.
+    ///
+    /// It is zero if the task isn't visible from the current namespace.
+    pub fn tgid_vnr(&self) -> Pid {
+        // SAFETY: By the type invariant, we know that `self.0` is valid.
+        unsafe { bindings::task_tgid_vnr(self.0.get()) }
+    }
+
+    /// Returns the PID of the given task as seen from the PID namespace `ns`.
+    ///
+    /// It is zero if the task isn't visible from `ns`.
+    pub fn pid_in_ns(&self, ns: &PidNamespace) -> Pid {
+        // SAFETY: By the type invariant, we know that `self.0` is valid, and `ns` is valid by its
+        // own type invariant.
+        unsafe { bindings::task_pid_nr_ns(self.0.get(), ns.as_ptr()) }
+    }
+
+    /// Returns the PID namespace the given task belongs to.
+    ///
+    /// Returns `None` if the task is exiting and has already detached from its namespace.
+    pub fn active_pid_ns(&self) -> Option<crate::types::ARef<PidNamespace>> {
+        // SAFETY: By the type invariant, we know that `self.0` is valid.
+        let ns = unsafe { bindings::task_active_pid_ns(self.0.get()) };
+        if ns.is_null() {
+            return None;
+        }
+        // SAFETY: `ns` is non-null, and it is valid until the task is released, which can't
+        // happen while we hold `&self`. Converting to `ARef` takes a new reference.
+        Some(unsafe { &*ns.cast::<PidNamespace>() }.into())
+    }
+
+    /// Returns the name of the executable of the given task.
+    pub fn comm(&self) -> Comm {
+        let mut comm = Comm {
+            buf: [0; bindings::TASK_COMM_LEN as usize],
+        };
+        // SAFETY: By the type invariant, we know that `self.0` is valid. `comm.buf` is valid for
+        // writes of its length, and `__get_task_comm` takes the task lock while copying.
+        unsafe {
+            bindings::__get_task_comm(comm.buf.as_mut_ptr().cast(), comm.buf.len(), self.0.get())
+        };
+        comm
+    }
+
+    /// Returns the real UID of the given task.
+    pub fn uid(&self) -> Kuid {
+        // SAFETY: By the type invariant, we know that `self.0` is valid.
+        Kuid::from_raw(unsafe { bindings::task_uid(self.0.get()) })
+    }
+
+    /// Returns the effective UID of the given task.
+    pub fn euid(&self) -> Kuid {
+        // SAFETY: By the type invariant, we know that `self.0` is valid.
+        Kuid::from_raw(unsafe { bindings::task_euid(self.0.get()) })
+    }
+
     /// Determines whether the given task has pending signals.
     pub fn signal_pending(&self) -> bool {
         // SAFETY: By the type invariant, we know that `self.0` is valid.
         unsafe { bindings::signal_pending(self.0.get()) != 0 }
     }
 
+    /// Sends a signal to this thread only.
+    ///
+    /// The signal is sent on behalf of the kernel (as `SEND_SIG_PRIV`), so no permission checks
+    /// are performed. Fails with `ESRCH` if the thread is exiting.
+    pub fn send_signal(&self, sig: Signal) -> Result {
+        // SAFETY: By the type invariant, we know that `self.0` is valid.
+        to_result(unsafe { bindings::send_sig(sig.to_raw(), self.0.get(), 1) })
+    }
+
+    /// Sends a signal to the whole thread group (process) this task belongs to.
+    ///
+    /// The signal is sent on behalf of the kernel (as `SEND_SIG_PRIV`), so no permission checks
+    /// are performed. Use this when the kernel itself is the originator, for example to report a
+    /// hardware fault to the process that owns a device.
+    pub fn send_group_signal(&self, sig: Signal) -> Result {
+        self.kill_group(sig, true)
+    }
+
+    /// Sends a signal to the whole thread group on behalf of the current task.
+    ///
+    /// The same permission checks as for `kill(2)` are applied, using the credentials of the
+    /// current task: this fails with `EPERM` if the current task isn't allowed to signal this one.
+    pub fn send_group_signal_checked(&self, sig: Signal) -> Result {
+        self.kill_group(sig, false)
+    }
+
+    fn kill_group(&self, sig: Signal, privileged: bool) -> Result {
+        // SAFETY: By the type invariant, we know that `self.0` is valid.
+        let pid = unsafe { bindings::task_tgid(self.0.get()) };
+        if pid.is_null() {
+            return Err(ESRCH);
+        }
+        // SAFETY: `pid` is the thread group pid of a valid task. `kill_pid` looks it up under RCU
+        // and copes with the process having exited in the meantime.
+        to_result(unsafe { bindings::kill_pid(pid, sig.to_raw(), privileged as _) })
+    }
+
     /// Wakes up the task.
     pub fn wake_up(&self) {
         // SAFETY: By the type invariant, we know that `self.0.get()` is non-null and valid.
@@ -0,0 +1,531 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Thermal zones and cooling devices.
+//!
+//! Temperature sensor drivers register a [`ZoneRegistration`], either with their own trip points
+//! or bound to a thermal zone of the devicetree, and drivers of throttling mechanisms, e.g. fans
+//! or clock limiters, register a [`CoolingRegistration`]. Temperatures are in millicelsius.
+//!
+//! C header: [`include/linux/thermal.h`](../../../../include/linux/thermal.h)
+
+use crate::{
+    bindings,
+    error::{code::*, from_err_ptr, from_result, to_result, Result},
+    str::CStr,
+    types::Opaque,
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    ffi::{c_int, c_ulong, c_void},
+    marker::{PhantomData, PhantomPinned},
+    mem::MaybeUninit,
+    pin::Pin,
+    ptr,
+};
+use macros::vtable;
+
+/// The kind of a trip point, the kernel's `enum thermal_trip_type`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TripType {
+    /// Active cooling, e.g. a fan, is started.
+    Active,
+    /// Passive cooling, e.g. clock throttling, is started.
+    Passive,
+    /// The zone is hot, which is reported to user space.
+    Hot,
+    /// The zone is critical, and the system is shut down.
+    Critical,
+}
+
+impl TripType {
+    fn as_raw(self) -> bindings::thermal_trip_type {
+        match self {
+            Self::Active => bindings::thermal_trip_type_THERMAL_TRIP_ACTIVE,
+            Self::Passive => bindings::thermal_trip_type_THERMAL_TRIP_PASSIVE,
+            Self::Hot => bindings::thermal_trip_type_THERMAL_TRIP_HOT,
+            Self::Critical => bindings::thermal_trip_type_THERMAL_TRIP_CRITICAL,
+        }
+    }
+}
+
+/// A trip point of a thermal zone, the kernel's `struct thermal_trip`.
+#[derive(Clone, Copy, Debug)]
+pub struct Trip {
+    /// The temperature that the trip point is crossed at.
+    pub temperature: i32,
+    /// How far the temperature must fall below the trip point for it to be crossed back.
+    pub hysteresis: i32,
+    /// The kind of the trip point.
+    pub kind: TripType,
+}
+
+/// The operations of a thermal zone, the kernel's `struct thermal_zone_device_ops`.
+///
+/// The driver data of the zone implements this trait.
+#[vtable]
+pub trait ZoneOperations: Send + Sync + Sized + 'static {
+    /// Returns the temperature of the zone.
+    fn get_temp(tz: &Zone<Self>) -> Result<i32>;
+
+    /// Requests an update of the zone, e.g. through an interrupt, when its temperature leaves
+    /// the range from `low` to `high`.
+    fn set_trips(_tz: &Zone<Self>, _low: i32, _high: i32) -> Result {
+        Err(EINVAL)
+    }
+}
+
+/// A thermal zone, the kernel's `struct thermal_zone_device`.
+///
+/// # Invariants
+///
+/// The zone is valid while references to it exist, and its driver data is a
+/// `ZoneRegistration<T>`.
+#[repr(transparent)]
+pub struct Zone<T: ZoneOperations>(Opaque<bindings::thermal_zone_device>, PhantomData<T>);
+
+impl<T: ZoneOperations> Zone<T> {
+    /// Creates a reference to a [`Zone`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is a zone registered by a `ZoneRegistration<T>` for the
+    /// lifetime of the returned reference.
+    unsafe fn from_raw<'a>(ptr: *mut bindings::thermal_zone_device) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct thermal_zone_device` pointer.
+    pub fn as_raw(&self) -> *mut bindings::thermal_zone_device {
+        self.0.get()
+    }
+
+    /// Returns the driver data of the zone.
+    pub fn data(&self) -> &T {
+        // SAFETY: The driver data of the zone is a `ZoneRegistration<T>` by the type invariants,
+        // which outlives the zone.
+        unsafe {
+            let reg = bindings::thermal_zone_device_priv(self.as_raw());
+            &(*reg.cast::<ZoneRegistration<T>>()).data
+        }
+    }
+
+    /// Updates the zone, e.g. when the temperature crossed a limit requested by
+    /// [`ZoneOperations::set_trips`].
+    ///
+    /// This may sleep.
+    pub fn update(&self) {
+        crate::might_sleep!();
+        // SAFETY: The zone is valid by the type invariants.
+        unsafe {
+            bindings::thermal_zone_device_update(
+                self.as_raw(),
+                bindings::thermal_notify_event_THERMAL_EVENT_UNSPECIFIED,
+            )
+        };
+    }
+}
+
+// SAFETY: The methods that take `&self` are safe to call concurrently, and the driver data is
+// `Sync`.
+unsafe impl<T: ZoneOperations> Sync for Zone<T> {}
+
+/// A registered thermal zone.
+///
+/// The zone is unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `tz` is null until the zone is registered, and then a zone whose driver data is this
+/// `ZoneRegistration<T>`, registered from the devicetree if `of` is `true`. `ops` and `trips`
+/// don't change while the zone is registered.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, prelude::*};
+/// use kernel::thermal::{Trip, TripType, Zone, ZoneOperations, ZoneRegistration};
+///
+/// struct Sensor;
+///
+/// #[vtable]
+/// impl ZoneOperations for Sensor {
+///     fn get_temp(_tz: &Zone<Self>) -> Result<i32> {
+///         Ok(45000)
+///     }
+/// }
+///
+/// fn probe() -> Result<Pin<Box<ZoneRegistration<Sensor>>>> {
+///     let trips = [Trip {
+///         temperature: 105000,
+///         hysteresis: 0,
+///         kind: TripType::Critical,
+///     }];
+///     ZoneRegistration::register(c_str!("soc-thermal"), &trips, 0, 1000, Sensor)
+/// }
+/// ```
+pub struct ZoneRegistration<T: ZoneOperations> {
+    tz: *mut bindings::thermal_zone_device,
+    #[cfg(CONFIG_THERMAL_OF)]
+    of: bool,
+    ops: bindings::thermal_zone_device_ops,
+    trips: Vec<bindings::thermal_trip>,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: ZoneOperations> ZoneRegistration<T> {
+    fn new(trips: &[Trip], data: T) -> Result<Pin<Box<Self>>> {
+        let mut raw_trips = Vec::try_with_capacity(trips.len())?;
+        for trip in trips {
+            raw_trips.try_push(bindings::thermal_trip {
+                temperature: trip.temperature,
+                hysteresis: trip.hysteresis,
+                type_: trip.kind.as_raw(),
+            })?;
+        }
+
+        // SAFETY: All other fields are optional, for which zero is valid.
+        let mut ops: bindings::thermal_zone_device_ops =
+            unsafe { MaybeUninit::zeroed().assume_init() };
+        ops.get_temp = Some(Self::get_temp_callback);
+        if T::HAS_SET_TRIPS {
+            ops.set_trips = Some(Self::set_trips_callback);
+        }
+
+        Ok(Pin::from(Box::try_new(Self {
+            tz: ptr::null_mut(),
+            #[cfg(CONFIG_THERMAL_OF)]
+            of: false,
+            ops,
+            trips: raw_trips,
+            data,
+            _pin: PhantomPinned,
+        })?))
+    }
+
+    /// Registers and enables a thermal zone named `name`, with the trip points `trips` and the
+    /// driver data `data`.
+    ///
+    /// The temperature is polled every `polling_delay` milliseconds, or every `passive_delay`
+    /// milliseconds while passive cooling is active, unless these are zero.
+    pub fn register(
+        name: &CStr,
+        trips: &[Trip],
+        passive_delay: u32,
+        polling_delay: u32,
+        data: T,
+    ) -> Result<Pin<Box<Self>>> {
+        crate::might_sleep!();
+        let mut reg = Self::new(trips, data)?;
+        let this: *const Self = &*reg;
+        // SAFETY: The trip points and the ops are in the pinned registration, which unregisters
+        // the zone before they are freed or changed, and so is the driver data. The name is
+        // copied.
+        let tz = from_err_ptr(unsafe {
+            bindings::thermal_zone_device_register_with_trips(
+                name.as_char_ptr(),
+                (*this).trips.as_ptr() as *mut _,
+                (*this).trips.len() as _,
+                0,
+                this as *mut c_void,
+                &(*this).ops as *const _ as *mut _,
+                ptr::null_mut(),
+                passive_delay as _,
+                polling_delay as _,
+            )
+        })?;
+        // INVARIANT: The zone was registered above, with this as its driver data.
+        // SAFETY: `tz` isn't structurally pinned.
+        unsafe { reg.as_mut().get_unchecked_mut() }.tz = tz;
+        // SAFETY: The zone was registered above.
+        to_result(unsafe { bindings::thermal_zone_device_enable(tz) })?;
+        Ok(reg)
+    }
+
+    /// Registers the thermal zone of the devicetree that refers to the sensor `id` of the node
+    /// `np`, with the driver data `data`.
+    ///
+    /// The trip points, cooling maps and polling delays are described by the devicetree. Fails
+    /// with `ENODEV` if no thermal zone refers to the sensor.
+    #[cfg(CONFIG_THERMAL_OF)]
+    pub fn register_of(np: &crate::of::DeviceNode, id: u32, data: T) -> Result<Pin<Box<Self>>> {
+        crate::might_sleep!();
+        let mut reg = Self::new(&[], data)?;
+        let this: *const Self = &*reg;
+        // SAFETY: `np` is valid, and the ops are copied. The driver data is pinned, and the zone
+        // is unregistered before it is freed.
+        let tz = from_err_ptr(unsafe {
+            bindings::thermal_of_zone_register(
+                np.as_raw(),
+                id as _,
+                this as *mut c_void,
+                &(*this).ops,
+            )
+        })?;
+        // INVARIANT: The zone was registered above, from the devicetree, with this as its
+        // driver data.
+        // SAFETY: `tz` and `of` aren't structurally pinned.
+        let reg_mut = unsafe { reg.as_mut().get_unchecked_mut() };
+        reg_mut.tz = tz;
+        reg_mut.of = true;
+        Ok(reg)
+    }
+
+    /// Returns the thermal zone.
+    pub fn zone(&self) -> &Zone<T> {
+        // SAFETY: The zone is registered once this is returned by `register` or `register_of`,
+        // by the type invariants, and its driver data is `self`.
+        unsafe { Zone::from_raw(self.tz) }
+    }
+
+    /// Returns the driver data of the zone.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    unsafe extern "C" fn get_temp_callback(
+        tz: *mut bindings::thermal_zone_device,
+        temp: *mut c_int,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The thermal core calls this with a zone registered by `register` or
+            // `register_of`.
+            let value = T::get_temp(unsafe { Zone::from_raw(tz) })?;
+            // SAFETY: The thermal core passes a valid pointer to write the temperature to.
+            unsafe { *temp = value };
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn set_trips_callback(
+        tz: *mut bindings::thermal_zone_device,
+        low: c_int,
+        high: c_int,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The thermal core calls this with a zone registered by `register` or
+            // `register_of`.
+            T::set_trips(unsafe { Zone::from_raw(tz) }, low, high)?;
+            Ok(0)
+        })
+    }
+}
+
+impl<T: ZoneOperations> Drop for ZoneRegistration<T> {
+    fn drop(&mut self) {
+        if self.tz.is_null() {
+            return;
+        }
+
+        // SAFETY: The zone was registered, from the devicetree if `of` is `true`, by the type
+        // invariants.
+        unsafe {
+            #[cfg(CONFIG_THERMAL_OF)]
+            if self.of {
+                bindings::thermal_of_zone_unregister(self.tz);
+                return;
+            }
+            bindings::thermal_zone_device_unregister(self.tz);
+        }
+    }
+}
+
+// SAFETY: The zone can be unregistered from any thread, and the driver data is `Send`.
+unsafe impl<T: ZoneOperations> Send for ZoneRegistration<T> {}
+
+// SAFETY: The methods that take `&self` only read fields that never change, and the driver data
+// is `Sync`.
+unsafe impl<T: ZoneOperations> Sync for ZoneRegistration<T> {}
+
+/// The operations of a cooling device, the kernel's `struct thermal_cooling_device_ops`.
+///
+/// The driver data of the device implements this trait. The cooling states go from zero, for no
+/// cooling, to the maximum state, for the most cooling.
+#[vtable]
+pub trait CoolingOperations: Send + Sync + Sized + 'static {
+    /// Returns the maximum cooling state of the device.
+    fn get_max_state(cdev: &CoolingDevice<Self>) -> Result<u64>;
+
+    /// Returns the current cooling state of the device.
+    fn get_cur_state(cdev: &CoolingDevice<Self>) -> Result<u64>;
+
+    /// Sets the cooling state of the device.
+    fn set_cur_state(cdev: &CoolingDevice<Self>, state: u64) -> Result;
+}
+
+/// A cooling device, the kernel's `struct thermal_cooling_device`.
+///
+/// # Invariants
+///
+/// The device is valid while references to it exist, and its driver data is a
+/// `CoolingRegistration<T>`.
+#[repr(transparent)]
+pub struct CoolingDevice<T: CoolingOperations>(
+    Opaque<bindings::thermal_cooling_device>,
+    PhantomData<T>,
+);
+
+impl<T: CoolingOperations> CoolingDevice<T> {
+    /// Creates a reference to a [`CoolingDevice`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is a device registered by a `CoolingRegistration<T>` for
+    /// the lifetime of the returned reference.
+    unsafe fn from_raw<'a>(ptr: *mut bindings::thermal_cooling_device) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct thermal_cooling_device` pointer.
+    pub fn as_raw(&self) -> *mut bindings::thermal_cooling_device {
+        self.0.get()
+    }
+
+    /// Returns the driver data of the device.
+    pub fn data(&self) -> &T {
+        // SAFETY: The driver data of the device is a `CoolingRegistration<T>` by the type
+        // invariants, which outlives the device.
+        unsafe { &(*(*self.as_raw()).devdata.cast::<CoolingRegistration<T>>()).data }
+    }
+}
+
+// SAFETY: The driver data is `Sync`, and nothing else is accessed through `&self`.
+unsafe impl<T: CoolingOperations> Sync for CoolingDevice<T> {}
+
+/// A registered cooling device.
+///
+/// The device is unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `cdev` is null until the device is registered, and then a cooling device whose driver data is
+/// this `CoolingRegistration<T>`.
+pub struct CoolingRegistration<T: CoolingOperations> {
+    cdev: *mut bindings::thermal_cooling_device,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: CoolingOperations> CoolingRegistration<T> {
+    const OPS: bindings::thermal_cooling_device_ops = bindings::thermal_cooling_device_ops {
+        get_max_state: Some(Self::get_max_state_callback),
+        get_cur_state: Some(Self::get_cur_state_callback),
+        set_cur_state: Some(Self::set_cur_state_callback),
+        // SAFETY: All other fields are optional, for which zero is valid.
+        ..unsafe { MaybeUninit::zeroed().assume_init() }
+    };
+
+    fn register_with(
+        data: T,
+        register: impl FnOnce(*mut c_void) -> *mut bindings::thermal_cooling_device,
+    ) -> Result<Pin<Box<Self>>> {
+        crate::might_sleep!();
+        let mut reg = Pin::from(Box::try_new(Self {
+            cdev: ptr::null_mut(),
+            data,
+            _pin: PhantomPinned,
+        })?);
+        let this: *const Self = &*reg;
+        let cdev = from_err_ptr(register(this as *mut c_void))?;
+        // INVARIANT: The device was registered by `register`, with this as its driver data.
+        // SAFETY: `cdev` isn't structurally pinned.
+        unsafe { reg.as_mut().get_unchecked_mut() }.cdev = cdev;
+        Ok(reg)
+    }
+
+    /// Registers a cooling device named `name`, with the driver data `data`.
+    pub fn register(name: &CStr, data: T) -> Result<Pin<Box<Self>>> {
+        Self::register_with(data, |this| {
+            // SAFETY: The name is copied and the ops are static. The driver data is pinned, and
+            // the device is unregistered before it is freed.
+            unsafe {
+                bindings::thermal_cooling_device_register(name.as_char_ptr(), this, &Self::OPS)
+            }
+        })
+    }
+
+    /// Registers a cooling device named `name` for the node `np`, which the cooling maps of the
+    /// thermal zones of the devicetree refer to, with the driver data `data`.
+    #[cfg(CONFIG_THERMAL_OF)]
+    pub fn register_of(np: &crate::of::DeviceNode, name: &CStr, data: T) -> Result<Pin<Box<Self>>> {
+        Self::register_with(data, |this| {
+            // SAFETY: `np` is valid, the name is copied and the ops are static. The driver data
+            // is pinned, and the device is unregistered before it is freed.
+            unsafe {
+                bindings::thermal_of_cooling_device_register(
+                    np.as_raw(),
+                    name.as_char_ptr(),
+                    this,
+                    &Self::OPS,
+                )
+            }
+        })
+    }
+
+    /// Returns the cooling device.
+    pub fn device(&self) -> &CoolingDevice<T> {
+        // SAFETY: The device is registered once this is returned by `register` or
+        // `register_of`, by the type invariants, and its driver data is `self`.
+        unsafe { CoolingDevice::from_raw(self.cdev) }
+    }
+
+    /// Returns the driver data of the device.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    unsafe extern "C" fn get_max_state_callback(
+        cdev: *mut bindings::thermal_cooling_device,
+        state: *mut c_ulong,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The thermal core calls this with a device registered by `register_with`.
+            let value = T::get_max_state(unsafe { CoolingDevice::from_raw(cdev) })?;
+            // SAFETY: The thermal core passes a valid pointer to write the state to.
+            unsafe { *state = value as _ };
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn get_cur_state_callback(
+        cdev: *mut bindings::thermal_cooling_device,
+        state: *mut c_ulong,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The thermal core calls this with a device registered by `register_with`.
+            let value = T::get_cur_state(unsafe { CoolingDevice::from_raw(cdev) })?;
+            // SAFETY: The thermal core passes a valid pointer to write the state to.
+            unsafe { *state = value as _ };
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn set_cur_state_callback(
+        cdev: *mut bindings::thermal_cooling_device,
+        state: c_ulong,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The thermal core calls this with a device registered by `register_with`.
+            T::set_cur_state(unsafe { CoolingDevice::from_raw(cdev) }, state as _)?;
+            Ok(0)
+        })
+    }
+}
+
+impl<T: CoolingOperations> Drop for CoolingRegistration<T> {
+    fn drop(&mut self) {
+        if !self.cdev.is_null() {
+            // SAFETY: The device was registered by the type invariants.
+            unsafe { bindings::thermal_cooling_device_unregister(self.cdev) };
+        }
+    }
+}
+
+// SAFETY: The device can be unregistered from any thread, and the driver data is `Send`.
+unsafe impl<T: CoolingOperations> Send for CoolingRegistration<T> {}
+
+// SAFETY: The methods that take `&self` only read fields that never change, and the driver data
+// is `Sync`.
+unsafe impl<T: CoolingOperations> Sync for CoolingRegistration<T> {}
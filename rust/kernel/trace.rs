@@ -0,0 +1,107 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Tracing.
+//!
+//! [`trace_printk!`] writes messages to the ftrace ring buffer, which is much cheaper than the
+//! kernel log, and so suits debugging hot paths. [`tracing_off`] freezes the ring buffer, e.g.
+//! when a bug is detected, so that the events leading to it can be read afterwards.
+//!
+//! Rust code is not instrumented for function tracing, i.e. it behaves as if it were `notrace`
+//! in C, and is safe to call from ftrace callbacks. Its functions still appear in stack traces,
+//! under their mangled Rust paths, which e.g. `rustfilt` demangles. This includes the callbacks
+//! that abstractions pass to C, e.g. `<kernel::file::OperationsVtable<A, T>>::read_callback`.
+//!
+//! C header: [`include/linux/kernel.h`](../../../../include/linux/kernel.h)
+
+use core::fmt;
+
+#[cfg(CONFIG_TRACING)]
+use crate::bindings;
+#[cfg(CONFIG_TRACING)]
+use core::ffi::c_void;
+
+/// The format string of [`call_trace_printk`].
+#[cfg(CONFIG_TRACING)]
+static FORMAT: &[u8] = b"%pA\0";
+
+/// Writes a message to the ftrace ring buffer, attributed to the code at address `ip`.
+///
+/// Public but hidden since it should only be used from [`trace_printk!`].
+#[doc(hidden)]
+#[cfg_attr(not(CONFIG_TRACING), allow(unused_variables))]
+pub fn call_trace_printk(ip: usize, args: fmt::Arguments<'_>) {
+    // SAFETY: The format string is `%pA`, which takes a `fmt::Arguments`. `__trace_printk`
+    // formats the message before it returns, so `args` doesn't need to outlive the call.
+    #[cfg(CONFIG_TRACING)]
+    unsafe {
+        bindings::__trace_printk(
+            ip as _,
+            FORMAT.as_ptr().cast(),
+            &args as *const _ as *const c_void,
+        );
+    }
+}
+
+/// Writes a message to the ftrace ring buffer, which is read from `trace` in tracefs.
+///
+/// Equivalent to the kernel's `trace_printk` macro. Like it, it is meant for debugging only,
+/// and makes the kernel print a warning at boot. It does nothing without `CONFIG_TRACING`.
+///
+/// Mimics the interface of [`std::print!`]. See [`core::fmt`] and `alloc::format!` for
+/// information about the formatting syntax.
+///
+/// [`std::print!`]: https://doc.rust-lang.org/std/macro.print.html
+///
+/// # Examples
+///
+/// ```
+/// # use kernel::trace_printk;
+/// let (head, tail) = (3, 7);
+/// trace_printk!("queue head {} tail {}\n", head, tail);
+/// ```
+#[macro_export]
+macro_rules! trace_printk (
+    ($($arg:tt)*) => ({
+        // The address of a function nested in the caller, whose symbol is named after it, is
+        // used in place of `_THIS_IP_`, so that the message is attributed to the caller.
+        fn here() {}
+        $crate::trace::call_trace_printk(here as usize, format_args!($($arg)*))
+    })
+);
+
+/// Stops recording to the ftrace ring buffers, so that their contents are kept.
+///
+/// This may be called from any context.
+pub fn tracing_off() {
+    // SAFETY: FFI call.
+    #[cfg(CONFIG_TRACING)]
+    unsafe {
+        bindings::tracing_off()
+    };
+}
+
+/// Resumes recording to the ftrace ring buffers.
+///
+/// This may be called from any context.
+pub fn tracing_on() {
+    // SAFETY: FFI call.
+    #[cfg(CONFIG_TRACING)]
+    unsafe {
+        bindings::tracing_on()
+    };
+}
+
+/// Returns whether the ftrace ring buffers are recording.
+#[cfg(CONFIG_TRACING)]
+pub fn tracing_is_on() -> bool {
+    // SAFETY: FFI call.
+    unsafe { bindings::tracing_is_on() != 0 }
+}
+
+/// Returns whether the ftrace ring buffers are recording.
+///
+/// Always `false` when tracing support isn't built in.
+#[cfg(not(CONFIG_TRACING))]
+pub fn tracing_is_on() -> bool {
+    false
+}
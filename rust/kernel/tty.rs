@@ -0,0 +1,299 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! TTYs and line disciplines.
+//!
+//! A line discipline sits between a TTY driver and its users, and processes the data received on
+//! the TTY, e.g. to implement a protocol on top of a serial port. It is attached to a TTY from
+//! user space with the `TIOCSETD` ioctl.
+//!
+//! C headers: [`include/linux/tty.h`](../../../../include/linux/tty.h) and
+//! [`include/linux/tty_ldisc.h`](../../../../include/linux/tty_ldisc.h)
+
+use crate::{
+    bindings,
+    error::{code::*, from_result, to_result, Error, Result},
+    str::CStr,
+    types::{ForeignOwnable, Opaque},
+    ThisModule,
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::{c_int, c_void},
+    marker::{PhantomData, PhantomPinned},
+    pin::Pin,
+    ptr, slice,
+};
+use macros::vtable;
+
+/// A TTY, the kernel's `struct tty_struct`.
+///
+/// # Invariants
+///
+/// The TTY is valid while references to it exist.
+#[repr(transparent)]
+pub struct Tty(Opaque<bindings::tty_struct>);
+
+impl Tty {
+    /// Creates a reference to a [`Tty`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is valid for the lifetime of the returned reference.
+    pub unsafe fn as_ref<'a>(ptr: *mut bindings::tty_struct) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct tty_struct` pointer.
+    pub fn as_raw(&self) -> *mut bindings::tty_struct {
+        self.0.get()
+    }
+
+    /// Returns the name of the TTY, e.g. `ttyS0`.
+    pub fn name(&self) -> &CStr {
+        // SAFETY: The TTY is valid by the type invariants, and its name is `NUL`-terminated.
+        unsafe { CStr::from_char_ptr((*self.as_raw()).name.as_ptr()) }
+    }
+
+    /// Returns the number of bytes that can be written without blocking.
+    pub fn write_room(&self) -> usize {
+        // SAFETY: The TTY is valid by the type invariants.
+        unsafe { bindings::tty_write_room(self.as_raw()) as _ }
+    }
+
+    /// Queues `buf` for transmission by the TTY driver, and returns the number of bytes that were
+    /// queued, which may be less than the length of `buf` if the driver's buffer is full.
+    ///
+    /// This doesn't block. See [`Tty::set_write_wakeup`] to be notified when there is room again.
+    pub fn write(&self, buf: &[u8]) -> Result<usize> {
+        let tty = self.as_raw();
+        // SAFETY: The TTY is valid by the type invariants, and all TTY drivers implement `write`.
+        let ret = unsafe {
+            let write = (*(*tty).ops).write.ok_or(EINVAL)?;
+            write(tty, buf.as_ptr(), buf.len().try_into().map_err(|_| EINVAL)?)
+        };
+        if ret < 0 {
+            Err(Error::from_errno(ret))
+        } else {
+            Ok(ret as usize)
+        }
+    }
+
+    /// Sets whether [`Ldisc::write_wakeup`] is called when the driver has room for more data.
+    pub fn set_write_wakeup(&self, enabled: bool) {
+        // SAFETY: The TTY is valid by the type invariants, and its flags are modified atomically.
+        unsafe {
+            let flags = ptr::addr_of_mut!((*self.as_raw()).flags);
+            if enabled {
+                bindings::set_bit(bindings::TTY_DO_WRITE_WAKEUP as _, flags);
+            } else {
+                bindings::clear_bit(bindings::TTY_DO_WRITE_WAKEUP as _, flags);
+            }
+        }
+    }
+}
+
+/// A line discipline, the kernel's `struct tty_ldisc_ops`.
+///
+/// The callbacks are serialised with each other for a given TTY, and may sleep.
+#[vtable]
+pub trait Ldisc: Sized + 'static {
+    /// The data associated with each TTY that the line discipline is attached to.
+    type Data: ForeignOwnable + Send + Sync;
+
+    /// The name of the line discipline, shown in `/proc/tty/ldiscs`.
+    const NAME: &'static CStr;
+
+    /// The number of the line discipline, one of the `N_*` constants, which user space passes to
+    /// `TIOCSETD`.
+    const NUM: u32;
+
+    /// Attaches the line discipline to `tty`.
+    fn open(tty: &Tty) -> Result<Self::Data>;
+
+    /// Detaches the line discipline from `tty`.
+    fn close(_tty: &Tty, _data: Self::Data) {}
+
+    /// Processes data received by `tty`. `flags` holds the status of each byte (e.g.
+    /// `TTY_NORMAL` or `TTY_PARITY`), if any byte was not received normally.
+    fn receive_buf(
+        tty: &Tty,
+        data: <Self::Data as ForeignOwnable>::Borrowed<'_>,
+        buf: &[u8],
+        flags: Option<&[u8]>,
+    );
+
+    /// Called when the TTY driver has room for more data, if enabled with
+    /// [`Tty::set_write_wakeup`].
+    ///
+    /// This may be called from atomic context.
+    fn write_wakeup(_tty: &Tty, _data: <Self::Data as ForeignOwnable>::Borrowed<'_>) {}
+}
+
+/// A registration of a line discipline.
+///
+/// The line discipline is unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `ops` is registered with the TTY core if `registered` is `true`, which it always is once the
+/// registration is returned by [`Registration::register`].
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, tty::{self, Ldisc, Tty}};
+/// # use kernel::prelude::*;
+///
+/// struct Nmea;
+///
+/// #[vtable]
+/// impl Ldisc for Nmea {
+///     type Data = ();
+///     const NAME: &'static CStr = c_str!("nmea");
+///     const NUM: u32 = kernel::bindings::N_DEVELOPMENT;
+///
+///     fn open(_tty: &Tty) -> Result {
+///         Ok(())
+///     }
+///
+///     fn receive_buf(tty: &Tty, _data: (), buf: &[u8], _flags: Option<&[u8]>) {
+///         if buf.starts_with(b"$PING") {
+///             let _ = tty.write(b"$PONG\r\n");
+///         }
+///     }
+/// }
+///
+/// fn register(module: &'static ThisModule) -> Result<Pin<Box<tty::Registration<Nmea>>>> {
+///     tty::Registration::register(module)
+/// }
+/// ```
+pub struct Registration<T: Ldisc> {
+    ops: Opaque<bindings::tty_ldisc_ops>,
+    registered: bool,
+    _pin: PhantomPinned,
+    _p: PhantomData<T>,
+}
+
+// SAFETY: The line discipline can be unregistered from any thread.
+unsafe impl<T: Ldisc> Send for Registration<T> {}
+
+// SAFETY: `Registration` has no methods that take `&self`.
+unsafe impl<T: Ldisc> Sync for Registration<T> {}
+
+impl<T: Ldisc> Registration<T> {
+    /// Registers the line discipline on behalf of `module`.
+    ///
+    /// Fails with `EINVAL` if [`Ldisc::NUM`] is out of range, and with `EBUSY` if another line
+    /// discipline has that number.
+    pub fn register(module: &'static ThisModule) -> Result<Pin<Box<Self>>> {
+        if T::NUM >= bindings::NR_LDISCS {
+            return Err(EINVAL);
+        }
+        let mut reg = Pin::from(Box::try_new(Self {
+            ops: Opaque::new(bindings::tty_ldisc_ops {
+                num: T::NUM as _,
+                name: T::NAME.as_char_ptr().cast_mut(),
+                open: Some(Adapter::<T>::open_callback),
+                close: Some(Adapter::<T>::close_callback),
+                receive_buf: Some(Adapter::<T>::receive_buf_callback),
+                write_wakeup: if T::HAS_WRITE_WAKEUP {
+                    Some(Adapter::<T>::write_wakeup_callback)
+                } else {
+                    None
+                },
+                owner: module.as_ptr(),
+                // SAFETY: All other fields are optional, for which zero is valid.
+                ..unsafe { core::mem::MaybeUninit::zeroed().assume_init() }
+            }),
+            registered: false,
+            _pin: PhantomPinned,
+            _p: PhantomData,
+        })?);
+        // SAFETY: `ops` is valid and pinned, and is unregistered before it is freed.
+        to_result(unsafe { bindings::tty_register_ldisc(reg.ops.get()) })?;
+        // INVARIANT: The line discipline was registered above.
+        // SAFETY: `reg` isn't moved out of.
+        unsafe { reg.as_mut().get_unchecked_mut() }.registered = true;
+        Ok(reg)
+    }
+}
+
+impl<T: Ldisc> Drop for Registration<T> {
+    fn drop(&mut self) {
+        // Unregistering clears the slot of the number, which may belong to another line
+        // discipline if registering this one failed.
+        if self.registered {
+            // SAFETY: The line discipline is registered by the type invariants. The module can't
+            // be unloaded while the line discipline is attached to a TTY, since it holds a
+            // reference to its owner.
+            unsafe { bindings::tty_unregister_ldisc(self.ops.get()) };
+        }
+    }
+}
+
+struct Adapter<T: Ldisc>(PhantomData<T>);
+
+impl<T: Ldisc> Adapter<T> {
+    /// Returns the TTY and the line discipline data.
+    ///
+    /// # Safety
+    ///
+    /// `tty` must be a valid TTY that the line discipline is attached to.
+    unsafe fn get<'a>(
+        tty: *mut bindings::tty_struct,
+    ) -> (&'a Tty, <T::Data as ForeignOwnable>::Borrowed<'a>) {
+        // SAFETY: The caller guarantees that the line discipline is attached, so `disc_data`
+        // holds a pointer returned by `into_foreign` in `open_callback`, which is only freed when
+        // the line discipline is closed.
+        unsafe { (Tty::as_ref(tty), T::Data::borrow((*tty).disc_data)) }
+    }
+
+    unsafe extern "C" fn open_callback(tty: *mut bindings::tty_struct) -> c_int {
+        from_result(|| {
+            // SAFETY: The TTY core calls this with a valid TTY.
+            let data = T::open(unsafe { Tty::as_ref(tty) })?;
+            // SAFETY: The line discipline owns `disc_data` while it is attached. Like most line
+            // disciplines, it accepts as much data as the TTY buffers hold.
+            unsafe {
+                (*tty).disc_data = data.into_foreign() as *mut c_void;
+                (*tty).receive_room = 65536;
+            }
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn close_callback(tty: *mut bindings::tty_struct) {
+        // SAFETY: The TTY core calls this with a valid TTY that the line discipline was attached
+        // to. No other callbacks run after this, so the data can be freed.
+        let data = unsafe {
+            let data = T::Data::from_foreign((*tty).disc_data);
+            (*tty).disc_data = ptr::null_mut();
+            data
+        };
+        // SAFETY: As above.
+        T::close(unsafe { Tty::as_ref(tty) }, data);
+    }
+
+    unsafe extern "C" fn receive_buf_callback(
+        tty: *mut bindings::tty_struct,
+        cp: *const u8,
+        fp: *const u8,
+        count: usize,
+    ) {
+        // SAFETY: The TTY core calls this with a TTY that the line discipline is attached to, and
+        // with `count` bytes in `cp` and in `fp` if it isn't null.
+        unsafe {
+            let (tty, data) = Self::get(tty);
+            let buf = slice::from_raw_parts(cp, count);
+            let flags = (!fp.is_null()).then(|| slice::from_raw_parts(fp, count));
+            T::receive_buf(tty, data, buf, flags);
+        }
+    }
+
+    unsafe extern "C" fn write_wakeup_callback(tty: *mut bindings::tty_struct) {
+        // SAFETY: The TTY core calls this with a TTY that the line discipline is attached to.
+        let (tty, data) = unsafe { Self::get(tty) };
+        T::write_wakeup(tty, data);
+    }
+}
@@ -353,6 +353,15 @@ pub unsafe fn from_raw(ptr: NonNull<T>) -> Self {
             _p: PhantomData,
         }
     }
+
+    /// Consumes the [`ARef`], returning a raw pointer to the object.
+    ///
+    /// The increment of the reference count that the [`ARef`] owned is handed over to the caller,
+    /// e.g. to be stored in a C structure, and can be turned back into an [`ARef`] with
+    /// [`ARef::from_raw`].
+    pub fn into_raw(me: Self) -> NonNull<T> {
+        core::mem::ManuallyDrop::new(me).ptr
+    }
 }
 
 impl<T: AlwaysRefCounted> Clone for ARef<T> {
@@ -0,0 +1,420 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Userspace I/O devices.
+//!
+//! A UIO driver exposes the memory regions of a device, e.g. its registers, to user space, which
+//! maps them from `/dev/uioN` and drives the device itself. Reading from the file blocks until the
+//! device raises an interrupt, and writing to it enables or disables the interrupt, see
+//! [`Operations::irq_control`]. The kernel side is usually a few lines long, which makes it handy
+//! to bring up new hardware.
+//!
+//! C header: [`include/linux/uio_driver.h`](../../../../include/linux/uio_driver.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/driver-api/uio-howto.html>
+
+use crate::{
+    bindings,
+    device::Device,
+    error::{code::*, from_result, to_result, Error, Result},
+    init::{self, InPlaceInit, PinInit},
+    str::{CStr, CString},
+    types::Opaque,
+    ThisModule,
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::{c_int, c_ulong},
+    fmt,
+    marker::PhantomPinned,
+    mem::MaybeUninit,
+    pin::Pin,
+    ptr,
+};
+use macros::vtable;
+
+/// The maximum number of memory regions of a device.
+pub const MAX_REGIONS: usize = bindings::MAX_UIO_MAPS as usize;
+
+/// A memory region that user space can map, the kernel's `struct uio_mem`.
+///
+/// Regions are mapped by whole pages. The offset of the start of a region in its first page is
+/// shown to user space in `/sys/class/uio/uioN/maps/mapM/offset`, so that it can find the region
+/// in the mapping.
+#[derive(Clone, Copy)]
+pub struct MemRegion {
+    name: &'static CStr,
+    memtype: u32,
+    addr: u64,
+    size: u64,
+}
+
+impl MemRegion {
+    /// Creates a region of `size` bytes at the physical address `addr`, e.g. the registers of the
+    /// device.
+    ///
+    /// It is mapped uncached.
+    pub const fn phys(name: &'static CStr, addr: u64, size: u64) -> Self {
+        Self {
+            name,
+            memtype: bindings::UIO_MEM_PHYS,
+            addr,
+            size,
+        }
+    }
+
+    /// Creates a region of `size` bytes at the device address `addr`, e.g. a DMA buffer.
+    ///
+    /// It is mapped like memory, i.e. cached.
+    pub const fn iova(name: &'static CStr, addr: u64, size: u64) -> Self {
+        Self {
+            name,
+            memtype: bindings::UIO_MEM_IOVA,
+            addr,
+            size,
+        }
+    }
+
+    fn to_raw(self) -> Result<bindings::uio_mem> {
+        if self.size == 0 {
+            return Err(EINVAL);
+        }
+        let page_mask = bindings::PAGE_SIZE as u64 - 1;
+        let offs = self.addr & page_mask;
+        let size = self
+            .size
+            .checked_add(offs)
+            .and_then(|size| size.checked_add(page_mask))
+            .ok_or(EINVAL)?
+            & !page_mask;
+        Ok(bindings::uio_mem {
+            name: self.name.as_char_ptr(),
+            memtype: self.memtype as _,
+            addr: (self.addr & !page_mask) as _,
+            offs: offs as _,
+            size: size as _,
+            // SAFETY: All other fields are optional or filled in on registration.
+            ..unsafe { MaybeUninit::zeroed().assume_init() }
+        })
+    }
+}
+
+/// The interrupt of a device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Irq {
+    /// The device has no interrupt, and reading from its file always blocks.
+    None,
+    /// The interrupt line `irq`, which is requested on registration and handled by
+    /// [`Operations::handle_irq`].
+    Line(u32),
+    /// Like [`Irq::Line`], for a line that is shared with other devices.
+    Shared(u32),
+    /// The driver handles its interrupt itself, and calls [`Registration::notify`] when it
+    /// fires.
+    Custom,
+}
+
+/// The configuration of a [`Registration`].
+#[derive(Clone, Copy)]
+pub struct Config<'a> {
+    /// The version of the driver, shown to user space in `/sys/class/uio/uioN/version`.
+    pub version: &'static CStr,
+    /// The memory regions of the device, at most [`MAX_REGIONS`].
+    pub regions: &'a [MemRegion],
+    /// The interrupt of the device.
+    pub irq: Irq,
+}
+
+/// The operations of a UIO device.
+///
+/// The driver data of the device implements this trait.
+#[vtable]
+pub trait Operations: Send + Sync + Sized + 'static {
+    /// Handles the interrupt of a device registered with [`Irq::Line`] or [`Irq::Shared`], and
+    /// returns whether it was raised by the device.
+    ///
+    /// It must at least keep the interrupt from firing again, usually by masking it at the
+    /// device, since user space handles it later. Readers of the file are woken up if this
+    /// returns `true`. It is called in interrupt context, and must not sleep.
+    fn handle_irq(_uio: &Registration<Self>) -> bool {
+        false
+    }
+
+    /// Enables or disables the interrupt, when user space writes 1 or 0 to the file.
+    ///
+    /// It is typically used to unmask the interrupt once user space has handled it, when the
+    /// device registers can't do so without racing with the rest of the driver.
+    fn irq_control(_uio: &Registration<Self>, _enable: bool) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Called when the file is opened.
+    fn open(_uio: &Registration<Self>) -> Result {
+        Ok(())
+    }
+
+    /// Called when the file is closed.
+    fn release(_uio: &Registration<Self>) -> Result {
+        Ok(())
+    }
+}
+
+/// A registered UIO device.
+///
+/// The device is unregistered when this is dropped, and its interrupt is freed.
+///
+/// # Invariants
+///
+/// `info` is registered with the name `name`, and its operations are those of `T`.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{c_str, device::Device, fmt, prelude::*, ThisModule};
+/// use kernel::io_mem::IoMem;
+/// use kernel::uio::{self, Config, Irq, MemRegion};
+///
+/// const INT_MASK: usize = 0x10;
+///
+/// struct Shim {
+///     regs: IoMem<0x1000>,
+/// }
+///
+/// #[vtable]
+/// impl uio::Operations for Shim {
+///     fn handle_irq(uio: &uio::Registration<Self>) -> bool {
+///         // User space unmasks the interrupt once it has handled it.
+///         uio.data().regs.writel(0, INT_MASK);
+///         true
+///     }
+/// }
+///
+/// fn probe(
+///     module: &'static ThisModule,
+///     dev: &Device,
+///     base: u64,
+///     irq: u32,
+///     regs: IoMem<0x1000>,
+/// ) -> Result<Pin<Box<uio::Registration<Shim>>>> {
+///     let config = Config {
+///         version: c_str!("0.1"),
+///         regions: &[MemRegion::phys(c_str!("regs"), base, 0x1000)],
+///         irq: Irq::Line(irq),
+///     };
+///     Box::pin_init(uio::Registration::register(
+///         module,
+///         dev,
+///         fmt!("shim"),
+///         &config,
+///         Shim { regs },
+///     ))
+/// }
+/// ```
+#[repr(C)]
+pub struct Registration<T: Operations> {
+    // Must be the first field, see `Registration::from_raw`.
+    info: Opaque<bindings::uio_info>,
+    name: CString,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: Operations> Registration<T> {
+    /// Returns an initialiser that registers a UIO device named `name`, whose parent is
+    /// `parent`, with the configuration `config` and the driver data `data`.
+    ///
+    /// Fails with `EINVAL` if there are too many or empty memory regions, or if the interrupt is
+    /// a line but `T` doesn't implement [`Operations::handle_irq`].
+    pub fn register(
+        module: &'static ThisModule,
+        parent: &Device,
+        name: fmt::Arguments<'_>,
+        config: &Config<'_>,
+        data: T,
+    ) -> impl PinInit<Self, Error> {
+        let name = CString::try_from_fmt(name);
+        let version = config.version;
+        let parent = parent.as_raw();
+        let regions = (|| {
+            if config.regions.len() > MAX_REGIONS {
+                return Err(EINVAL);
+            }
+            // SAFETY: A zeroed region is unused.
+            let mut mem: [bindings::uio_mem; MAX_REGIONS] =
+                unsafe { MaybeUninit::zeroed().assume_init() };
+            for (raw, region) in mem.iter_mut().zip(config.regions) {
+                *raw = region.to_raw()?;
+            }
+            Ok(mem)
+        })();
+        let (irq, irq_flags) = match config.irq {
+            Irq::None => (0, 0),
+            Irq::Line(irq) => (irq as _, 0),
+            Irq::Shared(irq) => (irq as _, bindings::IRQF_SHARED as c_ulong),
+            Irq::Custom => (bindings::UIO_IRQ_CUSTOM as _, 0),
+        };
+        let irq_line = matches!(config.irq, Irq::Line(_) | Irq::Shared(_));
+        // SAFETY: The closure initialises all fields on success, and drops those it initialised
+        // on failure. The registration isn't moved once the device is registered, since it is
+        // pinned.
+        unsafe {
+            init::pin_init_from_closure::<_, Error>(move |slot: *mut Self| {
+                if irq_line && !T::HAS_HANDLE_IRQ {
+                    return Err(EINVAL);
+                }
+                let mem = regions?;
+                let name_ptr = ptr::addr_of_mut!((*slot).name);
+                name_ptr.write(name?);
+                // The data is written before registering, since the callbacks can be called as
+                // soon as the device is registered.
+                let data_ptr = ptr::addr_of_mut!((*slot).data);
+                data_ptr.write(data);
+                let info = Opaque::raw_get(ptr::addr_of!((*slot).info));
+                // The name is owned by the registration, which outlives the device, and the
+                // version is static.
+                info.write(bindings::uio_info {
+                    name: (*name_ptr).as_char_ptr(),
+                    version: version.as_char_ptr(),
+                    mem,
+                    irq,
+                    irq_flags,
+                    handler: if T::HAS_HANDLE_IRQ {
+                        Some(Self::handler_callback)
+                    } else {
+                        None
+                    },
+                    irqcontrol: if T::HAS_IRQ_CONTROL {
+                        Some(Self::irqcontrol_callback)
+                    } else {
+                        None
+                    },
+                    open: if T::HAS_OPEN {
+                        Some(Self::open_callback)
+                    } else {
+                        None
+                    },
+                    release: if T::HAS_RELEASE {
+                        Some(Self::release_callback)
+                    } else {
+                        None
+                    },
+                    // SAFETY: All other fields are optional or filled in on registration.
+                    ..MaybeUninit::zeroed().assume_init()
+                });
+                if let Err(e) = to_result(bindings::__uio_register_device(
+                    module.as_ptr(),
+                    parent,
+                    info,
+                )) {
+                    ptr::drop_in_place(name_ptr);
+                    ptr::drop_in_place(data_ptr);
+                    return Err(e);
+                }
+                // INVARIANT: The device is only considered initialised if it was registered.
+                Ok(())
+            })
+        }
+    }
+
+    /// Allocates a registration and registers the UIO device, see [`Registration::register`].
+    pub fn register_new(
+        module: &'static ThisModule,
+        parent: &Device,
+        name: fmt::Arguments<'_>,
+        config: &Config<'_>,
+        data: T,
+    ) -> Result<Pin<Box<Self>>> {
+        Box::pin_init(Self::register(module, parent, name, config, data))
+    }
+
+    /// Creates a reference to a [`Registration`] from its `struct uio_info`.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `info` is the `info` field of a `Registration<T>` that outlives
+    /// the returned reference.
+    unsafe fn from_raw<'a>(info: *mut bindings::uio_info) -> &'a Self {
+        // SAFETY: `info` is the first field of `Registration`, which is `repr(C)`, and the
+        // registration is valid by the safety requirements of the function.
+        unsafe { &*info.cast() }
+    }
+
+    /// Returns the name of the device.
+    pub fn name(&self) -> &CStr {
+        &self.name
+    }
+
+    /// Returns the driver data of the device.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    /// Wakes up the readers of the file, as if an interrupt was handled.
+    ///
+    /// It is meant for devices registered with [`Irq::Custom`], and can be called from any
+    /// context.
+    pub fn notify(&self) {
+        // SAFETY: The device is registered by the type invariants.
+        unsafe { bindings::uio_event_notify(self.info.get()) };
+    }
+
+    unsafe extern "C" fn handler_callback(
+        _irq: c_int,
+        info: *mut bindings::uio_info,
+    ) -> bindings::irqreturn_t {
+        // SAFETY: The UIO core calls this with the `info` of a registered device, which is part
+        // of a `Registration<T>` by the type invariants.
+        if T::handle_irq(unsafe { Self::from_raw(info) }) {
+            bindings::irqreturn_IRQ_HANDLED
+        } else {
+            bindings::irqreturn_IRQ_NONE
+        }
+    }
+
+    unsafe extern "C" fn irqcontrol_callback(info: *mut bindings::uio_info, irq_on: i32) -> c_int {
+        from_result(|| {
+            // SAFETY: The UIO core calls this with the `info` of a registered device, which is
+            // part of a `Registration<T>` by the type invariants.
+            T::irq_control(unsafe { Self::from_raw(info) }, irq_on != 0)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn open_callback(
+        info: *mut bindings::uio_info,
+        _inode: *mut bindings::inode,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The UIO core calls this with the `info` of a registered device, which is
+            // part of a `Registration<T>` by the type invariants.
+            T::open(unsafe { Self::from_raw(info) })?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn release_callback(
+        info: *mut bindings::uio_info,
+        _inode: *mut bindings::inode,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The UIO core calls this with the `info` of a registered device, which is
+            // part of a `Registration<T>` by the type invariants.
+            T::release(unsafe { Self::from_raw(info) })?;
+            Ok(0)
+        })
+    }
+}
+
+impl<T: Operations> Drop for Registration<T> {
+    fn drop(&mut self) {
+        // SAFETY: The device is registered by the type invariants. Unregistering it frees its
+        // interrupt and waits for the callbacks to return.
+        unsafe { bindings::uio_unregister_device(self.info.get()) };
+    }
+}
+
+// SAFETY: The device can be unregistered from any thread, and the driver data is `Send`.
+unsafe impl<T: Operations> Send for Registration<T> {}
+
+// SAFETY: The methods that take `&self` only read fields that never change or call functions
+// with their own synchronisation, and the driver data is `Sync`.
+unsafe impl<T: Operations> Sync for Registration<T> {}
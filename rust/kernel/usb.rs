@@ -0,0 +1,14 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! USB.
+//!
+//! C header: [`include/linux/usb.h`](../../../../include/linux/usb.h)
+
+#[cfg(CONFIG_USB_LIBCOMPOSITE)]
+pub mod gadget;
+#[cfg(CONFIG_USB_PHY)]
+pub mod phy;
+#[cfg(CONFIG_USB_ROLE_SWITCH)]
+pub mod role;
+#[cfg(CONFIG_TYPEC)]
+pub mod typec;
@@ -0,0 +1,774 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! USB gadget functions.
+//!
+//! A gadget function implements one function of a USB device, e.g. a network adapter or a serial
+//! port, on top of the device-mode controller (UDC). Functions are registered with a
+//! [`Registration`] and composed into gadgets through configfs: creating a
+//! `functions/<name>.<instance>` directory creates an instance of the function, and linking it
+//! into a configuration creates its data with [`Function::new`] and binds it to the gadget.
+//!
+//! When it is bound, the function allocates its interfaces and endpoints and declares their
+//! descriptors. Once the host selects the configuration, the function enables its endpoints in
+//! [`Function::set_alt`] and queues [`Request`]s on them, whose completions are reported to a
+//! [`Completion`] handler.
+//!
+//! C header: [`include/linux/usb/composite.h`](../../../../include/linux/usb/composite.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/usb/gadget_configfs.html>
+
+use crate::{
+    bindings, c_str,
+    error::{code::*, from_result, to_result, Error, Result},
+    init::{self, InPlaceInit, PinInit},
+    str::CStr,
+    sync::Arc,
+    try_vec,
+    types::Opaque,
+    ThisModule,
+};
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    ffi::{c_int, c_uint, c_void},
+    marker::{PhantomData, PhantomPinned},
+    mem::MaybeUninit,
+    pin::Pin,
+    ptr::{self, NonNull},
+};
+
+/// The direction of an endpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// From the device to the host.
+    In,
+    /// From the host to the device.
+    Out,
+}
+
+/// The transfer type of an endpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transfer {
+    /// Bulk transfers, with the largest packets the speed allows.
+    Bulk,
+    /// Interrupt transfers of up to `max_packet` bytes, polled every `interval_ms` milliseconds.
+    Interrupt {
+        /// The largest packet, at most 64 bytes.
+        max_packet: u16,
+        /// The polling interval, in milliseconds.
+        interval_ms: u8,
+    },
+}
+
+/// The description of an endpoint, allocated with [`Func::autoconfig`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EndpointDesc {
+    /// The direction of the endpoint.
+    pub dir: Direction,
+    /// The transfer type of the endpoint.
+    pub transfer: Transfer,
+}
+
+impl EndpointDesc {
+    /// Returns the full-speed descriptor of the endpoint, whose address is filled in by
+    /// `usb_ep_autoconfig`.
+    fn to_raw_fs(self) -> bindings::usb_endpoint_descriptor {
+        let (attributes, max_packet, interval) = match self.transfer {
+            Transfer::Bulk => (bindings::USB_ENDPOINT_XFER_BULK, 64, 0),
+            Transfer::Interrupt {
+                max_packet,
+                interval_ms,
+            } => (bindings::USB_ENDPOINT_XFER_INT, max_packet, interval_ms),
+        };
+        bindings::usb_endpoint_descriptor {
+            bLength: bindings::USB_DT_ENDPOINT_SIZE as _,
+            bDescriptorType: bindings::USB_DT_ENDPOINT as _,
+            bEndpointAddress: match self.dir {
+                Direction::In => bindings::USB_DIR_IN as _,
+                Direction::Out => bindings::USB_DIR_OUT as _,
+            },
+            bmAttributes: attributes as _,
+            wMaxPacketSize: max_packet.to_le(),
+            bInterval: interval,
+            // SAFETY: All other fields are unused for these endpoints, for which zero is valid.
+            ..unsafe { MaybeUninit::zeroed().assume_init() }
+        }
+    }
+
+    /// Returns the high-speed descriptor of the endpoint, given its full-speed one.
+    fn to_raw_hs(
+        self,
+        fs: &bindings::usb_endpoint_descriptor,
+    ) -> bindings::usb_endpoint_descriptor {
+        let mut hs = *fs;
+        match self.transfer {
+            Transfer::Bulk => hs.wMaxPacketSize = 512u16.to_le(),
+            // High-speed intervals are powers of two of 125 µs microframes.
+            Transfer::Interrupt { interval_ms, .. } => {
+                let uframes = u32::from(interval_ms.max(1)) * 8;
+                hs.bInterval = (uframes.ilog2() + 1).min(16) as u8;
+            }
+        }
+        hs
+    }
+}
+
+/// The description of an interface, declared with [`Func::assign_descriptors`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InterfaceDesc {
+    /// The number of the interface, allocated with [`Configuration::interface_id`].
+    pub number: u8,
+    /// The class of the interface, e.g. `0xff` for a vendor-specific interface.
+    pub class: u8,
+    /// The subclass of the interface.
+    pub subclass: u8,
+    /// The protocol of the interface.
+    pub protocol: u8,
+}
+
+/// A configuration of a gadget, the kernel's `struct usb_configuration`.
+///
+/// # Invariants
+///
+/// The configuration is valid while references to it exist.
+#[repr(transparent)]
+pub struct Configuration(Opaque<bindings::usb_configuration>);
+
+impl Configuration {
+    /// Creates a reference to a [`Configuration`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is valid for the lifetime of the returned reference.
+    unsafe fn from_raw<'a>(ptr: *mut bindings::usb_configuration) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Allocates the number of an interface of `func` in the configuration.
+    ///
+    /// Fails with `ENODEV` if all interface numbers are used.
+    pub fn interface_id(&self, func: &Func) -> Result<u8> {
+        // SAFETY: The configuration and the function are valid by their type invariants, and the
+        // function is being bound to the configuration.
+        let id = unsafe { bindings::usb_interface_id(self.0.get(), func.as_raw()) };
+        to_result(id)?;
+        Ok(id as u8)
+    }
+}
+
+/// A function of a gadget, the kernel's `struct usb_function`.
+///
+/// # Invariants
+///
+/// The function is valid while references to it exist, and is added to a configuration.
+#[repr(transparent)]
+pub struct Func(Opaque<bindings::usb_function>);
+
+impl Func {
+    /// Creates a reference to a [`Func`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is a function added to a configuration for the lifetime of
+    /// the returned reference.
+    unsafe fn from_raw<'a>(ptr: *mut bindings::usb_function) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct usb_function` pointer.
+    pub fn as_raw(&self) -> *mut bindings::usb_function {
+        self.0.get()
+    }
+
+    /// Returns the gadget the function is bound to.
+    fn gadget(&self) -> *mut bindings::usb_gadget {
+        // SAFETY: The function is added to a configuration by the type invariants, whose
+        // composite device and gadget are valid.
+        unsafe { (*(*(*self.as_raw()).config).cdev).gadget }
+    }
+
+    /// Allocates an endpoint of the controller that matches `desc`, from [`Function::bind`].
+    ///
+    /// Fails with `ENODEV` if no endpoint is left that matches.
+    pub fn autoconfig(&self, desc: EndpointDesc) -> Result<Endpoint> {
+        let mut fs = desc.to_raw_fs();
+        // SAFETY: The gadget is valid, and `fs` is a valid descriptor, whose address is filled
+        // in.
+        let ep = unsafe { bindings::usb_ep_autoconfig(self.gadget(), &mut fs) };
+        let ep = NonNull::new(ep).ok_or(ENODEV)?;
+        let hs = desc.to_raw_hs(&fs);
+        // INVARIANT: The endpoint was allocated for the gadget above.
+        Ok(Endpoint { ep, fs, hs })
+    }
+
+    /// Declares the descriptors of the function, i.e. an interface and its endpoints, from
+    /// [`Function::bind`].
+    ///
+    /// The descriptors are declared at full and high speed, and SuperSpeed controllers use the
+    /// high-speed ones. They are freed when the function is unbound.
+    pub fn assign_descriptors(&self, intf: &InterfaceDesc, endpoints: &[&Endpoint]) -> Result {
+        let mut intf = bindings::usb_interface_descriptor {
+            bLength: bindings::USB_DT_INTERFACE_SIZE as _,
+            bDescriptorType: bindings::USB_DT_INTERFACE as _,
+            bInterfaceNumber: intf.number,
+            bAlternateSetting: 0,
+            bNumEndpoints: endpoints.len().try_into().map_err(|_| EINVAL)?,
+            bInterfaceClass: intf.class,
+            bInterfaceSubClass: intf.subclass,
+            bInterfaceProtocol: intf.protocol,
+            iInterface: 0,
+        };
+        let mut fs = Vec::try_with_capacity(endpoints.len())?;
+        let mut hs = Vec::try_with_capacity(endpoints.len())?;
+        for ep in endpoints {
+            fs.try_push(ep.fs)?;
+            hs.try_push(ep.hs)?;
+        }
+        let intf_ptr = ptr::addr_of_mut!(intf).cast::<bindings::usb_descriptor_header>();
+        let mut fs_list = try_vec![intf_ptr]?;
+        let mut hs_list = try_vec![intf_ptr]?;
+        for (f, h) in fs.iter_mut().zip(hs.iter_mut()) {
+            fs_list.try_push((f as *mut bindings::usb_endpoint_descriptor).cast())?;
+            hs_list.try_push((h as *mut bindings::usb_endpoint_descriptor).cast())?;
+        }
+        fs_list.try_push(ptr::null_mut())?;
+        hs_list.try_push(ptr::null_mut())?;
+        // SAFETY: The function is valid by the type invariants, and the lists are terminated and
+        // point to valid descriptors, which are copied.
+        to_result(unsafe {
+            bindings::usb_assign_descriptors(
+                self.as_raw(),
+                fs_list.as_mut_ptr(),
+                hs_list.as_mut_ptr(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        })
+    }
+}
+
+/// An endpoint of the controller, allocated for a function with [`Func::autoconfig`].
+///
+/// The endpoint, and the requests allocated for it, must be dropped when the function is unbound,
+/// in [`Function::unbind`].
+///
+/// # Invariants
+///
+/// `ep` was allocated for the gadget the function is bound to, and `fs` and `hs` are its
+/// descriptors.
+pub struct Endpoint {
+    ep: NonNull<bindings::usb_ep>,
+    fs: bindings::usb_endpoint_descriptor,
+    hs: bindings::usb_endpoint_descriptor,
+}
+
+impl Endpoint {
+    /// Returns the address of the endpoint, including its direction bit.
+    pub fn address(&self) -> u8 {
+        self.fs.bEndpointAddress
+    }
+
+    /// Enables the endpoint with the descriptor of the current speed, from
+    /// [`Function::set_alt`].
+    pub fn enable(&self, func: &Func) -> Result {
+        let ep = self.ep.as_ptr();
+        // SAFETY: The endpoint is allocated for the gadget the function is bound to, by the type
+        // invariants, and the function declared its descriptors.
+        to_result(unsafe { bindings::config_ep_by_speed(func.gadget(), func.as_raw(), ep) })?;
+        // SAFETY: The endpoint is valid, and its descriptor was selected above.
+        to_result(unsafe { bindings::usb_ep_enable(ep) })
+    }
+
+    /// Disables the endpoint, from [`Function::disable`] or before it is enabled again with
+    /// another descriptor.
+    ///
+    /// The requests queued on the endpoint are completed with `ESHUTDOWN`.
+    pub fn disable(&self) {
+        // SAFETY: The endpoint is valid by the type invariants.
+        unsafe { bindings::usb_ep_disable(self.ep.as_ptr()) };
+    }
+
+    /// Halts the endpoint, i.e. stalls the transfers on it until the host clears the halt.
+    pub fn set_halt(&self) -> Result {
+        // SAFETY: The endpoint is valid by the type invariants.
+        to_result(unsafe { bindings::usb_ep_set_halt(self.ep.as_ptr()) })
+    }
+}
+
+// SAFETY: The endpoint can be used from any thread, and the controller serialises the operations
+// on it with its own lock.
+unsafe impl Send for Endpoint {}
+
+// SAFETY: See above.
+unsafe impl Sync for Endpoint {}
+
+/// The handler of the completions of [`Request`]s.
+pub trait Completion: Send + Sync + Sized + 'static {
+    /// Called when the request `req` completes, with the number of bytes transferred, or an
+    /// error, e.g. `ESHUTDOWN` when the endpoint is disabled.
+    ///
+    /// It is called in interrupt context, and must not sleep. The request can be queued again
+    /// with [`Request::queue`].
+    fn complete(&self, req: Request<Self>, result: Result<usize>);
+}
+
+/// A transfer on an endpoint, the kernel's `struct usb_request`.
+///
+/// Requests are queued with [`Request::queue`], which passes their ownership to the controller
+/// until they complete, when it is handed back to the [`Completion`] handler.
+pub struct Request<T: Completion> {
+    inner: Box<RequestInner<T>>,
+}
+
+/// # Invariants
+///
+/// `req` was allocated for `ep`, and its buffer is `buf`.
+struct RequestInner<T: Completion> {
+    ep: NonNull<bindings::usb_ep>,
+    req: NonNull<bindings::usb_request>,
+    buf: Vec<u8>,
+    handler: Arc<T>,
+}
+
+impl<T: Completion> Request<T> {
+    /// Allocates a request for `ep` with a buffer of `len` bytes, whose completions are handled
+    /// by `handler`.
+    ///
+    /// This may sleep, so requests are usually allocated in [`Function::bind`].
+    pub fn new(ep: &Endpoint, len: usize, handler: Arc<T>) -> Result<Self> {
+        crate::might_sleep!();
+        let buf = try_vec![0u8; len]?;
+        // SAFETY: The endpoint is valid by its type invariants.
+        let req = unsafe { bindings::usb_ep_alloc_request(ep.ep.as_ptr(), bindings::GFP_KERNEL) };
+        let req = NonNull::new(req).ok_or(ENOMEM)?;
+        // INVARIANT: The request was allocated for the endpoint above, and its buffer is set
+        // below.
+        let mut inner = Box::try_new(RequestInner {
+            ep: ep.ep,
+            req,
+            buf,
+            handler,
+        })?;
+        // SAFETY: The request was just allocated. The buffer isn't moved, since the vector is
+        // never resized.
+        unsafe {
+            let raw = req.as_ptr();
+            (*raw).buf = inner.buf.as_mut_ptr().cast::<c_void>();
+            (*raw).length = len as _;
+            (*raw).complete = Some(Self::complete_callback);
+        }
+        Ok(Self { inner })
+    }
+
+    /// Returns the buffer of the request.
+    pub fn buffer(&self) -> &[u8] {
+        &self.inner.buf
+    }
+
+    /// Returns the buffer of the request, e.g. to fill it before an IN transfer.
+    pub fn buffer_mut(&mut self) -> &mut [u8] {
+        &mut self.inner.buf
+    }
+
+    /// Sets the number of bytes to transfer, at most the size of the buffer.
+    pub fn set_length(&mut self, len: usize) -> Result {
+        if len > self.inner.buf.len() {
+            return Err(EINVAL);
+        }
+        // SAFETY: The request is owned by `self`, so it isn't queued.
+        unsafe { (*self.inner.req.as_ptr()).length = len as _ };
+        Ok(())
+    }
+
+    /// Queues the request on its endpoint.
+    ///
+    /// The request is handed back to the handler when it completes. It may be called in
+    /// interrupt context, e.g. from [`Completion::complete`]. The request is dropped if it can't
+    /// be queued, e.g. if the endpoint is disabled.
+    pub fn queue(self) -> Result {
+        let (ep, req) = (self.inner.ep.as_ptr(), self.inner.req.as_ptr());
+        let inner = Box::into_raw(self.inner);
+        // SAFETY: The request was allocated for the endpoint by the type invariants. Its state is
+        // owned by the controller until it completes, when it is reclaimed from the context.
+        let ret = unsafe {
+            (*req).context = inner.cast::<c_void>();
+            bindings::usb_ep_queue(ep, req, bindings::GFP_ATOMIC)
+        };
+        if ret != 0 {
+            // SAFETY: The request wasn't queued, so its state is still owned here.
+            drop(unsafe { Box::from_raw(inner) });
+        }
+        to_result(ret)
+    }
+
+    unsafe extern "C" fn complete_callback(
+        _ep: *mut bindings::usb_ep,
+        req: *mut bindings::usb_request,
+    ) {
+        // SAFETY: The controller calls this once a request queued by `queue` completed, and
+        // hands its state back.
+        let (inner, status, actual) = unsafe {
+            (
+                Box::from_raw((*req).context.cast::<RequestInner<T>>()),
+                (*req).status,
+                (*req).actual,
+            )
+        };
+        let result = if status < 0 {
+            Err(Error::from_errno(status))
+        } else {
+            Ok(actual as usize)
+        };
+        let handler = inner.handler.clone();
+        handler.complete(Self { inner }, result);
+    }
+}
+
+impl<T: Completion> Drop for RequestInner<T> {
+    fn drop(&mut self) {
+        // SAFETY: The request isn't queued, since its state is owned by `self`, and it was
+        // allocated for `ep` by the type invariants.
+        unsafe { bindings::usb_ep_free_request(self.ep.as_ptr(), self.req.as_ptr()) };
+    }
+}
+
+// SAFETY: The request is owned by `self` when it isn't queued, and the handler is `Send + Sync`.
+unsafe impl<T: Completion> Send for Request<T> {}
+
+// SAFETY: The methods that take `&self` only read the buffer.
+unsafe impl<T: Completion> Sync for Request<T> {}
+
+/// A gadget function.
+///
+/// Each instance of the function in a configuration has its own data, which implements this
+/// trait.
+pub trait Function: Send + Sync + Sized + 'static {
+    /// The name of the function, i.e. of its directories in configfs.
+    const NAME: &'static CStr;
+
+    /// Creates the data of an instance of the function, when it is added to a configuration.
+    fn new() -> Result<Self>;
+
+    /// Binds the function to the gadget: allocates its interfaces and endpoints, declares their
+    /// descriptors with [`Func::assign_descriptors`], and allocates its requests.
+    ///
+    /// It is called in process context, and may sleep.
+    fn bind(&mut self, func: &Func, config: &Configuration) -> Result;
+
+    /// Unbinds the function from the gadget, once it is disabled. It must drop the endpoints and
+    /// requests allocated in [`Function::bind`].
+    ///
+    /// It is called in process context, and may sleep. The descriptors are freed afterwards.
+    fn unbind(&mut self, _func: &Func) {}
+
+    /// Selects the alternate setting `alt` of the interface `intf`, after the host selected the
+    /// configuration or the setting. Any setting that was selected before must be reset, and
+    /// the endpoints are enabled with [`Endpoint::enable`].
+    ///
+    /// It is called in interrupt context, and must not sleep.
+    fn set_alt(&self, func: &Func, intf: u32, alt: u32) -> Result;
+
+    /// Disables the function, when the gadget is disconnected or reconfigured. The endpoints
+    /// must be disabled with [`Endpoint::disable`].
+    ///
+    /// It is called in interrupt context, and must not sleep.
+    fn disable(&self, func: &Func);
+}
+
+#[repr(C)]
+struct FunctionData<T: Function> {
+    // Must be the first field, see `FunctionData::from_raw`.
+    func: Opaque<bindings::usb_function>,
+    data: T,
+}
+
+impl<T: Function> FunctionData<T> {
+    /// Returns the data of `func`.
+    ///
+    /// # Safety
+    ///
+    /// `func` must be the `func` field of a `FunctionData<T>` allocated by `alloc_func_callback`,
+    /// and the returned reference must not alias a mutable one.
+    unsafe fn from_raw<'a>(func: *mut bindings::usb_function) -> &'a mut Self {
+        // SAFETY: `func` is the first field of a `FunctionData<T>`, which is `repr(C)`.
+        unsafe { &mut *func.cast() }
+    }
+}
+
+/// A registered gadget function, the kernel's `struct usb_function_driver`.
+///
+/// The function is unregistered when this is dropped. The gadgets using it keep the module
+/// loaded, so this is only dropped once they are removed.
+///
+/// Instances of the function don't have configfs attributes.
+///
+/// # Invariants
+///
+/// `driver` is registered.
+///
+/// # Examples
+///
+/// A function that receives data on a bulk endpoint, and drops it:
+///
+/// ```
+/// use kernel::usb::gadget::{self, Completion, Configuration, Func, Request};
+/// use kernel::{c_str, new_spinlock, pin_init, prelude::*, sync::{Arc, SpinLock}};
+///
+/// #[pin_data]
+/// struct Sink {
+///     // The request, while the endpoint is disabled.
+///     #[pin]
+///     idle: SpinLock<Option<Request<Sink>>>,
+/// }
+///
+/// impl Completion for Sink {
+///     fn complete(&self, req: Request<Self>, result: Result<usize>) {
+///         match result {
+///             Ok(_) => {
+///                 let _ = req.queue();
+///             }
+///             Err(_) => *self.idle.lock() = Some(req),
+///         }
+///     }
+/// }
+///
+/// struct SinkFunction {
+///     ep: Option<gadget::Endpoint>,
+///     sink: Arc<Sink>,
+/// }
+///
+/// impl gadget::Function for SinkFunction {
+///     const NAME: &'static CStr = c_str!("rust_sink");
+///
+///     fn new() -> Result<Self> {
+///         Ok(Self {
+///             ep: None,
+///             sink: Arc::pin_init(pin_init!(Sink { idle <- new_spinlock!(None) }))?,
+///         })
+///     }
+///
+///     fn bind(&mut self, func: &Func, config: &Configuration) -> Result {
+///         let number = config.interface_id(func)?;
+///         let ep = func.autoconfig(gadget::EndpointDesc {
+///             dir: gadget::Direction::Out,
+///             transfer: gadget::Transfer::Bulk,
+///         })?;
+///         let intf = gadget::InterfaceDesc {
+///             number,
+///             class: 0xff,
+///             subclass: 0,
+///             protocol: 0,
+///         };
+///         func.assign_descriptors(&intf, &[&ep])?;
+///         *self.sink.idle.lock() = Some(Request::new(&ep, 512, self.sink.clone())?);
+///         self.ep = Some(ep);
+///         Ok(())
+///     }
+///
+///     fn unbind(&mut self, _func: &Func) {
+///         self.sink.idle.lock().take();
+///         self.ep = None;
+///     }
+///
+///     fn set_alt(&self, func: &Func, _intf: u32, _alt: u32) -> Result {
+///         let ep = self.ep.as_ref().ok_or(EINVAL)?;
+///         ep.disable();
+///         ep.enable(func)?;
+///         match self.sink.idle.lock().take() {
+///             Some(req) => req.queue(),
+///             None => Ok(()),
+///         }
+///     }
+///
+///     fn disable(&self, _func: &Func) {
+///         if let Some(ep) = &self.ep {
+///             ep.disable();
+///         }
+///     }
+/// }
+///
+/// fn register(
+///     module: &'static ThisModule,
+/// ) -> Result<Pin<Box<gadget::Registration<SinkFunction>>>> {
+///     gadget::Registration::register_new(module)
+/// }
+/// ```
+pub struct Registration<T: Function> {
+    driver: Opaque<bindings::usb_function_driver>,
+    _p: PhantomData<T>,
+    _pin: PhantomPinned,
+}
+
+impl<T: Function> Registration<T> {
+    const ITEM_OPS: bindings::configfs_item_operations = bindings::configfs_item_operations {
+        release: Some(Self::release_callback),
+        // SAFETY: All other fields are optional, for which zero is valid.
+        ..unsafe { MaybeUninit::zeroed().assume_init() }
+    };
+
+    const ITEM_TYPE: bindings::config_item_type = bindings::config_item_type {
+        ct_item_ops: &Self::ITEM_OPS,
+        // SAFETY: All other fields are optional, for which zero is valid. The module is kept
+        // loaded by the composite core, through the function driver.
+        ..unsafe { MaybeUninit::zeroed().assume_init() }
+    };
+
+    /// Returns an initialiser that registers the function `T` of the module `module`.
+    ///
+    /// Fails with `EEXIST` if a function with the same name is registered.
+    pub fn register(module: &'static ThisModule) -> impl PinInit<Self, Error> {
+        // SAFETY: The closure initialises the driver on success. The registration isn't moved
+        // once the driver is registered, since it is pinned.
+        unsafe {
+            init::pin_init_from_closure::<_, Error>(move |slot: *mut Self| {
+                let driver = Opaque::raw_get(ptr::addr_of!((*slot).driver));
+                driver.write(bindings::usb_function_driver {
+                    name: T::NAME.as_char_ptr(),
+                    mod_: module.as_ptr(),
+                    alloc_inst: Some(Self::alloc_inst_callback),
+                    alloc_func: Some(Self::alloc_func_callback),
+                    // SAFETY: The list is initialised on registration.
+                    ..MaybeUninit::zeroed().assume_init()
+                });
+                // INVARIANT: The driver is only considered initialised if it was registered.
+                to_result(bindings::usb_function_register(driver))
+            })
+        }
+    }
+
+    /// Allocates a registration and registers the function, see [`Registration::register`].
+    pub fn register_new(module: &'static ThisModule) -> Result<Pin<Box<Self>>> {
+        Box::pin_init(Self::register(module))
+    }
+
+    unsafe extern "C" fn alloc_inst_callback() -> *mut bindings::usb_function_instance {
+        // SAFETY: The instance is plain data, for which zero is valid.
+        let fi = match Box::try_new(unsafe {
+            MaybeUninit::<bindings::usb_function_instance>::zeroed().assume_init()
+        }) {
+            Ok(fi) => Box::into_raw(fi),
+            Err(_) => return ENOMEM.to_ptr(),
+        };
+        // SAFETY: The instance was just allocated. The item type is static, and its name is set
+        // by configfs.
+        unsafe {
+            (*fi).free_func_inst = Some(Self::free_inst_callback);
+            bindings::config_group_init_type_name(
+                ptr::addr_of_mut!((*fi).group),
+                c_str!("").as_char_ptr(),
+                &Self::ITEM_TYPE,
+            );
+        }
+        fi
+    }
+
+    unsafe extern "C" fn free_inst_callback(fi: *mut bindings::usb_function_instance) {
+        // SAFETY: The core calls this once the last reference to an instance allocated by
+        // `alloc_inst_callback` is dropped.
+        drop(unsafe { Box::from_raw(fi) });
+    }
+
+    unsafe extern "C" fn release_callback(item: *mut bindings::config_item) {
+        // The item is the first field of the group, which is the first field of the instance.
+        let fi = item.cast::<bindings::usb_function_instance>();
+        // SAFETY: configfs calls this once the directory of an instance allocated by
+        // `alloc_inst_callback` is removed, which drops its reference.
+        unsafe { bindings::usb_put_function_instance(fi) };
+    }
+
+    unsafe extern "C" fn alloc_func_callback(
+        _fi: *mut bindings::usb_function_instance,
+    ) -> *mut bindings::usb_function {
+        let alloc = || -> Result<*mut bindings::usb_function> {
+            let data = Box::try_new(FunctionData {
+                func: Opaque::new(bindings::usb_function {
+                    name: T::NAME.as_char_ptr(),
+                    bind: Some(Self::bind_callback),
+                    unbind: Some(Self::unbind_callback),
+                    free_func: Some(Self::free_func_callback),
+                    set_alt: Some(Self::set_alt_callback),
+                    disable: Some(Self::disable_callback),
+                    // SAFETY: All other fields are optional or filled in by the core.
+                    ..unsafe { MaybeUninit::zeroed().assume_init() }
+                }),
+                data: T::new()?,
+            })?;
+            Ok(Box::into_raw(data).cast())
+        };
+        alloc().unwrap_or_else(|e| e.to_ptr())
+    }
+
+    unsafe extern "C" fn free_func_callback(f: *mut bindings::usb_function) {
+        // SAFETY: The core calls this once a function allocated by `alloc_func_callback` is
+        // removed from its configuration, and `func` is the first field of the data.
+        drop(unsafe { Box::from_raw(f.cast::<FunctionData<T>>()) });
+    }
+
+    unsafe extern "C" fn bind_callback(
+        c: *mut bindings::usb_configuration,
+        f: *mut bindings::usb_function,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The core calls this with a function allocated by `alloc_func_callback`,
+            // which was added to `c`, while no other callback runs.
+            let (data, func, config) = unsafe {
+                (
+                    &mut FunctionData::<T>::from_raw(f).data,
+                    Func::from_raw(f),
+                    Configuration::from_raw(c),
+                )
+            };
+            if let Err(e) = data.bind(func, config) {
+                // SAFETY: The function isn't bound, so its descriptors, if any, aren't used.
+                unsafe { bindings::usb_free_all_descriptors(f) };
+                return Err(e);
+            }
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn unbind_callback(
+        _c: *mut bindings::usb_configuration,
+        f: *mut bindings::usb_function,
+    ) {
+        // SAFETY: The core calls this with a function bound by `bind_callback`, once it is
+        // disabled, while no other callback runs.
+        let (data, func) = unsafe { (&mut FunctionData::<T>::from_raw(f).data, Func::from_raw(f)) };
+        data.unbind(func);
+        // SAFETY: The function is unbound, so its descriptors aren't used anymore.
+        unsafe { bindings::usb_free_all_descriptors(f) };
+    }
+
+    unsafe extern "C" fn set_alt_callback(
+        f: *mut bindings::usb_function,
+        intf: c_uint,
+        alt: c_uint,
+    ) -> c_int {
+        from_result(|| {
+            // SAFETY: The core calls this with a function bound by `bind_callback`.
+            let (data, func) = unsafe { (&FunctionData::<T>::from_raw(f).data, Func::from_raw(f)) };
+            data.set_alt(func, intf, alt)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn disable_callback(f: *mut bindings::usb_function) {
+        // SAFETY: The core calls this with a function bound by `bind_callback`.
+        let (data, func) = unsafe { (&FunctionData::<T>::from_raw(f).data, Func::from_raw(f)) };
+        data.disable(func);
+    }
+}
+
+impl<T: Function> Drop for Registration<T> {
+    fn drop(&mut self) {
+        // SAFETY: The driver is registered by the type invariants.
+        unsafe { bindings::usb_function_unregister(self.driver.get()) };
+    }
+}
+
+// SAFETY: The function can be unregistered from any thread.
+unsafe impl<T: Function> Send for Registration<T> {}
+
+// SAFETY: The registration has no methods that take `&self`.
+unsafe impl<T: Function> Sync for Registration<T> {}
@@ -0,0 +1,182 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! USB transceivers (PHYs) and their OTG events.
+//!
+//! The driver of a USB PHY reports when VBUS is detected, when the ID pin is grounded, or when a
+//! charger is detected. Charger drivers look the PHY up with [`Phy::get_by_phandle`] and follow
+//! these events by adding a [`notifier::Handler`] to the chain returned by [`Phy::events`].
+//!
+//! C header: [`include/linux/usb/phy.h`](../../../../include/linux/usb/phy.h)
+
+use crate::{
+    bindings,
+    device::Device,
+    error::{from_err_ptr, to_result, Result},
+    notifier,
+    str::CStr,
+    types::Opaque,
+};
+use core::ffi::c_ulong;
+
+/// An OTG event, the kernel's `enum usb_phy_events`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Nothing is connected.
+    None = bindings::usb_phy_events_USB_EVENT_NONE,
+    /// VBUS is powered, i.e. a host or a charger is connected.
+    Vbus = bindings::usb_phy_events_USB_EVENT_VBUS,
+    /// The ID pin is grounded, i.e. a peripheral is connected through an OTG adapter.
+    Id = bindings::usb_phy_events_USB_EVENT_ID,
+    /// A charger is connected.
+    Charger = bindings::usb_phy_events_USB_EVENT_CHARGER,
+    /// The gadget was enumerated by the host.
+    Enumerated = bindings::usb_phy_events_USB_EVENT_ENUMERATED,
+}
+
+impl Event {
+    /// Converts the action of a notification of [`Phy::events`].
+    pub fn from_action(action: c_ulong) -> Option<Self> {
+        match action as bindings::usb_phy_events {
+            bindings::usb_phy_events_USB_EVENT_NONE => Some(Self::None),
+            bindings::usb_phy_events_USB_EVENT_VBUS => Some(Self::Vbus),
+            bindings::usb_phy_events_USB_EVENT_ID => Some(Self::Id),
+            bindings::usb_phy_events_USB_EVENT_CHARGER => Some(Self::Charger),
+            bindings::usb_phy_events_USB_EVENT_ENUMERATED => Some(Self::Enumerated),
+            _ => None,
+        }
+    }
+}
+
+/// A USB PHY, the kernel's `struct usb_phy`.
+///
+/// # Invariants
+///
+/// The PHY is registered while references to it exist.
+///
+/// # Examples
+///
+/// ```
+/// use core::ffi::{c_ulong, c_void};
+/// use core::sync::atomic::{AtomicBool, Ordering};
+/// use kernel::usb::phy::{Event, Events, Phy};
+/// use kernel::{c_str, device::Device, notifier, prelude::*};
+///
+/// struct Otg {
+///     host: AtomicBool,
+/// }
+///
+/// impl notifier::Handler for Otg {
+///     fn notify(&self, action: c_ulong, _data: *mut c_void) -> Result<notifier::Notify> {
+///         match Event::from_action(action) {
+///             Some(Event::Id) => self.host.store(true, Ordering::Relaxed),
+///             Some(Event::None) => self.host.store(false, Ordering::Relaxed),
+///             _ => return Ok(notifier::Notify::Done),
+///         }
+///         // The charger stops charging and supplies VBUS from a work item.
+///         Ok(notifier::Notify::Ok)
+///     }
+/// }
+///
+/// fn listen<'a>(events: &'a Events<'a>) -> Result<notifier::Registration<'a, Events<'a>, Otg>> {
+///     let otg = Otg {
+///         host: AtomicBool::new(events.phy().last_event() == Some(Event::Id)),
+///     };
+///     notifier::Registration::try_new(events, otg, 0)
+/// }
+///
+/// fn probe(dev: &Device) -> Result {
+///     let phy = Phy::get_by_phandle(dev, c_str!("usb-phy"), 0)?;
+///     let events = phy.events();
+///     let _reg = listen(&events)?;
+///     Ok(())
+/// }
+/// ```
+#[repr(transparent)]
+pub struct Phy(Opaque<bindings::usb_phy>);
+
+impl Phy {
+    /// Creates a reference to a [`Phy`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is a registered PHY for the lifetime of the returned
+    /// reference.
+    unsafe fn from_raw<'a>(ptr: *mut bindings::usb_phy) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct usb_phy` pointer.
+    pub fn as_raw(&self) -> *mut bindings::usb_phy {
+        self.0.get()
+    }
+
+    /// Returns the PHY referred to by the phandle property `property` of the devicetree node of
+    /// `dev`, at the position `index`.
+    ///
+    /// Fails with `EPROBE_DEFER` if the PHY isn't registered yet. The PHY is device-managed: it
+    /// is released when `dev` is unbound from its driver.
+    pub fn get_by_phandle<'a>(dev: &'a Device, property: &CStr, index: u8) -> Result<&'a Self> {
+        // SAFETY: `dev` is valid, and `property` is a valid string.
+        let phy = from_err_ptr(unsafe {
+            bindings::devm_usb_get_phy_by_phandle(dev.as_raw(), property.as_char_ptr(), index)
+        })?;
+        // SAFETY: The PHY was found above, and is held until `dev` is unbound.
+        Ok(unsafe { Self::from_raw(phy) })
+    }
+
+    /// Returns the device of the PHY.
+    pub fn device(&self) -> &Device {
+        // SAFETY: The PHY is registered by the type invariants, so its device is valid.
+        unsafe { Device::as_ref((*self.as_raw()).dev) }
+    }
+
+    /// Returns the last event reported by the PHY, e.g. to handle a cable that was connected
+    /// before the handler was added to [`Phy::events`].
+    pub fn last_event(&self) -> Option<Event> {
+        // SAFETY: The PHY is registered by the type invariants. The event is only read once.
+        let event = unsafe { core::ptr::addr_of!((*self.as_raw()).last_event).read_volatile() };
+        Event::from_action(event as _)
+    }
+
+    /// Returns the notifier chain of the OTG events of the PHY, which handlers can be added to
+    /// with a [`notifier::Registration`].
+    ///
+    /// The handlers are called with an [`Event`] as the action, see [`Event::from_action`], and
+    /// with the charger type or a PHY-specific value as data, in atomic context.
+    pub fn events(&self) -> Events<'_> {
+        Events { phy: self }
+    }
+}
+
+// SAFETY: The notifier chain of the PHY has its own lock, and the other fields that are read are
+// never changed after registration or only read once.
+unsafe impl Send for Phy {}
+
+// SAFETY: See above.
+unsafe impl Sync for Phy {}
+
+/// The notifier chain of the OTG events of a PHY, returned by [`Phy::events`].
+pub struct Events<'a> {
+    phy: &'a Phy,
+}
+
+impl<'a> Events<'a> {
+    /// Returns the PHY whose events are reported.
+    pub fn phy(&self) -> &'a Phy {
+        self.phy
+    }
+}
+
+impl notifier::Head for Events<'_> {
+    unsafe fn register(&self, nb: *mut bindings::notifier_block) -> Result {
+        // SAFETY: The PHY is valid, and the caller guarantees that `nb` is valid.
+        to_result(unsafe { bindings::usb_register_notifier(self.phy.as_raw(), nb) })
+    }
+
+    unsafe fn unregister(&self, nb: *mut bindings::notifier_block) {
+        // SAFETY: The PHY is valid, and the caller guarantees that `nb` is on the chain.
+        unsafe { bindings::usb_unregister_notifier(self.phy.as_raw(), nb) };
+    }
+}
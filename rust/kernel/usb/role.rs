@@ -0,0 +1,291 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! USB role switches.
+//!
+//! A role switch selects whether a dual-role USB controller acts as a host or as a device. The
+//! driver of the switch, e.g. of the controller or of a mux in front of it, registers a
+//! [`Registration`], and the driver that detects the role, e.g. of a Type-C port or of the ID pin
+//! of a micro-USB port, gets the switch with [`Switch::get`] and sets the role.
+//!
+//! C header: [`include/linux/usb/role.h`](../../../../include/linux/usb/role.h)
+
+use crate::{
+    bindings,
+    device::Device,
+    error::{code::*, from_err_ptr, from_result, to_result, Error, Result},
+    init::{self, InPlaceInit, PinInit},
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::{c_int, c_void},
+    marker::PhantomPinned,
+    mem::MaybeUninit,
+    pin::Pin,
+    ptr::{self, NonNull},
+};
+use macros::vtable;
+
+/// The role of a USB controller, the kernel's `enum usb_role`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// The controller is disconnected.
+    None = bindings::usb_role_USB_ROLE_NONE,
+    /// The controller is a host.
+    Host = bindings::usb_role_USB_ROLE_HOST,
+    /// The controller is a device.
+    Device = bindings::usb_role_USB_ROLE_DEVICE,
+}
+
+impl Role {
+    fn from_raw(role: bindings::usb_role) -> Option<Self> {
+        match role {
+            bindings::usb_role_USB_ROLE_NONE => Some(Self::None),
+            bindings::usb_role_USB_ROLE_HOST => Some(Self::Host),
+            bindings::usb_role_USB_ROLE_DEVICE => Some(Self::Device),
+            _ => None,
+        }
+    }
+}
+
+/// A role switch, as seen by the driver that sets its role.
+///
+/// The reference to the switch is dropped when this is dropped.
+///
+/// # Invariants
+///
+/// `sw` is a valid switch, which this holds a reference to.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{device::Device, prelude::*, usb::role::{Role, Switch}};
+///
+/// fn id_changed(sw: &Switch, id_grounded: bool, vbus: bool) -> Result {
+///     let role = match (id_grounded, vbus) {
+///         (true, _) => Role::Host,
+///         (false, true) => Role::Device,
+///         (false, false) => Role::None,
+///     };
+///     sw.set_role(role)
+/// }
+///
+/// fn probe(dev: &Device) -> Result<Switch> {
+///     Switch::get(dev)?.ok_or(ENODEV)
+/// }
+/// ```
+pub struct Switch {
+    sw: NonNull<bindings::usb_role_switch>,
+}
+
+impl Switch {
+    /// Gets the switch connected to `dev` in the devicetree, e.g. through the `usb-role-switch`
+    /// graph of a connector, or `None` if there is none.
+    ///
+    /// Fails with `EPROBE_DEFER` if the switch isn't registered yet.
+    pub fn get(dev: &Device) -> Result<Option<Self>> {
+        crate::might_sleep!();
+        // SAFETY: `dev` is valid.
+        let sw = from_err_ptr(unsafe { bindings::usb_role_switch_get(dev.as_raw()) })?;
+        // INVARIANT: `usb_role_switch_get` took a reference to the switch if it found one.
+        Ok(NonNull::new(sw).map(|sw| Self { sw }))
+    }
+
+    /// Sets the role of the controller.
+    ///
+    /// This may sleep.
+    pub fn set_role(&self, role: Role) -> Result {
+        crate::might_sleep!();
+        // SAFETY: The switch is valid by the type invariants.
+        to_result(unsafe { bindings::usb_role_switch_set_role(self.sw.as_ptr(), role as _) })
+    }
+
+    /// Returns the current role of the controller.
+    pub fn role(&self) -> Role {
+        // SAFETY: The switch is valid by the type invariants.
+        let role = unsafe { bindings::usb_role_switch_get_role(self.sw.as_ptr()) };
+        Role::from_raw(role).unwrap_or(Role::None)
+    }
+}
+
+impl Drop for Switch {
+    fn drop(&mut self) {
+        // SAFETY: This holds a reference to the switch by the type invariants.
+        unsafe { bindings::usb_role_switch_put(self.sw.as_ptr()) };
+    }
+}
+
+// SAFETY: The role is set under the lock of the switch, and the reference can be dropped from any
+// thread.
+unsafe impl Send for Switch {}
+
+// SAFETY: See above.
+unsafe impl Sync for Switch {}
+
+/// The operations of a role switch.
+///
+/// The driver data of the switch implements this trait. The callbacks are called in process
+/// context with the lock of the switch held, and may sleep.
+#[vtable]
+pub trait Operations: Send + Sync + Sized + 'static {
+    /// Switches the controller to `role`.
+    fn set(&self, role: Role) -> Result;
+
+    /// Returns the role of the controller, e.g. as read back from the hardware.
+    ///
+    /// Switches that don't implement it report the last role that was set.
+    fn get(&self) -> Role {
+        Role::None
+    }
+}
+
+/// A registered role switch, the kernel's `struct usb_role_switch`.
+///
+/// The switch is unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `sw` is registered, and its driver data is `data`.
+///
+/// # Examples
+///
+/// ```
+/// use core::pin::Pin;
+/// use kernel::{device::Device, io_mem::IoMem, prelude::*, usb::role};
+///
+/// const OTG_CTRL: usize = 0x0;
+/// const OTG_CTRL_HOST: u32 = 1 << 0;
+/// const OTG_CTRL_DEVICE: u32 = 1 << 1;
+///
+/// struct Mux {
+///     regs: IoMem<0x4>,
+/// }
+///
+/// #[vtable]
+/// impl role::Operations for Mux {
+///     fn set(&self, role: role::Role) -> Result {
+///         let ctrl = match role {
+///             role::Role::None => 0,
+///             role::Role::Host => OTG_CTRL_HOST,
+///             role::Role::Device => OTG_CTRL_DEVICE,
+///         };
+///         self.regs.writel(ctrl, OTG_CTRL);
+///         Ok(())
+///     }
+/// }
+///
+/// fn probe(dev: &Device, regs: IoMem<0x4>) -> Result<Pin<Box<role::Registration<Mux>>>> {
+///     role::Registration::register_new(dev, false, Mux { regs })
+/// }
+/// ```
+pub struct Registration<T: Operations> {
+    sw: *mut bindings::usb_role_switch,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: Operations> Registration<T> {
+    /// Returns an initialiser that registers a role switch whose parent is `parent`, with the
+    /// driver data `data`.
+    ///
+    /// The switch is found by its consumers through the devicetree node of `parent`. If
+    /// `allow_userspace_control` is set, the role can also be set through sysfs.
+    pub fn register(
+        parent: &Device,
+        allow_userspace_control: bool,
+        data: T,
+    ) -> impl PinInit<Self, Error> {
+        let parent = parent.as_raw();
+        // SAFETY: The closure initialises all fields on success, and drops those it initialised
+        // on failure. The registration isn't moved once the switch is registered, since it is
+        // pinned.
+        unsafe {
+            init::pin_init_from_closure::<_, Error>(move |slot: *mut Self| {
+                // The data is written before registering, since the callbacks can be called as
+                // soon as the switch is registered.
+                let data_ptr = ptr::addr_of_mut!((*slot).data);
+                data_ptr.write(data);
+                let desc = bindings::usb_role_switch_desc {
+                    fwnode: bindings::dev_fwnode(parent),
+                    set: Some(Self::set_callback),
+                    get: if T::HAS_GET {
+                        Some(Self::get_callback)
+                    } else {
+                        None
+                    },
+                    allow_userspace_control,
+                    driver_data: data_ptr.cast::<c_void>(),
+                    // SAFETY: All other fields are optional, for which zero is valid.
+                    ..MaybeUninit::zeroed().assume_init()
+                };
+                // The description is copied by the core.
+                let sw = match from_err_ptr(bindings::usb_role_switch_register(parent, &desc)) {
+                    Ok(sw) => sw,
+                    Err(e) => {
+                        ptr::drop_in_place(data_ptr);
+                        return Err(e);
+                    }
+                };
+                // INVARIANT: The switch was registered above, with `data` as its driver data.
+                ptr::addr_of_mut!((*slot).sw).write(sw);
+                Ok(())
+            })
+        }
+    }
+
+    /// Allocates a registration and registers the role switch, see [`Registration::register`].
+    pub fn register_new(
+        parent: &Device,
+        allow_userspace_control: bool,
+        data: T,
+    ) -> Result<Pin<Box<Self>>> {
+        Box::pin_init(Self::register(parent, allow_userspace_control, data))
+    }
+
+    /// Returns the driver data of the switch.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    /// Returns the driver data of `sw`.
+    ///
+    /// # Safety
+    ///
+    /// `sw` must be a switch registered by a `Registration<T>`.
+    unsafe fn data_of<'a>(sw: *mut bindings::usb_role_switch) -> &'a T {
+        // SAFETY: The driver data of the switch is the data of its registration, which outlives
+        // it.
+        unsafe { &*bindings::usb_role_switch_get_drvdata(sw).cast::<T>() }
+    }
+
+    unsafe extern "C" fn set_callback(
+        sw: *mut bindings::usb_role_switch,
+        role: bindings::usb_role,
+    ) -> c_int {
+        from_result(|| {
+            let role = Role::from_raw(role).ok_or(EINVAL)?;
+            // SAFETY: The core calls this with a switch registered by `register`.
+            unsafe { Self::data_of(sw) }.set(role)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn get_callback(sw: *mut bindings::usb_role_switch) -> bindings::usb_role {
+        // SAFETY: The core calls this with a switch registered by `register`.
+        unsafe { Self::data_of(sw) }.get() as _
+    }
+}
+
+impl<T: Operations> Drop for Registration<T> {
+    fn drop(&mut self) {
+        // SAFETY: The switch is registered by the type invariants. Once it is unregistered, the
+        // callbacks aren't called anymore, so the data can be dropped.
+        unsafe { bindings::usb_role_switch_unregister(self.sw) };
+    }
+}
+
+// SAFETY: The switch can be unregistered from any thread, and the driver data is `Send`.
+unsafe impl<T: Operations> Send for Registration<T> {}
+
+// SAFETY: The methods that take `&self` only read the driver data, which is `Sync`.
+unsafe impl<T: Operations> Sync for Registration<T> {}
@@ -0,0 +1,615 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! USB Type-C connectors.
+//!
+//! The driver of a Type-C port controller registers a [`Registration`] for each of its ports,
+//! reports the roles negotiated on the port, and registers the partner and the cable when they
+//! are attached. The connector class exposes them in `/sys/class/typec`, where user space can
+//! request role swaps, which are forwarded to the [`Operations`] of the port.
+//!
+//! C header: [`include/linux/usb/typec.h`](../../../../include/linux/usb/typec.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/driver-api/usb/typec.html>
+
+use crate::{
+    bindings,
+    device::Device,
+    error::{code::*, from_err_ptr, from_result, to_result, Error, Result},
+    init::{self, InPlaceInit, PinInit},
+    types::Opaque,
+};
+use alloc::boxed::Box;
+use core::{
+    ffi::{c_int, c_void},
+    marker::{PhantomData, PhantomPinned},
+    mem::MaybeUninit,
+    pin::Pin,
+    ptr::{self, NonNull},
+};
+use macros::vtable;
+
+/// The data role of a port, the kernel's `enum typec_data_role`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataRole {
+    /// The port is an upstream facing port, i.e. a device.
+    Device = bindings::typec_data_role_TYPEC_DEVICE,
+    /// The port is a downstream facing port, i.e. a host.
+    Host = bindings::typec_data_role_TYPEC_HOST,
+}
+
+impl DataRole {
+    fn from_raw(role: bindings::typec_data_role) -> Option<Self> {
+        match role {
+            bindings::typec_data_role_TYPEC_DEVICE => Some(Self::Device),
+            bindings::typec_data_role_TYPEC_HOST => Some(Self::Host),
+            _ => None,
+        }
+    }
+}
+
+/// The power or VCONN role of a port, the kernel's `enum typec_role`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerRole {
+    /// The port consumes power.
+    Sink = bindings::typec_role_TYPEC_SINK,
+    /// The port provides power.
+    Source = bindings::typec_role_TYPEC_SOURCE,
+}
+
+impl PowerRole {
+    fn from_raw(role: bindings::typec_role) -> Option<Self> {
+        match role {
+            bindings::typec_role_TYPEC_SINK => Some(Self::Sink),
+            bindings::typec_role_TYPEC_SOURCE => Some(Self::Source),
+            _ => None,
+        }
+    }
+}
+
+/// The power capabilities of a port, the kernel's `enum typec_port_type`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PortType {
+    /// The port can only provide power.
+    Source = bindings::typec_port_type_TYPEC_PORT_SRC,
+    /// The port can only consume power.
+    Sink = bindings::typec_port_type_TYPEC_PORT_SNK,
+    /// The port can do both, i.e. it is a dual-role port.
+    DualRole = bindings::typec_port_type_TYPEC_PORT_DRP,
+}
+
+impl PortType {
+    fn from_raw(port_type: bindings::typec_port_type) -> Option<Self> {
+        match port_type {
+            bindings::typec_port_type_TYPEC_PORT_SRC => Some(Self::Source),
+            bindings::typec_port_type_TYPEC_PORT_SNK => Some(Self::Sink),
+            bindings::typec_port_type_TYPEC_PORT_DRP => Some(Self::DualRole),
+            _ => None,
+        }
+    }
+}
+
+/// The data capabilities of a port, the kernel's `enum typec_port_data`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PortData {
+    /// The port can only be a host.
+    Dfp = bindings::typec_port_data_TYPEC_PORT_DFP,
+    /// The port can only be a device.
+    Ufp = bindings::typec_port_data_TYPEC_PORT_UFP,
+    /// The port can be both, i.e. it is a dual-role data port.
+    DualRole = bindings::typec_port_data_TYPEC_PORT_DRD,
+}
+
+/// The power operation mode of a port, the kernel's `enum typec_pwr_opmode`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PwrOpMode {
+    /// The default USB current.
+    Usb = bindings::typec_pwr_opmode_TYPEC_PWR_MODE_USB,
+    /// Type-C current at 1.5 A.
+    Current1A5 = bindings::typec_pwr_opmode_TYPEC_PWR_MODE_1_5A,
+    /// Type-C current at 3.0 A.
+    Current3A0 = bindings::typec_pwr_opmode_TYPEC_PWR_MODE_3_0A,
+    /// A contract was negotiated with USB Power Delivery.
+    Pd = bindings::typec_pwr_opmode_TYPEC_PWR_MODE_PD,
+}
+
+/// The orientation of the plug in a port, the kernel's `enum typec_orientation`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    /// Nothing is plugged in, or the orientation is unknown.
+    None = bindings::typec_orientation_TYPEC_ORIENTATION_NONE,
+    /// The plug is in the normal orientation, CC1 is used.
+    Normal = bindings::typec_orientation_TYPEC_ORIENTATION_NORMAL,
+    /// The plug is flipped, CC2 is used.
+    Reverse = bindings::typec_orientation_TYPEC_ORIENTATION_REVERSE,
+}
+
+/// The accessory mode of a partner, the kernel's `enum typec_accessory`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Accessory {
+    /// The partner isn't an accessory.
+    None = bindings::typec_accessory_TYPEC_ACCESSORY_NONE,
+    /// The partner is an audio adapter accessory.
+    Audio = bindings::typec_accessory_TYPEC_ACCESSORY_AUDIO,
+    /// The partner is a debug accessory.
+    Debug = bindings::typec_accessory_TYPEC_ACCESSORY_DEBUG,
+}
+
+/// The plug at the far end of a cable, the kernel's `enum typec_plug_type`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlugType {
+    /// The plug is unknown.
+    None = bindings::typec_plug_type_USB_PLUG_NONE,
+    /// A Type-A plug.
+    TypeA = bindings::typec_plug_type_USB_PLUG_TYPE_A,
+    /// A Type-B plug.
+    TypeB = bindings::typec_plug_type_USB_PLUG_TYPE_B,
+    /// A Type-C plug.
+    TypeC = bindings::typec_plug_type_USB_PLUG_TYPE_C,
+    /// The cable is captive, i.e. part of the partner.
+    Captive = bindings::typec_plug_type_USB_PLUG_CAPTIVE,
+}
+
+/// The capabilities of a port.
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    /// The power capabilities of the port.
+    pub port_type: PortType,
+    /// The data capabilities of the port.
+    pub data: PortData,
+    /// The role a dual-role port tries to get when a partner is attached, if any.
+    pub prefer_role: Option<PowerRole>,
+    /// The supported Type-C specification, in BCD, e.g. `0x0120` for 1.2.
+    pub revision: u16,
+    /// The supported USB Power Delivery specification, in BCD, or zero if the port doesn't
+    /// support it.
+    pub pd_revision: u16,
+    /// Whether the port reports the orientation of the plug.
+    pub orientation_aware: bool,
+}
+
+/// The description of a partner.
+#[derive(Clone, Copy, Debug)]
+pub struct PartnerDesc {
+    /// Whether the partner supports USB Power Delivery.
+    pub usb_pd: bool,
+    /// The accessory mode of the partner.
+    pub accessory: Accessory,
+    /// The USB Power Delivery specification supported by the partner, in BCD, or zero if it
+    /// isn't known.
+    pub pd_revision: u16,
+}
+
+/// The description of a cable.
+#[derive(Clone, Copy, Debug)]
+pub struct CableDesc {
+    /// The plug at the far end of the cable.
+    pub plug: PlugType,
+    /// Whether the cable is active, i.e. has electronics that respond on SOP'.
+    pub active: bool,
+    /// The USB Power Delivery specification supported by the cable, in BCD, or zero if it isn't
+    /// known.
+    pub pd_revision: u16,
+}
+
+/// The operations of a port, the kernel's `struct typec_operations`.
+///
+/// The driver data of the port implements this trait. The callbacks are called in process
+/// context when user space requests a role swap, and may sleep. Once the swap is done, they
+/// report the new role with the setters of [`Port`].
+#[vtable]
+pub trait Operations: Send + Sync + Sized + 'static {
+    /// Sets the role a dual-role port tries to get when a partner is attached, or none.
+    fn try_role(_port: &Port<Self>, _role: Option<PowerRole>) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Swaps the data role of the port to `role`.
+    fn dr_set(_port: &Port<Self>, _role: DataRole) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Swaps the power role of the port to `role`.
+    fn pr_set(_port: &Port<Self>, _role: PowerRole) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Swaps the VCONN role of the port to `role`.
+    fn vconn_set(_port: &Port<Self>, _role: PowerRole) -> Result {
+        Err(EINVAL)
+    }
+
+    /// Restricts the power capabilities of a dual-role port to `port_type`.
+    fn port_type_set(_port: &Port<Self>, _port_type: PortType) -> Result {
+        Err(EINVAL)
+    }
+}
+
+/// A Type-C port, the kernel's `struct typec_port`.
+///
+/// # Invariants
+///
+/// The port is valid while references to it exist, and its driver data is a `Registration<T>`.
+#[repr(transparent)]
+pub struct Port<T: Operations>(Opaque<bindings::typec_port>, PhantomData<T>);
+
+impl<T: Operations> Port<T> {
+    /// Creates a reference to a [`Port`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is a port registered by a `Registration<T>` for the
+    /// lifetime of the returned reference.
+    unsafe fn from_raw<'a>(ptr: *mut bindings::typec_port) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Returns the raw `struct typec_port` pointer.
+    pub fn as_raw(&self) -> *mut bindings::typec_port {
+        self.0.get()
+    }
+
+    /// Returns the driver data of the port.
+    pub fn data(&self) -> &T {
+        // SAFETY: The driver data of the port is a `Registration<T>` by the type invariants,
+        // which outlives the port.
+        unsafe { &(*bindings::typec_get_drvdata(self.as_raw()).cast::<Registration<T>>()).data }
+    }
+
+    /// Reports the data role of the port.
+    pub fn set_data_role(&self, role: DataRole) {
+        // SAFETY: The port is valid by the type invariants.
+        unsafe { bindings::typec_set_data_role(self.as_raw(), role as _) };
+    }
+
+    /// Reports the power role of the port.
+    pub fn set_pwr_role(&self, role: PowerRole) {
+        // SAFETY: The port is valid by the type invariants.
+        unsafe { bindings::typec_set_pwr_role(self.as_raw(), role as _) };
+    }
+
+    /// Reports the VCONN role of the port.
+    pub fn set_vconn_role(&self, role: PowerRole) {
+        // SAFETY: The port is valid by the type invariants.
+        unsafe { bindings::typec_set_vconn_role(self.as_raw(), role as _) };
+    }
+
+    /// Reports the power operation mode of the port.
+    pub fn set_pwr_opmode(&self, mode: PwrOpMode) {
+        // SAFETY: The port is valid by the type invariants.
+        unsafe { bindings::typec_set_pwr_opmode(self.as_raw(), mode as _) };
+    }
+
+    /// Reports the orientation of the plug, which also configures the orientation switch of the
+    /// port, if any.
+    pub fn set_orientation(&self, orientation: Orientation) -> Result {
+        // SAFETY: The port is valid by the type invariants.
+        to_result(unsafe { bindings::typec_set_orientation(self.as_raw(), orientation as _) })
+    }
+}
+
+/// A registered Type-C port, with the partner and the cable attached to it.
+///
+/// The partner, the cable and the port are unregistered when this is dropped.
+///
+/// # Invariants
+///
+/// `port` is registered, and its driver data is `self`. `partner` and `cable` are registered on
+/// it if they are set.
+///
+/// # Examples
+///
+/// ```
+/// use core::pin::Pin;
+/// use kernel::{device::Device, prelude::*, usb::typec};
+///
+/// struct Tcpc;
+///
+/// #[vtable]
+/// impl typec::Operations for Tcpc {
+///     fn dr_set(port: &typec::Port<Self>, role: typec::DataRole) -> Result {
+///         // The data role swap is negotiated with the partner here.
+///         port.set_data_role(role);
+///         Ok(())
+///     }
+/// }
+///
+/// fn probe(dev: &Device) -> Result<Pin<Box<typec::Registration<Tcpc>>>> {
+///     let config = typec::Config {
+///         port_type: typec::PortType::DualRole,
+///         data: typec::PortData::DualRole,
+///         prefer_role: Some(typec::PowerRole::Sink),
+///         revision: 0x0120,
+///         pd_revision: 0,
+///         orientation_aware: true,
+///     };
+///     typec::Registration::register_new(dev, &config, Tcpc)
+/// }
+///
+/// fn attached(mut reg: Pin<&mut typec::Registration<Tcpc>>, flipped: bool) -> Result {
+///     let port = reg.port();
+///     port.set_orientation(if flipped {
+///         typec::Orientation::Reverse
+///     } else {
+///         typec::Orientation::Normal
+///     })?;
+///     port.set_pwr_role(typec::PowerRole::Sink);
+///     port.set_data_role(typec::DataRole::Device);
+///     port.set_pwr_opmode(typec::PwrOpMode::Current1A5);
+///     reg.as_mut().register_partner(&typec::PartnerDesc {
+///         usb_pd: false,
+///         accessory: typec::Accessory::None,
+///         pd_revision: 0,
+///     })
+/// }
+///
+/// fn detached(mut reg: Pin<&mut typec::Registration<Tcpc>>) {
+///     reg.as_mut().unregister_partner();
+///     reg.port().set_orientation(typec::Orientation::None).ok();
+/// }
+/// ```
+pub struct Registration<T: Operations> {
+    port: *mut bindings::typec_port,
+    partner: Option<NonNull<bindings::typec_partner>>,
+    cable: Option<NonNull<bindings::typec_cable>>,
+    data: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: Operations> Registration<T> {
+    const OPS: bindings::typec_operations = bindings::typec_operations {
+        try_role: if T::HAS_TRY_ROLE {
+            Some(Self::try_role_callback)
+        } else {
+            None
+        },
+        dr_set: if T::HAS_DR_SET {
+            Some(Self::dr_set_callback)
+        } else {
+            None
+        },
+        pr_set: if T::HAS_PR_SET {
+            Some(Self::pr_set_callback)
+        } else {
+            None
+        },
+        vconn_set: if T::HAS_VCONN_SET {
+            Some(Self::vconn_set_callback)
+        } else {
+            None
+        },
+        port_type_set: if T::HAS_PORT_TYPE_SET {
+            Some(Self::port_type_set_callback)
+        } else {
+            None
+        },
+        // SAFETY: All other fields are optional, for which zero is valid.
+        ..unsafe { MaybeUninit::zeroed().assume_init() }
+    };
+
+    /// Returns an initialiser that registers a port whose parent is `parent`, with the
+    /// capabilities `config` and the driver data `data`.
+    ///
+    /// The port is described by the devicetree node of `parent`, e.g. its `connector` node.
+    pub fn register(parent: &Device, config: &Config, data: T) -> impl PinInit<Self, Error> {
+        let parent = parent.as_raw();
+        let config = *config;
+        // SAFETY: The closure initialises all fields on success, and drops those it initialised
+        // on failure. The registration isn't moved once the port is registered, since it is
+        // pinned.
+        unsafe {
+            init::pin_init_from_closure::<_, Error>(move |slot: *mut Self| {
+                // The data is written before registering, since the callbacks can be called as
+                // soon as the port is registered.
+                let data_ptr = ptr::addr_of_mut!((*slot).data);
+                data_ptr.write(data);
+                ptr::addr_of_mut!((*slot).partner).write(None);
+                ptr::addr_of_mut!((*slot).cable).write(None);
+                let mut cap = bindings::typec_capability {
+                    type_: config.port_type as _,
+                    data: config.data as _,
+                    revision: config.revision,
+                    pd_revision: config.pd_revision,
+                    prefer_role: config
+                        .prefer_role
+                        .map_or(bindings::TYPEC_NO_PREFERRED_ROLE, |role| role as _),
+                    fwnode: bindings::dev_fwnode(parent),
+                    driver_data: slot.cast::<c_void>(),
+                    ops: &Self::OPS,
+                    // SAFETY: All other fields are optional, for which zero is valid.
+                    ..MaybeUninit::zeroed().assume_init()
+                };
+                cap.set_orientation_aware(config.orientation_aware.into());
+                // The capabilities are copied by the core, and the ops are static.
+                let port = match from_err_ptr(bindings::typec_register_port(parent, &cap)) {
+                    Ok(port) => port,
+                    Err(e) => {
+                        ptr::drop_in_place(data_ptr);
+                        return Err(e);
+                    }
+                };
+                // INVARIANT: The port was registered above, with `slot` as its driver data.
+                ptr::addr_of_mut!((*slot).port).write(port);
+                Ok(())
+            })
+        }
+    }
+
+    /// Allocates a registration and registers the port, see [`Registration::register`].
+    pub fn register_new(parent: &Device, config: &Config, data: T) -> Result<Pin<Box<Self>>> {
+        Box::pin_init(Self::register(parent, config, data))
+    }
+
+    /// Returns the port.
+    pub fn port(&self) -> &Port<T> {
+        // SAFETY: The port is registered by the type invariants, and its driver data is `self`.
+        unsafe { Port::from_raw(self.port) }
+    }
+
+    /// Returns the driver data of the port.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    /// Returns whether a partner is registered.
+    pub fn has_partner(&self) -> bool {
+        self.partner.is_some()
+    }
+
+    /// Registers the partner attached to the port, described by `desc`.
+    ///
+    /// Fails with `EBUSY` if a partner is already registered.
+    pub fn register_partner(self: Pin<&mut Self>, desc: &PartnerDesc) -> Result {
+        // SAFETY: Only the partner is changed, which isn't pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.partner.is_some() {
+            return Err(EBUSY);
+        }
+        // SAFETY: The description is plain data, for which zero is valid.
+        let mut raw: bindings::typec_partner_desc = unsafe { MaybeUninit::zeroed().assume_init() };
+        raw.set_usb_pd(desc.usb_pd.into());
+        raw.accessory = desc.accessory as _;
+        raw.pd_revision = desc.pd_revision;
+        // SAFETY: The port is registered by the type invariants, and the description is copied.
+        let partner = from_err_ptr(unsafe { bindings::typec_register_partner(this.port, &raw) })?;
+        // INVARIANT: The partner was registered on the port above.
+        this.partner = NonNull::new(partner);
+        Ok(())
+    }
+
+    /// Unregisters the partner, once it is detached. It does nothing if none is registered.
+    pub fn unregister_partner(self: Pin<&mut Self>) {
+        // SAFETY: Only the partner is changed, which isn't pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Some(partner) = this.partner.take() {
+            // SAFETY: The partner is registered by the type invariants, and forgotten above.
+            unsafe { bindings::typec_unregister_partner(partner.as_ptr()) };
+        }
+    }
+
+    /// Registers the cable attached to the port, described by `desc`.
+    ///
+    /// Fails with `EBUSY` if a cable is already registered.
+    pub fn register_cable(self: Pin<&mut Self>, desc: &CableDesc) -> Result {
+        // SAFETY: Only the cable is changed, which isn't pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.cable.is_some() {
+            return Err(EBUSY);
+        }
+        // SAFETY: The description is plain data, for which zero is valid.
+        let mut raw: bindings::typec_cable_desc = unsafe { MaybeUninit::zeroed().assume_init() };
+        raw.type_ = desc.plug as _;
+        raw.set_active(desc.active.into());
+        raw.pd_revision = desc.pd_revision;
+        // SAFETY: The port is registered by the type invariants, and the description is copied.
+        let cable = from_err_ptr(unsafe { bindings::typec_register_cable(this.port, &raw) })?;
+        // INVARIANT: The cable was registered on the port above.
+        this.cable = NonNull::new(cable);
+        Ok(())
+    }
+
+    /// Unregisters the cable, once it is detached. It does nothing if none is registered.
+    pub fn unregister_cable(self: Pin<&mut Self>) {
+        // SAFETY: Only the cable is changed, which isn't pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Some(cable) = this.cable.take() {
+            // SAFETY: The cable is registered by the type invariants, and forgotten above.
+            unsafe { bindings::typec_unregister_cable(cable.as_ptr()) };
+        }
+    }
+
+    unsafe extern "C" fn try_role_callback(port: *mut bindings::typec_port, role: c_int) -> c_int {
+        from_result(|| {
+            let role = if role == bindings::TYPEC_NO_PREFERRED_ROLE {
+                None
+            } else {
+                Some(PowerRole::from_raw(role as _).ok_or(EINVAL)?)
+            };
+            // SAFETY: The core calls this with a port registered by `register`.
+            T::try_role(unsafe { Port::from_raw(port) }, role)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn dr_set_callback(
+        port: *mut bindings::typec_port,
+        role: bindings::typec_data_role,
+    ) -> c_int {
+        from_result(|| {
+            let role = DataRole::from_raw(role).ok_or(EINVAL)?;
+            // SAFETY: The core calls this with a port registered by `register`.
+            T::dr_set(unsafe { Port::from_raw(port) }, role)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn pr_set_callback(
+        port: *mut bindings::typec_port,
+        role: bindings::typec_role,
+    ) -> c_int {
+        from_result(|| {
+            let role = PowerRole::from_raw(role).ok_or(EINVAL)?;
+            // SAFETY: The core calls this with a port registered by `register`.
+            T::pr_set(unsafe { Port::from_raw(port) }, role)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn vconn_set_callback(
+        port: *mut bindings::typec_port,
+        role: bindings::typec_role,
+    ) -> c_int {
+        from_result(|| {
+            let role = PowerRole::from_raw(role).ok_or(EINVAL)?;
+            // SAFETY: The core calls this with a port registered by `register`.
+            T::vconn_set(unsafe { Port::from_raw(port) }, role)?;
+            Ok(0)
+        })
+    }
+
+    unsafe extern "C" fn port_type_set_callback(
+        port: *mut bindings::typec_port,
+        port_type: bindings::typec_port_type,
+    ) -> c_int {
+        from_result(|| {
+            let port_type = PortType::from_raw(port_type).ok_or(EINVAL)?;
+            // SAFETY: The core calls this with a port registered by `register`.
+            T::port_type_set(unsafe { Port::from_raw(port) }, port_type)?;
+            Ok(0)
+        })
+    }
+}
+
+impl<T: Operations> Drop for Registration<T> {
+    fn drop(&mut self) {
+        // SAFETY: By the type invariants, the cable and the partner are registered on the port if
+        // they are set, and the port is registered. They are unregistered before the port, after
+        // which the callbacks aren't called anymore, so the data can be dropped.
+        unsafe {
+            if let Some(cable) = self.cable {
+                bindings::typec_unregister_cable(cable.as_ptr());
+            }
+            if let Some(partner) = self.partner {
+                bindings::typec_unregister_partner(partner.as_ptr());
+            }
+            bindings::typec_unregister_port(self.port);
+        }
+    }
+}
+
+// SAFETY: The port can be unregistered from any thread, and the driver data is `Send`.
+unsafe impl<T: Operations> Send for Registration<T> {}
+
+// SAFETY: The setters of the port take the lock of the port, and the driver data is `Sync`.
+unsafe impl<T: Operations> Sync for Registration<T> {}
+
+// SAFETY: See above.
+unsafe impl<T: Operations> Sync for Port<T> {}
@@ -0,0 +1,178 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Work items and workqueues.
+//!
+//! Drivers defer work that may sleep, e.g. from interrupt handlers, to a kernel thread by
+//! embedding a [`Work`] in their data and queueing it on a [`Queue`], usually one of the system
+//! queues, e.g. [`system`].
+//!
+//! C header: [`include/linux/workqueue.h`](../../../../include/linux/workqueue.h)
+
+use crate::{
+    bindings,
+    init::{self, PinInit},
+    types::Opaque,
+};
+use core::{marker::PhantomPinned, pin::Pin, ptr};
+
+/// The handler of a [`Work`].
+pub trait Handler: Sync {
+    /// Runs the work, in a kernel thread where it may sleep.
+    fn run(&self);
+}
+
+/// A work item, the kernel's `struct work_struct`, with its handler.
+///
+/// A work item runs once each time it is queued, unless it is queued again before it starts, in
+/// which case it runs once for both. Dropping it cancels it, and waits for it to finish if it is
+/// running.
+///
+/// # Invariants
+///
+/// `work` is initialised with [`Work::work_callback`] as its function.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{prelude::*, workqueue};
+/// use core::sync::atomic::{AtomicU32, Ordering};
+///
+/// struct Flush {
+///     pending: AtomicU32,
+/// }
+///
+/// impl workqueue::Handler for Flush {
+///     fn run(&self) {
+///         let pending = self.pending.swap(0, Ordering::Relaxed);
+///         pr_info!("flushing {} entries\n", pending);
+///     }
+/// }
+///
+/// let work = Box::pin_init(workqueue::Work::new(Flush {
+///     pending: AtomicU32::new(0),
+/// }))?;
+/// work.handler().pending.fetch_add(1, Ordering::Relaxed);
+/// workqueue::system().enqueue(work.as_ref());
+/// # Ok::<(), Error>(())
+/// ```
+#[repr(C)]
+pub struct Work<T: Handler> {
+    work: Opaque<bindings::work_struct>,
+    handler: T,
+    _pin: PhantomPinned,
+}
+
+impl<T: Handler> Work<T> {
+    /// Returns an initialiser of a work item that runs `handler`.
+    pub fn new(handler: T) -> impl PinInit<Self> {
+        // SAFETY: The closure initialises all fields, and never fails. The work item isn't moved
+        // once it is initialised, since it is pinned.
+        unsafe {
+            init::pin_init_from_closure(move |slot: *mut Self| {
+                // INVARIANT: The work item is initialised with `work_callback`.
+                bindings::init_work(
+                    Opaque::raw_get(ptr::addr_of!((*slot).work)),
+                    Some(Self::work_callback),
+                );
+                ptr::addr_of_mut!((*slot).handler).write(handler);
+                Ok(())
+            })
+        }
+    }
+
+    /// Returns the handler of the work item.
+    pub fn handler(&self) -> &T {
+        &self.handler
+    }
+
+    /// Cancels the work item, and waits for it to finish if it is running.
+    ///
+    /// Returns `true` if it was pending. This may sleep.
+    pub fn cancel_sync(&self) -> bool {
+        crate::might_sleep!();
+        // SAFETY: The work item is initialised by the type invariants.
+        unsafe { bindings::cancel_work_sync(self.work.get()) }
+    }
+
+    unsafe extern "C" fn work_callback(work: *mut bindings::work_struct) {
+        // SAFETY: The work item is the first field of `Self`, which is `repr(C)`, and it is only
+        // queued through `Queue::enqueue`, which takes a pinned `Self` whose drop waits for it.
+        let this = unsafe { &*work.cast::<Self>() };
+        this.handler.run();
+    }
+}
+
+impl<T: Handler> Drop for Work<T> {
+    fn drop(&mut self) {
+        self.cancel_sync();
+    }
+}
+
+// SAFETY: The work item can be cancelled from any thread, and its handler is only accessed by
+// shared reference.
+unsafe impl<T: Handler + Send> Send for Work<T> {}
+
+// SAFETY: Queueing and cancelling are synchronised by the workqueue core, and the handler is
+// `Sync`.
+unsafe impl<T: Handler> Sync for Work<T> {}
+
+/// A workqueue, the kernel's `struct workqueue_struct`.
+///
+/// # Invariants
+///
+/// The workqueue is valid while references to it exist.
+#[repr(transparent)]
+pub struct Queue(Opaque<bindings::workqueue_struct>);
+
+impl Queue {
+    /// Creates a reference to a [`Queue`] from a valid pointer.
+    ///
+    /// # Safety
+    ///
+    /// Callers must ensure that `ptr` is valid for the lifetime of the returned reference.
+    pub unsafe fn as_ref<'a>(ptr: *mut bindings::workqueue_struct) -> &'a Self {
+        // SAFETY: Guaranteed by the safety requirements of the function.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Queues `work`, on the current CPU if the queue is bound to CPUs.
+    ///
+    /// Returns `false` if the work item was already pending, in which case it runs only once.
+    /// This may be called from atomic context.
+    pub fn enqueue<T: Handler>(&self, work: Pin<&Work<T>>) -> bool {
+        // SAFETY: The queue is valid by the type invariants, and the work item is initialised.
+        // The work item is pinned, and is cancelled before it is freed.
+        unsafe {
+            bindings::queue_work_on(
+                bindings::WORK_CPU_UNBOUND as _,
+                self.0.get(),
+                work.work.get(),
+            )
+        }
+    }
+}
+
+// SAFETY: Workqueues can be used from any thread.
+unsafe impl Send for Queue {}
+
+// SAFETY: Queueing is synchronised by the workqueue core.
+unsafe impl Sync for Queue {}
+
+/// Returns the system workqueue, for short work items.
+pub fn system() -> &'static Queue {
+    // SAFETY: The system workqueue is created at boot and never destroyed.
+    unsafe { Queue::as_ref(bindings::system_wq) }
+}
+
+/// Returns the system workqueue whose work items aren't bound to CPUs, for long work items.
+pub fn system_unbound() -> &'static Queue {
+    // SAFETY: The system workqueue is created at boot and never destroyed.
+    unsafe { Queue::as_ref(bindings::system_unbound_wq) }
+}
+
+/// Returns the system workqueue that is frozen during system suspend, for work items that must
+/// not run while the devices are suspended.
+pub fn system_freezable() -> &'static Queue {
+    // SAFETY: The system workqueue is created at boot and never destroyed.
+    unsafe { Queue::as_ref(bindings::system_freezable_wq) }
+}
@@ -0,0 +1,468 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! XArray, a sparse array of pointers indexed by integers.
+//!
+//! An [`XArray`] maps indices to objects owned by it, e.g. the `u32` handles that a driver hands
+//! out to user space to the contexts or buffers they refer to. It can allocate free indices
+//! itself, optionally cyclically so that recently freed handles aren't reused straight away.
+//!
+//! C header: [`include/linux/xarray.h`](../../../../include/linux/xarray.h)
+//!
+//! Reference: <https://www.kernel.org/doc/html/latest/core-api/xarray.html>
+
+use crate::{
+    bindings,
+    error::{code::*, to_result, Result},
+    init::{self, PinInit},
+    types::{ForeignOwnable, Opaque},
+};
+use core::{
+    cell::UnsafeCell,
+    ffi::{c_ulong, c_void},
+    marker::{PhantomData, PhantomPinned},
+    ops::RangeInclusive,
+    ptr,
+};
+
+/// Flags of an [`XArray`].
+pub mod flags {
+    use crate::bindings;
+
+    /// Flags of an [`XArray`], passed to [`super::XArray::new`].
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    pub struct Flags(Kind);
+
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    enum Kind {
+        None,
+        Alloc,
+        Alloc1,
+    }
+
+    /// The array doesn't allocate indices.
+    pub const NONE: Flags = Flags(Kind::None);
+
+    /// The array allocates indices, starting at 0, with [`super::XArray::alloc`].
+    pub const ALLOC: Flags = Flags(Kind::Alloc);
+
+    /// The array allocates indices, starting at 1, with [`super::XArray::alloc`].
+    pub const ALLOC1: Flags = Flags(Kind::Alloc1);
+
+    impl Flags {
+        /// Returns the C flags, whose values bindgen can't evaluate.
+        pub(super) fn as_raw(self) -> bindings::gfp_t {
+            match self.0 {
+                Kind::None => 0,
+                // SAFETY: These only return constants.
+                Kind::Alloc => unsafe { bindings::XA_FLAGS_ALLOC() },
+                // SAFETY: These only return constants.
+                Kind::Alloc1 => unsafe { bindings::XA_FLAGS_ALLOC1() },
+            }
+        }
+    }
+}
+
+/// An XArray, whose entries are objects of type `T` owned by it.
+///
+/// All operations take the spinlock of the array. Those that store entries may sleep to allocate
+/// memory, the others may be called from atomic context. The objects are dropped when they are
+/// erased, or when the array is dropped. They are accessed through the guard returned by
+/// [`XArray::lock`], which keeps them from being erased.
+///
+/// # Invariants
+///
+/// `xa` is an initialised `struct xarray`, whose entries are null, reserved, or were returned by
+/// [`ForeignOwnable::into_foreign`] for `T` and are owned by the array. `next` is only accessed
+/// by `xa_alloc_cyclic`, with the lock of the array held.
+///
+/// # Examples
+///
+/// ```
+/// use kernel::{prelude::*, xarray::{self, XArray}};
+///
+/// struct Context {
+///     id: u32,
+/// }
+///
+/// fn open(contexts: &XArray<Box<Context>>, id: u32) -> Result<u32> {
+///     let handle = contexts.alloc_cyclic(Box::try_new(Context { id })?, 1..=u32::MAX)?;
+///     pr_info!("context {} has handle {}\n", id, handle);
+///     Ok(handle)
+/// }
+///
+/// fn context_id(contexts: &XArray<Box<Context>>, handle: u32) -> Result<u32> {
+///     let contexts = contexts.lock();
+///     let ctx = contexts.get(handle as usize).ok_or(ENOENT)?;
+///     Ok(ctx.id)
+/// }
+///
+/// fn close(contexts: &XArray<Box<Context>>, handle: u32) -> Result {
+///     contexts.erase(handle as usize).ok_or(ENOENT)?;
+///     Ok(())
+/// }
+///
+/// fn active_contexts(contexts: &XArray<Box<Context>>) -> usize {
+///     contexts.lock().iter().filter(|(_, ctx)| ctx.id != 0).count()
+/// }
+///
+/// fn close_all(contexts: &XArray<Box<Context>>) {
+///     loop {
+///         // The guard is dropped at the end of the statement, before the entry is erased.
+///         let first = contexts.lock().iter().next().map(|(handle, _)| handle);
+///         match first {
+///             Some(handle) => drop(contexts.erase(handle)),
+///             None => break,
+///         }
+///     }
+/// }
+///
+/// fn new_table() -> Result<Pin<Box<XArray<Box<Context>>>>> {
+///     Box::pin_init(XArray::new(xarray::flags::ALLOC1))
+/// }
+/// ```
+#[repr(C)]
+pub struct XArray<T: ForeignOwnable> {
+    xa: Opaque<bindings::xarray>,
+    next: UnsafeCell<u32>,
+    _p: PhantomData<T>,
+    _pin: PhantomPinned,
+}
+
+impl<T: ForeignOwnable> XArray<T> {
+    /// Creates a new, empty array with the given [`flags`].
+    pub fn new(flags: flags::Flags) -> impl PinInit<Self> {
+        // SAFETY: The closure initialises all fields of the array and never fails.
+        unsafe {
+            init::pin_init_from_closure::<_, core::convert::Infallible>(move |slot: *mut Self| {
+                bindings::xa_init_flags(Opaque::raw_get(ptr::addr_of!((*slot).xa)), flags.as_raw());
+                ptr::addr_of_mut!((*slot).next).write(UnsafeCell::new(0));
+                ptr::addr_of_mut!((*slot)._p).write(PhantomData);
+                ptr::addr_of_mut!((*slot)._pin).write(PhantomPinned);
+                Ok(())
+            })
+        }
+    }
+
+    /// Returns the raw `struct xarray` pointer.
+    pub fn as_raw(&self) -> *mut bindings::xarray {
+        self.xa.get()
+    }
+
+    /// Converts `value` into an entry of the array.
+    ///
+    /// Entries whose two low bits are `0b10` are internal entries of the array, so they can't be
+    /// stored; no implementation of [`ForeignOwnable`] in this crate returns them.
+    fn into_entry(value: T) -> Result<*mut c_void> {
+        let entry = value.into_foreign();
+        if entry as usize & 3 == 2 {
+            // SAFETY: `entry` was returned by `into_foreign` above.
+            drop(unsafe { T::from_foreign(entry) });
+            return Err(EINVAL);
+        }
+        Ok(entry as _)
+    }
+
+    /// Takes ownership of an entry returned by the array, which may be null or an error.
+    ///
+    /// # Safety
+    ///
+    /// A non-null entry that isn't an error must have been removed from the array.
+    unsafe fn from_entry(entry: *mut c_void) -> Result<Option<T>> {
+        // SAFETY: `xa_err` only decodes the entry.
+        to_result(unsafe { bindings::xa_err(entry) })?;
+        if entry.is_null() {
+            return Ok(None);
+        }
+        // SAFETY: By the type invariants, the entry was returned by `into_foreign`, and the
+        // caller guarantees that the array doesn't own it anymore.
+        Ok(Some(unsafe { T::from_foreign(entry) }))
+    }
+
+    /// Stores `value` at `index`, which must be free.
+    ///
+    /// Fails with `EBUSY` if there is an entry, or a reservation, at `index`. `value` is dropped
+    /// on failure.
+    pub fn insert(&self, index: usize, value: T) -> Result {
+        let entry = Self::into_entry(value)?;
+        // SAFETY: The array is initialised, and `entry` is a valid entry.
+        let ret = unsafe { bindings::xa_insert(self.as_raw(), index as _, entry, GFP) };
+        if ret < 0 {
+            // SAFETY: The array didn't take ownership of the entry.
+            drop(unsafe { T::from_foreign(entry) });
+        }
+        to_result(ret)
+    }
+
+    /// Stores `value` at `index`, and returns the entry it replaced, if any.
+    ///
+    /// This also fills a reservation at `index`. `value` is dropped on failure.
+    pub fn replace(&self, index: usize, value: T) -> Result<Option<T>> {
+        let entry = Self::into_entry(value)?;
+        // SAFETY: The array is initialised, and `entry` is a valid entry.
+        let old = unsafe { bindings::xa_store(self.as_raw(), index as _, entry, GFP) };
+        // SAFETY: `xa_store` removed the returned entry from the array, if it isn't an error.
+        let old = unsafe { Self::from_entry(old) };
+        if old.is_err() {
+            // SAFETY: The array didn't take ownership of the entry.
+            drop(unsafe { T::from_foreign(entry) });
+        }
+        old
+    }
+
+    /// Reserves `index`, so that it isn't allocated, and a later store there doesn't need to
+    /// allocate memory.
+    ///
+    /// [`Guard::get`] returns `None` for the index until the reservation is filled. It is
+    /// released when the returned object is dropped, unless it has been filled.
+    pub fn reserve(&self, index: usize) -> Result<Reservation<'_, T>> {
+        // SAFETY: The array is initialised.
+        to_result(unsafe { bindings::xa_reserve(self.as_raw(), index as _, GFP) })?;
+        // INVARIANT: `index` was reserved above.
+        Ok(Reservation { xa: self, index })
+    }
+
+    /// Stores `value` at a free index within the range the array allocates from, and returns the
+    /// index.
+    ///
+    /// The array must have been created with [`flags::ALLOC`] or [`flags::ALLOC1`]. Fails with
+    /// `EBUSY` if there are no free indices. `value` is dropped on failure.
+    pub fn alloc(&self, value: T) -> Result<u32> {
+        self.alloc_limits(value, 0..=u32::MAX)
+    }
+
+    /// Stores `value` at a free index within `range`, and returns the index.
+    ///
+    /// See [`XArray::alloc`].
+    pub fn alloc_limits(&self, value: T, range: RangeInclusive<u32>) -> Result<u32> {
+        let limit = limit(range);
+        let entry = Self::into_entry(value)?;
+        let mut id = 0;
+        // SAFETY: The array is initialised, and `entry` is a valid entry.
+        let ret = unsafe { bindings::xa_alloc(self.as_raw(), &mut id, entry, limit, GFP) };
+        if ret < 0 {
+            // SAFETY: The array didn't take ownership of the entry.
+            drop(unsafe { T::from_foreign(entry) });
+        }
+        to_result(ret)?;
+        Ok(id)
+    }
+
+    /// Stores `value` at a free index within `range`, searching from after the index that was
+    /// allocated last, and returns the index.
+    ///
+    /// The search wraps around at the end of `range`, so indices are only reused after all
+    /// others have been allocated. See [`XArray::alloc`].
+    pub fn alloc_cyclic(&self, value: T, range: RangeInclusive<u32>) -> Result<u32> {
+        let limit = limit(range);
+        let entry = Self::into_entry(value)?;
+        let mut id = 0;
+        // SAFETY: The array is initialised, and `entry` is a valid entry. `next` is only accessed
+        // by `xa_alloc_cyclic`, with the lock of the array held.
+        let ret = unsafe {
+            bindings::xa_alloc_cyclic(self.as_raw(), &mut id, entry, limit, self.next.get(), GFP)
+        };
+        if ret < 0 {
+            // SAFETY: The array didn't take ownership of the entry.
+            drop(unsafe { T::from_foreign(entry) });
+        }
+        to_result(ret)?;
+        Ok(id)
+    }
+
+    /// Returns whether there is an entry at `index`.
+    pub fn contains(&self, index: usize) -> bool {
+        // SAFETY: The array is initialised.
+        !unsafe { bindings::xa_load(self.as_raw(), index as _) }.is_null()
+    }
+
+    /// Removes the entry at `index`, or the reservation there, and returns the entry, if any.
+    pub fn erase(&self, index: usize) -> Option<T> {
+        // SAFETY: The array is initialised.
+        let entry = unsafe { bindings::xa_erase(self.as_raw(), index as _) };
+        // SAFETY: `xa_erase` removed the entry from the array, and never fails.
+        unsafe { Self::from_entry(entry) }.unwrap_or(None)
+    }
+
+    /// Returns whether the array has neither entries nor reservations.
+    pub fn is_empty(&self) -> bool {
+        // SAFETY: The array is initialised. Reading the head races with stores, like the C
+        // `xa_empty`.
+        unsafe { ptr::addr_of!((*self.as_raw()).xa_head).read_volatile() }.is_null()
+    }
+
+    /// Takes the lock of the array, which gives access to its entries until the returned guard is
+    /// dropped.
+    ///
+    /// Lookups and iterations through the guard all happen under this one acquisition. The
+    /// methods of the array that store or erase entries take the lock too, so they must not be
+    /// called while the guard exists. Neither must anything that sleeps.
+    pub fn lock(&self) -> Guard<'_, T> {
+        // SAFETY: The array is initialised.
+        unsafe { bindings::xa_lock(self.as_raw()) };
+        // INVARIANT: The lock was taken above.
+        Guard {
+            xa: self,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl<T: ForeignOwnable> Drop for XArray<T> {
+    fn drop(&mut self) {
+        let mut index: c_ulong = 0;
+        loop {
+            // SAFETY: The array is initialised. Reserved entries are skipped.
+            let entry =
+                unsafe { bindings::xa_find(self.as_raw(), &mut index, c_ulong::MAX, xa_present()) };
+            if entry.is_null() {
+                break;
+            }
+            // SAFETY: By the type invariants, the array owns the entry, and nothing else accesses
+            // the array anymore. `xa_destroy` below removes it.
+            drop(unsafe { T::from_foreign(entry) });
+            if index == c_ulong::MAX {
+                break;
+            }
+            index += 1;
+        }
+        // SAFETY: The array is initialised, and isn't used after this.
+        unsafe { bindings::xa_destroy(self.as_raw()) };
+    }
+}
+
+// SAFETY: The array owns its entries, so it can be sent to another thread if they can.
+unsafe impl<T: ForeignOwnable + Send> Send for XArray<T> {}
+
+// SAFETY: The array can be accessed from any thread, as it synchronises its accesses with its
+// lock. Borrows of its entries may be used from any thread, so they must be `Sync`, and entries
+// that are erased are returned on any thread, so they must be `Send`.
+unsafe impl<T: ForeignOwnable + Send + Sync> Sync for XArray<T> {}
+
+/// Entries are stored with `GFP_KERNEL`, so the calls that store them may sleep.
+const GFP: bindings::gfp_t = bindings::GFP_KERNEL;
+
+/// Returns the mark that matches any entry.
+fn xa_present() -> bindings::xa_mark_t {
+    // SAFETY: This only returns a constant.
+    unsafe { bindings::XA_PRESENT() }
+}
+
+fn limit(range: RangeInclusive<u32>) -> bindings::xa_limit {
+    bindings::xa_limit {
+        min: *range.start(),
+        max: *range.end(),
+    }
+}
+
+/// The lock of an [`XArray`], returned by [`XArray::lock`].
+///
+/// The entries of the array can be borrowed while it is held, as they can't be erased meanwhile.
+///
+/// # Invariants
+///
+/// The lock of `xa` is held.
+pub struct Guard<'a, T: ForeignOwnable> {
+    xa: &'a XArray<T>,
+    _not_send: PhantomData<*mut ()>,
+}
+
+impl<T: ForeignOwnable> Guard<'_, T> {
+    /// Borrows the entry at `index`, if any.
+    pub fn get(&self, index: usize) -> Option<T::Borrowed<'_>> {
+        // SAFETY: The array is initialised.
+        let entry = unsafe { bindings::xa_load(self.xa.as_raw(), index as _) };
+        if entry.is_null() {
+            return None;
+        }
+        // SAFETY: By the type invariants of `XArray`, the entry was returned by `into_foreign`.
+        // It can't be erased while the lock is held, which the returned borrow can't outlive.
+        Some(unsafe { T::borrow(entry) })
+    }
+
+    /// Returns an iterator over the entries of the array, and their indices, in increasing order
+    /// of the indices.
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.iter_from(0)
+    }
+
+    /// Returns an iterator over the entries at `start` or above, and their indices, in increasing
+    /// order of the indices.
+    pub fn iter_from(&self, start: usize) -> Iter<'_, T> {
+        Iter {
+            guard: self,
+            index: Some(start),
+        }
+    }
+}
+
+impl<T: ForeignOwnable> Drop for Guard<'_, T> {
+    fn drop(&mut self) {
+        // SAFETY: The lock is held by the type invariants.
+        unsafe { bindings::xa_unlock(self.xa.as_raw()) };
+    }
+}
+
+/// A reserved index of an [`XArray`], returned by [`XArray::reserve`].
+///
+/// # Invariants
+///
+/// `index` was reserved in `xa`.
+pub struct Reservation<'a, T: ForeignOwnable> {
+    xa: &'a XArray<T>,
+    index: usize,
+}
+
+impl<T: ForeignOwnable> Reservation<'_, T> {
+    /// Returns the reserved index.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Stores `value` at the reserved index.
+    ///
+    /// This doesn't allocate memory unless the reservation was erased meanwhile. If another
+    /// entry was stored at the index meanwhile, it is replaced and dropped. On failure, `value`
+    /// is dropped and the reservation is released, like when it is dropped.
+    pub fn fill(self, value: T) -> Result {
+        let ret = self.xa.replace(self.index, value).map(drop);
+        if ret.is_ok() {
+            core::mem::forget(self);
+        }
+        ret
+    }
+}
+
+impl<T: ForeignOwnable> Drop for Reservation<'_, T> {
+    fn drop(&mut self) {
+        // SAFETY: The array is initialised. `xa_release` only removes the entry at the index if
+        // it is still a reservation.
+        unsafe { bindings::xa_release(self.xa.as_raw(), self.index as _) };
+    }
+}
+
+/// An iterator over the entries of an [`XArray`], returned by [`Guard::iter`].
+///
+/// The lock of the array is held by the guard for the whole iteration.
+pub struct Iter<'a, T: ForeignOwnable> {
+    guard: &'a Guard<'a, T>,
+    index: Option<usize>,
+}
+
+impl<'a, T: ForeignOwnable> Iterator for Iter<'a, T> {
+    type Item = (usize, T::Borrowed<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut index = self.index? as c_ulong;
+        let xa = self.guard.xa.as_raw();
+        // SAFETY: The array is initialised. Reserved entries are skipped.
+        let entry = unsafe { bindings::xa_find(xa, &mut index, c_ulong::MAX, xa_present()) };
+        if entry.is_null() {
+            self.index = None;
+            return None;
+        }
+        self.index = (index as usize).checked_add(1);
+        // SAFETY: By the type invariants of `XArray`, the entry was returned by `into_foreign`.
+        // It can't be erased while the lock is held, which the returned borrow can't outlive.
+        Some((index as usize, unsafe { T::borrow(entry) }))
+    }
+}
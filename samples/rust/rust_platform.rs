@@ -0,0 +1,169 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Rust platform driver sample.
+//!
+//! Drives a simple event counter, described in the devicetree by a node such as:
+//!
+//! ```text
+//! counter@40000000 {
+//!     compatible = "rust,platform-sample";
+//!     reg = <0x40000000 0x10>;
+//!     interrupts = <GIC_SPI 10 IRQ_TYPE_LEVEL_HIGH>;
+//! };
+//! ```
+//!
+//! The device raises its interrupt for each event, which the driver acknowledges and counts. The
+//! events are reported from a work item, and their total is exposed in debugfs.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use kernel::{
+    c_str, debugfs,
+    io_mem::IoMem,
+    irq, of, platform,
+    prelude::*,
+    sync::Arc,
+    workqueue::{self, Work},
+};
+
+kernel::module_platform_driver! {
+    type: RustPlatform,
+    name: "rust_platform",
+    author: "Rust for Linux Contributors",
+    description: "Rust platform driver sample",
+    license: "GPL",
+}
+
+const REGS_SIZE: usize = 0x10;
+
+/// The control register.
+const CTRL: usize = 0x0;
+const CTRL_ENABLE: u32 = 1 << 0;
+const CTRL_IRQ_ENABLE: u32 = 1 << 1;
+
+/// The status register, whose bits are cleared by writing 1 to them.
+const STATUS: usize = 0x4;
+const STATUS_EVENT: u32 = 1 << 0;
+
+/// Reports the events counted by the interrupt handler.
+struct Report {
+    name: CString,
+    pending: AtomicU32,
+    total: Arc<AtomicU32>,
+}
+
+impl workqueue::Handler for Report {
+    fn run(&self) {
+        let pending = self.pending.swap(0, Ordering::Relaxed);
+        let total = self.total.fetch_add(pending, Ordering::Relaxed) + pending;
+        pr_info!(
+            "{}: {} new events, {} in total\n",
+            &*self.name,
+            pending,
+            total
+        );
+    }
+}
+
+struct EventIrq {
+    regs: Arc<IoMem<REGS_SIZE>>,
+    report: Pin<Box<Work<Report>>>,
+}
+
+impl irq::Handler for EventIrq {
+    fn handle_irq(&self) -> irq::Return {
+        let status = self.regs.readl(STATUS);
+        if status & STATUS_EVENT == 0 {
+            return irq::Return::None;
+        }
+        self.regs.writel(STATUS_EVENT, STATUS);
+        self.report
+            .handler()
+            .pending
+            .fetch_add(1, Ordering::Relaxed);
+        workqueue::system().enqueue(self.report.as_ref());
+        irq::Return::Handled
+    }
+}
+
+struct DeviceData {
+    // The interrupt is freed first, which cancels the report.
+    irq: Pin<Box<irq::Registration<EventIrq>>>,
+    regs: Arc<IoMem<REGS_SIZE>>,
+    _debugfs: debugfs::Registration,
+}
+
+impl DeviceData {
+    fn enable(&self) {
+        self.regs.writel(CTRL_ENABLE | CTRL_IRQ_ENABLE, CTRL);
+    }
+
+    fn disable(&self) {
+        self.regs.writel(0, CTRL);
+    }
+}
+
+struct RustPlatform;
+
+#[vtable]
+impl platform::Driver for RustPlatform {
+    type Data = Box<DeviceData>;
+
+    const NAME: &'static CStr = c_str!("rust-platform");
+    const OF_MATCH: of::IdTable = kernel::of_id_table![c_str!("rust,platform-sample")];
+
+    fn probe(pdev: &platform::Device) -> Result<Box<DeviceData>> {
+        let name = pdev.device().name();
+
+        // SAFETY: The device doesn't do DMA.
+        let regs = Arc::try_new(unsafe { IoMem::<REGS_SIZE>::try_new(pdev.resource(0)?) }?)?;
+        regs.writel(0, CTRL);
+        regs.writel(STATUS_EVENT, STATUS);
+
+        let total = Arc::try_new(AtomicU32::new(0))?;
+        let report = Box::pin_init(Work::new(Report {
+            name: CString::try_from_fmt(fmt!("{}", name))?,
+            pending: AtomicU32::new(0),
+            total: total.clone(),
+        }))?;
+
+        let handler = EventIrq {
+            regs: regs.clone(),
+            report,
+        };
+        let irq = Box::pin_init(irq::Registration::register(
+            pdev.irq(0)?,
+            0,
+            fmt!("{}", name),
+            handler,
+        ))?;
+
+        // debugfs is optional, so failing to create the file isn't fatal.
+        let mut debugfs = debugfs::Registration::try_new(name, &THIS_MODULE)?;
+        let _ = debugfs.create_u32(debugfs.root(), c_str!("events"), 0o444, total);
+
+        let data = Box::try_new(DeviceData {
+            irq,
+            regs,
+            _debugfs: debugfs,
+        })?;
+        data.enable();
+        pr_info!("{}: probed, irq {}\n", name, data.irq.irq());
+        Ok(data)
+    }
+
+    fn remove(_pdev: &platform::Device, data: Box<DeviceData>) {
+        data.disable();
+    }
+
+    fn suspend(data: &DeviceData) -> Result {
+        data.disable();
+        // Events that aren't reported yet are reported with the next one after resuming.
+        data.irq.handler().report.cancel_sync();
+        Ok(())
+    }
+
+    fn resume(data: &DeviceData) -> Result {
+        data.enable();
+        Ok(())
+    }
+}